@@ -0,0 +1,735 @@
+use glam::{Mat4, Quat, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::math::Aabb;
+use crate::renderer::mesh::Vertex;
+use crate::scene::Transform;
+
+/// Joints one vertex can be weighted to, matches the skinning in animated.slang
+pub const MAX_VERTEX_JOINTS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum AnimationError {
+    #[error("joint {joint} has parent {parent}, parents must come before their children")]
+    ParentOrder { joint: usize, parent: usize },
+    #[error("track animates joint {joint} but the skeleton has {joints} joints")]
+    UnknownJoint { joint: usize, joints: usize },
+    #[error("{skins} vertex skins given for {vertices} vertices")]
+    SkinCount { skins: usize, vertices: usize },
+    #[error("vertex {vertex} is weighted to joint {joint} but the skeleton has {joints} joints")]
+    SkinJoint {
+        vertex: usize,
+        joint: u32,
+        joints: usize,
+    },
+    #[error("vertex animation has {animation} vertices but the mesh has {mesh}")]
+    VertexCount { animation: usize, mesh: usize },
+    #[error("frame rate and clip duration must be finite, frame rate greater than 0")]
+    InvalidTiming,
+    #[error("vertex animation has {texels} texels, {expected} are needed for its frames")]
+    TexelCount { texels: usize, expected: usize },
+}
+
+/// One bone of a Skeleton
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Joint {
+    pub name: String,
+    /// index of the parent in Skeleton::joints, None for roots
+    pub parent: Option<usize>,
+    /// transform relative to the parent when no clip moves the joint
+    pub rest: Transform,
+    /// mesh space to joint space at bind time
+    pub inverse_bind: Mat4,
+}
+
+impl Joint {
+    pub fn new(name: impl Into<String>, parent: Option<usize>, rest: Transform) -> Self {
+        Self {
+            name: name.into(),
+            parent,
+            rest,
+            inverse_bind: Mat4::IDENTITY,
+        }
+    }
+
+    pub fn with_inverse_bind(mut self, inverse_bind: Mat4) -> Self {
+        self.inverse_bind = inverse_bind;
+        self
+    }
+}
+
+/// Joints a skinned mesh is posed by, parents always come before their children
+/// Example Use:
+/// ```
+/// use glam::Vec3;
+/// use vulkan_engine::animation::{Joint, Skeleton};
+/// use vulkan_engine::scene::Transform;
+///
+/// let skeleton = Skeleton::new(vec![
+///     Joint::new("hip", None, Transform::IDENTITY),
+///     Joint::new("knee", Some(0), Transform::from_translation(Vec3::NEG_Y)),
+/// ])
+/// .unwrap()
+/// .with_rest_bind();
+/// let matrices = skeleton.skin_matrices(&skeleton.rest_pose());
+/// assert!(matrices[1].abs_diff_eq(glam::Mat4::IDENTITY, 1e-6));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Skeleton {
+    joints: Vec<Joint>,
+}
+
+impl Skeleton {
+    pub fn new(joints: Vec<Joint>) -> Result<Self, AnimationError> {
+        for (joint, parent) in joints
+            .iter()
+            .enumerate()
+            .filter_map(|(index, joint)| Some((index, joint.parent?)))
+        {
+            if parent >= joint {
+                return Err(AnimationError::ParentOrder { joint, parent });
+            }
+        }
+        Ok(Self { joints })
+    }
+
+    /// Binds the mesh where the rest pose puts it, for skeletons authored without inverse binds
+    pub fn with_rest_bind(mut self) -> Self {
+        let rest = self.joint_matrices(&self.rest_pose());
+        for (joint, matrix) in self.joints.iter_mut().zip(rest) {
+            joint.inverse_bind = matrix.inverse();
+        }
+        self
+    }
+
+    pub fn joints(&self) -> &[Joint] {
+        &self.joints
+    }
+
+    pub fn len(&self) -> usize {
+        self.joints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.joints.is_empty()
+    }
+
+    pub fn rest_pose(&self) -> Pose {
+        Pose {
+            local: self.joints.iter().map(|joint| joint.rest).collect(),
+        }
+    }
+
+    /// Mesh space matrix of every joint, joints missing from pose stay at rest
+    pub fn joint_matrices(&self, pose: &Pose) -> Vec<Mat4> {
+        let mut matrices: Vec<Mat4> = Vec::with_capacity(self.joints.len());
+        for (index, joint) in self.joints.iter().enumerate() {
+            let local = pose.local.get(index).unwrap_or(&joint.rest).to_matrix();
+            // parents come first so theirs is already worked out
+            let matrix = match joint.parent {
+                Some(parent) => matrices[parent] * local,
+                None => local,
+            };
+            matrices.push(matrix);
+        }
+        matrices
+    }
+
+    /// What skinned vertices are multiplied by, each joint's matrix after its inverse bind
+    pub fn skin_matrices(&self, pose: &Pose) -> Vec<Mat4> {
+        self.joint_matrices(pose)
+            .into_iter()
+            .zip(&self.joints)
+            .map(|(matrix, joint)| matrix * joint.inverse_bind)
+            .collect()
+    }
+}
+
+/// Local transform of every joint of a skeleton, in the same order
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pose {
+    pub local: Vec<Transform>,
+}
+
+impl Pose {
+    /// Blend towards other joint by joint, t of 0 is self and 1 is other
+    /// joints only one of the poses has are kept as they are
+    pub fn blend(&self, other: &Self, t: f32) -> Self {
+        let shared = self.local.len().min(other.local.len());
+        let longer = match self.local.len() > other.local.len() {
+            true => &self.local,
+            false => &other.local,
+        };
+        Self {
+            local: self
+                .local
+                .iter()
+                .zip(&other.local)
+                .map(|(a, b)| a.lerp(b, t))
+                .chain(longer[shared..].iter().copied())
+                .collect(),
+        }
+    }
+}
+
+/// Keyframes of one joint, each channel is sorted by time in seconds
+/// an empty channel leaves that part of the joint at rest
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct JointTrack {
+    pub joint: usize,
+    pub translations: Vec<(f32, Vec3)>,
+    pub rotations: Vec<(f32, Quat)>,
+    pub scales: Vec<(f32, Vec3)>,
+}
+
+impl JointTrack {
+    pub fn new(joint: usize) -> Self {
+        Self {
+            joint,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_translation(mut self, time: f32, translation: Vec3) -> Self {
+        self.translations.push((time, translation));
+        self
+    }
+
+    pub fn with_rotation(mut self, time: f32, rotation: Quat) -> Self {
+        self.rotations.push((time, rotation));
+        self
+    }
+
+    pub fn with_scale(mut self, time: f32, scale: Vec3) -> Self {
+        self.scales.push((time, scale));
+        self
+    }
+
+    /// The joint's transform at time, starting from rest
+    pub fn sample(&self, rest: Transform, time: f32) -> Transform {
+        Transform {
+            translation: sample_keys(&self.translations, time, Vec3::lerp)
+                .unwrap_or(rest.translation),
+            rotation: sample_keys(&self.rotations, time, Quat::slerp).unwrap_or(rest.rotation),
+            scale: sample_keys(&self.scales, time, Vec3::lerp).unwrap_or(rest.scale),
+        }
+    }
+}
+
+// value at time between the keys either side of it, held before the first and after the last
+fn sample_keys<T: Copy>(keys: &[(f32, T)], time: f32, lerp: impl Fn(T, T, f32) -> T) -> Option<T> {
+    let next = keys.partition_point(|(key_time, _)| *key_time <= time);
+    let Some(previous) = next.checked_sub(1) else {
+        return keys.first().map(|(_, value)| *value);
+    };
+    let (previous_time, previous) = keys[previous];
+    let Some(&(next_time, next)) = keys.get(next) else {
+        return Some(previous);
+    };
+    // previous_time <= time < next_time
+    let t = (time - previous_time) / (next_time - previous_time);
+    Some(lerp(previous, next, t))
+}
+
+/// Keyframed joint motion over duration seconds
+/// Example Use:
+/// ```ignore
+/// let wave = AnimationClip::new("wave", 1.0)
+///     .with_looping(true)
+///     .with_track(
+///         JointTrack::new(arm)
+///             .with_rotation(0.0, Quat::IDENTITY)
+///             .with_rotation(0.5, Quat::from_rotation_z(1.0))
+///             .with_rotation(1.0, Quat::IDENTITY),
+///     );
+/// let pose = wave.sample(&skeleton, elapsed);
+/// renderer.skinning.poses[pose_index] = skeleton.skin_matrices(&pose);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AnimationClip {
+    pub name: String,
+    pub duration: f32,
+    /// wraps back to the start instead of holding the last frame
+    pub looping: bool,
+    pub tracks: Vec<JointTrack>,
+}
+
+impl AnimationClip {
+    pub fn new(name: impl Into<String>, duration: f32) -> Self {
+        Self {
+            name: name.into(),
+            duration,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn with_track(mut self, track: JointTrack) -> Self {
+        self.tracks.push(track);
+        self
+    }
+
+    /// Checks every track animates a joint of skeleton
+    pub fn validate(&self, skeleton: &Skeleton) -> Result<(), AnimationError> {
+        match self
+            .tracks
+            .iter()
+            .find(|track| track.joint >= skeleton.len())
+        {
+            Some(track) => Err(AnimationError::UnknownJoint {
+                joint: track.joint,
+                joints: skeleton.len(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Time wrapped into the clip when looping, otherwise clamped to its ends
+    pub fn clip_time(&self, time: f32) -> f32 {
+        if self.duration <= 0.0 {
+            0.0
+        } else if self.looping {
+            time.rem_euclid(self.duration)
+        } else {
+            time.clamp(0.0, self.duration)
+        }
+    }
+
+    /// Pose of skeleton at time, tracks for joints it doesn't have are ignored
+    pub fn sample(&self, skeleton: &Skeleton, time: f32) -> Pose {
+        let time = self.clip_time(time);
+        let mut pose = skeleton.rest_pose();
+        for track in &self.tracks {
+            if let Some(local) = pose.local.get_mut(track.joint) {
+                *local = track.sample(*local, time);
+            }
+        }
+        pose
+    }
+}
+
+/// Joints a vertex follows and how much, matches the skin texels in animated.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VertexSkin {
+    pub joints: [u32; MAX_VERTEX_JOINTS],
+    pub weights: [f32; MAX_VERTEX_JOINTS],
+}
+
+impl VertexSkin {
+    /// Weights are scaled to add up to 1, a vertex without any weight follows joints[0]
+    pub fn new(joints: [u32; MAX_VERTEX_JOINTS], weights: [f32; MAX_VERTEX_JOINTS]) -> Self {
+        let weights = weights.map(|weight| match weight.is_finite() {
+            true => weight.max(0.0),
+            false => 0.0,
+        });
+        let total: f32 = weights.iter().sum();
+        let weights = match total > 0.0 {
+            true => weights.map(|weight| weight / total),
+            false => [1.0, 0.0, 0.0, 0.0],
+        };
+        Self { joints, weights }
+    }
+
+    /// Moves rigidly with one joint
+    pub fn rigid(joint: u32) -> Self {
+        Self::new([joint; MAX_VERTEX_JOINTS], [1.0, 0.0, 0.0, 0.0])
+    }
+
+    /// Weighted sum of the vertex's skin matrices, joints past matrices are left out
+    pub fn matrix(&self, matrices: &[Mat4]) -> Mat4 {
+        self.joints
+            .iter()
+            .zip(self.weights)
+            .filter_map(|(&joint, weight)| Some(*matrices.get(joint as usize)? * weight))
+            .fold(Mat4::ZERO, |sum, matrix| sum + matrix)
+    }
+
+    // the two texels animated.slang reads, joint indices then weights
+    pub(crate) fn texels(&self) -> [Vec4; 2] {
+        [
+            Vec4::from_array(self.joints.map(|joint| joint as f32)),
+            Vec4::from_array(self.weights),
+        ]
+    }
+}
+
+/// Checks there's a skin for every vertex and every joint is in a skeleton of joint_count joints
+pub fn validate_skins(
+    vertex_count: usize,
+    skins: &[VertexSkin],
+    joint_count: usize,
+) -> Result<(), AnimationError> {
+    if skins.len() != vertex_count {
+        return Err(AnimationError::SkinCount {
+            skins: skins.len(),
+            vertices: vertex_count,
+        });
+    }
+    for (vertex, skin) in skins.iter().enumerate() {
+        if let Some(&joint) = skin
+            .joints
+            .iter()
+            .zip(skin.weights)
+            .find(|(joint, weight)| *weight > 0.0 && **joint as usize >= joint_count)
+            .map(|(joint, _)| joint)
+        {
+            return Err(AnimationError::SkinJoint {
+                vertex,
+                joint,
+                joints: joint_count,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Vertices posed by skin matrices on the cpu, what the vertex shader does for skinned instances
+/// normals and tangents are normalized again afterwards
+pub fn skin_vertices(vertices: &[Vertex], skins: &[VertexSkin], matrices: &[Mat4]) -> Vec<Vertex> {
+    vertices
+        .iter()
+        .zip(skins)
+        .map(|(vertex, skin)| {
+            let matrix = skin.matrix(matrices);
+            let tangent = matrix
+                .transform_vector3(vertex.tangent.truncate())
+                .normalize_or_zero();
+            Vertex {
+                pos: matrix.transform_point3(vertex.pos),
+                normal: matrix.transform_vector3(vertex.normal).normalize_or_zero(),
+                tangent: tangent.extend(vertex.tangent.w),
+                ..*vertex
+            }
+        })
+        .collect()
+}
+
+/// The two frames of a baked clip time falls between and how far it is towards the second
+/// looping clips blend their last frame back into the first
+pub fn baked_frames(
+    frame_count: u32,
+    frame_rate: f32,
+    looping: bool,
+    time: f32,
+) -> (u32, u32, f32) {
+    let Some(last) = frame_count.checked_sub(1) else {
+        return (0, 0, 0.0);
+    };
+    let position = (time * frame_rate).max(0.0);
+    if looping {
+        let position = position % frame_count as f32;
+        let frame = (position as u32).min(last);
+        let next = (frame + 1) % frame_count;
+        (frame, next, position - frame as f32)
+    } else if position >= last as f32 {
+        (last, last, 0.0)
+    } else {
+        let frame = position as u32;
+        (frame, frame + 1, position - frame as f32)
+    }
+}
+
+/// A clip baked into the positions and normals of every vertex at a fixed frame rate,
+/// played back by the vertex shader without evaluating a skeleton, for crowds
+/// Example Use:
+/// ```ignore
+/// let walk = VertexAnimation::bake(&vertices, &skins, &skeleton, &walk_clip, 30.0)?;
+/// let clip = renderer.add_vertex_animation(soldier, &walk)?;
+/// renderer.instances[0].animation = Some(InstanceAnimation::Baked { clip, time: 0.0 });
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexAnimation {
+    pub name: String,
+    pub vertex_count: u32,
+    pub frame_count: u32,
+    pub frame_rate: f32,
+    pub looping: bool,
+    /// frame_count rows of positions then frame_count rows of normals, a texel per vertex
+    pub texels: Vec<Vec4>,
+    /// covers the mesh in every frame
+    pub bounds: Aabb,
+}
+
+impl VertexAnimation {
+    /// Samples clip frame_rate times a second, non looping clips also keep their last pose
+    pub fn bake(
+        vertices: &[Vertex],
+        skins: &[VertexSkin],
+        skeleton: &Skeleton,
+        clip: &AnimationClip,
+        frame_rate: f32,
+    ) -> Result<Self, AnimationError> {
+        if !(frame_rate.is_finite() && frame_rate > 0.0 && clip.duration.is_finite()) {
+            return Err(AnimationError::InvalidTiming);
+        }
+        validate_skins(vertices.len(), skins, skeleton.len())?;
+        clip.validate(skeleton)?;
+
+        let intervals = (clip.duration.max(0.0) * frame_rate).round().max(1.0) as u32;
+        let frame_count = match clip.looping {
+            true => intervals,
+            false => intervals + 1,
+        };
+        let frames: Vec<Vec<Vertex>> = (0..frame_count)
+            .map(|frame| {
+                let pose = clip.sample(skeleton, frame as f32 / frame_rate);
+                skin_vertices(vertices, skins, &skeleton.skin_matrices(&pose))
+            })
+            .collect();
+
+        let positions = frames.iter().flatten().map(|vertex| vertex.pos);
+        let normals = frames.iter().flatten().map(|vertex| vertex.normal);
+        let texels: Vec<Vec4> = positions
+            .clone()
+            .map(|position| position.extend(1.0))
+            .chain(normals.map(|normal| normal.extend(0.0)))
+            .collect();
+        let bounds = Aabb::from_points(&positions.collect::<Vec<_>>())
+            .unwrap_or(Aabb::new(Vec3::ZERO, Vec3::ZERO));
+
+        Ok(Self {
+            name: clip.name.clone(),
+            vertex_count: vertices.len() as u32,
+            frame_count,
+            frame_rate,
+            looping: clip.looping,
+            texels,
+            bounds,
+        })
+    }
+
+    /// Checks texels holds every frame's positions and normals
+    pub fn validate(&self) -> Result<(), AnimationError> {
+        if !(self.frame_rate.is_finite() && self.frame_rate > 0.0) {
+            return Err(AnimationError::InvalidTiming);
+        }
+        let expected = self.vertex_count as usize * self.frame_count as usize * 2;
+        match self.texels.len() == expected {
+            true => Ok(()),
+            false => Err(AnimationError::TexelCount {
+                texels: self.texels.len(),
+                expected,
+            }),
+        }
+    }
+
+    /// Seconds before a looping clip repeats or a non looping one stops
+    pub fn duration(&self) -> f32 {
+        let intervals = match self.looping {
+            true => self.frame_count,
+            false => self.frame_count.saturating_sub(1),
+        };
+        intervals as f32 / self.frame_rate
+    }
+
+    pub fn position(&self, frame: u32, vertex: u32) -> Vec3 {
+        self.texels[(frame * self.vertex_count + vertex) as usize].truncate()
+    }
+
+    pub fn normal(&self, frame: u32, vertex: u32) -> Vec3 {
+        let normals = self.frame_count * self.vertex_count;
+        self.texels[(normals + frame * self.vertex_count + vertex) as usize].truncate()
+    }
+
+    /// See baked_frames
+    pub fn frames_at(&self, time: f32) -> (u32, u32, f32) {
+        baked_frames(self.frame_count, self.frame_rate, self.looping, time)
+    }
+}
+
+#[cfg(test)]
+fn test_arm() -> (Skeleton, Vec<Vertex>, Vec<VertexSkin>) {
+    use glam::Vec2;
+
+    let skeleton = Skeleton::new(vec![
+        Joint::new("shoulder", None, Transform::IDENTITY),
+        Joint::new("elbow", Some(0), Transform::from_translation(Vec3::X)),
+    ])
+    .unwrap()
+    .with_rest_bind();
+    let mut vertices: Vec<Vertex> = [Vec3::ZERO, Vec3::X, Vec3::new(2.0, 0.0, 0.0)]
+        .into_iter()
+        .map(|position| Vertex::new(position, Vec3::ONE, Vec2::ZERO))
+        .collect();
+    for vertex in &mut vertices {
+        vertex.normal = Vec3::Y;
+        vertex.tangent = Vec4::new(1.0, 0.0, 0.0, 1.0);
+    }
+    let skins = vec![
+        VertexSkin::rigid(0),
+        VertexSkin::new([0, 1, 0, 0], [1.0, 1.0, 0.0, 0.0]),
+        VertexSkin::rigid(1),
+    ];
+    (skeleton, vertices, skins)
+}
+
+#[test]
+fn skeleton_test() {
+    let joints = vec![
+        Joint::new("child", Some(1), Transform::IDENTITY),
+        Joint::new("root", None, Transform::IDENTITY),
+    ];
+    assert_eq!(
+        Skeleton::new(joints),
+        Err(AnimationError::ParentOrder {
+            joint: 0,
+            parent: 1
+        })
+    );
+
+    let (skeleton, vertices, skins) = test_arm();
+    // bound at rest, so the rest pose leaves the mesh where it is
+    let rest = skeleton.skin_matrices(&skeleton.rest_pose());
+    for (skinned, vertex) in skin_vertices(&vertices, &skins, &rest)
+        .iter()
+        .zip(&vertices)
+    {
+        assert!(skinned.pos.abs_diff_eq(vertex.pos, 1e-5));
+    }
+
+    // bending the elbow a quarter turn swings the hand up, the middle vertex halfway
+    let mut pose = skeleton.rest_pose();
+    pose.local[1].rotation = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+    let matrices = skeleton.skin_matrices(&pose);
+    let skinned = skin_vertices(&vertices, &skins, &matrices);
+    assert!(skinned[0].pos.abs_diff_eq(Vec3::ZERO, 1e-5));
+    assert!(skinned[1].pos.abs_diff_eq(Vec3::X, 1e-5));
+    assert!(skinned[2].pos.abs_diff_eq(Vec3::new(1.0, 1.0, 0.0), 1e-5));
+    assert!(skinned[2].normal.abs_diff_eq(Vec3::NEG_X, 1e-5));
+    assert!(
+        skinned[2]
+            .tangent
+            .abs_diff_eq(Vec4::new(0.0, 1.0, 0.0, 1.0), 1e-5)
+    );
+
+    let blended = skeleton.rest_pose().blend(&pose, 0.5);
+    let expected = Quat::from_rotation_z(std::f32::consts::FRAC_PI_4);
+    assert!(blended.local[1].rotation.abs_diff_eq(expected, 1e-5));
+}
+
+#[test]
+fn vertex_skin_test() {
+    let skin = VertexSkin::new([0, 1, 2, 3], [2.0, 2.0, f32::NAN, -1.0]);
+    assert_eq!(skin.weights, [0.5, 0.5, 0.0, 0.0]);
+    assert_eq!(
+        VertexSkin::new([3; 4], [0.0; 4]).weights,
+        [1.0, 0.0, 0.0, 0.0]
+    );
+    assert_eq!(
+        skin.texels(),
+        [Vec4::new(0.0, 1.0, 2.0, 3.0), Vec4::new(0.5, 0.5, 0.0, 0.0)]
+    );
+
+    assert_eq!(validate_skins(1, &[skin], 2), Ok(()));
+    assert_eq!(
+        validate_skins(2, &[skin], 2),
+        Err(AnimationError::SkinCount {
+            skins: 1,
+            vertices: 2
+        })
+    );
+    assert_eq!(
+        validate_skins(1, &[VertexSkin::rigid(2)], 2),
+        Err(AnimationError::SkinJoint {
+            vertex: 0,
+            joint: 2,
+            joints: 2
+        })
+    );
+}
+
+#[test]
+fn clip_sample_test() {
+    let (skeleton, _, _) = test_arm();
+    let clip = AnimationClip::new("raise", 2.0).with_track(
+        JointTrack::new(1)
+            .with_translation(0.0, Vec3::X)
+            .with_translation(2.0, Vec3::new(3.0, 0.0, 0.0)),
+    );
+    let translation =
+        |clip: &AnimationClip, time| clip.sample(&skeleton, time).local[1].translation;
+    assert!(translation(&clip, 1.0).abs_diff_eq(Vec3::new(2.0, 0.0, 0.0), 1e-5));
+    assert!(translation(&clip, -1.0).abs_diff_eq(Vec3::X, 1e-5));
+    assert!(translation(&clip, 5.0).abs_diff_eq(Vec3::new(3.0, 0.0, 0.0), 1e-5));
+    // the untouched rotation and scale stay at rest
+    assert_eq!(clip.sample(&skeleton, 1.0).local[1].scale, Vec3::ONE);
+
+    let looping = clip.clone().with_looping(true);
+    assert!(translation(&looping, 3.0).abs_diff_eq(Vec3::new(2.0, 0.0, 0.0), 1e-5));
+
+    let stray = AnimationClip::new("stray", 1.0).with_track(JointTrack::new(5));
+    assert_eq!(
+        stray.validate(&skeleton),
+        Err(AnimationError::UnknownJoint {
+            joint: 5,
+            joints: 2
+        })
+    );
+}
+
+#[test]
+fn vertex_animation_test() {
+    let (skeleton, vertices, skins) = test_arm();
+    let bend = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+    let clip = AnimationClip::new("bend", 1.0).with_track(
+        JointTrack::new(1)
+            .with_rotation(0.0, Quat::IDENTITY)
+            .with_rotation(1.0, bend),
+    );
+    let baked = VertexAnimation::bake(&vertices, &skins, &skeleton, &clip, 4.0).unwrap();
+    assert_eq!(baked.frame_count, 5);
+    assert_eq!(baked.validate(), Ok(()));
+    assert_eq!(baked.duration(), 1.0);
+
+    // each frame matches skinning the clip's pose at that time
+    for frame in 0..baked.frame_count {
+        let pose = clip.sample(&skeleton, frame as f32 / 4.0);
+        let skinned = skin_vertices(&vertices, &skins, &skeleton.skin_matrices(&pose));
+        for (vertex, expected) in (0..).zip(&skinned) {
+            assert!(
+                baked
+                    .position(frame, vertex)
+                    .abs_diff_eq(expected.pos, 1e-5)
+            );
+            assert!(
+                baked
+                    .normal(frame, vertex)
+                    .abs_diff_eq(expected.normal, 1e-5)
+            );
+        }
+    }
+    assert!(
+        baked
+            .position(4, 2)
+            .abs_diff_eq(Vec3::new(1.0, 1.0, 0.0), 1e-5)
+    );
+    assert!(baked.bounds.max.abs_diff_eq(Vec3::new(2.0, 1.0, 0.0), 1e-5));
+
+    assert_eq!(baked.frames_at(0.375), (1, 2, 0.5));
+    assert_eq!(baked.frames_at(3.0), (4, 4, 0.0));
+    let looping = clip.with_looping(true);
+    let looped = VertexAnimation::bake(&vertices, &skins, &skeleton, &looping, 4.0).unwrap();
+    assert_eq!(looped.frame_count, 4);
+    assert_eq!(looped.frames_at(0.875), (3, 0, 0.5));
+    assert_eq!(looped.frames_at(1.125), (0, 1, 0.5));
+
+    let mut broken = baked.clone();
+    broken.texels.pop();
+    assert_eq!(
+        broken.validate(),
+        Err(AnimationError::TexelCount {
+            texels: 29,
+            expected: 30
+        })
+    );
+    assert_eq!(
+        VertexAnimation::bake(&vertices, &skins, &skeleton, &looping, 0.0),
+        Err(AnimationError::InvalidTiming)
+    );
+}
@@ -1,50 +1,583 @@
+use crate::color::LinearRgba;
+use crate::cvar::{CVar, CVarFlags, CVars};
+use crate::demo_scenes::DemoScene;
+#[cfg(feature = "gamepad")]
+use crate::gamepad::Gamepads;
+use crate::lod::{LodMeshInfo, LodSelector};
+use crate::photo_mode::PhotoMode;
+use crate::renderer::InstanceOptions;
+use crate::renderer::RendererOptions;
 use crate::renderer::VKContext;
 use crate::renderer::VKRenderer;
+use crate::renderer::capture::CaptureOptions;
+use crate::renderer::material::DEFAULT_MATERIAL;
+use crate::renderer::mesh::CUBE_MESH;
+use crate::renderer::replay::FrameRecording;
+use crate::resize_stress::{ResizeStress, ResizeStressError, ResizeStressStats};
+use crate::scene::{Node, Scene};
+use crate::smoke_test::{SmokeTest, SmokeTestError};
+use crate::snapshot::{EngineSnapshot, RenderSettings};
+use crate::text_input::TextInput;
+use crate::time::GameClock;
 use crate::utils::GameInfo;
 use crate::utils::ReplaceWith;
-use log::info;
+use glam::Vec3;
+use log::{error, info, warn};
 use winit::application::ApplicationHandler;
 use winit::error::EventLoopError;
-use winit::event::WindowEvent;
+use winit::event::{ElementState, KeyEvent, WindowEvent};
 use winit::event_loop::ActiveEventLoop;
 use winit::event_loop::ControlFlow;
 use winit::event_loop::EventLoop;
+use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::platform::run_on_demand::EventLoopExtRunOnDemand;
 use winit::window::Window;
 use winit::window::WindowId;
+use winit::window::WindowLevel;
+
+// F5 saves here and F9 restores from it
+const QUICK_SAVE_PATH: &str = "quicksave.ron";
+// console script run at startup, then the overrides saved on the last exit
+const CONFIG_PATH: &str = "engine.cfg";
+const USER_CONFIG_PATH: &str = "user.cfg";
+
+/// Options used when creating the game window
+/// Example Use:
+/// ```ignore
+/// // frame time overlay sitting on top of the desktop
+/// let overlay = WindowOptions::default()
+///     .size(320, 120)
+///     .transparent(true)
+///     .always_on_top(true)
+///     .click_through(true);
+/// App::new(game_info).with_window_options(overlay).start(&mut event_loop)?;
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct WindowOptions {
+    pub width: u32,
+    pub height: u32,
+    pub transparent: bool,
+    pub always_on_top: bool,
+    pub click_through: bool,
+}
+
+impl Default for WindowOptions {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            transparent: false,
+            always_on_top: false,
+            click_through: false,
+        }
+    }
+}
+
+impl WindowOptions {
+    /// Inner size in physical pixels
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// Shows the desktop through the window where the clear colour and materials have alpha below 1
+    /// for overlays and launchers, falls back to opaque when the compositor can't blend the surface
+    pub fn transparent(mut self, transparent: bool) -> Self {
+        self.transparent = transparent;
+        self
+    }
+
+    /// Keeps the window above other windows, e.g. for performance overlays
+    pub fn always_on_top(mut self, always_on_top: bool) -> Self {
+        self.always_on_top = always_on_top;
+        self
+    }
+
+    /// Lets mouse input pass through to whatever is behind the window, keyboard focus is unaffected
+    /// ignored with a warning on platforms that can't do it
+    pub fn click_through(mut self, click_through: bool) -> Self {
+        self.click_through = click_through;
+        self
+    }
+}
 
 pub struct AppCTX<'a> {
     pub game_info: GameInfo,
     pub window: Window,
     pub vulkan_renderer: VKRenderer<'a>,
+    pub demo_scene: Option<DemoScene>,
+    /// drawn when there is no demo scene
+    pub scene: Scene,
+    /// distance of the orbiting camera from the centre of the scene
+    pub orbit_radius: f32,
+    /// render settings and debug toggles, see engine_cvars
+    /// ` opens the console, enter runs the line and escape closes it
+    pub cvars: CVars,
+    /// line being typed while the console is open
+    pub console: Option<String>,
+    /// drives the demo animations and the orbiting camera
+    /// P pauses, . steps a frame while paused, - and = halve and double the speed, 0 resets it
+    pub clock: GameClock,
+    /// F8 toggles, screenshots are taken through it while it is on
+    pub photo_mode: Option<PhotoMode>,
+    /// F10 sets it, the next frame is saved to frame-<timestamp>.ron for bug reports
+    pub record_next_frame: bool,
+    /// drawn instead of the scene and with its camera when set, see App::with_replay
+    pub replay: Option<FrameRecording>,
+    /// text typed while a ui text field has focus, see set_text_input
+    pub text_input: TextInput,
+    /// None when gamepads couldn't be opened, updated before every frame
+    #[cfg(feature = "gamepad")]
+    pub gamepads: Option<Gamepads>,
+    /// exits after capturing a frame when set, see App::with_smoke_test
+    pub smoke_test: Option<SmokeTest>,
+    /// None until the smoke test captured its frame or was interrupted
+    pub smoke_test_result: Option<Result<usize, SmokeTestError>>,
+    /// resizes the window every frame and exits when set, see App::with_resize_stress
+    pub resize_stress: Option<ResizeStress>,
+    pub resize_stress_stats: ResizeStressStats,
+    /// None until the stress test finished or was interrupted
+    pub resize_stress_result: Option<Result<(), ResizeStressError>>,
+    pub frames_rendered: u32,
 }
 
 impl AppCTX<'_> {
-    fn new(game_info: GameInfo, event_loop: &ActiveEventLoop) -> Self {
-        let (width, height) = (800, 600);
+    fn new(
+        game_info: GameInfo,
+        demo_scene: Option<DemoScene>,
+        window_options: WindowOptions,
+        instance_options: InstanceOptions,
+        smoke_test: Option<SmokeTest>,
+        resize_stress: Option<ResizeStress>,
+        event_loop: &ActiveEventLoop,
+    ) -> Self {
         let window = event_loop
             .create_window(
                 Window::default_attributes()
                     .with_title(game_info.app_name.to_string_lossy())
-                    .with_inner_size(winit::dpi::PhysicalSize::new(width, height)),
+                    .with_inner_size(winit::dpi::PhysicalSize::new(
+                        window_options.width,
+                        window_options.height,
+                    ))
+                    .with_transparent(window_options.transparent)
+                    .with_window_level(if window_options.always_on_top {
+                        WindowLevel::AlwaysOnTop
+                    } else {
+                        WindowLevel::Normal
+                    }),
             )
             .unwrap();
 
-        let vulkan_ctx = VKContext::new(&game_info, &window).unwrap();
+        if window_options.click_through
+            && let Err(error) = window.set_cursor_hittest(false)
+        {
+            warn!("Click through windows are not supported: {error}");
+        }
+
+        let vulkan_ctx = VKContext::new(
+            &game_info,
+            &window,
+            &instance_options,
+            window_options.transparent,
+        )
+        .unwrap();
+
+        let mut vulkan_renderer = VKRenderer::new(vulkan_ctx, &RendererOptions::default()).unwrap();
+        vulkan_renderer
+            .vulkan_present
+            .enable_present_timing(vulkan_renderer.vulkan_ctx.present_target(), &window);
 
-        let vulkan_renderer = VKRenderer::new(vulkan_ctx, 2).unwrap();
+        let orbit_radius = match &demo_scene {
+            Some(demo_scene) => demo_scene.radius() * 1.5,
+            None => 2.5,
+        };
+
+        let mut scene = Scene::default();
+        scene
+            .add(
+                Node::new("cube").with_mesh(CUBE_MESH, DEFAULT_MATERIAL),
+                None,
+            )
+            .unwrap();
 
         Self {
             game_info,
             window,
             vulkan_renderer,
+            demo_scene,
+            scene,
+            orbit_radius,
+            cvars: load_cvars(),
+            console: None,
+            clock: GameClock::default(),
+            photo_mode: None,
+            record_next_frame: false,
+            replay: None,
+            text_input: TextInput::default(),
+            #[cfg(feature = "gamepad")]
+            gamepads: Gamepads::new()
+                .inspect_err(|error| warn!("Gamepads Unavailable: {error}"))
+                .ok(),
+            smoke_test,
+            smoke_test_result: None,
+            resize_stress,
+            resize_stress_stats: ResizeStressStats::default(),
+            resize_stress_result: None,
+            frames_rendered: 0,
+        }
+    }
+
+    // slowly circles the centre of the scene while looking down at it
+    fn update_camera(&mut self, time: f32) {
+        let speed: f32 = 10.0; // speed deg per second
+        let yaw = (time * speed % 360.0).to_radians();
+        let pitch = -20.0_f32.to_radians();
+
+        let renderer = &mut self.vulkan_renderer;
+        renderer.camera =
+            renderer
+                .camera
+                .orbit(Vec3::new(0.0, 0.2, 0.0), yaw, pitch, self.orbit_radius);
+    }
+
+    /// Scene, camera, render settings and animation time, see EngineSnapshot
+    pub fn snapshot(&self) -> EngineSnapshot {
+        let renderer = &self.vulkan_renderer;
+        EngineSnapshot::new(
+            self.clock.elapsed() as f32,
+            self.scene.clone(),
+            renderer.camera,
+            RenderSettings {
+                clear_color: renderer.clear_color,
+                lighting: renderer.lighting.clone(),
+                bloom: renderer.bloom.settings,
+                ambient_occlusion: renderer.ambient_occlusion.settings,
+                ray_query_shadows: renderer.ray_query_shadows.is_some(),
+                depth_convention: renderer.depth_convention(),
+                msaa_samples: renderer.vulkan_ctx.vulkan_swapchain.samples.as_raw(),
+            },
+        )
+    }
+
+    /// Puts the engine back into the state of snapshot, animations carry on from its time
+    /// the game re-seeds its own generators from snapshot.rng_seeds
+    /// depth convention and msaa can't change on a running renderer, differences are only logged
+    pub fn restore(&mut self, snapshot: EngineSnapshot) {
+        self.clock.set_elapsed(snapshot.time as f64);
+        let settings = snapshot.render_settings;
+        // the frame loop applies bloom and ssao from these, and would undo anything else
+        let cvars = &mut self.cvars;
+        let _ = cvars.set("r_bloom", settings.bloom.is_some());
+        if let Some(bloom) = settings.bloom {
+            let _ = cvars.set("r_bloom_threshold", bloom.threshold);
+            let _ = cvars.set("r_bloom_intensity", bloom.intensity);
+        }
+        let _ = cvars.set("r_ssao", settings.ambient_occlusion.is_some());
+        if let Some(ssao) = settings.ambient_occlusion {
+            let _ = cvars.set("r_ssao_radius", ssao.radius);
+            let _ = cvars.set("r_ssao_intensity", ssao.intensity);
+        }
+
+        let renderer = &mut self.vulkan_renderer;
+        renderer.camera = snapshot.camera;
+        renderer.clear_color = settings.clear_color;
+        renderer.lighting = settings.lighting;
+        if settings.bloom != renderer.bloom.settings
+            && let Err(error) = renderer.set_bloom(settings.bloom)
+        {
+            warn!("Bloom Unavailable: {error}");
+        }
+        if settings.ambient_occlusion != renderer.ambient_occlusion.settings
+            && let Err(error) = renderer.set_ambient_occlusion(settings.ambient_occlusion)
+        {
+            warn!("Ambient Occlusion Unavailable: {error}");
+        }
+        if settings.ray_query_shadows != renderer.ray_query_shadows.is_some()
+            && let Err(error) = renderer.set_ray_query_shadows(settings.ray_query_shadows)
+        {
+            warn!("Shadows Unavailable: {error}");
+        }
+        if settings.depth_convention != renderer.depth_convention() {
+            warn!(
+                "Snapshot Depth Convention {:?} Differs From Renderer's {:?}",
+                settings.depth_convention,
+                renderer.depth_convention()
+            );
+        }
+        let samples = renderer.vulkan_ctx.vulkan_swapchain.samples.as_raw();
+        if settings.msaa_samples != samples {
+            warn!(
+                "Snapshot MSAA {}x Differs From Renderer's {samples}x",
+                settings.msaa_samples
+            );
+        }
+        self.scene = snapshot.scene;
+    }
+
+    /// Call when a text field gains or loses focus
+    /// turns the platform input method on so CJK text can be composed, and keeps typing away from hotkeys
+    pub fn set_text_input(&mut self, active: bool) {
+        self.text_input.set_active(active);
+        self.window.set_ime_allowed(active);
+    }
+
+    /// Tells the input method where the text cursor is in window pixels
+    /// so its candidate window opens next to the text instead of a screen corner
+    pub fn set_text_cursor_area(&self, position: glam::Vec2, size: glam::Vec2) {
+        self.window.set_ime_cursor_area(
+            winit::dpi::PhysicalPosition::new(position.x, position.y),
+            winit::dpi::PhysicalSize::new(size.x, size.y),
+        );
+    }
+
+    // debug hotkeys for the game clock
+    fn control_time(&mut self, key_code: KeyCode) {
+        let clock = &mut self.clock;
+        match key_code {
+            KeyCode::KeyP => clock.toggle_pause(),
+            KeyCode::Period => clock.step(),
+            KeyCode::Minus => clock.time_scale *= 0.5,
+            KeyCode::Equal => clock.time_scale *= 2.0,
+            KeyCode::Digit0 => clock.time_scale = 1.0,
+            _ => return,
+        }
+        info!(
+            "Game Clock {} at {}x",
+            if clock.is_paused() {
+                "Paused"
+            } else {
+                "Running"
+            },
+            clock.time_scale
+        );
+    }
+
+    // ` opens the console, then keys edit and run its line until it closes
+    fn console_key(&mut self, key_code: KeyCode) {
+        let Some(line) = &mut self.console else {
+            if key_code == KeyCode::Backquote {
+                self.console = Some(String::new());
+                self.set_text_input(true);
+                // drop anything typed before it opened
+                self.text_input.take_committed();
+            }
+            return;
+        };
+        line.push_str(&self.text_input.take_committed());
+        match key_code {
+            KeyCode::KeyC if self.text_input.shortcut_held() && self.text_input.copy(line) => {
+                info!("Copied Console Line");
+            }
+            KeyCode::Backspace => {
+                line.pop();
+            }
+            KeyCode::Enter | KeyCode::NumpadEnter => {
+                let line = std::mem::take(line);
+                info!("> {line}");
+                match self.cvars.execute(&line) {
+                    Ok(printed) => printed.iter().for_each(|text| info!("{text}")),
+                    Err(err) => warn!("{err}"),
+                }
+            }
+            KeyCode::Escape | KeyCode::Backquote => {
+                self.console = None;
+                self.set_text_input(false);
+            }
+            _ => (),
+        }
+    }
+
+    // overrides are written on exit so the next run starts with them
+    fn save_cvars(&self) {
+        if let Err(err) = self.cvars.save_overrides(USER_CONFIG_PATH) {
+            error!("Failed to Save Console Variables: {err}");
+        }
+    }
+
+    fn quick_save(&self) {
+        match self.snapshot().save(QUICK_SAVE_PATH) {
+            Ok(()) => info!("Saved Snapshot: {QUICK_SAVE_PATH}"),
+            Err(err) => error!("Failed to Save Snapshot: {err}"),
+        }
+    }
+
+    fn quick_load(&mut self) {
+        match EngineSnapshot::load(QUICK_SAVE_PATH) {
+            Ok(snapshot) => {
+                self.restore(snapshot);
+                info!("Restored Snapshot: {QUICK_SAVE_PATH}");
+            }
+            Err(err) => error!("Failed to Restore Snapshot: {err}"),
+        }
+    }
+
+    // true once the smoke test has its result and the app should exit
+    fn run_smoke_test(&mut self) -> bool {
+        let Some(smoke_test) = &self.smoke_test else {
+            return false;
+        };
+        if self.frames_rendered < smoke_test.frames {
+            return false;
+        }
+
+        let result = self
+            .vulkan_renderer
+            .capture_frame(CaptureOptions::default())
+            .map_err(|err| SmokeTestError::Capture(err.to_string()))
+            .and_then(|capture| smoke_test.check(&capture));
+        match &result {
+            Ok(colors) => info!(
+                "Smoke Test Passed: {colors} colours in {}",
+                smoke_test.output.display()
+            ),
+            Err(err) => error!("Smoke Test Failed: {err}"),
+        }
+        self.smoke_test_result = Some(result);
+        true
+    }
+
+    // requests this frame's sizes before rendering it, the resize events land before the next one
+    fn resize_for_stress_test(&mut self) {
+        let Some(resize_stress) = &self.resize_stress else {
+            return;
+        };
+        let frame = self.frames_rendered;
+        if resize_stress.is_restore_frame(frame) {
+            let _ = self
+                .window
+                .request_inner_size(self.resize_stress_stats.baseline_size);
+        }
+        for size in resize_stress.sizes_at(frame) {
+            let _ = self.window.request_inner_size(size);
+        }
+    }
+
+    // true once the resize stress test has its result and the app should exit
+    fn run_resize_stress(&mut self, frame_time: std::time::Duration) -> bool {
+        let Some(resize_stress) = &self.resize_stress else {
+            return false;
+        };
+        let stats = &mut self.resize_stress_stats;
+        let vk_device = &self.vulkan_renderer.vulkan_ctx.vulkan_device;
+        stats.frames = self.frames_rendered;
+        stats.slowest_frame = stats.slowest_frame.max(frame_time);
+        stats.swapchain_rebuilds = self.vulkan_renderer.vulkan_present.swapchain_rebuilds();
+        stats.memory = vk_device.mem_allocator.report();
+        if resize_stress.is_baseline_frame(self.frames_rendered - 1) {
+            stats.baseline = stats.memory;
+            stats.baseline_size = self.window.inner_size();
+        }
+        if self.frames_rendered < resize_stress.total_frames() {
+            return false;
+        }
+
+        let result = resize_stress.check(stats);
+        match &result {
+            Ok(()) => info!(
+                "Resize Stress Test Passed: {} swapchain rebuilds in {} frames, slowest frame {:?}",
+                stats.swapchain_rebuilds, stats.frames, stats.slowest_frame
+            ),
+            Err(err) => error!("Resize Stress Test Failed: {err}"),
+        }
+        self.resize_stress_result = Some(result);
+        true
+    }
+
+    // pauses and frees the camera, or puts everything back
+    fn toggle_photo_mode(&mut self) {
+        let renderer = &mut self.vulkan_renderer;
+        match self.photo_mode.take() {
+            Some(photo_mode) => photo_mode.exit(renderer, &mut self.clock),
+            None => self.photo_mode = Some(PhotoMode::enter(renderer, &mut self.clock)),
+        }
+    }
+
+    // saves what this frame draws next to the executable, called just before rendering it
+    fn record_frame(&mut self) {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = format!("frame-{timestamp}.ron");
+
+        match self.vulkan_renderer.record_frame() {
+            Ok(recording) => match recording.save(&path) {
+                Ok(()) => info!("Saved Frame Recording: {path}"),
+                Err(err) => error!("Failed to Save Frame Recording: {err}"),
+            },
+            Err(err) => error!("Failed to Record Frame: {err}"),
+        }
+    }
+
+    // draws recording from now on, at the window size it was recorded at
+    fn start_replay(&mut self, recording: FrameRecording) {
+        let [width, height] = recording.extent;
+        let _ = self
+            .window
+            .request_inner_size(winit::dpi::PhysicalSize::new(width, height));
+        match self.vulkan_renderer.replay(&recording) {
+            Ok(()) => self.replay = Some(recording),
+            Err(err) => error!("Failed to Replay Frame: {err}"),
+        }
+    }
+
+    // 2x supersampled capture of the scene saved next to the executable
+    // photo mode captures at its own settings instead
+    fn screenshot(&mut self) {
+        let options = CaptureOptions::default().scale(2).downsample(true);
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = format!("screenshot-{timestamp}.ppm");
+
+        let capture = match &self.photo_mode {
+            Some(photo_mode) => photo_mode.capture(&mut self.vulkan_renderer),
+            None => self.vulkan_renderer.capture_frame(options),
+        };
+        match capture {
+            Ok(capture) => match capture.save_ppm(&path) {
+                Ok(()) => info!("Saved Screenshot: {path}"),
+                Err(err) => error!("Failed to Save Screenshot: {err}"),
+            },
+            Err(err) => error!("Failed to Capture Frame: {err}"),
+        }
+    }
+
+    // 360 screenshot from the camera saved next to the executable as an equirectangular image
+    fn panorama(&mut self) {
+        const PANORAMA_WIDTH: u32 = 4096;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = format!("panorama-{timestamp}.ppm");
+
+        let renderer = &mut self.vulkan_renderer;
+        let position = renderer.camera.position;
+        match renderer.capture_cubemap(position, PANORAMA_WIDTH / 4) {
+            Ok(cubemap) => match cubemap.to_equirect(PANORAMA_WIDTH).save_ppm(&path) {
+                Ok(()) => info!("Saved Panorama: {path}"),
+                Err(err) => error!("Failed to Save Panorama: {err}"),
+            },
+            Err(err) => error!("Failed to Capture Cubemap: {err}"),
         }
     }
 }
 
 pub enum App<'a> {
-    Initialised(AppCTX<'a>),
-    Uninitialised { game_info: GameInfo },
+    Initialised(Box<AppCTX<'a>>),
+    Uninitialised {
+        game_info: GameInfo,
+        demo_scene: Option<DemoScene>,
+        window_options: WindowOptions,
+        // the boxed fields keep the enum small, they're only read once
+        instance_options: Box<InstanceOptions>,
+        smoke_test: Option<SmokeTest>,
+        resize_stress: Option<Box<ResizeStress>>,
+        replay: Option<Box<FrameRecording>>,
+    },
 }
 
 impl ApplicationHandler for App<'_> {
@@ -57,47 +590,461 @@ impl ApplicationHandler for App<'_> {
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
-        _window_id: WindowId,
+        window_id: WindowId,
         event: WindowEvent,
     ) {
         match event {
             WindowEvent::CloseRequested => {
+                if let App::Initialised(app_ctx) = self {
+                    app_ctx.save_cvars();
+                    let frames = app_ctx.frames_rendered;
+                    if app_ctx.smoke_test.is_some() && app_ctx.smoke_test_result.is_none() {
+                        app_ctx.smoke_test_result = Some(Err(SmokeTestError::Interrupted(frames)));
+                    }
+                    if app_ctx.resize_stress.is_some() && app_ctx.resize_stress_result.is_none() {
+                        app_ctx.resize_stress_result =
+                            Some(Err(ResizeStressError::Interrupted(frames)));
+                    }
+                }
                 event_loop.exit();
             }
             WindowEvent::Resized(_size) => {
                 if let App::Initialised(app_ctx) = self {
                     // Window Resized
                     //info!("resized window");
-                    app_ctx.vulkan_renderer.vulkan_present.invalidate_swap();
+                    let renderer = &mut app_ctx.vulkan_renderer;
+                    match renderer.windows.get_mut(&window_id) {
+                        Some(window_target) => window_target.present.invalidate_swap(),
+                        None => renderer.vulkan_present.invalidate_swap(),
+                    }
                 }
             }
-            WindowEvent::RedrawRequested => {
+            WindowEvent::KeyboardInput { event, .. } => {
                 if let App::Initialised(app_ctx) = self {
-                    app_ctx.vulkan_renderer.render(&app_ctx.window);
-                    app_ctx.window.request_redraw();
+                    app_ctx.text_input.handle_key(&event);
+                    if let KeyEvent {
+                        physical_key: PhysicalKey::Code(key_code),
+                        state: ElementState::Pressed,
+                        ..
+                    } = event
+                        && (app_ctx.console.is_some() || key_code == KeyCode::Backquote)
+                    {
+                        app_ctx.console_key(key_code);
+                    } else if let KeyEvent {
+                        physical_key: PhysicalKey::Code(key_code),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    } = event
+                    {
+                        match key_code {
+                            KeyCode::F5 => app_ctx.quick_save(),
+                            KeyCode::F8 => app_ctx.toggle_photo_mode(),
+                            KeyCode::F9 => app_ctx.quick_load(),
+                            KeyCode::F10 => app_ctx.record_next_frame = true,
+                            KeyCode::F11 => app_ctx.panorama(),
+                            KeyCode::F12 => app_ctx.screenshot(),
+                            // typed letters belong to the focused text field
+                            _ if app_ctx.text_input.is_active() => (),
+                            _ => app_ctx.control_time(key_code),
+                        }
+                    }
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                if let App::Initialised(app_ctx) = self {
+                    app_ctx.text_input.set_modifiers(modifiers.state());
+                }
+            }
+            WindowEvent::Ime(ime) => {
+                if let App::Initialised(app_ctx) = self {
+                    app_ctx.text_input.handle_ime(&ime);
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                // windows added to the renderer are presented by render_window
+                if let App::Initialised(app_ctx) = self
+                    && window_id == app_ctx.window.id()
+                {
+                    let now = std::time::Instant::now();
+                    // paused or slowed time still presents every frame
+                    app_ctx.clock.tick(now);
+                    #[cfg(feature = "gamepad")]
+                    if let Some(gamepads) = &mut app_ctx.gamepads {
+                        gamepads.update();
+                    }
+                    let time = app_ctx.clock.elapsed() as f32;
+                    // lods are picked for this frame's camera
+                    match (&app_ctx.photo_mode, &app_ctx.replay) {
+                        (Some(photo_mode), _) => photo_mode.apply(&mut app_ctx.vulkan_renderer),
+                        // replays keep the recorded camera
+                        (None, Some(_)) => (),
+                        (None, None) => app_ctx.update_camera(time),
+                    }
+                    let renderer = &mut app_ctx.vulkan_renderer;
+                    renderer.instances = match (&app_ctx.replay, &app_ctx.demo_scene) {
+                        (Some(recording), _) => {
+                            // the lines are immediate mode so they go in again every frame
+                            renderer
+                                .debug_draw
+                                .vertices
+                                .extend_from_slice(&recording.debug_lines);
+                            std::mem::take(&mut renderer.instances)
+                        }
+                        (None, Some(demo_scene)) => demo_scene.instances_at(time),
+                        (None, None) => {
+                            app_ctx.scene.update_world_matrices();
+                            let cvars = &app_ctx.cvars;
+                            let vertex_budget = cvars.get_int("r_vertex_budget").unwrap_or(0);
+                            let selector = LodSelector::new(&renderer.camera)
+                                .with_bias(cvars.get_float("r_lod_bias").unwrap_or(1.0))
+                                .with_vertex_budget(
+                                    (vertex_budget > 0).then_some(vertex_budget as u32),
+                                );
+                            let meshes = &renderer.meshes;
+                            app_ctx.scene.mesh_instances_with_lod(&selector, |mesh| {
+                                meshes.get(mesh).map(|mesh| LodMeshInfo {
+                                    bounds: mesh.bounds,
+                                    vertex_count: mesh.vertex_count,
+                                })
+                            })
+                        }
+                    };
+                    let renderer = &mut app_ctx.vulkan_renderer;
+                    if app_ctx.cvars.get_bool("dbg_validate_scene") == Some(true) {
+                        match renderer.validate_scene() {
+                            Ok(()) => info!("Scene Has No Issues"),
+                            Err(error) => warn!("{error}"),
+                        }
+                        let _ = app_ctx.cvars.set("dbg_validate_scene", false);
+                    }
+                    if app_ctx.cvars.get_bool("dbg_draw_bounds") == Some(true) {
+                        for instance in &renderer.instances {
+                            if let Some(mesh) = renderer.meshes.get(instance.mesh) {
+                                let bounds = mesh.bounds.transformed(&instance.transform);
+                                renderer.debug_draw.aabb(
+                                    bounds.min,
+                                    bounds.max,
+                                    LinearRgba::rgb(0.0, 1.0, 0.0),
+                                );
+                            }
+                        }
+                    }
+                    app_ctx.resize_for_stress_test();
+                    let renderer = &mut app_ctx.vulkan_renderer;
+                    let gpu_culling = app_ctx.cvars.get_bool("r_gpu_culling") == Some(true);
+                    if gpu_culling != renderer.gpu_culling.is_some()
+                        && let Err(error) = renderer.set_gpu_culling(gpu_culling)
+                    {
+                        warn!("GPU Culling Unavailable: {error}");
+                        let _ = app_ctx.cvars.set("r_gpu_culling", false);
+                    }
+                    let ssao = (app_ctx.cvars.get_bool("r_ssao") == Some(true)).then(|| {
+                        (renderer.ambient_occlusion.settings)
+                            .unwrap_or_default()
+                            .radius(app_ctx.cvars.get_float("r_ssao_radius").unwrap_or(0.5))
+                            .intensity(app_ctx.cvars.get_float("r_ssao_intensity").unwrap_or(1.0))
+                    });
+                    if ssao != renderer.ambient_occlusion.settings
+                        && let Err(error) = renderer.set_ambient_occlusion(ssao)
+                    {
+                        warn!("Ambient Occlusion Unavailable: {error}");
+                        let _ = app_ctx.cvars.set("r_ssao", false);
+                    }
+                    let bloom = (app_ctx.cvars.get_bool("r_bloom") == Some(true)).then(|| {
+                        (renderer.bloom.settings)
+                            .unwrap_or_default()
+                            .threshold(app_ctx.cvars.get_float("r_bloom_threshold").unwrap_or(0.8))
+                            .intensity(app_ctx.cvars.get_float("r_bloom_intensity").unwrap_or(0.6))
+                    });
+                    if bloom != renderer.bloom.settings
+                        && let Err(error) = renderer.set_bloom(bloom)
+                    {
+                        warn!("Bloom Unavailable: {error}");
+                        let _ = app_ctx.cvars.set("r_bloom", false);
+                    }
+                    renderer.renderer2d.ui_scale =
+                        app_ctx.cvars.get_float("ui_scale").unwrap_or(1.0);
+                    if std::mem::take(&mut app_ctx.record_next_frame) {
+                        app_ctx.record_frame();
+                    }
+                    let renderer = &mut app_ctx.vulkan_renderer;
+                    renderer.render(&app_ctx.window);
+                    app_ctx.frames_rendered += 1;
+                    // the next frame is requested in about_to_wait, once the pacer allows it
+                    if app_ctx.run_smoke_test() || app_ctx.run_resize_stress(now.elapsed()) {
+                        event_loop.exit();
+                    }
                 }
             }
             _ => (),
         }
     }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if let App::Initialised(app_ctx) = self {
+            let now = std::time::Instant::now();
+            match app_ctx
+                .vulkan_renderer
+                .vulkan_present
+                .paced_frame_start(now)
+            {
+                Some(next_frame) => event_loop.set_control_flow(ControlFlow::WaitUntil(next_frame)),
+                None => {
+                    event_loop.set_control_flow(ControlFlow::Poll);
+                    app_ctx.window.request_redraw();
+                }
+            }
+        }
+    }
+}
+
+/// Console variables the engine reads every frame
+pub fn engine_cvars() -> CVars {
+    let mut cvars = CVars::default();
+    cvars
+        .register(
+            CVar::new(
+                "r_vertex_budget",
+                0i64,
+                "most vertices the scene should draw, nodes with lods step down to fit, 0 is unlimited",
+            )
+            .with_range(0.0, u32::MAX as f64)
+            .with_flags(CVarFlags::ARCHIVE),
+        )
+        .register(
+            CVar::new(
+                "r_lod_bias",
+                1.0_f32,
+                "multiplies lod coverage, above 1 keeps detail further away",
+            )
+            .with_range(0.1, 10.0)
+            .with_flags(CVarFlags::ARCHIVE),
+        )
+        .register(
+            CVar::new(
+                "r_gpu_culling",
+                false,
+                "culls and batches the scene's draws on the gpu, turns itself off where unsupported",
+            )
+            .with_flags(CVarFlags::ARCHIVE),
+        )
+        .register(
+            CVar::new(
+                "r_ssao",
+                false,
+                "darkens creases and corners, needs an internal resolution without msaa",
+            )
+            .with_flags(CVarFlags::ARCHIVE),
+        )
+        .register(
+            CVar::new(
+                "r_ssao_radius",
+                0.5_f32,
+                "world space distance geometry darkens its surroundings from",
+            )
+            .with_range(0.05, 8.0)
+            .with_flags(CVarFlags::ARCHIVE),
+        )
+        .register(
+            CVar::new(
+                "r_ssao_intensity",
+                1.0_f32,
+                "how dark ambient occlusion gets",
+            )
+            .with_range(0.0, 4.0)
+            .with_flags(CVarFlags::ARCHIVE),
+        )
+        .register(
+            CVar::new(
+                "r_bloom",
+                false,
+                "blurs the brightest parts of the scene onto it, needs an internal resolution",
+            )
+            .with_flags(CVarFlags::ARCHIVE),
+        )
+        .register(
+            CVar::new(
+                "r_bloom_threshold",
+                0.8_f32,
+                "brightness the scene starts to bloom at, above 1 only the hdr highlights bloom",
+            )
+            .with_range(0.0, f32::MAX as f64)
+            .with_flags(CVarFlags::ARCHIVE),
+        )
+        .register(
+            CVar::new(
+                "r_bloom_intensity",
+                0.6_f32,
+                "how strongly bloom is added onto the scene",
+            )
+            .with_range(0.0, 4.0)
+            .with_flags(CVarFlags::ARCHIVE),
+        )
+        .register(
+            CVar::new("ui_scale", 1.0_f32, "multiplies the size of sprites and the ui")
+                .with_range(0.5, 4.0)
+                .with_flags(CVarFlags::ARCHIVE),
+        )
+        .register(
+            CVar::new("dbg_draw_bounds", false, "draws the bounds of every mesh instance")
+                .with_flags(CVarFlags::DEV),
+        )
+        .register(
+            CVar::new(
+                "dbg_validate_scene",
+                false,
+                "checks the next frame's instances and logs any issues, then turns itself off",
+            )
+            .with_flags(CVarFlags::DEV),
+        );
+    cvars
+}
+
+// engine cvars with the config and saved overrides applied, missing files are skipped
+fn load_cvars() -> CVars {
+    let mut cvars = engine_cvars();
+    for path in [CONFIG_PATH, USER_CONFIG_PATH] {
+        if std::path::Path::new(path).exists()
+            && let Err(err) = cvars.exec_file(path)
+        {
+            warn!("Failed to Run {path}: {err}");
+        }
+    }
+    cvars
 }
 
 impl<F> ReplaceWith<F> for App<'_> {}
 
 impl App<'_> {
     pub fn new(game_info: GameInfo) -> Self {
-        App::Uninitialised { game_info }
+        App::Uninitialised {
+            game_info,
+            demo_scene: None,
+            window_options: WindowOptions::default(),
+            instance_options: Box::default(),
+            smoke_test: None,
+            resize_stress: None,
+            replay: None,
+        }
+    }
+
+    /// Renders demo_scene instead of the default cube
+    pub fn with_demo_scene(game_info: GameInfo, demo_scene: DemoScene) -> Self {
+        App::Uninitialised {
+            game_info,
+            demo_scene: Some(demo_scene),
+            window_options: WindowOptions::default(),
+            instance_options: Box::default(),
+            smoke_test: None,
+            resize_stress: None,
+            replay: None,
+        }
+    }
+
+    /// Window size and transparency, only used before the window is created
+    pub fn with_window_options(mut self, options: WindowOptions) -> Self {
+        if let App::Uninitialised { window_options, .. } = &mut self {
+            *window_options = options;
+        }
+        self
+    }
+
+    /// Validation, debug messenger and optional layers, only used before the window is created
+    pub fn with_instance_options(mut self, options: InstanceOptions) -> Self {
+        if let App::Uninitialised {
+            instance_options, ..
+        } = &mut self
+        {
+            **instance_options = options;
+        }
+        self
+    }
+
+    /// Captures a frame after a few frames and exits, for checking drivers and platforms work
+    /// see exit_code for the result
+    pub fn with_smoke_test(mut self, options: SmokeTest) -> Self {
+        if let App::Uninitialised { smoke_test, .. } = &mut self {
+            *smoke_test = Some(options);
+        }
+        self
+    }
+
+    /// Resizes the window every frame then exits, for checking swapchain rebuilds don't leak or hang
+    /// see exit_code for the result
+    pub fn with_resize_stress(mut self, options: ResizeStress) -> Self {
+        if let App::Uninitialised { resize_stress, .. } = &mut self {
+            *resize_stress = Some(Box::new(options));
+        }
+        self
+    }
+
+    /// Draws a frame recorded with F10 or VKRenderer::record_frame instead of the scene
+    pub fn with_replay(mut self, recording: FrameRecording) -> Self {
+        if let App::Uninitialised { replay, .. } = &mut self {
+            *replay = Some(Box::new(recording));
+        }
+        self
+    }
+
+    /// Process exit code once start returns, non zero when a smoke or stress test failed or never finished
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            App::Initialised(app_ctx) => {
+                let smoke_test = match (&app_ctx.smoke_test, &app_ctx.smoke_test_result) {
+                    (None, _) | (Some(_), Some(Ok(_))) => 0,
+                    (Some(_), Some(Err(err))) => err.exit_code(),
+                    (Some(_), None) => 1,
+                };
+                let resize_stress = match (&app_ctx.resize_stress, &app_ctx.resize_stress_result) {
+                    (None, _) | (Some(_), Some(Ok(_))) => 0,
+                    (Some(_), Some(Err(err))) => err.exit_code(),
+                    (Some(_), None) => 1,
+                };
+                if smoke_test != 0 {
+                    smoke_test
+                } else {
+                    resize_stress
+                }
+            }
+            // the window was never created
+            App::Uninitialised {
+                smoke_test,
+                resize_stress,
+                ..
+            } => (smoke_test.is_some() || resize_stress.is_some()) as i32,
+        }
     }
 
     fn init(&mut self, event_loop: &ActiveEventLoop) {
         self.replace_with(|state| match state {
             Self::Initialised(_) => panic!(),
-            Self::Uninitialised { game_info } => {
+            Self::Uninitialised {
+                game_info,
+                demo_scene,
+                window_options,
+                instance_options,
+                smoke_test,
+                resize_stress,
+                replay,
+            } => {
                 info!(
                     "Initialising Game: {}",
                     game_info.app_name.to_string_lossy()
                 );
-                Self::Initialised(AppCTX::new(game_info, event_loop))
+                let mut app_ctx = Box::new(AppCTX::new(
+                    game_info,
+                    demo_scene,
+                    window_options,
+                    *instance_options,
+                    smoke_test,
+                    resize_stress.map(|resize_stress| *resize_stress),
+                    event_loop,
+                ));
+                if let Some(recording) = replay {
+                    app_ctx.start_replay(*recording);
+                }
+                Self::Initialised(app_ctx)
             }
         });
     }
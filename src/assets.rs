@@ -0,0 +1,361 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+use thiserror::Error;
+
+/// Bumped whenever the asset metadata file changes in a way old files can't be read as
+pub const ASSET_METADATA_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum AssetError {
+    #[error("failed to access asset: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to parse asset metadata: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("failed to write asset metadata: {0}")]
+    Serialize(#[from] ron::Error),
+    #[error("asset metadata version {found} can't be read, expected {ASSET_METADATA_VERSION}")]
+    Version { found: u32 },
+    #[error("{} references missing {}", .0.asset.display(), .0.missing.display())]
+    BrokenReference(BrokenReference),
+}
+
+/// FNV-1a of an asset's bytes, assets are rebuilt when it changes
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AssetKind {
+    Material,
+    Texture,
+    Mesh,
+    Scene,
+    Shader,
+    Other,
+}
+
+/// What is known about an asset file, dependencies are the assets it references
+/// like a material's textures or a scene's meshes
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetMeta {
+    pub kind: AssetKind,
+    pub hash: u64,
+    pub dependencies: Vec<PathBuf>,
+}
+
+/// An asset referencing a file that doesn't exist
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BrokenReference {
+    pub asset: PathBuf,
+    pub missing: PathBuf,
+}
+
+/// Content hashes of loaded assets and the references between them
+/// saved next to caches so a later run can tell what changed while it wasn't running
+/// Example Use:
+/// ```ignore
+/// let mut assets = AssetGraph::load("cache/assets.ron").unwrap_or_default();
+/// assets.register_file("materials/rock.ron", AssetKind::Material, vec!["textures/rock.png".into()])?;
+/// assets.check_references("materials/rock.ron")?;
+/// // after files change on disk, rebuild rock.png and then rock.ron, in that order
+/// for path in assets.refresh() {
+///     reload(&path)?;
+/// }
+/// assets.save("cache/assets.ron")?;
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetGraph {
+    pub version: u32,
+    assets: BTreeMap<PathBuf, AssetMeta>,
+}
+
+impl Default for AssetGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AssetGraph {
+    pub fn new() -> Self {
+        Self {
+            version: ASSET_METADATA_VERSION,
+            assets: BTreeMap::new(),
+        }
+    }
+
+    /// Records an asset and what it references, replacing what was known about it before
+    pub fn register(
+        &mut self,
+        path: impl Into<PathBuf>,
+        kind: AssetKind,
+        hash: u64,
+        dependencies: Vec<PathBuf>,
+    ) {
+        self.assets.insert(
+            path.into(),
+            AssetMeta {
+                kind,
+                hash,
+                dependencies,
+            },
+        );
+    }
+
+    /// register with the hash of the file's current contents, returns the hash
+    pub fn register_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        kind: AssetKind,
+        dependencies: Vec<PathBuf>,
+    ) -> io::Result<u64> {
+        let path = path.as_ref();
+        let hash = content_hash(&fs::read(path)?);
+        self.register(path, kind, hash, dependencies);
+        Ok(hash)
+    }
+
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<&AssetMeta> {
+        self.assets.get(path.as_ref())
+    }
+
+    pub fn remove(&mut self, path: impl AsRef<Path>) -> Option<AssetMeta> {
+        self.assets.remove(path.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &AssetMeta)> {
+        self.assets
+            .iter()
+            .map(|(path, meta)| (path.as_path(), meta))
+    }
+
+    /// Whether bytes differ from what the asset was registered with, unknown assets have changed
+    pub fn has_changed(&self, path: impl AsRef<Path>, bytes: &[u8]) -> bool {
+        self.get(path)
+            .is_none_or(|meta| meta.hash != content_hash(bytes))
+    }
+
+    /// Assets referencing path directly or through other assets
+    pub fn dependents(&self, path: impl AsRef<Path>) -> Vec<PathBuf> {
+        let root = path.as_ref();
+        self.rebuild_order([root])
+            .into_iter()
+            .filter(|dependent| dependent != root)
+            .collect()
+    }
+
+    /// changed along with every asset depending on them, each after everything it depends on
+    /// so rebuilding in order never reads a stale dependency
+    pub fn rebuild_order<'p>(&self, changed: impl IntoIterator<Item = &'p Path>) -> Vec<PathBuf> {
+        // reverse edges, dependency -> assets referencing it
+        let mut dependents: BTreeMap<&Path, Vec<&Path>> = BTreeMap::new();
+        for (path, meta) in &self.assets {
+            for dependency in &meta.dependencies {
+                dependents
+                    .entry(dependency.as_path())
+                    .or_default()
+                    .push(path.as_path());
+            }
+        }
+
+        let mut affected = BTreeSet::new();
+        let mut pending: Vec<&Path> = changed.into_iter().collect();
+        while let Some(path) = pending.pop() {
+            if affected.insert(path) {
+                pending.extend(dependents.get(path).into_iter().flatten());
+            }
+        }
+
+        // depth first on dependencies, an asset goes after everything affected it references
+        let mut order = Vec::with_capacity(affected.len());
+        let mut visited = BTreeSet::new();
+        for path in &affected {
+            self.visit_dependencies(path, &affected, &mut visited, &mut order);
+        }
+        order
+    }
+
+    fn visit_dependencies<'g>(
+        &'g self,
+        path: &'g Path,
+        affected: &BTreeSet<&Path>,
+        visited: &mut BTreeSet<&'g Path>,
+        order: &mut Vec<PathBuf>,
+    ) {
+        // a reference cycle is broken wherever it was entered
+        if !visited.insert(path) {
+            return;
+        }
+        if let Some(meta) = self.assets.get(path) {
+            for dependency in &meta.dependencies {
+                if affected.contains(dependency.as_path()) {
+                    self.visit_dependencies(dependency, affected, visited, order);
+                }
+            }
+        }
+        order.push(path.to_path_buf());
+    }
+
+    /// Re-hashes every registered file, returns what has to be rebuilt in rebuild_order
+    /// files that can't be read are left alone, see broken_references
+    pub fn refresh(&mut self) -> Vec<PathBuf> {
+        let changed: Vec<PathBuf> = self
+            .assets
+            .iter_mut()
+            .filter_map(|(path, meta)| {
+                let hash = content_hash(&fs::read(path).ok()?);
+                (hash != meta.hash).then(|| {
+                    meta.hash = hash;
+                    path.clone()
+                })
+            })
+            .collect();
+        self.rebuild_order(changed.iter().map(PathBuf::as_path))
+    }
+
+    /// References to files that exist neither on disk nor as registered assets
+    pub fn broken_references(&self) -> Vec<BrokenReference> {
+        self.assets
+            .iter()
+            .flat_map(|(path, meta)| {
+                meta.dependencies
+                    .iter()
+                    .filter(|dependency| {
+                        !self.assets.contains_key(*dependency) && !dependency.exists()
+                    })
+                    .map(|dependency| BrokenReference {
+                        asset: path.clone(),
+                        missing: dependency.clone(),
+                    })
+            })
+            .collect()
+    }
+
+    /// Fails on the first reference of path or its dependencies to a missing file
+    /// meant for load time, so a typo in a material names the texture instead of failing later
+    pub fn check_references(&self, path: impl AsRef<Path>) -> Result<(), AssetError> {
+        let path = path.as_ref();
+        let mut pending = vec![path];
+        let mut visited = BTreeSet::new();
+        while let Some(asset) = pending.pop() {
+            if !visited.insert(asset) {
+                continue;
+            }
+            let Some(meta) = self.assets.get(asset) else {
+                continue;
+            };
+            for dependency in &meta.dependencies {
+                if !self.assets.contains_key(dependency) && !dependency.exists() {
+                    return Err(AssetError::BrokenReference(BrokenReference {
+                        asset: asset.to_path_buf(),
+                        missing: dependency.clone(),
+                    }));
+                }
+                pending.push(dependency);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_ron(&self) -> Result<String, AssetError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    /// Fails on metadata from another ASSET_METADATA_VERSION
+    pub fn from_ron(source: &str) -> Result<Self, AssetError> {
+        let graph: Self = ron::from_str(source)?;
+        if graph.version != ASSET_METADATA_VERSION {
+            return Err(AssetError::Version {
+                found: graph.version,
+            });
+        }
+        Ok(graph)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), AssetError> {
+        Ok(fs::write(path, self.to_ron()?)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AssetError> {
+        Self::from_ron(&fs::read_to_string(path)?)
+    }
+}
+
+#[test]
+fn assets_test() {
+    let mut assets = AssetGraph::new();
+    assets.register(
+        "scene.ron",
+        AssetKind::Scene,
+        1,
+        vec!["rock.ron".into(), "rock.alcm".into()],
+    );
+    assets.register("rock.ron", AssetKind::Material, 2, vec!["rock.png".into()]);
+    let pixels = content_hash(b"pixels");
+    assets.register("rock.png", AssetKind::Texture, pixels, vec![]);
+    assets.register("rock.alcm", AssetKind::Mesh, 4, vec![]);
+    assets.register("tree.ron", AssetKind::Material, 5, vec!["bark.png".into()]);
+
+    // a texture change reaches the scene through its material, dependencies come first
+    assert_eq!(
+        assets.rebuild_order([Path::new("rock.png")]),
+        [
+            PathBuf::from("rock.png"),
+            PathBuf::from("rock.ron"),
+            PathBuf::from("scene.ron")
+        ]
+    );
+    assert_eq!(assets.dependents("rock.alcm"), [PathBuf::from("scene.ron")]);
+    assert!(assets.dependents("scene.ron").is_empty());
+
+    assert!(!assets.has_changed("rock.png", b"pixels"));
+    assert!(assets.has_changed("rock.png", b"other pixels"));
+    assert!(assets.has_changed("unknown.png", b"pixels"));
+
+    // bark.png was never registered and isn't on disk
+    assert_eq!(
+        assets.broken_references(),
+        [BrokenReference {
+            asset: "tree.ron".into(),
+            missing: "bark.png".into(),
+        }]
+    );
+    assert!(assets.check_references("scene.ron").is_ok());
+    assert!(matches!(
+        assets.check_references("tree.ron"),
+        Err(AssetError::BrokenReference(_))
+    ));
+
+    // cycles don't loop forever
+    assets.register("a", AssetKind::Other, 0, vec!["b".into()]);
+    assets.register("b", AssetKind::Other, 0, vec!["a".into()]);
+    assert_eq!(assets.rebuild_order([Path::new("a")]).len(), 2);
+
+    let restored = AssetGraph::from_ron(&assets.to_ron().unwrap()).unwrap();
+    assert_eq!(restored, assets);
+
+    let directory = std::env::temp_dir().join(format!("alcor-assets-test-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let texture = directory.join("grass.png");
+    let material = directory.join("grass.ron");
+    fs::write(&texture, b"old pixels").unwrap();
+    fs::write(&material, b"material").unwrap();
+    let mut assets = AssetGraph::new();
+    assets
+        .register_file(&texture, AssetKind::Texture, vec![])
+        .unwrap();
+    assets
+        .register_file(&material, AssetKind::Material, vec![texture.clone()])
+        .unwrap();
+    assert!(assets.refresh().is_empty());
+    fs::write(&texture, b"new pixels").unwrap();
+    assert_eq!(assets.refresh(), [texture.clone(), material.clone()]);
+    assert!(assets.refresh().is_empty());
+    fs::remove_dir_all(&directory).unwrap();
+}
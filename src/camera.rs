@@ -0,0 +1,315 @@
+use glam::{Mat4, Quat, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+/// Which way depth runs in the depth buffer, set once with RendererOptions::depth_convention
+/// projections are built reversed, Forward flips them with clip_transform after the fact
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DepthConvention {
+    /// 1 at the near plane and 0 at the far plane, float precision is spent where it's needed
+    /// cleared to 0 and tested with GREATER_OR_EQUAL
+    #[default]
+    ReverseZ,
+    /// 0 at the near plane and 1 at the far plane, cleared to 1 and tested with LESS_OR_EQUAL
+    Forward,
+}
+
+impl DepthConvention {
+    /// Depth of the near plane
+    pub fn near_depth(self) -> f32 {
+        match self {
+            DepthConvention::ReverseZ => 1.0,
+            DepthConvention::Forward => 0.0,
+        }
+    }
+
+    /// Depth of the far plane, what the depth buffer is cleared to
+    pub fn far_depth(self) -> f32 {
+        1.0 - self.near_depth()
+    }
+
+    /// 1 when depth grows towards the camera, multiplying by it makes nearer always greater
+    pub fn sign(self) -> f32 {
+        match self {
+            DepthConvention::ReverseZ => 1.0,
+            DepthConvention::Forward => -1.0,
+        }
+    }
+
+    /// The nearer of two depths
+    pub fn nearer(self, a: f32, b: f32) -> f32 {
+        if a * self.sign() >= b * self.sign() {
+            a
+        } else {
+            b
+        }
+    }
+
+    /// Applied after a Projection::matrix, maps reverse depth onto this convention
+    pub fn clip_transform(self) -> Mat4 {
+        match self {
+            DepthConvention::ReverseZ => Mat4::IDENTITY,
+            // z' = w - z
+            DepthConvention::Forward => Mat4::from_cols(
+                Vec4::X,
+                Vec4::Y,
+                Vec4::new(0.0, 0.0, -1.0, 0.0),
+                Vec4::new(0.0, 0.0, 1.0, 1.0),
+            ),
+        }
+    }
+}
+
+/// How the camera maps view space onto the screen
+/// both use reverse z, depth is 1 at the near plane and 0 at the far plane
+/// see DepthConvention for flipping that
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Projection {
+    /// fov_y in radians, the far plane is infinite unless z_far is given
+    Perspective {
+        fov_y: f32,
+        z_near: f32,
+        #[serde(default)]
+        z_far: Option<f32>,
+    },
+    /// height of the view volume in world units, width follows the aspect ratio
+    Orthographic {
+        height: f32,
+        z_near: f32,
+        z_far: f32,
+    },
+}
+
+impl Projection {
+    /// Projection matrix for vulkan clip space, y points down the screen
+    pub fn matrix(&self, aspect_ratio: f32) -> Mat4 {
+        let mut projection = match *self {
+            Projection::Perspective {
+                fov_y,
+                z_near,
+                z_far: None,
+            } => Mat4::perspective_infinite_reverse_rh(fov_y, aspect_ratio, z_near),
+            // near and far swapped for reverse z
+            Projection::Perspective {
+                fov_y,
+                z_near,
+                z_far: Some(z_far),
+            } => Mat4::perspective_rh(fov_y, aspect_ratio, z_far, z_near),
+            Projection::Orthographic {
+                height,
+                z_near,
+                z_far,
+            } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * aspect_ratio;
+                // near and far swapped for reverse z
+                Mat4::orthographic_rh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    z_far,
+                    z_near,
+                )
+            }
+        };
+        projection.y_axis.y *= -1.0;
+        projection
+    }
+}
+
+/// A viewpoint into the scene
+/// looks down -z of its rotation like the rest of glam's right handed helpers
+/// Example Use:
+/// ```
+/// use glam::Vec3;
+/// use vulkan_engine::camera::Camera;
+///
+/// let camera = Camera::perspective(70.0_f32.to_radians(), 0.1)
+///     .look_at(Vec3::new(0.0, 2.0, 5.0), Vec3::ZERO, Vec3::Y);
+/// let view_projection = camera.view_projection(16.0 / 9.0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Camera {
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub projection: Projection,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::perspective(70.0_f32.to_radians(), 0.1)
+    }
+}
+
+impl Camera {
+    /// Perspective camera at the origin looking down -z, with an infinite far plane
+    pub fn perspective(fov_y: f32, z_near: f32) -> Self {
+        Self {
+            position: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            projection: Projection::Perspective {
+                fov_y,
+                z_near,
+                z_far: None,
+            },
+        }
+    }
+
+    /// Gives a perspective camera a far plane, orthographic cameras always have one
+    /// only needed when something relies on depth stopping somewhere, e.g. fog read back from depth
+    pub fn far_plane(mut self, far: f32) -> Self {
+        if let Projection::Perspective { z_far, .. } = &mut self.projection {
+            *z_far = Some(far);
+        }
+        self
+    }
+
+    /// Orthographic camera at the origin looking down -z
+    pub fn orthographic(height: f32, z_near: f32, z_far: f32) -> Self {
+        Self {
+            position: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            projection: Projection::Orthographic {
+                height,
+                z_near,
+                z_far,
+            },
+        }
+    }
+
+    /// Moves the camera to position looking in direction
+    pub fn look_to(mut self, position: Vec3, direction: Vec3, up: Vec3) -> Self {
+        let (_, rotation, _) = Mat4::look_to_rh(position, direction, up)
+            .inverse()
+            .to_scale_rotation_translation();
+        self.position = position;
+        self.rotation = rotation;
+        self
+    }
+
+    /// Moves the camera to position looking at target
+    pub fn look_at(self, position: Vec3, target: Vec3, up: Vec3) -> Self {
+        self.look_to(position, target - position, up)
+    }
+
+    /// Places the camera radius away from target, yaw and pitch in radians
+    /// yaw turns around the y axis, negative pitch looks down on the target
+    pub fn orbit(mut self, target: Vec3, yaw: f32, pitch: f32, radius: f32) -> Self {
+        self.rotation = Quat::from_rotation_y(yaw) * Quat::from_rotation_x(pitch);
+        self.position = target + self.rotation * Vec3::new(0.0, 0.0, radius);
+        self
+    }
+
+    /// Direction the camera is looking
+    pub fn forward(&self) -> Vec3 {
+        self.rotation * Vec3::NEG_Z
+    }
+
+    /// World to view space
+    pub fn view(&self) -> Mat4 {
+        Mat4::from_rotation_translation(self.rotation, self.position).inverse()
+    }
+
+    pub fn view_projection(&self, aspect_ratio: f32) -> Mat4 {
+        self.projection.matrix(aspect_ratio) * self.view()
+    }
+
+    /// Matrices for the per frame camera uniform buffer
+    pub fn uniform(&self, aspect_ratio: f32) -> CameraUniform {
+        let view = self.view();
+        let projection = self.projection.matrix(aspect_ratio);
+        CameraUniform {
+            view,
+            projection,
+            view_projection: projection * view,
+            position: self.position.extend(1.0),
+        }
+    }
+}
+
+/// Camera data as the shaders see it, matches CameraUniform in triangle.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraUniform {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub view_projection: Mat4,
+    /// w is always 1
+    pub position: Vec4,
+}
+
+impl CameraUniform {
+    /// Applies clip_transform after the projection, e.g. swapchain pre rotation
+    pub fn with_clip_transform(mut self, clip_transform: Mat4) -> Self {
+        self.projection = clip_transform * self.projection;
+        self.view_projection = clip_transform * self.view_projection;
+        self
+    }
+}
+
+#[test]
+fn camera_test() {
+    let camera = Camera::perspective(90.0_f32.to_radians(), 0.1).look_at(
+        Vec3::new(0.0, 0.0, 5.0),
+        Vec3::ZERO,
+        Vec3::Y,
+    );
+    assert!(camera.forward().abs_diff_eq(Vec3::NEG_Z, 1e-5));
+
+    // target lands in the centre of the screen
+    let clip = camera.view_projection(1.0) * Vec4::new(0.0, 0.0, 0.0, 1.0);
+    assert!((clip.x / clip.w).abs() < 1e-5 && (clip.y / clip.w).abs() < 1e-5);
+
+    // reverse z, the near plane is at depth 1
+    let near = camera.view_projection(1.0) * Vec4::new(0.0, 0.0, 4.9, 1.0);
+    assert!((near.z / near.w - 1.0).abs() < 1e-4);
+
+    // vulkan y points down so something above the target is in the top half
+    let above = camera.view_projection(1.0) * Vec4::new(0.0, 1.0, 0.0, 1.0);
+    assert!(above.y / above.w < 0.0);
+
+    // orbiting keeps the target in front of the camera
+    let orbit = Camera::default().orbit(Vec3::Y, 1.0, -0.3, 3.0);
+    assert!((orbit.position.distance(Vec3::Y) - 3.0).abs() < 1e-5);
+    let to_target = (Vec3::Y - orbit.position).normalize();
+    assert!(orbit.forward().abs_diff_eq(to_target, 1e-5));
+}
+
+#[test]
+fn orthographic_test() {
+    let camera = Camera::orthographic(4.0, 0.0, 10.0);
+    let view_projection = camera.view_projection(2.0);
+
+    let corner = view_projection * Vec4::new(4.0, 2.0, -10.0, 1.0);
+    assert!(corner.abs_diff_eq(Vec4::new(1.0, -1.0, 0.0, 1.0), 1e-5));
+    let near = view_projection * Vec4::new(0.0, 0.0, 0.0, 1.0);
+    assert!((near.z - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn depth_convention_test() {
+    let camera = Camera::perspective(90.0_f32.to_radians(), 0.1).look_at(
+        Vec3::new(0.0, 0.0, 5.0),
+        Vec3::ZERO,
+        Vec3::Y,
+    );
+    let depth = |convention: DepthConvention, camera: &Camera, z: f32| {
+        let clip =
+            convention.clip_transform() * camera.view_projection(1.0) * Vec4::new(0.0, 0.0, z, 1.0);
+        clip.z / clip.w
+    };
+
+    // forward puts the near plane at 0 and runs out towards 1
+    let forward = DepthConvention::Forward;
+    assert!((depth(forward, &camera, 4.9) - forward.near_depth()).abs() < 1e-4);
+    assert!(depth(forward, &camera, -1000.0) < forward.far_depth());
+    assert!(depth(forward, &camera, 0.0) < depth(forward, &camera, -10.0));
+    assert_eq!(forward.nearer(0.2, 0.7), 0.2);
+    assert_eq!(DepthConvention::default().nearer(0.2, 0.7), 0.7);
+
+    // a far plane stops depth there in either convention
+    let bounded = camera.far_plane(105.0);
+    assert!(depth(DepthConvention::ReverseZ, &bounded, -100.0).abs() < 1e-4);
+    assert!((depth(forward, &bounded, -100.0) - 1.0).abs() < 1e-4);
+    assert!(depth(DepthConvention::ReverseZ, &bounded, -200.0) < 0.0);
+}
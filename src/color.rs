@@ -0,0 +1,302 @@
+use ash::vk;
+use glam::{Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+/// Colour in linear space, what shaders and blending expect
+/// clear colours, lights and tints all take this so the colour space is never ambiguous
+/// Example Use:
+/// ```
+/// use vulkan_engine::color::{Hsva, LinearRgba, SrgbRgba};
+///
+/// // picked in srgb like an image editor shows it
+/// let orange: LinearRgba = SrgbRgba::from_hex(0xff8000).into();
+/// let candle = LinearRgba::from_temperature(1900.0);
+/// let pastel: LinearRgba = Hsva::new(0.6, 0.4, 1.0, 1.0).into();
+/// ```
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LinearRgba {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+/// Colour encoded with the srgb transfer function
+/// what colour pickers, hex codes and 8bit images use
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SrgbRgba {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+/// Hue, saturation and value of an srgb colour, all in 0..1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hsva {
+    pub hue: f32,
+    pub saturation: f32,
+    pub value: f32,
+    pub alpha: f32,
+}
+
+impl LinearRgba {
+    pub const WHITE: Self = Self::new(1.0, 1.0, 1.0, 1.0);
+    pub const BLACK: Self = Self::new(0.0, 0.0, 0.0, 1.0);
+    pub const TRANSPARENT: Self = Self::new(0.0, 0.0, 0.0, 0.0);
+
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Opaque colour
+    pub const fn rgb(r: f32, g: f32, b: f32) -> Self {
+        Self::new(r, g, b, 1.0)
+    }
+
+    /// Colour of a black body at kelvin, roughly 1000K candle to 40000K blue sky
+    /// normalised so the brightest channel is 1
+    pub fn from_temperature(kelvin: f32) -> Self {
+        SrgbRgba::from_temperature(kelvin).into()
+    }
+
+    /// Multiplies the colour channels, leaving alpha alone
+    pub fn scale(self, factor: f32) -> Self {
+        Self::new(self.r * factor, self.g * factor, self.b * factor, self.a)
+    }
+
+    /// Colour channels multiplied by alpha, what premultiplied blending and compositors expect
+    pub fn premultiplied(self) -> Self {
+        self.scale(self.a)
+    }
+
+    pub fn with_alpha(mut self, a: f32) -> Self {
+        self.a = a;
+        self
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.to_vec4().is_finite()
+    }
+
+    pub fn to_vec3(self) -> Vec3 {
+        Vec3::new(self.r, self.g, self.b)
+    }
+
+    pub fn to_vec4(self) -> Vec4 {
+        Vec4::new(self.r, self.g, self.b, self.a)
+    }
+
+    pub fn to_array(self) -> [f32; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+}
+
+impl Default for LinearRgba {
+    fn default() -> Self {
+        Self::WHITE
+    }
+}
+
+impl SrgbRgba {
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    pub fn from_u8(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self::new(
+            r as f32 / 255.0,
+            g as f32 / 255.0,
+            b as f32 / 255.0,
+            a as f32 / 255.0,
+        )
+    }
+
+    /// Opaque colour from 0xRRGGBB
+    pub fn from_hex(hex: u32) -> Self {
+        let [_, r, g, b] = hex.to_be_bytes();
+        Self::from_u8(r, g, b, 255)
+    }
+
+    /// See LinearRgba::from_temperature
+    pub fn from_temperature(kelvin: f32) -> Self {
+        // curve fit of black body colours by Tanner Helland, values in srgb 0..255
+        let temperature = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+        let r = if temperature <= 66.0 {
+            255.0
+        } else {
+            329.69873 * (temperature - 60.0).powf(-0.13320476)
+        };
+        let g = if temperature <= 66.0 {
+            99.4708 * temperature.ln() - 161.11957
+        } else {
+            288.12216 * (temperature - 60.0).powf(-0.07551485)
+        };
+        let b = if temperature >= 66.0 {
+            255.0
+        } else if temperature <= 19.0 {
+            0.0
+        } else {
+            138.51773 * (temperature - 10.0).ln() - 305.0448
+        };
+
+        Self::new(
+            r.clamp(0.0, 255.0) / 255.0,
+            g.clamp(0.0, 255.0) / 255.0,
+            b.clamp(0.0, 255.0) / 255.0,
+            1.0,
+        )
+    }
+
+    /// Rounds to 8bit per channel
+    pub fn to_u8(self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a]
+            .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+}
+
+impl Hsva {
+    pub const fn new(hue: f32, saturation: f32, value: f32, alpha: f32) -> Self {
+        Self {
+            hue,
+            saturation,
+            value,
+            alpha,
+        }
+    }
+
+    /// Fully saturated colour around the colour wheel
+    pub const fn from_hue(hue: f32) -> Self {
+        Self::new(hue, 1.0, 1.0, 1.0)
+    }
+}
+
+impl From<SrgbRgba> for LinearRgba {
+    fn from(color: SrgbRgba) -> Self {
+        Self::new(
+            srgb_to_linear(color.r),
+            srgb_to_linear(color.g),
+            srgb_to_linear(color.b),
+            color.a,
+        )
+    }
+}
+
+impl From<LinearRgba> for SrgbRgba {
+    fn from(color: LinearRgba) -> Self {
+        Self::new(
+            linear_to_srgb(color.r),
+            linear_to_srgb(color.g),
+            linear_to_srgb(color.b),
+            color.a,
+        )
+    }
+}
+
+impl From<Hsva> for SrgbRgba {
+    fn from(color: Hsva) -> Self {
+        let hue = color.hue.rem_euclid(1.0) * 6.0;
+        let channel = |offset: f32| {
+            let k = (hue + offset) % 6.0;
+            color.value - color.value * color.saturation * (k.min(4.0 - k)).clamp(0.0, 1.0)
+        };
+        Self::new(channel(5.0), channel(3.0), channel(1.0), color.alpha)
+    }
+}
+
+impl From<SrgbRgba> for Hsva {
+    fn from(color: SrgbRgba) -> Self {
+        let max = color.r.max(color.g).max(color.b);
+        let min = color.r.min(color.g).min(color.b);
+        let chroma = max - min;
+
+        let hue = if chroma == 0.0 {
+            0.0
+        } else if max == color.r {
+            ((color.g - color.b) / chroma).rem_euclid(6.0)
+        } else if max == color.g {
+            (color.b - color.r) / chroma + 2.0
+        } else {
+            (color.r - color.g) / chroma + 4.0
+        };
+        let saturation = if max == 0.0 { 0.0 } else { chroma / max };
+
+        Self::new(hue / 6.0, saturation, max, color.a)
+    }
+}
+
+impl From<Hsva> for LinearRgba {
+    fn from(color: Hsva) -> Self {
+        SrgbRgba::from(color).into()
+    }
+}
+
+impl From<LinearRgba> for Hsva {
+    fn from(color: LinearRgba) -> Self {
+        SrgbRgba::from(color).into()
+    }
+}
+
+impl From<LinearRgba> for vk::ClearColorValue {
+    fn from(color: LinearRgba) -> Self {
+        vk::ClearColorValue {
+            float32: color.to_array(),
+        }
+    }
+}
+
+pub fn srgb_to_linear(channel: f32) -> f32 {
+    if channel <= 0.04045 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(channel: f32) -> f32 {
+    if channel <= 0.0031308 {
+        channel * 12.92
+    } else {
+        1.055 * channel.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[test]
+fn srgb_round_trip_test() {
+    for value in 0..=255u8 {
+        let srgb = SrgbRgba::from_u8(value, value, value, 255);
+        let linear = LinearRgba::from(srgb);
+        assert_eq!(SrgbRgba::from(linear).to_u8(), [value, value, value, 255]);
+    }
+
+    // mid grey in srgb is much darker in linear
+    let grey = LinearRgba::from(SrgbRgba::new(0.5, 0.5, 0.5, 1.0));
+    assert!((grey.r - 0.214).abs() < 1e-3);
+}
+
+#[test]
+fn hsv_test() {
+    let red = SrgbRgba::from(Hsva::from_hue(0.0));
+    assert_eq!(red, SrgbRgba::new(1.0, 0.0, 0.0, 1.0));
+    let cyan = SrgbRgba::from(Hsva::from_hue(0.5));
+    assert_eq!(cyan, SrgbRgba::new(0.0, 1.0, 1.0, 1.0));
+
+    let color = SrgbRgba::from_hex(0x3366cc);
+    let back = SrgbRgba::from(Hsva::from(color));
+    assert_eq!(back.to_u8(), color.to_u8());
+}
+
+#[test]
+fn temperature_test() {
+    let candle = SrgbRgba::from_temperature(1900.0);
+    assert!(candle.r > candle.g && candle.g > candle.b);
+
+    // roughly white around daylight
+    let daylight = SrgbRgba::from_temperature(6600.0);
+    assert!(daylight.to_u8().iter().all(|&channel| channel > 240));
+
+    let sky = SrgbRgba::from_temperature(15000.0);
+    assert!(sky.b > sky.r);
+}
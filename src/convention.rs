@@ -0,0 +1,272 @@
+use glam::{Mat3, Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::{Camera, Projection};
+use crate::renderer::mesh::Vertex;
+use crate::scene::Transform;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Handedness {
+    #[default]
+    Right,
+    Left,
+}
+
+/// World axis pointing up, the other vertical candidate becomes the one pointing towards the viewer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpAxis {
+    #[default]
+    Y,
+    Z,
+}
+
+/// Axes and units content was authored in, converted into the engine's on import
+/// the engine is right handed with y up and -z forward in metres like glam's rh helpers
+/// set one for everything coming out of a tool instead of fixing up each asset
+/// Example Use:
+/// ```
+/// use glam::{Vec2, Vec3};
+/// use vulkan_engine::convention::AxisConvention;
+/// use vulkan_engine::renderer::mesh::Vertex;
+///
+/// // a triangle exported from blender, 1 unit up along z
+/// let mut vertices = [
+///     Vertex::new(Vec3::new(0.0, 0.0, 1.0), Vec3::ONE, Vec2::ZERO),
+///     Vertex::new(Vec3::new(1.0, 0.0, 0.0), Vec3::ONE, Vec2::X),
+///     Vertex::new(Vec3::new(0.0, 1.0, 0.0), Vec3::ONE, Vec2::Y),
+/// ];
+/// AxisConvention::BLENDER.import_mesh(&mut vertices, &mut []);
+/// assert!(vertices[0].pos.abs_diff_eq(Vec3::Y, 1e-6));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AxisConvention {
+    pub handedness: Handedness,
+    pub up: UpAxis,
+    /// metres per unit, 0.01 for centimetres
+    pub unit_scale: f32,
+}
+
+impl Default for AxisConvention {
+    fn default() -> Self {
+        Self::ENGINE
+    }
+}
+
+impl AxisConvention {
+    /// Nothing to convert
+    pub const ENGINE: Self = Self::new(Handedness::Right, UpAxis::Y, 1.0);
+    /// z up in metres, also what blender's gltf exporter undoes with +Y Up unticked
+    pub const BLENDER: Self = Self::new(Handedness::Right, UpAxis::Z, 1.0);
+    /// y up in centimetres
+    pub const MAYA: Self = Self::new(Handedness::Right, UpAxis::Y, 0.01);
+    /// z up in inches, 3ds Max's default system unit
+    pub const MAX: Self = Self::new(Handedness::Right, UpAxis::Z, 0.0254);
+
+    pub const fn new(handedness: Handedness, up: UpAxis, unit_scale: f32) -> Self {
+        Self {
+            handedness,
+            up,
+            unit_scale,
+        }
+    }
+
+    /// Turns and mirrors axes into the engine's without scaling
+    pub fn axes_to_engine(&self) -> Mat3 {
+        // z up right handed has y pointing away from the viewer, which is -z in the engine
+        let axes = match self.up {
+            UpAxis::Y => Mat3::IDENTITY,
+            UpAxis::Z => Mat3::from_cols(Vec3::X, Vec3::NEG_Z, Vec3::Y),
+        };
+        // left handed content is mirrored along its forward axis
+        match self.handedness {
+            Handedness::Right => axes,
+            Handedness::Left => {
+                let forward = match self.up {
+                    UpAxis::Y => Vec3::Z,
+                    UpAxis::Z => Vec3::Y,
+                };
+                axes * Mat3::from_diagonal(Vec3::ONE - 2.0 * forward)
+            }
+        }
+    }
+
+    /// Content space to engine space, axes and units
+    pub fn to_engine(&self) -> Mat4 {
+        Mat4::from_mat3(self.axes_to_engine() * self.unit_scale)
+    }
+
+    /// Engine space back to content space, for exporting
+    pub fn from_engine(&self) -> Mat4 {
+        self.to_engine().inverse()
+    }
+
+    /// Converting flips winding and the handedness of tangent frames
+    pub fn is_mirrored(&self) -> bool {
+        self.handedness == Handedness::Left
+    }
+
+    pub fn point(&self, point: Vec3) -> Vec3 {
+        self.axes_to_engine() * point * self.unit_scale
+    }
+
+    /// Normals and other directions, not scaled
+    pub fn direction(&self, direction: Vec3) -> Vec3 {
+        self.axes_to_engine() * direction
+    }
+
+    /// Converts vertices in place, indices are the mesh's triangles and empty for triangle lists
+    /// mirrored conventions get their triangles rewound so they still face outwards
+    pub fn import_mesh(&self, vertices: &mut [Vertex], indices: &mut [u32]) {
+        let axes = self.axes_to_engine();
+        let mirrored = self.is_mirrored();
+        for vertex in vertices.iter_mut() {
+            vertex.pos = axes * vertex.pos * self.unit_scale;
+            vertex.normal = axes * vertex.normal;
+            let tangent = axes * vertex.tangent.truncate();
+            let handedness = if mirrored {
+                -vertex.tangent.w
+            } else {
+                vertex.tangent.w
+            };
+            vertex.tangent = tangent.extend(handedness);
+        }
+        if !mirrored {
+            return;
+        }
+        if indices.is_empty() {
+            for triangle in vertices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        } else {
+            for triangle in indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+    }
+
+    /// Converts a node transform, for hierarchies whose meshes went through import_mesh
+    pub fn import_transform(&self, transform: &Transform) -> Transform {
+        let axes = self.axes_to_engine();
+        // conjugating by a mirror still gives a rotation, just turning the other way
+        let rotation = axes * Mat3::from_quat(transform.rotation) * axes.transpose();
+        Transform {
+            translation: self.point(transform.translation),
+            rotation: Quat::from_mat3(&rotation).normalize(),
+            // local axes are converted along with the mesh, so scale stays on the same axes
+            scale: (axes * transform.scale).abs(),
+        }
+    }
+
+    /// Camera placed by transform in content space, cameras look down their local -z in
+    /// blender, maya and max as they do in the engine so only the world axes are converted
+    pub fn import_camera(&self, transform: &Transform, projection: Projection) -> Camera {
+        let mut rotation = self.axes_to_engine() * Mat3::from_quat(transform.rotation);
+        // keeps forward and up, the camera's right follows from them
+        if self.is_mirrored() {
+            rotation *= Mat3::from_diagonal(Vec3::new(-1.0, 1.0, 1.0));
+        }
+        let rotation = Quat::from_mat3(&rotation).normalize();
+        let projection = match projection {
+            Projection::Perspective {
+                fov_y,
+                z_near,
+                z_far,
+            } => Projection::Perspective {
+                fov_y,
+                z_near: z_near * self.unit_scale,
+                z_far: z_far.map(|z_far| z_far * self.unit_scale),
+            },
+            Projection::Orthographic {
+                height,
+                z_near,
+                z_far,
+            } => Projection::Orthographic {
+                height: height * self.unit_scale,
+                z_near: z_near * self.unit_scale,
+                z_far: z_far * self.unit_scale,
+            },
+        };
+        Camera {
+            position: self.point(transform.translation),
+            rotation,
+            projection,
+        }
+    }
+}
+
+#[test]
+fn axis_convention_test() {
+    use glam::{Vec2, Vec4};
+
+    // blender's up, forward and right end up as the engine's
+    let blender = AxisConvention::BLENDER;
+    assert!(blender.point(Vec3::Z).abs_diff_eq(Vec3::Y, 1e-6));
+    assert!(blender.point(Vec3::Y).abs_diff_eq(Vec3::NEG_Z, 1e-6));
+    assert!(blender.point(Vec3::X).abs_diff_eq(Vec3::X, 1e-6));
+    assert!(!blender.is_mirrored());
+    let maya = AxisConvention::MAYA;
+    assert!(
+        maya.point(Vec3::new(0.0, 180.0, 0.0))
+            .abs_diff_eq(Vec3::Y * 1.8, 1e-5)
+    );
+    assert!(
+        AxisConvention::MAX
+            .to_engine()
+            .mul_mat4(&AxisConvention::MAX.from_engine())
+            .abs_diff_eq(Mat4::IDENTITY, 1e-5)
+    );
+
+    // left handed y up flips z and rewinds triangles so they still face the same way
+    let left = AxisConvention::new(Handedness::Left, UpAxis::Y, 1.0);
+    let mut vertices = [
+        Vertex::new(Vec3::ZERO, Vec3::ONE, Vec2::ZERO),
+        Vertex::new(Vec3::X, Vec3::ONE, Vec2::X),
+        Vertex::new(Vec3::Y, Vec3::ONE, Vec2::Y),
+    ];
+    let facing = |vertices: &[Vertex]| {
+        (vertices[1].pos - vertices[0].pos).cross(vertices[2].pos - vertices[0].pos)
+    };
+    assert!(facing(&vertices).z > 0.0);
+    vertices[0].normal = Vec3::Z;
+    vertices[0].tangent = Vec4::new(1.0, 0.0, 0.0, 1.0);
+    left.import_mesh(&mut vertices, &mut []);
+    // the winding agrees with the mirrored normal
+    assert_eq!(vertices[0].normal, Vec3::NEG_Z);
+    assert!(facing(&vertices).z < 0.0);
+    assert_eq!(vertices[0].tangent.w, -1.0);
+    let mut indices = [0, 1, 2];
+    left.import_mesh(&mut vertices, &mut indices);
+    assert_eq!(indices, [0, 2, 1]);
+
+    // a node turned and moved in blender lands in the same place as its converted mesh
+    let transform = Transform::from_translation(Vec3::new(1.0, 2.0, 3.0))
+        .with_rotation(Quat::from_rotation_z(0.5))
+        .with_scale(Vec3::new(1.0, 2.0, 3.0));
+    let local = Vec3::new(0.3, -0.2, 0.7);
+    let imported = blender.import_transform(&transform);
+    let expected = blender.point(transform.to_matrix().transform_point3(local));
+    let converted = imported.to_matrix().transform_point3(blender.point(local));
+    assert!(converted.abs_diff_eq(expected, 1e-5));
+    for convention in [left, AxisConvention::MAX] {
+        let imported = convention.import_transform(&transform);
+        let expected = convention.point(transform.to_matrix().transform_point3(local));
+        let converted = imported
+            .to_matrix()
+            .transform_point3(convention.point(local));
+        assert!(converted.abs_diff_eq(expected, 1e-5));
+    }
+
+    // blender cameras at rest look straight down, which is down in the engine too
+    let camera = blender.import_camera(
+        &Transform::from_translation(Vec3::new(0.0, 0.0, 10.0)),
+        Projection::Perspective {
+            fov_y: 1.0,
+            z_near: 0.1,
+            z_far: None,
+        },
+    );
+    assert!(camera.forward().abs_diff_eq(Vec3::NEG_Y, 1e-5));
+    assert!(camera.position.abs_diff_eq(Vec3::Y * 10.0, 1e-5));
+    let camera = left.import_camera(&Transform::IDENTITY, camera.projection);
+    assert!(camera.forward().abs_diff_eq(Vec3::Z, 1e-5));
+}
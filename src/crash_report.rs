@@ -0,0 +1,239 @@
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::backtrace::Backtrace;
+use std::collections::VecDeque;
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// number of log lines kept for the crash report
+const RECENT_LOG_LINES: usize = 64;
+
+static CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext::new());
+static RECENT_LOGS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Engine and vulkan state written into crash reports
+/// kept up to date by the renderer as things are created
+#[derive(Clone, Debug, Default)]
+pub struct CrashContext {
+    pub device_name: String,
+    pub driver: String,
+    pub api_version: String,
+    pub instance_extensions: Vec<String>,
+    pub device_extensions: Vec<String>,
+    /// last render pass recorded
+    pub last_pass: &'static str,
+    pub allocator_stats: String,
+}
+
+impl CrashContext {
+    const fn new() -> Self {
+        Self {
+            device_name: String::new(),
+            driver: String::new(),
+            api_version: String::new(),
+            instance_extensions: Vec::new(),
+            device_extensions: Vec::new(),
+            last_pass: "",
+            allocator_stats: String::new(),
+        }
+    }
+}
+
+/// Updates the context included in crash reports
+pub fn update_context<F: FnOnce(&mut CrashContext)>(update: F) {
+    update(&mut lock(&CONTEXT));
+}
+
+/// Copy of the context as it is now
+pub fn context() -> CrashContext {
+    lock(&CONTEXT).clone()
+}
+
+/// Records the last render pass, cheap enough to call every frame
+pub fn set_last_pass(pass: &'static str) {
+    lock(&CONTEXT).last_pass = pass;
+}
+
+/// Logger that keeps the last few lines for crash reports and forwards everything to inner
+pub struct CrashLogger<L: Log> {
+    inner: L,
+}
+
+impl<L: Log> Log for CrashLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let mut recent_logs = lock(&RECENT_LOGS);
+            if recent_logs.len() == RECENT_LOG_LINES {
+                recent_logs.pop_front();
+            }
+            recent_logs.push_back(format!(
+                "{:<5} [{}] {}",
+                record.level(),
+                record.target(),
+                record.args()
+            ));
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Installs inner wrapped in a CrashLogger as the global logger
+/// Example Use:
+/// ```ignore
+/// let logger = SimpleLogger::new().with_level(log::LevelFilter::Info);
+/// let max_level = logger.max_level();
+/// crash_report::init_logger(logger, max_level)?;
+/// ```
+pub fn init_logger<L: Log + 'static>(
+    inner: L,
+    max_level: LevelFilter,
+) -> Result<(), SetLoggerError> {
+    log::set_logger(Box::leak(Box::new(CrashLogger { inner })))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+/// Writes a crash report into directory when any thread panics
+/// the previous panic hook still runs afterwards
+pub fn install_panic_hook(directory: impl Into<PathBuf>) {
+    let directory = directory.into();
+    let previous_hook = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        match write_report(&directory, info) {
+            Ok(path) => eprintln!("Crash Report Written to {}", path.display()),
+            Err(error) => eprintln!("Failed to Write Crash Report: {error}"),
+        }
+        previous_hook(info);
+    }));
+}
+
+fn write_report(directory: &Path, info: &PanicHookInfo) -> std::io::Result<PathBuf> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let message = match info.location() {
+        Some(location) => format!("{} at {location}", panic_message(info)),
+        None => panic_message(info).to_string(),
+    };
+
+    // the panic may have happened while one of these was locked on this thread
+    let context = match CONTEXT.try_lock() {
+        Ok(context) => Some(context.clone()),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner().clone()),
+        Err(TryLockError::WouldBlock) => None,
+    };
+    let recent_logs = match RECENT_LOGS.try_lock() {
+        Ok(recent_logs) => recent_logs.iter().cloned().collect(),
+        Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner().iter().cloned().collect(),
+        Err(TryLockError::WouldBlock) => Vec::new(),
+    };
+
+    let report = format_report(
+        &message,
+        &Backtrace::force_capture().to_string(),
+        context.as_ref(),
+        &recent_logs,
+    );
+
+    std::fs::create_dir_all(directory)?;
+    let path = directory.join(format!("crash-{time}.txt"));
+    std::fs::write(&path, report)?;
+    Ok(path)
+}
+
+fn panic_message<'a>(info: &'a PanicHookInfo) -> &'a str {
+    if let Some(message) = info.payload().downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = info.payload().downcast_ref::<String>() {
+        message
+    } else {
+        "Unknown Panic"
+    }
+}
+
+fn format_report(
+    message: &str,
+    backtrace: &str,
+    context: Option<&CrashContext>,
+    recent_logs: &[String],
+) -> String {
+    let mut report = format!(
+        "Alcor Engine {} Crash Report\n\nPanic: {message}\n",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    match context {
+        Some(context) => {
+            report += &format!(
+                "\nDevice: {}\nDriver: {}\nVulkan API: {}\nLast Render Pass: {}\nAllocator: {}\n",
+                context.device_name,
+                context.driver,
+                context.api_version,
+                context.last_pass,
+                context.allocator_stats,
+            );
+            report += "\nInstance Extensions:\n";
+            for extension in &context.instance_extensions {
+                report += &format!("  {extension}\n");
+            }
+            report += "\nDevice Extensions:\n";
+            for extension in &context.device_extensions {
+                report += &format!("  {extension}\n");
+            }
+        }
+        None => report += "\nVulkan Context Unavailable\n",
+    }
+
+    report += "\nRecent Log:\n";
+    for line in recent_logs {
+        report += &format!("  {line}\n");
+    }
+
+    report += &format!("\nBacktrace:\n{backtrace}\n");
+    report
+}
+
+// a panic while locked poisons the mutex, the data is still fine for reporting
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[test]
+fn format_report_test() {
+    let context = CrashContext {
+        device_name: "Test GPU".to_string(),
+        device_extensions: vec!["VK_KHR_swapchain".to_string()],
+        last_pass: "scene",
+        ..Default::default()
+    };
+
+    let report = format_report(
+        "oh no at src/renderer.rs:1:1",
+        "<backtrace>",
+        Some(&context),
+        &["INFO  [vulkan_engine] hello".to_string()],
+    );
+
+    assert!(report.contains("Panic: oh no at src/renderer.rs:1:1"));
+    assert!(report.contains("Device: Test GPU"));
+    assert!(report.contains("Last Render Pass: scene"));
+    assert!(report.contains("  VK_KHR_swapchain\n"));
+    assert!(report.contains("  INFO  [vulkan_engine] hello\n"));
+    assert!(report.ends_with("<backtrace>\n"));
+
+    assert!(format_report("", "", None, &[]).contains("Vulkan Context Unavailable"));
+}
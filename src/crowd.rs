@@ -0,0 +1,452 @@
+use glam::Mat4;
+use serde::{Deserialize, Serialize};
+
+use crate::animation::{AnimationClip, Skeleton};
+use crate::color::LinearRgba;
+use crate::lod::LodSelector;
+use crate::math::{Aabb, Frustum};
+use crate::renderer::MeshInstance;
+use crate::renderer::material::MaterialId;
+use crate::renderer::mesh::MeshId;
+use crate::renderer::skinning::InstanceAnimation;
+
+/// How an agent is animated, from most to least expensive
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnimationTier {
+    /// skeleton evaluated on the cpu every interval frames, 1 is every frame
+    Skeletal { interval: u32 },
+    /// baked clip played by the vertex shader, nothing is evaluated on the cpu
+    Baked,
+    /// baked clip held where it was when the agent got this far away, rest pose without one
+    Static,
+}
+
+/// Tier of the agents up to max_distance from the eye
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AnimationLodLevel {
+    pub max_distance: f32,
+    pub tier: AnimationTier,
+}
+
+impl AnimationLodLevel {
+    pub fn new(max_distance: f32, tier: AnimationTier) -> Self {
+        Self { max_distance, tier }
+    }
+}
+
+/// Distance bands of a crowd from nearest to furthest, agents past the last band use its tier
+/// Example Use:
+/// ```
+/// use vulkan_engine::crowd::{AnimationLod, AnimationLodLevel, AnimationTier};
+///
+/// let lod = AnimationLod::new(vec![
+///     AnimationLodLevel::new(20.0, AnimationTier::Skeletal { interval: 1 }),
+///     AnimationLodLevel::new(60.0, AnimationTier::Baked),
+///     AnimationLodLevel::new(f32::INFINITY, AnimationTier::Static),
+/// ]);
+/// assert_eq!(lod.select(35.0), AnimationTier::Baked);
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AnimationLod {
+    pub levels: Vec<AnimationLodLevel>,
+}
+
+impl AnimationLod {
+    /// Levels are sorted so the nearest comes first
+    pub fn new(mut levels: Vec<AnimationLodLevel>) -> Self {
+        levels.sort_by(|a, b| a.max_distance.total_cmp(&b.max_distance));
+        Self { levels }
+    }
+
+    /// Tier at distance, skeletal every frame without any levels
+    pub fn select(&self, distance: f32) -> AnimationTier {
+        self.levels
+            .iter()
+            .find(|level| distance <= level.max_distance)
+            .or(self.levels.last())
+            .map_or(AnimationTier::Skeletal { interval: 1 }, |level| level.tier)
+    }
+
+    // interval of the cheapest skeletal level, for agents whose clip isn't baked
+    fn coarsest_interval(&self) -> u32 {
+        self.levels
+            .iter()
+            .filter_map(|level| match level.tier {
+                AnimationTier::Skeletal { interval } => Some(interval),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(1)
+    }
+}
+
+impl Default for AnimationLod {
+    fn default() -> Self {
+        Self::new(vec![
+            AnimationLodLevel::new(15.0, AnimationTier::Skeletal { interval: 1 }),
+            AnimationLodLevel::new(40.0, AnimationTier::Skeletal { interval: 4 }),
+            AnimationLodLevel::new(120.0, AnimationTier::Baked),
+            AnimationLodLevel::new(f32::INFINITY, AnimationTier::Static),
+        ])
+    }
+}
+
+/// One animated instance of a crowd
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrowdAgent {
+    /// world matrix
+    pub transform: Mat4,
+    pub tint: LinearRgba,
+    /// index in CrowdAnimator::clips
+    pub clip: usize,
+    /// seconds into the clip
+    pub time: f32,
+    /// multiplies the time passed to CrowdAnimator::update
+    pub speed: f32,
+    tier: Option<AnimationTier>,
+    // time a static agent is held at
+    held_time: f32,
+}
+
+impl CrowdAgent {
+    pub fn new(transform: Mat4) -> Self {
+        Self {
+            transform,
+            tint: LinearRgba::WHITE,
+            clip: 0,
+            time: 0.0,
+            speed: 1.0,
+            tier: None,
+            held_time: 0.0,
+        }
+    }
+
+    /// Plays clip from time, different start times keep a crowd out of step
+    pub fn with_clip(mut self, clip: usize, time: f32) -> Self {
+        self.clip = clip;
+        self.time = time;
+        self
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn with_tint(mut self, tint: LinearRgba) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    /// Tier picked by the last update, None before the first
+    pub fn tier(&self) -> Option<AnimationTier> {
+        self.tier
+    }
+}
+
+/// What the last CrowdAnimator::update did
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CrowdStats {
+    pub skeletal: u32,
+    /// skeletal agents whose pose was evaluated
+    pub posed: u32,
+    pub baked: u32,
+    pub static_poses: u32,
+    /// skeletal agents outside the frustum, their poses aren't evaluated
+    pub culled: u32,
+}
+
+/// Animates many instances of one skinned mesh, agents near the eye are posed from the
+/// skeleton, less often further away, then switch to the mesh's baked clips and finally
+/// hold still, agents outside the frustum aren't posed at all
+/// Example Use:
+/// ```ignore
+/// let walk = renderer.add_vertex_animation(soldier, &walk_baked)?;
+/// let mut crowd = CrowdAnimator::new(soldier, cloth, skeleton, reach)
+///     .with_clip(walk_clip, Some(walk))
+///     .with_first_pose(renderer.skinning.poses.len());
+/// crowd.agents = spawn_points.map(|at| CrowdAgent::new(at).with_clip(0, rng.f32())).collect();
+/// // each frame
+/// let frustum = Frustum::from_view_projection(renderer.camera.view_projection(aspect));
+/// crowd.update(dt, &LodSelector::new(&renderer.camera), &frustum, &mut renderer.skinning.poses);
+/// renderer.instances.truncate(static_instances);
+/// renderer.instances.extend(crowd.instances());
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrowdAnimator {
+    pub mesh: MeshId,
+    pub material: MaterialId,
+    pub skeleton: Skeleton,
+    pub clips: Vec<AnimationClip>,
+    /// each clip's index in the mesh's baked clips, agents of clips that aren't baked stay
+    /// skeletal at the coarsest interval instead
+    pub baked: Vec<Option<usize>>,
+    pub lod: AnimationLod,
+    /// local bounds of the mesh in every pose, agents are culled with them
+    pub bounds: Aabb,
+    pub agents: Vec<CrowdAgent>,
+    /// the crowd's first pose in VKSkinning::poses, it has one per agent from there
+    pub first_pose: usize,
+    frame: u64,
+}
+
+impl CrowdAnimator {
+    pub fn new(mesh: MeshId, material: MaterialId, skeleton: Skeleton, bounds: Aabb) -> Self {
+        Self {
+            mesh,
+            material,
+            skeleton,
+            clips: Vec::new(),
+            baked: Vec::new(),
+            lod: AnimationLod::default(),
+            bounds,
+            agents: Vec::new(),
+            first_pose: 0,
+            frame: 0,
+        }
+    }
+
+    /// Adds a clip agents can play, baked is its index from VKRenderer::add_vertex_animation
+    pub fn with_clip(mut self, clip: AnimationClip, baked: Option<usize>) -> Self {
+        self.clips.push(clip);
+        self.baked.push(baked);
+        self
+    }
+
+    pub fn with_lod(mut self, lod: AnimationLod) -> Self {
+        self.lod = lod;
+        self
+    }
+
+    pub fn with_first_pose(mut self, first_pose: usize) -> Self {
+        self.first_pose = first_pose;
+        self
+    }
+
+    /// Advances every agent dt seconds and picks its tier by distance from selector's eye,
+    /// divided by its bias so a bias above 1 keeps detail further away
+    /// skeletal agents inside frustum are posed into poses when their interval is up, others
+    /// keep their last pose, poses of agents that aren't skeletal are emptied
+    pub fn update(
+        &mut self,
+        dt: f32,
+        selector: &LodSelector,
+        frustum: &Frustum,
+        poses: &mut Vec<Vec<Mat4>>,
+    ) -> CrowdStats {
+        self.frame += 1;
+        let end = self.first_pose + self.agents.len();
+        if poses.len() < end {
+            poses.resize(end, Vec::new());
+        }
+
+        let mut stats = CrowdStats::default();
+        let bias = selector.bias.max(f32::EPSILON);
+        for (index, agent) in self.agents.iter_mut().enumerate() {
+            agent.time += dt * agent.speed;
+            let distance = selector.eye.distance(agent.transform.w_axis.truncate()) / bias;
+            let baked = self.baked.get(agent.clip).copied().flatten();
+            let tier = match (self.lod.select(distance), baked) {
+                (AnimationTier::Baked | AnimationTier::Static, None) => AnimationTier::Skeletal {
+                    interval: self.lod.coarsest_interval(),
+                },
+                (tier, _) => tier,
+            };
+            if tier == AnimationTier::Static && agent.tier != Some(AnimationTier::Static) {
+                agent.held_time = agent.time;
+            }
+            agent.tier = Some(tier);
+
+            let pose = &mut poses[self.first_pose + index];
+            let AnimationTier::Skeletal { interval } = tier else {
+                match tier {
+                    AnimationTier::Static => stats.static_poses += 1,
+                    _ => stats.baked += 1,
+                }
+                pose.clear();
+                continue;
+            };
+            stats.skeletal += 1;
+
+            if !frustum.intersects_aabb(&self.bounds.transformed(&agent.transform)) {
+                stats.culled += 1;
+                continue;
+            }
+            // agents are spread over the interval so they aren't all posed on the same frame
+            let due = pose.is_empty()
+                || (self.frame + index as u64).is_multiple_of(interval.max(1) as u64);
+            if let Some(clip) = self.clips.get(agent.clip)
+                && due
+            {
+                let local = clip.sample(&self.skeleton, agent.time);
+                *pose = self.skeleton.skin_matrices(&local);
+                stats.posed += 1;
+            }
+        }
+        stats
+    }
+
+    /// The agents as instances of the crowd's mesh, posed as the last update left them
+    pub fn instances(&self) -> impl Iterator<Item = MeshInstance> + '_ {
+        self.agents.iter().enumerate().map(|(index, agent)| {
+            let baked = self.baked.get(agent.clip).copied().flatten();
+            let animation = match agent.tier {
+                Some(AnimationTier::Baked) => baked.map(|clip| InstanceAnimation::Baked {
+                    clip,
+                    time: agent.time,
+                }),
+                Some(AnimationTier::Static) => baked.map(|clip| InstanceAnimation::Baked {
+                    clip,
+                    time: agent.held_time,
+                }),
+                _ => Some(InstanceAnimation::Skinned {
+                    pose: self.first_pose + index,
+                }),
+            };
+            MeshInstance {
+                mesh: self.mesh,
+                transform: agent.transform,
+                tint: agent.tint,
+                material: self.material,
+                animation,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+fn test_crowd() -> CrowdAnimator {
+    use crate::animation::{Joint, JointTrack};
+    use crate::scene::Transform;
+    use glam::{Quat, Vec3};
+
+    let skeleton = Skeleton::new(vec![Joint::new("root", None, Transform::IDENTITY)]).unwrap();
+    let spin = AnimationClip::new("spin", 1.0)
+        .with_looping(true)
+        .with_track(
+            JointTrack::new(0)
+                .with_rotation(0.0, Quat::IDENTITY)
+                .with_rotation(1.0, Quat::from_rotation_y(1.0)),
+        );
+    let bounds = Aabb::new(Vec3::splat(-0.5), Vec3::splat(0.5));
+    let mut crowd = CrowdAnimator::new(1, 0, skeleton, bounds)
+        .with_clip(spin.clone(), Some(0))
+        .with_clip(spin, None)
+        .with_lod(AnimationLod::new(vec![
+            AnimationLodLevel::new(10.0, AnimationTier::Skeletal { interval: 1 }),
+            AnimationLodLevel::new(20.0, AnimationTier::Skeletal { interval: 3 }),
+            AnimationLodLevel::new(30.0, AnimationTier::Baked),
+            AnimationLodLevel::new(f32::INFINITY, AnimationTier::Static),
+        ]))
+        .with_first_pose(2);
+    // along the camera's view, one behind it
+    crowd.agents = [5.0, 15.0, 25.0, 50.0, -5.0]
+        .into_iter()
+        .map(|z| CrowdAgent::new(Mat4::from_translation(Vec3::new(0.0, 0.0, -z))))
+        .collect();
+    crowd
+}
+
+#[cfg(test)]
+fn test_view() -> (LodSelector, Frustum) {
+    use crate::camera::Camera;
+    use glam::Vec3;
+
+    let camera =
+        Camera::perspective(90.0_f32.to_radians(), 0.1).look_at(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y);
+    let frustum = Frustum::from_view_projection(camera.view_projection(1.0));
+    (LodSelector::new(&camera), frustum)
+}
+
+#[test]
+fn animation_lod_test() {
+    let lod = AnimationLod::default();
+    assert_eq!(lod.select(0.0), AnimationTier::Skeletal { interval: 1 });
+    assert_eq!(lod.select(20.0), AnimationTier::Skeletal { interval: 4 });
+    assert_eq!(lod.select(1000.0), AnimationTier::Static);
+    assert_eq!(lod.coarsest_interval(), 4);
+    assert_eq!(
+        AnimationLod::new(Vec::new()).select(5.0),
+        AnimationTier::Skeletal { interval: 1 }
+    );
+}
+
+#[test]
+fn crowd_update_test() {
+    let (selector, frustum) = test_view();
+    let mut crowd = test_crowd();
+    let mut poses = vec![vec![Mat4::IDENTITY]; 2];
+
+    let stats = crowd.update(0.25, &selector, &frustum, &mut poses);
+    assert_eq!(poses.len(), 7);
+    // the one behind the camera is skeletal but culled
+    assert_eq!(
+        stats,
+        CrowdStats {
+            skeletal: 3,
+            posed: 2,
+            baked: 1,
+            static_poses: 1,
+            culled: 1,
+        }
+    );
+    assert!(poses[6].is_empty());
+    assert!(poses[5].is_empty() && poses[4].is_empty());
+    assert_eq!(poses[2].len(), 1);
+
+    let instances: Vec<_> = crowd.instances().collect();
+    assert_eq!(
+        instances[0].animation,
+        Some(InstanceAnimation::Skinned { pose: 2 })
+    );
+    assert_eq!(
+        instances[2].animation,
+        Some(InstanceAnimation::Baked {
+            clip: 0,
+            time: 0.25
+        })
+    );
+    assert_eq!(instances[3].mesh, 1);
+
+    // the near agent is posed every frame, the further one once every 3
+    let mut posed = 0;
+    for _ in 0..6 {
+        posed += crowd.update(0.25, &selector, &frustum, &mut poses).posed;
+    }
+    assert_eq!(posed, 6 + 2);
+
+    // static agents hold the time they stopped at while baked ones keep playing
+    let instances: Vec<_> = crowd.instances().collect();
+    assert_eq!(
+        instances[3].animation,
+        Some(InstanceAnimation::Baked {
+            clip: 0,
+            time: 0.25
+        })
+    );
+    assert_eq!(
+        instances[2].animation,
+        Some(InstanceAnimation::Baked {
+            clip: 0,
+            time: 1.75
+        })
+    );
+}
+
+#[test]
+fn unbaked_crowd_test() {
+    let (selector, frustum) = test_view();
+    let mut crowd = test_crowd();
+    for agent in &mut crowd.agents {
+        agent.clip = 1;
+    }
+    let mut poses = Vec::new();
+    let stats = crowd.update(0.1, &selector, &frustum, &mut poses);
+    // without a baked clip far agents stay skeletal at the coarsest interval
+    assert_eq!(stats.skeletal, 5);
+    assert_eq!(stats.baked + stats.static_poses, 0);
+    assert_eq!(
+        crowd.agents[3].tier(),
+        Some(AnimationTier::Skeletal { interval: 3 })
+    );
+}
@@ -0,0 +1,528 @@
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::{BitOr, BitOrAssign};
+use std::path::Path;
+use std::{fs, io};
+use thiserror::Error;
+
+/// Value of a console variable, a variable keeps the type it was registered with
+#[derive(Clone, Debug, PartialEq)]
+pub enum CVarValue {
+    Float(f32),
+    Int(i64),
+    Bool(bool),
+    String(String),
+}
+
+impl CVarValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Float(_) => "float",
+            Self::Int(_) => "int",
+            Self::Bool(_) => "bool",
+            Self::String(_) => "string",
+        }
+    }
+
+    // parses text as the same type as self
+    fn parse_like(&self, text: &str) -> Option<Self> {
+        Some(match self {
+            Self::Float(_) => Self::Float(text.parse().ok()?),
+            Self::Int(_) => Self::Int(text.parse().ok()?),
+            Self::Bool(_) => Self::Bool(match text.to_ascii_lowercase().as_str() {
+                "1" | "true" | "on" | "yes" => true,
+                "0" | "false" | "off" | "no" => false,
+                _ => return None,
+            }),
+            Self::String(_) => Self::String(text.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for CVarValue {
+    /// Written so the console reads it back, strings are quoted
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Float(value) => write!(f, "{value}"),
+            Self::Int(value) => write!(f, "{value}"),
+            Self::Bool(value) => write!(f, "{}", *value as u8),
+            Self::String(value) => write!(f, "{value:?}"),
+        }
+    }
+}
+
+impl From<f32> for CVarValue {
+    fn from(value: f32) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<i64> for CVarValue {
+    fn from(value: i64) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<bool> for CVarValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<&str> for CVarValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<String> for CVarValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CVarFlags(pub u32);
+
+impl CVarFlags {
+    pub const NONE: Self = Self(0);
+    /// changes are saved with CVars::save_overrides
+    pub const ARCHIVE: Self = Self(1);
+    /// only code can change it, the console and config files can't
+    pub const READ_ONLY: Self = Self(1 << 1);
+    /// debug toggles, the console and config files can only change it in debug builds
+    pub const DEV: Self = Self(1 << 2);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for CVarFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for CVarFlags {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+/// A named setting, see CVars
+#[derive(Clone, Debug, PartialEq)]
+pub struct CVar {
+    pub name: String,
+    pub description: String,
+    pub default: CVarValue,
+    /// inclusive, numbers are clamped into it
+    pub range: Option<(f64, f64)>,
+    pub flags: CVarFlags,
+    value: CVarValue,
+}
+
+impl CVar {
+    pub fn new(name: &str, default: impl Into<CVarValue>, description: &str) -> Self {
+        let default = default.into();
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            value: default.clone(),
+            default,
+            range: None,
+            flags: CVarFlags::NONE,
+        }
+    }
+
+    pub fn with_range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+        self.default = self.clamped(self.default.clone());
+        self.value = self.default.clone();
+        self
+    }
+
+    pub fn with_flags(mut self, flags: CVarFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn value(&self) -> &CVarValue {
+        &self.value
+    }
+
+    /// Whether the value differs from the default
+    pub fn is_modified(&self) -> bool {
+        self.value != self.default
+    }
+
+    fn clamped(&self, value: CVarValue) -> CVarValue {
+        match (value, self.range) {
+            (CVarValue::Float(value), Some((min, max))) => {
+                CVarValue::Float(value.clamp(min as f32, max as f32))
+            }
+            (CVarValue::Int(value), Some((min, max))) => {
+                CVarValue::Int(value.clamp(min as i64, max as i64))
+            }
+            (value, _) => value,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CVarError {
+    #[error("unknown console variable or command {0}")]
+    Unknown(String),
+    #[error("{name} is a {expected}")]
+    Type {
+        name: String,
+        expected: &'static str,
+    },
+    #[error("{name} is a {expected}, {value:?} isn't")]
+    Parse {
+        name: String,
+        value: String,
+        expected: &'static str,
+    },
+    #[error("{0} is read only")]
+    ReadOnly(String),
+    #[error("{0} can only be changed in debug builds")]
+    DevOnly(String),
+    #[error("{0}")]
+    Usage(&'static str),
+    #[error("failed to read or write console script: {0}")]
+    Io(#[from] io::Error),
+    #[error("line {line}: {error}")]
+    Script { line: usize, error: Box<CVarError> },
+}
+
+/// Typed console variables backing render settings and debug toggles
+/// the console, config files and saved overrides all use the same script, one command per line
+/// or separated by ;, // starts a comment
+/// - `name` prints the variable
+/// - `name value` sets it, strings with spaces are quoted
+/// - `toggle name` flips a bool
+/// - `reset name` puts it back to its default
+/// - `list prefix` prints every variable starting with prefix
+/// - `exec path` runs a script file
+///
+/// Example Use:
+/// ```
+/// use vulkan_engine::cvar::{CVar, CVarFlags, CVars};
+///
+/// let mut cvars = CVars::default();
+/// cvars.register(
+///     CVar::new("r_lod_bias", 1.0_f32, "scales lod coverage")
+///         .with_range(0.1, 10.0)
+///         .with_flags(CVarFlags::ARCHIVE),
+/// );
+/// cvars.register(CVar::new("dbg_bounds", false, "draws node bounds"));
+///
+/// cvars.exec_script("r_lod_bias 2.5; toggle dbg_bounds").unwrap();
+/// assert_eq!(cvars.get_float("r_lod_bias"), Some(2.5));
+/// assert_eq!(cvars.get_bool("dbg_bounds"), Some(true));
+/// assert_eq!(cvars.overrides(), "r_lod_bias 2.5\n");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CVars {
+    vars: BTreeMap<String, CVar>,
+}
+
+impl CVars {
+    /// Adds a variable, an existing one with the same name is kept
+    pub fn register(&mut self, cvar: CVar) -> &mut Self {
+        if self.vars.contains_key(&cvar.name) {
+            warn!("Console Variable {} Registered Twice", cvar.name);
+        } else {
+            self.vars.insert(cvar.name.clone(), cvar);
+        }
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CVar> {
+        self.vars.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CVar> {
+        self.vars.values()
+    }
+
+    pub fn get_float(&self, name: &str) -> Option<f32> {
+        match self.get(name)?.value {
+            CVarValue::Float(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_int(&self, name: &str) -> Option<i64> {
+        match self.get(name)?.value {
+            CVarValue::Int(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        match self.get(name)?.value {
+            CVarValue::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        match &self.get(name)?.value {
+            CVarValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Sets a variable from code, flags don't apply and numbers are clamped into range
+    pub fn set(&mut self, name: &str, value: impl Into<CVarValue>) -> Result<(), CVarError> {
+        let cvar = self
+            .vars
+            .get_mut(name)
+            .ok_or_else(|| CVarError::Unknown(name.to_string()))?;
+        let value = value.into();
+        if std::mem::discriminant(&value) != std::mem::discriminant(&cvar.default) {
+            return Err(CVarError::Type {
+                name: name.to_string(),
+                expected: cvar.default.type_name(),
+            });
+        }
+        cvar.value = cvar.clamped(value);
+        Ok(())
+    }
+
+    /// Puts a variable back to its default
+    pub fn reset(&mut self, name: &str) -> Result<(), CVarError> {
+        let cvar = self
+            .vars
+            .get_mut(name)
+            .ok_or_else(|| CVarError::Unknown(name.to_string()))?;
+        cvar.value = cvar.default.clone();
+        Ok(())
+    }
+
+    // what the console may change
+    fn writable(&mut self, name: &str) -> Result<&mut CVar, CVarError> {
+        let cvar = self
+            .vars
+            .get_mut(name)
+            .ok_or_else(|| CVarError::Unknown(name.to_string()))?;
+        if cvar.flags.contains(CVarFlags::READ_ONLY) {
+            return Err(CVarError::ReadOnly(name.to_string()));
+        }
+        if cvar.flags.contains(CVarFlags::DEV) && !cfg!(debug_assertions) {
+            return Err(CVarError::DevOnly(name.to_string()));
+        }
+        Ok(cvar)
+    }
+
+    /// Runs one line of console input, returns what it prints
+    pub fn execute(&mut self, line: &str) -> Result<Vec<String>, CVarError> {
+        let mut printed = Vec::new();
+        for command in split_commands(line) {
+            let words = split_words(command);
+            let Some((command, args)) = words.split_first() else {
+                continue;
+            };
+            match (command.as_str(), args) {
+                ("toggle", [name]) => {
+                    let cvar = self.writable(name)?;
+                    let CVarValue::Bool(value) = cvar.value else {
+                        return Err(CVarError::Type {
+                            name: name.clone(),
+                            expected: cvar.default.type_name(),
+                        });
+                    };
+                    cvar.value = CVarValue::Bool(!value);
+                }
+                ("reset", [name]) => {
+                    let cvar = self.writable(name)?;
+                    cvar.value = cvar.default.clone();
+                }
+                ("list", prefix) => printed.extend(
+                    self.vars
+                        .values()
+                        .filter(|cvar| cvar.name.starts_with(prefix.first().map_or("", |p| p)))
+                        .map(describe),
+                ),
+                ("exec", [path]) => self.exec_file(path)?,
+                ("toggle" | "reset" | "exec", _) => {
+                    return Err(CVarError::Usage("expected toggle|reset|exec <name>"));
+                }
+                (name, []) => printed.push(describe(
+                    self.get(name)
+                        .ok_or_else(|| CVarError::Unknown(name.to_string()))?,
+                )),
+                (name, [text]) => {
+                    let cvar = self.writable(name)?;
+                    let value = cvar
+                        .default
+                        .parse_like(text)
+                        .ok_or_else(|| CVarError::Parse {
+                            name: name.to_string(),
+                            value: text.clone(),
+                            expected: cvar.default.type_name(),
+                        })?;
+                    cvar.value = cvar.clamped(value);
+                }
+                _ => return Err(CVarError::Usage("expected <name> <value>, quote strings")),
+            }
+        }
+        Ok(printed)
+    }
+
+    /// Runs every line of a script, lines that fail are skipped and the first failure is returned
+    pub fn exec_script(&mut self, source: &str) -> Result<(), CVarError> {
+        let mut first_error = None;
+        for (line, text) in source.lines().enumerate() {
+            match self.execute(text) {
+                Ok(printed) => printed.iter().for_each(|text| info!("{text}")),
+                Err(error) => {
+                    warn!("Console Script Line {}: {error}", line + 1);
+                    first_error.get_or_insert(CVarError::Script {
+                        line: line + 1,
+                        error: Box::new(error),
+                    });
+                }
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// Runs a config file
+    pub fn exec_file(&mut self, path: impl AsRef<Path>) -> Result<(), CVarError> {
+        self.exec_script(&fs::read_to_string(path)?)
+    }
+
+    /// Script setting every changed ARCHIVE variable
+    pub fn overrides(&self) -> String {
+        self.vars
+            .values()
+            .filter(|cvar| cvar.flags.contains(CVarFlags::ARCHIVE) && cvar.is_modified())
+            .map(|cvar| format!("{} {}\n", cvar.name, cvar.value))
+            .collect()
+    }
+
+    /// Writes overrides to path so exec_file restores them next run
+    pub fn save_overrides(&self, path: impl AsRef<Path>) -> Result<(), CVarError> {
+        Ok(fs::write(path, self.overrides())?)
+    }
+}
+
+fn describe(cvar: &CVar) -> String {
+    let mut text = format!("{} = {}", cvar.name, cvar.value);
+    if cvar.is_modified() {
+        text += &format!(" (default {})", cvar.default);
+    }
+    if let Some((min, max)) = cvar.range {
+        text += &format!(" [{min}, {max}]");
+    }
+    if !cvar.description.is_empty() {
+        text += &format!(" {}", cvar.description);
+    }
+    text
+}
+
+// ; separated commands with // comments removed, both are ignored inside quotes
+fn split_commands(line: &str) -> Vec<&str> {
+    let mut commands = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    let bytes = line.as_bytes();
+    for (index, &byte) in bytes.iter().enumerate() {
+        match byte {
+            b'"' => quoted = !quoted,
+            b';' if !quoted => {
+                commands.push(&line[start..index]);
+                start = index + 1;
+            }
+            b'/' if !quoted && bytes.get(index + 1) == Some(&b'/') => {
+                commands.push(&line[start..index]);
+                return commands;
+            }
+            _ => (),
+        }
+    }
+    commands.push(&line[start..]);
+    commands
+}
+
+// whitespace separated words, "quoted words" keep their spaces and \" \\ escapes
+fn split_words(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut chars = command.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut word = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => word.extend(chars.next()),
+                    c => word.push(c),
+                }
+            }
+            words.push(word);
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek().filter(|c| !c.is_whitespace()) {
+                word.push(c);
+                chars.next();
+            }
+            words.push(word);
+        }
+    }
+    words
+}
+
+#[test]
+fn cvar_test() {
+    let mut cvars = CVars::default();
+    cvars
+        .register(CVar::new("r_vertex_budget", 0i64, "").with_range(0.0, 1e9))
+        .register(CVar::new("name", "player", "").with_flags(CVarFlags::ARCHIVE))
+        .register(CVar::new("build", "dev", "").with_flags(CVarFlags::READ_ONLY));
+
+    cvars.execute("r_vertex_budget -5").unwrap();
+    assert_eq!(cvars.get_int("r_vertex_budget"), Some(0));
+    assert!(matches!(
+        cvars.execute("r_vertex_budget lots"),
+        Err(CVarError::Parse { .. })
+    ));
+    assert!(matches!(
+        cvars.set("r_vertex_budget", 1.5),
+        Err(CVarError::Type { .. })
+    ));
+    assert!(matches!(
+        cvars.execute("build release"),
+        Err(CVarError::ReadOnly(_))
+    ));
+    assert!(matches!(
+        cvars.execute("toggle name"),
+        Err(CVarError::Type { .. })
+    ));
+
+    cvars.execute(r#"name "a \"b\"; c" // comment"#).unwrap();
+    assert_eq!(cvars.get_str("name"), Some(r#"a "b"; c"#));
+    assert_eq!(
+        cvars.execute("name").unwrap(),
+        [r#"name = "a \"b\"; c" (default "player")"#]
+    );
+
+    // overrides read back into a fresh set of variables
+    let overrides = cvars.overrides();
+    let mut restored = CVars::default();
+    restored.register(CVar::new("name", "player", "").with_flags(CVarFlags::ARCHIVE));
+    restored.exec_script(&overrides).unwrap();
+    assert_eq!(restored.get_str("name"), cvars.get_str("name"));
+
+    let error = restored.exec_script("name x\nmissing 1\n").unwrap_err();
+    assert!(matches!(error, CVarError::Script { line: 2, .. }));
+    assert_eq!(restored.get_str("name"), Some("x"));
+}
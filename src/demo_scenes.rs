@@ -0,0 +1,218 @@
+use glam::{Mat4, Quat, Vec3};
+
+use crate::color::{Hsva, LinearRgba};
+use crate::renderer::MeshInstance;
+use crate::validation::{SceneIssue, SceneValidationError, validate_instances};
+
+/// Procedurally generated content for demos and stress tests
+/// so renderer features can be checked without any external assets
+/// Example Use:
+/// ```
+/// use vulkan_engine::demo_scenes::DemoScene;
+///
+/// // 32x32 cubes with a light for each of them
+/// let scene = DemoScene::cube_grid(32, 2.0).scatter_lights(32 * 32, 7);
+/// let instances = scene.instances_at(1.5);
+/// assert_eq!(instances.len(), 1024);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DemoScene {
+    pub instances: Vec<DemoInstance>,
+    pub lights: Vec<DemoLight>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DemoInstance {
+    pub position: Vec3,
+    pub scale: f32,
+    pub color: LinearRgba,
+    pub animation: DemoAnimation,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DemoAnimation {
+    Static,
+    /// rotate around axis at speed radians per second
+    Spin {
+        axis: Vec3,
+        speed: f32,
+    },
+    /// move up and down by height at speed cycles per second
+    Bob {
+        height: f32,
+        speed: f32,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DemoLight {
+    pub position: Vec3,
+    pub color: LinearRgba,
+    pub intensity: f32,
+    pub radius: f32,
+}
+
+impl DemoScene {
+    /// size x size grid of unit cubes centred on the origin, each with its own colour
+    /// cubes alternate between static, spinning and bobbing
+    pub fn cube_grid(size: u32, spacing: f32) -> Self {
+        let mut rng = SplitMix64(size as u64);
+        let half_extent = (size.saturating_sub(1)) as f32 * spacing * 0.5;
+        let count = size * size;
+
+        let instances = (0..count)
+            .map(|index| {
+                let (x, z) = (index % size, index / size);
+                let position = Vec3::new(
+                    x as f32 * spacing - half_extent,
+                    0.0,
+                    z as f32 * spacing - half_extent,
+                );
+
+                let animation = match index % 3 {
+                    0 => DemoAnimation::Static,
+                    1 => DemoAnimation::Spin {
+                        axis: Vec3::new(rng.next_f32() - 0.5, 1.0, rng.next_f32() - 0.5)
+                            .normalize(),
+                        speed: 0.5 + rng.next_f32() * 2.0,
+                    },
+                    _ => DemoAnimation::Bob {
+                        height: 0.25 + rng.next_f32() * 0.5,
+                        speed: 0.2 + rng.next_f32(),
+                    },
+                };
+
+                DemoInstance {
+                    position,
+                    scale: 1.0,
+                    color: Hsva::from_hue(index as f32 / count as f32).into(),
+                    animation,
+                }
+            })
+            .collect();
+
+        Self {
+            instances,
+            lights: Vec::new(),
+        }
+    }
+
+    /// Adds count point lights scattered over the scene
+    /// the same seed always gives the same lights
+    pub fn scatter_lights(mut self, count: u32, seed: u64) -> Self {
+        let mut rng = SplitMix64(seed);
+        let radius = self.radius().max(1.0);
+
+        self.lights.extend((0..count).map(|_| DemoLight {
+            position: Vec3::new(
+                (rng.next_f32() * 2.0 - 1.0) * radius,
+                0.5 + rng.next_f32() * 2.0,
+                (rng.next_f32() * 2.0 - 1.0) * radius,
+            ),
+            color: Hsva::from_hue(rng.next_f32()).into(),
+            intensity: 0.5 + rng.next_f32() * 4.5,
+            radius: 1.0 + rng.next_f32() * 4.0,
+        }));
+        self
+    }
+
+    /// Radius of a sphere around the origin containing every instance
+    pub fn radius(&self) -> f32 {
+        self.instances
+            .iter()
+            .map(|instance| instance.position.length() + instance.scale)
+            .fold(0.0, f32::max)
+    }
+
+    /// Checks instances and lights for values that would break rendering
+    /// reports every issue found rather than stopping at the first one
+    pub fn validate(&self) -> Result<(), SceneValidationError> {
+        let mut issues = validate_instances(&self.instances_at(0.0));
+
+        for (light, demo_light) in self.lights.iter().enumerate() {
+            if !(demo_light.position.is_finite()
+                && demo_light.color.is_finite()
+                && demo_light.intensity.is_finite())
+            {
+                issues.push(SceneIssue::NonFiniteLight { light });
+            }
+            if demo_light.radius.is_nan() || demo_light.radius <= 0.0 {
+                issues.push(SceneIssue::InvalidLightRadius {
+                    light,
+                    radius: demo_light.radius,
+                });
+            }
+        }
+
+        SceneValidationError::check(issues)
+    }
+
+    /// Instances with their animations evaluated at time seconds
+    pub fn instances_at(&self, time: f32) -> Vec<MeshInstance> {
+        self.instances
+            .iter()
+            .map(|instance| {
+                let (rotation, offset) = match instance.animation {
+                    DemoAnimation::Static => (Quat::IDENTITY, Vec3::ZERO),
+                    DemoAnimation::Spin { axis, speed } => {
+                        (Quat::from_axis_angle(axis, time * speed), Vec3::ZERO)
+                    }
+                    DemoAnimation::Bob { height, speed } => (
+                        Quat::IDENTITY,
+                        Vec3::Y * height * (time * speed * std::f32::consts::TAU).sin(),
+                    ),
+                };
+
+                MeshInstance {
+                    transform: Mat4::from_scale_rotation_translation(
+                        Vec3::splat(instance.scale),
+                        rotation,
+                        instance.position + offset,
+                    ),
+                    tint: instance.color,
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+}
+
+// small deterministic random number generator so scenes are reproducible
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // uniform in 0..1
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[test]
+fn cube_grid_test() {
+    let scene = DemoScene::cube_grid(4, 2.0).scatter_lights(100, 1);
+
+    assert_eq!(scene.instances.len(), 16);
+    assert_eq!(scene.lights.len(), 100);
+
+    // grid is centred on the origin
+    let centre = scene
+        .instances
+        .iter()
+        .fold(Vec3::ZERO, |acc, instance| acc + instance.position)
+        / 16.0;
+    assert!(centre.length() < 1e-5);
+
+    // same seed same lights
+    let again = DemoScene::cube_grid(4, 2.0).scatter_lights(100, 1);
+    assert_eq!(scene.lights[42].position, again.lights[42].position);
+
+    assert!(scene.validate().is_ok());
+}
@@ -0,0 +1,423 @@
+use gilrs::ff::{
+    BaseEffect, BaseEffectType, Effect, EffectBuilder, Envelope, Repeat, Replay, Ticks,
+};
+use gilrs::{EventType, Gilrs};
+use log::info;
+use std::time::Duration;
+use thiserror::Error;
+
+pub use gilrs::GamepadId;
+
+#[derive(Debug, Error)]
+pub enum GamepadError {
+    // gilrs::Error can carry a whole Gilrs, only its message is kept
+    #[error("gamepads are unavailable: {0}")]
+    Unavailable(String),
+    #[error("force feedback failed: {0}")]
+    ForceFeedback(#[from] gilrs::ff::Error),
+}
+
+/// What a connected gamepad can do, see Gamepads::capabilities
+#[derive(Clone, Debug, PartialEq)]
+pub struct GamepadCapabilities {
+    pub id: GamepadId,
+    pub name: String,
+    /// rumble patterns can be played on it
+    pub force_feedback: bool,
+    /// battery left from 0 to 1, None when wired or unknown
+    pub battery: Option<f32>,
+}
+
+/// Fade in and out of a rumble pulse, levels are fractions of the pulse's strength
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RumbleEnvelope {
+    pub attack: Duration,
+    /// strength the attack starts from
+    pub attack_level: f32,
+    pub fade: Duration,
+    /// strength the fade ends on
+    pub fade_level: f32,
+}
+
+impl Default for RumbleEnvelope {
+    fn default() -> Self {
+        Self {
+            attack: Duration::ZERO,
+            attack_level: 1.0,
+            fade: Duration::ZERO,
+            fade_level: 1.0,
+        }
+    }
+}
+
+impl RumbleEnvelope {
+    /// Ramps up from nothing over attack and back down to nothing over fade
+    pub fn ramp(attack: Duration, fade: Duration) -> Self {
+        Self {
+            attack,
+            attack_level: 0.0,
+            fade,
+            fade_level: 0.0,
+        }
+    }
+
+    // gilrs wants the attack and fade to fit inside the pulse, longer ones are shortened to fit
+    fn fitted(&self, duration: Duration) -> Self {
+        let total = self.attack + self.fade;
+        if total < duration || total.is_zero() {
+            return *self;
+        }
+        let scale = duration.as_secs_f32() / total.as_secs_f32() * 0.99;
+        Self {
+            attack: self.attack.mul_f32(scale),
+            fade: self.fade.mul_f32(scale),
+            ..*self
+        }
+    }
+
+    fn level_at(&self, time: Duration, duration: Duration) -> f32 {
+        let envelope = self.fitted(duration);
+        if time < envelope.attack {
+            let progress = time.as_secs_f32() / envelope.attack.as_secs_f32();
+            envelope.attack_level + (1.0 - envelope.attack_level) * progress
+        } else if time + envelope.fade > duration {
+            let progress =
+                (time + envelope.fade - duration).as_secs_f32() / envelope.fade.as_secs_f32();
+            1.0 + (envelope.fade_level - 1.0) * progress
+        } else {
+            1.0
+        }
+    }
+}
+
+/// One burst of rumble in a RumblePattern
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RumblePulse {
+    /// low frequency motor from 0 to 1, the heavy rumble
+    pub strong: f32,
+    /// high frequency motor from 0 to 1, the light buzz
+    pub weak: f32,
+    /// time from the start of the pattern
+    pub start: Duration,
+    pub duration: Duration,
+    pub envelope: RumbleEnvelope,
+}
+
+impl RumblePulse {
+    pub fn new(strong: f32, weak: f32, duration: Duration) -> Self {
+        Self {
+            strong: strong.clamp(0.0, 1.0),
+            weak: weak.clamp(0.0, 1.0),
+            start: Duration::ZERO,
+            duration,
+            envelope: RumbleEnvelope::default(),
+        }
+    }
+
+    pub fn after(mut self, start: Duration) -> Self {
+        self.start = start;
+        self
+    }
+
+    pub fn with_envelope(mut self, envelope: RumbleEnvelope) -> Self {
+        self.envelope = envelope;
+        self
+    }
+
+    fn end(&self) -> Duration {
+        self.start + self.duration
+    }
+}
+
+/// Pulses played together on a gamepad's motors, overlapping pulses add up
+/// gilrs plays them in 50ms steps, so shorter details are rounded up
+/// Example Use:
+/// ```
+/// use std::time::Duration;
+/// use vulkan_engine::gamepad::{RumbleEnvelope, RumblePattern, RumblePulse};
+///
+/// // a heartbeat, two thumps a beat that keep going until stopped
+/// let thump = RumbleEnvelope::ramp(Duration::from_millis(50), Duration::from_millis(100));
+/// let heartbeat = RumblePattern::default()
+///     .pulse(RumblePulse::new(0.8, 0.0, Duration::from_millis(200)).with_envelope(thump))
+///     .pulse(
+///         RumblePulse::new(0.5, 0.0, Duration::from_millis(200))
+///             .after(Duration::from_millis(250))
+///             .with_envelope(thump),
+///     )
+///     .period(Duration::from_millis(900))
+///     .looping();
+///
+/// let (strong, weak) = heartbeat.strength_at(Duration::from_millis(1000));
+/// assert!(strong > 0.0 && weak == 0.0);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RumblePattern {
+    pub pulses: Vec<RumblePulse>,
+    /// time between repeats, at least as long as the pulses
+    pub period: Option<Duration>,
+    /// None plays the pattern once, Some(None) repeats it until stopped
+    pub repeat: Option<Option<Duration>>,
+}
+
+impl RumblePattern {
+    /// A single pulse with both motors
+    pub fn once(strong: f32, weak: f32, duration: Duration) -> Self {
+        Self::default().pulse(RumblePulse::new(strong, weak, duration))
+    }
+
+    pub fn pulse(mut self, pulse: RumblePulse) -> Self {
+        self.pulses.push(pulse);
+        self
+    }
+
+    pub fn period(mut self, period: Duration) -> Self {
+        self.period = Some(period);
+        self
+    }
+
+    /// Repeats the pattern until the Rumble is stopped or dropped
+    pub fn looping(mut self) -> Self {
+        self.repeat = Some(None);
+        self
+    }
+
+    /// Repeats the pattern until total has passed
+    pub fn repeat_for(mut self, total: Duration) -> Self {
+        self.repeat = Some(Some(total));
+        self
+    }
+
+    /// Time from one repeat to the next, the end of the last pulse when no period is set
+    pub fn length(&self) -> Duration {
+        let end = self
+            .pulses
+            .iter()
+            .map(RumblePulse::end)
+            .max()
+            .unwrap_or_default();
+        self.period.map_or(end, |period| period.max(end))
+    }
+
+    /// How long the pattern plays, None when it loops
+    pub fn total_length(&self) -> Option<Duration> {
+        match self.repeat {
+            None => Some(self.length()),
+            Some(total) => total,
+        }
+    }
+
+    /// Strong and weak motor strength time after the pattern started playing
+    pub fn strength_at(&self, time: Duration) -> (f32, f32) {
+        let length = self.length();
+        if length.is_zero() || self.total_length().is_some_and(|total| time >= total) {
+            return (0.0, 0.0);
+        }
+        let time = Duration::from_secs_f64(time.as_secs_f64() % length.as_secs_f64());
+        let (mut strong, mut weak) = (0.0, 0.0);
+        for pulse in &self.pulses {
+            if time < pulse.start || time >= pulse.end() {
+                continue;
+            }
+            let level = pulse.envelope.level_at(time - pulse.start, pulse.duration);
+            strong += pulse.strong * level;
+            weak += pulse.weak * level;
+        }
+        (f32::min(strong, 1.0), f32::min(weak, 1.0))
+    }
+
+    // each motor of each pulse is its own gilrs effect, after only delays the first play so every
+    // pulse waits out the rest of the length before repeating
+    fn base_effects(&self) -> Vec<BaseEffect> {
+        let length = self.length();
+        self.pulses
+            .iter()
+            .filter(|pulse| !pulse.duration.is_zero())
+            .flat_map(|pulse| {
+                let envelope = pulse.envelope.fitted(pulse.duration);
+                let scheduling = Replay {
+                    after: pulse.start.into(),
+                    play_for: pulse.duration.into(),
+                    with_delay: (length - pulse.duration).into(),
+                };
+                let envelope = Envelope {
+                    attack_length: envelope.attack.into(),
+                    attack_level: envelope.attack_level,
+                    fade_length: envelope.fade.into(),
+                    fade_level: envelope.fade_level,
+                };
+                let magnitude = |strength: f32| (strength * u16::MAX as f32) as u16;
+                [
+                    BaseEffectType::Strong {
+                        magnitude: magnitude(pulse.strong),
+                    },
+                    BaseEffectType::Weak {
+                        magnitude: magnitude(pulse.weak),
+                    },
+                ]
+                .into_iter()
+                .map(move |kind| BaseEffect {
+                    kind,
+                    scheduling,
+                    envelope,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A pattern playing on a gamepad, it stops when dropped
+pub struct Rumble {
+    effect: Effect,
+}
+
+impl Rumble {
+    pub fn stop(&self) -> Result<(), GamepadError> {
+        Ok(self.effect.stop()?)
+    }
+
+    /// Scales every pulse, 0 silences it without stopping
+    pub fn set_gain(&self, gain: f32) -> Result<(), GamepadError> {
+        Ok(self.effect.set_gain(gain.max(0.0))?)
+    }
+}
+
+/// Connected gamepads and their rumble motors, behind the gamepad feature
+/// update has to run every frame so connections and force feedback stay current
+/// Example Use:
+/// ```ignore
+/// // a short strong thump on every gamepad that can rumble
+/// let thump = RumblePattern::once(1.0, 0.3, Duration::from_millis(150));
+/// if let Some(gamepads) = &mut app_ctx.gamepads {
+///     for gamepad in gamepads.connected() {
+///         if gamepad.force_feedback {
+///             rumbles.push(gamepads.rumble(gamepad.id, &thump)?);
+///         }
+///     }
+/// }
+/// ```
+pub struct Gamepads {
+    gilrs: Gilrs,
+}
+
+impl Gamepads {
+    pub fn new() -> Result<Self, GamepadError> {
+        let gilrs = Gilrs::new().map_err(|error| GamepadError::Unavailable(error.to_string()))?;
+        Ok(Self { gilrs })
+    }
+
+    /// Handles connections and disconnections since the last update
+    pub fn update(&mut self) {
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::Connected => {
+                    let gamepad = self.gilrs.gamepad(event.id);
+                    info!(
+                        "Gamepad Connected: {}, Force Feedback: {}",
+                        gamepad.name(),
+                        gamepad.is_ff_supported()
+                    );
+                }
+                EventType::Disconnected => info!("Gamepad Disconnected: {}", event.id),
+                _ => (),
+            }
+        }
+    }
+
+    pub fn connected(&self) -> Vec<GamepadCapabilities> {
+        self.gilrs
+            .gamepads()
+            .map(|(id, _)| id)
+            .filter_map(|id| self.capabilities(id))
+            .collect()
+    }
+
+    /// None when the gamepad isn't connected
+    pub fn capabilities(&self, id: GamepadId) -> Option<GamepadCapabilities> {
+        let gamepad = self.gilrs.connected_gamepad(id)?;
+        let battery = match gamepad.power_info() {
+            gilrs::PowerInfo::Discharging(percent) | gilrs::PowerInfo::Charging(percent) => {
+                Some(percent as f32 / 100.0)
+            }
+            _ => None,
+        };
+        Some(GamepadCapabilities {
+            id,
+            name: gamepad.name().to_string(),
+            force_feedback: gamepad.is_ff_supported(),
+            battery,
+        })
+    }
+
+    /// Starts playing pattern on the gamepad, fails when it's gone or can't rumble
+    pub fn rumble(
+        &mut self,
+        id: GamepadId,
+        pattern: &RumblePattern,
+    ) -> Result<Rumble, GamepadError> {
+        let mut builder = EffectBuilder::new();
+        for effect in pattern.base_effects() {
+            builder.add_effect(effect);
+        }
+        let repeat = match pattern.total_length() {
+            Some(total) => Repeat::For(Ticks::from(total)),
+            None => Repeat::Infinitely,
+        };
+        let effect = builder
+            .gamepads(&[id])
+            .repeat(repeat)
+            .finish(&mut self.gilrs)?;
+        effect.play()?;
+        Ok(Rumble { effect })
+    }
+}
+
+#[test]
+fn rumble_pattern_test() {
+    let millis = Duration::from_millis;
+    let pattern = RumblePattern::default()
+        .pulse(RumblePulse::new(1.0, 0.0, millis(100)))
+        .pulse(RumblePulse::new(0.0, 0.5, millis(100)).after(millis(200)))
+        .period(millis(400))
+        .repeat_for(millis(1000));
+    assert_eq!(pattern.length(), millis(400));
+    assert_eq!(pattern.strength_at(millis(50)), (1.0, 0.0));
+    assert_eq!(pattern.strength_at(millis(150)), (0.0, 0.0));
+    assert_eq!(pattern.strength_at(millis(250)), (0.0, 0.5));
+    // second repeat, then nothing once the total has passed
+    assert_eq!(pattern.strength_at(millis(450)), (1.0, 0.0));
+    assert_eq!(pattern.strength_at(millis(1050)), (0.0, 0.0));
+
+    // overlapping pulses add up to at most full strength
+    let overlap = RumblePattern::once(0.75, 0.0, millis(100))
+        .pulse(RumblePulse::new(0.75, 0.0, millis(100)).after(millis(50)));
+    assert_eq!(overlap.strength_at(millis(75)), (1.0, 0.0));
+    assert_eq!(overlap.strength_at(millis(200)), (0.0, 0.0));
+
+    // a strong and a weak effect per pulse, repeating every length
+    let effects = pattern.base_effects();
+    assert_eq!(effects.len(), 4);
+    assert_eq!(effects[2].scheduling.after, Ticks::from_ms(200));
+    assert_eq!(
+        effects[2].scheduling.play_for + effects[2].scheduling.with_delay,
+        Ticks::from_ms(400)
+    );
+}
+
+#[test]
+fn rumble_envelope_test() {
+    let millis = Duration::from_millis;
+    let pulse = RumblePulse::new(1.0, 1.0, millis(400))
+        .with_envelope(RumbleEnvelope::ramp(millis(100), millis(200)));
+    let pattern = RumblePattern::default().pulse(pulse);
+    let (strong, weak) = pattern.strength_at(millis(50));
+    assert!((strong - 0.5).abs() < 1e-4 && strong == weak);
+    assert_eq!(pattern.strength_at(millis(150)), (1.0, 1.0));
+    let (strong, _) = pattern.strength_at(millis(300));
+    assert!((strong - 0.5).abs() < 1e-4);
+
+    // an envelope longer than its pulse is shortened to fit inside it
+    let envelope = RumbleEnvelope::ramp(millis(300), millis(300)).fitted(millis(200));
+    assert!(envelope.attack + envelope.fade < millis(200));
+    assert_eq!(envelope.attack, envelope.fade);
+}
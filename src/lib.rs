@@ -1,3 +1,33 @@
+pub mod animation;
 pub mod app;
+pub mod assets;
+pub mod camera;
+pub mod color;
+pub mod convention;
+pub mod crash_report;
+pub mod crowd;
+pub mod cvar;
+pub mod demo_scenes;
+#[cfg(feature = "gamepad")]
+pub mod gamepad;
+pub mod lighting;
+pub mod lod;
+pub mod math;
+pub mod mesh_cache;
+#[cfg(feature = "navmesh")]
+pub mod navmesh;
+pub mod photo_mode;
+pub mod prefab;
 pub mod renderer;
+pub mod replication;
+pub mod resize_stress;
+pub mod scene;
+pub mod smoke_test;
+pub mod snapshot;
+pub mod streaming;
+pub mod text_input;
+pub mod time;
+pub mod tween;
+pub mod undo;
 pub mod utils;
+pub mod validation;
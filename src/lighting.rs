@@ -0,0 +1,312 @@
+use glam::{Mat4, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+use crate::color::LinearRgba;
+
+/// Most lights uploaded per frame, matches MAX_LIGHTS in triangle.slang
+pub const MAX_LIGHTS: usize = 64;
+
+/// Index of a light in Lighting, stays valid until the light is removed
+pub type LightId = usize;
+
+/// Shape of a light
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LightKind {
+    /// infinitely far away like the sun, direction is the way the light travels
+    Directional { direction: Vec3 },
+    /// shines in every direction, fades out to nothing at range
+    Point { position: Vec3, range: f32 },
+    /// cone of light, full strength inside inner_angle fading out at outer_angle
+    /// angles in radians from the centre of the cone
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+/// A light in the scene, lit materials add up every light's contribution
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Light {
+    pub kind: LightKind,
+    /// linear colour, multiplied by intensity
+    pub color: LinearRgba,
+    pub intensity: f32,
+}
+
+impl Light {
+    pub fn directional(direction: Vec3) -> Self {
+        Self::new(LightKind::Directional { direction })
+    }
+
+    pub fn point(position: Vec3, range: f32) -> Self {
+        Self::new(LightKind::Point { position, range })
+    }
+
+    pub fn spot(position: Vec3, direction: Vec3, range: f32, outer_angle: f32) -> Self {
+        Self::new(LightKind::Spot {
+            position,
+            direction,
+            range,
+            inner_angle: outer_angle * 0.8,
+            outer_angle,
+        })
+    }
+
+    fn new(kind: LightKind) -> Self {
+        Self {
+            kind,
+            color: LinearRgba::WHITE,
+            intensity: 1.0,
+        }
+    }
+
+    pub fn with_color(mut self, color: LinearRgba) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Light as the shaders see it
+    pub fn to_gpu(&self) -> GpuLight {
+        let (position, range, direction, light_type, cone) = match self.kind {
+            LightKind::Directional { direction } => (Vec3::ZERO, 0.0, direction, 0, Vec4::ZERO),
+            LightKind::Point { position, range } => (position, range, Vec3::ZERO, 1, Vec4::ZERO),
+            LightKind::Spot {
+                position,
+                direction,
+                range,
+                inner_angle,
+                outer_angle,
+            } => {
+                let cone = Vec4::new(inner_angle.cos(), outer_angle.cos(), 0.0, 0.0);
+                (position, range, direction, 2, cone)
+            }
+        };
+
+        GpuLight {
+            position_range: position.extend(range),
+            direction_type: direction.normalize_or_zero().extend(light_type as f32),
+            color_intensity: self.color.to_vec3().extend(self.intensity),
+            cone,
+        }
+    }
+
+    /// Light reaching point on a surface facing normal, what lit materials multiply their colour by
+    /// matches lightContribution in triangle.slang
+    pub fn contribution(&self, point: Vec3, normal: Vec3) -> Vec3 {
+        let (to_light, falloff) = match self.kind {
+            LightKind::Directional { direction } => (-direction.normalize_or_zero(), 1.0),
+            LightKind::Point { position, range } => {
+                let offset = position - point;
+                let distance = offset.length();
+                (offset / distance.max(1e-4), attenuation(distance, range))
+            }
+            LightKind::Spot {
+                position,
+                direction,
+                range,
+                inner_angle,
+                outer_angle,
+            } => {
+                let offset = position - point;
+                let distance = offset.length();
+                let to_light = offset / distance.max(1e-4);
+                let cos_angle = (-to_light).dot(direction.normalize_or_zero());
+                let cone = smoothstep(outer_angle.cos(), inner_angle.cos(), cos_angle);
+                (to_light, attenuation(distance, range) * cone)
+            }
+        };
+
+        let diffuse = normal.dot(to_light).max(0.0);
+        self.color.to_vec3() * self.intensity * diffuse * falloff
+    }
+}
+
+/// Windowed inverse square falloff, 1 / distance² smoothly reaching 0 at range
+pub fn attenuation(distance: f32, range: f32) -> f32 {
+    let ratio = distance / range;
+    let window = (1.0 - ratio.powi(4)).clamp(0.0, 1.0);
+    window * window / (distance * distance).max(1e-4)
+}
+
+// hermite between edge0 and edge1 like the shader builtin
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Light as the shaders see it, matches Light in triangle.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GpuLight {
+    /// xyz position, w range
+    pub position_range: Vec4,
+    /// xyz direction the light travels, w 0 directional, 1 point, 2 spot
+    pub direction_type: Vec4,
+    /// rgb linear colour, a intensity
+    pub color_intensity: Vec4,
+    /// x cos inner angle, y cos outer angle
+    pub cone: Vec4,
+}
+
+/// Every light for a frame, uploaded to set 0 binding 3, matches Lights in triangle.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightUniform {
+    pub ambient: Vec4,
+    pub count: u32,
+    pub _padding: [u32; 3],
+    pub lights: [GpuLight; MAX_LIGHTS],
+    /// camera the sampled ambient occlusion was worked out through, see VKAmbientOcclusion
+    pub occlusion_view_projection: Mat4,
+    /// x is 1 while there is ambient occlusion to sample
+    pub occlusion: Vec4,
+}
+
+/// Lights registered with the renderer
+/// Example Use:
+/// ```
+/// use glam::Vec3;
+/// use vulkan_engine::color::LinearRgba;
+/// use vulkan_engine::lighting::{Light, Lighting};
+///
+/// let mut lighting = Lighting::default();
+/// lighting.add(Light::directional(Vec3::new(-1.0, -2.0, -1.0)).with_intensity(0.8));
+/// let lamp = lighting.add(
+///     Light::point(Vec3::new(0.0, 2.0, 0.0), 5.0)
+///         .with_color(LinearRgba::rgb(1.0, 0.6, 0.3))
+///         .with_intensity(4.0),
+/// );
+///
+/// // lights can be moved every frame
+/// if let Some(lamp) = lighting.get_mut(lamp) {
+///     lamp.intensity = 2.0;
+/// }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Lighting {
+    /// added to every light, keeps unlit sides from going black
+    pub ambient: LinearRgba,
+    /// stops of brightness applied to every light and the ambient, emissive surfaces are left alone
+    #[serde(default)]
+    pub exposure: f32,
+    // removed lights leave a hole so other ids stay valid
+    lights: Vec<Option<Light>>,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            ambient: LinearRgba::rgb(0.03, 0.03, 0.03),
+            exposure: 0.0,
+            lights: Vec::new(),
+        }
+    }
+}
+
+impl Lighting {
+    pub fn add(&mut self, light: Light) -> LightId {
+        self.lights.push(Some(light));
+        self.lights.len() - 1
+    }
+
+    pub fn remove(&mut self, light: LightId) -> Option<Light> {
+        self.lights.get_mut(light).and_then(Option::take)
+    }
+
+    pub fn get(&self, light: LightId) -> Option<&Light> {
+        self.lights.get(light).and_then(Option::as_ref)
+    }
+
+    pub fn get_mut(&mut self, light: LightId) -> Option<&mut Light> {
+        self.lights.get_mut(light).and_then(Option::as_mut)
+    }
+
+    /// Every light with its id
+    pub fn iter(&self) -> impl Iterator<Item = (LightId, &Light)> {
+        self.lights
+            .iter()
+            .enumerate()
+            .filter_map(|(id, light)| light.as_ref().map(|light| (id, light)))
+    }
+
+    pub fn clear(&mut self) {
+        self.lights.clear();
+    }
+
+    /// Lights packed for upload, only the first MAX_LIGHTS are kept
+    pub fn uniform(&self) -> LightUniform {
+        let exposure = self.exposure.exp2();
+        let mut uniform = LightUniform {
+            ambient: (self.ambient.to_vec3() * exposure).extend(self.ambient.a),
+            count: 0,
+            _padding: [0; 3],
+            lights: [GpuLight::default(); MAX_LIGHTS],
+            occlusion_view_projection: Mat4::IDENTITY,
+            occlusion: Vec4::ZERO,
+        };
+        for ((_, light), gpu_light) in self.iter().zip(&mut uniform.lights) {
+            *gpu_light = light.to_gpu();
+            gpu_light.color_intensity.w *= exposure;
+            uniform.count += 1;
+        }
+        uniform
+    }
+}
+
+#[test]
+fn lighting_test() {
+    // std140 layout the shader expects
+    assert_eq!(size_of::<GpuLight>(), 64);
+    assert_eq!(std::mem::offset_of!(LightUniform, lights), 32);
+    assert_eq!(
+        std::mem::offset_of!(LightUniform, occlusion_view_projection),
+        32 + 64 * MAX_LIGHTS
+    );
+
+    let sun = Light::directional(Vec3::NEG_Y).with_intensity(2.0);
+    assert!(
+        sun.contribution(Vec3::ZERO, Vec3::Y)
+            .abs_diff_eq(Vec3::splat(2.0), 1e-5)
+    );
+    // facing away gets nothing
+    assert_eq!(sun.contribution(Vec3::ZERO, Vec3::NEG_Y), Vec3::ZERO);
+
+    // inverse square up close, nothing past range
+    let lamp = Light::point(Vec3::Y * 2.0, 10.0);
+    let near = lamp.contribution(Vec3::Y, Vec3::Y).x;
+    let far = lamp.contribution(Vec3::ZERO, Vec3::Y).x;
+    assert!(near > far * 3.5 && near < far * 4.5);
+    assert_eq!(lamp.contribution(Vec3::NEG_Y * 9.0, Vec3::Y), Vec3::ZERO);
+
+    let spot = Light::spot(Vec3::Y * 2.0, Vec3::NEG_Y, 10.0, 30.0_f32.to_radians());
+    assert!(spot.contribution(Vec3::ZERO, Vec3::Y).x > 0.0);
+    assert_eq!(spot.contribution(Vec3::X * 5.0, Vec3::Y), Vec3::ZERO);
+
+    let mut lighting = Lighting::default();
+    let first = lighting.add(sun);
+    for _ in 0..MAX_LIGHTS {
+        lighting.add(lamp);
+    }
+    assert_eq!(lighting.uniform().count, MAX_LIGHTS as u32);
+    lighting.remove(first);
+    let uniform = lighting.uniform();
+    assert_eq!(uniform.lights[0].direction_type.w, 1.0);
+    assert_eq!(
+        uniform.lights[0].position_range,
+        Vec4::new(0.0, 2.0, 0.0, 10.0)
+    );
+
+    // a stop up doubles every light
+    lighting.exposure = 1.0;
+    let exposed = lighting.uniform();
+    assert_eq!(exposed.lights[0].color_intensity.w, 2.0);
+    assert_eq!(exposed.ambient.x, uniform.ambient.x * 2.0);
+}
@@ -0,0 +1,194 @@
+use glam::{Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::{Camera, Projection};
+use crate::math::Aabb;
+use crate::renderer::mesh::MeshId;
+
+/// One mesh of a LodGroup
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LodLevel {
+    pub mesh: MeshId,
+    /// drawn while the node's bounding sphere covers at least this fraction of the screen height
+    pub min_coverage: f32,
+}
+
+impl LodLevel {
+    pub fn new(mesh: MeshId, min_coverage: f32) -> Self {
+        Self { mesh, min_coverage }
+    }
+}
+
+/// Meshes of one model from most to least detailed
+/// Example Use:
+/// ```ignore
+/// let rock = LodGroup::new(vec![
+///     LodLevel::new(rock_high, 0.25),
+///     LodLevel::new(rock_medium, 0.05),
+///     LodLevel::new(rock_low, 0.0),
+/// ]);
+/// scene.add(Node::new("rock").with_lods(rock, stone_material), None)?;
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LodGroup {
+    pub levels: Vec<LodLevel>,
+}
+
+impl LodGroup {
+    /// Levels are sorted so the most detailed comes first
+    pub fn new(mut levels: Vec<LodLevel>) -> Self {
+        levels.sort_by(|a, b| b.min_coverage.total_cmp(&a.min_coverage));
+        Self { levels }
+    }
+
+    /// Index of the most detailed level allowed at coverage
+    /// the least detailed level is kept when the node is smaller than every threshold
+    pub fn select(&self, coverage: f32) -> Option<usize> {
+        let last = self.levels.len().checked_sub(1)?;
+        Some(
+            self.levels
+                .iter()
+                .position(|level| coverage >= level.min_coverage)
+                .unwrap_or(last),
+        )
+    }
+}
+
+/// What a LodSelector needs to know about a mesh
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LodMeshInfo {
+    /// local space bounds
+    pub bounds: Aabb,
+    pub vertex_count: u32,
+}
+
+/// Picks LodGroup levels for a view during scene traversal, see Scene::mesh_instances_with_lod
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LodSelector {
+    pub eye: Vec3,
+    pub projection: Projection,
+    /// multiplies every coverage, above 1 keeps detail further away
+    pub bias: f32,
+    /// levels are stepped down, smallest nodes first, until the scene fits
+    pub vertex_budget: Option<u32>,
+}
+
+impl LodSelector {
+    pub fn new(camera: &Camera) -> Self {
+        Self {
+            eye: camera.position,
+            projection: camera.projection,
+            bias: 1.0,
+            vertex_budget: None,
+        }
+    }
+
+    pub fn with_bias(mut self, bias: f32) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    pub fn with_vertex_budget(mut self, vertex_budget: Option<u32>) -> Self {
+        self.vertex_budget = vertex_budget;
+        self
+    }
+
+    /// Fraction of the screen height covered by bounds placed with world_matrix, 1 when the eye is inside it
+    pub fn coverage(&self, bounds: &Aabb, world_matrix: &Mat4) -> f32 {
+        let sphere = bounds.transformed(world_matrix).bounding_sphere();
+        let coverage = match self.projection {
+            Projection::Perspective { fov_y, .. } => {
+                let distance = self.eye.distance(sphere.center);
+                if distance <= sphere.radius {
+                    return 1.0;
+                }
+                sphere.radius / (distance * (fov_y * 0.5).tan())
+            }
+            Projection::Orthographic { height, .. } => 2.0 * sphere.radius / height,
+        };
+        (coverage * self.bias).min(1.0)
+    }
+
+    /// Level index for every group, coverages are the ones the groups are drawn at
+    /// vertex_counts gives the vertices of each group's levels in the same order
+    pub fn select(&self, groups: &[(&LodGroup, f32)], vertex_counts: &[Vec<u32>]) -> Vec<usize> {
+        let mut selected: Vec<usize> = groups
+            .iter()
+            .map(|(group, coverage)| group.select(*coverage).unwrap_or(0))
+            .collect();
+        let Some(vertex_budget) = self.vertex_budget else {
+            return selected;
+        };
+
+        let vertices = |selected: &[usize]| -> u64 {
+            selected
+                .iter()
+                .zip(vertex_counts)
+                .map(|(&level, counts)| counts.get(level).copied().unwrap_or(0) as u64)
+                .sum()
+        };
+        // the least visible nodes lose detail first
+        let mut order: Vec<usize> = (0..groups.len()).collect();
+        order.sort_by(|&a, &b| groups[a].1.total_cmp(&groups[b].1));
+
+        let mut total = vertices(&selected);
+        while total > vertex_budget as u64 {
+            let mut stepped = false;
+            for &index in &order {
+                if selected[index] + 1 < vertex_counts[index].len() {
+                    total -= vertex_counts[index][selected[index]] as u64;
+                    selected[index] += 1;
+                    total += vertex_counts[index][selected[index]] as u64;
+                    stepped = true;
+                    if total <= vertex_budget as u64 {
+                        break;
+                    }
+                }
+            }
+            // everything is at its coarsest
+            if !stepped {
+                break;
+            }
+        }
+        selected
+    }
+}
+
+#[test]
+fn lod_select_test() {
+    let group = LodGroup::new(vec![
+        LodLevel::new(2, 0.0),
+        LodLevel::new(0, 0.5),
+        LodLevel::new(1, 0.1),
+    ]);
+    assert_eq!(
+        group
+            .levels
+            .iter()
+            .map(|level| level.mesh)
+            .collect::<Vec<_>>(),
+        [0, 1, 2]
+    );
+    assert_eq!(group.select(0.8), Some(0));
+    assert_eq!(group.select(0.2), Some(1));
+    assert_eq!(group.select(0.01), Some(2));
+    assert_eq!(LodGroup::default().select(1.0), None);
+
+    let camera = Camera::perspective(90.0_f32.to_radians(), 0.1);
+    let selector = LodSelector::new(&camera);
+    let unit = Aabb::new(Vec3::splat(-0.5), Vec3::splat(0.5));
+    let near = selector.coverage(&unit, &Mat4::from_translation(Vec3::new(0.0, 0.0, -2.0)));
+    let far = selector.coverage(&unit, &Mat4::from_translation(Vec3::new(0.0, 0.0, -20.0)));
+    assert!((near - 0.75_f32.sqrt() / 2.0).abs() < 1e-5);
+    assert!(far < near);
+    assert_eq!(selector.coverage(&unit, &Mat4::IDENTITY), 1.0);
+
+    let counts = vec![vec![1000, 100, 10]; 2];
+    let groups = [(&group, 0.8), (&group, 0.6)];
+    assert_eq!(selector.select(&groups, &counts), [0, 0]);
+    // the smaller node steps down first
+    let selector = selector.with_vertex_budget(Some(1100));
+    assert_eq!(selector.select(&groups, &counts), [0, 1]);
+    let selector = selector.with_vertex_budget(Some(5));
+    assert_eq!(selector.select(&groups, &counts), [2, 2]);
+}
@@ -1,18 +1,31 @@
 use simple_logger::SimpleLogger;
+use std::ffi::CString;
 use vulkan_engine::app::App;
+use vulkan_engine::crash_report;
+use vulkan_engine::demo_scenes::DemoScene;
+use vulkan_engine::renderer::benchmark::benchmark_all_devices;
+use vulkan_engine::renderer::quirks::QuirkOverrides;
+use vulkan_engine::renderer::replay::FrameRecording;
+use vulkan_engine::renderer::{InstanceOptions, VKInstance};
+use vulkan_engine::resize_stress::ResizeStress;
+use vulkan_engine::smoke_test::SmokeTest;
 use vulkan_engine::utils::GameInfo;
 use winit::event_loop::EventLoop;
 
 fn main() {
     let logger = SimpleLogger::new().with_level(log::LevelFilter::Info);
+    let max_level = logger.max_level();
 
-    match logger.init() {
+    // keeps recent log lines around for crash reports
+    match crash_report::init_logger(logger, max_level) {
         Ok(l) => l,
         Err(e) => {
             println!("Logger Init Error: {}", e);
         }
     }
 
+    crash_report::install_panic_hook(".");
+
     let game_info = GameInfo {
         app_name: c"Test",
         major: 0,
@@ -20,6 +33,66 @@ fn main() {
         patch: 1,
     };
 
+    // --demo-grid <size> renders a size x size grid of animated cubes for stress testing
+    let args: Vec<String> = std::env::args().collect();
+    let demo_grid = args
+        .iter()
+        .position(|arg| arg == "--demo-grid")
+        .and_then(|index| args.get(index + 1))
+        .and_then(|size| size.parse::<u32>().ok());
+
+    // --smoke-test renders a small scene, saves smoke-test.ppm and exits non zero if it's blank
+    let smoke_test = args.iter().any(|arg| arg == "--smoke-test");
+
+    // --resize-stress [frames] resizes the window every frame and exits non zero on leaks or hangs
+    let resize_stress =
+        args.iter()
+            .position(|arg| arg == "--resize-stress")
+            .map(
+                |index| match args.get(index + 1).and_then(|frames| frames.parse().ok()) {
+                    Some(frames) => ResizeStress::default().frames(frames),
+                    None => ResizeStress::default(),
+                },
+            );
+
+    // --replay <file> draws a frame recorded with F10, eg one attached to a bug report
+    let replay = args
+        .iter()
+        .position(|arg| arg == "--replay")
+        .and_then(|index| args.get(index + 1))
+        .map(|path| match FrameRecording::load(path) {
+            Ok(recording) => recording,
+            Err(error) => panic!("Invalid Frame Recording: {error}"),
+        });
+
+    // --layer <name> enables an instance layer if it's installed, can be repeated
+    // eg --layer VK_LAYER_LUNARG_api_dump or --layer VK_LAYER_RENDERDOC_Capture
+    let instance_options = args
+        .windows(2)
+        .filter(|pair| pair[0] == "--layer")
+        .filter_map(|pair| CString::new(pair[1].as_str()).ok())
+        .fold(InstanceOptions::default(), |options, layer| {
+            options.optional_layer(&layer)
+        });
+
+    // --quirks <list> forces driver workarounds on or off, eg --quirks -no_ray_tracing,+no_mailbox
+    let instance_options = match args
+        .iter()
+        .position(|arg| arg == "--quirks")
+        .and_then(|index| args.get(index + 1))
+    {
+        Some(quirks) => match QuirkOverrides::parse(quirks) {
+            Ok(overrides) => instance_options.quirk_overrides(overrides),
+            Err(error) => panic!("Invalid Quirks: {error}"),
+        },
+        None => instance_options,
+    };
+
+    // --benchmark-devices measures every device again without opening a window, then exits
+    if args.iter().any(|arg| arg == "--benchmark-devices") {
+        std::process::exit(benchmark_devices(&game_info, &instance_options));
+    }
+
     let event_loop_result = EventLoop::new();
 
     let mut event_loop = match event_loop_result {
@@ -27,9 +100,61 @@ fn main() {
         Err(error) => panic!("Failed to Create Event Loop: {error:?}"),
     };
 
-    let mut app = App::new(game_info);
+    let mut app = match demo_grid {
+        Some(size) => {
+            let scene = DemoScene::cube_grid(size, 2.0).scatter_lights(size * size, 7);
+            // fail fast on bad scene data instead of rendering garbage
+            if let Err(error) = scene.validate() {
+                panic!("Invalid Demo Scene: {error}");
+            }
+            App::with_demo_scene(game_info, scene)
+        }
+        None if smoke_test => {
+            App::with_demo_scene(game_info, DemoScene::cube_grid(3, 2.0).scatter_lights(3, 7))
+        }
+        None => App::new(game_info),
+    };
+    app = app.with_instance_options(instance_options);
+    if let Some(replay) = replay {
+        app = app.with_replay(replay);
+    }
+    if smoke_test {
+        app = app.with_smoke_test(SmokeTest::default());
+    }
+    let exit_with_code = smoke_test || resize_stress.is_some();
+    if let Some(resize_stress) = resize_stress {
+        app = app.with_resize_stress(resize_stress);
+    }
 
     if let Err(error) = app.start(&mut event_loop) {
         panic!("Failed on EventLoop: {error:?}");
     }
+    if exit_with_code {
+        std::process::exit(app.exit_code());
+    }
+}
+
+// headless run of the device benchmarks, the scores are logged and saved for the next launch
+fn benchmark_devices(game_info: &GameInfo, instance_options: &InstanceOptions) -> i32 {
+    let mut vk_instance = match VKInstance::new(game_info, None, instance_options) {
+        Ok(vk_instance) => vk_instance,
+        Err(error) => {
+            log::error!("Failed to Create Instance: {error}");
+            return 1;
+        }
+    };
+    let result = benchmark_all_devices(
+        &vk_instance.instance,
+        instance_options.pipeline_cache_directory.as_deref(),
+    );
+    unsafe { vk_instance.destroy() };
+
+    match result {
+        // every device was tried, ones that failed are logged with why
+        Ok(scores) => scores.devices.values().all(Option::is_none) as i32,
+        Err(error) => {
+            log::error!("Failed to Benchmark Devices: {error}");
+            1
+        }
+    }
 }
@@ -0,0 +1,354 @@
+use glam::{Mat4, Vec3, Vec4};
+
+/// Plane where dot(normal, point) + distance = 0
+/// the normal points to the positive (inside) half space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+impl Plane {
+    pub fn new(normal: Vec3, distance: f32) -> Self {
+        Self { normal, distance }
+    }
+
+    pub fn from_point_normal(point: Vec3, normal: Vec3) -> Self {
+        let normal = normal.normalize();
+        Self {
+            normal,
+            distance: -normal.dot(point),
+        }
+    }
+
+    /// Plane from xyzw coefficients, normalised so signed distances are in world units
+    /// planes at infinity have no normal, every point is infinitely in front of or behind them
+    pub fn from_coefficients(coefficients: Vec4) -> Self {
+        let normal = coefficients.truncate();
+        let length = normal.length();
+        if length > f32::EPSILON {
+            Self::new(normal / length, coefficients.w / length)
+        } else {
+            Self::new(Vec3::ZERO, f32::INFINITY.copysign(coefficients.w))
+        }
+    }
+
+    /// Positive in front of the plane, negative behind it
+    pub fn signed_distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl Sphere {
+    pub fn new(center: Vec3, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.center.distance_squared(point) <= self.radius * self.radius
+    }
+
+    pub fn intersects_sphere(&self, other: &Sphere) -> bool {
+        let radius = self.radius + other.radius;
+        self.center.distance_squared(other.center) <= radius * radius
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        aabb.closest_point(self.center)
+            .distance_squared(self.center)
+            <= self.radius * self.radius
+    }
+}
+
+/// Axis aligned bounding box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// Smallest box containing every point, None if there are no points
+    pub fn from_points(points: &[Vec3]) -> Option<Self> {
+        let first = *points.first()?;
+        Some(
+            points
+                .iter()
+                .fold(Self::new(first, first), |aabb, point| Self {
+                    min: aabb.min.min(*point),
+                    max: aabb.max.max(*point),
+                }),
+        )
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Half the size of the box on each axis
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    pub fn intersects_aabb(&self, other: &Aabb) -> bool {
+        self.min.cmple(other.max).all() && self.max.cmpge(other.min).all()
+    }
+
+    pub fn closest_point(&self, point: Vec3) -> Vec3 {
+        point.clamp(self.min, self.max)
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    /// Box containing this box after it has been transformed
+    /// can be larger than the tightest box around the transformed corners when rotated
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        let center = transform.transform_point3(self.center());
+        let half_extents = self.half_extents();
+        // project the half extents onto each axis using the absolute rotation/scale
+        let half_extents = Vec3::new(
+            transform.row(0).truncate().abs().dot(half_extents),
+            transform.row(1).truncate().abs().dot(half_extents),
+            transform.row(2).truncate().abs().dot(half_extents),
+        );
+        Self::new(center - half_extents, center + half_extents)
+    }
+
+    pub fn bounding_sphere(&self) -> Sphere {
+        Sphere::new(self.center(), self.half_extents().length())
+    }
+}
+
+/// Result of testing a volume against a frustum
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Intersection {
+    Outside,
+    Intersecting,
+    Inside,
+}
+
+/// Six planes with normals pointing into the frustum
+/// order is left, right, bottom, top then the z = 0 and z = w clip planes
+/// which are far and near with reverse z
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the frustum planes from a view projection matrix with vulkan 0..1 clip depth
+    /// works with reverse z and infinite projections, an infinite plane never culls anything
+    pub fn from_view_projection(view_projection: Mat4) -> Self {
+        let (x, y, z, w) = (
+            view_projection.row(0),
+            view_projection.row(1),
+            view_projection.row(2),
+            view_projection.row(3),
+        );
+
+        Self {
+            planes: [
+                Plane::from_coefficients(w + x),
+                Plane::from_coefficients(w - x),
+                Plane::from_coefficients(w + y),
+                Plane::from_coefficients(w - y),
+                Plane::from_coefficients(z),
+                Plane::from_coefficients(w - z),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+
+    pub fn classify_sphere(&self, sphere: &Sphere) -> Intersection {
+        let mut intersection = Intersection::Inside;
+        for plane in &self.planes {
+            let distance = plane.signed_distance(sphere.center);
+            if distance < -sphere.radius {
+                return Intersection::Outside;
+            }
+            if distance < sphere.radius {
+                intersection = Intersection::Intersecting;
+            }
+        }
+        intersection
+    }
+
+    pub fn classify_aabb(&self, aabb: &Aabb) -> Intersection {
+        let center = aabb.center();
+        let half_extents = aabb.half_extents();
+
+        let mut intersection = Intersection::Inside;
+        for plane in &self.planes {
+            // distance from the center to the box corner furthest along the plane normal
+            let radius = plane.normal.abs().dot(half_extents);
+            let distance = plane.signed_distance(center);
+            if distance < -radius {
+                return Intersection::Outside;
+            }
+            if distance < radius {
+                intersection = Intersection::Intersecting;
+            }
+        }
+        intersection
+    }
+
+    /// Conservative, can return true for spheres just outside the frustum corners
+    /// where they cross two planes' extensions without touching the frustum
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.classify_sphere(sphere) != Intersection::Outside
+    }
+
+    /// Conservative, can return true for boxes just outside the frustum corners
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.classify_aabb(aabb) != Intersection::Outside
+    }
+}
+
+#[cfg(test)]
+fn test_frustum() -> Frustum {
+    // same projection as the renderer, camera at the origin looking down -z
+    let mut projection = Mat4::perspective_infinite_reverse_rh(90f32.to_radians(), 1.0, 0.1);
+    projection.y_axis.y *= -1.0;
+    Frustum::from_view_projection(projection)
+}
+
+#[test]
+fn plane_test() {
+    let plane = Plane::from_point_normal(Vec3::new(0.0, 2.0, 0.0), Vec3::Y * 3.0);
+    assert_eq!(plane.signed_distance(Vec3::new(5.0, 3.0, -1.0)), 1.0);
+    assert_eq!(plane.signed_distance(Vec3::ZERO), -2.0);
+
+    let plane = Plane::from_coefficients(Vec4::new(0.0, 0.0, 2.0, 4.0));
+    assert_eq!(plane, Plane::new(Vec3::Z, 2.0));
+
+    // plane at infinity
+    let plane = Plane::from_coefficients(Vec4::new(0.0, 0.0, 0.0, 0.1));
+    assert_eq!(plane.signed_distance(Vec3::splat(1.0e6)), f32::INFINITY);
+}
+
+#[test]
+fn sphere_test() {
+    let sphere = Sphere::new(Vec3::ZERO, 1.0);
+    assert!(sphere.contains_point(Vec3::new(0.0, 1.0, 0.0)));
+    assert!(!sphere.contains_point(Vec3::new(0.8, 0.8, 0.0)));
+    assert!(sphere.intersects_sphere(&Sphere::new(Vec3::X * 1.5, 0.5)));
+    assert!(!sphere.intersects_sphere(&Sphere::new(Vec3::X * 1.6, 0.5)));
+
+    // closest point on the box is its corner
+    let aabb = Aabb::new(Vec3::splat(0.6), Vec3::splat(2.0));
+    assert!(!sphere.intersects_aabb(&aabb));
+    assert!(Sphere::new(Vec3::ZERO, 1.1).intersects_aabb(&aabb));
+}
+
+#[test]
+fn aabb_test() {
+    let aabb = Aabb::from_points(&[
+        Vec3::new(1.0, -2.0, 0.0),
+        Vec3::new(-1.0, 2.0, 0.5),
+        Vec3::new(0.0, 0.0, -0.5),
+    ])
+    .unwrap();
+    assert_eq!(
+        aabb,
+        Aabb::new(Vec3::new(-1.0, -2.0, -0.5), Vec3::new(1.0, 2.0, 0.5))
+    );
+    assert_eq!(Aabb::from_points(&[]), None);
+
+    assert!(aabb.contains_point(Vec3::new(1.0, 2.0, 0.5)));
+    assert!(!aabb.contains_point(Vec3::new(1.1, 0.0, 0.0)));
+
+    let other = Aabb::new(Vec3::splat(0.5), Vec3::splat(3.0));
+    assert!(aabb.intersects_aabb(&other));
+    assert!(!aabb.intersects_aabb(&Aabb::new(Vec3::new(1.1, 0.0, 0.0), Vec3::splat(3.0))));
+    assert_eq!(
+        aabb.union(&other),
+        Aabb::new(Vec3::new(-1.0, -2.0, -0.5), Vec3::splat(3.0))
+    );
+}
+
+#[test]
+fn aabb_transformed_test() {
+    let unit = Aabb::new(Vec3::splat(-0.5), Vec3::splat(0.5));
+
+    let moved = unit.transformed(&Mat4::from_scale_rotation_translation(
+        Vec3::new(2.0, 1.0, 1.0),
+        glam::Quat::IDENTITY,
+        Vec3::new(10.0, 0.0, 0.0),
+    ));
+    assert_eq!(
+        moved,
+        Aabb::new(Vec3::new(9.0, -0.5, -0.5), Vec3::new(11.0, 0.5, 0.5))
+    );
+
+    // 45 degree rotation around y grows x and z by sqrt 2
+    let rotated = unit.transformed(&Mat4::from_rotation_y(45f32.to_radians()));
+    let half_diagonal = 0.5 * 2f32.sqrt();
+    assert!((rotated.max.x - half_diagonal).abs() < 1e-5);
+    assert!((rotated.max.z - half_diagonal).abs() < 1e-5);
+    assert!((rotated.max.y - 0.5).abs() < 1e-5);
+}
+
+#[test]
+fn frustum_point_test() {
+    let frustum = test_frustum();
+
+    assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -1.0)));
+    // infinite far plane
+    assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -1.0e6)));
+    // behind the camera and in front of the near plane
+    assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, 1.0)));
+    assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -0.05)));
+    // 90 degree fov, sides are at x = +-z
+    assert!(frustum.contains_point(Vec3::new(0.9, 0.9, -1.0)));
+    assert!(!frustum.contains_point(Vec3::new(1.1, 0.0, -1.0)));
+    assert!(!frustum.contains_point(Vec3::new(0.0, -1.1, -1.0)));
+}
+
+#[test]
+fn frustum_volume_test() {
+    let frustum = test_frustum();
+
+    let inside = Aabb::new(Vec3::new(-0.5, -0.5, -5.0), Vec3::new(0.5, 0.5, -4.0));
+    let straddling = Aabb::new(Vec3::new(3.0, -0.5, -5.0), Vec3::new(6.0, 0.5, -4.0));
+    let outside = Aabb::new(Vec3::new(6.0, -0.5, -5.0), Vec3::new(7.0, 0.5, -4.0));
+    let behind = Aabb::new(Vec3::new(-0.5, -0.5, 4.0), Vec3::new(0.5, 0.5, 5.0));
+
+    assert_eq!(frustum.classify_aabb(&inside), Intersection::Inside);
+    assert_eq!(
+        frustum.classify_aabb(&straddling),
+        Intersection::Intersecting
+    );
+    assert_eq!(frustum.classify_aabb(&outside), Intersection::Outside);
+    assert_eq!(frustum.classify_aabb(&behind), Intersection::Outside);
+
+    assert_eq!(
+        frustum.classify_sphere(&inside.bounding_sphere()),
+        Intersection::Inside
+    );
+    assert_eq!(
+        frustum.classify_sphere(&Sphere::new(Vec3::ZERO, 0.5)),
+        Intersection::Intersecting
+    );
+    assert!(!frustum.intersects_sphere(&Sphere::new(Vec3::new(0.0, 0.0, 5.0), 1.0)));
+}
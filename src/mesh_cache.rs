@@ -0,0 +1,743 @@
+use glam::{Vec2, Vec3, Vec4};
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::{fs, mem};
+use thiserror::Error;
+
+use crate::assets::content_hash;
+use crate::math::Aabb;
+use crate::renderer::mesh::{Vertex, generate_indexed_normals, generate_indexed_tangents};
+
+/// Bumped whenever the layout of cached meshes changes, old files are rebuilt
+pub const MESH_CACHE_VERSION: u32 = 1;
+const MAGIC: [u8; 4] = *b"ALCM";
+
+/// Meshlet limits, match what mesh shaders are usually given
+pub const MESHLET_MAX_VERTICES: usize = 64;
+pub const MESHLET_MAX_TRIANGLES: usize = 124;
+
+// grid resolutions LODs are clustered on, from most to least detailed
+const LOD_GRID_SIZES: [f32; 3] = [64.0, 24.0, 8.0];
+// a LOD that doesn't drop at least this share of the triangles isn't worth keeping
+const LOD_MIN_REDUCTION: f32 = 0.25;
+
+#[derive(Debug, Error)]
+pub enum MeshCacheError {
+    #[error("failed to access mesh cache: {0}")]
+    Io(#[from] io::Error),
+    #[error("cached mesh is corrupt: {0}")]
+    Corrupt(&'static str),
+    #[error("cached mesh version {found} can't be read, expected {MESH_CACHE_VERSION}")]
+    Version { found: u32 },
+    #[error("failed to build mesh: {0}")]
+    Build(String),
+}
+
+/// Vertex with quantized position, normal and tangent, 28 bytes instead of 64
+/// positions are 16bit steps across the mesh bounds, directions are octahedral encoded
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PackedVertex {
+    pub position: [u16; 3],
+    /// handedness of the bitangent, 1 or -1
+    pub tangent_sign: i16,
+    pub normal: [i16; 2],
+    pub tangent: [i16; 2],
+    pub color: [u8; 4],
+    pub uv: [f32; 2],
+}
+
+impl PackedVertex {
+    const SIZE: usize = 28;
+
+    pub fn pack(vertex: &Vertex, bounds: &Aabb) -> Self {
+        let extent = (bounds.max - bounds.min).max(Vec3::splat(f32::EPSILON));
+        let position = ((vertex.pos - bounds.min) / extent * 65535.0)
+            .round()
+            .clamp(Vec3::ZERO, Vec3::splat(65535.0));
+        let color = (vertex.color.extend(1.0) * 255.0)
+            .round()
+            .clamp(Vec4::ZERO, Vec4::splat(255.0));
+
+        Self {
+            position: [position.x as u16, position.y as u16, position.z as u16],
+            tangent_sign: if vertex.tangent.w < 0.0 { -1 } else { 1 },
+            normal: octahedral_encode(vertex.normal),
+            tangent: octahedral_encode(vertex.tangent.truncate()),
+            color: [color.x as u8, color.y as u8, color.z as u8, color.w as u8],
+            uv: vertex.uv.to_array(),
+        }
+    }
+
+    pub fn unpack(&self, bounds: &Aabb) -> Vertex {
+        let position = Vec3::new(
+            self.position[0] as f32,
+            self.position[1] as f32,
+            self.position[2] as f32,
+        ) / 65535.0;
+        let color = Vec3::new(
+            self.color[0] as f32,
+            self.color[1] as f32,
+            self.color[2] as f32,
+        ) / 255.0;
+
+        Vertex {
+            pos: bounds.min + position * (bounds.max - bounds.min),
+            color,
+            uv: Vec2::from_array(self.uv),
+            normal: octahedral_decode(self.normal),
+            tangent: octahedral_decode(self.tangent).extend(self.tangent_sign as f32),
+        }
+    }
+}
+
+// unit vector folded onto an octahedron and flattened to 2 snorm16 values
+fn octahedral_encode(direction: Vec3) -> [i16; 2] {
+    let direction = direction.normalize_or_zero();
+    let direction =
+        direction / (direction.x.abs() + direction.y.abs() + direction.z.abs()).max(f32::EPSILON);
+    let mut encoded = Vec2::new(direction.x, direction.y);
+    if direction.z < 0.0 {
+        let folded = Vec2::ONE - Vec2::new(encoded.y.abs(), encoded.x.abs());
+        encoded = folded * Vec2::new(encoded.x.signum(), encoded.y.signum());
+    }
+    let encoded = (encoded.clamp(Vec2::NEG_ONE, Vec2::ONE) * 32767.0).round();
+    [encoded.x as i16, encoded.y as i16]
+}
+
+fn octahedral_decode(encoded: [i16; 2]) -> Vec3 {
+    let encoded = Vec2::new(encoded[0] as f32, encoded[1] as f32) / 32767.0;
+    let mut direction = Vec3::new(
+        encoded.x,
+        encoded.y,
+        1.0 - encoded.x.abs() - encoded.y.abs(),
+    );
+    if direction.z < 0.0 {
+        let folded = Vec2::ONE - Vec2::new(direction.y.abs(), direction.x.abs());
+        direction.x = folded.x * direction.x.signum();
+        direction.y = folded.y * direction.y.signum();
+    }
+    direction.normalize_or_zero()
+}
+
+/// Up to MESHLET_MAX_TRIANGLES triangles over up to MESHLET_MAX_VERTICES vertices
+/// offsets are into CompressedMesh::meshlet_vertices and meshlet_triangles
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Meshlet {
+    pub vertex_offset: u32,
+    pub vertex_count: u32,
+    /// in triangles, each is 3 bytes of meshlet local vertex indices
+    pub triangle_offset: u32,
+    pub triangle_count: u32,
+}
+
+/// Processed mesh as stored in the mesh cache, LODs share the vertices and differ in indices
+/// Example Use:
+/// ```ignore
+/// let mesh = CompressedMesh::build(&vertices, &indices)?;
+/// let lods = renderer.add_compressed_mesh(&mesh)?;
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompressedMesh {
+    pub bounds: Aabb,
+    pub vertices: Vec<PackedVertex>,
+    /// index lists from most to least detailed, lods[0] is the mesh as imported
+    pub lods: Vec<Vec<u32>>,
+    /// meshlets of lods[0]
+    pub meshlets: Vec<Meshlet>,
+    pub meshlet_vertices: Vec<u32>,
+    pub meshlet_triangles: Vec<u8>,
+}
+
+impl CompressedMesh {
+    /// Generates missing normals and tangents, LODs and meshlets, then quantizes the vertices
+    pub fn build(vertices: &[Vertex], indices: &[u32]) -> Result<Self, MeshCacheError> {
+        if indices.is_empty()
+            || !indices.len().is_multiple_of(3)
+            || indices
+                .iter()
+                .any(|&index| index as usize >= vertices.len())
+        {
+            return Err(MeshCacheError::Build(format!(
+                "{} indices don't make triangles of the {} vertices",
+                indices.len(),
+                vertices.len()
+            )));
+        }
+        let positions: Vec<Vec3> = vertices.iter().map(|vertex| vertex.pos).collect();
+        let bounds = Aabb::from_points(&positions)
+            .ok_or_else(|| MeshCacheError::Build("mesh has no vertices".into()))?;
+
+        let mut vertices = vertices.to_vec();
+        generate_indexed_normals(&mut vertices, indices);
+        generate_indexed_tangents(&mut vertices, indices);
+
+        let mut lods = vec![indices.to_vec()];
+        for grid_size in LOD_GRID_SIZES {
+            let previous = lods.last().map_or(0, Vec::len);
+            let simplified = cluster_indices(&positions, indices, &bounds, grid_size);
+            if !simplified.is_empty()
+                && (simplified.len() as f32) <= previous as f32 * (1.0 - LOD_MIN_REDUCTION)
+            {
+                lods.push(simplified);
+            }
+        }
+
+        let (meshlets, meshlet_vertices, meshlet_triangles) = build_meshlets(indices);
+
+        Ok(Self {
+            bounds,
+            vertices: vertices
+                .iter()
+                .map(|vertex| PackedVertex::pack(vertex, &bounds))
+                .collect(),
+            lods,
+            meshlets,
+            meshlet_vertices,
+            meshlet_triangles,
+        })
+    }
+
+    /// Vertices at full precision for uploading with VKMesh::new_indexed
+    pub fn unpack_vertices(&self) -> Vec<Vertex> {
+        self.vertices
+            .iter()
+            .map(|vertex| vertex.unpack(&self.bounds))
+            .collect()
+    }
+
+    pub fn write(&self, source_hash: u64, writer: &mut impl Write) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(
+            64 + self.vertices.len() * PackedVertex::SIZE
+                + self.lods.iter().map(Vec::len).sum::<usize>() * 4
+                + self.meshlets.len() * 16
+                + self.meshlet_vertices.len() * 4
+                + self.meshlet_triangles.len(),
+        );
+
+        bytes.extend(MAGIC);
+        bytes.extend(MESH_CACHE_VERSION.to_le_bytes());
+        bytes.extend(source_hash.to_le_bytes());
+        for value in self
+            .bounds
+            .min
+            .to_array()
+            .iter()
+            .chain(&self.bounds.max.to_array())
+        {
+            bytes.extend(value.to_le_bytes());
+        }
+        for count in [
+            self.vertices.len(),
+            self.lods.len(),
+            self.meshlets.len(),
+            self.meshlet_vertices.len(),
+            self.meshlet_triangles.len(),
+        ] {
+            bytes.extend((count as u32).to_le_bytes());
+        }
+
+        for vertex in &self.vertices {
+            for value in vertex.position {
+                bytes.extend(value.to_le_bytes());
+            }
+            bytes.extend(vertex.tangent_sign.to_le_bytes());
+            for value in vertex.normal.iter().chain(&vertex.tangent) {
+                bytes.extend(value.to_le_bytes());
+            }
+            bytes.extend(vertex.color);
+            for value in vertex.uv {
+                bytes.extend(value.to_le_bytes());
+            }
+        }
+        for lod in &self.lods {
+            bytes.extend((lod.len() as u32).to_le_bytes());
+            for index in lod {
+                bytes.extend(index.to_le_bytes());
+            }
+        }
+        for meshlet in &self.meshlets {
+            for value in [
+                meshlet.vertex_offset,
+                meshlet.vertex_count,
+                meshlet.triangle_offset,
+                meshlet.triangle_count,
+            ] {
+                bytes.extend(value.to_le_bytes());
+            }
+        }
+        for index in &self.meshlet_vertices {
+            bytes.extend(index.to_le_bytes());
+        }
+        bytes.extend(&self.meshlet_triangles);
+
+        writer.write_all(&bytes)
+    }
+
+    /// Reads a mesh written by write, along with the source hash it was written with
+    pub fn read(bytes: &[u8]) -> Result<(Self, u64), MeshCacheError> {
+        let mut reader = ByteReader { bytes };
+        if reader.take(4)? != MAGIC {
+            return Err(MeshCacheError::Corrupt("not a cached mesh"));
+        }
+        let version = reader.u32()?;
+        if version != MESH_CACHE_VERSION {
+            return Err(MeshCacheError::Version { found: version });
+        }
+        let source_hash = reader.u64()?;
+        let min = Vec3::new(reader.f32()?, reader.f32()?, reader.f32()?);
+        let max = Vec3::new(reader.f32()?, reader.f32()?, reader.f32()?);
+        let vertex_count = reader.count(PackedVertex::SIZE)?;
+        let lod_count = reader.count(4)?;
+        let meshlet_count = reader.count(16)?;
+        let meshlet_vertex_count = reader.count(4)?;
+        let meshlet_triangle_bytes = reader.count(1)?;
+
+        let vertices = (0..vertex_count)
+            .map(|_| {
+                Ok(PackedVertex {
+                    position: [reader.u16()?, reader.u16()?, reader.u16()?],
+                    tangent_sign: reader.u16()? as i16,
+                    normal: [reader.u16()? as i16, reader.u16()? as i16],
+                    tangent: [reader.u16()? as i16, reader.u16()? as i16],
+                    color: reader.take(4)?.try_into().unwrap_or_default(),
+                    uv: [reader.f32()?, reader.f32()?],
+                })
+            })
+            .collect::<Result<Vec<_>, MeshCacheError>>()?;
+
+        let lods = (0..lod_count)
+            .map(|_| {
+                let index_count = reader.count(4)?;
+                reader.u32s(index_count)
+            })
+            .collect::<Result<Vec<_>, MeshCacheError>>()?;
+        if lods.iter().any(|lod| !lod.len().is_multiple_of(3)) {
+            return Err(MeshCacheError::Corrupt(
+                "indices don't make whole triangles",
+            ));
+        }
+        if lods
+            .iter()
+            .flatten()
+            .any(|&index| index as usize >= vertex_count)
+        {
+            return Err(MeshCacheError::Corrupt("index outside the vertices"));
+        }
+
+        let meshlets = (0..meshlet_count)
+            .map(|_| {
+                Ok(Meshlet {
+                    vertex_offset: reader.u32()?,
+                    vertex_count: reader.u32()?,
+                    triangle_offset: reader.u32()?,
+                    triangle_count: reader.u32()?,
+                })
+            })
+            .collect::<Result<Vec<_>, MeshCacheError>>()?;
+        let meshlet_vertices = reader.u32s(meshlet_vertex_count)?;
+        let meshlet_triangles = reader.take(meshlet_triangle_bytes)?.to_vec();
+        validate_meshlets(
+            &meshlets,
+            &meshlet_vertices,
+            &meshlet_triangles,
+            vertex_count,
+        )?;
+
+        Ok((
+            Self {
+                bounds: Aabb::new(min, max),
+                vertices,
+                lods,
+                meshlets,
+                meshlet_vertices,
+                meshlet_triangles,
+            },
+            source_hash,
+        ))
+    }
+}
+
+// every meshlet range has to lie within the arrays it points into and every index within what it
+// indexes, otherwise a stale or damaged file turns into out of bounds reads on the gpu
+fn validate_meshlets(
+    meshlets: &[Meshlet],
+    meshlet_vertices: &[u32],
+    meshlet_triangles: &[u8],
+    vertex_count: usize,
+) -> Result<(), MeshCacheError> {
+    if !meshlet_triangles.len().is_multiple_of(3) {
+        return Err(MeshCacheError::Corrupt("meshlet triangles aren't whole"));
+    }
+    if meshlet_vertices
+        .iter()
+        .any(|&index| index as usize >= vertex_count)
+    {
+        return Err(MeshCacheError::Corrupt(
+            "meshlet vertex outside the vertices",
+        ));
+    }
+
+    for meshlet in meshlets {
+        if meshlet.vertex_count as usize > MESHLET_MAX_VERTICES
+            || meshlet.triangle_count as usize > MESHLET_MAX_TRIANGLES
+        {
+            return Err(MeshCacheError::Corrupt("meshlet is over the limits"));
+        }
+        let vertices_end = meshlet.vertex_offset as usize + meshlet.vertex_count as usize;
+        let triangles = meshlet.triangle_offset as usize * 3
+            ..(meshlet.triangle_offset as usize + meshlet.triangle_count as usize) * 3;
+        if vertices_end > meshlet_vertices.len() || triangles.end > meshlet_triangles.len() {
+            return Err(MeshCacheError::Corrupt(
+                "meshlet range outside the meshlet data",
+            ));
+        }
+        if meshlet_triangles[triangles]
+            .iter()
+            .any(|&local| local as u32 >= meshlet.vertex_count)
+        {
+            return Err(MeshCacheError::Corrupt(
+                "meshlet triangle outside its vertices",
+            ));
+        }
+    }
+    Ok(())
+}
+
+// bounds checked little endian reads, a truncated file is an error rather than a panic
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], MeshCacheError> {
+        if count > self.bytes.len() {
+            return Err(MeshCacheError::Corrupt("file is truncated"));
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], MeshCacheError> {
+        Ok(self.take(N)?.try_into().unwrap_or([0; N]))
+    }
+
+    fn u16(&mut self) -> Result<u16, MeshCacheError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, MeshCacheError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, MeshCacheError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, MeshCacheError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    // element count that has to fit in what's left of the file, stops huge allocations from bad counts
+    fn count(&mut self, element_size: usize) -> Result<usize, MeshCacheError> {
+        let count = self.u32()? as usize;
+        if count.saturating_mul(element_size) > self.bytes.len() {
+            return Err(MeshCacheError::Corrupt("count is larger than the file"));
+        }
+        Ok(count)
+    }
+
+    fn u32s(&mut self, count: usize) -> Result<Vec<u32>, MeshCacheError> {
+        Ok(self
+            .take(count * mem::size_of::<u32>())?
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect())
+    }
+}
+
+// vertex clustering, every vertex snaps to the first vertex of its grid cell
+// triangles that collapse are dropped, holes are fine for distant LODs
+fn cluster_indices(positions: &[Vec3], indices: &[u32], bounds: &Aabb, grid_size: f32) -> Vec<u32> {
+    let extent = (bounds.max - bounds.min).max(Vec3::splat(f32::EPSILON));
+    let mut cells: HashMap<[u32; 3], u32> = HashMap::new();
+    let representative: Vec<u32> = positions
+        .iter()
+        .enumerate()
+        .map(|(index, position)| {
+            let cell = ((*position - bounds.min) / extent * grid_size)
+                .floor()
+                .min(Vec3::splat(grid_size - 1.0));
+            *cells
+                .entry([cell.x as u32, cell.y as u32, cell.z as u32])
+                .or_insert(index as u32)
+        })
+        .collect();
+
+    indices
+        .chunks_exact(3)
+        .map(|triangle| [0, 1, 2].map(|corner| representative[triangle[corner] as usize]))
+        .filter(|[a, b, c]| a != b && b != c && a != c)
+        .flatten()
+        .collect()
+}
+
+// greedy meshlets in index order, starts a new one when either limit would be passed
+fn build_meshlets(indices: &[u32]) -> (Vec<Meshlet>, Vec<u32>, Vec<u8>) {
+    let mut meshlets = Vec::new();
+    let mut meshlet_vertices: Vec<u32> = Vec::new();
+    let mut meshlet_triangles: Vec<u8> = Vec::new();
+    let mut current = Meshlet::default();
+    let mut local: HashMap<u32, u8> = HashMap::new();
+
+    for triangle in indices.chunks_exact(3) {
+        let new_vertices = triangle
+            .iter()
+            .filter(|index| !local.contains_key(index))
+            .count();
+        if local.len() + new_vertices > MESHLET_MAX_VERTICES
+            || current.triangle_count as usize == MESHLET_MAX_TRIANGLES
+        {
+            meshlets.push(current);
+            current = Meshlet {
+                vertex_offset: meshlet_vertices.len() as u32,
+                vertex_count: 0,
+                triangle_offset: (meshlet_triangles.len() / 3) as u32,
+                triangle_count: 0,
+            };
+            local.clear();
+        }
+
+        for &index in triangle {
+            let local_index = *local.entry(index).or_insert_with(|| {
+                meshlet_vertices.push(index);
+                current.vertex_count += 1;
+                (current.vertex_count - 1) as u8
+            });
+            meshlet_triangles.push(local_index);
+        }
+        current.triangle_count += 1;
+    }
+    if current.triangle_count > 0 {
+        meshlets.push(current);
+    }
+
+    (meshlets, meshlet_vertices, meshlet_triangles)
+}
+
+/// Directory of processed meshes, written the first time a source is imported
+/// and memory mapped on later runs instead of importing again
+/// Example Use:
+/// ```ignore
+/// let cache = MeshCache::new("cache/meshes");
+/// let source = fs::read("assets/rock.gltf")?;
+/// let mesh = cache.load_or_build("rock", &source, || {
+///     let (mut vertices, mut indices) = import_gltf(&source)?;
+///     AxisConvention::BLENDER.import_mesh(&mut vertices, &mut indices);
+///     CompressedMesh::build(&vertices, &indices)
+/// })?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct MeshCache {
+    pub directory: PathBuf,
+}
+
+impl MeshCache {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.directory.join(format!("{name}.alcm"))
+    }
+
+    /// Cached mesh for name if it was built from source, None when missing, stale or unreadable
+    pub fn load(&self, name: &str, source: &[u8]) -> Option<CompressedMesh> {
+        let path = self.path(name);
+        let file = fs::File::open(&path).ok()?;
+        // store renames a finished file over the old one, so a mapped file is never written to
+        let read = match unsafe { Mmap::map(&file) } {
+            Ok(map) => CompressedMesh::read(&map),
+            // some file systems can't be mapped, reading the whole file works everywhere
+            Err(_) => CompressedMesh::read(&fs::read(&path).ok()?),
+        };
+        match read {
+            Ok((mesh, source_hash)) if source_hash == content_hash(source) => Some(mesh),
+            Ok(_) => None,
+            Err(error) => {
+                log::warn!("Mesh Cache: Rebuilding {name}, {error}");
+                None
+            }
+        }
+    }
+
+    /// Writes through a temporary file so a crash never leaves half a mesh behind
+    pub fn store(
+        &self,
+        name: &str,
+        source: &[u8],
+        mesh: &CompressedMesh,
+    ) -> Result<(), MeshCacheError> {
+        fs::create_dir_all(&self.directory)?;
+        let path = self.path(name);
+        let temporary = path.with_extension("alcm.tmp");
+        let mut file = io::BufWriter::new(fs::File::create(&temporary)?);
+        mesh.write(content_hash(source), &mut file)?;
+        file.into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()?;
+        fs::rename(&temporary, &path)?;
+        Ok(())
+    }
+
+    /// Cached mesh for source, or build's mesh which is then cached
+    /// failing to write the cache is logged, the built mesh is still returned
+    pub fn load_or_build<F>(
+        &self,
+        name: &str,
+        source: &[u8],
+        build: F,
+    ) -> Result<CompressedMesh, MeshCacheError>
+    where
+        F: FnOnce() -> Result<CompressedMesh, MeshCacheError>,
+    {
+        if let Some(mesh) = self.load(name, source) {
+            return Ok(mesh);
+        }
+        let mesh = build()?;
+        if let Err(error) = self.store(name, source, &mesh) {
+            log::warn!("Mesh Cache: Failed to Store {name}, {error}");
+        }
+        Ok(mesh)
+    }
+
+    /// Removes every cached mesh
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.directory) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl AsRef<Path> for MeshCache {
+    fn as_ref(&self) -> &Path {
+        &self.directory
+    }
+}
+
+#[test]
+fn mesh_cache_test() {
+    use crate::renderer::mesh::CUBE_VERTICES;
+
+    // a finely tessellated grid so clustering has something to remove
+    let size = 32;
+    let vertices: Vec<Vertex> = (0..=size)
+        .flat_map(|z| (0..=size).map(move |x| (x, z)))
+        .map(|(x, z)| {
+            let uv = Vec2::new(x as f32, z as f32) / size as f32;
+            Vertex::new(
+                Vec3::new(uv.x, (uv.x * 6.0).sin() * 0.1, uv.y),
+                Vec3::ONE,
+                uv,
+            )
+        })
+        .collect();
+    let indices: Vec<u32> = (0..size)
+        .flat_map(|z| (0..size).map(move |x| (x, z)))
+        .flat_map(|(x, z)| {
+            let corner = z * (size + 1) + x;
+            [
+                corner,
+                corner + size + 1,
+                corner + 1,
+                corner + 1,
+                corner + size + 1,
+                corner + size + 2,
+            ]
+        })
+        .collect();
+
+    let mesh = CompressedMesh::build(&vertices, &indices).unwrap();
+    assert_eq!(mesh.lods[0], indices);
+    assert!(mesh.lods.len() > 1);
+    assert!(
+        mesh.lods
+            .windows(2)
+            .all(|lods| lods[1].len() < lods[0].len())
+    );
+
+    // every triangle lands in exactly one meshlet within the limits
+    let triangles: u32 = mesh
+        .meshlets
+        .iter()
+        .map(|meshlet| meshlet.triangle_count)
+        .sum();
+    assert_eq!(triangles as usize, indices.len() / 3);
+    for meshlet in &mesh.meshlets {
+        assert!(meshlet.vertex_count as usize <= MESHLET_MAX_VERTICES);
+        assert!(meshlet.triangle_count as usize <= MESHLET_MAX_TRIANGLES);
+    }
+    let first = &mesh.meshlets[0];
+    let local = &mesh.meshlet_triangles[..3];
+    let global: Vec<u32> = local
+        .iter()
+        .map(|&index| mesh.meshlet_vertices[(first.vertex_offset + index as u32) as usize])
+        .collect();
+    assert_eq!(global, indices[..3]);
+
+    // quantization stays well under a millimetre on a metre sized mesh
+    for (original, unpacked) in vertices.iter().zip(mesh.unpack_vertices()) {
+        assert!(original.pos.abs_diff_eq(unpacked.pos, 1e-4));
+    }
+    let up = octahedral_decode(octahedral_encode(Vec3::Y));
+    assert!(up.abs_diff_eq(Vec3::Y, 1e-4));
+    let down_left = Vec3::new(-1.0, -2.0, -3.0).normalize();
+    assert!(octahedral_decode(octahedral_encode(down_left)).abs_diff_eq(down_left, 1e-3));
+
+    let mut bytes = Vec::new();
+    mesh.write(content_hash(b"source"), &mut bytes).unwrap();
+    let (read, source_hash) = CompressedMesh::read(&bytes).unwrap();
+    assert_eq!(read, mesh);
+    assert_eq!(source_hash, content_hash(b"source"));
+    assert!(matches!(
+        CompressedMesh::read(&bytes[..bytes.len() - 1]),
+        Err(MeshCacheError::Corrupt(_))
+    ));
+    // ranges and indices pointing past the data are caught on read
+    let corrupt = |mesh: &CompressedMesh| {
+        let mut bytes = Vec::new();
+        mesh.write(0, &mut bytes).unwrap();
+        matches!(
+            CompressedMesh::read(&bytes),
+            Err(MeshCacheError::Corrupt(_))
+        )
+    };
+    let mut stale = mesh.clone();
+    stale.meshlets[0].vertex_offset = stale.meshlet_vertices.len() as u32;
+    assert!(corrupt(&stale));
+    let mut stale = mesh.clone();
+    stale.meshlets.last_mut().unwrap().triangle_count += 1;
+    assert!(corrupt(&stale));
+    let mut stale = mesh.clone();
+    stale.meshlet_vertices[0] = vertices.len() as u32;
+    assert!(corrupt(&stale));
+    let mut stale = mesh.clone();
+    stale.meshlet_triangles[0] = stale.meshlets[0].vertex_count as u8;
+    assert!(corrupt(&stale));
+
+    let cache = MeshCache::new(
+        std::env::temp_dir().join(format!("alcor-mesh-cache-test-{}", std::process::id())),
+    );
+    let cube: Vec<u32> = (0..CUBE_VERTICES.len() as u32).collect();
+    let build = || CompressedMesh::build(&CUBE_VERTICES, &cube);
+    let built = cache.load_or_build("cube", b"v1", build).unwrap();
+    assert_eq!(cache.load("cube", b"v1"), Some(built));
+    // a changed source misses the cache
+    assert_eq!(cache.load("cube", b"v2"), None);
+    cache.clear().unwrap();
+}
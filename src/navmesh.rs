@@ -0,0 +1,675 @@
+use glam::{Vec2, Vec3};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+use thiserror::Error;
+
+use crate::color::LinearRgba;
+use crate::math::Aabb;
+use crate::renderer::mesh::Vertex;
+
+// grids above this many columns are almost certainly a cell_size mistake
+const MAX_COLUMNS: usize = 4096 * 4096;
+
+// 4 orthogonal then 4 diagonal neighbours
+const DIRECTIONS: [(isize, isize); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+/// Voxel and agent settings for building a navmesh, distances are in world units
+/// Example Use:
+/// ```ignore
+/// let options = NavMeshOptions::default().agent_radius(0.3).max_climb(0.25);
+/// let navmesh = NavMesh::build(&level_triangles, &options)?;
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NavMeshOptions {
+    /// width and depth of a voxel column
+    pub cell_size: f32,
+    /// surfaces closer than this vertically are merged
+    pub cell_height: f32,
+    /// space needed above a surface to stand on it
+    pub agent_height: f32,
+    /// walkable area is shrunk by this much away from walls and edges
+    pub agent_radius: f32,
+    /// highest step an agent can walk up or down
+    pub max_climb: f32,
+    /// steepest walkable slope in radians
+    pub max_slope: f32,
+}
+
+impl Default for NavMeshOptions {
+    fn default() -> Self {
+        Self {
+            cell_size: 0.25,
+            cell_height: 0.1,
+            agent_height: 2.0,
+            agent_radius: 0.5,
+            max_climb: 0.4,
+            max_slope: 45.0_f32.to_radians(),
+        }
+    }
+}
+
+impl NavMeshOptions {
+    pub fn cell_size(mut self, cell_size: f32) -> Self {
+        self.cell_size = cell_size;
+        self
+    }
+
+    pub fn cell_height(mut self, cell_height: f32) -> Self {
+        self.cell_height = cell_height;
+        self
+    }
+
+    pub fn agent_height(mut self, agent_height: f32) -> Self {
+        self.agent_height = agent_height;
+        self
+    }
+
+    pub fn agent_radius(mut self, agent_radius: f32) -> Self {
+        self.agent_radius = agent_radius;
+        self
+    }
+
+    pub fn max_climb(mut self, max_climb: f32) -> Self {
+        self.max_climb = max_climb;
+        self
+    }
+
+    pub fn max_slope(mut self, max_slope: f32) -> Self {
+        self.max_slope = max_slope;
+        self
+    }
+
+    fn is_valid(&self) -> bool {
+        let positive = [self.cell_size, self.cell_height, self.agent_height];
+        let non_negative = [self.agent_radius, self.max_climb];
+        positive
+            .iter()
+            .all(|value| value.is_finite() && *value > 0.0)
+            && non_negative
+                .iter()
+                .all(|value| value.is_finite() && *value >= 0.0)
+            && self.max_slope > 0.0
+            && self.max_slope <= std::f32::consts::FRAC_PI_2
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum NavMeshError {
+    #[error("navmesh geometry has no triangles")]
+    NoTriangles,
+    #[error(
+        "navmesh options must be finite, sizes greater than 0 and max_slope at most 90 degrees"
+    )]
+    InvalidOptions,
+    #[error("navmesh grid of {width}x{depth} cells is too large, increase cell_size")]
+    TooLarge { width: usize, depth: usize },
+}
+
+// solid part of a voxel column, the top of a walkable span is a surface agents can stand on
+#[derive(Clone, Copy, Debug)]
+struct Span {
+    min: f32,
+    max: f32,
+    walkable: bool,
+}
+
+// walkable surface in one column
+#[derive(Clone, Copy, Debug)]
+struct NavNode {
+    x: usize,
+    z: usize,
+    height: f32,
+}
+
+/// Walkable surfaces of level geometry voxelized into columns, recast style
+/// columns can hold several surfaces so bridges and multi storey levels work
+/// Example Use:
+/// ```
+/// use glam::{Mat4, Vec3};
+/// use vulkan_engine::navmesh::{NavMesh, NavMeshOptions};
+/// use vulkan_engine::renderer::mesh::CUBE_VERTICES;
+///
+/// // the top of a flattened cube as the floor
+/// let floor = Mat4::from_scale(Vec3::new(10.0, 1.0, 10.0));
+/// let triangles: Vec<Vec3> = CUBE_VERTICES
+///     .iter()
+///     .map(|vertex| floor.transform_point3(vertex.pos))
+///     .collect();
+///
+/// let navmesh = NavMesh::build(&triangles, &NavMeshOptions::default()).unwrap();
+/// let path = navmesh
+///     .find_path(Vec3::new(-3.0, 0.5, -3.0), Vec3::new(3.0, 0.5, 3.0))
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct NavMesh {
+    options: NavMeshOptions,
+    // min corner of the grid
+    origin: Vec3,
+    width: usize,
+    depth: usize,
+    nodes: Vec<NavNode>,
+    // nodes in each column, indexed by x + z * width
+    columns: Vec<Vec<usize>>,
+}
+
+impl NavMesh {
+    /// Voxelizes a triangle list in world space, triangles wind counter clockwise seen from the front
+    pub fn build(triangles: &[Vec3], options: &NavMeshOptions) -> Result<Self, NavMeshError> {
+        if !options.is_valid() {
+            return Err(NavMeshError::InvalidOptions);
+        }
+        if triangles.len() < 3 {
+            return Err(NavMeshError::NoTriangles);
+        }
+        let bounds = Aabb::from_points(triangles).ok_or(NavMeshError::NoTriangles)?;
+
+        let size = (bounds.max - bounds.min) / options.cell_size;
+        let width = (size.x.ceil() as usize).max(1);
+        let depth = (size.z.ceil() as usize).max(1);
+        if width.saturating_mul(depth) > MAX_COLUMNS {
+            return Err(NavMeshError::TooLarge { width, depth });
+        }
+
+        let mut navmesh = Self {
+            options: *options,
+            origin: bounds.min,
+            width,
+            depth,
+            nodes: Vec::new(),
+            columns: vec![Vec::new(); width * depth],
+        };
+
+        let mut spans = vec![Vec::new(); width * depth];
+        let min_normal_y = options.max_slope.cos();
+        for triangle in triangles.chunks_exact(3) {
+            let normal = (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]);
+            let length = normal.length();
+            if !length.is_finite() || length <= f32::EPSILON {
+                continue;
+            }
+            // small tolerance so a 45 degree limit accepts 45 degree ramps
+            let walkable = normal.y / length >= min_normal_y - 1e-6;
+            navmesh.rasterize_triangle(triangle, walkable, &mut spans);
+        }
+
+        // surfaces with enough head room become nodes
+        for (column, column_spans) in spans.iter_mut().enumerate() {
+            merge_spans(column_spans, options.cell_height);
+            for (index, span) in column_spans.iter().enumerate() {
+                let ceiling = column_spans
+                    .get(index + 1)
+                    .map_or(f32::INFINITY, |above| above.min);
+                if span.walkable && ceiling - span.max >= options.agent_height {
+                    navmesh.nodes.push(NavNode {
+                        x: column % width,
+                        z: column / width,
+                        height: span.max,
+                    });
+                    navmesh.columns[column].push(navmesh.nodes.len() - 1);
+                }
+            }
+        }
+
+        navmesh.erode();
+        Ok(navmesh)
+    }
+
+    /// Number of walkable cells
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Closest point on the navmesh, None when nothing is walkable
+    pub fn nearest_point(&self, point: Vec3) -> Option<Vec3> {
+        self.nearest_node(point)
+            .map(|node| self.node_position(node))
+    }
+
+    /// Whether point is within max_climb of a walkable surface
+    pub fn is_walkable(&self, point: Vec3) -> bool {
+        self.node_at(point, point.y).is_some()
+    }
+
+    /// Shortest walkable path using A* over the cells
+    /// the path is snapped onto the navmesh and smoothed so it only turns at corners
+    pub fn find_path(&self, start: Vec3, goal: Vec3) -> Option<Vec<Vec3>> {
+        let start = self.nearest_node(start)?;
+        let goal = self.nearest_node(goal)?;
+
+        let heuristic = |node: usize| self.node_position(node).distance(self.node_position(goal));
+
+        let mut cost = vec![f32::INFINITY; self.nodes.len()];
+        let mut came_from = vec![usize::MAX; self.nodes.len()];
+        let mut open = BinaryHeap::new();
+
+        cost[start] = 0.0;
+        open.push(OpenNode {
+            estimate: heuristic(start),
+            node: start,
+        });
+
+        while let Some(OpenNode { estimate, node }) = open.pop() {
+            if node == goal {
+                break;
+            }
+            // stale entry, a cheaper way here was already expanded
+            if estimate > cost[node] + heuristic(node) {
+                continue;
+            }
+
+            for neighbour in self.neighbours(node) {
+                let step = self
+                    .node_position(node)
+                    .distance(self.node_position(neighbour));
+                let new_cost = cost[node] + step;
+                if new_cost < cost[neighbour] {
+                    cost[neighbour] = new_cost;
+                    came_from[neighbour] = node;
+                    open.push(OpenNode {
+                        estimate: new_cost + heuristic(neighbour),
+                        node: neighbour,
+                    });
+                }
+            }
+        }
+
+        if !cost[goal].is_finite() {
+            return None;
+        }
+
+        let mut cells = vec![goal];
+        while let Some(&node) = cells.last()
+            && node != start
+        {
+            cells.push(came_from[node]);
+        }
+        cells.reverse();
+
+        Some(
+            self.smooth(&cells)
+                .into_iter()
+                .map(|node| self.node_position(node))
+                .collect(),
+        )
+    }
+
+    /// Two triangles per walkable cell lifted slightly off the floor
+    /// add it with VKRenderer::add_mesh to see where agents can go
+    pub fn debug_vertices(&self, color: LinearRgba) -> Vec<Vertex> {
+        let color = color.to_vec3();
+        let lift = self.options.cell_height * 0.5;
+        let mut vertices = Vec::with_capacity(self.nodes.len() * 6);
+
+        for node in &self.nodes {
+            let x0 = self.origin.x + node.x as f32 * self.options.cell_size;
+            let z0 = self.origin.z + node.z as f32 * self.options.cell_size;
+            let (x1, z1) = (x0 + self.options.cell_size, z0 + self.options.cell_size);
+            let y = node.height + lift;
+
+            let corner = |x: f32, z: f32, u: f32, v: f32| {
+                Vertex::new(Vec3::new(x, y, z), color, Vec2::new(u, v))
+            };
+            // counter clockwise seen from above
+            vertices.extend([
+                corner(x0, z1, 0.0, 1.0),
+                corner(x1, z1, 1.0, 1.0),
+                corner(x1, z0, 1.0, 0.0),
+                corner(x1, z0, 1.0, 0.0),
+                corner(x0, z0, 0.0, 0.0),
+                corner(x0, z1, 0.0, 1.0),
+            ]);
+        }
+        vertices
+    }
+
+    // centre of the cell on the walkable surface
+    fn node_position(&self, node: usize) -> Vec3 {
+        let node = self.nodes[node];
+        Vec3::new(
+            self.origin.x + (node.x as f32 + 0.5) * self.options.cell_size,
+            node.height,
+            self.origin.z + (node.z as f32 + 0.5) * self.options.cell_size,
+        )
+    }
+
+    fn column_of(&self, point: Vec3) -> Option<(usize, usize)> {
+        let cell = ((point - self.origin) / self.options.cell_size).floor();
+        if cell.x < 0.0 || cell.z < 0.0 {
+            return None;
+        }
+        let (x, z) = (cell.x as usize, cell.z as usize);
+        (x < self.width && z < self.depth).then_some((x, z))
+    }
+
+    // surface in column (x, z) closest to height and within max_climb of it
+    fn node_in_column(&self, x: usize, z: usize, height: f32) -> Option<usize> {
+        self.columns[x + z * self.width]
+            .iter()
+            .copied()
+            .filter(|&node| (self.nodes[node].height - height).abs() <= self.options.max_climb)
+            .min_by(|&a, &b| {
+                let a = (self.nodes[a].height - height).abs();
+                let b = (self.nodes[b].height - height).abs();
+                a.total_cmp(&b)
+            })
+    }
+
+    fn node_at(&self, point: Vec3, height: f32) -> Option<usize> {
+        let (x, z) = self.column_of(point)?;
+        self.node_in_column(x, z, height)
+    }
+
+    fn nearest_node(&self, point: Vec3) -> Option<usize> {
+        // surface under the point first, the agent is usually standing on it
+        if let Some((x, z)) = self.column_of(point)
+            && let Some(node) = self.columns[x + z * self.width]
+                .iter()
+                .copied()
+                .filter(|&node| self.nodes[node].height <= point.y + self.options.max_climb)
+                .max_by(|&a, &b| self.nodes[a].height.total_cmp(&self.nodes[b].height))
+        {
+            return Some(node);
+        }
+
+        (0..self.nodes.len()).min_by(|&a, &b| {
+            let a = self.node_position(a).distance_squared(point);
+            let b = self.node_position(b).distance_squared(point);
+            a.total_cmp(&b)
+        })
+    }
+
+    fn neighbour(&self, node: usize, (dx, dz): (isize, isize)) -> Option<usize> {
+        let NavNode { x, z, height } = self.nodes[node];
+        let x = x.checked_add_signed(dx).filter(|&x| x < self.width)?;
+        let z = z.checked_add_signed(dz).filter(|&z| z < self.depth)?;
+        self.node_in_column(x, z, height)
+    }
+
+    fn neighbours(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        DIRECTIONS.into_iter().filter_map(move |(dx, dz)| {
+            // diagonal moves can't cut corners
+            if dx != 0
+                && dz != 0
+                && (self.neighbour(node, (dx, 0)).is_none()
+                    || self.neighbour(node, (0, dz)).is_none())
+            {
+                return None;
+            }
+            self.neighbour(node, (dx, dz))
+        })
+    }
+
+    // shrinks the walkable area by agent_radius away from edges and walls
+    fn erode(&mut self) {
+        let radius = (self.options.agent_radius / self.options.cell_size).ceil() as u32;
+        if radius == 0 {
+            return;
+        }
+
+        // cells missing an orthogonal neighbour are on an edge, distance spreads inwards from them
+        let mut distance = vec![u32::MAX; self.nodes.len()];
+        let mut queue = VecDeque::new();
+        for (node, distance) in distance.iter_mut().enumerate() {
+            if DIRECTIONS[..4]
+                .iter()
+                .any(|&direction| self.neighbour(node, direction).is_none())
+            {
+                *distance = 1;
+                queue.push_back(node);
+            }
+        }
+
+        while let Some(node) = queue.pop_front() {
+            for &direction in &DIRECTIONS[..4] {
+                if let Some(neighbour) = self.neighbour(node, direction)
+                    && distance[neighbour] == u32::MAX
+                {
+                    distance[neighbour] = distance[node] + 1;
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+
+        let nodes = std::mem::take(&mut self.nodes);
+        self.columns.iter_mut().for_each(Vec::clear);
+        for (node, distance) in nodes.into_iter().zip(distance) {
+            if distance > radius {
+                self.nodes.push(node);
+                self.columns[node.x + node.z * self.width].push(self.nodes.len() - 1);
+            }
+        }
+    }
+
+    // drops path cells that can be skipped by walking in a straight line
+    fn smooth(&self, cells: &[usize]) -> Vec<usize> {
+        let mut smoothed = vec![cells[0]];
+        let mut anchor = 0;
+        while anchor < cells.len() - 1 {
+            let furthest = (anchor + 1..cells.len())
+                .rev()
+                .find(|&index| self.straight_walkable(cells[anchor], cells[index]))
+                .unwrap_or(anchor + 1);
+            smoothed.push(cells[furthest]);
+            anchor = furthest;
+        }
+        smoothed
+    }
+
+    // samples the line between two cells every half cell, following surfaces within max_climb
+    fn straight_walkable(&self, from: usize, to: usize) -> bool {
+        let (start, end) = (self.node_position(from), self.node_position(to));
+        let steps = (start.distance(end) / (self.options.cell_size * 0.5)).ceil() as usize;
+
+        let mut height = start.y;
+        for step in 1..=steps {
+            let point = start.lerp(end, step as f32 / steps as f32);
+            match self.node_at(point, height) {
+                Some(node) => height = self.nodes[node].height,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    // adds a span to every column the triangle overlaps
+    fn rasterize_triangle(&self, triangle: &[Vec3], walkable: bool, spans: &mut [Vec<Span>]) {
+        let cell_size = self.options.cell_size;
+        let min = triangle[0].min(triangle[1]).min(triangle[2]) - self.origin;
+        let max = triangle[0].max(triangle[1]).max(triangle[2]) - self.origin;
+        let cell_range = |min: f32, max: f32, count: usize| {
+            let first = ((min / cell_size).floor().max(0.0) as usize).min(count - 1);
+            let last = ((max / cell_size).floor().max(0.0) as usize).min(count - 1);
+            first..=last
+        };
+
+        for z in cell_range(min.z, max.z, self.depth) {
+            let row_min = self.origin.z + z as f32 * cell_size;
+            let row = clip_polygon(triangle, 2, row_min, row_min + cell_size);
+            if row.len() < 3 {
+                continue;
+            }
+
+            for x in cell_range(min.x, max.x, self.width) {
+                let column_min = self.origin.x + x as f32 * cell_size;
+                let cell = clip_polygon(&row, 0, column_min, column_min + cell_size);
+                if cell.len() < 3 {
+                    continue;
+                }
+
+                let (min, max) = cell
+                    .iter()
+                    .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), point| {
+                        (min.min(point.y), max.max(point.y))
+                    });
+                spans[x + z * self.width].push(Span { min, max, walkable });
+            }
+        }
+    }
+}
+
+// min heap entry for A*
+struct OpenNode {
+    estimate: f32,
+    node: usize,
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed so the binary heap pops the lowest estimate
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+// sorts a column and merges overlapping spans, the higher top decides if the result is walkable
+fn merge_spans(column: &mut Vec<Span>, merge_distance: f32) {
+    column.sort_by(|a, b| a.min.total_cmp(&b.min));
+    let mut merged: Vec<Span> = Vec::with_capacity(column.len());
+    for span in column.drain(..) {
+        match merged.last_mut() {
+            Some(last) if span.min <= last.max + merge_distance => {
+                if (span.max - last.max).abs() <= merge_distance {
+                    last.walkable |= span.walkable;
+                } else if span.max > last.max {
+                    last.walkable = span.walkable;
+                }
+                last.max = last.max.max(span.max);
+            }
+            _ => merged.push(span),
+        }
+    }
+    *column = merged;
+}
+
+// keeps the part of polygon with min <= position[axis] <= max
+fn clip_polygon(polygon: &[Vec3], axis: usize, min: f32, max: f32) -> Vec<Vec3> {
+    let above_min = clip_against(polygon, |point| point[axis] - min);
+    clip_against(&above_min, |point| max - point[axis])
+}
+
+// sutherland hodgman against one plane, keeps points with distance >= 0
+fn clip_against(polygon: &[Vec3], distance: impl Fn(Vec3) -> f32) -> Vec<Vec3> {
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for (index, &current) in polygon.iter().enumerate() {
+        let previous = polygon[(index + polygon.len() - 1) % polygon.len()];
+        let (current_distance, previous_distance) = (distance(current), distance(previous));
+        if (current_distance >= 0.0) != (previous_distance >= 0.0) {
+            let t = previous_distance / (previous_distance - current_distance);
+            clipped.push(previous.lerp(current, t));
+        }
+        if current_distance >= 0.0 {
+            clipped.push(current);
+        }
+    }
+    clipped
+}
+
+#[test]
+fn navmesh_path_test() {
+    use crate::renderer::mesh::CUBE_VERTICES;
+    use glam::Mat4;
+
+    let cube = |transform: Mat4| {
+        CUBE_VERTICES
+            .iter()
+            .map(move |vertex| transform.transform_point3(vertex.pos))
+    };
+
+    // 10x10 floor with its top at y = 0 and a wall across most of it at x = -1..1, z = -5..3
+    let floor = Mat4::from_scale_rotation_translation(
+        Vec3::new(10.0, 1.0, 10.0),
+        glam::Quat::IDENTITY,
+        Vec3::new(0.0, -0.5, 0.0),
+    );
+    let wall = Mat4::from_scale_rotation_translation(
+        Vec3::new(2.0, 4.0, 8.0),
+        glam::Quat::IDENTITY,
+        Vec3::new(0.0, 1.0, -1.0),
+    );
+    let triangles: Vec<Vec3> = cube(floor).chain(cube(wall)).collect();
+
+    let navmesh = NavMesh::build(&triangles, &NavMeshOptions::default()).unwrap();
+    assert!(navmesh.is_walkable(Vec3::new(-3.0, 0.0, 0.0)));
+    // eroded away from the wall and the edge of the floor
+    assert!(!navmesh.is_walkable(Vec3::new(-1.2, 0.0, 0.0)));
+    assert!(!navmesh.is_walkable(Vec3::new(-4.9, 0.0, 0.0)));
+
+    let start = Vec3::new(-3.0, 0.0, 0.0);
+    let goal = Vec3::new(3.0, 0.0, 0.0);
+    let path = navmesh.find_path(start, goal).unwrap();
+
+    // has to go around the end of the wall
+    assert!(path.iter().any(|point| point.z > 3.0));
+    assert!(path.first().unwrap().distance(start) < 0.25);
+    assert!(path.last().unwrap().distance(goal) < 0.25);
+    for segment in path.windows(2) {
+        assert!(navmesh.straight_walkable(
+            navmesh.nearest_node(segment[0]).unwrap(),
+            navmesh.nearest_node(segment[1]).unwrap()
+        ));
+    }
+
+    // the wall top is walkable but too high to climb onto
+    assert!(navmesh.is_walkable(Vec3::new(0.0, 3.0, 0.0)));
+    assert!(
+        navmesh
+            .find_path(start, Vec3::new(0.0, 3.0, -1.0))
+            .is_none()
+    );
+}
+
+#[test]
+fn navmesh_filter_test() {
+    // 60 degree ramp
+    let ramp = [
+        Vec3::new(0.0, 0.0, 4.0),
+        Vec3::new(4.0, 4.0 * 3.0_f32.sqrt(), 4.0),
+        Vec3::new(4.0, 4.0 * 3.0_f32.sqrt(), 0.0),
+    ];
+    let navmesh = NavMesh::build(&ramp, &NavMeshOptions::default().agent_radius(0.0)).unwrap();
+    assert_eq!(navmesh.node_count(), 0);
+    assert!(navmesh.find_path(Vec3::ZERO, Vec3::ONE).is_none());
+
+    // floor under a ceiling lower than the agent
+    let floor = [
+        Vec3::new(0.0, 0.0, 4.0),
+        Vec3::new(4.0, 0.0, 4.0),
+        Vec3::new(4.0, 0.0, 0.0),
+    ];
+    let ceiling = floor.map(|point| point + Vec3::Y * 1.5);
+    let options = NavMeshOptions::default().agent_radius(0.0);
+    let open = NavMesh::build(&floor, &options).unwrap();
+    assert!(open.node_count() > 0);
+    let covered = NavMesh::build(&[floor, ceiling].concat(), &options).unwrap();
+    // the ceiling's top is still walkable, only the floor is lost
+    assert_eq!(covered.node_count(), open.node_count());
+    assert!(!covered.is_walkable(Vec3::new(3.0, 0.0, 1.0)));
+
+    assert_eq!(
+        NavMesh::build(&floor, &options.cell_size(0.0)).unwrap_err(),
+        NavMeshError::InvalidOptions
+    );
+}
@@ -0,0 +1,292 @@
+use glam::{EulerRot, Quat, Vec2, Vec3};
+use log::info;
+use std::error;
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use crate::camera::{Camera, Projection};
+use crate::renderer::VKRenderer;
+use crate::renderer::capture::{CaptureOptions, VKCapture};
+use crate::time::GameClock;
+
+// narrowest and widest the free camera zooms to, in radians
+const MIN_FOV_Y: f32 = 5.0 * PI / 180.0;
+const MAX_FOV_Y: f32 = 120.0 * PI / 180.0;
+
+// keeps the free camera from flipping over when looking straight up or down
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+/// Blur in front of and behind the focus distance, only in photo captures
+/// averaged from captures taken across the lens, so it costs a capture per sample
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthOfField {
+    /// distance in front of the camera that stays sharp
+    pub focus_distance: f32,
+    /// lens radius in world units, larger blurs more
+    pub aperture: f32,
+    /// captures averaged, more is smoother and slower
+    pub samples: u32,
+}
+
+impl DepthOfField {
+    pub fn new(focus_distance: f32, aperture: f32) -> Self {
+        Self {
+            focus_distance,
+            aperture,
+            samples: 16,
+        }
+    }
+
+    pub fn samples(mut self, samples: u32) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    /// Points on the unit disc the lens is sampled at, the first in the centre
+    /// spread along a golden angle spiral so any number of them covers the disc evenly
+    pub fn lens_offsets(&self) -> Vec<Vec2> {
+        let samples = self.samples.max(1);
+        let golden_angle = PI * (3.0 - 5.0_f32.sqrt());
+        (0..samples)
+            .map(|sample| {
+                let radius = (sample as f32 / samples as f32).sqrt();
+                Vec2::from_angle(sample as f32 * golden_angle) * radius
+            })
+            .collect()
+    }
+
+    /// Camera moved to offset on the lens, aimed so the focus point stays where it was on screen
+    pub fn lens_camera(&self, camera: &Camera, offset: Vec2) -> Camera {
+        let focus = camera.position + camera.forward() * self.focus_distance;
+        let right = camera.rotation * Vec3::X;
+        let up = camera.rotation * Vec3::Y;
+        let position = camera.position + (right * offset.x + up * offset.y) * self.aperture;
+        camera.look_at(position, focus, up)
+    }
+}
+
+/// What photo mode changes on top of the free camera
+#[derive(Clone, Copy, Debug)]
+pub struct PhotoSettings {
+    /// stops added to the scene's exposure, see Lighting::exposure
+    pub exposure: f32,
+    pub depth_of_field: Option<DepthOfField>,
+    /// stops drawing sprites while in photo mode
+    pub hide_ui: bool,
+    /// resolution of PhotoMode::capture, twice the window's by default
+    pub capture: CaptureOptions,
+}
+
+impl Default for PhotoSettings {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            depth_of_field: None,
+            hide_ui: true,
+            capture: CaptureOptions::default().scale(2),
+        }
+    }
+}
+
+/// Pauses the game and hands the camera to the player for taking screenshots
+/// built from the game clock, the renderer's camera and frame captures, games drive the
+/// free camera from their own input and can show their own photo mode ui
+/// Example Use:
+/// ```ignore
+/// let mut photo_mode = PhotoMode::enter(&mut renderer, &mut clock);
+/// photo_mode.settings.depth_of_field = Some(DepthOfField::new(4.0, 0.05));
+///
+/// // every frame, with real time as the clock is paused
+/// photo_mode.fly(movement, mouse_delta * 0.002, real_delta);
+/// photo_mode.roll += roll_input * real_delta;
+/// photo_mode.apply(&mut renderer);
+///
+/// photo_mode.capture(&mut renderer)?.save_ppm("photo.ppm")?;
+/// photo_mode.exit(&mut renderer, &mut clock);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct PhotoMode {
+    pub position: Vec3,
+    /// radians, turning around y then pitching up then rolling around the view direction
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    /// radians, perspective cameras only, clamped to a range a lens could have
+    pub fov_y: f32,
+    /// world units per second fly moves at
+    pub speed: f32,
+    pub settings: PhotoSettings,
+    // what enter found, put back by exit
+    camera: Camera,
+    exposure: f32,
+    ui_visible: bool,
+    was_paused: bool,
+}
+
+impl PhotoMode {
+    /// Free camera starting where camera is, with camera's projection
+    pub fn from_camera(camera: &Camera) -> Self {
+        let (yaw, pitch, roll) = camera.rotation.to_euler(EulerRot::YXZ);
+        let fov_y = match camera.projection {
+            Projection::Perspective { fov_y, .. } => fov_y,
+            Projection::Orthographic { .. } => 70.0_f32.to_radians(),
+        };
+        Self {
+            position: camera.position,
+            yaw,
+            pitch,
+            roll,
+            fov_y,
+            speed: 5.0,
+            settings: PhotoSettings::default(),
+            camera: *camera,
+            exposure: 0.0,
+            ui_visible: true,
+            was_paused: false,
+        }
+    }
+
+    /// Pauses the clock and takes over the renderer's camera from where it is
+    pub fn enter(renderer: &mut VKRenderer, clock: &mut GameClock) -> Self {
+        let mut photo_mode = Self::from_camera(&renderer.camera);
+        photo_mode.exposure = renderer.lighting.exposure;
+        photo_mode.ui_visible = renderer.renderer2d.visible;
+        photo_mode.was_paused = clock.is_paused();
+        clock.pause();
+        photo_mode.apply(renderer);
+        info!("Entered Photo Mode");
+        photo_mode
+    }
+
+    /// Puts the camera, exposure and ui back and resumes the clock unless it was already paused
+    pub fn exit(self, renderer: &mut VKRenderer, clock: &mut GameClock) {
+        renderer.camera = self.camera;
+        renderer.lighting.exposure = self.exposure;
+        renderer.renderer2d.visible = self.ui_visible;
+        if !self.was_paused {
+            clock.resume();
+        }
+        info!("Exited Photo Mode");
+    }
+
+    /// Moves and turns the free camera, delta is in real seconds as game time is paused
+    /// movement is along the camera's right, up and back axes, look turns right and up in radians
+    pub fn fly(&mut self, movement: Vec3, look: Vec2, delta: f32) {
+        self.yaw -= look.x;
+        self.pitch = (self.pitch + look.y).clamp(-MAX_PITCH, MAX_PITCH);
+        self.position += self.rotation() * movement * self.speed * delta;
+    }
+
+    pub fn rotation(&self) -> Quat {
+        Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, self.roll)
+    }
+
+    /// The free camera, with the projection of the camera photo mode started from
+    pub fn camera(&self) -> Camera {
+        let mut camera = self.camera;
+        camera.position = self.position;
+        camera.rotation = self.rotation();
+        if let Projection::Perspective { fov_y, .. } = &mut camera.projection {
+            *fov_y = self.fov_y.clamp(MIN_FOV_Y, MAX_FOV_Y);
+        }
+        camera
+    }
+
+    /// Shows the free camera and settings, call every frame after moving the camera
+    pub fn apply(&self, renderer: &mut VKRenderer) {
+        renderer.camera = self.camera();
+        renderer.lighting.exposure = self.exposure + self.settings.exposure;
+        renderer.renderer2d.visible = self.ui_visible && !self.settings.hide_ui;
+    }
+
+    /// Captures the free camera's view at settings.capture's resolution, with depth of field
+    /// waits for the gpu to go idle for every lens sample
+    pub fn capture(&self, renderer: &mut VKRenderer) -> Result<VKCapture, Box<dyn error::Error>> {
+        self.apply(renderer);
+        let options = self.settings.capture;
+        let Some(depth_of_field) = self
+            .settings
+            .depth_of_field
+            .filter(|depth_of_field| depth_of_field.samples > 1)
+        else {
+            return renderer.capture_frame(options);
+        };
+
+        let camera = renderer.camera;
+        let capture = capture_depth_of_field(renderer, &camera, &depth_of_field, options);
+        renderer.camera = camera;
+        info!(
+            "Captured Photo With {} Lens Samples",
+            depth_of_field.samples
+        );
+        capture
+    }
+}
+
+// averages a capture from each lens sample, the renderer's camera is left on the last one
+fn capture_depth_of_field(
+    renderer: &mut VKRenderer,
+    camera: &Camera,
+    depth_of_field: &DepthOfField,
+    options: CaptureOptions,
+) -> Result<VKCapture, Box<dyn error::Error>> {
+    let offsets = depth_of_field.lens_offsets();
+    let mut sums: Vec<u32> = Vec::new();
+    let mut first = None;
+    for offset in &offsets {
+        renderer.camera = depth_of_field.lens_camera(camera, *offset);
+        let capture = renderer.capture_frame(options)?;
+        sums.resize(capture.pixels.len(), 0);
+        for (sum, pixel) in sums.iter_mut().zip(&capture.pixels) {
+            *sum += *pixel as u32;
+        }
+        first.get_or_insert(capture);
+    }
+
+    let mut capture = first.ok_or("Depth Of Field Has No Lens Samples")?;
+    let count = offsets.len() as u32;
+    capture.pixels = sums
+        .iter()
+        .map(|sum| ((sum + count / 2) / count) as u8)
+        .collect();
+    Ok(capture)
+}
+
+#[test]
+fn photo_mode_test() {
+    let camera =
+        Camera::perspective(1.0, 0.1).look_at(Vec3::new(0.0, 2.0, 5.0), Vec3::ZERO, Vec3::Y);
+    let mut photo_mode = PhotoMode::from_camera(&camera);
+    let free = photo_mode.camera();
+    assert!(free.position.abs_diff_eq(camera.position, 1e-6));
+    assert!(free.forward().abs_diff_eq(camera.forward(), 1e-5));
+
+    // flying forward for a second covers speed units along the view direction
+    photo_mode.fly(Vec3::NEG_Z, Vec2::ZERO, 1.0);
+    let moved = photo_mode.camera().position - camera.position;
+    assert!(moved.abs_diff_eq(camera.forward() * photo_mode.speed, 1e-4));
+
+    // rolling keeps the view direction, zooming is clamped
+    photo_mode.roll = 0.5;
+    photo_mode.fov_y = 10.0;
+    let rolled = photo_mode.camera();
+    assert!(rolled.forward().abs_diff_eq(camera.forward(), 1e-5));
+    assert!(matches!(
+        rolled.projection,
+        Projection::Perspective { fov_y, .. } if fov_y == MAX_FOV_Y
+    ));
+    photo_mode.fly(Vec3::ZERO, Vec2::new(0.0, 10.0), 0.0);
+    assert_eq!(photo_mode.pitch, MAX_PITCH);
+
+    // every lens sample sees the focus point in the middle of the screen
+    let depth_of_field = DepthOfField::new(4.0, 0.1).samples(8);
+    let offsets = depth_of_field.lens_offsets();
+    assert_eq!(offsets.len(), 8);
+    assert_eq!(offsets[0], Vec2::ZERO);
+    assert!(offsets.iter().all(|offset| offset.length() < 1.0));
+    let focus = rolled.position + rolled.forward() * 4.0;
+    for offset in offsets {
+        let lens = depth_of_field.lens_camera(&rolled, offset);
+        let clip = lens.view_projection(1.0).project_point3(focus);
+        assert!(clip.truncate().abs_diff_eq(Vec2::ZERO, 1e-4));
+    }
+}
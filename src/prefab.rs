@@ -0,0 +1,735 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::mem;
+use std::path::Path;
+use std::{fs, io};
+use thiserror::Error;
+
+use crate::color::LinearRgba;
+use crate::renderer::material::MaterialId;
+use crate::renderer::mesh::MeshId;
+use crate::scene::{Node, NodeId, Scene, SceneError, Transform};
+
+/// Bumped whenever the prefab file changes in a way old files can't be read as
+pub const PREFAB_VERSION: u32 = 1;
+
+/// Index of a prefab in a PrefabLibrary
+pub type PrefabId = usize;
+/// Index of a placed prefab in a PrefabLibrary, stays valid until the instance is removed
+pub type PrefabInstanceId = usize;
+
+#[derive(Debug, Error)]
+pub enum PrefabError {
+    #[error("failed to access prefab: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to parse prefab: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("failed to write prefab: {0}")]
+    Serialize(#[from] ron::Error),
+    #[error("prefab version {found} can't be read, expected {PREFAB_VERSION}")]
+    Version { found: u32 },
+    #[error("prefab {0} does not exist")]
+    MissingPrefab(PrefabId),
+    #[error("prefab instance {0} does not exist")]
+    MissingInstance(PrefabInstanceId),
+    #[error("prefab has no node {0}")]
+    MissingNode(NodeId),
+    #[error("prefab {prefab} has no nested prefab {index}")]
+    MissingNested { prefab: PrefabId, index: usize },
+    #[error("prefab {nested} contains prefab {prefab} and can't be nested in it")]
+    Cycle { prefab: PrefabId, nested: PrefabId },
+    #[error(transparent)]
+    Scene(#[from] SceneError),
+}
+
+/// One property of a prefab node changed for a single instance
+/// edits to the prefab reach every other property of the node
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PropertyOverride {
+    Name(String),
+    Transform(Transform),
+    Mesh(Option<MeshId>),
+    Material(MaterialId),
+    Tint(LinearRgba),
+}
+
+impl PropertyOverride {
+    pub fn apply(&self, node: &mut Node) {
+        match self {
+            PropertyOverride::Name(name) => node.name.clone_from(name),
+            PropertyOverride::Transform(transform) => node.transform = *transform,
+            PropertyOverride::Mesh(mesh) => node.mesh = *mesh,
+            PropertyOverride::Material(material) => node.material = *material,
+            PropertyOverride::Tint(tint) => node.tint = *tint,
+        }
+    }
+
+    /// What node currently has for the same property
+    pub fn read(&self, node: &Node) -> Self {
+        match self {
+            PropertyOverride::Name(_) => PropertyOverride::Name(node.name.clone()),
+            PropertyOverride::Transform(_) => PropertyOverride::Transform(node.transform),
+            PropertyOverride::Mesh(_) => PropertyOverride::Mesh(node.mesh),
+            PropertyOverride::Material(_) => PropertyOverride::Material(node.material),
+            PropertyOverride::Tint(_) => PropertyOverride::Tint(node.tint),
+        }
+    }
+
+    /// Whether both override the same property
+    pub fn same_property(&self, other: &Self) -> bool {
+        mem::discriminant(self) == mem::discriminant(other)
+    }
+}
+
+/// Overrides per prefab node
+pub type NodeOverrides = BTreeMap<NodeId, Vec<PropertyOverride>>;
+
+/// Another prefab placed inside a prefab
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NestedPrefab {
+    pub prefab: PrefabId,
+    /// prefab node the nested prefab is placed under, None places it at the prefab's root
+    pub parent: Option<NodeId>,
+    pub transform: Transform,
+    /// keyed by nodes of the nested prefab
+    #[serde(default)]
+    pub overrides: NodeOverrides,
+}
+
+impl NestedPrefab {
+    pub fn new(prefab: PrefabId) -> Self {
+        Self {
+            prefab,
+            parent: None,
+            transform: Transform::IDENTITY,
+            overrides: NodeOverrides::new(),
+        }
+    }
+
+    pub fn with_parent(mut self, parent: NodeId) -> Self {
+        self.parent = Some(parent);
+        self
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_override(mut self, node: NodeId, property: PropertyOverride) -> Self {
+        set_override(&mut self.overrides, node, property);
+        self
+    }
+}
+
+/// A sub-scene saved on its own and placed into scenes any number of times
+/// node ids of a prefab's scene are what overrides refer to
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Prefab {
+    pub version: u32,
+    pub name: String,
+    pub scene: Scene,
+    // only changed through PrefabLibrary::nest so cycles are caught
+    nested: Vec<NestedPrefab>,
+}
+
+impl Prefab {
+    pub fn new(name: impl Into<String>, scene: Scene) -> Self {
+        Self {
+            version: PREFAB_VERSION,
+            name: name.into(),
+            scene,
+            nested: Vec::new(),
+        }
+    }
+
+    pub fn nested(&self) -> &[NestedPrefab] {
+        &self.nested
+    }
+
+    pub fn to_ron(&self) -> Result<String, PrefabError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    /// Fails on prefabs from another PREFAB_VERSION
+    pub fn from_ron(source: &str) -> Result<Self, PrefabError> {
+        let prefab: Self = ron::from_str(source)?;
+        if prefab.version != PREFAB_VERSION {
+            return Err(PrefabError::Version {
+                found: prefab.version,
+            });
+        }
+        Ok(prefab)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), PrefabError> {
+        Ok(fs::write(path, self.to_ron()?)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PrefabError> {
+        Self::from_ron(&fs::read_to_string(path)?)
+    }
+}
+
+/// A prefab placed in a scene
+#[derive(Clone, Debug)]
+pub struct PrefabInstance {
+    pub prefab: PrefabId,
+    /// node the prefab's roots are placed under, moving it moves the whole instance
+    pub pivot: NodeId,
+    // prefab node -> scene node
+    nodes: BTreeMap<NodeId, NodeId>,
+    overrides: NodeOverrides,
+    // one per entry of the prefab's nested list
+    nested: Vec<PrefabInstanceId>,
+}
+
+impl PrefabInstance {
+    /// Scene node made from prefab_node
+    pub fn node(&self, prefab_node: NodeId) -> Option<NodeId> {
+        self.nodes.get(&prefab_node).copied()
+    }
+
+    pub fn overrides(&self, prefab_node: NodeId) -> &[PropertyOverride] {
+        self.overrides.get(&prefab_node).map_or(&[], Vec::as_slice)
+    }
+
+    /// Instances of the prefabs nested in this one, in the prefab's nested order
+    pub fn nested(&self) -> &[PrefabInstanceId] {
+        &self.nested
+    }
+}
+
+/// Prefabs and every place they were instantiated, edits to a prefab are copied to its instances
+/// nodes of an instance are rewritten from the prefab on every edit, changes that should stick
+/// have to be overrides, and nodes removed from the scene directly come back
+/// Example Use:
+/// ```
+/// use glam::Vec3;
+/// use vulkan_engine::color::LinearRgba;
+/// use vulkan_engine::prefab::{Prefab, PrefabLibrary, PropertyOverride};
+/// use vulkan_engine::renderer::material::DEFAULT_MATERIAL;
+/// use vulkan_engine::renderer::mesh::CUBE_MESH;
+/// use vulkan_engine::scene::{Node, Scene, Transform};
+///
+/// let mut lamp = Scene::default();
+/// let bulb = lamp.add(Node::new("bulb").with_mesh(CUBE_MESH, DEFAULT_MATERIAL), None).unwrap();
+///
+/// let mut library = PrefabLibrary::default();
+/// let lamp = library.add(Prefab::new("lamp", lamp)).unwrap();
+/// let mut scene = Scene::default();
+/// let left = library.instantiate(&mut scene, lamp, None, Transform::from_translation(-Vec3::X)).unwrap();
+/// let right = library.instantiate(&mut scene, lamp, None, Transform::from_translation(Vec3::X)).unwrap();
+/// library.set_override(&mut scene, left, bulb, PropertyOverride::Tint(LinearRgba::rgb(1.0, 0.0, 0.0))).unwrap();
+///
+/// // the edit reaches both lamps, the left one stays red
+/// library.edit(&mut scene, lamp, |lamp| lamp.get_mut(bulb).unwrap().tint = LinearRgba::BLACK).unwrap();
+/// let right_bulb = library.instance(right).unwrap().node(bulb).unwrap();
+/// assert_eq!(scene.get(right_bulb).unwrap().tint, LinearRgba::BLACK);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PrefabLibrary {
+    prefabs: Vec<Prefab>,
+    // removed instances leave a hole so other ids stay valid
+    instances: Vec<Option<PrefabInstance>>,
+}
+
+impl PrefabLibrary {
+    /// Prefabs nested in prefab have to be added before it
+    pub fn add(&mut self, prefab: Prefab) -> Result<PrefabId, PrefabError> {
+        let id = self.prefabs.len();
+        for nested in &prefab.nested {
+            // anything nested already being in the library rules out cycles
+            if nested.prefab >= id {
+                return Err(PrefabError::MissingPrefab(nested.prefab));
+            }
+        }
+        self.prefabs.push(prefab);
+        Ok(id)
+    }
+
+    pub fn get(&self, prefab: PrefabId) -> Option<&Prefab> {
+        self.prefabs.get(prefab)
+    }
+
+    pub fn instance(&self, instance: PrefabInstanceId) -> Option<&PrefabInstance> {
+        self.instances.get(instance).and_then(Option::as_ref)
+    }
+
+    /// Every live instance of prefab, including those nested in other prefabs' instances
+    pub fn instances_of(&self, prefab: PrefabId) -> Vec<PrefabInstanceId> {
+        self.instances
+            .iter()
+            .enumerate()
+            .filter(|(_, instance)| instance.as_ref().is_some_and(|i| i.prefab == prefab))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Copies prefab into scene under a new pivot node placed at transform
+    pub fn instantiate(
+        &mut self,
+        scene: &mut Scene,
+        prefab: PrefabId,
+        parent: Option<NodeId>,
+        transform: Transform,
+    ) -> Result<PrefabInstanceId, PrefabError> {
+        self.create_instance(scene, prefab, parent, transform, NodeOverrides::new())
+    }
+
+    /// Changes prefab and copies the change to every instance of it
+    pub fn edit(
+        &mut self,
+        scene: &mut Scene,
+        prefab: PrefabId,
+        edit: impl FnOnce(&mut Scene),
+    ) -> Result<(), PrefabError> {
+        let target = self
+            .prefabs
+            .get_mut(prefab)
+            .ok_or(PrefabError::MissingPrefab(prefab))?;
+        edit(&mut target.scene);
+        self.propagate(scene, prefab)
+    }
+
+    /// Places nested inside prefab, returns its index in Prefab::nested
+    pub fn nest(
+        &mut self,
+        scene: &mut Scene,
+        prefab: PrefabId,
+        nested: NestedPrefab,
+    ) -> Result<usize, PrefabError> {
+        self.get(prefab).ok_or(PrefabError::MissingPrefab(prefab))?;
+        self.get(nested.prefab)
+            .ok_or(PrefabError::MissingPrefab(nested.prefab))?;
+        if self.contains(nested.prefab, prefab) {
+            return Err(PrefabError::Cycle {
+                prefab,
+                nested: nested.prefab,
+            });
+        }
+        let index = self.prefabs[prefab].nested.len();
+        self.prefabs[prefab].nested.push(nested);
+        self.propagate(scene, prefab)?;
+        Ok(index)
+    }
+
+    /// Takes the nested prefab at index out of prefab and its instances
+    pub fn unnest(
+        &mut self,
+        scene: &mut Scene,
+        prefab: PrefabId,
+        index: usize,
+    ) -> Result<NestedPrefab, PrefabError> {
+        let target = self
+            .prefabs
+            .get_mut(prefab)
+            .ok_or(PrefabError::MissingPrefab(prefab))?;
+        if index >= target.nested.len() {
+            return Err(PrefabError::MissingNested { prefab, index });
+        }
+        let nested = target.nested.remove(index);
+        // later entries shift down, their instances are rebuilt rather than matched up again
+        for id in self.instances_of(prefab) {
+            if let Some(instance) = self.instances[id].as_mut() {
+                let removed: Vec<_> = instance.nested.drain(index..).collect();
+                for nested in removed {
+                    self.remove_instance(scene, nested)?;
+                }
+            }
+        }
+        self.propagate(scene, prefab)?;
+        Ok(nested)
+    }
+
+    /// Overrides one property of prefab_node for this instance only, replacing an earlier
+    /// override of the same property
+    pub fn set_override(
+        &mut self,
+        scene: &mut Scene,
+        instance: PrefabInstanceId,
+        prefab_node: NodeId,
+        property: PropertyOverride,
+    ) -> Result<(), PrefabError> {
+        let target = self
+            .instances
+            .get_mut(instance)
+            .and_then(Option::as_mut)
+            .ok_or(PrefabError::MissingInstance(instance))?;
+        self.prefabs[target.prefab]
+            .scene
+            .get(prefab_node)
+            .ok_or(PrefabError::MissingNode(prefab_node))?;
+        set_override(&mut target.overrides, prefab_node, property);
+        self.sync_instance(scene, instance)
+    }
+
+    /// Puts prefab_node of instance back to what the prefab says
+    pub fn clear_overrides(
+        &mut self,
+        scene: &mut Scene,
+        instance: PrefabInstanceId,
+        prefab_node: NodeId,
+    ) -> Result<(), PrefabError> {
+        let target = self
+            .instances
+            .get_mut(instance)
+            .and_then(Option::as_mut)
+            .ok_or(PrefabError::MissingInstance(instance))?;
+        target.overrides.remove(&prefab_node);
+        self.sync_instance(scene, instance)
+    }
+
+    /// Copies prefab to every instance of it, called by edit
+    pub fn propagate(&mut self, scene: &mut Scene, prefab: PrefabId) -> Result<(), PrefabError> {
+        for instance in self.instances_of(prefab) {
+            // syncing an outer instance can remove nested ones further down the list
+            if self.instance(instance).is_some() {
+                self.sync_instance(scene, instance)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the instance and its nodes from scene, nested instances go with it
+    pub fn remove_instance(
+        &mut self,
+        scene: &mut Scene,
+        instance: PrefabInstanceId,
+    ) -> Result<(), PrefabError> {
+        let removed = self
+            .instances
+            .get_mut(instance)
+            .and_then(Option::take)
+            .ok_or(PrefabError::MissingInstance(instance))?;
+        for nested in removed.nested {
+            self.remove_instance(scene, nested)?;
+        }
+        // already gone when it was below a removed node
+        if scene.get(removed.pivot).is_some() {
+            scene.remove(removed.pivot)?;
+        }
+        Ok(())
+    }
+
+    // whether prefab is target or nests it at any depth
+    fn contains(&self, prefab: PrefabId, target: PrefabId) -> bool {
+        prefab == target
+            || self.prefabs[prefab]
+                .nested
+                .iter()
+                .any(|nested| self.contains(nested.prefab, target))
+    }
+
+    fn create_instance(
+        &mut self,
+        scene: &mut Scene,
+        prefab: PrefabId,
+        parent: Option<NodeId>,
+        transform: Transform,
+        overrides: NodeOverrides,
+    ) -> Result<PrefabInstanceId, PrefabError> {
+        let name = &self
+            .get(prefab)
+            .ok_or(PrefabError::MissingPrefab(prefab))?
+            .name;
+        let pivot = scene.add(Node::new(name.clone()).with_transform(transform), parent)?;
+        let id = self.instances.len();
+        self.instances.push(Some(PrefabInstance {
+            prefab,
+            pivot,
+            nodes: BTreeMap::new(),
+            overrides,
+            nested: Vec::new(),
+        }));
+        self.sync_instance(scene, id)?;
+        Ok(id)
+    }
+
+    // makes the instance's nodes match its prefab with overrides applied
+    fn sync_instance(
+        &mut self,
+        scene: &mut Scene,
+        instance: PrefabInstanceId,
+    ) -> Result<(), PrefabError> {
+        let Some(target) = self.instances.get_mut(instance).and_then(Option::as_mut) else {
+            return Err(PrefabError::MissingInstance(instance));
+        };
+        let prefab = &self.prefabs[target.prefab];
+
+        // nodes taken out of the prefab, their children were removed with them
+        target.nodes.retain(|prefab_node, scene_node| {
+            let keep = prefab.scene.get(*prefab_node).is_some();
+            if !keep && scene.get(*scene_node).is_some() {
+                let _ = scene.remove(*scene_node);
+            }
+            keep
+        });
+
+        // parents before children so every parent exists in the scene when it's needed
+        let mut stack: Vec<(NodeId, NodeId)> = prefab
+            .scene
+            .roots()
+            .iter()
+            .rev()
+            .map(|&root| (root, target.pivot))
+            .collect();
+        while let Some((prefab_node, parent)) = stack.pop() {
+            let Some(source) = prefab.scene.get(prefab_node) else {
+                continue;
+            };
+            let mut node = source.clone();
+            for property in target.overrides.get(&prefab_node).into_iter().flatten() {
+                property.apply(&mut node);
+            }
+
+            let existing = target
+                .node(prefab_node)
+                .filter(|id| scene.get(*id).is_some());
+            let scene_node = match existing {
+                Some(scene_node) => {
+                    if let Some(placed) = scene.get_mut(scene_node) {
+                        copy_properties(placed, &node);
+                    }
+                    if scene.get(scene_node).and_then(Node::parent) != Some(parent) {
+                        scene.set_parent(scene_node, Some(parent))?;
+                    }
+                    scene_node
+                }
+                None => {
+                    let scene_node = scene.add(node, Some(parent))?;
+                    target.nodes.insert(prefab_node, scene_node);
+                    scene_node
+                }
+            };
+            stack.extend(
+                source
+                    .children()
+                    .iter()
+                    .rev()
+                    .map(|&child| (child, scene_node)),
+            );
+        }
+
+        let declared = prefab.nested.clone();
+        let pivot = target.pivot;
+        let placements: Vec<NodeId> = declared
+            .iter()
+            .map(|nested| {
+                nested
+                    .parent
+                    .and_then(|parent| target.node(parent))
+                    .unwrap_or(pivot)
+            })
+            .collect();
+        let mut existing = mem::take(&mut target.nested);
+
+        // entries the prefab no longer has
+        for nested in existing.split_off(declared.len().min(existing.len())) {
+            self.remove_instance(scene, nested)?;
+        }
+
+        let mut nested_instances = Vec::with_capacity(declared.len());
+        for (index, (nested, parent)) in declared.into_iter().zip(placements).enumerate() {
+            let reusable = existing.get(index).copied().filter(|&id| {
+                self.instance(id).is_some_and(|instance| {
+                    instance.prefab == nested.prefab && scene.get(instance.pivot).is_some()
+                })
+            });
+            let id = match reusable {
+                Some(id) => {
+                    let Some(instance) = self.instances[id].as_mut() else {
+                        return Err(PrefabError::MissingInstance(id));
+                    };
+                    // overrides of nested instances come from the prefab nesting them
+                    instance.overrides = nested.overrides;
+                    let pivot = instance.pivot;
+                    if let Some(pivot) = scene.get_mut(pivot) {
+                        pivot.transform = nested.transform;
+                    }
+                    if scene.get(pivot).and_then(Node::parent) != Some(parent) {
+                        scene.set_parent(pivot, Some(parent))?;
+                    }
+                    self.sync_instance(scene, id)?;
+                    id
+                }
+                None => {
+                    if let Some(&stale) = existing.get(index)
+                        && self.instance(stale).is_some()
+                    {
+                        self.remove_instance(scene, stale)?;
+                    }
+                    self.create_instance(
+                        scene,
+                        nested.prefab,
+                        Some(parent),
+                        nested.transform,
+                        nested.overrides,
+                    )?
+                }
+            };
+            nested_instances.push(id);
+        }
+
+        if let Some(target) = self.instances[instance].as_mut() {
+            target.nested = nested_instances;
+        }
+        Ok(())
+    }
+}
+
+fn set_override(overrides: &mut NodeOverrides, node: NodeId, property: PropertyOverride) {
+    let properties = overrides.entry(node).or_default();
+    properties.retain(|existing| !existing.same_property(&property));
+    properties.push(property);
+}
+
+// everything a prefab defines about a node, the hierarchy is handled separately
+fn copy_properties(target: &mut Node, source: &Node) {
+    target.name.clone_from(&source.name);
+    target.transform = source.transform;
+    target.mesh = source.mesh;
+    target.material = source.material;
+    target.tint = source.tint;
+    target.lods.clone_from(&source.lods);
+}
+
+#[test]
+fn prefab_test() {
+    use crate::renderer::material::DEFAULT_MATERIAL;
+    use glam::Vec3;
+
+    let mut lamp = Scene::default();
+    let post = lamp
+        .add(Node::new("post").with_mesh(0, DEFAULT_MATERIAL), None)
+        .unwrap();
+    let bulb = lamp
+        .add(
+            Node::new("bulb")
+                .with_mesh(1, DEFAULT_MATERIAL)
+                .with_transform(Transform::from_translation(Vec3::Y * 3.0)),
+            Some(post),
+        )
+        .unwrap();
+
+    let mut library = PrefabLibrary::default();
+    let lamp = library.add(Prefab::new("lamp", lamp)).unwrap();
+    let mut scene = Scene::default();
+    let left = library
+        .instantiate(
+            &mut scene,
+            lamp,
+            None,
+            Transform::from_translation(-Vec3::X),
+        )
+        .unwrap();
+    let right = library
+        .instantiate(&mut scene, lamp, None, Transform::from_translation(Vec3::X))
+        .unwrap();
+    // a pivot, post and bulb each
+    assert_eq!(scene.iter().count(), 6);
+    let left_bulb = library.instance(left).unwrap().node(bulb).unwrap();
+    let right_bulb = library.instance(right).unwrap().node(bulb).unwrap();
+    scene.update_world_matrices();
+    assert_eq!(
+        scene.get(left_bulb).unwrap().world_matrix().w_axis,
+        glam::Vec4::new(-1.0, 3.0, 0.0, 1.0)
+    );
+
+    library
+        .set_override(
+            &mut scene,
+            left,
+            bulb,
+            PropertyOverride::Tint(LinearRgba::rgb(1.0, 0.0, 0.0)),
+        )
+        .unwrap();
+    library
+        .set_override(&mut scene, left, bulb, PropertyOverride::Mesh(None))
+        .unwrap();
+    assert_eq!(library.instance(left).unwrap().overrides(bulb).len(), 2);
+
+    // edits reach every instance, overridden properties stay
+    let mut shade = None;
+    library
+        .edit(&mut scene, lamp, |lamp| {
+            let bulb = lamp.get_mut(bulb).unwrap();
+            bulb.tint = LinearRgba::BLACK;
+            bulb.mesh = Some(2);
+            shade = Some(lamp.add(Node::new("shade"), Some(post)).unwrap());
+        })
+        .unwrap();
+    assert_eq!(
+        scene.get(left_bulb).unwrap().tint,
+        LinearRgba::rgb(1.0, 0.0, 0.0)
+    );
+    assert_eq!(scene.get(left_bulb).unwrap().mesh, None);
+    assert_eq!(scene.get(right_bulb).unwrap().tint, LinearRgba::BLACK);
+    assert_eq!(scene.get(right_bulb).unwrap().mesh, Some(2));
+    let right_shade = library
+        .instance(right)
+        .unwrap()
+        .node(shade.unwrap())
+        .unwrap();
+    assert_eq!(scene.get(right_shade).unwrap().name, "shade");
+
+    library.clear_overrides(&mut scene, left, bulb).unwrap();
+    assert_eq!(scene.get(left_bulb).unwrap().tint, LinearRgba::BLACK);
+
+    library
+        .edit(&mut scene, lamp, |lamp| lamp.remove(bulb).unwrap())
+        .unwrap();
+    assert!(scene.get(left_bulb).is_none() && scene.get(right_bulb).is_none());
+    assert_eq!(scene.iter().count(), 6);
+
+    // a street of two lamps, lamp edits reach lamps inside streets too
+    let mut road = Scene::default();
+    road.add(Node::new("road"), None).unwrap();
+    let street = library.add(Prefab::new("street", road)).unwrap();
+    for x in [-5.0, 5.0] {
+        library
+            .nest(
+                &mut scene,
+                street,
+                NestedPrefab::new(lamp).with_transform(Transform::from_translation(Vec3::X * x)),
+            )
+            .unwrap();
+    }
+    let placed = library
+        .instantiate(&mut scene, street, None, Transform::IDENTITY)
+        .unwrap();
+    let nested = library.instance(placed).unwrap().nested().to_vec();
+    assert_eq!(nested.len(), 2);
+    assert_eq!(library.instances_of(lamp).len(), 4);
+    library
+        .edit(&mut scene, lamp, |lamp| {
+            lamp.get_mut(post).unwrap().name = "pole".into()
+        })
+        .unwrap();
+    let nested_post = library.instance(nested[1]).unwrap().node(post).unwrap();
+    assert_eq!(scene.get(nested_post).unwrap().name, "pole");
+
+    // lamp can't contain the street it's in
+    assert!(matches!(
+        library.nest(&mut scene, lamp, NestedPrefab::new(street)),
+        Err(PrefabError::Cycle { .. })
+    ));
+
+    library.unnest(&mut scene, street, 0).unwrap();
+    assert_eq!(library.instance(placed).unwrap().nested().len(), 1);
+    assert_eq!(library.instances_of(lamp).len(), 3);
+
+    let count = scene.iter().count();
+    library.remove_instance(&mut scene, placed).unwrap();
+    // pivot and road of the street, pivot and pole and shade of its lamp
+    assert_eq!(scene.iter().count(), count - 5);
+    assert_eq!(library.instances_of(lamp).len(), 2);
+
+    let saved = library.get(street).unwrap();
+    let restored = Prefab::from_ron(&saved.to_ron().unwrap()).unwrap();
+    assert_eq!(restored.nested(), saved.nested());
+    assert_eq!(restored.name, "street");
+}
@@ -1,32 +1,394 @@
+pub mod allocator;
+pub mod benchmark;
+pub mod bloom;
+pub mod buffer;
+pub mod capture;
+pub mod color_filter;
+pub mod command_cache;
+pub mod compute;
+pub mod cubemap;
+pub mod debug;
+pub mod debug_draw;
+pub mod debug_text;
+pub mod descriptor;
 pub mod device;
+pub mod draw_cull;
+pub mod image;
+pub mod indirect;
+pub mod material;
+pub mod mesh;
+pub mod mock;
+pub mod occlusion;
+pub mod parallel;
+pub mod perf_query;
+pub mod pipeline;
+pub mod pipeline_cache;
 pub mod presentation;
+pub mod quirks;
+pub mod ray_tracing;
+pub mod render_graph;
+pub mod render_texture;
+pub mod renderer2d;
+pub mod replay;
+pub mod retro;
+pub mod scaling;
+pub mod scan;
+pub mod scene_buffer;
 pub mod shader;
-
+pub mod shader_inputs;
+pub mod skinning;
+pub mod skybox;
+pub mod sort;
+pub mod ssao;
+#[cfg(feature = "text")]
+pub mod text;
+pub mod texture;
+pub mod timing;
+pub mod tonemap;
+pub mod uniform_ring;
+pub mod upload;
+pub mod vertex;
+pub mod window_target;
+
+use crate::animation::{VertexAnimation, VertexSkin};
+use crate::assets::{AssetGraph, AssetKind};
+use crate::camera::{Camera, CameraUniform, DepthConvention};
+use crate::color::LinearRgba;
+use crate::crash_report;
+use crate::lighting::{LightUniform, Lighting};
+use crate::math::{Aabb, Frustum};
+use crate::mesh_cache::CompressedMesh;
+use crate::renderer::debug::{
+    VALIDATION_LAYER, VKDebugLabels, VKDebugMessenger, available_instance_layers,
+    instance_extension_available, instance_layer_available,
+};
 use crate::renderer::device::VKDevice;
-use crate::renderer::presentation::VKPresent;
+use crate::renderer::presentation::{PresentTarget, VKPresent};
+use crate::renderer::window_target::{FrameCadence, VKWindowTarget};
 use crate::utils::GameInfo;
-use ash::vk::{CommandBufferUsageFlags, CompareOp, PolygonMode, ShaderStageFlags};
+use crate::validation::{SceneValidationError, validate_instances, validate_references};
+use ash::vk::{CommandBufferUsageFlags, ShaderStageFlags};
 use ash::{Entry, Instance, vk};
 use gpu_allocator::MemoryLocation;
-use gpu_allocator::vulkan;
 use log::error;
+use log::info;
 use log::warn;
-use presser;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::error;
 
-use presentation::{VKSurface, VKSwapchain};
-use shader::{VKShader, VKShaderLoader};
-use std::ffi::c_char;
-use winit::raw_window_handle::HasDisplayHandle;
-use winit::window::Window;
-
-use glam::{Mat4, Vec3};
+use allocator::{AllocatorFactory, GpuAllocator};
+use bloom::{BloomSettings, BloomStep, VKBloomPass};
+use buffer::VKBuffer;
+use color_filter::ColorFilter;
+use command_cache::{FrameInputs, VKCommandCache};
+use cubemap::VKCubemap;
+use debug_draw::{DebugDraw, VKDebugDraw};
+use debug_text::DebugFont;
+use descriptor::{DEFAULT_POOL_RATIOS, VKDescriptorAllocator};
+use draw_cull::VKGpuCulling;
+use material::{
+    DEFAULT_MATERIAL, MaterialDesc, MaterialFeatures, MaterialId, MaterialParams, PipelineVariant,
+    VKMaterial,
+};
+use mesh::{CUBE_MESH, CUBE_VERTICES, MeshId, VKMesh, Vertex};
+use occlusion::VKOcclusionCuller;
+use parallel::{RenderingInheritance, VKParallelRecorder};
+use perf_query::{PassCounters, VKPerfQueries};
+use pipeline::{DepthState, GraphicsPipelineBuilder};
+use pipeline_cache::PIPELINE_CACHE_DIRECTORY;
+use presentation::{VKSurface, VKSwapchain, surface_instance_extensions};
+use quirks::QuirkOverrides;
+use ray_tracing::{VKRayQueryShadows, VKRayTracer, VKSceneBvh};
+use render_graph::{Access, ImageHandle, RenderGraph, RenderPass};
+use render_texture::{RenderCamera, RenderTextureId, VKRenderTexture};
+use renderer2d::{Sprite, SpriteTextureId, VKRenderer2D};
+use retro::{RetroSettings, VKRetroPass};
+use scaling::{InternalResolution, VKInternalTarget};
+use scene_buffer::VKSceneBuffer;
+use shader::{VKShader, VKShaderLoader, reload_shaders};
+use shader_inputs::ShaderInputs;
+use skinning::{InstanceAnimation, VKSkinning};
+use skybox::VKSkybox;
+use ssao::{AmbientOcclusionSettings, AmbientOcclusionStep, VKAmbientOcclusion};
+use std::borrow::Cow;
+use std::ffi::{CStr, CString, c_char};
+use std::path::PathBuf;
+use texture::VKTexture;
+use tonemap::VKTonemapPass;
+use uniform_ring::VKUniformRing;
+use upload::VKUploader;
+use vertex::VertexLayout;
+use winit::window::{Window, WindowId};
+
+use glam::{Mat4, Vec2, Vec3, Vec4};
 
 pub const ENGINE_MAJOR: &str = env!("CARGO_PKG_VERSION_MAJOR");
 pub const ENGINE_MINOR: &str = env!("CARGO_PKG_VERSION_MINOR");
 pub const ENGINE_PATCH: &str = env!("CARGO_PKG_VERSION_PATCH");
 
+// whole image with a single mip level and layer
+pub const COLOR_SUBRESOURCE_RANGE: vk::ImageSubresourceRange = vk::ImageSubresourceRange {
+    aspect_mask: vk::ImageAspectFlags::COLOR,
+    base_mip_level: 0,
+    level_count: 1,
+    base_array_layer: 0,
+    layer_count: 1,
+};
+
+pub const DEPTH_SUBRESOURCE_RANGE: vk::ImageSubresourceRange = vk::ImageSubresourceRange {
+    aspect_mask: vk::ImageAspectFlags::DEPTH,
+    base_mip_level: 0,
+    level_count: 1,
+    base_array_layer: 0,
+    layer_count: 1,
+};
+
+// every face of a cubemap with a single mip level
+pub const CUBE_SUBRESOURCE_RANGE: vk::ImageSubresourceRange = vk::ImageSubresourceRange {
+    aspect_mask: vk::ImageAspectFlags::COLOR,
+    base_mip_level: 0,
+    level_count: 1,
+    base_array_layer: 0,
+    layer_count: CUBE_FACES,
+};
+
+// layers in a cubemap image
+pub const CUBE_FACES: u32 = 6;
+
+// depth buffer format used by the swapchain, offscreen captures and the pipeline
+pub const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
+/// Colour format of the scene while it renders into an internal target, so light can go past 1
+/// until the tonemap pass resolves it into the swapchain format
+pub const HDR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// Cameras camera_ring holds per frame, the frame's own and up to one less render textures
+pub const MAX_FRAME_CAMERAS: u64 = 64;
+
+/// Depth attachments are cleared to the far plane of depth_convention
+pub fn depth_clear_value(depth_convention: DepthConvention) -> vk::ClearValue {
+    vk::ClearValue {
+        depth_stencil: vk::ClearDepthStencilValue {
+            depth: depth_convention.far_depth(),
+            stencil: 0,
+        },
+    }
+}
+
+/// Passes fragments at least as near as what is already in the depth buffer
+pub fn depth_compare_op(depth_convention: DepthConvention) -> vk::CompareOp {
+    match depth_convention {
+        DepthConvention::ReverseZ => vk::CompareOp::GREATER_OR_EQUAL,
+        DepthConvention::Forward => vk::CompareOp::LESS_OR_EQUAL,
+    }
+}
+
+// colours for engine debug labels
+pub const FRAME_LABEL_COLOR: LinearRgba = LinearRgba::rgb(0.5, 0.5, 0.5);
+pub const SCENE_LABEL_COLOR: LinearRgba = LinearRgba::rgb(0.1, 0.4, 1.0);
+pub const CAPTURE_LABEL_COLOR: LinearRgba = LinearRgba::rgb(1.0, 0.6, 0.1);
+
+/// Passes performance counters are measured over, see enable_performance_counters
+/// post covers bloom, the retro effects and scaling to the window, it only runs with an internal
+/// resolution
+pub const PERF_PASSES: [&str; 2] = ["Scene", "Post"];
+pub const PERF_SCENE_PASS: usize = 0;
+pub const PERF_POST_PASS: usize = 1;
+
+/// Options used when creating the vulkan instance
+/// validation and the debug messenger default to on in debug builds and off in release builds
+#[derive(Clone, Debug)]
+pub struct InstanceOptions {
+    pub validation: bool,
+    pub debug_messenger: bool,
+    pub message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    pub debug_labels: bool,
+    /// layers enabled when installed, missing ones are logged and skipped
+    pub optional_layers: Vec<CString>,
+    /// changes to the driver workarounds picked for the device, see quirks::QUIRK_RULES
+    pub quirk_overrides: QuirkOverrides,
+    /// where compiled pipelines are saved between runs, None keeps them in memory only
+    pub pipeline_cache_directory: Option<PathBuf>,
+    /// benchmark candidate devices the first time they're seen and pick by measured speed
+    /// scores are saved next to the pipeline caches, see benchmark::DeviceScores
+    pub device_benchmarks: bool,
+}
+
+impl Default for InstanceOptions {
+    fn default() -> Self {
+        Self {
+            validation: cfg!(debug_assertions),
+            debug_messenger: cfg!(debug_assertions),
+            message_severity: vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
+                | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                | vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
+            debug_labels: true,
+            optional_layers: Vec::new(),
+            quirk_overrides: QuirkOverrides::default(),
+            pipeline_cache_directory: Some(PathBuf::from(PIPELINE_CACHE_DIRECTORY)),
+            device_benchmarks: true,
+        }
+    }
+}
+
+impl InstanceOptions {
+    /// Enable VK_LAYER_KHRONOS_validation if it is installed
+    pub fn validation(mut self, validation: bool) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    /// Log messages from VK_EXT_debug_utils
+    pub fn debug_messenger(mut self, debug_messenger: bool) -> Self {
+        self.debug_messenger = debug_messenger;
+        self
+    }
+
+    /// Severities the debug messenger reports
+    pub fn message_severity(
+        mut self,
+        message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    ) -> Self {
+        self.message_severity = message_severity;
+        self
+    }
+
+    /// Label passes in command buffers for graphics debuggers, cheap enough to leave on
+    pub fn debug_labels(mut self, debug_labels: bool) -> Self {
+        self.debug_labels = debug_labels;
+        self
+    }
+
+    /// Enable an instance layer if it is installed, eg API_DUMP_LAYER or RENDERDOC_CAPTURE_LAYER
+    /// see available_instance_layers for what is installed
+    pub fn optional_layer(mut self, layer: &CStr) -> Self {
+        if !self
+            .optional_layers
+            .iter()
+            .any(|name| name.as_c_str() == layer)
+        {
+            self.optional_layers.push(layer.to_owned());
+        }
+        self
+    }
+
+    /// Force driver workarounds on or off regardless of the device, see QuirkOverrides::parse
+    pub fn quirk_overrides(mut self, quirk_overrides: QuirkOverrides) -> Self {
+        self.quirk_overrides = quirk_overrides;
+        self
+    }
+
+    /// Directory the per device pipeline cache is loaded from and saved to on shutdown
+    pub fn pipeline_cache_directory(mut self, directory: Option<PathBuf>) -> Self {
+        self.pipeline_cache_directory = directory;
+        self
+    }
+
+    /// Pick devices by measured speed instead of only by their properties
+    /// without a pipeline cache directory the benchmarks run on every launch
+    pub fn device_benchmarks(mut self, device_benchmarks: bool) -> Self {
+        self.device_benchmarks = device_benchmarks;
+        self
+    }
+}
+
+/// Watched for changes when RendererOptions::hot_reload_shaders is set
+pub const SHADER_DIRECTORY: &str = "shaders";
+
+/// Options used when creating the renderer
+#[derive(Clone, Copy, Debug)]
+pub struct RendererOptions {
+    pub frames_in_flight: u32,
+    pub msaa_samples: vk::SampleCountFlags,
+    pub reuse_command_buffers: bool,
+    pub recording_threads: u32,
+    pub hot_reload_shaders: bool,
+    pub depth_convention: DepthConvention,
+    pub max_instances: usize,
+    pub max_joints: usize,
+}
+
+impl Default for RendererOptions {
+    fn default() -> Self {
+        Self {
+            frames_in_flight: 2,
+            msaa_samples: vk::SampleCountFlags::TYPE_4,
+            reuse_command_buffers: false,
+            recording_threads: 1,
+            hot_reload_shaders: cfg!(debug_assertions),
+            depth_convention: DepthConvention::default(),
+            max_instances: 16384,
+            max_joints: 16384,
+        }
+    }
+}
+
+impl RendererOptions {
+    /// Frames the cpu can record ahead of the gpu, clamped to the swapchain image count
+    pub fn frames_in_flight(mut self, frames_in_flight: u32) -> Self {
+        self.frames_in_flight = frames_in_flight;
+        self
+    }
+
+    /// Desired msaa sample count, lowered to the closest count the device supports
+    /// TYPE_1 disables msaa
+    pub fn msaa_samples(mut self, msaa_samples: vk::SampleCountFlags) -> Self {
+        self.msaa_samples = msaa_samples;
+        self
+    }
+
+    /// Submit last frame's commands again when nothing drawn has changed instead of re-recording them
+    /// pays off for static scenes with a still camera, see VKCommandCache
+    pub fn reuse_command_buffers(mut self, reuse_command_buffers: bool) -> Self {
+        self.reuse_command_buffers = reuse_command_buffers;
+        self
+    }
+
+    /// Threads scene draws are recorded on, 1 records everything into the primary buffer
+    /// ignored with reuse_command_buffers, reused frames would execute secondaries already reset
+    pub fn recording_threads(mut self, recording_threads: u32) -> Self {
+        self.recording_threads = recording_threads;
+        self
+    }
+
+    /// Watch the shaders directory and rebuild pipelines when their shaders change
+    /// on by default in debug builds, see VKRenderer::reload_changed_shaders
+    pub fn hot_reload_shaders(mut self, hot_reload_shaders: bool) -> Self {
+        self.hot_reload_shaders = hot_reload_shaders;
+        self
+    }
+
+    /// Which way depth runs for every pass and culler, reverse z unless something
+    /// like an external depth reader expects 0 at the near plane
+    pub fn depth_convention(mut self, depth_convention: DepthConvention) -> Self {
+        self.depth_convention = depth_convention;
+        self
+    }
+
+    /// Instances the scene buffer has room for, any past it aren't drawn
+    pub fn max_instances(mut self, max_instances: usize) -> Self {
+        self.max_instances = max_instances;
+        self
+    }
+
+    /// Skin matrices every pose of a frame shares, poses past it are drawn at rest
+    pub fn max_joints(mut self, max_joints: usize) -> Self {
+        self.max_joints = max_joints;
+        self
+    }
+}
+
 pub struct VKInstance {
+    pub debug_messenger: Option<VKDebugMessenger>,
+    /// VK_EXT_debug_utils was enabled, needed for debug labels
+    pub debug_utils: bool,
+    /// applied to the quirks of the device created from this instance
+    pub quirk_overrides: QuirkOverrides,
+    /// handed to the device created from this instance, see VKPipelineCache
+    pub pipeline_cache_directory: Option<PathBuf>,
+    /// from InstanceOptions::device_benchmarks
+    pub device_benchmarks: bool,
     pub instance: Instance,
     pub entry: Entry,
 }
@@ -35,10 +397,20 @@ impl VKInstance {
     pub fn new(
         game_info: &GameInfo,
         extension_names: Option<&[*const c_char]>,
+        options: &InstanceOptions,
     ) -> Result<Self, Box<dyn error::Error>> {
         // Load Vulkan Library
         let entry = unsafe { Entry::load()? };
+        Self::with_entry(entry, game_info, extension_names, options)
+    }
 
+    /// Like new but with an already loaded library, lets extensions be checked before creation
+    pub fn with_entry(
+        entry: Entry,
+        game_info: &GameInfo,
+        extension_names: Option<&[*const c_char]>,
+        options: &InstanceOptions,
+    ) -> Result<Self, Box<dyn error::Error>> {
         let engine_version = vk::make_api_version(
             0,
             ENGINE_MAJOR.parse()?,
@@ -58,25 +430,105 @@ impl VKInstance {
             .engine_name(c"Alcor")
             .engine_version(engine_version);
 
-        let extension_names: &[*const c_char] = if let Some(ext_names) = extension_names {
-            ext_names
+        let mut extension_names: Vec<*const c_char> = extension_names.unwrap_or_default().to_vec();
+
+        // missing debug layers/extensions are not fatal, we just run without them
+        let mut layer_names: Vec<*const c_char> = Vec::new();
+        if options.validation {
+            if instance_layer_available(&entry, VALIDATION_LAYER) {
+                layer_names.push(VALIDATION_LAYER.as_ptr());
+            } else {
+                warn!("Validation Layer Requested but not Found");
+            }
+        }
+
+        let available_layers = available_instance_layers(&entry);
+        for layer in &options.optional_layers {
+            let validation = options.validation && layer.as_c_str() == VALIDATION_LAYER;
+            if validation || layer_names.contains(&layer.as_ptr()) {
+                continue;
+            }
+            if available_layers
+                .iter()
+                .any(|available| available.name == *layer)
+            {
+                info!("Enabling Optional Layer {}", layer.to_string_lossy());
+                layer_names.push(layer.as_ptr());
+            } else {
+                warn!(
+                    "Optional Layer {} Requested but not Found",
+                    layer.to_string_lossy()
+                );
+            }
+        }
+
+        let debug_utils = (options.debug_messenger || options.debug_labels)
+            && instance_extension_available(&entry, ash::ext::debug_utils::NAME);
+        if debug_utils {
+            extension_names.push(ash::ext::debug_utils::NAME.as_ptr());
+        } else if options.debug_messenger {
+            warn!("Debug Messenger Requested but VK_EXT_debug_utils not Found");
+        }
+        let debug_messenger = debug_utils && options.debug_messenger;
+
+        crash_report::update_context(|context| {
+            context.instance_extensions = extension_names
+                .iter()
+                .map(|name| {
+                    unsafe { CStr::from_ptr(*name) }
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect();
+        });
+
+        let instance = Self::create_instance(
+            &entry,
+            &app_info,
+            &extension_names,
+            &layer_names,
+            debug_messenger.then_some(options.message_severity),
+        )?;
+
+        let debug_messenger = if debug_messenger {
+            Some(VKDebugMessenger::new(
+                &entry,
+                &instance,
+                options.message_severity,
+            )?)
         } else {
-            &[] as &[*const c_char]
+            None
         };
 
-        let instance = Self::create_instance(&entry, &app_info, extension_names)?;
-
-        Ok(Self { entry, instance })
+        Ok(Self {
+            entry,
+            instance,
+            debug_messenger,
+            debug_utils,
+            quirk_overrides: options.quirk_overrides,
+            pipeline_cache_directory: options.pipeline_cache_directory.clone(),
+            device_benchmarks: options.device_benchmarks,
+        })
     }
 
     fn create_instance(
         entry: &Entry,
         app_info: &vk::ApplicationInfo,
         extension_names: &[*const c_char],
+        layer_names: &[*const c_char],
+        message_severity: Option<vk::DebugUtilsMessageSeverityFlagsEXT>,
     ) -> Result<Instance, Box<dyn error::Error>> {
-        let create_info = vk::InstanceCreateInfo::default()
+        let mut create_info = vk::InstanceCreateInfo::default()
             .application_info(app_info)
-            .enabled_extension_names(extension_names);
+            .enabled_extension_names(extension_names)
+            .enabled_layer_names(layer_names);
+
+        // messenger chained onto instance creation reports issues in create/destroy instance
+        let mut messenger_info = message_severity.map(VKDebugMessenger::create_info);
+        if let Some(messenger_info) = messenger_info.as_mut() {
+            create_info = create_info.push_next(messenger_info);
+        }
+
         let instance = unsafe { entry.create_instance(&create_info, None)? };
 
         Ok(instance)
@@ -87,6 +539,10 @@ impl VKInstance {
     /// Read VK Docs For Destruction Order
     pub unsafe fn destroy(&mut self) {
         unsafe {
+            // messenger is a child of the instance
+            if let Some(debug_messenger) = self.debug_messenger.as_mut() {
+                debug_messenger.destroy();
+            }
             self.instance.destroy_instance(None);
         }
     }
@@ -101,18 +557,46 @@ pub struct VKContext {
 }
 
 impl VKContext {
-    pub fn new(game_info: &GameInfo, window: &Window) -> Result<Self, Box<dyn error::Error>> {
-        let vk_instance_ext = display_vk_ext(window)?;
-        let vulkan_instance = VKInstance::new(game_info, Some(vk_instance_ext))?;
+    pub fn new(
+        game_info: &GameInfo,
+        window: &Window,
+        instance_options: &InstanceOptions,
+        transparent: bool,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        Self::with_allocator(
+            game_info,
+            window,
+            instance_options,
+            transparent,
+            GpuAllocator::create,
+        )
+    }
+
+    /// Like new but device memory comes from the allocator create_allocator builds
+    /// see VKAllocator
+    pub fn with_allocator(
+        game_info: &GameInfo,
+        window: &Window,
+        instance_options: &InstanceOptions,
+        transparent: bool,
+        create_allocator: AllocatorFactory,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let entry = unsafe { Entry::load()? };
+        let vk_instance_ext = display_vk_ext(&entry, window)?;
+        let vulkan_instance =
+            VKInstance::with_entry(entry, game_info, Some(&vk_instance_ext), instance_options)?;
         let vulkan_surface = VKSurface::new(&vulkan_instance, window)?;
-        let mut vulkan_device = VKDevice::new(&vulkan_instance, &vulkan_surface)?;
+        let mut vulkan_device =
+            VKDevice::with_allocator(&vulkan_instance, &vulkan_surface, create_allocator)?;
 
         let vulkan_swapchain = VKSwapchain::new(
             &vulkan_instance,
             &mut vulkan_device,
             &vulkan_surface,
-            &window,
+            window,
             None,
+            vk::SampleCountFlags::TYPE_1,
+            transparent,
         )?;
 
         Ok(Self {
@@ -123,6 +607,16 @@ impl VKContext {
         })
     }
 
+    /// The window the context was created for, as VKPresent presents to it
+    pub fn present_target(&mut self) -> PresentTarget<'_> {
+        PresentTarget {
+            instance: &self.vulkan_instance,
+            device: &mut self.vulkan_device,
+            surface: &self.vulkan_surface,
+            swapchain: &mut self.vulkan_swapchain,
+        }
+    }
+
     /// # Safety
     /// Vulkan CTX should be destroyed after all of your vk objects
     /// Read VK Docs For Destruction Order
@@ -136,45 +630,260 @@ impl VKContext {
     }
 }
 
-pub fn display_vk_ext(window: &Window) -> Result<&'static [*const c_char], Box<dyn error::Error>> {
-    let display_handle = window.display_handle()?;
+/// Instance extensions window's surface needs, falls back to other surface extensions
+/// for the same display when the preferred one is missing, see choose_surface_extension
+pub fn display_vk_ext(
+    entry: &Entry,
+    window: &Window,
+) -> Result<[*const c_char; 2], Box<dyn error::Error>> {
+    Ok(surface_instance_extensions(entry, window)?.map(CStr::as_ptr))
+}
+
+/// Colour and depth attachments the scene gets rendered into
+/// can point at a swapchain image or an offscreen image
+#[derive(Copy, Clone, Debug)]
+pub struct RenderTarget {
+    pub image: vk::Image,
+    pub image_view: vk::ImageView,
+    pub depth_image: vk::Image,
+    pub depth_image_view: vk::ImageView,
+    /// multisampled colour image resolved into image, null when samples is TYPE_1
+    pub msaa_image: vk::Image,
+    pub msaa_image_view: vk::ImageView,
+    /// sample count of the depth and msaa images
+    pub samples: vk::SampleCountFlags,
+    pub extent: vk::Extent2D,
+    /// rotation the presentation engine applies when displaying the image
+    pub pre_transform: vk::SurfaceTransformFlagsKHR,
+}
+
+impl RenderTarget {
+    pub fn from_swapchain(vk_swapchain: &VKSwapchain, img_index: u32) -> Self {
+        Self {
+            image: vk_swapchain.images[img_index as usize],
+            image_view: vk_swapchain.image_views[img_index as usize],
+            depth_image: vk_swapchain.depth_image.image,
+            depth_image_view: vk_swapchain.depth_image.view,
+            msaa_image: vk_swapchain.msaa_image.image,
+            msaa_image_view: vk_swapchain.msaa_image.view,
+            samples: vk_swapchain.samples,
+            extent: vk_swapchain.image_extent,
+            pre_transform: vk_swapchain.pre_transform,
+        }
+    }
+
+    /// Extent as seen by the user, width and height are swapped for 90 and 270 degree rotations
+    pub fn display_extent(&self) -> vk::Extent2D {
+        if is_quarter_rotation(self.pre_transform) {
+            vk::Extent2D {
+                width: self.extent.height,
+                height: self.extent.width,
+            }
+        } else {
+            self.extent
+        }
+    }
+}
+
+/// True if transform rotates by 90 or 270 degrees
+pub fn is_quarter_rotation(transform: vk::SurfaceTransformFlagsKHR) -> bool {
+    transform.intersects(
+        vk::SurfaceTransformFlagsKHR::ROTATE_90
+            | vk::SurfaceTransformFlagsKHR::ROTATE_270
+            | vk::SurfaceTransformFlagsKHR::HORIZONTAL_MIRROR_ROTATE_90
+            | vk::SurfaceTransformFlagsKHR::HORIZONTAL_MIRROR_ROTATE_270,
+    )
+}
+
+/// Clip space rotation undoing the presentation engine's rotation
+/// so the image appears upright on rotated displays
+pub fn pre_rotation(transform: vk::SurfaceTransformFlagsKHR) -> Mat4 {
+    let angle: f32 = if transform.contains(vk::SurfaceTransformFlagsKHR::ROTATE_90) {
+        90.0
+    } else if transform.contains(vk::SurfaceTransformFlagsKHR::ROTATE_180) {
+        180.0
+    } else if transform.contains(vk::SurfaceTransformFlagsKHR::ROTATE_270) {
+        270.0
+    } else {
+        0.0
+    };
+    Mat4::from_rotation_z(angle.to_radians())
+}
+
+/// Records a single use command buffer with record and submits it to the graphics queue
+/// blocks until the queue is idle, only use for uploads and other one off work
+pub fn submit_one_time<F>(
+    vk_device: &VKDevice,
+    vk_command_pool: vk::CommandPool,
+    record: F,
+) -> Result<(), vk::Result>
+where
+    F: FnOnce(vk::CommandBuffer),
+{
+    let buff_info = vk::CommandBufferAllocateInfo::default()
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_pool(vk_command_pool)
+        .command_buffer_count(1);
+
+    let cmd_buffer = unsafe { vk_device.device.allocate_command_buffers(&buff_info)?[0] };
+
+    let begin_info =
+        vk::CommandBufferBeginInfo::default().flags(CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+    let cmd_buffer_info = [vk::CommandBufferSubmitInfo::default().command_buffer(cmd_buffer)];
+    let submit_info = vk::SubmitInfo2::default().command_buffer_infos(&cmd_buffer_info);
+
+    let result = unsafe {
+        vk_device
+            .device
+            .begin_command_buffer(cmd_buffer, &begin_info)
+            .and_then(|_| {
+                record(cmd_buffer);
+                vk_device.device.end_command_buffer(cmd_buffer)
+            })
+            .and_then(|_| {
+                vk_device.device.queue_submit2(
+                    vk_device.graphics_queue,
+                    &[submit_info],
+                    vk::Fence::null(),
+                )
+            })
+            // fence more flexible than queue wait idle
+            .and_then(|_| vk_device.device.queue_wait_idle(vk_device.graphics_queue))
+    };
+
+    // free single use command buffer, whether or not it made it to the queue
+    // a failed wait means the device is lost and nothing is pending anymore
+    unsafe {
+        vk_device
+            .device
+            .free_command_buffers(vk_command_pool, &[cmd_buffer]);
+    }
 
-    Ok(ash_window::enumerate_required_extensions(
-        display_handle.as_raw(),
-    )?)
+    result
 }
 
 pub struct VKRenderer<'a> {
     pub vulkan_ctx: VKContext,
     pub vulkan_shader_loader: VKShaderLoader<&'static str>,
     pub vulkan_present: VKPresent,
+    /// windows presented to beside the main one at their own cadence, see add_window
+    pub windows: HashMap<WindowId, VKWindowTarget>,
 
     pub vulkan_cmd_pool: vk::CommandPool,
     pub vulkan_cmd_buffs: Vec<vk::CommandBuffer>,
     pub vertex_shader: VKShader<'a>,
     pub fragment_shader: VKShader<'a>,
 
-    pub vertex_buffer: vk::Buffer,
-    pub vertex_allocation: vulkan::Allocation,
+    /// everything instances can draw, CUBE_MESH is always present
+    pub meshes: Vec<VKMesh>,
+    /// mesh buffers are copied on the transfer queue, frames wait on the copies instead of the cpu
+    pub uploader: VKUploader,
+    /// hashes and references of the asset files loaded through the renderer
+    pub assets: AssetGraph,
 
-    pub pipeline: vk::Pipeline,
     pub pipeline_layout: vk::PipelineLayout,
+    /// push constant ranges declared on pipeline_layout
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
 
     pub descriptor_layout: vk::DescriptorSetLayout,
-
-    pub vertices_len: u32,
+    /// every material's descriptor sets come from here, it owns descriptor_layout
+    pub descriptor_allocator: VKDescriptorAllocator,
+
+    /// every camera a frame draws from, its own first then the render textures', bound with
+    /// the offset it was pushed at
+    pub camera_ring: VKUniformRing,
+    /// shader_inputs for each frame in flight, stays mapped and is rewritten every frame
+    pub shader_input_buffers: Vec<VKBuffer>,
+    /// lighting for each frame in flight
+    pub light_buffers: Vec<VKBuffer>,
+
+    /// bound for materials without their own albedo texture
+    pub texture: VKTexture,
+    /// bound for materials without their own normal map
+    pub flat_normal_texture: VKTexture,
+    /// pipeline variants of the uber-shader, DEFAULT_MATERIAL is always present
+    pub materials: Vec<VKMaterial>,
+    /// a pipeline per variant in use, shared by materials
+    pub pipelines: HashMap<PipelineVariant, vk::Pipeline>,
+
+    /// copies of the cube to draw each frame
+    pub instances: Vec<MeshInstance>,
+    /// transform and tint of each instance, in the same order, only changes are uploaded
+    pub scene_objects: VKSceneBuffer<ObjectData>,
+    /// skins and baked clips animated meshes are posed with, see add_mesh_skin
+    pub skinning: VKSkinning<'a>,
+    /// quads drawn over the 3d scene each frame by renderer2d
+    pub sprites: Vec<Sprite>,
+    pub renderer2d: VKRenderer2D<'a>,
+    /// renderer2d texture of the font shader errors are drawn with in dev builds
+    pub debug_font: DebugFont,
+    /// lines drawn over the scene next frame, cleared once it is rendered
+    pub debug_draw: DebugDraw,
+    pub debug_renderer: VKDebugDraw<'a>,
+    /// instances drawn and culled by the last frame
+    pub draw_stats: DrawStats,
+    /// recorded frames kept for reuse, None unless RendererOptions::reuse_command_buffers is set
+    pub command_cache: Option<VKCommandCache>,
+    /// splits scene draws across threads, None unless RendererOptions::recording_threads is above 1
+    pub parallel_recorder: Option<VKParallelRecorder>,
+    // bumped by anything that invalidates recorded frames without showing up in FrameInputs
+    resource_generation: u64,
+    /// hardware counters per pass, None until enable_performance_counters finds some
+    pub perf_queries: Option<VKPerfQueries>,
+    /// counters of the last frame that finished on the gpu, one entry per PERF_PASSES pass that ran
+    pub pass_counters: Vec<PassCounters>,
+    /// camera the scene is rendered from
+    pub camera: Camera,
+    /// floats for custom shader effects, uploaded with every frame
+    pub shader_inputs: ShaderInputs,
+    /// lights lit materials are shaded with, uploaded with every frame
+    pub lighting: Lighting,
+    /// drawn where no geometry is, see set_skybox
+    pub skybox: VKSkybox<'a>,
+    /// alpha below 1 shows through transparent windows, ignored by opaque ones
+    pub clear_color: LinearRgba,
+    /// scene renders here instead of the swapchain when set, see set_internal_resolution
+    pub internal_target: Option<VKInternalTarget>,
+    /// cameras drawn into textures materials sample, see add_render_texture
+    pub render_textures: Vec<VKRenderTexture>,
+    /// darkens creases and corners of the internal target, see set_ambient_occlusion
+    pub ambient_occlusion: VKAmbientOcclusion<'a>,
+    /// glow around the brightest parts of the internal target, see set_bloom
+    pub bloom: VKBloomPass<'a>,
+    /// resolves the HDR internal target into the swapchain format for retro and the window
+    pub tonemap: VKTonemapPass<'a>,
+    /// palette and dithering between the tonemapped scene and the window, see set_retro_effects
+    pub retro: VKRetroPass<'a>,
+    /// how the scene is drawn, see set_render_mode
+    pub render_mode: RenderMode,
+    /// None until the scene is first ray traced
+    pub ray_tracer: Option<VKRayTracer<'a>>,
+    /// shadow rays from lit materials' fragments, None while off, see set_ray_query_shadows
+    pub ray_query_shadows: Option<VKRayQueryShadows<'a>>,
+    /// BVHs of the instances, None until ray tracing or ray query shadows first need them
+    pub scene_bvh: Option<VKSceneBvh>,
+    /// culls the scene on the gpu and draws it in batches, None while off, see set_gpu_culling
+    pub gpu_culling: Option<VKGpuCulling<'a>>,
+    // pipelines are built for it, so it can't change after creation
+    depth_convention: DepthConvention,
 
     pub created_time: std::time::Instant,
+
+    /// None without VK_EXT_debug_utils, labelling is skipped
+    pub debug_labels: Option<VKDebugLabels>,
 }
 
 impl VKRenderer<'_> {
     pub fn new(
         mut vulkan_ctx: VKContext,
-        frames_in_flight: u32,
+        options: &RendererOptions,
     ) -> Result<Self, Box<dyn error::Error>> {
+        let frames_in_flight = options.frames_in_flight;
+
         let vulkan_present = unsafe {
             VKPresent::default()
-                .max_frames(frames_in_flight, &vulkan_ctx)
+                .max_frames(frames_in_flight, vulkan_ctx.present_target())
                 .unwrap()
         };
 
@@ -205,6 +914,11 @@ impl VKRenderer<'_> {
         };
 
         let mut vulkan_shader_loader = VKShaderLoader::default();
+        if options.hot_reload_shaders
+            && let Err(error) = vulkan_shader_loader.watch(SHADER_DIRECTORY)
+        {
+            warn!("Shader Hot Reloading Unavailable: {error}");
+        }
         let vertex_shader = VKShader::new(
             &vulkan_ctx.vulkan_device,
             "shaders/triangle.spv",
@@ -221,361 +935,2979 @@ impl VKRenderer<'_> {
             &mut vulkan_shader_loader,
         )?;
 
-        // our triangle to render
-        // static VERTICES: [Vertex; 3] = [
-        //     Vertex::new(Vec3::new(0.0, -0.5, 0.0), Vec3::new(1.0, 0.0, 0.0)),
-        //     Vertex::new(Vec3::new(0.5, 0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
-        //     Vertex::new(Vec3::new(-0.5, 0.5, 0.0), Vec3::new(0.0, 0.0, 1.0)),
-        // ];
-
-        static VERTICES: [Vertex; 36] = [
-            // FRONT FACE (Z = 0.5) - RED
-            Vertex::new(Vec3::new(-0.5, -0.5, 0.5), Vec3::new(1.0, 0.0, 0.0)),
-            Vertex::new(Vec3::new(0.5, -0.5, 0.5), Vec3::new(1.0, 0.0, 0.0)),
-            Vertex::new(Vec3::new(0.5, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0)),
-            Vertex::new(Vec3::new(0.5, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0)),
-            Vertex::new(Vec3::new(-0.5, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0)),
-            Vertex::new(Vec3::new(-0.5, -0.5, 0.5), Vec3::new(1.0, 0.0, 0.0)),
-            // BACK FACE (Z = -0.5) - GREEN
-            Vertex::new(Vec3::new(0.5, -0.5, -0.5), Vec3::new(0.0, 1.0, 0.0)),
-            Vertex::new(Vec3::new(-0.5, -0.5, -0.5), Vec3::new(0.0, 1.0, 0.0)),
-            Vertex::new(Vec3::new(-0.5, 0.5, -0.5), Vec3::new(0.0, 1.0, 0.0)),
-            Vertex::new(Vec3::new(-0.5, 0.5, -0.5), Vec3::new(0.0, 1.0, 0.0)),
-            Vertex::new(Vec3::new(0.5, 0.5, -0.5), Vec3::new(0.0, 1.0, 0.0)),
-            Vertex::new(Vec3::new(0.5, -0.5, -0.5), Vec3::new(0.0, 1.0, 0.0)),
-            // LEFT FACE (X = -0.5) - BLUE
-            Vertex::new(Vec3::new(-0.5, -0.5, -0.5), Vec3::new(0.0, 0.0, 1.0)),
-            Vertex::new(Vec3::new(-0.5, -0.5, 0.5), Vec3::new(0.0, 0.0, 1.0)),
-            Vertex::new(Vec3::new(-0.5, 0.5, 0.5), Vec3::new(0.0, 0.0, 1.0)),
-            Vertex::new(Vec3::new(-0.5, 0.5, 0.5), Vec3::new(0.0, 0.0, 1.0)),
-            Vertex::new(Vec3::new(-0.5, 0.5, -0.5), Vec3::new(0.0, 0.0, 1.0)),
-            Vertex::new(Vec3::new(-0.5, -0.5, -0.5), Vec3::new(0.0, 0.0, 1.0)),
-            // RIGHT FACE (X = 0.5) - YELLOW
-            Vertex::new(Vec3::new(0.5, -0.5, 0.5), Vec3::new(1.0, 1.0, 0.0)),
-            Vertex::new(Vec3::new(0.5, -0.5, -0.5), Vec3::new(1.0, 1.0, 0.0)),
-            Vertex::new(Vec3::new(0.5, 0.5, -0.5), Vec3::new(1.0, 1.0, 0.0)),
-            Vertex::new(Vec3::new(0.5, 0.5, -0.5), Vec3::new(1.0, 1.0, 0.0)),
-            Vertex::new(Vec3::new(0.5, 0.5, 0.5), Vec3::new(1.0, 1.0, 0.0)),
-            Vertex::new(Vec3::new(0.5, -0.5, 0.5), Vec3::new(1.0, 1.0, 0.0)),
-            // TOP FACE (Y = 0.5) - CYAN
-            Vertex::new(Vec3::new(-0.5, 0.5, 0.5), Vec3::new(0.0, 1.0, 1.0)),
-            Vertex::new(Vec3::new(0.5, 0.5, 0.5), Vec3::new(0.0, 1.0, 1.0)),
-            Vertex::new(Vec3::new(0.5, 0.5, -0.5), Vec3::new(0.0, 1.0, 1.0)),
-            Vertex::new(Vec3::new(0.5, 0.5, -0.5), Vec3::new(0.0, 1.0, 1.0)),
-            Vertex::new(Vec3::new(-0.5, 0.5, -0.5), Vec3::new(0.0, 1.0, 1.0)),
-            Vertex::new(Vec3::new(-0.5, 0.5, 0.5), Vec3::new(0.0, 1.0, 1.0)),
-            // BOTTOM FACE (Y = -0.5) - MAGENTA
-            Vertex::new(Vec3::new(-0.5, -0.5, -0.5), Vec3::new(1.0, 0.0, 1.0)),
-            Vertex::new(Vec3::new(0.5, -0.5, -0.5), Vec3::new(1.0, 0.0, 1.0)),
-            Vertex::new(Vec3::new(0.5, -0.5, 0.5), Vec3::new(1.0, 0.0, 1.0)),
-            Vertex::new(Vec3::new(0.5, -0.5, 0.5), Vec3::new(1.0, 0.0, 1.0)),
-            Vertex::new(Vec3::new(-0.5, -0.5, 0.5), Vec3::new(1.0, 0.0, 1.0)),
-            Vertex::new(Vec3::new(-0.5, -0.5, -0.5), Vec3::new(1.0, 0.0, 1.0)),
-        ];
-        let vertices_len = VERTICES.len() as u32;
+        let mut uploader = VKUploader::new(&mut vulkan_ctx.vulkan_device)?;
+        let cube = VKMesh::new(&mut vulkan_ctx.vulkan_device, &mut uploader, &CUBE_VERTICES)?;
+
+        // per object data is in the scene buffer, only the bound material's parameters are pushed
+        let push_constant_ranges = vec![push_constant_range::<MaterialParams>(
+            vk::ShaderStageFlags::FRAGMENT,
+            0,
+        )];
+
+        let samples = vulkan_ctx
+            .vulkan_device
+            .supported_sample_count(options.msaa_samples);
+        if samples != options.msaa_samples {
+            warn!(
+                "MSAA {:?} Not Supported, Using {samples:?}",
+                options.msaa_samples
+            );
+        }
+        // nothing has rendered to the swapchain yet
+        unsafe {
+            vulkan_ctx
+                .vulkan_swapchain
+                .set_samples(&mut vulkan_ctx.vulkan_device, samples)?
+        };
 
-        let (vertex_buffer, vertex_allocation) =
-            create_vertex_buffer(&mut vulkan_ctx.vulkan_device, &vulkan_cmd_pool, &VERTICES)?;
+        let mut descriptor_allocator =
+            VKDescriptorAllocator::new(&DEFAULT_POOL_RATIOS, frames_in_flight as usize);
+        let (pipeline_layout, descriptor_layout) = create_pipeline_layout(
+            &vulkan_ctx.vulkan_device,
+            &mut descriptor_allocator,
+            &push_constant_ranges,
+        )?;
+
+        let skybox = VKSkybox::new(
+            &vulkan_ctx.vulkan_device,
+            &vulkan_ctx.vulkan_swapchain,
+            &mut vulkan_shader_loader,
+            options.depth_convention,
+        )?;
+
+        let ambient_occlusion = VKAmbientOcclusion::new(
+            &vulkan_ctx.vulkan_device,
+            &mut vulkan_shader_loader,
+            options.depth_convention,
+        )?;
+
+        let bloom = VKBloomPass::new(&vulkan_ctx.vulkan_device, &mut vulkan_shader_loader)?;
+
+        let tonemap = VKTonemapPass::new(
+            &vulkan_ctx.vulkan_device,
+            &vulkan_ctx.vulkan_swapchain,
+            &mut vulkan_shader_loader,
+        )?;
 
-        let (pipeline, pipeline_layout, descriptor_layout) = create_pipeline(
+        let retro = VKRetroPass::new(
             &vulkan_ctx.vulkan_device,
             &vulkan_ctx.vulkan_swapchain,
-            &vertex_shader.shader_info,
-            &fragment_shader.shader_info,
+            &mut vulkan_shader_loader,
+        )?;
+
+        let mut renderer2d = VKRenderer2D::new(
+            &mut vulkan_ctx.vulkan_device,
+            &vulkan_ctx.vulkan_swapchain,
+            &mut vulkan_shader_loader,
+            vulkan_cmd_pool,
+            frames_in_flight,
+        )?;
+
+        let (width, height, pixels) = DebugFont::atlas_pixels();
+        let debug_font_atlas = VKTexture::from_rgba8(
+            &mut vulkan_ctx.vulkan_device,
+            vulkan_cmd_pool,
+            width,
+            height,
+            &pixels,
+        )?;
+        let debug_font = DebugFont::new(
+            renderer2d.add_texture(&mut vulkan_ctx.vulkan_device, debug_font_atlas)?,
+        );
+
+        let debug_renderer = VKDebugDraw::new(
+            &mut vulkan_ctx.vulkan_device,
+            &vulkan_ctx.vulkan_swapchain,
+            &mut vulkan_shader_loader,
+            frames_in_flight,
+            options.depth_convention,
+        )?;
+
+        let texture =
+            VKTexture::checkerboard(&mut vulkan_ctx.vulkan_device, vulkan_cmd_pool, 256, 8)?;
+        let flat_normal_texture =
+            VKTexture::flat_normal_map(&mut vulkan_ctx.vulkan_device, vulkan_cmd_pool)?;
+
+        let camera_ring = VKUniformRing::new(
+            &mut vulkan_ctx.vulkan_device,
+            size_of::<CameraUniform>() as u64,
+            MAX_FRAME_CAMERAS,
+            frames_in_flight,
+        )?;
+        let mut shader_input_buffers = Vec::with_capacity(frames_in_flight as usize);
+        let mut light_buffers = Vec::with_capacity(frames_in_flight as usize);
+        for _ in 0..frames_in_flight {
+            shader_input_buffers.push(VKBuffer::new(
+                &mut vulkan_ctx.vulkan_device,
+                size_of::<ShaderInputs>() as u64,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                MemoryLocation::CpuToGpu,
+                "Shader Inputs",
+            )?);
+            light_buffers.push(VKBuffer::new(
+                &mut vulkan_ctx.vulkan_device,
+                size_of::<LightUniform>() as u64,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                MemoryLocation::CpuToGpu,
+                "Lights",
+            )?);
+        }
+
+        let scene_objects = VKSceneBuffer::new(
+            &mut vulkan_ctx.vulkan_device,
+            options.max_instances,
+            frames_in_flight,
+        )?;
+
+        let skinning = VKSkinning::new(
+            &mut vulkan_ctx.vulkan_device,
+            &mut vulkan_shader_loader,
+            frames_in_flight,
+            options.max_instances,
+            options.max_joints,
         )?;
 
+        let debug_labels = vulkan_ctx.vulkan_instance.debug_utils.then(|| {
+            VKDebugLabels::new(
+                &vulkan_ctx.vulkan_instance.instance,
+                &vulkan_ctx.vulkan_device.device,
+            )
+        });
+
+        let parallel_recorder = match options.recording_threads {
+            0 | 1 => None,
+            _ if options.reuse_command_buffers => {
+                warn!("Recording Threads Ignored: Command Buffers Are Reused");
+                None
+            }
+            threads => VKParallelRecorder::new(
+                &vulkan_ctx.vulkan_device,
+                frames_in_flight as usize,
+                threads as usize,
+            )
+            .inspect_err(|error| error!("Failed To Create Recording Threads: {error}"))
+            .ok(),
+        };
+
         let created_time = std::time::Instant::now();
 
-        Ok(Self {
+        crash_report::update_context(|context| {
+            context.allocator_stats = vulkan_ctx.vulkan_device.allocator_stats();
+        });
+
+        let mut renderer = Self {
             vulkan_ctx,
             vulkan_shader_loader,
             vulkan_present,
+            windows: HashMap::new(),
             vulkan_cmd_pool,
             vulkan_cmd_buffs,
             vertex_shader,
             fragment_shader,
 
-            vertex_buffer,
-            vertex_allocation,
+            meshes: vec![cube],
+            uploader,
+            assets: AssetGraph::new(),
 
-            pipeline,
             pipeline_layout,
+            push_constant_ranges,
 
             descriptor_layout,
-
-            vertices_len,
+            descriptor_allocator,
+
+            camera_ring,
+            shader_input_buffers,
+            light_buffers,
+
+            texture,
+            flat_normal_texture,
+            materials: Vec::new(),
+            pipelines: HashMap::new(),
+
+            instances: vec![MeshInstance::default()],
+            scene_objects,
+            skinning,
+            sprites: Vec::new(),
+            renderer2d,
+            debug_font,
+            debug_draw: DebugDraw::default(),
+            debug_renderer,
+            draw_stats: DrawStats::default(),
+            command_cache: options.reuse_command_buffers.then(VKCommandCache::default),
+            parallel_recorder,
+            resource_generation: 0,
+            perf_queries: None,
+            pass_counters: Vec::new(),
+            camera: Camera::perspective(100.0_f32.to_radians(), 0.1).orbit(
+                Vec3::new(0.0, 0.2, 0.0),
+                0.0,
+                -20.0_f32.to_radians(),
+                2.5,
+            ),
+            shader_inputs: ShaderInputs::default(),
+            lighting: Lighting::default(),
+            skybox,
+            clear_color: LinearRgba::rgb(0.74757, 0.02016, 0.253),
+            internal_target: None,
+            render_textures: Vec::new(),
+            ambient_occlusion,
+            bloom,
+            tonemap,
+            retro,
+            render_mode: RenderMode::default(),
+            ray_tracer: None,
+            ray_query_shadows: None,
+            gpu_culling: None,
+            scene_bvh: None,
+            depth_convention: options.depth_convention,
             created_time,
-        })
+            debug_labels,
+        };
+
+        // vertex colours over the checkerboard
+        let mut fallback = MaterialDesc::fallback().compile()?;
+        fallback.features |= MaterialFeatures::ALBEDO_TEXTURE;
+        renderer.add_compiled_material(fallback)?;
+
+        Ok(renderer)
     }
 
-    pub fn render(&mut self, window: &Window) {
-        let vk_ctx = &mut self.vulkan_ctx;
-        let vk_present = &mut self.vulkan_present;
+    /// Uploads a triangle list, instances draw it by setting their mesh
+    /// Example Use:
+    /// ```ignore
+    /// let triangle = renderer.add_mesh(&[
+    ///     Vertex::new(Vec3::new(0.0, -0.5, 0.0), Vec3::X, Vec2::new(0.5, 0.0)),
+    ///     Vertex::new(Vec3::new(-0.5, 0.5, 0.0), Vec3::Y, Vec2::new(0.0, 1.0)),
+    ///     Vertex::new(Vec3::new(0.5, 0.5, 0.0), Vec3::Z, Vec2::new(1.0, 1.0)),
+    /// ])?;
+    /// ```
+    pub fn add_mesh(&mut self, vertices: &[Vertex]) -> Result<MeshId, vk::Result> {
+        let mesh = VKMesh::new(
+            &mut self.vulkan_ctx.vulkan_device,
+            &mut self.uploader,
+            vertices,
+        )?;
+        self.meshes.push(mesh);
+        self.invalidate_command_buffers();
+        Ok(self.meshes.len() - 1)
+    }
 
-        let render_info = match vk_present.aquire_img(vk_ctx, window) {
-            Ok(render_info) => render_info,
-            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                warn!("Swap Out of Date");
-                return;
-            }
-            Err(err) => {
-                error!("Error aquiring fame from swapchain: {}", err);
-                return;
-            }
-        };
+    /// Like add_mesh but triangles are made of indices into vertices
+    pub fn add_indexed_mesh(
+        &mut self,
+        vertices: &[Vertex],
+        indices: &[u32],
+    ) -> Result<MeshId, vk::Result> {
+        let mesh = VKMesh::new_indexed(
+            &mut self.vulkan_ctx.vulkan_device,
+            &mut self.uploader,
+            vertices,
+            indices,
+        )?;
+        self.meshes.push(mesh);
+        self.invalidate_command_buffers();
+        Ok(self.meshes.len() - 1)
+    }
 
-        let vk_device = &vk_ctx.vulkan_device;
+    /// Adds every LOD of a cached mesh, returns their ids from most to least detailed
+    /// ready to make a LodGroup from
+    pub fn add_compressed_mesh(
+        &mut self,
+        mesh: &CompressedMesh,
+    ) -> Result<Vec<MeshId>, vk::Result> {
+        let vertices = mesh.unpack_vertices();
+        mesh.lods
+            .iter()
+            .map(|indices| self.add_indexed_mesh(&vertices, indices))
+            .collect()
+    }
 
+    /// Lets instances of mesh be skinned, skins has a VertexSkin for each of its vertices
+    /// weighted to joints of a skeleton with joint_count joints
+    /// bounds should cover every pose the mesh is drawn in, it's culled with them
+    /// Example Use:
+    /// ```ignore
+    /// let soldier = renderer.add_indexed_mesh(&vertices, &indices)?;
+    /// renderer.add_mesh_skin(soldier, &skins, skeleton.len(), reach)?;
+    /// renderer.skinning.poses = vec![skeleton.skin_matrices(&walk.sample(&skeleton, elapsed))];
+    /// renderer.instances[0].animation = Some(InstanceAnimation::Skinned { pose: 0 });
+    /// ```
+    /// Waits for the gpu to go idle.
+    pub fn add_mesh_skin(
+        &mut self,
+        mesh: MeshId,
+        skins: &[VertexSkin],
+        joint_count: usize,
+        bounds: Aabb,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let Some(vertex_count) = self.meshes.get(mesh).map(|mesh| mesh.vertex_count) else {
+            return Err(format!("No Mesh {mesh}").into());
+        };
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
         unsafe {
-            Self::record_cmd_buffer(
-                self.vulkan_cmd_buffs[render_info.frame_in_flight as usize],
+            vk_device.device.device_wait_idle()?;
+            self.skinning.add_skin(
                 vk_device,
-                vk_ctx.vulkan_swapchain.images[render_info.img_aquired_index as usize],
-                vk_ctx.vulkan_swapchain.image_views[render_info.img_aquired_index as usize],
-                vk_ctx.vulkan_swapchain.depth_image,
-                vk_ctx.vulkan_swapchain.depth_image_view,
-                vk_ctx.vulkan_swapchain.image_extent,
-                self.pipeline,
-                self.pipeline_layout,
-                self.vertex_buffer,
-                self.vertices_len,
-                self.created_time,
-            )
-            .unwrap();
+                &mut self.uploader,
+                mesh,
+                vertex_count as usize,
+                skins,
+                joint_count,
+            )?;
         }
+        let mesh = &mut self.meshes[mesh];
+        mesh.bounds = mesh.bounds.union(&bounds);
+        self.animation_data_changed()
+    }
 
-        let command_buffer_infos = &[vk::CommandBufferSubmitInfo::default()
-            .command_buffer(self.vulkan_cmd_buffs[render_info.frame_in_flight as usize])];
-
-        let wait_semaphore_infos = &[vk::SemaphoreSubmitInfo::default()
-            .semaphore(render_info.img_aquired_gpu)
-            .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)];
-
-        let signal_semaphore_infos = &[vk::SemaphoreSubmitInfo::default()
-            .semaphore(render_info.done_rendering_gpu)
-            .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)];
-
-        let submits = [vk::SubmitInfo2::default()
-            .wait_semaphore_infos(wait_semaphore_infos)
-            .signal_semaphore_infos(signal_semaphore_infos)
-            .command_buffer_infos(command_buffer_infos)];
+    /// Adds a clip baked for mesh, instances play it with InstanceAnimation::Baked and the
+    /// returned index, the mesh's bounds grow to cover every frame
+    /// Example Use:
+    /// ```ignore
+    /// let walk = VertexAnimation::bake(&vertices, &skins, &skeleton, &walk_clip, 30.0)?;
+    /// let walk = renderer.add_vertex_animation(soldier, &walk)?;
+    /// for (instance, offset) in renderer.instances.iter_mut().zip(offsets) {
+    ///     instance.animation = Some(InstanceAnimation::Baked { clip: walk, time: elapsed + offset });
+    /// }
+    /// ```
+    /// Waits for the gpu to go idle.
+    pub fn add_vertex_animation(
+        &mut self,
+        mesh: MeshId,
+        animation: &VertexAnimation,
+    ) -> Result<usize, Box<dyn error::Error>> {
+        let Some(vertex_count) = self.meshes.get(mesh).map(|mesh| mesh.vertex_count) else {
+            return Err(format!("No Mesh {mesh}").into());
+        };
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        let clip = unsafe {
+            vk_device.device.device_wait_idle()?;
+            self.skinning.add_clip(
+                vk_device,
+                &mut self.uploader,
+                mesh,
+                vertex_count as usize,
+                animation,
+            )?
+        };
+        let mesh = &mut self.meshes[mesh];
+        mesh.bounds = mesh.bounds.union(&animation.bounds);
+        self.animation_data_changed()?;
+        Ok(clip)
+    }
 
+    // points every material's set at the new animation data and builds the animated pipelines
+    // the first time there's something to draw with them, the gpu must be idle
+    fn animation_data_changed(&mut self) -> Result<(), Box<dyn error::Error>> {
+        let buffer_infos = [self.skinning.data_buffer.descriptor_buffer_info()];
+        let writes: Vec<_> = self
+            .materials
+            .iter()
+            .flat_map(|material| &material.descriptor_sets)
+            .map(|&descriptor_set| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(descriptor_set)
+                    .dst_binding(9)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(&buffer_infos)
+            })
+            .collect();
         unsafe {
-            vk_device
+            self.vulkan_ctx
+                .vulkan_device
                 .device
-                .queue_submit2(
-                    vk_device.graphics_queue,
-                    &submits,
-                    render_info.done_rendering_cpu,
-                )
-                .unwrap()
+                .update_descriptor_sets(&writes, &[])
         };
 
-        // required for wayland
-        window.pre_present_notify();
-
-        match vk_present.present_frame(vk_ctx, window) {
-            Ok(_) => (),
-            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                warn!("Swap Out of Date");
-            }
-            Err(err) => {
-                error!("Error Presenting Frame: {}", err)
+        for index in 0..self.materials.len() {
+            if self.materials[index].animated_pipeline == vk::Pipeline::null() {
+                self.materials[index].animated_pipeline = self.material_pipelines(index)?.1;
             }
         }
+        self.invalidate_command_buffers();
+        Ok(())
     }
 
-    unsafe fn record_cmd_buffer(
-        cmd_buffer: vk::CommandBuffer,
-        vk_device: &VKDevice,
-        image: vk::Image,
-        image_view: vk::ImageView,
-        depth_image: vk::Image,
-        depth_image_view: vk::ImageView,
-        render_area: vk::Extent2D,
-        pipeline: vk::Pipeline,
-        pipeline_layout: vk::PipelineLayout,
-        vertex_buffer: vk::Buffer,
-        vertices_len: u32,
-        created_time: std::time::Instant,
-    ) -> Result<(), ash::vk::Result> {
-        let begin_info = vk::CommandBufferBeginInfo::default();
-
-        let sub_resource_range = vk::ImageSubresourceRange::default()
-            .aspect_mask(vk::ImageAspectFlags::COLOR)
-            .level_count(1)
-            .layer_count(1);
-
-        let sub_resource_range_depth = vk::ImageSubresourceRange::default()
-            .aspect_mask(vk::ImageAspectFlags::DEPTH)
-            .level_count(1)
-            .layer_count(1);
-        // memory barriar info for rendering
-        // we use memory barriars to transistion the image into the correct layout
-        // this is for transitioning the layout to the required layout for screen clear cmd
-        // also transitions depth image to correct layout
-        let image_memory_barriers = [
-            vk::ImageMemoryBarrier2::default()
-                .old_layout(vk::ImageLayout::UNDEFINED)
-                .new_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-                .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
-                .dst_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
-                .dst_access_mask(
-                    vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
-                        | vk::AccessFlags2::COLOR_ATTACHMENT_READ,
-                )
-                .image(image)
-                .subresource_range(sub_resource_range),
-            vk::ImageMemoryBarrier2::default()
-                .old_layout(vk::ImageLayout::UNDEFINED)
-                .new_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
-                .src_stage_mask(
-                    vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
-                        | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+    /// Rebuilds the pipelines of shaders changed on disk, called at the start of every frame
+    /// does nothing unless RendererOptions::hot_reload_shaders started the watcher
+    /// failures are logged and the old pipelines kept, so a broken shader doesn't stop the game
+    /// compute shaders and the ray tracer are only reloaded on restart
+    pub fn reload_changed_shaders(&mut self) {
+        let changed = self.vulkan_shader_loader.changed_shaders();
+        if changed.is_empty() {
+            return;
+        }
+        info!("Reloading Shaders: {}", changed.join(", "));
+
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        let vk_swapchain = &self.vulkan_ctx.vulkan_swapchain;
+        let loader = &mut self.vulkan_shader_loader;
+        if let Err(error) = unsafe { vk_device.device.device_wait_idle() } {
+            error!("Failed To Reload Shaders: {error}");
+            return;
+        }
+        let results = unsafe {
+            [
+                (
+                    "Skybox",
+                    self.skybox
+                        .reload_shaders(vk_device, vk_swapchain, loader, &changed),
+                ),
+                (
+                    "Debug Line",
+                    self.debug_renderer
+                        .reload_shaders(vk_device, vk_swapchain, loader, &changed),
+                ),
+                (
+                    "Sprite",
+                    self.renderer2d
+                        .reload_shaders(vk_device, vk_swapchain, loader, &changed),
+                ),
+                (
+                    "Ambient Occlusion",
+                    self.ambient_occlusion
+                        .reload_shaders(vk_device, loader, &changed),
+                ),
+                (
+                    "Bloom",
+                    self.bloom.reload_shaders(vk_device, loader, &changed),
+                ),
+                (
+                    "Tonemap",
+                    self.tonemap.reload_shaders(vk_device, loader, &changed),
+                ),
+                (
+                    "Retro",
+                    self.retro.reload_shaders(vk_device, loader, &changed),
+                ),
+            ]
+        };
+        for (pass, result) in results {
+            if let Err(error) = result {
+                error!("Failed To Reload {pass} Shaders: {error}");
+            }
+        }
+        if let Err(error) = self.reload_scene_shaders(&changed) {
+            error!("Failed To Reload Scene Shaders: {error}");
+        }
+        self.invalidate_command_buffers();
+    }
+
+    // the uber-shader is shared by every material pipeline, all of them are rebuilt
+    fn reload_scene_shaders(&mut self, changed: &[&str]) -> Result<(), Box<dyn error::Error>> {
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        let mut shaders = vec![
+            &mut self.vertex_shader,
+            &mut self.fragment_shader,
+            &mut self.skinning.vertex_shader,
+        ];
+        if let Some(shadows) = &mut self.ray_query_shadows {
+            shaders.push(&mut shadows.fragment_shader);
+        }
+        if !unsafe {
+            reload_shaders(
+                vk_device,
+                &mut self.vulkan_shader_loader,
+                &mut shaders,
+                changed,
+            )?
+        } {
+            return Ok(());
+        }
+
+        Ok(self.rebuild_material_pipelines()?)
+    }
+
+    // builds every material's pipeline again, for new shaders or a new scene format
+    // old pipelines stay in use until every new one has been made
+    fn rebuild_material_pipelines(&mut self) -> Result<(), vk::Result> {
+        let old_pipelines = std::mem::take(&mut self.pipelines);
+        let mut rebuilt = Vec::with_capacity(self.materials.len());
+        for index in 0..self.materials.len() {
+            match self.material_pipelines(index) {
+                Ok(pipelines) => rebuilt.push(pipelines),
+                Err(error) => {
+                    let new_pipelines = std::mem::replace(&mut self.pipelines, old_pipelines);
+                    for (_, pipeline) in new_pipelines {
+                        unsafe {
+                            self.vulkan_ctx
+                                .vulkan_device
+                                .device
+                                .destroy_pipeline(pipeline, None)
+                        };
+                    }
+                    return Err(error);
+                }
+            }
+        }
+
+        for (material, (pipeline, animated_pipeline)) in self.materials.iter_mut().zip(rebuilt) {
+            material.pipeline = pipeline;
+            material.animated_pipeline = animated_pipeline;
+        }
+        for (_, pipeline) in old_pipelines {
+            unsafe {
+                self.vulkan_ctx
+                    .vulkan_device
+                    .device
+                    .destroy_pipeline(pipeline, None)
+            };
+        }
+        Ok(())
+    }
+
+    // switches the scene between HDR_FORMAT and the swapchain format, everything drawn in the
+    // scene pass is rebuilt for it and render textures are recreated in it
+    // the gpu must be idle
+    fn set_hdr_scene(&mut self, hdr_scene: bool) -> Result<(), vk::Result> {
+        if self.vulkan_ctx.vulkan_swapchain.hdr_scene == hdr_scene {
+            return Ok(());
+        }
+        self.vulkan_ctx.vulkan_swapchain.hdr_scene = hdr_scene;
+
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        let vk_swapchain = &self.vulkan_ctx.vulkan_swapchain;
+        unsafe {
+            self.skybox.rebuild_pipeline(vk_device, vk_swapchain)?;
+            self.debug_renderer
+                .rebuild_pipeline(vk_device, vk_swapchain)?;
+            self.renderer2d.rebuild_pipeline(vk_device, vk_swapchain)?;
+        }
+        let format = vk_swapchain.scene_format();
+        for render_texture in &mut self.render_textures {
+            let extent = render_texture.extent();
+            unsafe { render_texture.resize(vk_device, extent, format)? };
+        }
+        for material in 0..self.materials.len() {
+            if self.materials[material].render_texture.is_some() {
+                self.write_material_albedo(material);
+            }
+        }
+        self.rebuild_material_pipelines()
+    }
+
+    /// Makes reused command buffers record again next frame
+    /// only needed after changing something drawn that the renderer can't see change,
+    /// like a material's params or a mesh's vertex buffer
+    pub fn invalidate_command_buffers(&mut self) {
+        self.resource_generation += 1;
+    }
+
+    /// Replaces the sky drawn behind the scene, None goes back to the clear colour
+    /// waits for the gpu to finish with the old cubemap before destroying it
+    /// Example Use:
+    /// ```ignore
+    /// let sky = VKCubemap::from_files(
+    ///     &mut renderer.vulkan_ctx.vulkan_device,
+    ///     renderer.vulkan_cmd_pool,
+    ///     ["sky/px.png", "sky/nx.png", "sky/py.png", "sky/ny.png", "sky/pz.png", "sky/nz.png"],
+    /// )?;
+    /// renderer.set_skybox(Some(sky))?;
+    /// ```
+    pub fn set_skybox(&mut self, cubemap: Option<VKCubemap>) -> Result<(), vk::Result> {
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        unsafe {
+            vk_device.device.device_wait_idle()?;
+            if let Some(mut old) = self.skybox.set_cubemap(vk_device, cubemap) {
+                old.destroy(vk_device);
+            }
+        }
+        self.invalidate_command_buffers();
+        Ok(())
+    }
+
+    /// Loads a PNG or JPEG for sprites to draw with
+    /// Example Use:
+    /// ```ignore
+    /// let tiles = renderer.load_sprite_texture("sprites/tiles.png")?;
+    /// let atlas = TextureAtlas::from_grid(128, 128, 16, 16);
+    /// renderer.sprites.push(
+    ///     Sprite::new(tiles, Vec2::new(0.0, 0.0), Vec2::splat(16.0)).with_uv(atlas.region(3).unwrap()),
+    /// );
+    /// ```
+    pub fn load_sprite_texture(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<SpriteTextureId, Box<dyn error::Error>> {
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        let texture = VKTexture::from_file(vk_device, self.vulkan_cmd_pool, path)?;
+        let id = self.renderer2d.add_texture(vk_device, texture)?;
+        self.invalidate_command_buffers();
+        Ok(id)
+    }
+
+    /// Loads a truetype or opentype font and gives it a sprite texture for its glyphs
+    /// glyphs are rasterized with text_rendering, set_rendering on the font changes it
+    #[cfg(feature = "text")]
+    pub fn load_font(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<text::Font, Box<dyn error::Error>> {
+        let mut font = text::Font::load(path)?.with_rendering(self.text_rendering());
+        self.add_font(&mut font)?;
+        Ok(font)
+    }
+
+    /// How text is best rasterized for the scene's current format, see TextRendering::for_format
+    /// subpixel text is left off since the display's subpixel order isn't known
+    #[cfg(feature = "text")]
+    pub fn text_rendering(&self) -> text::TextRendering {
+        text::TextRendering::for_format(self.vulkan_ctx.vulkan_swapchain.scene_format())
+    }
+
+    /// Uploads font's atlas as a new sprite texture and points font at it
+    #[cfg(feature = "text")]
+    pub fn add_font(&mut self, font: &mut text::Font) -> Result<(), vk::Result> {
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        let atlas = &mut font.atlas;
+        let texture = VKTexture::from_rgba8_with_format(
+            vk_device,
+            self.vulkan_cmd_pool,
+            atlas.width,
+            atlas.height,
+            &atlas.pixels,
+            text::GLYPH_ATLAS_FORMAT,
+        )?;
+        font.texture = self.renderer2d.add_texture(vk_device, texture)?;
+        atlas.dirty = false;
+        self.invalidate_command_buffers();
+        Ok(())
+    }
+
+    /// Uploads the glyphs font rasterized since its last upload, does nothing when there are none
+    /// waits for the gpu to finish with the old atlas before replacing it
+    #[cfg(feature = "text")]
+    pub fn upload_glyphs(&mut self, font: &mut text::Font) -> Result<(), vk::Result> {
+        if !font.atlas.dirty {
+            return Ok(());
+        }
+        if font.texture == renderer2d::WHITE_TEXTURE {
+            return self.add_font(font);
+        }
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        let atlas = &mut font.atlas;
+        let texture = VKTexture::from_rgba8_with_format(
+            vk_device,
+            self.vulkan_cmd_pool,
+            atlas.width,
+            atlas.height,
+            &atlas.pixels,
+            text::GLYPH_ATLAS_FORMAT,
+        )?;
+        unsafe {
+            vk_device.device.device_wait_idle()?;
+            self.renderer2d
+                .replace_texture(vk_device, font.texture, texture);
+        }
+        atlas.dirty = false;
+        self.invalidate_command_buffers();
+        Ok(())
+    }
+
+    /// Renders the scene at a fixed resolution scaled to fit the window, None renders at window size
+    /// Example Use:
+    /// ```ignore
+    /// renderer.set_internal_resolution(Some(
+    ///     InternalResolution::new(320, 180).filter(vk::Filter::NEAREST),
+    /// ))?;
+    /// ```
+    /// The scaled image is not pre-rotated, rotated displays are left to the presentation engine.
+    /// The internal target is HDR, it is tonemapped into the swapchain format after bloom, so
+    /// scene pipelines and render textures are rebuilt when it is first set or removed.
+    pub fn set_internal_resolution(
+        &mut self,
+        resolution: Option<InternalResolution>,
+    ) -> Result<(), vk::Result> {
+        self.invalidate_command_buffers();
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        unsafe {
+            vk_device.device.device_wait_idle()?;
+            if let Some(mut old) = self.internal_target.take() {
+                old.destroy(vk_device);
+            }
+        }
+
+        if let Some(resolution) = resolution {
+            self.internal_target = Some(VKInternalTarget::new(
+                vk_device,
+                resolution,
+                HDR_FORMAT,
+                self.vulkan_ctx.vulkan_swapchain.samples,
+            )?);
+        }
+        // only the internal target is HDR, the swapchain images aren't
+        self.set_hdr_scene(self.internal_target.is_some())?;
+
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        unsafe {
+            self.ambient_occlusion.set_target(
+                vk_device,
+                self.vulkan_cmd_pool,
+                self.internal_target.as_ref(),
+            )?;
+            self.bloom
+                .set_target(vk_device, self.internal_target.as_ref())?;
+            self.tonemap
+                .set_target(vk_device, self.internal_target.as_ref())?;
+            self.retro
+                .set_target(vk_device, self.tonemap.output.as_ref(), &self.texture)?;
+        }
+        // the materials still point at the old occlusion image
+        self.write_material_occlusion();
+        Ok(())
+    }
+
+    /// Darkens creases and corners where nearby geometry blocks ambient light, None turns it off
+    /// Example Use:
+    /// ```ignore
+    /// renderer.set_internal_resolution(Some(InternalResolution::new(1280, 720)))?;
+    /// renderer.set_ambient_occlusion(Some(AmbientOcclusionSettings::default().radius(1.0)))?;
+    /// ```
+    /// Worked out from the depth of the internal target after the scene pass, lit materials darken
+    /// their ambient light by it the frame after.
+    /// Without an internal resolution, or with msaa, the settings are kept but nothing is drawn.
+    pub fn set_ambient_occlusion(
+        &mut self,
+        settings: Option<AmbientOcclusionSettings>,
+    ) -> Result<(), vk::Result> {
+        self.invalidate_command_buffers();
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        unsafe {
+            vk_device.device.device_wait_idle()?;
+            self.ambient_occlusion.settings = settings;
+            self.ambient_occlusion.set_target(
+                vk_device,
+                self.vulkan_cmd_pool,
+                self.internal_target.as_ref(),
+            )?;
+        }
+        self.write_material_occlusion();
+
+        if settings.is_some() {
+            match &self.internal_target {
+                None => warn!("Ambient Occlusion Needs An Internal Resolution"),
+                Some(target) if !VKAmbientOcclusion::accepts(target) => {
+                    warn!("Ambient Occlusion Needs Single Sampled Depth, Turn Off MSAA")
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Blurs the brightest parts of the scene and adds them back on, None turns it off
+    /// Example Use:
+    /// ```ignore
+    /// renderer.set_internal_resolution(Some(InternalResolution::new(1280, 720)))?;
+    /// renderer.set_bloom(Some(BloomSettings::default().threshold(0.9).intensity(0.4)))?;
+    /// ```
+    /// Runs at the internal resolution before the tonemap and retro effects, without one the
+    /// settings are kept but nothing is drawn. The internal target is HDR, so thresholds above 1
+    /// leave everything but lights brighter than white alone.
+    pub fn set_bloom(&mut self, settings: Option<BloomSettings>) -> Result<(), vk::Result> {
+        self.invalidate_command_buffers();
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        unsafe {
+            vk_device.device.device_wait_idle()?;
+            self.bloom.settings = settings;
+            self.bloom
+                .set_target(vk_device, self.internal_target.as_ref())?;
+        }
+
+        if settings.is_some() && self.internal_target.is_none() {
+            warn!("Bloom Needs An Internal Resolution");
+        }
+        Ok(())
+    }
+
+    /// Quantizes the scene to a palette or a number of levels with optional dithering, None turns it off
+    /// Example Use:
+    /// ```ignore
+    /// renderer.set_internal_resolution(Some(InternalResolution::pixel_perfect(160, 144)))?;
+    /// renderer.set_retro_effects(Some(&RetroSettings::load("retro.ron")?))?;
+    /// ```
+    /// Runs at the internal resolution, without one the settings are kept but nothing is drawn.
+    pub fn set_retro_effects(
+        &mut self,
+        settings: Option<&RetroSettings>,
+    ) -> Result<(), Box<dyn error::Error>> {
+        self.invalidate_command_buffers();
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        unsafe {
+            vk_device.device.device_wait_idle()?;
+            self.retro
+                .set_settings(vk_device, self.vulkan_cmd_pool, settings)?;
+            self.retro
+                .set_target(vk_device, self.tonemap.output.as_ref(), &self.texture)?;
+        }
+
+        if settings.is_some() && self.internal_target.is_none() {
+            warn!("Retro Effects Need An Internal Resolution");
+        }
+        Ok(())
+    }
+
+    /// Colour blindness simulation or correction over the whole image, None turns it off
+    /// Example Use:
+    /// ```ignore
+    /// renderer.set_color_filter(Some(ColorFilter::correct(ColorBlindness::Deuteranopia)))?;
+    /// ```
+    /// Runs in the post pass at the internal resolution, without one the filter is kept but
+    /// nothing is drawn. Sprites and debug lines are drawn before it and are filtered too.
+    pub fn set_color_filter(
+        &mut self,
+        color_filter: Option<ColorFilter>,
+    ) -> Result<(), vk::Result> {
+        self.invalidate_command_buffers();
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        unsafe {
+            vk_device.device.device_wait_idle()?;
+            self.retro.set_color_filter(color_filter);
+            self.retro
+                .set_target(vk_device, self.tonemap.output.as_ref(), &self.texture)?;
+        }
+
+        if color_filter.is_some() && self.internal_target.is_none() {
+            warn!("Color Filters Need An Internal Resolution");
+        }
+        Ok(())
+    }
+
+    /// Renders the scene from another camera into a texture every frame or at camera's update rate
+    /// materials show it once bound with set_material_render_texture
+    /// Example Use:
+    /// ```ignore
+    /// let lobby = Camera::default().look_at(Vec3::new(4.0, 3.0, 4.0), Vec3::ZERO, Vec3::Y);
+    /// let monitor = renderer.add_render_texture(
+    ///     RenderCamera::new(lobby, 320, 240).update_rate(UpdateRate::Interval(2)),
+    /// )?;
+    /// renderer.set_material_render_texture(screen_material, Some(monitor))?;
+    /// // later frames, the texture follows the camera
+    /// renderer.render_textures[monitor].camera = lobby.orbit(Vec3::ZERO, yaw, -0.3, 6.0);
+    /// ```
+    /// Render textures are always rasterized, without sprites or debug lines.
+    pub fn add_render_texture(
+        &mut self,
+        camera: RenderCamera,
+    ) -> Result<RenderTextureId, vk::Result> {
+        let vk_swapchain = &self.vulkan_ctx.vulkan_swapchain;
+        let format = vk_swapchain.scene_format();
+        let samples = vk_swapchain.samples;
+        let render_texture =
+            VKRenderTexture::new(&mut self.vulkan_ctx.vulkan_device, camera, format, samples)?;
+        self.render_textures.push(render_texture);
+        Ok(self.render_textures.len() - 1)
+    }
+
+    /// Draws render_texture next frame, for textures with UpdateRate::Manual
+    pub fn refresh_render_texture(&mut self, render_texture: RenderTextureId) {
+        if let Some(render_texture) = self.render_textures.get_mut(render_texture) {
+            render_texture.refresh();
+        }
+    }
+
+    /// Recreates render_texture at width by height, materials sampling it are pointed at the new image
+    pub fn set_render_texture_resolution(
+        &mut self,
+        render_texture: RenderTextureId,
+        width: u32,
+        height: u32,
+    ) -> Result<(), vk::Result> {
+        if render_texture >= self.render_textures.len() {
+            warn!("Unknown Render Texture {render_texture}");
+            return Ok(());
+        }
+        self.invalidate_command_buffers();
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        let format = self.vulkan_ctx.vulkan_swapchain.scene_format();
+        let extent = vk::Extent2D {
+            width: width.max(1),
+            height: height.max(1),
+        };
+        unsafe {
+            vk_device.device.device_wait_idle()?;
+            self.render_textures[render_texture].resize(vk_device, extent, format)?;
+        }
+
+        for material in 0..self.materials.len() {
+            if self.materials[material].render_texture == Some(render_texture) {
+                self.write_material_albedo(material);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the instances about to be drawn and what they refer to
+    /// reports every issue found, nothing is changed
+    pub fn validate_scene(&self) -> Result<(), SceneValidationError> {
+        let material_render_textures: Vec<Option<RenderTextureId>> = self
+            .materials
+            .iter()
+            .map(|material| material.render_texture)
+            .collect();
+
+        let mut issues = validate_instances(&self.instances);
+        issues.extend(validate_references(
+            &self.instances,
+            self.meshes.len(),
+            &material_render_textures,
+            self.render_textures.len(),
+        ));
+        SceneValidationError::check(issues)
+    }
+
+    /// Samples render_texture in place of material's albedo texture, None goes back to its own
+    pub fn set_material_render_texture(
+        &mut self,
+        material: MaterialId,
+        render_texture: Option<RenderTextureId>,
+    ) -> Result<(), vk::Result> {
+        if material >= self.materials.len()
+            || render_texture.is_some_and(|id| id >= self.render_textures.len())
+        {
+            warn!("Unknown Material {material} Or Render Texture {render_texture:?}");
+            return Ok(());
+        }
+        self.invalidate_command_buffers();
+        // descriptor sets can't be written while a frame that binds them is in flight
+        // so the material gets new ones and the old ones are recycled once those frames are done
+        let frame_buffers = self.frame_buffer_infos();
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        let descriptor_sets = self.descriptor_allocator.allocate_many(
+            vk_device,
+            self.descriptor_layout,
+            self.materials[material].descriptor_sets.len(),
+        )?;
+        let vk_material = &self.materials[material];
+        write_descriptor_sets(
+            vk_device,
+            &descriptor_sets,
+            vk_material.texture.as_ref().unwrap_or(&self.texture),
+            vk_material
+                .normal_texture
+                .as_ref()
+                .unwrap_or(&self.flat_normal_texture),
+            self.occlusion_image_info(),
+            &frame_buffers,
+        );
+        let vk_material = &mut self.materials[material];
+        for descriptor_set in std::mem::replace(&mut vk_material.descriptor_sets, descriptor_sets) {
+            self.descriptor_allocator.free(descriptor_set);
+        }
+        vk_material.render_texture = render_texture;
+        self.write_material_albedo(material);
+        Ok(())
+    }
+
+    // points every frame's albedo binding of material at its render texture or its own texture
+    fn write_material_albedo(&self, material: MaterialId) {
+        let material = &self.materials[material];
+        let render_texture = material
+            .render_texture
+            .and_then(|render_texture| self.render_textures.get(render_texture));
+        let image_infos = [match render_texture {
+            Some(render_texture) => render_texture.descriptor_image_info(),
+            None => material
+                .texture
+                .as_ref()
+                .unwrap_or(&self.texture)
+                .descriptor_image_info(),
+        }];
+        let writes: Vec<_> = material
+            .descriptor_sets
+            .iter()
+            .map(|&descriptor_set| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(descriptor_set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&image_infos)
+            })
+            .collect();
+        unsafe {
+            self.vulkan_ctx
+                .vulkan_device
+                .device
+                .update_descriptor_sets(&writes, &[])
+        };
+    }
+
+    // the ambient occlusion lit materials sample, or the default texture while there is none,
+    // lights.occlusion tells the shader which
+    fn occlusion_image_info(&self) -> vk::DescriptorImageInfo {
+        self.ambient_occlusion
+            .descriptor_image_info()
+            .unwrap_or_else(|| self.texture.descriptor_image_info())
+    }
+
+    // points every material's occlusion binding at the current ambient occlusion
+    // the gpu must be idle, the sets are written in place
+    fn write_material_occlusion(&self) {
+        let image_infos = [self.occlusion_image_info()];
+        let writes: Vec<_> = self
+            .materials
+            .iter()
+            .flat_map(|material| &material.descriptor_sets)
+            .map(|&descriptor_set| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(descriptor_set)
+                    .dst_binding(6)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&image_infos)
+            })
+            .collect();
+        unsafe {
+            self.vulkan_ctx
+                .vulkan_device
+                .device
+                .update_descriptor_sets(&writes, &[])
+        };
+    }
+
+    /// Switches between rasterizing and ray tracing the scene
+    /// the ray tracer is made the first time it's needed, if the device can't ray trace or the
+    /// ray tracing shaders can't be loaded it warns and keeps rasterizing, returning the error
+    /// Example Use:
+    /// ```ignore
+    /// if renderer.set_render_mode(RenderMode::RayTraced).is_err() {
+    ///     show_setting_unavailable("Ray Tracing");
+    /// }
+    /// ```
+    /// Ray traced frames skip the skybox and multisampled targets skip debug lines and sprites.
+    /// Meshes added before the device supported ray tracing can't be traced.
+    pub fn set_render_mode(&mut self, mode: RenderMode) -> Result<(), Box<dyn error::Error>> {
+        if mode == RenderMode::RayTraced && self.ray_tracer.is_none() {
+            match VKRayTracer::new(
+                &mut self.vulkan_ctx.vulkan_device,
+                &mut self.vulkan_shader_loader,
+                self.vulkan_cmd_buffs.len(),
+                self.depth_convention,
+            ) {
+                Ok(ray_tracer) => self.ray_tracer = Some(ray_tracer),
+                Err(error) => {
+                    warn!("Ray Tracing Unavailable, Falling Back To Rasterizing: {error}");
+                    self.render_mode = RenderMode::Rasterized;
+                    self.invalidate_command_buffers();
+                    return Err(error);
+                }
+            }
+        }
+        if mode == RenderMode::RayTraced && self.scene_bvh.is_none() {
+            self.scene_bvh = Some(VKSceneBvh::new(self.vulkan_cmd_buffs.len()));
+        }
+        self.render_mode = mode;
+        self.invalidate_command_buffers();
+        Ok(())
+    }
+
+    /// Which way depth runs, fixed by RendererOptions::depth_convention
+    pub fn depth_convention(&self) -> DepthConvention {
+        self.depth_convention
+    }
+
+    /// Traces a shadow ray per light from every pixel of lit materials, needs VK_KHR_ray_query
+    /// the instances are kept in a BVH on the gpu while it's on
+    /// Example Use:
+    /// ```ignore
+    /// if let Err(err) = renderer.set_ray_query_shadows(true) {
+    ///     warn!("Shadows Unavailable: {}", err);
+    /// }
+    /// ```
+    /// Meshes added before the device supported ray tracing don't cast shadows.
+    pub fn set_ray_query_shadows(&mut self, enabled: bool) -> Result<(), Box<dyn error::Error>> {
+        if enabled && self.ray_query_shadows.is_none() {
+            self.ray_query_shadows = Some(VKRayQueryShadows::new(
+                &mut self.vulkan_ctx.vulkan_device,
+                &mut self.vulkan_shader_loader,
+                self.descriptor_layout,
+                &self.push_constant_ranges,
+                self.vulkan_cmd_buffs.len(),
+            )?);
+            if self.scene_bvh.is_none() {
+                self.scene_bvh = Some(VKSceneBvh::new(self.vulkan_cmd_buffs.len()));
+            }
+        } else if !enabled && let Some(mut shadows) = self.ray_query_shadows.take() {
+            // the shadowed pipelines are made with its layout
+            let vk_device = &mut self.vulkan_ctx.vulkan_device;
+            unsafe {
+                vk_device.device.device_wait_idle()?;
+                self.pipelines.retain(|variant, pipeline| {
+                    let shadowed = variant
+                        .features
+                        .contains(MaterialFeatures::RAY_QUERY_SHADOWS);
+                    if shadowed {
+                        vk_device.device.destroy_pipeline(*pipeline, None);
+                    }
+                    !shadowed
+                });
+                shadows.destroy(vk_device);
+            }
+        }
+
+        // lit materials switch between the plain and shadowed variants of their pipeline
+        for index in 0..self.materials.len() {
+            let (pipeline, animated_pipeline) = self.material_pipelines(index)?;
+            self.materials[index].pipeline = pipeline;
+            self.materials[index].animated_pipeline = animated_pipeline;
+        }
+        self.invalidate_command_buffers();
+        Ok(())
+    }
+
+    /// Culls instances against the camera in a compute pass and draws the ones left with one
+    /// indirect call per mesh and material, instead of testing and drawing each on the cpu
+    /// needs indirect draws that start past instance 0
+    /// Example Use:
+    /// ```ignore
+    /// if let Err(err) = renderer.set_gpu_culling(true) {
+    ///     warn!("GPU Culling Unavailable: {}", err);
+    /// }
+    /// ```
+    /// Meshes without indices are still drawn by the cpu, predicated on an occlusion test against
+    /// an earlier frame's depth when VK_EXT_conditional_rendering is available. draw_stats counts
+    /// every instance given to the gpu as submitted since only it knows which were culled.
+    pub fn set_gpu_culling(&mut self, enabled: bool) -> Result<(), Box<dyn error::Error>> {
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        if enabled && self.gpu_culling.is_none() {
+            if !vk_device.draw_indirect_first_instance {
+                return Err("Indirect Draws Can't Start Past The First Instance".into());
+            }
+            // prepare resizes the occlusion pyramids to the scene once it's drawn
+            let extent = self.vulkan_ctx.vulkan_swapchain.image_extent;
+            self.gpu_culling = Some(VKGpuCulling::new(
+                vk_device,
+                &mut self.vulkan_shader_loader,
+                self.vulkan_cmd_buffs.len(),
+                self.scene_objects.capacity as u32,
+                extent,
+                self.depth_convention,
+            )?);
+        } else if !enabled && let Some(mut gpu_culling) = self.gpu_culling.take() {
+            unsafe {
+                vk_device.device.device_wait_idle()?;
+                gpu_culling.destroy(vk_device);
+            }
+        }
+        self.invalidate_command_buffers();
+        Ok(())
+    }
+
+    // hands the instances the scene buffer has room for to gpu_culling's culler of frame_in_flight
+    // pyramid is the scene's depth extent and camera when the frame builds an occlusion pyramid
+    fn prepare_gpu_culling(
+        &mut self,
+        frame_in_flight: usize,
+        pyramid: Option<(vk::Extent2D, Mat4)>,
+    ) {
+        let Some(gpu_culling) = &mut self.gpu_culling else {
+            return;
+        };
+        // recordings bake in the pyramid's camera and extent, and whether one is built
+        let occlusion_state = |gpu_culling: &VKGpuCulling| {
+            let occlusion = &gpu_culling.occlusion[frame_in_flight];
+            (
+                occlusion.pyramid_view_projection,
+                occlusion.extent,
+                gpu_culling.builds_pyramid(frame_in_flight),
+            )
+        };
+        let last_state = occlusion_state(gpu_culling);
+        let object_count = self.instances.len().min(self.scene_objects.len());
+        if let Err(error) = gpu_culling.prepare(
+            &mut self.vulkan_ctx.vulkan_device,
+            frame_in_flight,
+            &self.instances[..object_count],
+            &self.meshes,
+            self.materials.len(),
+            pyramid,
+        ) {
+            error!("Error Preparing GPU Culling: {error}");
+        }
+        if occlusion_state(gpu_culling) != last_state {
+            self.invalidate_command_buffers();
+        }
+    }
+
+    // the variant a material's pipeline is built from, lit ones trace shadows while they're on
+    fn shaded_variant(&self, mut variant: PipelineVariant) -> PipelineVariant {
+        if self.ray_query_shadows.is_some() && variant.features.contains(MaterialFeatures::LIT) {
+            variant.features |= MaterialFeatures::RAY_QUERY_SHADOWS;
+        }
+        variant
+    }
+
+    // whether this frame uses scene_bvh
+    fn bvh_in_use(&self) -> bool {
+        self.ray_query_shadows.is_some()
+            || (self.render_mode == RenderMode::RayTraced && self.ray_tracer.is_some())
+    }
+
+    // layout material sets are bound with, ray query shadows add a set
+    fn scene_pipeline_layout(&self) -> vk::PipelineLayout {
+        match &self.ray_query_shadows {
+            Some(shadows) => shadows.pipeline_layout,
+            None => self.pipeline_layout,
+        }
+    }
+
+    /// Measures hardware counters whose names contain one of counter_names in every PERF_PASSES pass
+    /// results show up in pass_counters a few frames later, an empty list turns measuring off
+    /// returns whether any counter could be measured, needs VK_KHR_performance_query
+    /// Example Use:
+    /// ```ignore
+    /// renderer.enable_performance_counters(&perf_query::DEFAULT_COUNTERS)?;
+    /// // later
+    /// for pass in &renderer.pass_counters {
+    ///     for counter in &pass.values {
+    ///         info!("{} {}: {}", pass.pass, counter.name, counter.value);
+    ///     }
+    /// }
+    /// ```
+    pub fn enable_performance_counters(
+        &mut self,
+        counter_names: &[&str],
+    ) -> Result<bool, vk::Result> {
+        self.invalidate_command_buffers();
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        unsafe { vk_device.device.device_wait_idle()? };
+        if let Some(mut perf_queries) = self.perf_queries.take() {
+            unsafe { perf_queries.destroy(vk_device) };
+        }
+        self.pass_counters.clear();
+        if counter_names.is_empty() {
+            return Ok(false);
+        }
+
+        self.perf_queries = VKPerfQueries::new(
+            &self.vulkan_ctx.vulkan_instance,
+            vk_device,
+            counter_names,
+            &PERF_PASSES,
+            self.vulkan_cmd_buffs.len() as u32,
+        )?;
+        if self.perf_queries.is_none() {
+            warn!("No Performance Counters Match {counter_names:?}");
+        }
+        Ok(self.perf_queries.is_some())
+    }
+
+    /// Replaces the floats shaders see from the next frame on, e.g. the audio spectrum
+    /// values past MAX_SHADER_INPUTS are dropped, returns how many were kept
+    /// Example Use:
+    /// ```ignore
+    /// let spectrum = analyser.fft_bins();
+    /// renderer.set_shader_inputs(&spectrum);
+    /// ```
+    pub fn set_shader_inputs(&mut self, values: &[f32]) -> usize {
+        self.shader_inputs.set(values)
+    }
+
+    /// Loads a RON material file and builds its pipeline variant
+    /// Example Use:
+    /// ```ignore
+    /// let leaves = renderer.load_material("materials/leaves.ron")?;
+    /// renderer.instances[0].material = leaves;
+    /// ```
+    /// the material and its textures are recorded in assets, a texture that doesn't exist is
+    /// reported by name before anything is uploaded
+    pub fn load_material(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<MaterialId, Box<dyn error::Error>> {
+        let path = path.as_ref();
+        let desc = MaterialDesc::load(path)?;
+        let textures: Vec<_> = desc.textures.values().cloned().collect();
+        self.assets
+            .register_file(path, AssetKind::Material, textures.clone())?;
+        self.assets.check_references(path)?;
+        for texture in textures {
+            self.assets
+                .register_file(texture, AssetKind::Texture, Vec::new())?;
+        }
+        self.add_material(&desc)
+    }
+
+    /// Compiles desc onto the uber-shader, instances draw with it by setting their material
+    pub fn add_material(
+        &mut self,
+        desc: &MaterialDesc,
+    ) -> Result<MaterialId, Box<dyn error::Error>> {
+        self.add_compiled_material(desc.compile()?)
+    }
+
+    fn add_compiled_material(
+        &mut self,
+        material: material::CompiledMaterial,
+    ) -> Result<MaterialId, Box<dyn error::Error>> {
+        let variant = material.variant();
+        let pipeline = self.variant_pipeline(self.shaded_variant(variant))?;
+        let animated_pipeline = match self.skinning.meshes.is_empty() {
+            true => vk::Pipeline::null(),
+            false => self.variant_pipeline(self.shaded_variant(PipelineVariant {
+                animated: true,
+                ..variant
+            }))?,
+        };
+        let frame_buffers = self.frame_buffer_infos();
+        let occlusion = self.occlusion_image_info();
+
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+
+        let texture = match &material.albedo {
+            Some(path) => Some(VKTexture::from_file(vk_device, self.vulkan_cmd_pool, path)?),
+            None => None,
+        };
+        let normal_texture = match &material.normal_map {
+            Some(path) => {
+                match VKTexture::normal_map_from_file(vk_device, self.vulkan_cmd_pool, path) {
+                    Ok(normal_texture) => Some(normal_texture),
+                    Err(error) => {
+                        if let Some(mut texture) = texture {
+                            unsafe { texture.destroy(vk_device) };
+                        }
+                        return Err(error);
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let descriptor_sets = match self.descriptor_allocator.allocate_many(
+            vk_device,
+            self.descriptor_layout,
+            self.vulkan_cmd_buffs.len(),
+        ) {
+            Ok(descriptor_sets) => descriptor_sets,
+            Err(error) => {
+                for mut texture in [texture, normal_texture].into_iter().flatten() {
+                    unsafe { texture.destroy(vk_device) };
+                }
+                return Err(error.into());
+            }
+        };
+
+        write_descriptor_sets(
+            vk_device,
+            &descriptor_sets,
+            texture.as_ref().unwrap_or(&self.texture),
+            normal_texture.as_ref().unwrap_or(&self.flat_normal_texture),
+            occlusion,
+            &frame_buffers,
+        );
+
+        info!(
+            "Created Material {} With Features {:#x}",
+            material.name, material.features.0
+        );
+
+        self.materials.push(VKMaterial {
+            name: material.name,
+            variant,
+            params: material.params,
+            pipeline,
+            animated_pipeline,
+            texture,
+            normal_texture,
+            albedo: material.albedo,
+            normal_map: material.normal_map,
+            render_texture: None,
+            descriptor_sets,
+        });
+        self.invalidate_command_buffers();
+        Ok(self.materials.len() - 1)
+    }
+
+    // pipelines of materials[index] for plain and animated meshes, the animated one is null
+    // until there's an animated mesh
+    fn material_pipelines(
+        &mut self,
+        index: MaterialId,
+    ) -> Result<(vk::Pipeline, vk::Pipeline), vk::Result> {
+        let variant = self.shaded_variant(self.materials[index].variant);
+        let pipeline = self.variant_pipeline(variant)?;
+        if self.skinning.meshes.is_empty() {
+            return Ok((pipeline, vk::Pipeline::null()));
+        }
+        let animated = PipelineVariant {
+            animated: true,
+            ..variant
+        };
+        Ok((pipeline, self.variant_pipeline(animated)?))
+    }
+
+    // pipeline for variant, built the first time a material needs it
+    fn variant_pipeline(&mut self, variant: PipelineVariant) -> Result<vk::Pipeline, vk::Result> {
+        if let Some(&pipeline) = self.pipelines.get(&variant) {
+            return Ok(pipeline);
+        }
+
+        // both stages see the features so they are always specialized together
+        let features = variant.features.0.to_ne_bytes();
+        let map_entries = [vk::SpecializationMapEntry::default()
+            .constant_id(0)
+            .offset(0)
+            .size(size_of::<u32>())];
+        let specialization_info = vk::SpecializationInfo::default()
+            .map_entries(&map_entries)
+            .data(&features);
+
+        // shadowed variants swap in the fragment stage that can trace rays
+        let (fragment_shader, pipeline_layout) = match (&self.ray_query_shadows, variant.features) {
+            (Some(shadows), features) if features.contains(MaterialFeatures::RAY_QUERY_SHADOWS) => {
+                (&shadows.fragment_shader, shadows.pipeline_layout)
+            }
+            _ => (&self.fragment_shader, self.pipeline_layout),
+        };
+        let vertex_shader = match variant.animated {
+            true => &self.skinning.vertex_shader,
+            false => &self.vertex_shader,
+        };
+        let stages = [
+            vertex_shader
+                .shader_info
+                .specialization_info(&specialization_info),
+            fragment_shader
+                .shader_info
+                .specialization_info(&specialization_info),
+        ];
+
+        let cull_mode = if variant.double_sided {
+            vk::CullModeFlags::NONE
+        } else {
+            vk::CullModeFlags::BACK
+        };
+
+        let pipeline = create_pipeline::<Vertex>(
+            &self.vulkan_ctx.vulkan_device,
+            &self.vulkan_ctx.vulkan_swapchain,
+            &stages,
+            pipeline_layout,
+            cull_mode,
+            self.depth_convention,
+        )?;
+        self.pipelines.insert(variant, pipeline);
+        Ok(pipeline)
+    }
+
+    /// Presents to another window from the same device, with its own surface and swapchain
+    /// frame_rate caps how often render_window presents to it, None presents every call
+    /// Example Use:
+    /// ```ignore
+    /// // tool window at 30 Hz, the game view keeps presenting at the display's rate
+    /// renderer.add_window(&tool_window, Some(30.0), false)?;
+    /// // each pass of the event loop
+    /// renderer.render(&game_window);
+    /// renderer.render_window(&tool_window, |vk_device, cmd_buffer, target| {
+    ///     draw_tool_ui(vk_device, cmd_buffer, target.extent);
+    /// })?;
+    /// ```
+    pub fn add_window(
+        &mut self,
+        window: &Window,
+        frame_rate: Option<f32>,
+        transparent: bool,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let frames_in_flight = self.vulkan_cmd_buffs.len() as u32;
+        let window_target =
+            VKWindowTarget::new(&mut self.vulkan_ctx, window, frames_in_flight, transparent)?
+                .with_cadence(frame_rate.map_or(FrameCadence::default(), FrameCadence::from_rate));
+        if let Some(mut replaced) = self.windows.insert(window.id(), window_target) {
+            unsafe { replaced.destroy(&mut self.vulkan_ctx) };
+        }
+        Ok(())
+    }
+
+    /// Stops presenting to a window added with add_window, before the window itself is dropped
+    pub fn remove_window(&mut self, window_id: WindowId) -> bool {
+        match self.windows.remove(&window_id) {
+            Some(mut window_target) => {
+                unsafe { window_target.destroy(&mut self.vulkan_ctx) };
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Presents a frame to a window added with add_window if its cadence is due
+    /// false when it isn't, the window can't be drawn to right now or wasn't added
+    /// see VKWindowTarget::render
+    pub fn render_window(
+        &mut self,
+        window: &Window,
+        record: impl FnOnce(&VKDevice, vk::CommandBuffer, &RenderTarget),
+    ) -> Result<bool, vk::Result> {
+        let Some(window_target) = self.windows.get_mut(&window.id()) else {
+            return Ok(false);
+        };
+        window_target.render(
+            &mut self.vulkan_ctx,
+            window,
+            std::time::Instant::now(),
+            record,
+        )
+    }
+
+    /// Soonest any added window's cadence is due, for waiting in the event loop
+    pub fn next_window_frame(&self) -> Option<std::time::Instant> {
+        self.windows
+            .values()
+            .filter_map(|window_target| window_target.cadence.next_frame())
+            .min()
+    }
+
+    pub fn render(&mut self, window: &Window) {
+        // immediate mode, whatever happens to this frame the lines don't carry over
+        let mut debug_draw = std::mem::take(&mut self.debug_draw);
+
+        // nothing to render to while minimised
+        let window_size = window.inner_size();
+        if window_size.width == 0 || window_size.height == 0 {
+            return;
+        }
+
+        self.reload_changed_shaders();
+
+        self.vulkan_present.begin_frame();
+        let mut aquire_result = self
+            .vulkan_present
+            .aquire_img(self.vulkan_ctx.present_target(), window);
+
+        // swap is rebuilt when out of date, so retry once instead of dropping the frame
+        if matches!(aquire_result, Err(vk::Result::ERROR_OUT_OF_DATE_KHR))
+            && !self.vulkan_present.is_swap_invalid()
+        {
+            aquire_result = self
+                .vulkan_present
+                .aquire_img(self.vulkan_ctx.present_target(), window);
+        }
+
+        let render_info = match aquire_result {
+            Ok(render_info) => render_info,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                warn!("Swap Out of Date");
+                return;
+            }
+            Err(err) => {
+                error!("Error aquiring fame from swapchain: {}", err);
+                return;
+            }
+        };
+
+        // the frame's fence has signalled so its sprite buffer is free
+        let mut sprites = Cow::Borrowed(match self.renderer2d.visible {
+            true => &self.sprites[..],
+            false => &[],
+        });
+        // shown even with the ui hidden
+        let shader_errors = self.shader_diagnostic_sprites();
+        if !shader_errors.is_empty() {
+            sprites.to_mut().extend(shader_errors);
+        }
+        if let Err(err) = unsafe {
+            self.renderer2d.prepare(
+                &mut self.vulkan_ctx.vulkan_device,
+                render_info.frame_in_flight as usize,
+                &sprites,
+            )
+        } {
+            error!("Error preparing sprites: {}", err);
+        }
+        if let Err(err) = unsafe {
+            self.debug_renderer.prepare(
+                &mut self.vulkan_ctx.vulkan_device,
+                render_info.frame_in_flight as usize,
+                &debug_draw,
+            )
+        } {
+            error!("Error preparing debug lines: {}", err);
+        }
+        // keeps the allocation for next frame's lines
+        debug_draw.clear();
+        self.debug_draw = debug_draw;
+
+        let frame_in_flight = render_info.frame_in_flight as usize;
+        self.uploader.collect(&mut self.vulkan_ctx.vulkan_device);
+        self.descriptor_allocator.begin_frame(frame_in_flight);
+        // the fence also means this frame's last counters are in
+        if let Some(perf_queries) = &mut self.perf_queries {
+            let vk_device = &self.vulkan_ctx.vulkan_device;
+            let pass_counters = perf_queries.read(vk_device, frame_in_flight);
+            if !pass_counters.is_empty() {
+                self.pass_counters = pass_counters;
+            }
+            unsafe { perf_queries.reset(vk_device, frame_in_flight) };
+        }
+        // a recording only copies the objects that had changed when it was made
+        if self.sync_scene_objects(frame_in_flight) {
+            self.invalidate_command_buffers();
+        }
+        self.skinning
+            .write_frame(frame_in_flight, &self.instances, self.scene_objects.len());
+
+        let target = RenderTarget::from_swapchain(
+            &self.vulkan_ctx.vulkan_swapchain,
+            render_info.img_aquired_index,
+        );
+
+        // traced and multisampled scenes leave no depth the occlusion pyramid can be built from
+        let scene_target = match &self.internal_target {
+            Some(internal_target) => internal_target.render_target(),
+            None => target,
+        };
+        let ray_traced = self.ray_tracer.is_some() && self.render_mode == RenderMode::RayTraced;
+        let pyramid =
+            (!ray_traced && scene_target.samples == vk::SampleCountFlags::TYPE_1).then(|| {
+                (
+                    scene_target.extent,
+                    self.frame_camera(&target).view_projection,
+                )
+            });
+        self.prepare_gpu_culling(frame_in_flight, pyramid);
+        // traced scenes leave no depth to work occlusion out from either
+        let occlusion_view_projection =
+            (!ray_traced).then(|| self.frame_camera(&target).view_projection);
+        self.ambient_occlusion
+            .begin_frame(occlusion_view_projection);
+
+        if self.bvh_in_use() {
+            let extent = match &self.internal_target {
+                Some(internal_target) => internal_target.render_target().extent,
+                None => target.extent,
+            };
+            match unsafe { self.prepare_ray_tracing(frame_in_flight, extent) } {
+                // recordings bind the frame's old descriptor sets
+                Ok(true) => self.invalidate_command_buffers(),
+                Ok(false) => (),
+                Err(err) => error!("Error preparing ray tracing: {}", err),
+            }
+        }
+
+        // a recording that drew render textures can't stand in for a frame that doesn't
+        let mut render_textures_changed = false;
+        for render_texture in &mut self.render_textures {
+            render_textures_changed |= render_texture.is_due() | render_texture.advance();
+        }
+        if render_textures_changed {
+            self.invalidate_command_buffers();
+        }
+
+        let cameras = self.push_frame_cameras(&target, frame_in_flight);
+        let cmd_buffer = match self.command_cache.take() {
+            Some(mut command_cache) => {
+                let cmd_buffer =
+                    self.cached_cmd_buffer(&mut command_cache, &target, &cameras, frame_in_flight);
+                self.command_cache = Some(command_cache);
+                cmd_buffer
+            }
+            None => {
+                let cmd_buffer = self.vulkan_cmd_buffs[frame_in_flight];
+                self.draw_stats = unsafe {
+                    self.record_cmd_buffer(cmd_buffer, &target, &cameras, frame_in_flight)
+                        .unwrap()
+                };
+                cmd_buffer
+            }
+        };
+
+        // meshes added since last frame are drawn once their copies land
+        if let Err(err) = self.uploader.flush(&mut self.vulkan_ctx.vulkan_device) {
+            error!("Error submitting uploads: {}", err);
+        }
+
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+
+        let command_buffer_infos =
+            &[vk::CommandBufferSubmitInfo::default().command_buffer(cmd_buffer)];
+
+        let wait_semaphore_infos: Vec<vk::SemaphoreSubmitInfo> = std::iter::once(
+            vk::SemaphoreSubmitInfo::default()
+                .semaphore(render_info.img_aquired_gpu)
+                .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT),
+        )
+        .chain(self.uploader.take_wait())
+        .collect();
+
+        let signal_semaphore_infos = &[vk::SemaphoreSubmitInfo::default()
+            .semaphore(render_info.done_rendering_gpu)
+            .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)];
+
+        let submits = [vk::SubmitInfo2::default()
+            .wait_semaphore_infos(&wait_semaphore_infos)
+            .signal_semaphore_infos(signal_semaphore_infos)
+            .command_buffer_infos(command_buffer_infos)];
+
+        unsafe {
+            vk_device
+                .device
+                .queue_submit2(
+                    vk_device.graphics_queue,
+                    &submits,
+                    render_info.done_rendering_cpu,
                 )
-                .dst_stage_mask(
-                    vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
-                        | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                .unwrap()
+        };
+
+        // required for wayland
+        window.pre_present_notify();
+
+        match self
+            .vulkan_present
+            .present_frame(self.vulkan_ctx.present_target())
+        {
+            Ok(_) => (),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                warn!("Swap Out of Date");
+            }
+            Err(err) => {
+                error!("Error Presenting Frame: {}", err)
+            }
+        }
+    }
+
+    // updates the scene bvh and whatever traces through it, true when descriptor sets were rewritten
+    unsafe fn prepare_ray_tracing(
+        &mut self,
+        frame_in_flight: usize,
+        extent: vk::Extent2D,
+    ) -> Result<bool, vk::Result> {
+        let Some(scene_bvh) = &mut self.scene_bvh else {
+            return Ok(false);
+        };
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        // bottom levels are built from the graphics queue straight away, new meshes have to be there
+        self.uploader.wait(vk_device)?;
+        let replaced = unsafe {
+            scene_bvh.update(
+                vk_device,
+                self.vulkan_cmd_pool,
+                frame_in_flight,
+                &self.meshes,
+                &self.materials,
+                &self.instances,
+            )?
+        };
+
+        let mut rewritten = false;
+        if let Some(shadows) = &mut self.ray_query_shadows {
+            rewritten |=
+                unsafe { shadows.prepare(vk_device, frame_in_flight, scene_bvh, replaced) };
+        }
+        if self.render_mode == RenderMode::RayTraced
+            && let Some(ray_tracer) = &mut self.ray_tracer
+        {
+            rewritten |= unsafe {
+                ray_tracer.prepare(
+                    vk_device,
+                    frame_in_flight,
+                    scene_bvh,
+                    replaced,
+                    self.light_buffers[frame_in_flight].buffer,
+                    extent,
+                )?
+            };
+        }
+        Ok(rewritten)
+    }
+
+    // submits the frame's last recording again if nothing it depends on changed, records it otherwise
+    fn cached_cmd_buffer(
+        &mut self,
+        command_cache: &mut VKCommandCache,
+        target: &RenderTarget,
+        cameras: &FrameCameras,
+        frame_in_flight: usize,
+    ) -> vk::CommandBuffer {
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        // recordings for the images of a rebuilt swapchain are dead
+        unsafe {
+            command_cache.retain_images(
+                vk_device,
+                self.vulkan_cmd_pool,
+                &self.vulkan_ctx.vulkan_swapchain.images,
+            )
+        };
+
+        let inputs = FrameInputs {
+            swapchain: self.vulkan_ctx.vulkan_swapchain.swapchain,
+            image: target.image,
+            extent: target.extent,
+            generation: self.resource_generation,
+            camera: cameras.main.uniform,
+            clear_color: self.clear_color,
+            instances: self.instances.clone(),
+            sprite_draws: self.renderer2d.batch.draws.clone(),
+            camera_2d: self.renderer2d.scaled_camera(),
+            vertex_buffers: [
+                self.renderer2d.vertex_buffers[frame_in_flight].buffer,
+                self.debug_renderer.vertex_buffers[frame_in_flight].buffer,
+            ],
+            debug_vertices: self.debug_renderer.vertex_count,
+        };
+
+        if let Some((cmd_buffer, draw_stats)) = command_cache.reuse(frame_in_flight, &inputs) {
+            // recording writes the uniforms, they change every frame even when the commands don't
+            // the camera was pushed at the offset the recording binds
+            unsafe { self.write_frame_uniforms(frame_in_flight) };
+            self.draw_stats = draw_stats;
+            return cmd_buffer;
+        }
+
+        let cmd_buffer = command_cache
+            .begin(
+                vk_device,
+                self.vulkan_cmd_pool,
+                frame_in_flight,
+                target.image,
+            )
+            .unwrap();
+        self.draw_stats = unsafe {
+            self.record_cmd_buffer(cmd_buffer, target, cameras, frame_in_flight)
+                .unwrap()
+        };
+        command_cache.store(frame_in_flight, inputs, self.draw_stats);
+        cmd_buffer
+    }
+
+    // camera for a frame drawn onto target, through the internal target when there is one
+    fn frame_camera(&self, target: &RenderTarget) -> CameraUniform {
+        match &self.internal_target {
+            Some(internal_target) => {
+                let extent = internal_target.render_target().extent;
+                self.camera
+                    .uniform(extent.width as f32 / extent.height as f32)
+                    .with_clip_transform(self.depth_convention.clip_transform())
+            }
+            None => {
+                // camera sees the display orientation, pre rotation maps it onto the swapchain image
+                let display_extent = target.display_extent();
+                self.camera
+                    .uniform(display_extent.width as f32 / display_extent.height as f32)
+                    .with_clip_transform(
+                        pre_rotation(target.pre_transform) * self.depth_convention.clip_transform(),
+                    )
+            }
+        }
+    }
+
+    // starts frame_in_flight's cameras over, the frame's own first so recordings always bind it
+    // at the same offset, then those of the render textures due this frame
+    // the gpu must be done with frame_in_flight
+    fn push_frame_cameras(
+        &mut self,
+        target: &RenderTarget,
+        frame_in_flight: usize,
+    ) -> FrameCameras {
+        self.camera_ring.begin_frame(frame_in_flight);
+        let uniform = self.frame_camera(target);
+        let main = FrameCamera {
+            uniform,
+            // an empty region always has room for one
+            offset: self.camera_ring.push(&uniform).unwrap_or_default(),
+        };
+
+        let due: Vec<CameraUniform> = self
+            .render_textures
+            .iter()
+            .filter(|render_texture| render_texture.is_due())
+            .map(|render_texture| {
+                render_texture
+                    .uniform()
+                    .with_clip_transform(self.depth_convention.clip_transform())
+            })
+            .collect();
+        let render_textures: Vec<FrameCamera> = due
+            .iter()
+            .map_while(|uniform| {
+                Some(FrameCamera {
+                    uniform: *uniform,
+                    offset: self.camera_ring.push(uniform)?,
+                })
+            })
+            .collect();
+        if render_textures.len() < due.len() {
+            warn!(
+                "{} Render Textures Due, Only {} Are Drawn This Frame",
+                due.len(),
+                render_textures.len()
+            );
+        }
+        FrameCameras {
+            main,
+            render_textures,
+        }
+    }
+
+    /// Records a full frame for the swapchain image in target
+    /// the passes go through a render graph, which transitions the image for rendering and
+    /// leaves it ready for presenting
+    /// with an internal target the scene is drawn there and blitted onto the swapchain image
+    unsafe fn record_cmd_buffer(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        target: &RenderTarget,
+        cameras: &FrameCameras,
+        frame_in_flight: usize,
+    ) -> Result<DrawStats, ash::vk::Result> {
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        let begin_info = vk::CommandBufferBeginInfo::default();
+
+        let camera = cameras.main;
+        let internal = self
+            .internal_target
+            .as_ref()
+            .map(|internal_target| (internal_target, internal_target.render_target()));
+        let render_texture_views: Vec<_> = self
+            .render_textures
+            .iter()
+            .filter(|render_texture| render_texture.is_due())
+            .zip(&cameras.render_textures)
+            .map(|(render_texture, camera)| RenderTextureView {
+                render_texture,
+                target: render_texture.target.render_target(),
+                camera: *camera,
+            })
+            .collect();
+        let mut draw_stats = DrawStats::default();
+
+        unsafe {
+            vk_device
+                .device
+                .begin_command_buffer(cmd_buffer, &begin_info)?;
+
+            self.cmd_begin_label(cmd_buffer, c"Frame", FRAME_LABEL_COLOR);
+        }
+
+        let mut graph = RenderGraph::default();
+        let swap_image =
+            graph.import_image(target.image, COLOR_SUBRESOURCE_RANGE, &[Access::Present]);
+        graph.add_pass(
+            RenderPass::new(c"Begin Scene Counters").record(move |cmd_buffer| unsafe {
+                self.cmd_begin_perf_pass(cmd_buffer, frame_in_flight, PERF_SCENE_PASS)
+            }),
+        );
+        graph.add_pass(
+            RenderPass::new(c"Upload Scene Objects").record(move |cmd_buffer| unsafe {
+                self.scene_objects
+                    .cmd_copy_staged(vk_device, cmd_buffer, frame_in_flight)
+            }),
+        );
+        // before anything traces or casts shadows through it, render textures included
+        graph.add_pass(
+            RenderPass::new(c"Build Scene BVH").record(move |cmd_buffer| unsafe {
+                self.cmd_build_scene_bvh(cmd_buffer, frame_in_flight)
+            }),
+        );
+        unsafe {
+            self.add_render_texture_passes(&mut graph, &render_texture_views, frame_in_flight)
+        };
+
+        if let Some((internal_target, internal)) = &internal {
+            // internal targets are shared between frames, the last frame's post pass or blit may
+            // still be reading
+            let scene_color = graph.import_image(
+                internal.image,
+                COLOR_SUBRESOURCE_RANGE,
+                &[Access::FragmentSampled, Access::TransferSrc],
+            );
+            let scene_depth = unsafe {
+                self.add_scene_passes(
+                    &mut graph,
+                    scene_color,
+                    internal,
+                    &camera,
+                    frame_in_flight,
+                    &mut draw_stats,
                 )
-                .dst_access_mask(
-                    vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
-                        | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ,
+            };
+            // shared between frames like the internal target, the blurred image is left for the
+            // next frame's lit materials, which sampled the last one during the scene pass
+            if let (Some([raw, blurred]), Some(scene_depth)) =
+                (&self.ambient_occlusion.images, scene_depth)
+                && self.ambient_occlusion.is_active()
+            {
+                let raw =
+                    graph.import_image(raw.image, COLOR_SUBRESOURCE_RANGE, &[Access::ComputeRead]);
+                let blurred = graph.import_image(
+                    blurred.image,
+                    COLOR_SUBRESOURCE_RANGE,
+                    &[Access::FragmentSampled],
+                );
+                for (step, source, output) in [
+                    (AmbientOcclusionStep::Occlusion, scene_depth, raw),
+                    (AmbientOcclusionStep::Blur, raw, blurred),
+                ] {
+                    graph.add_pass(
+                        RenderPass::new(step.name())
+                            .read_image(source, Access::ComputeRead)
+                            .discard_image(output, Access::ComputeWrite)
+                            .record(move |cmd_buffer| unsafe {
+                                self.ambient_occlusion.record(
+                                    vk_device,
+                                    cmd_buffer,
+                                    step,
+                                    camera.uniform.projection,
+                                )
+                            }),
+                    );
+                }
+                graph.export_image(blurred, Access::FragmentSampled);
+            }
+
+            graph.add_pass(
+                RenderPass::new(c"Post Counters").record(move |cmd_buffer| unsafe {
+                    self.cmd_end_perf_pass(cmd_buffer, frame_in_flight, PERF_SCENE_PASS);
+                    self.cmd_begin_perf_pass(cmd_buffer, frame_in_flight, PERF_POST_PASS);
+                }),
+            );
+
+            // levels are shared between frames like the internal target
+            let bloom_levels: Vec<ImageHandle> = self
+                .bloom
+                .levels
+                .iter()
+                .map(|level| {
+                    graph.import_image(
+                        level.image,
+                        COLOR_SUBRESOURCE_RANGE,
+                        &[Access::FragmentSampled, Access::ColorAttachment],
+                    )
+                })
+                .collect();
+            for step in self.bloom.steps() {
+                let image = |level: Option<usize>| level.map_or(scene_color, |l| bloom_levels[l]);
+                let pass = RenderPass::new(step.name())
+                    .read_image(image(step.source()), Access::FragmentSampled);
+                let pass = match step {
+                    BloomStep::Downsample(_) => {
+                        pass.discard_image(image(step.output()), Access::ColorAttachment)
+                    }
+                    BloomStep::Upsample(_) | BloomStep::Composite => {
+                        pass.write_image(image(step.output()), Access::ColorAttachment)
+                    }
+                };
+                graph.add_pass(pass.record(move |cmd_buffer| unsafe {
+                    self.bloom.record(vk_device, cmd_buffer, step)
+                }));
+            }
+
+            // the blit can still convert the HDR scene if the tonemap output couldn't be made
+            let (tonemapped, tonemapped_image) = match self.tonemap.output_image() {
+                Some(tonemap_image) => {
+                    // shared between frames, the last frame's retro pass or blit may still be reading
+                    let output = graph.import_image(
+                        tonemap_image,
+                        COLOR_SUBRESOURCE_RANGE,
+                        &[Access::FragmentSampled, Access::TransferSrc],
+                    );
+                    graph.add_pass(
+                        RenderPass::new(c"Tonemap")
+                            .read_image(scene_color, Access::FragmentSampled)
+                            .discard_image(output, Access::ColorAttachment)
+                            .record(move |cmd_buffer| unsafe {
+                                self.tonemap.record(vk_device, cmd_buffer)
+                            }),
+                    );
+                    (output, tonemap_image)
+                }
+                None => (scene_color, internal.image),
+            };
+
+            let (source, source_image) = match self.retro.output_image() {
+                Some(retro_image) => {
+                    let output = graph.import_image(
+                        retro_image,
+                        COLOR_SUBRESOURCE_RANGE,
+                        &[Access::TransferSrc],
+                    );
+                    graph.add_pass(
+                        RenderPass::new(c"Retro")
+                            .read_image(tonemapped, Access::FragmentSampled)
+                            .discard_image(output, Access::ColorAttachment)
+                            .record(move |cmd_buffer| unsafe {
+                                self.retro.record(vk_device, cmd_buffer)
+                            }),
+                    );
+                    (output, retro_image)
+                }
+                None => (tonemapped, tonemapped_image),
+            };
+
+            graph.add_pass(
+                RenderPass::new(c"Scale To Window")
+                    .read_image(source, Access::TransferSrc)
+                    .discard_image(swap_image, Access::TransferDst)
+                    .record(move |cmd_buffer| unsafe {
+                        self.cmd_insert_label(cmd_buffer, c"Scale To Window", FRAME_LABEL_COLOR);
+                        internal_target.record_blit(
+                            vk_device,
+                            cmd_buffer,
+                            source_image,
+                            target.image,
+                            target.extent,
+                            LinearRgba::BLACK,
+                        );
+                        self.cmd_end_perf_pass(cmd_buffer, frame_in_flight, PERF_POST_PASS);
+                    }),
+            );
+        } else {
+            unsafe {
+                self.add_scene_passes(
+                    &mut graph,
+                    swap_image,
+                    target,
+                    &camera,
+                    frame_in_flight,
+                    &mut draw_stats,
                 )
-                .image(depth_image)
-                .subresource_range(sub_resource_range_depth),
-        ];
+            };
+            graph.add_pass(RenderPass::new(c"End Scene Counters").record(
+                move |cmd_buffer| unsafe {
+                    self.cmd_end_perf_pass(cmd_buffer, frame_in_flight, PERF_SCENE_PASS)
+                },
+            ));
+        }
+
+        // the transition itself is recorded after the last pass, with the graph's exports
+        graph.add_pass(
+            RenderPass::new(c"Present Transition").record(move |cmd_buffer| unsafe {
+                self.cmd_insert_label(cmd_buffer, c"Present Transition", FRAME_LABEL_COLOR)
+            }),
+        );
+        graph.export_image(swap_image, Access::Present);
+
+        unsafe {
+            graph.execute(vk_device, cmd_buffer);
+
+            self.cmd_end_label(cmd_buffer);
+
+            vk_device.device.end_command_buffer(cmd_buffer)?;
+        }
+        Ok(draw_stats)
+    }
+
+    /// Adds the passes drawing the scene into color as seen from camera, color is target's image
+    /// the frame's other uniforms are written into frame_in_flight's buffers, so the gpu must be
+    /// done with them
+    /// the attachments' previous contents are discarded, color is left as a colour attachment
+    /// in RenderMode::RayTraced the scene is traced first and only the overlays are rasterized
+    /// draw_stats is filled in once the graph has been executed
+    /// returns the depth image the scene pass drew the scene into, None when the scene was traced
+    /// # Safety
+    /// graph must be executed before the gpu is done with frame_in_flight's buffers
+    unsafe fn add_scene_passes<'g>(
+        &'g self,
+        graph: &mut RenderGraph<'g>,
+        color: ImageHandle,
+        target: &'g RenderTarget,
+        camera: &'g FrameCamera,
+        frame_in_flight: usize,
+        draw_stats: &'g mut DrawStats,
+    ) -> Option<ImageHandle> {
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        let ray_tracer = self
+            .ray_tracer
+            .as_ref()
+            .filter(|_| self.render_mode == RenderMode::RayTraced);
+        let multisampled = target.samples != vk::SampleCountFlags::TYPE_1;
+
+        unsafe { self.write_frame_uniforms(frame_in_flight) };
+
+        // the culled commands have to be written before the scene pass draws them
+        if ray_tracer.is_none()
+            && let Some(gpu_culling) = &self.gpu_culling
+        {
+            let culler = &gpu_culling.cullers[frame_in_flight];
+            let occlusion = &gpu_culling.occlusion[frame_in_flight];
+            graph.add_pass(
+                RenderPass::new(c"Cull Draws").record(move |cmd_buffer| unsafe {
+                    culler.record_cull(vk_device, cmd_buffer, camera.uniform.view_projection);
+                    occlusion.record_cull(vk_device, cmd_buffer);
+                }),
+            );
+        }
+
+        // the ray tracer blits the scene into the colour image, the overlays are drawn on top
+        let mut scene_pass = match ray_tracer {
+            Some(ray_tracer) => {
+                draw_stats.submitted = self.instances.len() as u32;
+                let clear_color = self.scene_clear_color();
+                graph.add_pass(
+                    RenderPass::new(c"Ray Trace")
+                        .discard_image(color, Access::TransferDst)
+                        .record(move |cmd_buffer| unsafe {
+                            self.cmd_begin_label(cmd_buffer, c"Ray Trace", SCENE_LABEL_COLOR);
+                            ray_tracer.record(
+                                vk_device,
+                                cmd_buffer,
+                                frame_in_flight,
+                                target.image,
+                                &camera.uniform,
+                                clear_color,
+                            );
+                            self.cmd_end_label(cmd_buffer);
+                            crash_report::set_last_pass("scene");
+                        }),
+                );
+
+                // the msaa image would resolve over the traced scene, so there's nothing to draw
+                if multisampled {
+                    return None;
+                }
+                RenderPass::new(c"Scene Pass").write_image(color, Access::ColorAttachment)
+            }
+            None => RenderPass::new(c"Scene Pass").discard_image(color, Access::ColorAttachment),
+        };
+
+        // depth and msaa images are shared between frames in flight, wait for the last frame's writes
+        // and ambient occlusion's reads
+        let depth = graph.import_image(
+            target.depth_image,
+            DEPTH_SUBRESOURCE_RANGE,
+            &[Access::DepthAttachment, Access::ComputeRead],
+        );
+        scene_pass = scene_pass.discard_image(depth, Access::DepthAttachment);
+        if multisampled {
+            let msaa = graph.import_image(
+                target.msaa_image,
+                COLOR_SUBRESOURCE_RANGE,
+                &[Access::ColorAttachment],
+            );
+            scene_pass = scene_pass.discard_image(msaa, Access::ColorAttachment);
+        }
+
+        graph.add_pass(scene_pass.record(move |cmd_buffer| unsafe {
+            self.record_scene_draws(cmd_buffer, target, camera, frame_in_flight, draw_stats)
+        }));
+
+        // the depth is left for a later frame's occlusion test
+        if ray_tracer.is_none()
+            && let Some(gpu_culling) = &self.gpu_culling
+            && gpu_culling.builds_pyramid(frame_in_flight)
+        {
+            let occlusion = &gpu_culling.occlusion[frame_in_flight];
+            graph.add_pass(
+                RenderPass::new(c"Depth Pyramid").record(move |cmd_buffer| unsafe {
+                    occlusion.record_pyramid(vk_device, cmd_buffer, target);
+                }),
+            );
+        }
+        // only the overlays drew into it when traced
+        ray_tracer.is_none().then_some(depth)
+    }
+
+    // the compositor expects premultiplied colour when it blends a transparent window
+    fn scene_clear_color(&self) -> LinearRgba {
+        let composite_alpha = self.vulkan_ctx.vulkan_swapchain.composite_alpha;
+        if composite_alpha == vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED {
+            self.clear_color.premultiplied()
+        } else {
+            self.clear_color
+        }
+    }
+
+    /// Adds a pass for each render texture in views, drawn before the scene that samples them
+    /// # Safety
+    /// graph must be executed before the gpu is done with frame_in_flight's buffers
+    unsafe fn add_render_texture_passes<'g>(
+        &'g self,
+        graph: &mut RenderGraph<'g>,
+        views: &'g [RenderTextureView<'g>],
+        frame_in_flight: usize,
+    ) {
+        if views.is_empty() {
+            return;
+        }
+
+        let mut colors = Vec::with_capacity(views.len());
+        for view in views {
+            let target = &view.target;
+            let color = graph.import_image(
+                target.image,
+                COLOR_SUBRESOURCE_RANGE,
+                view.render_texture.previous_access(),
+            );
+            let depth = graph.import_image(
+                target.depth_image,
+                DEPTH_SUBRESOURCE_RANGE,
+                &[Access::DepthAttachment],
+            );
+            let mut pass = RenderPass::new(c"Render Texture")
+                .discard_image(color, Access::ColorAttachment)
+                .discard_image(depth, Access::DepthAttachment);
+            if target.samples != vk::SampleCountFlags::TYPE_1 {
+                let msaa = graph.import_image(
+                    target.msaa_image,
+                    COLOR_SUBRESOURCE_RANGE,
+                    &[Access::ColorAttachment],
+                );
+                pass = pass.discard_image(msaa, Access::ColorAttachment);
+            }
+
+            graph.add_pass(pass.record(move |cmd_buffer| unsafe {
+                self.record_render_texture(cmd_buffer, target, &view.camera, frame_in_flight)
+            }));
+            colors.push(color);
+        }
+
+        // nothing to record, the barriers before it ready the textures for the scene
+        let ready = colors
+            .into_iter()
+            .fold(RenderPass::new(c"Render Textures Ready"), |ready, color| {
+                ready.read_image(color, Access::FragmentSampled)
+            });
+        graph.add_pass(ready);
+    }
+
+    // instances and the skybox drawn into a render texture's target, always rasterized
+    unsafe fn record_render_texture(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        target: &RenderTarget,
+        camera: &FrameCamera,
+        frame_in_flight: usize,
+    ) {
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        // the texture is never composited, so its clear colour isn't premultiplied
+        let (color_attachments, depth_attachment) = scene_attachments(
+            target,
+            vk::AttachmentLoadOp::CLEAR,
+            self.clear_color,
+            self.depth_convention,
+        );
+        let rendering_info = vk::RenderingInfo::default()
+            .color_attachments(&color_attachments)
+            .depth_attachment(&depth_attachment)
+            .layer_count(1)
+            .render_area(vk::Rect2D::default().extent(target.extent));
+        let frustum = Frustum::from_view_projection(camera.uniform.view_projection);
+
+        unsafe {
+            self.cmd_begin_label(cmd_buffer, c"Render Texture", SCENE_LABEL_COLOR);
+            vk_device
+                .device
+                .cmd_begin_rendering(cmd_buffer, &rendering_info);
+            self.cmd_set_draw_state(cmd_buffer, target, frame_in_flight);
+            self.record_instances(
+                cmd_buffer,
+                0..self.instances.len(),
+                &frustum,
+                frame_in_flight,
+                camera.offset,
+                None,
+            );
+            self.skybox.record(vk_device, cmd_buffer, &camera.uniform);
+            vk_device.device.cmd_end_rendering(cmd_buffer);
+            self.cmd_end_label(cmd_buffer);
+        }
+    }
+
+    unsafe fn cmd_build_scene_bvh(&self, cmd_buffer: vk::CommandBuffer, frame_in_flight: usize) {
+        if self.bvh_in_use()
+            && let Some(scene_bvh) = &self.scene_bvh
+        {
+            unsafe {
+                scene_bvh.cmd_build(&self.vulkan_ctx.vulkan_device, cmd_buffer, frame_in_flight)
+            };
+        }
+    }
 
-        // memory barriar info for present
-        // we use memory barriars to transistion the image into the correct layout
-        // this is for the final layout before presenting
-        let present_image_memory_barriers = [vk::ImageMemoryBarrier2::default()
-            .old_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-            .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-            .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
-            .src_access_mask(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE)
-            .dst_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
-            .dst_access_mask(vk::AccessFlags2::MEMORY_READ)
-            .image(image)
-            .subresource_range(sub_resource_range)];
-
-        let dependency_info =
-            vk::DependencyInfo::default().image_memory_barriers(&image_memory_barriers);
-
-        let present_dependency_info =
-            vk::DependencyInfo::default().image_memory_barriers(&present_image_memory_barriers);
-
-        let mut clear_value = vk::ClearValue::default();
-        clear_value.color = vk::ClearColorValue::default();
-        clear_value.color.float32 = [0.74757, 0.02016, 0.253, 1.0];
-        // clear_value.color.float32 = [0.0, 0.0, 0.0, 1.0];
-
-        let color_attachments = [vk::RenderingAttachmentInfo::default()
-            .image_view(image_view)
-            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
-            .clear_value(clear_value)];
-
-        let depth_attachment = vk::RenderingAttachmentInfo::default()
-            .image_view(depth_image_view)
-            .image_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE);
-
-        let render_area_extent = vk::Rect2D::default()
-            .extent(render_area)
-            .offset(vk::Offset2D::default().x(0).y(0));
+    // rasterized part of the scene pass, attachments are already in their attachment layouts
+    unsafe fn record_scene_draws(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        target: &RenderTarget,
+        camera: &FrameCamera,
+        frame_in_flight: usize,
+        draw_stats: &mut DrawStats,
+    ) {
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        let ray_traced = self.ray_tracer.is_some() && self.render_mode == RenderMode::RayTraced;
+
+        // traced scenes are loaded so the overlays are drawn on top
+        let color_load_op = if ray_traced {
+            vk::AttachmentLoadOp::LOAD
+        } else {
+            vk::AttachmentLoadOp::CLEAR
+        };
+        let (color_attachments, depth_attachment) = scene_attachments(
+            target,
+            color_load_op,
+            self.scene_clear_color(),
+            self.depth_convention,
+        );
 
         let rendering_info = vk::RenderingInfo::default()
             .color_attachments(&color_attachments)
             .depth_attachment(&depth_attachment)
             .layer_count(1)
-            .render_area(render_area_extent);
+            .render_area(vk::Rect2D::default().extent(target.extent));
 
-        let viewport = [vk::Viewport::default()
-            .x(0.0)
-            .y(0.0)
-            .width(render_area.width as f32)
-            .height(render_area.height as f32)
-            .min_depth(0.0)
-            .max_depth(1.0)];
+        // traced instances are already in the colour image, culled ones are drawn in batches
+        let gpu_culling = self.gpu_culling.as_ref().filter(|_| !ray_traced);
+        let object_count = if ray_traced || gpu_culling.is_some() {
+            0
+        } else {
+            self.instances.len()
+        };
 
-        let aspect_ratio = render_area.width as f32 / render_area.height as f32;
+        unsafe {
+            self.cmd_begin_label(cmd_buffer, c"Scene Pass", SCENE_LABEL_COLOR);
+
+            let secondaries = self
+                .parallel_recorder
+                .as_ref()
+                .filter(|recorder| recorder.thread_count(object_count) > 1)
+                .and_then(|recorder| {
+                    self.record_parallel_draws(
+                        recorder,
+                        target,
+                        camera,
+                        frame_in_flight,
+                        object_count,
+                    )
+                    .inspect_err(|error| {
+                        error!("Parallel Recording Failed, Recording Inline: {error}")
+                    })
+                    .ok()
+                });
+
+            match secondaries {
+                Some((cmd_buffers, stats)) => {
+                    draw_stats.submitted += stats.submitted;
+                    draw_stats.culled += stats.culled;
+                    vk_device.device.cmd_begin_rendering(
+                        cmd_buffer,
+                        &rendering_info
+                            .flags(vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS),
+                    );
+                    vk_device
+                        .device
+                        .cmd_execute_commands(cmd_buffer, &cmd_buffers);
+                }
+                None => {
+                    vk_device
+                        .device
+                        .cmd_begin_rendering(cmd_buffer, &rendering_info);
+                    self.cmd_set_draw_state(cmd_buffer, target, frame_in_flight);
+                    let frustum = Frustum::from_view_projection(camera.uniform.view_projection);
+                    let stats = match gpu_culling {
+                        Some(gpu_culling) => self.record_culled_batches(
+                            cmd_buffer,
+                            gpu_culling,
+                            &frustum,
+                            frame_in_flight,
+                            camera.offset,
+                        ),
+                        None => self.record_instances(
+                            cmd_buffer,
+                            0..object_count,
+                            &frustum,
+                            frame_in_flight,
+                            camera.offset,
+                            None,
+                        ),
+                    };
+                    draw_stats.submitted += stats.submitted;
+                    draw_stats.culled += stats.culled;
+                    self.record_overlays(cmd_buffer, target, &camera.uniform, frame_in_flight);
+                }
+            }
 
-        let speed: f32 = 10.0; // speed deg per second
+            vk_device.device.cmd_end_rendering(cmd_buffer);
 
-        let yaw: f32 = created_time.elapsed().as_secs_f32() * speed % 360.0; // Rotation around the target
-        let pitch: f32 = -20.0; // Angle looking down
-        let radius: f32 = 2.5; // Distance from the target
-        let target_point = Vec3::new(0.0, 0.2, 0.0); // The point you want to orbit
+            self.cmd_end_label(cmd_buffer);
+        }
 
-        let spin_around = Mat4::from_translation(target_point)
-            * Mat4::from_rotation_y(yaw.to_radians())
-            * Mat4::from_rotation_x(pitch.to_radians())
-            * Mat4::from_translation(Vec3::new(0.0, 0.0, radius));
+        crash_report::set_last_pass("scene");
+    }
 
-        let (_, rotation, translation) = spin_around.to_scale_rotation_translation();
+    // draws split across the parallel recorder's threads, then the overlays in one more buffer
+    // returns the secondary buffers in the order they have to be executed
+    unsafe fn record_parallel_draws(
+        &self,
+        recorder: &VKParallelRecorder,
+        target: &RenderTarget,
+        camera: &FrameCamera,
+        frame_in_flight: usize,
+        object_count: usize,
+    ) -> Result<(Vec<vk::CommandBuffer>, DrawStats), vk::Result> {
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        let inheritance = RenderingInheritance {
+            color_format: self.vulkan_ctx.vulkan_swapchain.scene_format(),
+            depth_format: DEPTH_FORMAT,
+            samples: target.samples,
+        };
+        let frustum = Frustum::from_view_projection(camera.uniform.view_projection);
 
-        let camera_mat = CameraTransforms::new(
-            100.0_f32.to_radians(),
-            aspect_ratio,
-            0.1_f32,
-            rotation,
-            translation,
-        );
+        let recorded = unsafe {
+            recorder.record(
+                vk_device,
+                frame_in_flight,
+                &inheritance,
+                object_count,
+                |cmd_buffer, range| {
+                    self.cmd_set_draw_state(cmd_buffer, target, frame_in_flight);
+                    self.record_instances(
+                        cmd_buffer,
+                        range,
+                        &frustum,
+                        frame_in_flight,
+                        camera.offset,
+                        None,
+                    )
+                },
+            )?
+        };
+        let overlays = unsafe {
+            recorder.record_last(vk_device, frame_in_flight, &inheritance, |cmd_buffer| {
+                self.cmd_set_draw_state(cmd_buffer, target, frame_in_flight);
+                self.record_overlays(cmd_buffer, target, &camera.uniform, frame_in_flight);
+            })?
+        };
 
-        unsafe {
-            let camera_mat_bytes = std::slice::from_raw_parts(
-                &camera_mat as *const CameraTransforms as *const u8,
-                size_of::<CameraTransforms>(),
-            );
+        let mut draw_stats = DrawStats::default();
+        let mut cmd_buffers = Vec::with_capacity(recorded.len() + 1);
+        for (cmd_buffer, stats) in recorded {
+            draw_stats.submitted += stats.submitted;
+            draw_stats.culled += stats.culled;
+            cmd_buffers.push(cmd_buffer);
+        }
+        cmd_buffers.push(overlays);
+        Ok((cmd_buffers, draw_stats))
+    }
 
+    // dynamic state and sets every buffer drawing the scene needs, secondary buffers don't
+    // inherit them from the primary
+    unsafe fn cmd_set_draw_state(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        target: &RenderTarget,
+        frame_in_flight: usize,
+    ) {
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        let render_area = vk::Rect2D::default().extent(target.extent);
+        let viewport = [vk::Viewport::default()
+            .width(target.extent.width as f32)
+            .height(target.extent.height as f32)
+            .max_depth(1.0)];
+        unsafe {
+            vk_device.device.cmd_set_viewport(cmd_buffer, 0, &viewport);
             vk_device
                 .device
-                .begin_command_buffer(cmd_buffer, &begin_info)
-                .unwrap();
+                .cmd_set_scissor(cmd_buffer, 0, &[render_area]);
+            if let Some(shadows) = &self.ray_query_shadows {
+                shadows.cmd_bind(vk_device, cmd_buffer, frame_in_flight);
+            }
+        }
+    }
 
-            vk_device
-                .device
-                .cmd_pipeline_barrier2(cmd_buffer, &dependency_info);
+    // draws the instances at objects that aren't outside of frustum, binds only what changes
+    // between them, an instance's object is its index in instances and the scene buffer
+    // with occlusion each draw only runs if the culler's draw at the same place in objects passed
+    unsafe fn record_instances(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        objects: impl IntoIterator<Item = usize>,
+        frustum: &Frustum,
+        frame_in_flight: usize,
+        camera_offset: u32,
+        occlusion: Option<&VKOcclusionCuller>,
+    ) -> DrawStats {
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        let mut draw_stats = DrawStats::default();
+        let mut bound_material = None;
+        let mut bound_pipeline = None;
+        let mut bound_mesh = None;
 
-            vk_device
-                .device
-                .cmd_begin_rendering(cmd_buffer, &rendering_info);
+        unsafe {
+            for (draw, object) in (0..).zip(objects) {
+                // past the scene buffer's capacity there's nothing for the shader to read
+                if object >= self.scene_objects.len() {
+                    continue;
+                }
+                let instance = &self.instances[object];
+                let Some(mesh) = self.meshes.get(instance.mesh) else {
+                    continue;
+                };
+
+                // skip instances completely outside the camera
+                if !frustum.intersects_aabb(&mesh.bounds.transformed(&instance.transform)) {
+                    draw_stats.culled += 1;
+                    continue;
+                }
+
+                if bound_mesh != Some(instance.mesh) {
+                    mesh.cmd_bind(vk_device, cmd_buffer);
+                    bound_mesh = Some(instance.mesh);
+                }
+
+                // unknown materials draw with the default rather than not at all
+                let material_id = if instance.material < self.materials.len() {
+                    instance.material
+                } else {
+                    DEFAULT_MATERIAL
+                };
+                let animated = self.skinning.is_animated(instance.mesh);
+
+                if bound_material != Some((material_id, animated)) {
+                    self.cmd_bind_material(
+                        cmd_buffer,
+                        material_id,
+                        animated,
+                        frame_in_flight,
+                        camera_offset,
+                        &mut bound_pipeline,
+                    );
+                    bound_material = Some((material_id, animated));
+                }
+
+                // the vertex stage finds its transform and tint at the first instance
+                match occlusion {
+                    Some(occlusion) => {
+                        occlusion.cmd_if_visible(vk_device, cmd_buffer, draw, || {
+                            mesh.cmd_draw(vk_device, cmd_buffer, 1, object as u32)
+                        });
+                    }
+                    None => mesh.cmd_draw(vk_device, cmd_buffer, 1, object as u32),
+                }
+                draw_stats.submitted += 1;
+            }
+        }
+        draw_stats
+    }
+
+    // the batches gpu_culling's culler kept for this frame, then the instances it left to the cpu
+    // the gpu decides what is culled, so every batched instance counts as submitted
+    unsafe fn record_culled_batches(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        gpu_culling: &VKGpuCulling,
+        frustum: &Frustum,
+        frame_in_flight: usize,
+        camera_offset: u32,
+    ) -> DrawStats {
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        let culler = &gpu_culling.cullers[frame_in_flight];
+        let mut bound_pipeline = None;
+        let mut submitted = 0;
+
+        unsafe {
+            for (batch, draw_batch) in (0..).zip(&gpu_culling.batches) {
+                self.meshes[draw_batch.mesh].cmd_bind(vk_device, cmd_buffer);
+                self.cmd_bind_material(
+                    cmd_buffer,
+                    draw_batch.material,
+                    self.skinning.is_animated(draw_batch.mesh),
+                    frame_in_flight,
+                    camera_offset,
+                    &mut bound_pipeline,
+                );
+                culler.cmd_draw_batch(
+                    vk_device,
+                    cmd_buffer,
+                    batch,
+                    draw_batch.first,
+                    draw_batch.len,
+                );
+                submitted += draw_batch.len;
+            }
+
+            let mut draw_stats = self.record_instances(
+                cmd_buffer,
+                gpu_culling
+                    .cpu_objects
+                    .iter()
+                    .map(|object| *object as usize),
+                frustum,
+                frame_in_flight,
+                camera_offset,
+                Some(&gpu_culling.occlusion[frame_in_flight]),
+            );
+            draw_stats.submitted += submitted;
+            draw_stats
+        }
+    }
+
+    // binds material_id's set with the camera at camera_offset and pushes its parameters,
+    // its pipeline for animated or plain meshes only if bound_pipeline differs
+    unsafe fn cmd_bind_material(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        material_id: MaterialId,
+        animated: bool,
+        frame_in_flight: usize,
+        camera_offset: u32,
+        bound_pipeline: &mut Option<vk::Pipeline>,
+    ) {
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        let material = &self.materials[material_id];
+        let pipeline = match animated {
+            true => material.animated_pipeline,
+            false => material.pipeline,
+        };
+        unsafe {
+            // materials of the same variant share a pipeline
+            if *bound_pipeline != Some(pipeline) {
+                vk_device.device.cmd_bind_pipeline(
+                    cmd_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+                *bound_pipeline = Some(pipeline);
+            }
 
-            vk_device.device.cmd_bind_pipeline(
+            vk_device.device.cmd_bind_descriptor_sets(
                 cmd_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
-                pipeline,
+                self.scene_pipeline_layout(),
+                0,
+                &[material.descriptor_sets[frame_in_flight]],
+                &[camera_offset],
             );
 
-            vk_device
-                .device
-                .cmd_bind_vertex_buffers(cmd_buffer, 0, &[vertex_buffer], &[0u64]);
+            self.cmd_push_constants(
+                cmd_buffer,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                &material.params,
+            );
+        }
+    }
 
-            vk_device.device.cmd_set_viewport(cmd_buffer, 0, &viewport);
+    // dev builds draw the errors of every shader that failed to compile over the frame until it
+    // compiles, at the top left of the screen whatever the 2d camera is looking at
+    fn shader_diagnostic_sprites(&self) -> Vec<Sprite> {
+        let diagnostics = &self.vulkan_shader_loader.diagnostics;
+        if !cfg!(debug_assertions) || diagnostics.is_empty() {
+            return Vec::new();
+        }
+        let text: String = diagnostics
+            .iter()
+            .map(|(_, diagnostic)| diagnostic.to_string())
+            .collect();
+
+        let camera = self.renderer2d.scaled_camera();
+        let scale = self.renderer2d.ui_scale / camera.zoom;
+        let position = camera.offset + Vec2::splat(8.0) * scale;
+        let mut sprites = vec![DebugFont::backdrop(
+            &text,
+            position,
+            scale,
+            4.0,
+            LinearRgba::new(0.0, 0.0, 0.0, 0.8),
+            i32::MAX - 1,
+        )];
+        sprites.extend(self.debug_font.layout(
+            &text,
+            position,
+            scale,
+            LinearRgba::rgb(1.0, 0.35, 0.3),
+            i32::MAX,
+        ));
+        sprites
+    }
 
-            vk_device
-                .device
-                .cmd_set_scissor(cmd_buffer, 0, &[render_area_extent]);
+    // skybox, debug lines and sprites, drawn after the instances
+    unsafe fn record_overlays(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        target: &RenderTarget,
+        camera: &CameraUniform,
+        frame_in_flight: usize,
+    ) {
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        let ray_traced = self.ray_tracer.is_some() && self.render_mode == RenderMode::RayTraced;
+        unsafe {
+            // after opaque geometry so covered sky pixels fail the depth test instead of being shaded
+            if !ray_traced {
+                self.skybox.record(vk_device, cmd_buffer, camera);
+            }
+
+            self.debug_renderer
+                .record(vk_device, cmd_buffer, frame_in_flight, camera);
+
+            self.renderer2d
+                .record(vk_device, cmd_buffer, frame_in_flight, target);
+        }
+    }
+
+    /// Starts a labelled region shown by graphics debuggers, does nothing without debug labels
+    /// Example Use:
+    /// ```ignore
+    /// renderer.cmd_begin_label(cmd_buffer, c"Particles", LinearRgba::rgb(1.0, 0.5, 0.0));
+    /// // record particles
+    /// renderer.cmd_end_label(cmd_buffer);
+    /// ```
+    /// # Safety
+    /// cmd_buffer must be recording
+    pub unsafe fn cmd_begin_label(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        name: &CStr,
+        color: LinearRgba,
+    ) {
+        if let Some(debug_labels) = &self.debug_labels {
+            unsafe { debug_labels.begin(cmd_buffer, name, color) };
+        }
+    }
+
+    // measures one of PERF_PASSES when performance counters are enabled
+    unsafe fn cmd_begin_perf_pass(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        frame_in_flight: usize,
+        pass: usize,
+    ) {
+        if let Some(perf_queries) = &self.perf_queries {
+            let vk_device = &self.vulkan_ctx.vulkan_device;
+            unsafe { perf_queries.cmd_begin(vk_device, cmd_buffer, frame_in_flight, pass) };
+        }
+    }
+
+    unsafe fn cmd_end_perf_pass(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        frame_in_flight: usize,
+        pass: usize,
+    ) {
+        if let Some(perf_queries) = &self.perf_queries {
+            let vk_device = &self.vulkan_ctx.vulkan_device;
+            unsafe { perf_queries.cmd_end(vk_device, cmd_buffer, frame_in_flight, pass) };
+        }
+    }
+
+    /// Ends the region started by the last cmd_begin_label
+    /// # Safety
+    /// cmd_buffer must be recording inside a labelled region
+    pub unsafe fn cmd_end_label(&self, cmd_buffer: vk::CommandBuffer) {
+        if let Some(debug_labels) = &self.debug_labels {
+            unsafe { debug_labels.end(cmd_buffer) };
+        }
+    }
+
+    /// Single marker rather than a region
+    /// # Safety
+    /// cmd_buffer must be recording
+    pub unsafe fn cmd_insert_label(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        name: &CStr,
+        color: LinearRgba,
+    ) {
+        if let Some(debug_labels) = &self.debug_labels {
+            unsafe { debug_labels.insert(cmd_buffer, name, color) };
+        }
+    }
+
+    /// Writes constants into the push constant range declared at offset for stage_flags
+    /// # Safety
+    /// cmd_buffer must be recording with a pipeline using pipeline_layout bound
+    pub unsafe fn cmd_push_constants<T: Copy>(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        stage_flags: vk::ShaderStageFlags,
+        offset: u32,
+        constants: &T,
+    ) {
+        debug_assert!(
+            self.push_constant_ranges.iter().any(|range| {
+                range.stage_flags.contains(stage_flags)
+                    && range.offset <= offset
+                    && offset + size_of::<T>() as u32 <= range.offset + range.size
+            }),
+            "push constants not covered by a declared range"
+        );
 
-            vk_device.device.cmd_push_constants(
+        unsafe {
+            self.vulkan_ctx.vulkan_device.cmd_push_constants(
                 cmd_buffer,
-                pipeline_layout,
-                vk::ShaderStageFlags::VERTEX,
-                0,
-                camera_mat_bytes,
+                self.pipeline_layout,
+                stage_flags,
+                offset,
+                constants,
             );
+        }
+    }
 
-            vk_device.device.cmd_draw(cmd_buffer, vertices_len, 1, 0, 0);
+    // what each frame in flight's material descriptor sets point at
+    fn frame_buffer_infos(&self) -> Vec<FrameBufferInfos> {
+        (0..self.vulkan_cmd_buffs.len())
+            .map(|frame| FrameBufferInfos {
+                camera: self.camera_ring.descriptor_buffer_info(),
+                shader_inputs: self.shader_input_buffers[frame].descriptor_buffer_info(),
+                lights: self.light_buffers[frame].descriptor_buffer_info(),
+                objects: self.scene_objects.descriptor_buffer_info(frame),
+                animation: self.skinning.descriptor_buffer_infos(frame),
+            })
+            .collect()
+    }
 
-            vk_device.device.cmd_end_rendering(cmd_buffer);
+    // copies the instances that changed into the scene buffer and stages them for frame_in_flight
+    // true when anything changed, recordings that uploaded older objects can't be reused
+    // the gpu must be done with frame_in_flight
+    fn sync_scene_objects(&mut self, frame_in_flight: usize) -> bool {
+        let was_full = self.scene_objects.len() == self.scene_objects.capacity;
+        let mut changed = false;
+        for (index, instance) in self.instances.iter().enumerate() {
+            let object = ObjectData::from(instance);
+            match self.scene_objects.get(index) {
+                Some(current) if *current == object => (),
+                Some(_) => {
+                    self.scene_objects.set(index, object);
+                    changed = true;
+                }
+                None => {
+                    if self.scene_objects.push(object).is_none() {
+                        if !was_full {
+                            warn!(
+                                "{} Instances, Only The First {} Are Drawn",
+                                self.instances.len(),
+                                self.scene_objects.capacity
+                            );
+                        }
+                        break;
+                    }
+                    changed = true;
+                }
+            }
+        }
+        if let Err(error) = self.scene_objects.stage_upload(frame_in_flight) {
+            error!("Error Uploading Scene Objects: {error}");
+        }
+        changed
+    }
 
-            vk_device
-                .device
-                .cmd_pipeline_barrier2(cmd_buffer, &present_dependency_info);
+    // the lighting plus the ambient occlusion lit materials sample this frame
+    fn light_uniform(&self) -> LightUniform {
+        let mut uniform = self.lighting.uniform();
+        if let Some(view_projection) = self.ambient_occlusion.reprojection() {
+            uniform.occlusion_view_projection = view_projection;
+            uniform.occlusion = Vec4::X;
+        }
+        uniform
+    }
 
-            vk_device.device.end_command_buffer(cmd_buffer)
+    // uniform buffers are host coherent and stay mapped, so a plain write is enough
+    // gpu must not be reading the buffers of frame_in_flight
+    unsafe fn write_frame_uniforms(&self, frame_in_flight: usize) {
+        if let Some(mapped) = self.shader_input_buffers[frame_in_flight]
+            .allocation
+            .mapped_ptr()
+        {
+            unsafe {
+                mapped
+                    .cast::<ShaderInputs>()
+                    .as_ptr()
+                    .write_unaligned(self.shader_inputs)
+            };
+        }
+        if let Some(mapped) = self.light_buffers[frame_in_flight].allocation.mapped_ptr() {
+            unsafe {
+                mapped
+                    .cast::<LightUniform>()
+                    .as_ptr()
+                    .write_unaligned(self.light_uniform())
+            };
         }
     }
 }
@@ -589,39 +3921,87 @@ impl Drop for VKRenderer<'_> {
                 .device_wait_idle()
                 .unwrap_unchecked();
 
-            self.vulkan_ctx
-                .vulkan_device
-                .device
-                .destroy_pipeline(self.pipeline, None);
-
-            self.vulkan_ctx
-                .vulkan_device
-                .device
-                .destroy_pipeline_layout(self.pipeline_layout, None);
+            for material in &mut self.materials {
+                material.destroy(&mut self.vulkan_ctx.vulkan_device);
+            }
 
-            self.vulkan_ctx
-                .vulkan_device
-                .device
-                .destroy_descriptor_set_layout(self.descriptor_layout, None);
+            for (_, pipeline) in self.pipelines.drain() {
+                self.vulkan_ctx
+                    .vulkan_device
+                    .device
+                    .destroy_pipeline(pipeline, None);
+            }
 
-            // need to move it out of &mut self so it can be freed by memory allocator, achieved by replacing with empty Allocation
-            let vertex_allocation = std::mem::take(&mut self.vertex_allocation);
+            self.descriptor_allocator
+                .destroy(&self.vulkan_ctx.vulkan_device);
 
-            self.vulkan_ctx
-                .vulkan_device
-                .mem_allocator
-                .free(vertex_allocation)
-                .unwrap_unchecked();
+            self.skybox.destroy(&mut self.vulkan_ctx.vulkan_device);
+            if let Some(mut ray_tracer) = self.ray_tracer.take() {
+                ray_tracer.destroy(&mut self.vulkan_ctx.vulkan_device);
+            }
+            if let Some(mut shadows) = self.ray_query_shadows.take() {
+                shadows.destroy(&mut self.vulkan_ctx.vulkan_device);
+            }
+            if let Some(mut scene_bvh) = self.scene_bvh.take() {
+                scene_bvh.destroy(&mut self.vulkan_ctx.vulkan_device);
+            }
+            if let Some(mut gpu_culling) = self.gpu_culling.take() {
+                gpu_culling.destroy(&mut self.vulkan_ctx.vulkan_device);
+            }
+            if let Some(mut perf_queries) = self.perf_queries.take() {
+                perf_queries.destroy(&self.vulkan_ctx.vulkan_device);
+            }
 
             self.vulkan_ctx
                 .vulkan_device
                 .device
-                .destroy_buffer(self.vertex_buffer, None);
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+
+            self.texture.destroy(&mut self.vulkan_ctx.vulkan_device);
+            self.ambient_occlusion
+                .destroy(&mut self.vulkan_ctx.vulkan_device);
+            self.bloom.destroy(&mut self.vulkan_ctx.vulkan_device);
+            self.tonemap.destroy(&mut self.vulkan_ctx.vulkan_device);
+            self.retro.destroy(&mut self.vulkan_ctx.vulkan_device);
+            self.renderer2d.destroy(&mut self.vulkan_ctx.vulkan_device);
+            self.debug_renderer
+                .destroy(&mut self.vulkan_ctx.vulkan_device);
+            if let Some(mut internal_target) = self.internal_target.take() {
+                internal_target.destroy(&mut self.vulkan_ctx.vulkan_device);
+            }
+            for mut render_texture in self.render_textures.drain(..) {
+                render_texture.destroy(&mut self.vulkan_ctx.vulkan_device);
+            }
+            self.flat_normal_texture
+                .destroy(&mut self.vulkan_ctx.vulkan_device);
+
+            self.camera_ring.destroy(&mut self.vulkan_ctx.vulkan_device);
+            for mut buffer in self
+                .shader_input_buffers
+                .drain(..)
+                .chain(self.light_buffers.drain(..))
+            {
+                buffer.destroy(&mut self.vulkan_ctx.vulkan_device);
+            }
+            self.scene_objects
+                .destroy(&mut self.vulkan_ctx.vulkan_device);
+            self.skinning.destroy(&mut self.vulkan_ctx.vulkan_device);
+
+            self.uploader.destroy(&mut self.vulkan_ctx.vulkan_device);
+            if let Some(recorder) = &mut self.parallel_recorder {
+                recorder.destroy(&self.vulkan_ctx.vulkan_device);
+            }
+            for mesh in &mut self.meshes {
+                mesh.destroy(&mut self.vulkan_ctx.vulkan_device);
+            }
 
             self.fragment_shader.destroy(&self.vulkan_ctx.vulkan_device);
             self.vertex_shader.destroy(&self.vulkan_ctx.vulkan_device);
 
-            self.vulkan_present.destroy(&self.vulkan_ctx);
+            for (_, mut window_target) in self.windows.drain() {
+                window_target.destroy(&mut self.vulkan_ctx);
+            }
+            self.vulkan_present.destroy(&self.vulkan_ctx.vulkan_device);
 
             self.vulkan_ctx
                 .vulkan_device
@@ -632,305 +4012,345 @@ impl Drop for VKRenderer<'_> {
     }
 }
 
-// Repr C here so that rust does not change the order on compile and it is what vulkan expects
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
-struct Vertex {
-    pos: Vec3,
-    color: Vec3,
+// a render texture drawn this frame, with what its passes need kept alive for the graph
+struct RenderTextureView<'a> {
+    render_texture: &'a VKRenderTexture,
+    target: RenderTarget,
+    camera: FrameCamera,
 }
 
-impl Vertex {
-    const fn new(pos: Vec3, color: Vec3) -> Self {
-        Self { pos, color }
-    }
-
-    // vulkan information for layout in memory
-    fn binding_description() -> vk::VertexInputBindingDescription {
-        vk::VertexInputBindingDescription::default()
-            .binding(0)
-            .stride(size_of::<Vertex>() as u32)
-            .input_rate(vk::VertexInputRate::VERTEX)
-    }
+// a camera pushed into camera_ring, draws from it bind set 0 with offset
+#[derive(Clone, Copy, Debug)]
+struct FrameCamera {
+    uniform: CameraUniform,
+    offset: u32,
+}
 
-    // vulkan information for the sub elements in memory
-    fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
-        let pos = vk::VertexInputAttributeDescription::default()
-            .binding(0)
-            .location(0)
-            .format(vk::Format::R32G32B32_SFLOAT)
-            .offset(0);
-        let color = vk::VertexInputAttributeDescription::default()
-            .binding(0)
-            .location(1)
-            .format(vk::Format::R32G32B32_SFLOAT)
-            .offset(size_of::<Vec3>() as u32);
-        [pos, color]
-    }
+// the frame's own camera and those of the render textures drawn before it, in due order
+struct FrameCameras {
+    main: FrameCamera,
+    render_textures: Vec<FrameCamera>,
 }
 
-// Repr C here so that rust does not change the order on compile and it is what vulkan expects
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
-struct CameraTransforms {
-    view_projection: Mat4,
+/// Instances the scene pass drew or skipped for being outside the camera
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DrawStats {
+    pub submitted: u32,
+    pub culled: u32,
 }
 
-impl CameraTransforms {
-    fn new(
-        fov: f32,
-        aspect_ratio: f32,
-        z_near: f32,
-        rotation: glam::Quat,
-        translation: Vec3,
-    ) -> Self {
-        let mut projection = Mat4::perspective_infinite_reverse_rh(fov, aspect_ratio, z_near);
-        projection.y_axis.y *= -1.0;
-        let transform = Mat4::from_rotation_translation(rotation, translation).inverse();
-        let view_projection = projection * transform;
-        Self { view_projection }
+impl DrawStats {
+    /// Share of the instances that were culled, 0 when there were none
+    pub fn culled_fraction(&self) -> f32 {
+        let total = self.submitted + self.culled;
+        if total == 0 {
+            0.0
+        } else {
+            self.culled as f32 / total as f32
+        }
     }
 }
 
-// this is just for learning it will be split up and organised and made more universal/generic.
-fn create_vertex_buffer(
-    vk_device: &mut VKDevice,
-    vk_command_pool: &vk::CommandPool,
-    vertices: &[Vertex],
-) -> Result<(vk::Buffer, vulkan::Allocation), vk::Result> {
-    // create a staging buffer
-
-    let vk_info = vk::BufferCreateInfo::default()
-        .usage(vk::BufferUsageFlags::TRANSFER_SRC)
-        .size((size_of::<Vertex>() * vertices.len()) as u64)
-        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+/// How VKRenderer draws the scene
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RenderMode {
+    /// instances drawn with their material's pipeline
+    #[default]
+    Rasterized,
+    /// one ray per pixel through a BVH of the instances, needs VK_KHR_ray_tracing_pipeline
+    /// and shaders/raytrace.spv, dev builds compile shaders/raytrace.slang with slangc without it
+    RayTraced,
+}
 
-    let staging_buffer = unsafe { vk_device.device.create_buffer(&vk_info, None)? };
+/// A mesh placed in the world
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MeshInstance {
+    pub mesh: MeshId,
+    /// world matrix
+    pub transform: Mat4,
+    /// multiplied with the vertex colours
+    pub tint: LinearRgba,
+    pub material: MaterialId,
+    /// pose of a mesh with a skin or baked clips, see VKSkinning
+    #[serde(default)]
+    pub animation: Option<InstanceAnimation>,
+}
 
-    let requirments = unsafe {
-        vk_device
-            .device
-            .get_buffer_memory_requirements(staging_buffer)
-    };
+impl Default for MeshInstance {
+    fn default() -> Self {
+        Self {
+            mesh: CUBE_MESH,
+            transform: Mat4::IDENTITY,
+            tint: LinearRgba::WHITE,
+            material: DEFAULT_MATERIAL,
+            animation: None,
+        }
+    }
+}
 
-    // allocate memory for staging buffer
+/// An instance as the scene's vertex stage sees it, found at the draw's first instance
+/// matches ObjectData in triangle.slang
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ObjectData {
+    pub model: Mat4,
+    pub tint: Vec4,
+}
 
-    let mut staging_allocation = vk_device
-        .mem_allocator
-        .allocate(&vulkan::AllocationCreateDesc {
-            name: "Vertecies Staging",
-            requirements: requirments,
-            location: MemoryLocation::CpuToGpu,
-            linear: true,
-            allocation_scheme: vulkan::AllocationScheme::DedicatedBuffer(staging_buffer),
-        })
-        .unwrap();
+impl From<&MeshInstance> for ObjectData {
+    fn from(instance: &MeshInstance) -> Self {
+        Self {
+            model: instance.transform,
+            tint: instance.tint.to_vec4(),
+        }
+    }
+}
 
-    // bind staging buffer to memory
+// a frame in flight's buffers in a material's descriptor set
+#[derive(Clone, Copy)]
+struct FrameBufferInfos {
+    camera: vk::DescriptorBufferInfo,
+    shader_inputs: vk::DescriptorBufferInfo,
+    lights: vk::DescriptorBufferInfo,
+    objects: vk::DescriptorBufferInfo,
+    // bindings 7, 8 and 9
+    animation: [vk::DescriptorBufferInfo; 3],
+}
 
-    unsafe {
-        vk_device.device.bind_buffer_memory(
-            staging_buffer,
-            staging_allocation.memory(),
-            staging_allocation.offset(),
-        )?
+// points a material's set for each frame in flight at its textures and that frame's uniforms
+// colour and depth attachments of a scene pass into target, depth is always cleared to the far plane
+// with msaa the samples are averaged into the target image and then thrown away
+fn scene_attachments(
+    target: &RenderTarget,
+    color_load_op: vk::AttachmentLoadOp,
+    clear_color: LinearRgba,
+    depth_convention: DepthConvention,
+) -> (
+    [vk::RenderingAttachmentInfo<'static>; 1],
+    vk::RenderingAttachmentInfo<'static>,
+) {
+    let color_attachment = vk::RenderingAttachmentInfo::default()
+        .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .load_op(color_load_op)
+        .clear_value(vk::ClearValue {
+            color: clear_color.into(),
+        });
+
+    let color_attachments = if target.samples != vk::SampleCountFlags::TYPE_1 {
+        [color_attachment
+            .image_view(target.msaa_image_view)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .resolve_mode(vk::ResolveModeFlags::AVERAGE)
+            .resolve_image_view(target.image_view)
+            .resolve_image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)]
+    } else {
+        [color_attachment
+            .image_view(target.image_view)
+            .store_op(vk::AttachmentStoreOp::STORE)]
     };
 
-    // copy vertecies into staging buffer
-    // non 0 start offset issue?
-
-    let _copy_info = presser::copy_from_slice_to_offset_with_align(
-        &vertices,
-        &mut staging_allocation,
-        0,
-        requirments.alignment as usize,
-    )
-    .unwrap();
-
-    //info!("Vertex Memory Offset: {}", copy_info.copy_start_offset);
-
-    // create vertex buffer
+    let depth_attachment = vk::RenderingAttachmentInfo::default()
+        .image_view(target.depth_image_view)
+        .image_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .clear_value(depth_clear_value(depth_convention));
 
-    let vk_info = vk::BufferCreateInfo::default()
-        .usage(vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER)
-        .size((size_of::<Vertex>() * vertices.len()) as u64)
-        .sharing_mode(vk::SharingMode::EXCLUSIVE);
-
-    let vertex_buffer = unsafe { vk_device.device.create_buffer(&vk_info, None)? };
-
-    let requirments = unsafe {
-        vk_device
-            .device
-            .get_buffer_memory_requirements(vertex_buffer)
-    };
-
-    // allocate memory for vertex buffer
+    (color_attachments, depth_attachment)
+}
 
-    let vertices_allocation = vk_device
-        .mem_allocator
-        .allocate(&vulkan::AllocationCreateDesc {
-            name: "Vertices",
-            requirements: requirments,
-            location: MemoryLocation::GpuOnly,
-            linear: true,
-            allocation_scheme: vulkan::AllocationScheme::DedicatedBuffer(vertex_buffer),
+fn write_descriptor_sets(
+    vk_device: &VKDevice,
+    descriptor_sets: &[vk::DescriptorSet],
+    texture: &VKTexture,
+    normal_texture: &VKTexture,
+    occlusion: vk::DescriptorImageInfo,
+    frame_buffers: &[FrameBufferInfos],
+) {
+    let image_infos = [texture.descriptor_image_info()];
+    let normal_image_infos = [normal_texture.descriptor_image_info()];
+    let occlusion_image_infos = [occlusion];
+    // each frame's uniform buffers in binding order from 1
+    let buffer_infos: Vec<_> = frame_buffers
+        .iter()
+        .map(|buffers| {
+            [buffers.camera, buffers.shader_inputs, buffers.lights].map(|buffer_info| [buffer_info])
         })
-        .unwrap();
-
-    // bind vertex buffer to memory
-
-    unsafe {
-        vk_device.device.bind_buffer_memory(
-            vertex_buffer,
-            vertices_allocation.memory(),
-            vertices_allocation.offset(),
-        )?
-    };
-
-    // copy staging buffer memory to vertex buffer memory
-
-    let buff_info = vk::CommandBufferAllocateInfo::default()
-        .level(vk::CommandBufferLevel::PRIMARY)
-        .command_pool(*vk_command_pool)
-        .command_buffer_count(1);
-
-    let cmd_buffer = unsafe { vk_device.device.allocate_command_buffers(&buff_info)?[0] };
-
-    let begin_info =
-        vk::CommandBufferBeginInfo::default().flags(CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-
-    let copy_region = vk::BufferCopy::default().size((size_of::<Vertex>() * vertices.len()) as u64);
-
-    let cmd_buffer_info = [vk::CommandBufferSubmitInfo::default().command_buffer(cmd_buffer)];
-    let submit_info = vk::SubmitInfo2::default().command_buffer_infos(&cmd_buffer_info);
-    unsafe {
-        vk_device
-            .device
-            .begin_command_buffer(cmd_buffer, &begin_info)?;
-
-        vk_device
-            .device
-            .cmd_copy_buffer(cmd_buffer, staging_buffer, vertex_buffer, &[copy_region]);
-
-        vk_device.device.end_command_buffer(cmd_buffer)?;
-
-        vk_device.device.queue_submit2(
-            vk_device.graphics_queue,
-            &[submit_info],
-            vk::Fence::null(),
-        )?;
-
-        // fence more flexible than queue wait idle
-        vk_device.device.queue_wait_idle(vk_device.graphics_queue)?;
-
-        // free single use command buffer
-        vk_device
-            .device
-            .free_command_buffers(*vk_command_pool, &[cmd_buffer]);
-    }
-
-    // clean up staging buffer as we no longer need it
-    vk_device.mem_allocator.free(staging_allocation).unwrap();
+        .collect();
+    let object_infos: Vec<_> = frame_buffers
+        .iter()
+        .map(|buffers| [buffers.objects])
+        .collect();
+    let animation_infos: Vec<_> = frame_buffers
+        .iter()
+        .map(|buffers| buffers.animation.map(|buffer_info| [buffer_info]))
+        .collect();
+
+    let writes: Vec<_> = descriptor_sets
+        .iter()
+        .zip(&buffer_infos)
+        .zip(&object_infos)
+        .zip(&animation_infos)
+        .flat_map(
+            |(((&descriptor_set, buffer_infos), object_infos), animation_infos)| {
+                let albedo = vk::WriteDescriptorSet::default()
+                    .dst_set(descriptor_set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&image_infos);
+                let normal_map = vk::WriteDescriptorSet::default()
+                    .dst_set(descriptor_set)
+                    .dst_binding(4)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&normal_image_infos);
+                let objects = vk::WriteDescriptorSet::default()
+                    .dst_set(descriptor_set)
+                    .dst_binding(5)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(object_infos);
+                let occlusion = vk::WriteDescriptorSet::default()
+                    .dst_set(descriptor_set)
+                    .dst_binding(6)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&occlusion_image_infos);
+                // the camera is picked with a dynamic offset
+                let uniforms = buffer_infos
+                    .iter()
+                    .zip(1..)
+                    .map(move |(buffer_info, binding)| {
+                        let descriptor_type = match binding {
+                            1 => vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+                            _ => vk::DescriptorType::UNIFORM_BUFFER,
+                        };
+                        vk::WriteDescriptorSet::default()
+                            .dst_set(descriptor_set)
+                            .dst_binding(binding)
+                            .descriptor_type(descriptor_type)
+                            .buffer_info(buffer_info)
+                    });
+                let animation = animation_write(descriptor_set, animation_infos);
+                [albedo, normal_map, objects, occlusion]
+                    .into_iter()
+                    .chain(uniforms)
+                    .chain(animation)
+            },
+        )
+        .collect();
+
+    unsafe { vk_device.device.update_descriptor_sets(&writes, &[]) };
+}
 
-    unsafe {
-        vk_device.device.destroy_buffer(staging_buffer, None);
-    };
+// the animation buffers of a frame at bindings 7 to 9 of a material's set
+fn animation_write(
+    descriptor_set: vk::DescriptorSet,
+    buffer_infos: &[[vk::DescriptorBufferInfo; 1]; 3],
+) -> impl Iterator<Item = vk::WriteDescriptorSet<'_>> {
+    buffer_infos
+        .iter()
+        .zip(7..)
+        .map(move |(buffer_info, binding)| {
+            vk::WriteDescriptorSet::default()
+                .dst_set(descriptor_set)
+                .dst_binding(binding)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(buffer_info)
+        })
+}
 
-    Ok((vertex_buffer, vertices_allocation))
+/// Push constant range sized for T
+pub fn push_constant_range<T>(
+    stage_flags: vk::ShaderStageFlags,
+    offset: u32,
+) -> vk::PushConstantRange {
+    vk::PushConstantRange::default()
+        .stage_flags(stage_flags)
+        .offset(offset)
+        .size(size_of::<T>() as u32)
 }
 
-fn create_pipeline(
+// set 0 layout and the pipeline layout shared by every material
+fn create_pipeline_layout(
     vk_device: &VKDevice,
-    vk_swapchain: &VKSwapchain,
-    vertex_stage: &vk::PipelineShaderStageCreateInfo,
-    fragment_stage: &vk::PipelineShaderStageCreateInfo,
-) -> Result<(vk::Pipeline, vk::PipelineLayout, vk::DescriptorSetLayout), vk::Result> {
-    // we wan't the viewport and scissor to be dynamic so that we don't have to recreat the pipeline when the window size changes
-    let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
-        .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
-
-    let bind_desc = [Vertex::binding_description()];
-    let attr_desc = Vertex::attribute_descriptions();
-
-    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default()
-        .vertex_binding_descriptions(&bind_desc)
-        .vertex_attribute_descriptions(&attr_desc);
-
-    //tringle list aka no vertices are shared between triangles
-    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::default()
-        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-        .primitive_restart_enable(false);
-
-    // only specify count because viewport state is dynamic
-    let viewport_state = vk::PipelineViewportStateCreateInfo::default()
-        .viewport_count(1)
-        .scissor_count(1);
-
-    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
-        .depth_clamp_enable(false)
-        .rasterizer_discard_enable(false)
-        .polygon_mode(PolygonMode::FILL)
-        .line_width(1.0)
-        .cull_mode(vk::CullModeFlags::BACK)
-        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-        .depth_bias_enable(false);
-
-    let multisample_state = vk::PipelineMultisampleStateCreateInfo::default()
-        .sample_shading_enable(false)
-        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
-
-    // depth test
-    // Greater_or_Equal is used because we are using a reversed depth buffer
-
-    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::default()
-        .depth_compare_op(CompareOp::GREATER_OR_EQUAL)
-        .depth_test_enable(true)
-        .depth_write_enable(true)
-        .depth_bounds_test_enable(false)
-        .stencil_test_enable(false);
-
-    //blending disabled Probably need alpha blending later
-    let color_blend_attachment = [vk::PipelineColorBlendAttachmentState::default()
-        .color_write_mask(vk::ColorComponentFlags::RGBA)
-        .blend_enable(false)];
-
-    let color_blend_state =
-        vk::PipelineColorBlendStateCreateInfo::default().attachments(&color_blend_attachment);
-
-    let color_attachment_formats = [vk_swapchain.capibilities.ideal_surface_format().format];
-
-    let mut rendering_info = vk::PipelineRenderingCreateInfo::default()
-        .color_attachment_formats(&color_attachment_formats)
-        .depth_attachment_format(vk::Format::D32_SFLOAT);
+    descriptor_allocator: &mut VKDescriptorAllocator,
+    push_constant_ranges: &[vk::PushConstantRange],
+) -> Result<(vk::PipelineLayout, vk::DescriptorSetLayout), vk::Result> {
+    // only 128 bytes are guaranteed
+    let max_push_constants_size = vk_device.limits.max_push_constants_size;
+    if let Some(range) = push_constant_ranges
+        .iter()
+        .find(|range| range.offset + range.size > max_push_constants_size)
+    {
+        error!(
+            "Push Constant Range {}..{} Exceeds Device Limit of {max_push_constants_size} Bytes",
+            range.offset,
+            range.offset + range.size
+        );
+        return Err(vk::Result::ERROR_INITIALIZATION_FAILED);
+    }
 
     // Move out of here
-    // this is the descriptor layout for the uniform buffer that contains the view prjoction matrix
-
-    let uniform_buffer_desc = [vk::DescriptorSetLayoutBinding::default()
-        .binding(0)
-        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-        .descriptor_count(1)
-        .stage_flags(vk::ShaderStageFlags::VERTEX)];
-
-    let descriptor_layout_info =
-        vk::DescriptorSetLayoutCreateInfo::default().bindings(&uniform_buffer_desc);
-
-    let descriptor_layout = unsafe {
-        vk_device
-            .device
-            .create_descriptor_set_layout(&descriptor_layout_info, None)?
-    };
+    // this is the descriptor layout for the albedo texture sampled in the fragment shader
+    // the camera uniform read by the vertex shader, the shader inputs for either stage
+    // the lights read by lit materials, the normal map, every instance's transform and tint and
+    // the ambient occlusion lit materials darken their ambient light by, then what animated.slang
+    // poses animated meshes with
+
+    let set_bindings = [
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        VKUniformRing::layout_binding(1, vk::ShaderStageFlags::VERTEX),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(2)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(3)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(4)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(5)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(6)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(7)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(8)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(9)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX),
+    ];
+
+    let descriptor_layout = descriptor_allocator.layout(vk_device, &set_bindings)?;
 
     let descriptor_layouts = [descriptor_layout];
 
-    let push_constant_ranges = [vk::PushConstantRange::default()
-        .stage_flags(vk::ShaderStageFlags::VERTEX)
-        .offset(0)
-        .size(std::mem::size_of::<CameraTransforms>() as u32)];
-
     let layout_info = vk::PipelineLayoutCreateInfo::default()
         .set_layouts(&descriptor_layouts)
-        .push_constant_ranges(&push_constant_ranges);
+        .push_constant_ranges(push_constant_ranges);
 
     let pipeline_layout = unsafe {
         vk_device
@@ -938,33 +4358,77 @@ fn create_pipeline(
             .create_pipeline_layout(&layout_info, None)?
     };
 
-    let stages = [*vertex_stage, *fragment_stage];
-
-    let create_infos = &[vk::GraphicsPipelineCreateInfo::default()
-        .dynamic_state(&dynamic_state)
-        .vertex_input_state(&vertex_input_state)
-        .input_assembly_state(&input_assembly_state)
-        .viewport_state(&viewport_state)
-        .rasterization_state(&rasterization_state)
-        .multisample_state(&multisample_state)
-        .depth_stencil_state(&depth_stencil_state)
-        .color_blend_state(&color_blend_state)
-        .layout(pipeline_layout)
-        .push_next(&mut rendering_info)
-        .stages(&stages)];
+    Ok((pipeline_layout, descriptor_layout))
+}
 
-    unsafe {
-        let pipline_result = vk_device.device.create_graphics_pipelines(
-            vk::PipelineCache::null(),
-            create_infos,
-            None,
-        );
+// stages usually come from the uber-shader with a material's specialization info
+// V is the vertex type of the meshes the pipeline draws
+fn create_pipeline<V: VertexLayout>(
+    vk_device: &VKDevice,
+    vk_swapchain: &VKSwapchain,
+    stages: &[vk::PipelineShaderStageCreateInfo],
+    pipeline_layout: vk::PipelineLayout,
+    cull_mode: vk::CullModeFlags,
+    depth_convention: DepthConvention,
+) -> Result<vk::Pipeline, vk::Result> {
+    GraphicsPipelineBuilder::new(stages, pipeline_layout)
+        .swapchain(vk_swapchain)
+        .vertex_layout::<V>()
+        .cull_mode(cull_mode)
+        .depth(DepthState::read_write(depth_convention))
+        .build(vk_device)
+}
 
-        // the result of create_graphics_pipeline can include the pipeleines that did get sucesfully created.
-        // this match statement just ignores that ant returns error if any of them fail
-        match pipline_result {
-            Ok(pipeline) => Ok((pipeline[0], pipeline_layout, descriptor_layout)),
-            Err(error) => Err(error.1),
-        }
-    }
+#[test]
+fn pre_rotation_test() {
+    assert_eq!(
+        pre_rotation(vk::SurfaceTransformFlagsKHR::IDENTITY),
+        Mat4::IDENTITY
+    );
+
+    let rotated = pre_rotation(vk::SurfaceTransformFlagsKHR::ROTATE_90).transform_point3(Vec3::X);
+    assert!(rotated.abs_diff_eq(Vec3::Y, 1e-6));
+
+    let target = RenderTarget {
+        image: vk::Image::null(),
+        image_view: vk::ImageView::null(),
+        depth_image: vk::Image::null(),
+        depth_image_view: vk::ImageView::null(),
+        msaa_image: vk::Image::null(),
+        msaa_image_view: vk::ImageView::null(),
+        samples: vk::SampleCountFlags::TYPE_1,
+        extent: vk::Extent2D::default().width(1080).height(1920),
+        pre_transform: vk::SurfaceTransformFlagsKHR::ROTATE_270,
+    };
+    assert_eq!(
+        target.display_extent(),
+        vk::Extent2D::default().width(1920).height(1080)
+    );
+}
+
+#[test]
+fn draw_stats_test() {
+    assert_eq!(DrawStats::default().culled_fraction(), 0.0);
+    let stats = DrawStats {
+        submitted: 3,
+        culled: 1,
+    };
+    assert_eq!(stats.culled_fraction(), 0.25);
+}
+
+#[test]
+fn instance_options_test() {
+    use crate::renderer::debug::{API_DUMP_LAYER, RENDERDOC_CAPTURE_LAYER};
+
+    let options = InstanceOptions::default()
+        .optional_layer(API_DUMP_LAYER)
+        .optional_layer(RENDERDOC_CAPTURE_LAYER)
+        .optional_layer(API_DUMP_LAYER);
+    assert_eq!(
+        options.optional_layers,
+        [
+            API_DUMP_LAYER.to_owned(),
+            RENDERDOC_CAPTURE_LAYER.to_owned()
+        ]
+    );
 }
@@ -0,0 +1,320 @@
+use ash::{Device, Instance, vk};
+use gpu_allocator::{MemoryLocation, vulkan};
+use std::any::Any;
+use std::error;
+use std::ffi::c_void;
+use std::ptr::NonNull;
+use std::slice;
+use thiserror::Error;
+
+/// Builds the allocator VKDevice hands every buffer and image to
+/// called once the logical device exists, see VKDevice::with_allocator
+pub type AllocatorFactory =
+    fn(&AllocatorContext) -> Result<Box<dyn VKAllocator>, Box<dyn error::Error>>;
+
+/// Handles an allocator needs to talk to vulkan
+pub struct AllocatorContext<'a> {
+    pub instance: &'a Instance,
+    pub device: &'a Device,
+    pub physical_device: vk::PhysicalDevice,
+    pub buffer_device_address: bool,
+}
+
+/// How the memory is going to be bound
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocationScheme {
+    /// memory only the buffer will use
+    DedicatedBuffer(vk::Buffer),
+    /// memory only the image will use
+    DedicatedImage(vk::Image),
+    /// the allocator may place the memory inside a larger block
+    Managed,
+}
+
+/// What VKAllocator::allocate is asked for
+#[derive(Clone, Copy, Debug)]
+pub struct AllocationDesc<'a> {
+    /// shown in reports and warnings
+    pub name: &'a str,
+    pub requirements: vk::MemoryRequirements,
+    pub location: MemoryLocation,
+    /// buffers and linear tiled images, kept apart from optimal tiled images
+    pub linear: bool,
+    pub scheme: AllocationScheme,
+}
+
+#[derive(Debug, Error)]
+pub enum AllocatorError {
+    #[error("out of memory")]
+    OutOfMemory,
+    #[error("no memory type suits the allocation")]
+    NoCompatibleMemoryType,
+    #[error("allocation does not belong to this allocator")]
+    ForeignAllocation,
+    #[error("{0}")]
+    Other(String),
+}
+
+impl From<gpu_allocator::AllocationError> for AllocatorError {
+    fn from(error: gpu_allocator::AllocationError) -> Self {
+        match error {
+            gpu_allocator::AllocationError::OutOfMemory => Self::OutOfMemory,
+            gpu_allocator::AllocationError::NoCompatibleMemoryTypeFound => {
+                Self::NoCompatibleMemoryType
+            }
+            error => Self::Other(error.to_string()),
+        }
+    }
+}
+
+/// Memory use summary for logging and crash reports
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocatorReport {
+    pub allocations: usize,
+    /// vkDeviceMemory objects backing the allocations
+    pub blocks: usize,
+    pub allocated_bytes: u64,
+    pub capacity_bytes: u64,
+}
+
+/// Hands out device memory for buffers and images
+/// the engine uses gpu-allocator through GpuAllocator, implement this to plug in another strategy
+/// GpuAllocator is the only backend the engine ships, others are passed to VKDevice::with_allocator
+/// Example Use:
+/// ```ignore
+/// fn create_allocator(
+///     ctx: &AllocatorContext,
+/// ) -> Result<Box<dyn VKAllocator>, Box<dyn std::error::Error>> {
+///     Ok(Box::new(MyAllocator::new(ctx.device.clone())))
+/// }
+///
+/// let vk_device = VKDevice::with_allocator(&vk_instance, &vk_surface, create_allocator)?;
+/// ```
+pub trait VKAllocator: Send + Sync {
+    fn allocate(&mut self, desc: &AllocationDesc) -> Result<VKAllocation, AllocatorError>;
+
+    /// Null allocations are ignored
+    fn free(&mut self, allocation: VKAllocation) -> Result<(), AllocatorError>;
+
+    fn report(&self) -> AllocatorReport;
+}
+
+/// A piece of device memory from a VKAllocator
+/// the backend keeps whatever it needs to free the memory inside it
+#[derive(Debug, Default)]
+pub struct VKAllocation {
+    memory: vk::DeviceMemory,
+    offset: u64,
+    size: u64,
+    memory_properties: vk::MemoryPropertyFlags,
+    mapped_ptr: Option<NonNull<c_void>>,
+    backend: Option<Box<dyn Any + Send + Sync>>,
+}
+
+// mapped_ptr points into device memory owned by the allocation, like gpu-allocator's own allocation
+unsafe impl Send for VKAllocation {}
+unsafe impl Sync for VKAllocation {}
+
+impl VKAllocation {
+    /// mapped_ptr must point at offset within memory and stay valid until the allocation is freed
+    pub fn new(
+        memory: vk::DeviceMemory,
+        offset: u64,
+        size: u64,
+        memory_properties: vk::MemoryPropertyFlags,
+        mapped_ptr: Option<NonNull<c_void>>,
+        backend: impl Any + Send + Sync,
+    ) -> Self {
+        Self {
+            memory,
+            offset,
+            size,
+            memory_properties,
+            mapped_ptr,
+            backend: Some(Box::new(backend)),
+        }
+    }
+
+    pub fn memory(&self) -> vk::DeviceMemory {
+        self.memory
+    }
+
+    /// Where the allocation starts inside memory
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Flags of the memory type the allocation landed in
+    pub fn memory_properties(&self) -> vk::MemoryPropertyFlags {
+        self.memory_properties
+    }
+
+    /// Null allocations come from Default and own no memory
+    pub fn is_null(&self) -> bool {
+        self.backend.is_none()
+    }
+
+    /// Start of the allocation when it lives in host visible memory
+    pub fn mapped_ptr(&self) -> Option<NonNull<c_void>> {
+        self.mapped_ptr
+    }
+
+    pub fn mapped_slice(&self) -> Option<&[u8]> {
+        self.mapped_ptr
+            .map(|ptr| unsafe { slice::from_raw_parts(ptr.as_ptr().cast(), self.size as usize) })
+    }
+
+    pub fn mapped_slice_mut(&mut self) -> Option<&mut [u8]> {
+        self.mapped_ptr.map(|ptr| unsafe {
+            slice::from_raw_parts_mut(ptr.as_ptr().cast(), self.size as usize)
+        })
+    }
+
+    /// The backend's own handle, for VKAllocator implementations
+    pub fn backend<T: Any>(&self) -> Option<&T> {
+        self.backend.as_ref()?.downcast_ref()
+    }
+
+    /// Takes the backend's handle back out when freeing
+    /// gives the allocation back unchanged if the handle isn't a T
+    pub fn into_backend<T: Any>(mut self) -> Result<T, Self> {
+        match self.backend.take().map(|backend| backend.downcast::<T>()) {
+            Some(Ok(backend)) => Ok(*backend),
+            Some(Err(backend)) => {
+                self.backend = Some(backend);
+                Err(self)
+            }
+            None => Err(self),
+        }
+    }
+}
+
+// same contract as gpu-allocator's allocation, copying into unmapped memory panics
+unsafe impl presser::Slab for VKAllocation {
+    fn base_ptr(&self) -> *const u8 {
+        self.mapped_ptr
+            .expect("tried to use a non-mapped Allocation as a Slab")
+            .as_ptr()
+            .cast()
+    }
+
+    fn base_ptr_mut(&mut self) -> *mut u8 {
+        self.mapped_ptr
+            .expect("tried to use a non-mapped Allocation as a Slab")
+            .as_ptr()
+            .cast()
+    }
+
+    fn size(&self) -> usize {
+        self.size as usize
+    }
+}
+
+/// The default VKAllocator, backed by gpu-allocator
+pub struct GpuAllocator {
+    pub allocator: vulkan::Allocator,
+}
+
+impl GpuAllocator {
+    pub fn new(ctx: &AllocatorContext) -> Result<Self, gpu_allocator::AllocationError> {
+        let allocator = vulkan::Allocator::new(&vulkan::AllocatorCreateDesc {
+            instance: ctx.instance.clone(),
+            device: ctx.device.clone(),
+            physical_device: ctx.physical_device,
+            debug_settings: Default::default(),
+            buffer_device_address: ctx.buffer_device_address,
+            allocation_sizes: Default::default(),
+        })?;
+        Ok(Self { allocator })
+    }
+
+    /// AllocatorFactory for VKDevice::with_allocator
+    pub fn create(ctx: &AllocatorContext) -> Result<Box<dyn VKAllocator>, Box<dyn error::Error>> {
+        Ok(Box::new(Self::new(ctx)?))
+    }
+}
+
+impl VKAllocator for GpuAllocator {
+    fn allocate(&mut self, desc: &AllocationDesc) -> Result<VKAllocation, AllocatorError> {
+        let allocation = self.allocator.allocate(&vulkan::AllocationCreateDesc {
+            name: desc.name,
+            requirements: desc.requirements,
+            location: desc.location,
+            linear: desc.linear,
+            allocation_scheme: match desc.scheme {
+                AllocationScheme::DedicatedBuffer(buffer) => {
+                    vulkan::AllocationScheme::DedicatedBuffer(buffer)
+                }
+                AllocationScheme::DedicatedImage(image) => {
+                    vulkan::AllocationScheme::DedicatedImage(image)
+                }
+                AllocationScheme::Managed => vulkan::AllocationScheme::GpuAllocatorManaged,
+            },
+        })?;
+
+        let memory = unsafe { allocation.memory() };
+        Ok(VKAllocation::new(
+            memory,
+            allocation.offset(),
+            allocation.size(),
+            allocation.memory_properties(),
+            allocation.mapped_ptr(),
+            allocation,
+        ))
+    }
+
+    fn free(&mut self, allocation: VKAllocation) -> Result<(), AllocatorError> {
+        if allocation.is_null() {
+            return Ok(());
+        }
+        let allocation = allocation
+            .into_backend::<vulkan::Allocation>()
+            .map_err(|_| AllocatorError::ForeignAllocation)?;
+        Ok(self.allocator.free(allocation)?)
+    }
+
+    fn report(&self) -> AllocatorReport {
+        let report = self.allocator.generate_report();
+        AllocatorReport {
+            allocations: report.allocations.len(),
+            blocks: report.blocks.len(),
+            allocated_bytes: report.total_allocated_bytes,
+            capacity_bytes: report.total_capacity_bytes,
+        }
+    }
+}
+
+#[test]
+fn allocation_test() {
+    let null = VKAllocation::default();
+    assert!(null.is_null());
+    assert!(null.mapped_slice().is_none());
+
+    let mut memory = vec![0u8; 16];
+    let mapped_ptr = NonNull::new(memory.as_mut_ptr().cast::<c_void>());
+    let mut allocation = VKAllocation::new(
+        vk::DeviceMemory::null(),
+        0,
+        16,
+        vk::MemoryPropertyFlags::HOST_VISIBLE,
+        mapped_ptr,
+        7_usize,
+    );
+    assert!(!allocation.is_null());
+    assert_eq!(allocation.backend::<usize>(), Some(&7));
+    assert_eq!(allocation.backend::<u32>(), None);
+
+    presser::copy_from_slice_to_offset(&[1u32, 2], &mut allocation, 4).unwrap();
+    assert_eq!(
+        &allocation.mapped_slice().unwrap()[4..8],
+        &1u32.to_ne_bytes()
+    );
+
+    // the wrong backend type leaves the allocation intact for the right one
+    let allocation = allocation.into_backend::<u32>().unwrap_err();
+    assert_eq!(allocation.into_backend::<usize>().unwrap(), 7);
+}
@@ -0,0 +1,652 @@
+use ash::util::read_spv;
+use ash::{Device, Instance, vk};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::Path;
+use std::{error, io};
+use thiserror::Error;
+
+use crate::renderer::push_constant_range;
+
+/// Compiled from benchmark.slang
+pub const BENCHMARK_SHADER: &str = "shaders/benchmark.spv";
+
+/// File in the cache directory measured scores are kept in, see InstanceOptions::device_benchmarks
+pub const DEVICE_SCORES_FILE: &str = "device_scores.ron";
+
+/// Added to measured scores so a measured device always outranks one only scored by heuristics
+pub const MEASURED_SCORE_BASE: u64 = 1 << 32;
+
+// bytes copied by the transfer workload, less on devices with small heaps
+const TRANSFER_BYTES: u64 = 64 << 20;
+
+// threads dispatched by the compute workload, 64 to a workgroup, and the loop each runs
+const COMPUTE_THREADS: u32 = 64 * 1024;
+const COMPUTE_ITERATIONS: u32 = 4096;
+// two float4 fused multiply adds per iteration in benchmark.slang
+const FLOPS_PER_ITERATION: u64 = 16;
+
+// each workload runs this many times and the fastest counts, the first run wakes clocks up
+const BENCHMARK_RUNS: usize = 3;
+
+#[derive(Debug, Error)]
+pub enum BenchmarkError {
+    #[error("benchmark failed: {0}")]
+    Vulkan(#[from] vk::Result),
+    #[error("failed to read {BENCHMARK_SHADER}: {0}")]
+    Shader(#[from] io::Error),
+    #[error("device can't run the benchmark: {0}")]
+    Unsupported(&'static str),
+}
+
+#[derive(Debug, Error)]
+pub enum DeviceScoresError {
+    #[error("failed to access device scores: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to parse device scores: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("failed to write device scores: {0}")]
+    Serialize(#[from] ron::Error),
+}
+
+// matches BenchmarkConstants in benchmark.slang
+#[repr(C)]
+struct BenchmarkConstants {
+    iterations: u32,
+}
+
+/// How fast a device ran the benchmark workloads
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeviceBenchmark {
+    /// single precision, counting a fused multiply add as two
+    pub compute_gflops: f64,
+    /// copying between device local buffers
+    pub transfer_gbps: f64,
+}
+
+impl DeviceBenchmark {
+    /// Geometric mean of the workloads so neither outweighs the other, higher is better
+    pub fn score(&self) -> u64 {
+        ((self.compute_gflops * self.transfer_gbps).max(0.0).sqrt() * 100.0) as u64
+    }
+}
+
+/// Name, ids and driver version, a driver update measures the device again
+pub fn device_key(properties: &vk::PhysicalDeviceProperties) -> String {
+    format!(
+        "{} {:04x}:{:04x} driver {:x}",
+        properties
+            .device_name_as_c_str()
+            .unwrap_or_default()
+            .to_string_lossy(),
+        properties.vendor_id,
+        properties.device_id,
+        properties.driver_version
+    )
+}
+
+/// Benchmarks of every device measured so far, saved so they only run at first launch
+/// Example Use:
+/// ```ignore
+/// let mut scores = DeviceScores::load(Some(Path::new("cache")));
+/// // benchmarked now unless it was on an earlier run
+/// let score = scores.score(&instance, physical_device);
+/// scores.save(Path::new("cache"))?;
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DeviceScores {
+    /// by device_key, None for devices the benchmark couldn't run on so they aren't tried again
+    pub devices: BTreeMap<String, Option<DeviceBenchmark>>,
+    // something was measured since loading
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl DeviceScores {
+    /// Scores saved in directory, empty when there are none or they can't be read
+    pub fn load(directory: Option<&Path>) -> Self {
+        let Some(path) = directory.map(|directory| directory.join(DEVICE_SCORES_FILE)) else {
+            return Self::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(source) => Self::from_ron(&source).unwrap_or_else(|error| {
+                warn!("Device Scores Discarded: {error}");
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, DeviceScoresError> {
+        Ok(ron::from_str(source)?)
+    }
+
+    pub fn to_ron(&self) -> Result<String, DeviceScoresError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    /// Writes the scores to directory, creating it if needed
+    pub fn save(&self, directory: &Path) -> Result<(), DeviceScoresError> {
+        fs::create_dir_all(directory)?;
+        Ok(fs::write(
+            directory.join(DEVICE_SCORES_FILE),
+            self.to_ron()?,
+        )?)
+    }
+
+    /// Whether a device was measured since the scores were loaded
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    /// Measured score of physical_device, benchmarked the first time it's asked for
+    /// None when the benchmark couldn't run on it
+    pub fn score(
+        &mut self,
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> Option<u64> {
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let key = device_key(&properties);
+        if let Some(benchmark) = self.devices.get(&key) {
+            return benchmark.map(|benchmark| benchmark.score());
+        }
+
+        let benchmark = self.measure(instance, physical_device, key);
+        benchmark.map(|benchmark| benchmark.score())
+    }
+
+    /// Benchmarks every device again, whether or not they were measured before
+    pub fn measure_all(&mut self, instance: &Instance) -> Result<(), vk::Result> {
+        for physical_device in unsafe { instance.enumerate_physical_devices()? } {
+            let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+            self.measure(instance, physical_device, device_key(&properties));
+        }
+        Ok(())
+    }
+
+    fn measure(
+        &mut self,
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        key: String,
+    ) -> Option<DeviceBenchmark> {
+        let benchmark = match benchmark_device(instance, physical_device) {
+            Ok(benchmark) => {
+                info!(
+                    "Benchmarked {key}: {:.0} GFLOPS, {:.1} GB/s, Score {}",
+                    benchmark.compute_gflops,
+                    benchmark.transfer_gbps,
+                    benchmark.score()
+                );
+                Some(benchmark)
+            }
+            // the device is fine, measure it once the shader is back
+            Err(error @ BenchmarkError::Shader(_)) => {
+                warn!("Failed to Benchmark {key}: {error}");
+                return None;
+            }
+            Err(error) => {
+                warn!("Failed to Benchmark {key}: {error}");
+                None
+            }
+        };
+        self.devices.insert(key, benchmark);
+        self.changed = true;
+        benchmark
+    }
+}
+
+/// Times the compute and transfer workloads on physical_device with a short lived device of its own
+/// blocks until they finish, well under a second on anything that can run the engine
+pub fn benchmark_device(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> Result<DeviceBenchmark, BenchmarkError> {
+    let spirv = read_spv(&mut File::open(BENCHMARK_SHADER)?)?;
+    let mut device = BenchmarkDevice::new(instance, physical_device)?;
+    let benchmark = device.run(&spirv);
+    unsafe { device.destroy() };
+    benchmark
+}
+
+// queue family that can dispatch compute and write timestamps, transfers work on any of them
+fn benchmark_queue_family(families: &[vk::QueueFamilyProperties]) -> Option<u32> {
+    families
+        .iter()
+        .position(|family| {
+            family.queue_flags.contains(vk::QueueFlags::COMPUTE) && family.timestamp_valid_bits > 0
+        })
+        .map(|index| index as u32)
+}
+
+// device local when the buffer can be, otherwise anything it fits in
+fn benchmark_memory_type(
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    type_bits: u32,
+) -> Option<u32> {
+    let allowed = |index: &u32| type_bits & (1 << index) != 0;
+    (0..memory_properties.memory_type_count)
+        .filter(allowed)
+        .find(|&index| {
+            memory_properties.memory_types[index as usize]
+                .property_flags
+                .contains(vk::MemoryPropertyFlags::DEVICE_LOCAL)
+        })
+        .or_else(|| (0..memory_properties.memory_type_count).find(allowed))
+}
+
+// bytes the transfer workload copies, an eighth of the largest device local heap at most
+fn transfer_size(memory_properties: &vk::PhysicalDeviceMemoryProperties) -> u64 {
+    let largest_heap = memory_properties.memory_heaps
+        [..memory_properties.memory_heap_count as usize]
+        .iter()
+        .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|heap| heap.size)
+        .max()
+        .unwrap_or(0);
+    // the compute workload writes a float4 per thread into the same buffer
+    let results_size = COMPUTE_THREADS as u64 * 16;
+    TRANSFER_BYTES.min(largest_heap / 8).max(results_size)
+}
+
+// a logical device with just what the workloads need, only core 1.0 commands so any candidate
+// can be measured, handles are null until created and destroy skips those
+struct BenchmarkDevice {
+    device: Device,
+    queue: vk::Queue,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    /// nanoseconds per timestamp tick
+    timestamp_period: f64,
+    command_pool: vk::CommandPool,
+    cmd_buffer: vk::CommandBuffer,
+    fence: vk::Fence,
+    query_pool: vk::QueryPool,
+    buffers: Vec<(vk::Buffer, vk::DeviceMemory)>,
+    shader: vk::ShaderModule,
+    descriptor_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+impl BenchmarkDevice {
+    fn new(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> Result<Self, BenchmarkError> {
+        let families =
+            unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+        let queue_family = benchmark_queue_family(&families).ok_or(BenchmarkError::Unsupported(
+            "no compute queue with timestamps",
+        ))?;
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+
+        let priorities = [1.0];
+        let queue_infos = [vk::DeviceQueueCreateInfo::default()
+            .queue_family_index(queue_family)
+            .queue_priorities(&priorities)];
+        let device = unsafe {
+            instance.create_device(
+                physical_device,
+                &vk::DeviceCreateInfo::default().queue_create_infos(&queue_infos),
+                None,
+            )?
+        };
+
+        let mut benchmark_device = Self {
+            queue: unsafe { device.get_device_queue(queue_family, 0) },
+            device,
+            memory_properties: unsafe {
+                instance.get_physical_device_memory_properties(physical_device)
+            },
+            timestamp_period: properties.limits.timestamp_period as f64,
+            command_pool: vk::CommandPool::null(),
+            cmd_buffer: vk::CommandBuffer::null(),
+            fence: vk::Fence::null(),
+            query_pool: vk::QueryPool::null(),
+            buffers: Vec::new(),
+            shader: vk::ShaderModule::null(),
+            descriptor_layout: vk::DescriptorSetLayout::null(),
+            descriptor_pool: vk::DescriptorPool::null(),
+            pipeline_layout: vk::PipelineLayout::null(),
+            pipeline: vk::Pipeline::null(),
+        };
+        if let Err(error) = benchmark_device.create_submission(queue_family) {
+            unsafe { benchmark_device.destroy() };
+            return Err(error.into());
+        }
+        Ok(benchmark_device)
+    }
+
+    // command buffer, fence and timestamps every workload is submitted with
+    fn create_submission(&mut self, queue_family: u32) -> Result<(), vk::Result> {
+        let device = &self.device;
+        unsafe {
+            self.command_pool = device.create_command_pool(
+                &vk::CommandPoolCreateInfo::default()
+                    .queue_family_index(queue_family)
+                    .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER),
+                None,
+            )?;
+            self.cmd_buffer = device.allocate_command_buffers(
+                &vk::CommandBufferAllocateInfo::default()
+                    .command_pool(self.command_pool)
+                    .level(vk::CommandBufferLevel::PRIMARY)
+                    .command_buffer_count(1),
+            )?[0];
+            self.fence = device.create_fence(&vk::FenceCreateInfo::default(), None)?;
+            self.query_pool = device.create_query_pool(
+                &vk::QueryPoolCreateInfo::default()
+                    .query_type(vk::QueryType::TIMESTAMP)
+                    .query_count(2),
+                None,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn run(&mut self, spirv: &[u32]) -> Result<DeviceBenchmark, BenchmarkError> {
+        let size = transfer_size(&self.memory_properties);
+        let source = self.create_buffer(size, vk::BufferUsageFlags::TRANSFER_SRC)?;
+        let destination = self.create_buffer(
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::STORAGE_BUFFER,
+        )?;
+        let set = self.create_pipeline(spirv, destination)?;
+
+        let device = &self.device;
+        let transfer_seconds = self.time(|cmd_buffer| unsafe {
+            device.cmd_copy_buffer(
+                cmd_buffer,
+                source,
+                destination,
+                &[vk::BufferCopy::default().size(size)],
+            );
+        })?;
+
+        let constants = BenchmarkConstants {
+            iterations: COMPUTE_ITERATIONS,
+        };
+        let compute_seconds = self.time(|cmd_buffer| unsafe {
+            device.cmd_bind_pipeline(cmd_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+            device.cmd_bind_descriptor_sets(
+                cmd_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[set],
+                &[],
+            );
+            device.cmd_push_constants(
+                cmd_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                &constants.iterations.to_ne_bytes(),
+            );
+            device.cmd_dispatch(cmd_buffer, COMPUTE_THREADS / 64, 1, 1);
+        })?;
+
+        if transfer_seconds <= 0.0 || compute_seconds <= 0.0 {
+            return Err(BenchmarkError::Unsupported("timestamps didn't advance"));
+        }
+        let flops = COMPUTE_THREADS as u64 * COMPUTE_ITERATIONS as u64 * FLOPS_PER_ITERATION;
+        Ok(DeviceBenchmark {
+            compute_gflops: flops as f64 / compute_seconds / 1e9,
+            transfer_gbps: size as f64 / transfer_seconds / 1e9,
+        })
+    }
+
+    fn create_buffer(
+        &mut self,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+    ) -> Result<vk::Buffer, BenchmarkError> {
+        let device = &self.device;
+        let buffer = unsafe {
+            device.create_buffer(
+                &vk::BufferCreateInfo::default()
+                    .size(size)
+                    .usage(usage)
+                    .sharing_mode(vk::SharingMode::EXCLUSIVE),
+                None,
+            )?
+        };
+        // tracked before allocating so a failed allocation still destroys the buffer
+        self.buffers.push((buffer, vk::DeviceMemory::null()));
+
+        let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+        let memory_type =
+            benchmark_memory_type(&self.memory_properties, requirements.memory_type_bits).ok_or(
+                BenchmarkError::Unsupported("no memory type for the buffers"),
+            )?;
+        let memory = unsafe {
+            device.allocate_memory(
+                &vk::MemoryAllocateInfo::default()
+                    .allocation_size(requirements.size)
+                    .memory_type_index(memory_type),
+                None,
+            )?
+        };
+        if let Some(last) = self.buffers.last_mut() {
+            last.1 = memory;
+        }
+        unsafe { device.bind_buffer_memory(buffer, memory, 0)? };
+        Ok(buffer)
+    }
+
+    // the compute pipeline and a set writing its results into results
+    fn create_pipeline(
+        &mut self,
+        spirv: &[u32],
+        results: vk::Buffer,
+    ) -> Result<vk::DescriptorSet, vk::Result> {
+        let device = &self.device;
+        unsafe {
+            self.shader = device
+                .create_shader_module(&vk::ShaderModuleCreateInfo::default().code(spirv), None)?;
+
+            let bindings = [vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)];
+            self.descriptor_layout = device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings),
+                None,
+            )?;
+
+            let pool_sizes = [vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)];
+            self.descriptor_pool = device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .max_sets(1)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )?;
+
+            let set_layouts = [self.descriptor_layout];
+            let push_constant_ranges = [push_constant_range::<BenchmarkConstants>(
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+            )];
+            self.pipeline_layout = device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&set_layouts)
+                    .push_constant_ranges(&push_constant_ranges),
+                None,
+            )?;
+
+            let create_info = vk::ComputePipelineCreateInfo::default()
+                .stage(
+                    vk::PipelineShaderStageCreateInfo::default()
+                        .stage(vk::ShaderStageFlags::COMPUTE)
+                        .module(self.shader)
+                        .name(c"main"),
+                )
+                .layout(self.pipeline_layout);
+            self.pipeline = device
+                .create_compute_pipelines(vk::PipelineCache::null(), &[create_info], None)
+                .map_err(|(_, error)| error)?[0];
+
+            let set = device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(self.descriptor_pool)
+                    .set_layouts(&set_layouts),
+            )?[0];
+            let buffer_infos = [vk::DescriptorBufferInfo::default()
+                .buffer(results)
+                .range(vk::WHOLE_SIZE)];
+            let write = vk::WriteDescriptorSet::default()
+                .dst_set(set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&buffer_infos);
+            device.update_descriptor_sets(&[write], &[]);
+            Ok(set)
+        }
+    }
+
+    // seconds the fastest of BENCHMARK_RUNS submissions of record took on the gpu
+    fn time(&self, record: impl Fn(vk::CommandBuffer)) -> Result<f64, vk::Result> {
+        let device = &self.device;
+        let mut fastest = u64::MAX;
+        for _ in 0..BENCHMARK_RUNS {
+            let cmd_buffers = [self.cmd_buffer];
+            let submit_info = vk::SubmitInfo::default().command_buffers(&cmd_buffers);
+            let mut timestamps = [0_u64; 2];
+            unsafe {
+                device
+                    .reset_command_buffer(self.cmd_buffer, vk::CommandBufferResetFlags::empty())?;
+                device.begin_command_buffer(
+                    self.cmd_buffer,
+                    &vk::CommandBufferBeginInfo::default()
+                        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+                )?;
+                device.cmd_reset_query_pool(self.cmd_buffer, self.query_pool, 0, 2);
+                device.cmd_write_timestamp(
+                    self.cmd_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    self.query_pool,
+                    0,
+                );
+                record(self.cmd_buffer);
+                device.cmd_write_timestamp(
+                    self.cmd_buffer,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    self.query_pool,
+                    1,
+                );
+                device.end_command_buffer(self.cmd_buffer)?;
+
+                device.reset_fences(&[self.fence])?;
+                device.queue_submit(self.queue, &[submit_info], self.fence)?;
+                device.wait_for_fences(&[self.fence], true, u64::MAX)?;
+                device.get_query_pool_results(
+                    self.query_pool,
+                    0,
+                    &mut timestamps,
+                    vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT,
+                )?;
+            }
+            fastest = fastest.min(timestamps[1].wrapping_sub(timestamps[0]));
+        }
+        Ok(fastest as f64 * self.timestamp_period / 1e9)
+    }
+
+    /// # Safety
+    /// Waits for the device to go idle, nothing may be used afterwards
+    unsafe fn destroy(&mut self) {
+        let device = &self.device;
+        unsafe {
+            // destroying null handles does nothing
+            let _ = device.device_wait_idle();
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            // sets are freed with their pool
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_layout, None);
+            device.destroy_shader_module(self.shader, None);
+            for (buffer, memory) in self.buffers.drain(..) {
+                device.destroy_buffer(buffer, None);
+                device.free_memory(memory, None);
+            }
+            device.destroy_query_pool(self.query_pool, None);
+            device.destroy_fence(self.fence, None);
+            device.destroy_command_pool(self.command_pool, None);
+            device.destroy_device(None);
+        }
+    }
+}
+
+/// Measures every device again and saves the scores to directory, for checking automatic
+/// device choice on new hardware without opening a window
+pub fn benchmark_all_devices(
+    instance: &Instance,
+    directory: Option<&Path>,
+) -> Result<DeviceScores, Box<dyn error::Error>> {
+    let mut scores = DeviceScores::load(directory);
+    scores.measure_all(instance)?;
+    if let Some(directory) = directory {
+        scores.save(directory)?;
+    }
+    Ok(scores)
+}
+
+#[test]
+fn device_scores_test() {
+    // faster at both workloads scores higher, and a measured device beats any heuristic one
+    let slow = DeviceBenchmark {
+        compute_gflops: 500.0,
+        transfer_gbps: 20.0,
+    };
+    let fast = DeviceBenchmark {
+        compute_gflops: 10_000.0,
+        transfer_gbps: 400.0,
+    };
+    assert!(fast.score() > slow.score());
+    assert_eq!(slow.score(), 10_000);
+
+    let mut scores = DeviceScores::default();
+    scores.devices.insert("fast".to_string(), Some(fast));
+    scores.devices.insert("broken".to_string(), None);
+    let restored = DeviceScores::from_ron(&scores.to_ron().unwrap()).unwrap();
+    assert_eq!(restored.devices, scores.devices);
+    assert!(!restored.is_changed());
+
+    // compute queues need timestamps, device local memory is preferred
+    let family = |queue_flags, timestamp_valid_bits| vk::QueueFamilyProperties {
+        queue_flags,
+        timestamp_valid_bits,
+        ..Default::default()
+    };
+    let families = [
+        family(vk::QueueFlags::TRANSFER, 64),
+        family(vk::QueueFlags::COMPUTE, 0),
+        family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER, 64),
+    ];
+    assert_eq!(benchmark_queue_family(&families), Some(2));
+    assert_eq!(benchmark_queue_family(&families[..2]), None);
+
+    let mut memory_properties = vk::PhysicalDeviceMemoryProperties {
+        memory_type_count: 2,
+        memory_heap_count: 1,
+        ..Default::default()
+    };
+    memory_properties.memory_types[1].property_flags = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+    memory_properties.memory_heaps[0] = vk::MemoryHeap {
+        size: 256 << 20,
+        flags: vk::MemoryHeapFlags::DEVICE_LOCAL,
+    };
+    assert_eq!(benchmark_memory_type(&memory_properties, 0b11), Some(1));
+    assert_eq!(benchmark_memory_type(&memory_properties, 0b01), Some(0));
+    assert_eq!(benchmark_memory_type(&memory_properties, 0), None);
+    assert_eq!(transfer_size(&memory_properties), 32 << 20);
+}
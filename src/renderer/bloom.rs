@@ -0,0 +1,613 @@
+use ash::vk;
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::ffi::CStr;
+
+use crate::renderer::device::VKDevice;
+use crate::renderer::image::VKImage;
+use crate::renderer::pipeline::{BlendMode, GraphicsPipelineBuilder};
+use crate::renderer::scaling::VKInternalTarget;
+use crate::renderer::shader::{VKShader, VKShaderLoader, reload_shaders};
+use crate::renderer::{HDR_FORMAT, push_constant_range};
+
+/// Format of the blurred levels, half floats so faint light doesn't band as it spreads
+pub const BLOOM_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// How bright parts of the scene glow
+/// Example Use:
+/// ```
+/// use vulkan_engine::renderer::bloom::BloomSettings;
+///
+/// // only the brightest highlights, spread wide
+/// let settings = BloomSettings::default().threshold(0.9).intensity(0.4).levels(7);
+/// assert_eq!(settings.levels, 7);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BloomSettings {
+    /// linear brightness colours start to bloom at
+    pub threshold: f32,
+    /// 0 to 1, how far below threshold colours fade in instead of cutting off
+    pub knee: f32,
+    /// how strongly the blurred light is added back onto the scene
+    pub intensity: f32,
+    /// times the scene is halved, more spreads the glow further
+    pub levels: u32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 0.8,
+            knee: 0.5,
+            intensity: 0.6,
+            levels: 5,
+        }
+    }
+}
+
+impl BloomSettings {
+    pub fn threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold.max(0.0);
+        self
+    }
+
+    pub fn knee(mut self, knee: f32) -> Self {
+        self.knee = knee.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity.max(0.0);
+        self
+    }
+
+    pub fn levels(mut self, levels: u32) -> Self {
+        self.levels = levels.max(1);
+        self
+    }
+}
+
+/// Pushed before every draw of the chain, matches BloomConstants in bloom.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BloomConstants {
+    /// one over the size of the image sampled
+    pub texel_size: Vec2,
+    pub threshold: f32,
+    pub knee: f32,
+    pub intensity: f32,
+    /// 1 on the first downsample, which applies the threshold
+    pub prefilter: u32,
+}
+
+impl BloomConstants {
+    /// source is the extent of the image step samples
+    pub fn new(settings: &BloomSettings, source: vk::Extent2D, step: BloomStep) -> Self {
+        Self {
+            texel_size: Vec2::new(1.0 / source.width as f32, 1.0 / source.height as f32),
+            threshold: settings.threshold,
+            knee: settings.knee,
+            intensity: settings.intensity,
+            prefilter: (step == BloomStep::Downsample(0)) as u32,
+        }
+    }
+}
+
+/// One draw of the chain, levels count from the largest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BloomStep {
+    /// filters the scene, or the level before, down into the level
+    Downsample(usize),
+    /// adds the level after, blurred, onto the level
+    Upsample(usize),
+    /// adds level 0, blurred, onto the scene
+    Composite,
+}
+
+impl BloomStep {
+    /// Level the step samples, None for the scene
+    pub fn source(self) -> Option<usize> {
+        match self {
+            Self::Downsample(level) => level.checked_sub(1),
+            Self::Upsample(level) => Some(level + 1),
+            Self::Composite => Some(0),
+        }
+    }
+
+    /// Level the step draws into, None for the scene
+    pub fn output(self) -> Option<usize> {
+        match self {
+            Self::Downsample(level) | Self::Upsample(level) => Some(level),
+            Self::Composite => None,
+        }
+    }
+
+    pub fn name(self) -> &'static CStr {
+        match self {
+            Self::Downsample(_) => c"Bloom Downsample",
+            Self::Upsample(_) => c"Bloom Upsample",
+            Self::Composite => c"Bloom Composite",
+        }
+    }
+}
+
+/// Draws of a chain of level_count levels in the order they are recorded
+/// down to the smallest level, back up to the largest then onto the scene
+pub fn bloom_steps(level_count: usize) -> Vec<BloomStep> {
+    if level_count == 0 {
+        return Vec::new();
+    }
+    (0..level_count)
+        .map(BloomStep::Downsample)
+        .chain((0..level_count - 1).rev().map(BloomStep::Upsample))
+        .chain([BloomStep::Composite])
+        .collect()
+}
+
+/// Sizes of up to levels halvings of extent, stops once the image is a single pixel
+pub fn level_extents(extent: vk::Extent2D, levels: u32) -> Vec<vk::Extent2D> {
+    let mut extents = Vec::new();
+    let mut level = extent;
+    for _ in 0..levels {
+        if level.width <= 1 && level.height <= 1 {
+            break;
+        }
+        level = vk::Extent2D {
+            width: (level.width / 2).max(1),
+            height: (level.height / 2).max(1),
+        };
+        extents.push(level);
+    }
+    extents
+}
+
+/// Threshold, blur and composite over the internal target, drawn between the scene and the
+/// tonemap pass through a chain of half sized images
+pub struct VKBloomPass<'a> {
+    pub vertex_shader: VKShader<'a>,
+    pub downsample_shader: VKShader<'a>,
+    pub upsample_shader: VKShader<'a>,
+    pub composite_shader: VKShader<'a>,
+    pub descriptor_layout: vk::DescriptorSetLayout,
+    pub pipeline_layout: vk::PipelineLayout,
+    /// downsample, upsample and composite
+    pub pipelines: [vk::Pipeline; 3],
+    /// linear and clamped, the taps between texels do half the blurring
+    pub sampler: vk::Sampler,
+    /// null while the pass has no levels
+    pub descriptor_pool: vk::DescriptorPool,
+    /// the scene's then each level's, only written while the gpu is idle
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    /// None disables the pass
+    pub settings: Option<BloomSettings>,
+    /// HDR_FORMAT, the composite draws onto the internal target
+    pub format: vk::Format,
+    /// each half the size of the one before, empty while the pass has nothing to draw into
+    pub levels: Vec<VKImage>,
+    // internal target the chain reads and composites onto
+    target: Option<(vk::ImageView, vk::Extent2D)>,
+}
+
+impl VKBloomPass<'_> {
+    pub fn new(
+        vk_device: &VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let vertex_shader = VKShader::new(
+            vk_device,
+            "shaders/bloom.spv",
+            vk::ShaderStageFlags::VERTEX,
+            c"vertexMain",
+            vk_shader_loader,
+        )?;
+        let [downsample_shader, upsample_shader, composite_shader] =
+            [c"downsampleMain", c"upsampleMain", c"compositeMain"].map(|entry| {
+                VKShader::new(
+                    vk_device,
+                    "shaders/bloom.spv",
+                    vk::ShaderStageFlags::FRAGMENT,
+                    entry,
+                    vk_shader_loader,
+                )
+            });
+        let (downsample_shader, upsample_shader, composite_shader) =
+            (downsample_shader?, upsample_shader?, composite_shader?);
+
+        let set_bindings = [vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)];
+
+        let descriptor_layout = unsafe {
+            vk_device.device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(&set_bindings),
+                None,
+            )?
+        };
+
+        let descriptor_layouts = [descriptor_layout];
+        let push_constant_ranges = [push_constant_range::<BloomConstants>(
+            vk::ShaderStageFlags::FRAGMENT,
+            0,
+        )];
+        let pipeline_layout = unsafe {
+            vk_device.device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&descriptor_layouts)
+                    .push_constant_ranges(&push_constant_ranges),
+                None,
+            )?
+        };
+
+        let format = HDR_FORMAT;
+        let stages = [
+            &vertex_shader,
+            &downsample_shader,
+            &upsample_shader,
+            &composite_shader,
+        ]
+        .map(|shader| shader.shader_info);
+        let pipelines = create_bloom_pipelines(vk_device, format, &stages, pipeline_layout)?;
+
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = unsafe { vk_device.device.create_sampler(&sampler_info, None)? };
+
+        Ok(Self {
+            vertex_shader,
+            downsample_shader,
+            upsample_shader,
+            composite_shader,
+            descriptor_layout,
+            pipeline_layout,
+            pipelines,
+            sampler,
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_sets: Vec::new(),
+            settings: None,
+            format,
+            levels: Vec::new(),
+            target: None,
+        })
+    }
+
+    /// Whether there is anything for the pass to do given a target
+    pub fn is_enabled(&self) -> bool {
+        self.settings.is_some()
+    }
+
+    /// The chain's draws in order, empty while the pass is disabled or has no target
+    pub fn steps(&self) -> Vec<BloomStep> {
+        bloom_steps(self.levels.len())
+    }
+
+    /// Recreates the levels for target and points the descriptors at them and the target
+    /// without settings or without a target the levels are destroyed and the pass does nothing
+    /// # Safety
+    /// The gpu must not be using the pass
+    pub unsafe fn set_target(
+        &mut self,
+        vk_device: &mut VKDevice,
+        target: Option<&VKInternalTarget>,
+    ) -> Result<(), vk::Result> {
+        unsafe { self.destroy_levels(vk_device) };
+
+        let (Some(settings), Some(target)) = (self.settings, target) else {
+            return Ok(());
+        };
+
+        let extent = target.resolution.extent;
+        for level_extent in level_extents(extent, settings.levels) {
+            let level = VKImage::new(
+                vk_device,
+                level_extent,
+                BLOOM_FORMAT,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                vk::SampleCountFlags::TYPE_1,
+                vk::ImageAspectFlags::COLOR,
+            );
+            match level {
+                Ok(level) => self.levels.push(level),
+                Err(error) => {
+                    unsafe { self.destroy_levels(vk_device) };
+                    return Err(error);
+                }
+            }
+        }
+        if let Err(error) = unsafe { self.allocate_sets(vk_device, target.image.view) } {
+            unsafe { self.destroy_levels(vk_device) };
+            return Err(error);
+        }
+        self.target = Some((target.image.view, extent));
+        Ok(())
+    }
+
+    // one set per image sampled, the scene then each level
+    unsafe fn allocate_sets(
+        &mut self,
+        vk_device: &VKDevice,
+        scene_view: vk::ImageView,
+    ) -> Result<(), vk::Result> {
+        let set_count = self.levels.len() as u32 + 1;
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: set_count,
+        }];
+        self.descriptor_pool = unsafe {
+            vk_device.device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .max_sets(set_count)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )?
+        };
+
+        let descriptor_layouts = vec![self.descriptor_layout; set_count as usize];
+        self.descriptor_sets = unsafe {
+            vk_device.device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(self.descriptor_pool)
+                    .set_layouts(&descriptor_layouts),
+            )?
+        };
+
+        let image_infos: Vec<_> = [scene_view]
+            .into_iter()
+            .chain(self.levels.iter().map(|level| level.view))
+            .map(|image_view| {
+                [vk::DescriptorImageInfo::default()
+                    .sampler(self.sampler)
+                    .image_view(image_view)
+                    .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]
+            })
+            .collect();
+        let writes: Vec<_> = image_infos
+            .iter()
+            .zip(&self.descriptor_sets)
+            .map(|(image_info, descriptor_set)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(*descriptor_set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(image_info)
+            })
+            .collect();
+        unsafe { vk_device.device.update_descriptor_sets(&writes, &[]) };
+        Ok(())
+    }
+
+    /// Draws step of the chain, does nothing while the pass is disabled
+    /// the image step samples must be in SHADER_READ_ONLY_OPTIMAL and the one it draws into in
+    /// COLOR_ATTACHMENT_OPTIMAL, the render graph transitions both
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering, after the scene pass into the target
+    /// and the steps before step
+    pub unsafe fn record(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        step: BloomStep,
+    ) {
+        let (Some(settings), Some((scene_view, scene_extent))) = (&self.settings, self.target)
+        else {
+            return;
+        };
+        let image = |level: Option<usize>| match level {
+            Some(level) => (self.levels[level].view, self.levels[level].extent),
+            None => (scene_view, scene_extent),
+        };
+        let (_, source_extent) = image(step.source());
+        let (output_view, output_extent) = image(step.output());
+        let descriptor_set = self.descriptor_sets[step.source().map_or(0, |level| level + 1)];
+        let (pipeline, load_op) = match step {
+            // every pixel is written so the old contents don't matter
+            BloomStep::Downsample(_) => (self.pipelines[0], vk::AttachmentLoadOp::DONT_CARE),
+            BloomStep::Upsample(_) => (self.pipelines[1], vk::AttachmentLoadOp::LOAD),
+            BloomStep::Composite => (self.pipelines[2], vk::AttachmentLoadOp::LOAD),
+        };
+        let constants = BloomConstants::new(settings, source_extent, step);
+
+        let color_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(output_view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(load_op)
+            .store_op(vk::AttachmentStoreOp::STORE)];
+
+        let render_area = vk::Rect2D::default().extent(output_extent);
+        let rendering_info = vk::RenderingInfo::default()
+            .color_attachments(&color_attachments)
+            .layer_count(1)
+            .render_area(render_area);
+
+        let viewport = [vk::Viewport::default()
+            .width(output_extent.width as f32)
+            .height(output_extent.height as f32)
+            .max_depth(1.0)];
+
+        unsafe {
+            vk_device
+                .device
+                .cmd_begin_rendering(cmd_buffer, &rendering_info);
+            vk_device.device.cmd_set_viewport(cmd_buffer, 0, &viewport);
+            vk_device
+                .device
+                .cmd_set_scissor(cmd_buffer, 0, &[render_area]);
+            vk_device.device.cmd_bind_pipeline(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline,
+            );
+            vk_device.device.cmd_bind_descriptor_sets(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            vk_device.cmd_push_constants(
+                cmd_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                &constants,
+            );
+            vk_device.device.cmd_draw(cmd_buffer, 3, 1, 0, 0);
+            vk_device.device.cmd_end_rendering(cmd_buffer);
+        }
+    }
+
+    // levels and their descriptors only, the pipelines and sampler stay
+    unsafe fn destroy_levels(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            for mut level in self.levels.drain(..) {
+                level.destroy(vk_device);
+            }
+            // the sets go with their pool
+            vk_device
+                .device
+                .destroy_descriptor_pool(std::mem::take(&mut self.descriptor_pool), None);
+        }
+        self.descriptor_sets.clear();
+        self.target = None;
+    }
+
+    /// Rebuilds the pipelines when one of their shaders is in changed, see VKShaderLoader::changed_shaders
+    /// # Safety
+    /// The gpu must not be using the pass
+    pub unsafe fn reload_shaders(
+        &mut self,
+        vk_device: &VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        changed: &[&str],
+    ) -> Result<(), Box<dyn error::Error>> {
+        let shaders = &mut [
+            &mut self.vertex_shader,
+            &mut self.downsample_shader,
+            &mut self.upsample_shader,
+            &mut self.composite_shader,
+        ];
+        if !unsafe { reload_shaders(vk_device, vk_shader_loader, shaders, changed)? } {
+            return Ok(());
+        }
+        let stages = [
+            self.vertex_shader.shader_info,
+            self.downsample_shader.shader_info,
+            self.upsample_shader.shader_info,
+            self.composite_shader.shader_info,
+        ];
+        let pipelines =
+            create_bloom_pipelines(vk_device, self.format, &stages, self.pipeline_layout)?;
+        for pipeline in std::mem::replace(&mut self.pipelines, pipelines) {
+            unsafe { vk_device.device.destroy_pipeline(pipeline, None) };
+        }
+        Ok(())
+    }
+
+    /// # Safety
+    /// The gpu must not be using the pass
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            self.destroy_levels(vk_device);
+            vk_device.device.destroy_sampler(self.sampler, None);
+            for pipeline in self.pipelines {
+                vk_device.device.destroy_pipeline(pipeline, None);
+            }
+            vk_device
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            vk_device
+                .device
+                .destroy_descriptor_set_layout(self.descriptor_layout, None);
+            self.composite_shader.destroy(vk_device);
+            self.upsample_shader.destroy(vk_device);
+            self.downsample_shader.destroy(vk_device);
+            self.vertex_shader.destroy(vk_device);
+        }
+    }
+}
+
+// fullscreen triangles without vertex input or depth, single sampled
+// stages are the vertex stage then the downsample, upsample and composite fragment stages
+fn create_bloom_pipelines(
+    vk_device: &VKDevice,
+    format: vk::Format,
+    stages: &[vk::PipelineShaderStageCreateInfo; 4],
+    pipeline_layout: vk::PipelineLayout,
+) -> Result<[vk::Pipeline; 3], vk::Result> {
+    let passes = [
+        (stages[1], BLOOM_FORMAT, BlendMode::Opaque),
+        (stages[2], BLOOM_FORMAT, BlendMode::Additive),
+        (stages[3], format, BlendMode::Additive),
+    ];
+    let mut pipelines = Vec::with_capacity(passes.len());
+    for (fragment_stage, format, blend) in passes {
+        let pipeline = GraphicsPipelineBuilder::new(&[stages[0], fragment_stage], pipeline_layout)
+            .color_formats(&[format])
+            .blend(blend)
+            .build(vk_device);
+        match pipeline {
+            Ok(pipeline) => pipelines.push(pipeline),
+            Err(error) => {
+                for pipeline in pipelines {
+                    unsafe { vk_device.device.destroy_pipeline(pipeline, None) };
+                }
+                return Err(error);
+            }
+        }
+    }
+    Ok([pipelines[0], pipelines[1], pipelines[2]])
+}
+
+#[test]
+fn bloom_chain_test() {
+    let extent = vk::Extent2D {
+        width: 320,
+        height: 3,
+    };
+    // the short side stops at 1 while the long one keeps halving
+    let extents: Vec<_> = level_extents(extent, 4)
+        .iter()
+        .map(|extent| (extent.width, extent.height))
+        .collect();
+    assert_eq!(extents, vec![(160, 1), (80, 1), (40, 1), (20, 1)]);
+    let one_pixel = vk::Extent2D {
+        width: 1,
+        height: 1,
+    };
+    assert!(level_extents(one_pixel, 4).is_empty());
+
+    let steps = bloom_steps(3);
+    assert_eq!(
+        steps,
+        vec![
+            BloomStep::Downsample(0),
+            BloomStep::Downsample(1),
+            BloomStep::Downsample(2),
+            BloomStep::Upsample(1),
+            BloomStep::Upsample(0),
+            BloomStep::Composite,
+        ]
+    );
+    // every step samples what the one before it drew into
+    for pair in steps.windows(2) {
+        assert_eq!(pair[1].source(), pair[0].output());
+    }
+    assert!(bloom_steps(0).is_empty());
+
+    let settings = BloomSettings::default();
+    assert_eq!(
+        BloomConstants::new(&settings, extent, BloomStep::Downsample(0)).prefilter,
+        1
+    );
+    assert_eq!(
+        BloomConstants::new(&settings, extent, BloomStep::Upsample(0)).prefilter,
+        0
+    );
+}
@@ -0,0 +1,139 @@
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+
+use crate::renderer::allocator::VKAllocation;
+use crate::renderer::device::VKDevice;
+
+/// A buffer together with the memory bound to it and what it was created for
+/// Example Use:
+/// ```ignore
+/// let mut uniforms = VKBuffer::new(
+///     vk_device,
+///     size_of::<CameraUniform>() as u64,
+///     vk::BufferUsageFlags::UNIFORM_BUFFER,
+///     MemoryLocation::CpuToGpu,
+///     "Camera Uniform",
+/// )?;
+/// uniforms.write_value(0, &camera_uniform)?;
+/// let info = uniforms.descriptor_buffer_info();
+/// ```
+#[derive(Debug, Default)]
+pub struct VKBuffer {
+    pub buffer: vk::Buffer,
+    pub allocation: VKAllocation,
+    /// bytes asked for, the allocation can be larger
+    pub size: u64,
+    pub usage: vk::BufferUsageFlags,
+}
+
+impl VKBuffer {
+    pub fn new(
+        vk_device: &mut VKDevice,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+        mem_location: MemoryLocation,
+        name: &str,
+    ) -> Result<Self, vk::Result> {
+        let (buffer, allocation) = vk_device.create_buffer(size, usage, mem_location, name)?;
+        Ok(Self::from_parts(buffer, allocation, size, usage))
+    }
+
+    /// Takes ownership of a buffer created some other way, like VKDevice::create_shared_buffer
+    pub fn from_parts(
+        buffer: vk::Buffer,
+        allocation: VKAllocation,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+    ) -> Self {
+        Self {
+            buffer,
+            allocation,
+            size,
+            usage,
+        }
+    }
+
+    /// Host visible memory that stays mapped, gpu only buffers aren't
+    pub fn is_mapped(&self) -> bool {
+        self.allocation.mapped_ptr().is_some()
+    }
+
+    /// The buffer's bytes when mapped, writes are seen by the gpu from the next submit
+    pub fn map(&mut self) -> Option<&mut [u8]> {
+        let size = self.size as usize;
+        self.allocation
+            .mapped_slice_mut()
+            .map(|mapped| &mut mapped[..size])
+    }
+
+    /// Copies data in at offset bytes, fails when the buffer isn't mapped or data doesn't fit
+    pub fn write<T: Copy>(&mut self, offset: usize, data: &[T]) -> Result<(), vk::Result> {
+        let fits = offset
+            .checked_add(size_of_val(data))
+            .is_some_and(|end| end as u64 <= self.size);
+        if !self.is_mapped() || !fits {
+            return Err(vk::Result::ERROR_MEMORY_MAP_FAILED);
+        }
+        presser::copy_from_slice_to_offset(data, &mut self.allocation, offset)
+            .map(|_| ())
+            .map_err(|_| vk::Result::ERROR_MEMORY_MAP_FAILED)
+    }
+
+    /// Like write for a single value
+    pub fn write_value<T: Copy>(&mut self, offset: usize, value: &T) -> Result<(), vk::Result> {
+        self.write(offset, std::slice::from_ref(value))
+    }
+
+    /// The whole buffer for a descriptor
+    pub fn descriptor_buffer_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffer)
+            .offset(0)
+            .range(self.size)
+    }
+
+    /// # Safety
+    /// The gpu must not be using the buffer
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        let allocation = std::mem::take(&mut self.allocation);
+        unsafe { vk_device.destroy_buffer(self.buffer, allocation) };
+        self.buffer = vk::Buffer::null();
+    }
+}
+
+#[test]
+fn buffer_write_test() {
+    use std::ffi::c_void;
+    use std::ptr::NonNull;
+
+    // the allocation is rounded up past the buffer like a real allocator would
+    let mut memory = vec![0u8; 32];
+    let mapped_ptr = NonNull::new(memory.as_mut_ptr().cast::<c_void>());
+    let allocation = VKAllocation::new(
+        vk::DeviceMemory::null(),
+        0,
+        32,
+        vk::MemoryPropertyFlags::HOST_VISIBLE,
+        mapped_ptr,
+        (),
+    );
+    let mut buffer = VKBuffer::from_parts(
+        vk::Buffer::null(),
+        allocation,
+        16,
+        vk::BufferUsageFlags::UNIFORM_BUFFER,
+    );
+
+    assert!(buffer.is_mapped());
+    buffer.write(4, &[1u32, 2]).unwrap();
+    buffer.write_value(12, &3u32).unwrap();
+    assert_eq!(&buffer.map().unwrap()[8..12], &2u32.to_ne_bytes());
+    assert_eq!(buffer.map().unwrap().len(), 16);
+    // past the buffer's size even though the allocation has room
+    assert!(buffer.write_value(16, &4u32).is_err());
+    // an end past usize::MAX is out of bounds rather than a panic or a wrap
+    assert!(buffer.write_value(usize::MAX - 1, &4u32).is_err());
+
+    assert!(VKBuffer::default().write_value(0, &1u32).is_err());
+    assert_eq!(buffer.descriptor_buffer_info().range, 16);
+}
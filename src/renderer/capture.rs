@@ -0,0 +1,830 @@
+use ash::vk::{self, Handle};
+use glam::Vec3;
+use gpu_allocator::MemoryLocation;
+use log::{info, warn};
+use std::error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::camera::{Camera, CameraUniform};
+use crate::renderer::buffer::VKBuffer;
+use crate::renderer::device::VKDevice;
+use crate::renderer::image::VKImage;
+use crate::renderer::render_graph::{Access, RenderGraph, RenderPass};
+use crate::renderer::{
+    CAPTURE_LABEL_COLOR, COLOR_SUBRESOURCE_RANGE, DEPTH_FORMAT, DrawStats, FrameCamera,
+    RenderTarget, VKRenderer, submit_one_time,
+};
+
+/// Options for capturing a single frame offscreen
+/// Example Use:
+/// ```ignore
+/// // render at 4x the window resolution and average back down (supersampling)
+/// let options = CaptureOptions::default().scale(4).downsample(true);
+/// renderer.capture_frame(options)?.save_ppm("screenshot.ppm")?;
+/// ```
+/// Captures only record the scene pass, overlays are never included.
+#[derive(Clone, Copy, Debug)]
+pub struct CaptureOptions {
+    pub scale: u32,
+    pub downsample: bool,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1,
+            downsample: false,
+        }
+    }
+}
+
+impl CaptureOptions {
+    /// Render at scale times the swapchain resolution, or the internal resolution when set
+    pub fn scale(mut self, scale: u32) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    /// Average the scaled image back down to the unscaled resolution
+    pub fn downsample(mut self, downsample: bool) -> Self {
+        self.downsample = downsample;
+        self
+    }
+}
+
+/// A frame read back from the gpu
+/// pixels are tightly packed, 4 bytes per pixel in the order given by format
+pub struct VKCapture {
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    pub pixels: Vec<u8>,
+}
+
+impl VKCapture {
+    /// Box filters the capture down by factor in each dimension
+    pub fn downsample(&self, factor: u32) -> Self {
+        let (pixels, width, height) =
+            box_downsample(&self.pixels, self.extent.width, self.extent.height, factor);
+        Self {
+            extent: vk::Extent2D { width, height },
+            format: self.format,
+            pixels,
+        }
+    }
+
+    /// Returns the pixels as packed 8bit RGB
+    pub fn to_rgb8(&self) -> Result<Vec<u8>, io::Error> {
+        let swizzle: [usize; 3] = match self.format {
+            vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => [2, 1, 0],
+            vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM => [0, 1, 2],
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unsupported Capture Format",
+                ));
+            }
+        };
+
+        Ok(self
+            .pixels
+            .chunks_exact(4)
+            .flat_map(|pixel| swizzle.map(|channel| pixel[channel]))
+            .collect())
+    }
+
+    /// Writes the capture as a binary PPM image
+    pub fn save_ppm<P: AsRef<Path>>(&self, path: P) -> Result<(), io::Error> {
+        let rgb = self.to_rgb8()?;
+        let mut file = BufWriter::new(File::create(path)?);
+        write!(
+            file,
+            "P6\n{} {}\n255\n",
+            self.extent.width, self.extent.height
+        )?;
+        file.write_all(&rgb)?;
+        file.flush()
+    }
+}
+
+/// Whether captures can read back images of this format
+pub fn capture_format_supported(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::B8G8R8A8_SRGB
+            | vk::Format::B8G8R8A8_UNORM
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::R8G8B8A8_UNORM
+    )
+}
+
+// averages each factor x factor block of 4 channel pixels into one pixel
+// averaging happens on the stored values, for srgb images this is slightly darker than a linear average
+fn box_downsample(pixels: &[u8], width: u32, height: u32, factor: u32) -> (Vec<u8>, u32, u32) {
+    let factor = factor.max(1);
+    let (out_width, out_height) = (width / factor, height / factor);
+    let samples = factor * factor;
+
+    // indices in usize, large captures overflow u32
+    let (width, step) = (width as usize, factor as usize);
+    let mut out = Vec::with_capacity(out_width as usize * out_height as usize * 4);
+    for y in 0..out_height as usize {
+        for x in 0..out_width as usize {
+            let mut sum = [0u32; 4];
+            for sy in 0..step {
+                for sx in 0..step {
+                    let src = ((y * step + sy) * width + x * step + sx) * 4;
+                    for (channel, total) in sum.iter_mut().enumerate() {
+                        *total += pixels[src + channel] as u32;
+                    }
+                }
+            }
+            out.extend(sum.map(|total| ((total + samples / 2) / samples) as u8));
+        }
+    }
+    (out, out_width, out_height)
+}
+
+// cube faces in vulkan order +X, -X, +Y, -Y, +Z, -Z as (look direction, up)
+// rendered like a normal camera and mirrored afterwards, see capture_cubemap
+const CUBE_FACES: [(Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::Y),
+    (Vec3::NEG_X, Vec3::Y),
+    (Vec3::Y, Vec3::NEG_Z),
+    (Vec3::NEG_Y, Vec3::Z),
+    (Vec3::Z, Vec3::Y),
+    (Vec3::NEG_Z, Vec3::Y),
+];
+
+/// The six faces of a cubemap read back from the gpu
+/// faces are in vulkan cube face order +X, -X, +Y, -Y, +Z, -Z
+pub struct VKCubemapCapture {
+    pub face_size: u32,
+    pub format: vk::Format,
+    pub faces: Vec<Vec<u8>>,
+}
+
+impl VKCubemapCapture {
+    /// Writes the faces as a KTX2 cubemap with a single mip level
+    pub fn save_ktx2<P: AsRef<Path>>(&self, path: P) -> Result<(), io::Error> {
+        let bytes = ktx2_cubemap_bytes(self.format, self.face_size, &self.faces)?;
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&bytes)?;
+        file.flush()
+    }
+
+    /// Stitches the faces into an equirectangular panorama half as tall as width
+    /// the centre looks down -Z like an unrotated camera, +X is to its right
+    /// Example Use:
+    /// ```ignore
+    /// // faces a quarter of the panorama's width keep about one texel per pixel at the horizon
+    /// let cubemap = renderer.capture_cubemap(camera.position, 1024)?;
+    /// cubemap.to_equirect(4096).save_ppm("panorama.ppm")?;
+    /// ```
+    pub fn to_equirect(&self, width: u32) -> VKCapture {
+        let width = width.max(1);
+        let height = width.div_ceil(2);
+        VKCapture {
+            extent: vk::Extent2D { width, height },
+            format: self.format,
+            pixels: equirect_from_faces(&self.faces, self.face_size, width, height),
+        }
+    }
+}
+
+// the face direction lands on and where on it in 0..1 from the top left
+// follows the cube map face selection table of the vulkan spec
+fn cube_face_uv(direction: Vec3) -> (usize, f32, f32) {
+    let abs = direction.abs();
+    let (face, major, s, t) = if abs.x >= abs.y && abs.x >= abs.z {
+        if direction.x > 0.0 {
+            (0, abs.x, -direction.z, -direction.y)
+        } else {
+            (1, abs.x, direction.z, -direction.y)
+        }
+    } else if abs.y >= abs.z {
+        if direction.y > 0.0 {
+            (2, abs.y, direction.x, direction.z)
+        } else {
+            (3, abs.y, direction.x, -direction.z)
+        }
+    } else if direction.z > 0.0 {
+        (4, abs.z, direction.x, -direction.y)
+    } else {
+        (5, abs.z, -direction.x, -direction.y)
+    };
+    (face, (s / major + 1.0) * 0.5, (t / major + 1.0) * 0.5)
+}
+
+// nearest samples 4 channel cube faces into a width x height equirectangular image
+fn equirect_from_faces(faces: &[Vec<u8>], face_size: u32, width: u32, height: u32) -> Vec<u8> {
+    let size = face_size as usize;
+    let texel = |coordinate: f32| ((coordinate * face_size as f32) as usize).min(size - 1);
+
+    let mut out = Vec::with_capacity(width as usize * height as usize * 4);
+    for y in 0..height {
+        // latitude from straight up to straight down
+        let latitude = (0.5 - (y as f32 + 0.5) / height as f32) * std::f32::consts::PI;
+        for x in 0..width {
+            // longitude from behind on the left round to behind on the right
+            let longitude = ((x as f32 + 0.5) / width as f32 - 0.5) * std::f32::consts::TAU;
+            let direction = Vec3::new(
+                longitude.sin() * latitude.cos(),
+                latitude.sin(),
+                -longitude.cos() * latitude.cos(),
+            );
+            let (face, u, v) = cube_face_uv(direction);
+            let src = (texel(v) * size + texel(u)) * 4;
+            out.extend_from_slice(&faces[face][src..src + 4]);
+        }
+    }
+    out
+}
+
+// flips each row of a 4 channel image so the left column becomes the right
+fn mirror_horizontal(pixels: &[u8], width: u32) -> Vec<u8> {
+    pixels
+        .chunks_exact(width as usize * 4)
+        .flat_map(|row| row.chunks_exact(4).rev().flatten().copied())
+        .collect()
+}
+
+// data format descriptor for an 8bit 4 channel format, see the khronos data format spec
+fn ktx2_dfd(format: vk::Format) -> Result<Vec<u8>, io::Error> {
+    // (channel id, bit offset) for each sample, RGBSDA colour model ids R = 0, G = 1, B = 2, A = 15
+    let (samples, transfer_function): ([(u8, u16); 4], u8) = match format {
+        vk::Format::B8G8R8A8_SRGB => ([(2, 0), (1, 8), (0, 16), (15, 24)], 2),
+        vk::Format::B8G8R8A8_UNORM => ([(2, 0), (1, 8), (0, 16), (15, 24)], 1),
+        vk::Format::R8G8B8A8_SRGB => ([(0, 0), (1, 8), (2, 16), (15, 24)], 2),
+        vk::Format::R8G8B8A8_UNORM => ([(0, 0), (1, 8), (2, 16), (15, 24)], 1),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unsupported KTX2 Format",
+            ));
+        }
+    };
+
+    let block_size: u16 = 24 + 16 * samples.len() as u16;
+    let mut dfd = Vec::with_capacity(4 + block_size as usize);
+    dfd.extend((4 + block_size as u32).to_le_bytes()); // dfdTotalSize
+    dfd.extend(0u32.to_le_bytes()); // vendorId + descriptorType
+    dfd.extend(2u16.to_le_bytes()); // versionNumber
+    dfd.extend(block_size.to_le_bytes());
+    dfd.extend([1, 1, transfer_function, 0]); // RGBSDA, BT709 primaries, transfer, straight alpha
+    dfd.extend([0u8; 4]); // texel block dimensions, 1x1x1x1 stored as 0
+    dfd.extend([4, 0, 0, 0, 0, 0, 0, 0]); // bytes per plane
+
+    for (channel, bit_offset) in samples {
+        // alpha stays linear in srgb formats
+        let qualifiers = if channel == 15 && transfer_function == 2 {
+            0x10
+        } else {
+            0
+        };
+        dfd.extend(bit_offset.to_le_bytes());
+        dfd.push(7); // bit length - 1
+        dfd.push(channel | qualifiers);
+        dfd.extend([0u8; 4]); // sample position
+        dfd.extend(0u32.to_le_bytes()); // sample lower
+        dfd.extend(255u32.to_le_bytes()); // sample upper
+    }
+    Ok(dfd)
+}
+
+// builds a KTX2 file holding a single mip level cubemap
+fn ktx2_cubemap_bytes(
+    format: vk::Format,
+    face_size: u32,
+    faces: &[Vec<u8>],
+) -> Result<Vec<u8>, io::Error> {
+    const IDENTIFIER: [u8; 12] = [
+        0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+    ];
+    const HEADER_SIZE: u32 = 80;
+    const LEVEL_INDEX_SIZE: u32 = 24;
+
+    if faces.len() != 6 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Cubemap Needs 6 Faces",
+        ));
+    }
+
+    let dfd = ktx2_dfd(format)?;
+    let dfd_offset = HEADER_SIZE + LEVEL_INDEX_SIZE;
+    // level data has to be 4 byte aligned, dfd size is always a multiple of 4
+    let level_offset = (dfd_offset + dfd.len() as u32) as u64;
+    let level_size: u64 = faces.iter().map(|face| face.len() as u64).sum();
+
+    let mut bytes = Vec::with_capacity((level_offset + level_size) as usize);
+    bytes.extend(IDENTIFIER);
+    for value in [
+        format.as_raw() as u32, // vkFormat
+        1,                      // typeSize
+        face_size,              // pixelWidth
+        face_size,              // pixelHeight
+        0,                      // pixelDepth
+        0,                      // layerCount
+        6,                      // faceCount
+        1,                      // levelCount
+        0,                      // supercompressionScheme
+        dfd_offset,
+        dfd.len() as u32,
+        0, // kvdByteOffset
+        0, // kvdByteLength
+    ] {
+        bytes.extend(value.to_le_bytes());
+    }
+    // sgdByteOffset, sgdByteLength
+    bytes.extend(0u64.to_le_bytes());
+    bytes.extend(0u64.to_le_bytes());
+
+    // level index, offset length and uncompressed length
+    bytes.extend(level_offset.to_le_bytes());
+    bytes.extend(level_size.to_le_bytes());
+    bytes.extend(level_size.to_le_bytes());
+
+    bytes.extend(dfd);
+    faces.iter().for_each(|face| bytes.extend(face));
+    Ok(bytes)
+}
+
+impl VKRenderer<'_> {
+    /// Renders a one off frame into an offscreen image and reads it back
+    /// waits for the gpu to go idle, don't call every frame
+    pub fn capture_frame(
+        &mut self,
+        options: CaptureOptions,
+    ) -> Result<VKCapture, Box<dyn error::Error>> {
+        // captures are upright even when the display is rotated
+        // and match the internal resolution whatever size the window is
+        let vk_swapchain = &self.vulkan_ctx.vulkan_swapchain;
+        let swap_extent = match &self.internal_target {
+            Some(internal_target) => internal_target.resolution.extent,
+            None => RenderTarget::from_swapchain(vk_swapchain, 0).display_extent(),
+        };
+
+        // keep the scaled image within what the device can allocate
+        let max_dimension = self.vulkan_ctx.vulkan_device.limits.max_image_dimension2_d;
+        let max_scale = max_scale(swap_extent, max_dimension);
+        let scale = options.scale.clamp(1, max_scale);
+        if scale < options.scale {
+            warn!(
+                "Capture Scale {} Exceeds Device Limit {}, Using {}",
+                options.scale, max_dimension, scale
+            );
+        }
+        let extent = vk::Extent2D {
+            width: swap_extent.width * scale,
+            height: swap_extent.height * scale,
+        };
+
+        let format = self.capture_format()?;
+        let camera = self
+            .camera
+            .uniform(extent.width as f32 / extent.height as f32)
+            .with_clip_transform(self.depth_convention().clip_transform());
+        let mut views = self.capture_offscreen(extent, format, &[camera])?;
+
+        let capture = VKCapture {
+            extent,
+            format,
+            pixels: views.remove(0),
+        };
+
+        info!(
+            "Captured Frame {}x{}",
+            capture.extent.width, capture.extent.height
+        );
+
+        if options.downsample && scale > 1 {
+            Ok(capture.downsample(scale))
+        } else {
+            Ok(capture)
+        }
+    }
+
+    /// Renders the six cube faces seen from position, for 360 screenshots and environment maps
+    /// faces are in the swapchain format like every capture, see VKCubemapCapture::to_equirect
+    /// waits for the gpu to go idle, don't call every frame
+    pub fn capture_cubemap(
+        &mut self,
+        position: Vec3,
+        face_size: u32,
+    ) -> Result<VKCubemapCapture, Box<dyn error::Error>> {
+        let extent = vk::Extent2D {
+            width: face_size,
+            height: face_size,
+        };
+
+        let format = self.capture_format()?;
+        let cameras = CUBE_FACES.map(|(direction, up)| {
+            Camera::perspective(90.0_f32.to_radians(), 0.1)
+                .look_to(position, direction, up)
+                .uniform(1.0)
+                .with_clip_transform(self.depth_convention().clip_transform())
+        });
+
+        // cube faces are looked at from the inside, so they are mirrored
+        // compared to a camera looking in the same direction
+        let faces = self
+            .capture_offscreen(extent, format, &cameras)?
+            .iter()
+            .map(|face| mirror_horizontal(face, face_size))
+            .collect();
+
+        info!("Captured Cubemap {face_size}x{face_size}");
+
+        Ok(VKCubemapCapture {
+            face_size,
+            format,
+            faces,
+        })
+    }
+
+    // captures are read back in the swapchain format, an HDR scene is drawn in its own format
+    // and clamped into it, captures skip the tonemap like every other post pass
+    fn capture_format(&self) -> Result<vk::Format, Box<dyn error::Error>> {
+        let format = self
+            .vulkan_ctx
+            .vulkan_swapchain
+            .capibilities
+            .ideal_surface_format()
+            .format;
+
+        if capture_format_supported(format) {
+            Ok(format)
+        } else {
+            Err("Unsupported Capture Format".into())
+        }
+    }
+
+    // renders the scene once per camera into an offscreen target and reads each view back
+    fn capture_offscreen(
+        &mut self,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        cameras: &[CameraUniform],
+    ) -> Result<Vec<Vec<u8>>, Box<dyn error::Error>> {
+        let max_dimension = self.vulkan_ctx.vulkan_device.limits.max_image_dimension2_d;
+
+        if extent.width > max_dimension || extent.height > max_dimension {
+            return Err(format!(
+                "Capture Resolution {}x{} Exceeds Device Limit {}",
+                extent.width, extent.height, max_dimension
+            )
+            .into());
+        }
+
+        // captures submit on their own, meshes still being copied have to land first
+        self.uploader.wait(&mut self.vulkan_ctx.vulkan_device)?;
+
+        // frames in flight use the queue, let them finish first
+        unsafe { self.vulkan_ctx.vulkan_device.device.device_wait_idle()? };
+
+        // sprites and debug lines are part of the scene pass, views reuse the first frame in flight like the camera
+        unsafe {
+            self.renderer2d
+                .prepare(&mut self.vulkan_ctx.vulkan_device, 0, &self.sprites)?
+        };
+        unsafe {
+            self.debug_renderer
+                .prepare(&mut self.vulkan_ctx.vulkan_device, 0, &self.debug_draw)?
+        };
+        if self.sync_scene_objects(0) {
+            self.invalidate_command_buffers();
+        }
+        self.prepare_gpu_culling(0, None);
+
+        // host readable buffer every view gets copied into back to back
+        let view_size = u64::from(extent.width)
+            .checked_mul(u64::from(extent.height))
+            .and_then(|pixels| pixels.checked_mul(4))
+            .ok_or("Capture Too Large")?;
+        let readback_size = view_size
+            .checked_mul(cameras.len() as u64)
+            .filter(|size| usize::try_from(*size).is_ok())
+            .ok_or("Capture Too Large")?;
+
+        // the pipeline is built for the scene format and swapchain sample count so the capture
+        // has to match
+        let scene_format = self.vulkan_ctx.vulkan_swapchain.scene_format();
+        let samples = self.vulkan_ctx.vulkan_swapchain.samples;
+
+        let mut resources = CaptureResources::default();
+        let result = resources
+            .create(
+                &mut self.vulkan_ctx.vulkan_device,
+                extent,
+                [scene_format, format],
+                samples,
+                readback_size,
+            )
+            .and_then(|_| self.render_views(&resources, extent, cameras, view_size));
+
+        // clean up offscreen resources before reporting any error
+        unsafe { resources.destroy(&mut self.vulkan_ctx.vulkan_device) };
+
+        result
+    }
+
+    // records one submit per camera into the capture target and reads every view back
+    fn render_views(
+        &mut self,
+        resources: &CaptureResources,
+        extent: vk::Extent2D,
+        cameras: &[CameraUniform],
+        view_size: u64,
+    ) -> Result<Vec<Vec<u8>>, Box<dyn error::Error>> {
+        let (color_image, depth_image, msaa_image) = (
+            &resources.color_image,
+            &resources.depth_image,
+            &resources.msaa_image,
+        );
+        let (image, readback_buffer) = (color_image.image, resources.readback_buffer.buffer);
+        let resolve_image = resources.resolve_image.image;
+        // without a resolve image the scene is drawn in the capture format and read back directly
+        let readback_image = if resolve_image.is_null() {
+            image
+        } else {
+            resolve_image
+        };
+        let readback_size = view_size * cameras.len() as u64;
+
+        let target = RenderTarget {
+            image,
+            image_view: color_image.view,
+            depth_image: depth_image.image,
+            depth_image_view: depth_image.view,
+            msaa_image: msaa_image.image,
+            msaa_image_view: msaa_image.view,
+            samples: depth_image.samples,
+            extent,
+            // offscreen images are never shown by the presentation engine
+            pre_transform: vk::SurfaceTransformFlagsKHR::IDENTITY,
+        };
+
+        // one submit per view, each waits for the queue so the target can be reused
+        for (index, camera) in cameras.iter().enumerate() {
+            // the queue is idle so the first frame in flight's camera is free
+            self.camera_ring.begin_frame(0);
+            let camera = FrameCamera {
+                uniform: *camera,
+                offset: self.camera_ring.push(camera).unwrap_or_default(),
+            };
+            let subresource = vk::ImageSubresourceLayers::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .layer_count(1);
+            let corners = [
+                vk::Offset3D::default(),
+                vk::Offset3D {
+                    x: extent.width as i32,
+                    y: extent.height as i32,
+                    z: 1,
+                },
+            ];
+            let blit_region = vk::ImageBlit::default()
+                .src_subresource(subresource)
+                .src_offsets(corners)
+                .dst_subresource(subresource)
+                .dst_offsets(corners);
+            let copy_region = vk::BufferImageCopy::default()
+                .buffer_offset(view_size * index as u64)
+                .image_subresource(subresource)
+                .image_extent(vk::Extent3D {
+                    width: extent.width,
+                    height: extent.height,
+                    depth: 1,
+                });
+
+            let vk_device = &self.vulkan_ctx.vulkan_device;
+            submit_one_time(vk_device, self.vulkan_cmd_pool, |cmd_buffer| unsafe {
+                self.cmd_begin_label(cmd_buffer, c"Capture", CAPTURE_LABEL_COLOR);
+
+                let mut graph = RenderGraph::default();
+                let color = graph.import_image(image, COLOR_SUBRESOURCE_RANGE, &[]);
+                let readback = graph.import_buffer(readback_buffer, &[]);
+                let mut draw_stats = DrawStats::default();
+                self.scene_objects.cmd_copy_staged(vk_device, cmd_buffer, 0);
+                self.add_scene_passes(&mut graph, color, &target, &camera, 0, &mut draw_stats);
+                let readback_color = if resolve_image.is_null() {
+                    color
+                } else {
+                    let resolved = graph.import_image(resolve_image, COLOR_SUBRESOURCE_RANGE, &[]);
+                    graph.add_pass(
+                        RenderPass::new(c"Resolve HDR")
+                            .read_image(color, Access::TransferSrc)
+                            .discard_image(resolved, Access::TransferDst)
+                            .record(|cmd_buffer| {
+                                vk_device.device.cmd_blit_image(
+                                    cmd_buffer,
+                                    image,
+                                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                                    resolve_image,
+                                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                                    &[blit_region],
+                                    vk::Filter::NEAREST,
+                                )
+                            }),
+                    );
+                    resolved
+                };
+                graph.add_pass(
+                    RenderPass::new(c"Readback")
+                        .read_image(readback_color, Access::TransferSrc)
+                        .write_buffer(readback, Access::TransferDst)
+                        .record(|cmd_buffer| {
+                            vk_device.device.cmd_copy_image_to_buffer(
+                                cmd_buffer,
+                                readback_image,
+                                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                                readback_buffer,
+                                &[copy_region],
+                            )
+                        }),
+                );
+                // make the copy visible to the host
+                graph.export_buffer(readback, Access::HostRead);
+                graph.execute(vk_device, cmd_buffer);
+
+                self.cmd_end_label(cmd_buffer);
+            })?;
+        }
+
+        let views = resources
+            .readback_buffer
+            .allocation
+            .mapped_slice()
+            .ok_or("Capture Readback Memory Not Mapped")?[..readback_size as usize]
+            .chunks_exact(view_size as usize)
+            .map(|view| view.to_vec())
+            .collect();
+
+        Ok(views)
+    }
+}
+
+// largest scale that keeps a capture of extent within max_dimension on both sides
+fn max_scale(extent: vk::Extent2D, max_dimension: u32) -> u32 {
+    (max_dimension / extent.width.max(extent.height).max(1)).max(1)
+}
+
+// offscreen images and the readback buffer a capture renders into
+// anything not created yet stays null, so destroy is safe after a partial create
+#[derive(Default)]
+struct CaptureResources {
+    color_image: VKImage,
+    depth_image: VKImage,
+    msaa_image: VKImage,
+    // the colour image converted to the capture format, null when the scene is drawn in it
+    resolve_image: VKImage,
+    readback_buffer: VKBuffer,
+}
+
+impl CaptureResources {
+    // formats are the scene's then the one read back
+    fn create(
+        &mut self,
+        vk_device: &mut VKDevice,
+        extent: vk::Extent2D,
+        [scene_format, format]: [vk::Format; 2],
+        samples: vk::SampleCountFlags,
+        readback_size: u64,
+    ) -> Result<(), Box<dyn error::Error>> {
+        self.color_image = VKImage::new(
+            vk_device,
+            extent,
+            scene_format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::SampleCountFlags::TYPE_1,
+            vk::ImageAspectFlags::COLOR,
+        )?;
+
+        self.depth_image = VKImage::new(
+            vk_device,
+            extent,
+            DEPTH_FORMAT,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            samples,
+            vk::ImageAspectFlags::DEPTH,
+        )?;
+
+        if samples != vk::SampleCountFlags::TYPE_1 {
+            self.msaa_image = VKImage::new(
+                vk_device,
+                extent,
+                scene_format,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                samples,
+                vk::ImageAspectFlags::COLOR,
+            )?;
+        }
+
+        if scene_format != format {
+            self.resolve_image = VKImage::new(
+                vk_device,
+                extent,
+                format,
+                vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::TRANSFER_SRC,
+                vk::SampleCountFlags::TYPE_1,
+                vk::ImageAspectFlags::COLOR,
+            )?;
+        }
+
+        self.readback_buffer = VKBuffer::new(
+            vk_device,
+            readback_size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+            "Capture Readback",
+        )?;
+
+        Ok(())
+    }
+
+    unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            self.color_image.destroy(vk_device);
+            self.depth_image.destroy(vk_device);
+            self.msaa_image.destroy(vk_device);
+            self.resolve_image.destroy(vk_device);
+            self.readback_buffer.destroy(vk_device);
+        }
+    }
+}
+
+#[test]
+fn box_downsample_test() {
+    // 2x2 image of 4 distinct pixels averages into one
+    let pixels = [
+        0, 0, 0, 255, 100, 0, 0, 255, //
+        0, 100, 0, 255, 0, 0, 100, 255,
+    ];
+    let (out, width, height) = box_downsample(&pixels, 2, 2, 2);
+
+    assert_eq!((width, height), (1, 1));
+    assert_eq!(out, vec![25, 25, 25, 255]);
+}
+
+#[test]
+fn max_scale_test() {
+    let extent = vk::Extent2D {
+        width: 1920,
+        height: 1080,
+    };
+    assert_eq!(max_scale(extent, 16384), 8);
+    // never below 1 even when the window is already too big
+    assert_eq!(max_scale(extent, 1024), 1);
+}
+
+#[test]
+fn mirror_horizontal_test() {
+    let pixels = [
+        1, 1, 1, 1, 2, 2, 2, 2, //
+        3, 3, 3, 3, 4, 4, 4, 4,
+    ];
+    let mirrored = mirror_horizontal(&pixels, 2);
+
+    assert_eq!(
+        mirrored,
+        vec![2, 2, 2, 2, 1, 1, 1, 1, 4, 4, 4, 4, 3, 3, 3, 3]
+    );
+}
+
+#[test]
+fn ktx2_cubemap_layout_test() {
+    let faces = vec![vec![0u8; 4]; 6];
+    let bytes = ktx2_cubemap_bytes(vk::Format::B8G8R8A8_SRGB, 1, &faces).unwrap();
+
+    let read_u32 =
+        |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+
+    assert_eq!(
+        bytes[..12],
+        [
+            0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A
+        ]
+    );
+    assert_eq!(read_u32(12), vk::Format::B8G8R8A8_SRGB.as_raw() as u32);
+    // face count
+    assert_eq!(read_u32(36), 6);
+    // dfd follows the header and the single level index entry
+    assert_eq!(read_u32(48), 104);
+    assert_eq!(read_u32(52), 92);
+    assert_eq!(bytes.len(), 104 + 92 + 6 * 4);
+}
+
+#[test]
+fn equirect_from_faces_test() {
+    // one texel faces that hold their own index
+    let faces: Vec<Vec<u8>> = (0..6).map(|face| vec![face; 4]).collect();
+    let pixels = equirect_from_faces(&faces, 1, 8, 4);
+    let row = |y: usize| -> Vec<u8> { (0..8).map(|x| pixels[(y * 8 + x) * 4]).collect() };
+
+    // the top row looks up, the middle goes round from behind through -X, -Z and +X
+    assert_eq!(row(0), vec![2; 8]);
+    assert_eq!(row(1), vec![4, 1, 1, 5, 5, 0, 0, 4]);
+    assert_eq!(row(3), vec![3; 8]);
+}
@@ -0,0 +1,169 @@
+use glam::{Mat3, Vec4};
+use serde::{Deserialize, Serialize};
+
+/// Colour vision deficiency a filter is built for, each missing one kind of cone
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorBlindness {
+    /// no long wavelength cones, reds look dark and close to greens
+    Protanopia,
+    /// no medium wavelength cones, the most common, reds and greens are confused
+    Deuteranopia,
+    /// no short wavelength cones, blues and greens are confused as are yellows and pinks
+    Tritanopia,
+}
+
+impl ColorBlindness {
+    /// What the deficiency sees of a linear colour, from Machado et al. 2009 at full severity
+    pub fn simulation(&self) -> Mat3 {
+        // rows as printed in the paper, glam takes columns
+        let rows = match self {
+            Self::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            Self::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            Self::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+        };
+        Mat3::from_cols_array_2d(&rows).transpose()
+    }
+
+    /// Where the colour lost to the deficiency is moved to, channels it can still tell apart
+    fn error_shift(&self) -> Mat3 {
+        let rows = match self {
+            Self::Protanopia | Self::Deuteranopia => {
+                [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]]
+            }
+            Self::Tritanopia => [[1.0, 0.0, 0.7], [0.0, 1.0, 0.7], [0.0, 0.0, 0.0]],
+        };
+        Mat3::from_cols_array_2d(&rows).transpose()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorFilterMode {
+    /// shows the image as the deficiency sees it, for checking a game is readable
+    Simulate,
+    /// daltonizes the image, colours the deficiency can't tell apart are pushed apart
+    #[default]
+    Correct,
+}
+
+/// Full screen accessibility filter, the last thing the post pass does before scaling to the window
+/// Example Use:
+/// ```
+/// use glam::Vec3;
+/// use vulkan_engine::renderer::color_filter::{ColorBlindness, ColorFilter};
+///
+/// let filter = ColorFilter::correct(ColorBlindness::Deuteranopia).strength(0.8);
+/// // greys are left alone
+/// let grey = filter.matrix() * Vec3::splat(0.5);
+/// assert!(grey.abs_diff_eq(Vec3::splat(0.5), 1e-3));
+/// ```
+/// Needs an internal resolution, see VKRenderer::set_color_filter
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ColorFilter {
+    pub deficiency: ColorBlindness,
+    pub mode: ColorFilterMode,
+    /// 0 leaves the image alone, 1 is the full filter
+    pub strength: f32,
+}
+
+impl ColorFilter {
+    pub fn new(deficiency: ColorBlindness, mode: ColorFilterMode) -> Self {
+        Self {
+            deficiency,
+            mode,
+            strength: 1.0,
+        }
+    }
+
+    pub fn simulate(deficiency: ColorBlindness) -> Self {
+        Self::new(deficiency, ColorFilterMode::Simulate)
+    }
+
+    pub fn correct(deficiency: ColorBlindness) -> Self {
+        Self::new(deficiency, ColorFilterMode::Correct)
+    }
+
+    pub fn strength(mut self, strength: f32) -> Self {
+        self.strength = strength;
+        self
+    }
+
+    /// Applied to linear colours, both modes are linear so the whole filter is one matrix
+    pub fn matrix(&self) -> Mat3 {
+        let simulation = self.deficiency.simulation();
+        let filter = match self.mode {
+            ColorFilterMode::Simulate => simulation,
+            // the difference between what is there and what is seen, added back where it shows
+            ColorFilterMode::Correct => {
+                Mat3::IDENTITY + self.deficiency.error_shift() * (Mat3::IDENTITY - simulation)
+            }
+        };
+        let strength = self.strength.clamp(0.0, 1.0);
+        Mat3::IDENTITY * (1.0 - strength) + filter * strength
+    }
+
+    /// Rows of matrix padded to vec4s, the layout the post shader reads
+    pub fn rows(&self) -> [Vec4; 3] {
+        let matrix = self.matrix().transpose();
+        [matrix.x_axis, matrix.y_axis, matrix.z_axis].map(|row| row.extend(0.0))
+    }
+}
+
+/// Rows of the identity, what the post shader gets without a filter
+pub const IDENTITY_ROWS: [Vec4; 3] = [Vec4::X, Vec4::Y, Vec4::Z];
+
+#[test]
+fn color_filter_test() {
+    use glam::Vec3;
+
+    let red = Vec3::X;
+    let green = Vec3::Y;
+
+    // red and green end up close for a simulated deuteranope
+    let simulate = ColorFilter::simulate(ColorBlindness::Deuteranopia);
+    let seen_red = simulate.matrix() * red;
+    let seen_green = simulate.matrix() * green;
+    assert!(seen_red.distance(seen_green) < red.distance(green));
+
+    // correcting moves them apart again as seen by the same deficiency
+    let correct = ColorFilter::correct(ColorBlindness::Deuteranopia);
+    let simulation = ColorBlindness::Deuteranopia.simulation();
+    let corrected_red = simulation * (correct.matrix() * red);
+    let corrected_green = simulation * (correct.matrix() * green);
+    assert!(corrected_red.distance(corrected_green) > seen_red.distance(seen_green));
+
+    // simulations keep white white, so every filter leaves greys alone
+    for deficiency in [
+        ColorBlindness::Protanopia,
+        ColorBlindness::Deuteranopia,
+        ColorBlindness::Tritanopia,
+    ] {
+        for filter in [
+            ColorFilter::simulate(deficiency),
+            ColorFilter::correct(deficiency),
+        ] {
+            assert!((filter.matrix() * Vec3::ONE).abs_diff_eq(Vec3::ONE, 1e-2));
+        }
+    }
+
+    assert_eq!(
+        ColorFilter::simulate(ColorBlindness::Tritanopia)
+            .strength(0.0)
+            .matrix(),
+        Mat3::IDENTITY
+    );
+    let rows = simulate.rows();
+    assert_eq!(rows[0].truncate().dot(red), seen_red.x);
+    assert_eq!(rows[1].truncate().dot(red), seen_red.y);
+}
@@ -0,0 +1,182 @@
+use ash::vk;
+use std::collections::HashMap;
+
+use crate::camera::CameraUniform;
+use crate::color::LinearRgba;
+use crate::renderer::device::VKDevice;
+use crate::renderer::renderer2d::{Camera2D, SpriteDraw};
+use crate::renderer::{DrawStats, MeshInstance};
+
+/// Everything a recorded frame depends on, apart from uniform buffer contents which are written every frame
+/// a frame whose inputs compare equal to the last recording can be submitted again as is
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameInputs {
+    pub swapchain: vk::SwapchainKHR,
+    pub image: vk::Image,
+    pub extent: vk::Extent2D,
+    /// bumped when meshes, materials, the skybox or render settings change
+    pub generation: u64,
+    /// culling and the skybox depend on it
+    pub camera: CameraUniform,
+    pub clear_color: LinearRgba,
+    /// which instances are drawn, and how they're batched when culled on the gpu, is recorded
+    pub instances: Vec<MeshInstance>,
+    /// sprite vertices are uploaded every frame, only how they are split into draws is recorded
+    pub sprite_draws: Vec<SpriteDraw>,
+    pub camera_2d: Camera2D,
+    /// sprite and debug line vertex buffers of the frame, they are replaced when they grow
+    pub vertex_buffers: [vk::Buffer; 2],
+    pub debug_vertices: u32,
+}
+
+/// A recorded frame kept for reuse
+pub struct CachedFrame {
+    pub cmd_buffer: vk::CommandBuffer,
+    /// None until recording succeeds
+    pub inputs: Option<FrameInputs>,
+    pub draw_stats: DrawStats,
+}
+
+/// Command buffers recorded per frame in flight and swapchain image, only re-recorded when their inputs change
+/// turned on with RendererOptions::reuse_command_buffers
+#[derive(Default)]
+pub struct VKCommandCache {
+    pub frames: HashMap<(usize, vk::Image), CachedFrame>,
+    /// frames submitted without recording
+    pub reused: u64,
+    pub recorded: u64,
+}
+
+impl VKCommandCache {
+    /// The recorded buffer when nothing changed since it was recorded
+    pub fn reuse(
+        &mut self,
+        frame_in_flight: usize,
+        inputs: &FrameInputs,
+    ) -> Option<(vk::CommandBuffer, DrawStats)> {
+        let frame = self.frames.get(&(frame_in_flight, inputs.image))?;
+        if frame.inputs.as_ref() != Some(inputs) {
+            return None;
+        }
+        self.reused += 1;
+        Some((frame.cmd_buffer, frame.draw_stats))
+    }
+
+    /// Buffer to record the frame into, allocated from vk_command_pool the first time
+    /// the recording is forgotten until store is called
+    pub fn begin(
+        &mut self,
+        vk_device: &VKDevice,
+        vk_command_pool: vk::CommandPool,
+        frame_in_flight: usize,
+        image: vk::Image,
+    ) -> Result<vk::CommandBuffer, vk::Result> {
+        if let Some(frame) = self.frames.get_mut(&(frame_in_flight, image)) {
+            frame.inputs = None;
+            return Ok(frame.cmd_buffer);
+        }
+
+        let cmd_buffer = unsafe {
+            vk_device.device.allocate_command_buffers(
+                &vk::CommandBufferAllocateInfo::default()
+                    .command_pool(vk_command_pool)
+                    .command_buffer_count(1)
+                    .level(vk::CommandBufferLevel::PRIMARY),
+            )?[0]
+        };
+        self.frames.insert(
+            (frame_in_flight, image),
+            CachedFrame {
+                cmd_buffer,
+                inputs: None,
+                draw_stats: DrawStats::default(),
+            },
+        );
+        Ok(cmd_buffer)
+    }
+
+    /// Marks the buffer from begin as recorded with inputs
+    pub fn store(&mut self, frame_in_flight: usize, inputs: FrameInputs, draw_stats: DrawStats) {
+        if let Some(frame) = self.frames.get_mut(&(frame_in_flight, inputs.image)) {
+            frame.inputs = Some(inputs);
+            frame.draw_stats = draw_stats;
+            self.recorded += 1;
+        }
+    }
+
+    /// Frees buffers recorded for images that are no longer in the swapchain
+    /// # Safety
+    /// The gpu must be done with them, rebuilding the swapchain waits for the queue
+    pub unsafe fn retain_images(
+        &mut self,
+        vk_device: &VKDevice,
+        vk_command_pool: vk::CommandPool,
+        images: &[vk::Image],
+    ) {
+        self.frames.retain(|(_, image), frame| {
+            let keep = images.contains(image);
+            if !keep {
+                unsafe {
+                    vk_device
+                        .device
+                        .free_command_buffers(vk_command_pool, &[frame.cmd_buffer])
+                };
+            }
+            keep
+        });
+    }
+}
+
+#[test]
+fn command_cache_test() {
+    use crate::camera::Camera;
+    use ash::vk::Handle;
+
+    let image = vk::Image::from_raw(1);
+    let inputs = FrameInputs {
+        swapchain: vk::SwapchainKHR::from_raw(1),
+        image,
+        extent: vk::Extent2D::default().width(640).height(480),
+        generation: 0,
+        camera: Camera::perspective(1.0, 0.1).uniform(640.0 / 480.0),
+        clear_color: LinearRgba::BLACK,
+        instances: vec![MeshInstance::default()],
+        sprite_draws: Vec::new(),
+        camera_2d: Camera2D::default(),
+        vertex_buffers: [vk::Buffer::null(); 2],
+        debug_vertices: 0,
+    };
+
+    let mut cache = VKCommandCache::default();
+    // what begin leaves behind, allocating needs a device
+    cache.frames.insert(
+        (0, image),
+        CachedFrame {
+            cmd_buffer: vk::CommandBuffer::from_raw(7),
+            inputs: None,
+            draw_stats: DrawStats::default(),
+        },
+    );
+    assert_eq!(cache.reuse(0, &inputs), None);
+
+    let draw_stats = DrawStats {
+        submitted: 1,
+        culled: 0,
+    };
+    cache.store(0, inputs.clone(), draw_stats);
+    assert_eq!(
+        cache.reuse(0, &inputs),
+        Some((vk::CommandBuffer::from_raw(7), draw_stats))
+    );
+    // other frames in flight record their own
+    assert_eq!(cache.reuse(1, &inputs), None);
+
+    let mut moved = inputs.clone();
+    moved.instances[0].transform = glam::Mat4::from_translation(glam::Vec3::X);
+    assert_eq!(cache.reuse(0, &moved), None);
+
+    let mut invalidated = inputs.clone();
+    invalidated.generation += 1;
+    assert_eq!(cache.reuse(0, &invalidated), None);
+    assert_eq!((cache.reused, cache.recorded), (1, 1));
+}
@@ -0,0 +1,491 @@
+use ash::vk;
+use std::error;
+use std::ffi::CStr;
+
+use crate::renderer::device::VKDevice;
+use crate::renderer::shader::{VKShader, VKShaderLoader};
+use crate::renderer::{VKRenderer, push_constant_range, submit_one_time};
+
+/// Compute shader entry points reading and writing storage buffers at set 0 bindings 0..storage_buffers
+/// with push constants of the type given to new, every entry point shares the same layout
+/// Example Use:
+/// ```ignore
+/// let pipeline = VKComputePipeline::new::<MyConstants>(
+///     &renderer.vulkan_ctx.vulkan_device,
+///     &mut renderer.vulkan_shader_loader,
+///     "shaders/my_compute.spv",
+///     &[c"main"],
+///     2,
+///     1,
+/// )?;
+/// let set = pipeline.allocate_set(vk_device, &[input, output])?;
+/// unsafe {
+///     pipeline.cmd_dispatch(vk_device, cmd_buffer, 0, set, &constants, group_count(len, 256));
+///     cmd_compute_barrier(vk_device, cmd_buffer);
+/// }
+/// ```
+pub struct VKComputePipeline<'a> {
+    pub shaders: Vec<VKShader<'a>>,
+    pub descriptor_layout: vk::DescriptorSetLayout,
+    pub pipeline_layout: vk::PipelineLayout,
+    /// one per entry point, in the order given to new
+    pub pipelines: Vec<vk::Pipeline>,
+    /// sets from allocate_set come from here and can be freed one at a time
+    pub descriptor_pool: vk::DescriptorPool,
+    pub storage_buffers: u32,
+}
+
+impl VKComputePipeline<'_> {
+    /// max_sets is how many buffer sets can be bound at once
+    pub fn new<T>(
+        vk_device: &VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        shader_path: &'static str,
+        shader_entries: &[&'static CStr],
+        storage_buffers: u32,
+        max_sets: u32,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let shaders = shader_entries
+            .iter()
+            .map(|entry| {
+                VKShader::new(
+                    vk_device,
+                    shader_path,
+                    vk::ShaderStageFlags::COMPUTE,
+                    entry,
+                    vk_shader_loader,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let set_bindings: Vec<_> = (0..storage_buffers)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(binding)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            })
+            .collect();
+
+        let descriptor_layout = unsafe {
+            vk_device.device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(&set_bindings),
+                None,
+            )?
+        };
+
+        let descriptor_layouts = [descriptor_layout];
+        let push_constant_ranges = [push_constant_range::<T>(vk::ShaderStageFlags::COMPUTE, 0)];
+        let pipeline_layout = unsafe {
+            vk_device.device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&descriptor_layouts)
+                    .push_constant_ranges(&push_constant_ranges),
+                None,
+            )?
+        };
+
+        let create_infos: Vec<_> = shaders
+            .iter()
+            .map(|shader| {
+                vk::ComputePipelineCreateInfo::default()
+                    .stage(shader.shader_info)
+                    .layout(pipeline_layout)
+            })
+            .collect();
+        let pipelines = unsafe {
+            vk_device
+                .device
+                .create_compute_pipelines(vk_device.pipeline_cache.cache, &create_infos, None)
+                .map_err(|(_, error)| error)?
+        };
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: storage_buffers.max(1) * max_sets,
+        }];
+        let descriptor_pool = unsafe {
+            vk_device.device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+                    .max_sets(max_sets)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )?
+        };
+
+        Ok(Self {
+            shaders,
+            descriptor_layout,
+            pipeline_layout,
+            pipelines,
+            descriptor_pool,
+            storage_buffers,
+        })
+    }
+
+    /// Set pointing at the whole of each buffer, in binding order
+    /// buffers need STORAGE_BUFFER usage
+    pub fn allocate_set(
+        &self,
+        vk_device: &VKDevice,
+        buffers: &[vk::Buffer],
+    ) -> Result<vk::DescriptorSet, vk::Result> {
+        assert_eq!(
+            buffers.len(),
+            self.storage_buffers as usize,
+            "compute pipeline takes {} storage buffers",
+            self.storage_buffers
+        );
+
+        let set = unsafe {
+            vk_device.device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(self.descriptor_pool)
+                    .set_layouts(&[self.descriptor_layout]),
+            )?[0]
+        };
+
+        let buffer_infos: Vec<_> = buffers
+            .iter()
+            .map(|buffer| {
+                [vk::DescriptorBufferInfo::default()
+                    .buffer(*buffer)
+                    .offset(0)
+                    .range(vk::WHOLE_SIZE)]
+            })
+            .collect();
+        let writes: Vec<_> = buffer_infos
+            .iter()
+            .enumerate()
+            .map(|(binding, info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(binding as u32)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(info)
+            })
+            .collect();
+        unsafe { vk_device.device.update_descriptor_sets(&writes, &[]) };
+
+        Ok(set)
+    }
+
+    /// # Safety
+    /// The gpu must not be using set
+    pub unsafe fn free_set(
+        &self,
+        vk_device: &VKDevice,
+        set: vk::DescriptorSet,
+    ) -> Result<(), vk::Result> {
+        unsafe {
+            vk_device
+                .device
+                .free_descriptor_sets(self.descriptor_pool, &[set])
+        }
+    }
+
+    /// Binds the pipeline for entry and set then dispatches group_count workgroups along x
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering, constants must be the type given to new
+    pub unsafe fn cmd_dispatch<T: Copy>(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        entry: usize,
+        set: vk::DescriptorSet,
+        constants: &T,
+        group_count: u32,
+    ) {
+        unsafe {
+            self.cmd_dispatch_3d(
+                vk_device,
+                cmd_buffer,
+                entry,
+                set,
+                constants,
+                [group_count, 1, 1],
+            )
+        };
+    }
+
+    /// Like cmd_dispatch with workgroups along x, y and z, e.g. one per tile of an image
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering, constants must be the type given to new
+    pub unsafe fn cmd_dispatch_3d<T: Copy>(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        entry: usize,
+        set: vk::DescriptorSet,
+        constants: &T,
+        group_counts: [u32; 3],
+    ) {
+        let [x, y, z] = group_counts;
+        unsafe {
+            self.cmd_bind(vk_device, cmd_buffer, entry, set, constants);
+            vk_device.device.cmd_dispatch(cmd_buffer, x, y, z);
+        }
+    }
+
+    /// Like cmd_dispatch with the workgroup counts read from the vk::DispatchIndirectCommand the gpu
+    /// wrote at (buffer, offset) in arguments, so an earlier dispatch can size a later one
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering, buffer needs INDIRECT_BUFFER usage and
+    /// the write must be made visible with cmd_buffer_barrier(.., BufferUse::IndirectRead)
+    pub unsafe fn cmd_dispatch_indirect<T: Copy>(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        entry: usize,
+        set: vk::DescriptorSet,
+        constants: &T,
+        arguments: (vk::Buffer, vk::DeviceSize),
+    ) {
+        let (buffer, offset) = arguments;
+        unsafe {
+            self.cmd_bind(vk_device, cmd_buffer, entry, set, constants);
+            vk_device
+                .device
+                .cmd_dispatch_indirect(cmd_buffer, buffer, offset);
+        }
+    }
+
+    // pipeline for entry, its set and constants
+    unsafe fn cmd_bind<T: Copy>(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        entry: usize,
+        set: vk::DescriptorSet,
+        constants: &T,
+    ) {
+        unsafe {
+            vk_device.device.cmd_bind_pipeline(
+                cmd_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipelines[entry],
+            );
+            vk_device.device.cmd_bind_descriptor_sets(
+                cmd_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[set],
+                &[],
+            );
+            vk_device.cmd_push_constants(
+                cmd_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                constants,
+            );
+        }
+    }
+
+    /// # Safety
+    /// The gpu must not be using the pipeline, sets allocated from it are freed with it
+    pub unsafe fn destroy(&mut self, vk_device: &VKDevice) {
+        unsafe {
+            vk_device
+                .device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            for pipeline in self.pipelines.drain(..) {
+                vk_device.device.destroy_pipeline(pipeline, None);
+            }
+            vk_device
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            vk_device
+                .device
+                .destroy_descriptor_set_layout(self.descriptor_layout, None);
+            for shader in &mut self.shaders {
+                shader.destroy(vk_device);
+            }
+        }
+    }
+}
+
+/// Makes storage buffer writes from earlier dispatches visible to later ones
+/// # Safety
+/// cmd_buffer must be recording outside of rendering
+pub unsafe fn cmd_compute_barrier(vk_device: &VKDevice, cmd_buffer: vk::CommandBuffer) {
+    let barriers = [vk::MemoryBarrier2::default()
+        .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+        .src_access_mask(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+        .dst_access_mask(
+            vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+        )];
+    unsafe {
+        vk_device.device.cmd_pipeline_barrier2(
+            cmd_buffer,
+            &vk::DependencyInfo::default().memory_barriers(&barriers),
+        );
+    }
+}
+
+/// How a buffer is used on either side of cmd_buffer_barrier
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferUse {
+    ComputeRead,
+    ComputeWrite,
+    /// copies and fills
+    TransferRead,
+    TransferWrite,
+    /// mapped memory read or written by the cpu
+    HostRead,
+    HostWrite,
+    /// vertex or index buffer of a draw
+    VertexInput,
+    /// draw or dispatch arguments
+    IndirectRead,
+    /// storage or uniform buffer read while drawing
+    GraphicsRead,
+}
+
+impl BufferUse {
+    pub fn stage(self) -> vk::PipelineStageFlags2 {
+        match self {
+            Self::ComputeRead | Self::ComputeWrite => vk::PipelineStageFlags2::COMPUTE_SHADER,
+            Self::TransferRead | Self::TransferWrite => vk::PipelineStageFlags2::ALL_TRANSFER,
+            Self::HostRead | Self::HostWrite => vk::PipelineStageFlags2::HOST,
+            Self::VertexInput => vk::PipelineStageFlags2::VERTEX_INPUT,
+            Self::IndirectRead => vk::PipelineStageFlags2::DRAW_INDIRECT,
+            Self::GraphicsRead => {
+                vk::PipelineStageFlags2::VERTEX_SHADER | vk::PipelineStageFlags2::FRAGMENT_SHADER
+            }
+        }
+    }
+
+    pub fn access(self) -> vk::AccessFlags2 {
+        match self {
+            Self::ComputeRead | Self::GraphicsRead => vk::AccessFlags2::SHADER_READ,
+            Self::ComputeWrite => vk::AccessFlags2::SHADER_WRITE,
+            Self::TransferRead => vk::AccessFlags2::TRANSFER_READ,
+            Self::TransferWrite => vk::AccessFlags2::TRANSFER_WRITE,
+            Self::HostRead => vk::AccessFlags2::HOST_READ,
+            Self::HostWrite => vk::AccessFlags2::HOST_WRITE,
+            Self::VertexInput => {
+                vk::AccessFlags2::VERTEX_ATTRIBUTE_READ | vk::AccessFlags2::INDEX_READ
+            }
+            Self::IndirectRead => vk::AccessFlags2::INDIRECT_COMMAND_READ,
+        }
+    }
+
+    pub fn is_write(self) -> bool {
+        matches!(
+            self,
+            Self::ComputeWrite | Self::TransferWrite | Self::HostWrite
+        )
+    }
+}
+
+/// Makes buffer accesses of src finish before, and writes visible to, the dst accesses after it
+/// read after read needs no barrier, cmd_compute_barrier covers dispatches feeding dispatches
+/// Example Use:
+/// ```ignore
+/// pipeline.cmd_dispatch(vk_device, cmd_buffer, 0, set, &constants, groups);
+/// // the dispatch wrote the vertices the next draw reads
+/// cmd_buffer_barrier(vk_device, cmd_buffer, BufferUse::ComputeWrite, BufferUse::VertexInput);
+/// ```
+/// # Safety
+/// cmd_buffer must be recording outside of rendering
+pub unsafe fn cmd_buffer_barrier(
+    vk_device: &VKDevice,
+    cmd_buffer: vk::CommandBuffer,
+    src: BufferUse,
+    dst: BufferUse,
+) {
+    // reads only have to finish before what comes after, there is nothing to make visible
+    let src_access = if src.is_write() {
+        src.access()
+    } else {
+        vk::AccessFlags2::NONE
+    };
+    let barriers = [vk::MemoryBarrier2::default()
+        .src_stage_mask(src.stage())
+        .src_access_mask(src_access)
+        .dst_stage_mask(dst.stage())
+        .dst_access_mask(dst.access())];
+    unsafe {
+        vk_device.device.cmd_pipeline_barrier2(
+            cmd_buffer,
+            &vk::DependencyInfo::default().memory_barriers(&barriers),
+        );
+    }
+}
+
+impl VKRenderer<'_> {
+    /// Records a one off compute workload and waits for it, results can be read from mapped
+    /// buffers afterwards, for gpgpu work outside of frames
+    /// Example Use:
+    /// ```ignore
+    /// let set = pipeline.allocate_set(&renderer.vulkan_ctx.vulkan_device, &[input, output])?;
+    /// renderer.run_compute(|vk_device, cmd_buffer| unsafe {
+    ///     pipeline.cmd_dispatch(vk_device, cmd_buffer, 0, set, &constants, group_count(len, 64));
+    /// })?;
+    /// ```
+    /// Blocks until the graphics queue is idle, don't call every frame.
+    pub fn run_compute<F>(&self, record: F) -> Result<(), vk::Result>
+    where
+        F: FnOnce(&VKDevice, vk::CommandBuffer),
+    {
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        submit_one_time(vk_device, self.vulkan_cmd_pool, |cmd_buffer| {
+            record(vk_device, cmd_buffer);
+            // storage buffer writes have to reach mapped memory before the cpu reads them
+            unsafe {
+                cmd_buffer_barrier(
+                    vk_device,
+                    cmd_buffer,
+                    BufferUse::ComputeWrite,
+                    BufferUse::HostRead,
+                )
+            };
+        })
+    }
+}
+
+/// Workgroups needed for one thread per item
+pub fn group_count(items: u32, group_size: u32) -> u32 {
+    items.div_ceil(group_size)
+}
+
+#[test]
+fn buffer_use_test() {
+    // graphics stages never show up for compute and transfer work
+    for buffer_use in [
+        BufferUse::ComputeRead,
+        BufferUse::ComputeWrite,
+        BufferUse::TransferRead,
+        BufferUse::TransferWrite,
+    ] {
+        assert!(
+            !buffer_use
+                .stage()
+                .intersects(vk::PipelineStageFlags2::FRAGMENT_SHADER)
+        );
+    }
+    assert!(BufferUse::ComputeWrite.is_write());
+    assert!(!BufferUse::IndirectRead.is_write());
+    assert_eq!(
+        BufferUse::IndirectRead.access(),
+        vk::AccessFlags2::INDIRECT_COMMAND_READ
+    );
+    // indirect dispatches read their arguments in the draw indirect stage too
+    assert_eq!(
+        BufferUse::IndirectRead.stage(),
+        vk::PipelineStageFlags2::DRAW_INDIRECT
+    );
+}
+
+#[test]
+fn group_count_test() {
+    assert_eq!(group_count(0, 256), 0);
+    assert_eq!(group_count(1, 256), 1);
+    assert_eq!(group_count(256, 256), 1);
+    assert_eq!(group_count(257, 256), 2);
+}
@@ -0,0 +1,447 @@
+use ash::vk;
+use glam::{Vec3, Vec4};
+use gpu_allocator::MemoryLocation;
+use image::DynamicImage;
+use std::error;
+use std::f32::consts::PI;
+use std::path::Path;
+
+use crate::color::srgb_to_linear;
+use crate::renderer::allocator::VKAllocation;
+use crate::renderer::buffer::VKBuffer;
+use crate::renderer::device::VKDevice;
+use crate::renderer::texture::TEXTURE_FORMAT;
+use crate::renderer::{CUBE_FACES, CUBE_SUBRESOURCE_RANGE, submit_one_time};
+
+// hdr skies keep values above 1, half floats are the widest format every device can filter
+pub const HDR_CUBEMAP_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// A sampled cube image with its own sampler, looked up with a direction instead of uvs
+/// faces are square and stored in +X -X +Y -Y +Z -Z order
+pub struct VKCubemap {
+    pub image: vk::Image,
+    pub allocation: VKAllocation,
+    pub image_view: vk::ImageView,
+    pub sampler: vk::Sampler,
+    /// width and height of every face
+    pub size: u32,
+    pub format: vk::Format,
+}
+
+impl VKCubemap {
+    /// Loads 6 square PNG or JPEG faces of the same size in +X -X +Y -Y +Z -Z order
+    /// Example Use:
+    /// ```ignore
+    /// let sky = VKCubemap::from_files(&mut vk_device, cmd_pool, [
+    ///     "sky/px.png", "sky/nx.png", "sky/py.png", "sky/ny.png", "sky/pz.png", "sky/nz.png",
+    /// ])?;
+    /// ```
+    pub fn from_files(
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+        paths: [impl AsRef<Path>; 6],
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let mut size = None;
+        let mut pixels = Vec::new();
+        for path in paths {
+            let face = image::open(path.as_ref())?.into_rgba8();
+            let (width, height) = face.dimensions();
+            if width != height || size.is_some_and(|size| size != width) {
+                return Err(format!(
+                    "cubemap face {} is {width}x{height}, faces must be square and the same size",
+                    path.as_ref().display()
+                )
+                .into());
+            }
+            size = Some(width);
+            pixels.extend_from_slice(face.as_raw());
+        }
+
+        Ok(Self::from_rgba8(
+            vk_device,
+            vk_command_pool,
+            size.unwrap_or_default(),
+            &pixels,
+        )?)
+    }
+
+    /// Loads a 2:1 equirectangular panorama and projects it onto faces of face_size
+    /// Radiance .hdr files keep their full range, PNG and JPEG are treated as srgb
+    /// Example Use:
+    /// ```ignore
+    /// let sky = VKCubemap::from_equirectangular_file(&mut vk_device, cmd_pool, "sky/sunset.hdr", 512)?;
+    /// ```
+    pub fn from_equirectangular_file(
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+        path: impl AsRef<Path>,
+        face_size: u32,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let image = image::open(path)?;
+        let hdr = matches!(
+            image,
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+        );
+
+        let mut panorama = image.into_rgba32f();
+        if !hdr {
+            for pixel in panorama.pixels_mut() {
+                for channel in &mut pixel.0[..3] {
+                    *channel = srgb_to_linear(*channel);
+                }
+            }
+        }
+
+        let (width, height) = panorama.dimensions();
+        let faces = equirectangular_to_faces(width, height, panorama.as_raw(), face_size);
+        Ok(Self::from_rgba32f(
+            vk_device,
+            vk_command_pool,
+            face_size,
+            &faces,
+        )?)
+    }
+
+    /// Uploads 8bit srgb RGBA faces packed one after another
+    pub fn from_rgba8(
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+        size: u32,
+        pixels: &[u8],
+    ) -> Result<Self, vk::Result> {
+        if pixels.len() != face_texels(size) * 4 {
+            return Err(vk::Result::ERROR_INITIALIZATION_FAILED);
+        }
+        Self::upload(vk_device, vk_command_pool, size, TEXTURE_FORMAT, pixels)
+    }
+
+    /// Uploads linear float RGBA faces packed one after another, stored as half floats
+    pub fn from_rgba32f(
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+        size: u32,
+        pixels: &[f32],
+    ) -> Result<Self, vk::Result> {
+        if pixels.len() != face_texels(size) * 4 {
+            return Err(vk::Result::ERROR_INITIALIZATION_FAILED);
+        }
+        let bytes: Vec<u8> = pixels
+            .iter()
+            .flat_map(|&channel| f32_to_f16_bits(channel).to_ne_bytes())
+            .collect();
+        Self::upload(vk_device, vk_command_pool, size, HDR_CUBEMAP_FORMAT, &bytes)
+    }
+
+    // same staging buffer path as VKTexture, with every face copied in one region
+    fn upload(
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+        size: u32,
+        format: vk::Format,
+        bytes: &[u8],
+    ) -> Result<Self, vk::Result> {
+        if size == 0 {
+            return Err(vk::Result::ERROR_INITIALIZATION_FAILED);
+        }
+
+        let mut staging = VKBuffer::new(
+            vk_device,
+            bytes.len() as u64,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+            "Cubemap Staging",
+        )?;
+
+        if let Err(error) = staging.write(0, bytes) {
+            unsafe { staging.destroy(vk_device) };
+            return Err(error);
+        }
+
+        let (image, allocation) = match vk_device.create_cube_image(
+            size,
+            format,
+            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+        ) {
+            Ok(image) => image,
+            Err(error) => {
+                unsafe { staging.destroy(vk_device) };
+                return Err(error);
+            }
+        };
+
+        let upload_result = submit_one_time(vk_device, vk_command_pool, |cmd_buffer| unsafe {
+            record_upload(vk_device, cmd_buffer, staging.buffer, image, size);
+        });
+
+        // upload has finished or failed, either way the staging buffer is done with
+        unsafe { staging.destroy(vk_device) };
+
+        if let Err(error) = upload_result {
+            unsafe { vk_device.destroy_image(image, allocation) };
+            return Err(error);
+        }
+
+        let image_view = match vk_device.create_cube_image_view(image, format) {
+            Ok(image_view) => image_view,
+            Err(error) => {
+                unsafe { vk_device.destroy_image(image, allocation) };
+                return Err(error);
+            }
+        };
+
+        // cube lookups filter across face edges by themselves, clamping keeps the edges from wrapping
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .max_lod(vk::LOD_CLAMP_NONE);
+
+        let sampler = match unsafe { vk_device.device.create_sampler(&sampler_info, None) } {
+            Ok(sampler) => sampler,
+            Err(error) => {
+                unsafe {
+                    vk_device.device.destroy_image_view(image_view, None);
+                    vk_device.destroy_image(image, allocation);
+                }
+                return Err(error);
+            }
+        };
+
+        Ok(Self {
+            image,
+            allocation,
+            image_view,
+            sampler,
+            size,
+            format,
+        })
+    }
+
+    /// Descriptor info for writing this cubemap into a combined image sampler binding
+    pub fn descriptor_image_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo::default()
+            .sampler(self.sampler)
+            .image_view(self.image_view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+
+    /// # Safety
+    /// Cubemap must not be in use by the gpu
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            vk_device.device.destroy_sampler(self.sampler, None);
+            vk_device.device.destroy_image_view(self.image_view, None);
+            vk_device.destroy_image(self.image, std::mem::take(&mut self.allocation));
+        }
+    }
+}
+
+// UNDEFINED -> TRANSFER_DST, copy every face, TRANSFER_DST -> SHADER_READ_ONLY
+unsafe fn record_upload(
+    vk_device: &VKDevice,
+    cmd_buffer: vk::CommandBuffer,
+    staging_buffer: vk::Buffer,
+    image: vk::Image,
+    size: u32,
+) {
+    let to_transfer_dst = [vk::ImageMemoryBarrier2::default()
+        .old_layout(vk::ImageLayout::UNDEFINED)
+        .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .src_stage_mask(vk::PipelineStageFlags2::NONE)
+        .dst_stage_mask(vk::PipelineStageFlags2::COPY)
+        .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+        .image(image)
+        .subresource_range(CUBE_SUBRESOURCE_RANGE)];
+
+    let to_shader_read = [vk::ImageMemoryBarrier2::default()
+        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .src_stage_mask(vk::PipelineStageFlags2::COPY)
+        .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags2::FRAGMENT_SHADER)
+        .dst_access_mask(vk::AccessFlags2::SHADER_SAMPLED_READ)
+        .image(image)
+        .subresource_range(CUBE_SUBRESOURCE_RANGE)];
+
+    // faces are tightly packed one after another, so one region covers every layer
+    let copy_region = vk::BufferImageCopy::default()
+        .image_subresource(
+            vk::ImageSubresourceLayers::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .layer_count(CUBE_FACES),
+        )
+        .image_extent(vk::Extent3D {
+            width: size,
+            height: size,
+            depth: 1,
+        });
+
+    unsafe {
+        vk_device.device.cmd_pipeline_barrier2(
+            cmd_buffer,
+            &vk::DependencyInfo::default().image_memory_barriers(&to_transfer_dst),
+        );
+
+        vk_device.device.cmd_copy_buffer_to_image(
+            cmd_buffer,
+            staging_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[copy_region],
+        );
+
+        vk_device.device.cmd_pipeline_barrier2(
+            cmd_buffer,
+            &vk::DependencyInfo::default().image_memory_barriers(&to_shader_read),
+        );
+    }
+}
+
+fn face_texels(size: u32) -> usize {
+    size as usize * size as usize * CUBE_FACES as usize
+}
+
+/// Direction a cube lookup needs to land on s, t of face, both 0..1 from the top left
+/// undoes the face selection from the vulkan spec's cube map table
+pub fn cube_face_direction(face: u32, s: f32, t: f32) -> Vec3 {
+    let sc = s * 2.0 - 1.0;
+    let tc = t * 2.0 - 1.0;
+    let direction = match face {
+        0 => Vec3::new(1.0, -tc, -sc),
+        1 => Vec3::new(-1.0, -tc, sc),
+        2 => Vec3::new(sc, 1.0, tc),
+        3 => Vec3::new(sc, -1.0, -tc),
+        4 => Vec3::new(sc, -tc, 1.0),
+        _ => Vec3::new(-sc, -tc, -1.0),
+    };
+    direction.normalize()
+}
+
+/// Projects a linear RGBA equirectangular panorama onto 6 faces of face_size, packed like from_rgba32f wants
+/// the centre of the panorama faces -Z, the top row is straight up
+pub fn equirectangular_to_faces(
+    width: u32,
+    height: u32,
+    pixels: &[f32],
+    face_size: u32,
+) -> Vec<f32> {
+    let mut faces = Vec::with_capacity(face_texels(face_size) * 4);
+    for face in 0..CUBE_FACES {
+        for y in 0..face_size {
+            for x in 0..face_size {
+                let s = (x as f32 + 0.5) / face_size as f32;
+                let t = (y as f32 + 0.5) / face_size as f32;
+                let direction = cube_face_direction(face, s, t);
+                let texel = sample_equirectangular(width, height, pixels, direction);
+                faces.extend_from_slice(&texel.to_array());
+            }
+        }
+    }
+    faces
+}
+
+// bilinear lookup, wraps around horizontally and clamps at the poles
+fn sample_equirectangular(width: u32, height: u32, pixels: &[f32], direction: Vec3) -> Vec4 {
+    let longitude = direction.x.atan2(-direction.z);
+    let latitude = direction.y.clamp(-1.0, 1.0).asin();
+    let u = 0.5 + longitude / (2.0 * PI);
+    let v = 0.5 - latitude / PI;
+
+    let x = u * width as f32 - 0.5;
+    let y = (v * height as f32 - 0.5).clamp(0.0, height as f32 - 1.0);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let texel = |x: f32, y: f32| {
+        let x = (x as i64).rem_euclid(width as i64) as usize;
+        let y = (y as usize).min(height as usize - 1);
+        let index = (y * width as usize + x) * 4;
+        Vec4::from_slice(&pixels[index..index + 4])
+    };
+
+    let top = texel(x0, y0).lerp(texel(x0 + 1.0, y0), fx);
+    let bottom = texel(x0, y0 + 1.0).lerp(texel(x0 + 1.0, y0 + 1.0), fx);
+    top.lerp(bottom, fy)
+}
+
+// round to nearest even, too large becomes infinity and too small flushes towards 0
+fn f32_to_f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    // infinity and nan
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    // subnormal halfs keep the implicit 1 in the mantissa
+    let (half, mantissa, shift) = if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - half_exponent) as u32;
+        (mantissa >> shift, mantissa, shift)
+    } else {
+        (
+            ((half_exponent as u32) << 10) | (mantissa >> 13),
+            mantissa,
+            13,
+        )
+    };
+
+    // round bit set and either something after it or an odd result, carrying into the exponent is fine
+    let round_bit = 1 << (shift - 1);
+    let round_up = mantissa & round_bit != 0 && mantissa & (3 * round_bit - 1) != 0;
+    sign | (half + round_up as u32) as u16
+}
+
+#[test]
+fn cube_face_direction_test() {
+    // face centres point down their axis
+    let axes = [
+        Vec3::X,
+        Vec3::NEG_X,
+        Vec3::Y,
+        Vec3::NEG_Y,
+        Vec3::Z,
+        Vec3::NEG_Z,
+    ];
+    for (face, axis) in (0..CUBE_FACES).zip(axes) {
+        assert!(cube_face_direction(face, 0.5, 0.5).abs_diff_eq(axis, 1e-6));
+    }
+
+    // top of the side faces is up
+    assert!(cube_face_direction(4, 0.5, 0.0).y > 0.0);
+    assert!(cube_face_direction(0, 0.5, 0.0).y > 0.0);
+
+    // 4x2 panorama, left half red and right half green
+    let red = [1.0, 0.0, 0.0, 1.0];
+    let green = [0.0, 1.0, 0.0, 1.0];
+    let panorama: Vec<f32> = [red, red, green, green, red, red, green, green].concat();
+    let faces = equirectangular_to_faces(4, 2, &panorama, 2);
+    assert_eq!(faces.len(), face_texels(2) * 4);
+
+    // -X is a quarter of the way along, +X three quarters
+    let face_texel = |face: usize| &faces[face * 2 * 2 * 4..][..4];
+    assert_eq!(face_texel(1), red);
+    assert_eq!(face_texel(0), green);
+
+    assert_eq!(f32_to_f16_bits(1.0), 0x3c00);
+    assert_eq!(f32_to_f16_bits(-2.0), 0xc000);
+    assert_eq!(f32_to_f16_bits(0.333_333_34), 0x3555);
+    assert_eq!(f32_to_f16_bits(65504.0), 0x7bff);
+    assert_eq!(f32_to_f16_bits(1e6), 0x7c00);
+    assert_eq!(f32_to_f16_bits(2.0_f32.powi(-24)), 0x0001);
+    assert_eq!(f32_to_f16_bits(1e-10), 0);
+}
@@ -0,0 +1,204 @@
+use ash::ext::debug_utils;
+use ash::{Entry, Instance, vk};
+use log::{debug, error, trace, warn};
+use std::ffi::{CStr, CString, c_void};
+
+use crate::color::LinearRgba;
+
+pub const VALIDATION_LAYER: &CStr = c"VK_LAYER_KHRONOS_validation";
+/// Prints every vulkan call and its arguments, very slow
+pub const API_DUMP_LAYER: &CStr = c"VK_LAYER_LUNARG_api_dump";
+/// Lets RenderDoc capture frames without launching through it
+pub const RENDERDOC_CAPTURE_LAYER: &CStr = c"VK_LAYER_RENDERDOC_Capture";
+
+/// Routes validation layer and driver messages through log
+pub struct VKDebugMessenger {
+    pub debug_utils: debug_utils::Instance,
+    pub messenger: vk::DebugUtilsMessengerEXT,
+}
+
+impl VKDebugMessenger {
+    /// Create info for the messenger
+    /// can also be chained onto instance creation to catch messages from create/destroy instance
+    pub fn create_info(
+        message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    ) -> vk::DebugUtilsMessengerCreateInfoEXT<'static> {
+        vk::DebugUtilsMessengerCreateInfoEXT::default()
+            .message_severity(message_severity)
+            .message_type(
+                vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                    | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                    | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+            )
+            .pfn_user_callback(Some(vulkan_debug_callback))
+    }
+
+    pub fn new(
+        entry: &Entry,
+        instance: &Instance,
+        message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    ) -> Result<Self, vk::Result> {
+        let debug_utils = debug_utils::Instance::new(entry, instance);
+        let messenger = unsafe {
+            debug_utils.create_debug_utils_messenger(&Self::create_info(message_severity), None)?
+        };
+
+        Ok(Self {
+            debug_utils,
+            messenger,
+        })
+    }
+
+    /// # Safety
+    /// Destroy Before Vulkan Instance
+    /// Read VK Docs For Destruction Order
+    pub unsafe fn destroy(&mut self) {
+        unsafe {
+            self.debug_utils
+                .destroy_debug_utils_messenger(self.messenger, None);
+        }
+    }
+}
+
+/// Labelled regions in command buffers, shown as a tree by RenderDoc, Nsight and friends
+/// regions nest and every begin needs a matching end in the same command buffer
+/// Example Use:
+/// ```ignore
+/// let labels = VKDebugLabels::new(&vk_instance.instance, &vk_device.device);
+/// unsafe {
+///     labels.begin(cmd_buffer, c"Shadows", LinearRgba::rgb(0.2, 0.2, 0.2));
+///     // record shadow pass
+///     labels.end(cmd_buffer);
+/// }
+/// ```
+pub struct VKDebugLabels {
+    pub debug_utils: debug_utils::Device,
+}
+
+impl VKDebugLabels {
+    /// VK_EXT_debug_utils must be enabled on instance
+    pub fn new(instance: &Instance, device: &ash::Device) -> Self {
+        Self {
+            debug_utils: debug_utils::Device::new(instance, device),
+        }
+    }
+
+    /// # Safety
+    /// cmd_buffer must be recording
+    pub unsafe fn begin(&self, cmd_buffer: vk::CommandBuffer, name: &CStr, color: LinearRgba) {
+        let label = vk::DebugUtilsLabelEXT::default()
+            .label_name(name)
+            .color(color.to_array());
+        unsafe {
+            self.debug_utils
+                .cmd_begin_debug_utils_label(cmd_buffer, &label)
+        };
+    }
+
+    /// # Safety
+    /// cmd_buffer must be recording inside a region started with begin
+    pub unsafe fn end(&self, cmd_buffer: vk::CommandBuffer) {
+        unsafe { self.debug_utils.cmd_end_debug_utils_label(cmd_buffer) };
+    }
+
+    /// Single marker rather than a region
+    /// # Safety
+    /// cmd_buffer must be recording
+    pub unsafe fn insert(&self, cmd_buffer: vk::CommandBuffer, name: &CStr, color: LinearRgba) {
+        let label = vk::DebugUtilsLabelEXT::default()
+            .label_name(name)
+            .color(color.to_array());
+        unsafe {
+            self.debug_utils
+                .cmd_insert_debug_utils_label(cmd_buffer, &label)
+        };
+    }
+}
+
+/// An instance layer the vulkan loader found
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LayerInfo {
+    pub name: CString,
+    pub description: String,
+    pub spec_version: u32,
+    pub implementation_version: u32,
+}
+
+/// Instance layers the vulkan loader can find, empty if enumeration fails
+/// Example Use:
+/// ```ignore
+/// for layer in available_instance_layers(&entry) {
+///     info!("{:?}: {}", layer.name, layer.description);
+/// }
+/// ```
+pub fn available_instance_layers(entry: &Entry) -> Vec<LayerInfo> {
+    unsafe { entry.enumerate_instance_layer_properties() }
+        .unwrap_or_default()
+        .iter()
+        .map(|layer| LayerInfo {
+            name: layer.layer_name_as_c_str().unwrap_or_default().to_owned(),
+            description: layer
+                .description_as_c_str()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            spec_version: layer.spec_version,
+            implementation_version: layer.implementation_version,
+        })
+        .collect()
+}
+
+/// Returns true if the vulkan loader can find the instance layer
+pub fn instance_layer_available(entry: &Entry, layer_name: &CStr) -> bool {
+    available_instance_layers(entry)
+        .iter()
+        .any(|layer| layer.name.as_c_str() == layer_name)
+}
+
+/// Returns true if the instance extension is supported by the loader or an implicit layer
+pub fn instance_extension_available(entry: &Entry, extension_name: &CStr) -> bool {
+    unsafe { entry.enumerate_instance_extension_properties(None) }
+        .unwrap_or_default()
+        .iter()
+        .any(|extension| extension.extension_name_as_c_str().unwrap_or_default() == extension_name)
+}
+
+// validation messages can be very chatty, info level messages are logged as debug
+unsafe extern "system" fn vulkan_debug_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT<'_>,
+    _user_data: *mut c_void,
+) -> vk::Bool32 {
+    let (message_id, message) = match unsafe { callback_data.as_ref() } {
+        Some(callback_data) => unsafe {
+            (
+                callback_data
+                    .message_id_name_as_c_str()
+                    .unwrap_or_default()
+                    .to_string_lossy(),
+                callback_data
+                    .message_as_c_str()
+                    .unwrap_or_default()
+                    .to_string_lossy(),
+            )
+        },
+        None => return vk::FALSE,
+    };
+
+    match message_severity {
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => {
+            error!("VK {message_type:?} [{message_id}]: {message}")
+        }
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => {
+            warn!("VK {message_type:?} [{message_id}]: {message}")
+        }
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => {
+            debug!("VK {message_type:?} [{message_id}]: {message}")
+        }
+        _ => trace!("VK {message_type:?} [{message_id}]: {message}"),
+    }
+
+    // returning true would abort the call that triggered the message
+    vk::FALSE
+}
@@ -0,0 +1,434 @@
+use ash::vk;
+use glam::{Mat4, Vec3, Vec4};
+use gpu_allocator::MemoryLocation;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::mem::offset_of;
+
+use crate::camera::{Camera, CameraUniform, DepthConvention};
+use crate::color::LinearRgba;
+use crate::renderer::buffer::VKBuffer;
+use crate::renderer::device::VKDevice;
+use crate::renderer::pipeline::{BlendMode, DepthState, GraphicsPipelineBuilder};
+use crate::renderer::presentation::VKSwapchain;
+use crate::renderer::push_constant_range;
+use crate::renderer::shader::{VKShader, VKShaderLoader, reload_shaders};
+use crate::renderer::vertex::{VertexAttribute, VertexLayout};
+
+// segments in each of a sphere's three circles
+const SPHERE_SEGMENTS: usize = 24;
+
+// lines each frame's vertex buffer holds before it has to grow
+const INITIAL_LINE_CAPACITY: usize = 1024;
+
+/// Vertex layout of debug_line.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DebugVertex {
+    pub position: Vec3,
+    pub color: Vec4,
+}
+
+impl VertexLayout for DebugVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute::new(
+            vk::Format::R32G32B32_SFLOAT,
+            offset_of!(DebugVertex, position),
+        ),
+        VertexAttribute::new(
+            vk::Format::R32G32B32A32_SFLOAT,
+            offset_of!(DebugVertex, color),
+        ),
+    ];
+}
+
+/// Immediate mode lines in world space, cleared after every frame is rendered
+/// Example Use:
+/// ```
+/// use glam::Vec3;
+/// use vulkan_engine::color::LinearRgba;
+/// use vulkan_engine::renderer::debug_draw::DebugDraw;
+///
+/// let mut debug_draw = DebugDraw::default();
+/// debug_draw
+///     .line(Vec3::ZERO, Vec3::X, LinearRgba::rgb(1.0, 0.0, 0.0))
+///     .aabb(Vec3::splat(-1.0), Vec3::ONE, LinearRgba::WHITE)
+///     .sphere(Vec3::Y, 0.5, LinearRgba::rgb(0.0, 1.0, 0.0));
+/// assert_eq!(debug_draw.line_count(), 1 + 12 + 72);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DebugDraw {
+    /// pairs of line ends
+    pub vertices: Vec<DebugVertex>,
+}
+
+impl DebugDraw {
+    pub fn line(&mut self, start: Vec3, end: Vec3, color: LinearRgba) -> &mut Self {
+        let color = color.to_vec4();
+        self.vertices.extend([
+            DebugVertex {
+                position: start,
+                color,
+            },
+            DebugVertex {
+                position: end,
+                color,
+            },
+        ]);
+        self
+    }
+
+    /// Axis aligned box from its corners
+    pub fn aabb(&mut self, min: Vec3, max: Vec3, color: LinearRgba) -> &mut Self {
+        let corners: [Vec3; 8] = std::array::from_fn(|corner| {
+            Vec3::new(
+                if corner & 1 == 0 { min.x } else { max.x },
+                if corner & 2 == 0 { min.y } else { max.y },
+                if corner & 4 == 0 { min.z } else { max.z },
+            )
+        });
+        self.box_edges(&corners, color)
+    }
+
+    /// Three circles around the axes
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: LinearRgba) -> &mut Self {
+        let point = |segment: usize| {
+            let angle = segment as f32 / SPHERE_SEGMENTS as f32 * std::f32::consts::TAU;
+            (angle.cos() * radius, angle.sin() * radius)
+        };
+        for segment in 0..SPHERE_SEGMENTS {
+            let (a_cos, a_sin) = point(segment);
+            let (b_cos, b_sin) = point(segment + 1);
+            self.line(
+                center + Vec3::new(a_cos, a_sin, 0.0),
+                center + Vec3::new(b_cos, b_sin, 0.0),
+                color,
+            );
+            self.line(
+                center + Vec3::new(a_cos, 0.0, a_sin),
+                center + Vec3::new(b_cos, 0.0, b_sin),
+                color,
+            );
+            self.line(
+                center + Vec3::new(0.0, a_cos, a_sin),
+                center + Vec3::new(0.0, b_cos, b_sin),
+                color,
+            );
+        }
+        self
+    }
+
+    /// What camera sees out to far, the projection's far plane is infinite so it has to be given
+    pub fn frustum(
+        &mut self,
+        camera: &Camera,
+        aspect_ratio: f32,
+        far: f32,
+        color: LinearRgba,
+    ) -> &mut Self {
+        let inverse_projection = camera.projection.matrix(aspect_ratio).inverse();
+        let camera_to_world = Mat4::from_rotation_translation(camera.rotation, camera.position);
+
+        // reverse z puts the near plane at depth 1, half depth gives the direction out from it
+        let corners: [Vec3; 8] = std::array::from_fn(|corner| {
+            let x = if corner & 1 == 0 { -1.0 } else { 1.0 };
+            let y = if corner & 2 == 0 { -1.0 } else { 1.0 };
+            let near = inverse_projection.project_point3(Vec3::new(x, y, 1.0));
+            if corner & 4 == 0 {
+                return camera_to_world.transform_point3(near);
+            }
+            let along = inverse_projection.project_point3(Vec3::new(x, y, 0.5)) - near;
+            let to_far = (-far - near.z) / along.z;
+            camera_to_world.transform_point3(near + along * to_far)
+        });
+        self.box_edges(&corners, color)
+    }
+
+    // corners indexed by x in bit 0, y in bit 1 and z in bit 2
+    fn box_edges(&mut self, corners: &[Vec3; 8], color: LinearRgba) -> &mut Self {
+        for corner in 0..8 {
+            for axis in [1, 2, 4] {
+                if corner & axis == 0 {
+                    self.line(corners[corner], corners[corner | axis], color);
+                }
+            }
+        }
+        self
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.vertices.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+}
+
+// pushed before drawing lines, matches DebugDrawConstants in debug_line.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct DebugDrawConstants {
+    view_projection: Mat4,
+}
+
+/// Draws VKRenderer::debug_draw with a line list, depth tested against the scene but never written
+pub struct VKDebugDraw<'a> {
+    pub vertex_shader: VKShader<'a>,
+    pub fragment_shader: VKShader<'a>,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    /// host visible and mapped, rewritten each frame once its fence has signalled
+    pub vertex_buffers: Vec<VKBuffer>,
+    /// vertices each frame's buffer holds
+    pub vertex_capacities: Vec<usize>,
+    /// vertices written by the last prepare
+    pub vertex_count: u32,
+    // the pipeline is built for it
+    depth_convention: DepthConvention,
+}
+
+impl VKDebugDraw<'_> {
+    pub fn new(
+        vk_device: &mut VKDevice,
+        vk_swapchain: &VKSwapchain,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        frames_in_flight: u32,
+        depth_convention: DepthConvention,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let vertex_shader = VKShader::new(
+            vk_device,
+            "shaders/debug_line.spv",
+            vk::ShaderStageFlags::VERTEX,
+            c"vertexMain",
+            vk_shader_loader,
+        )?;
+
+        let fragment_shader = VKShader::new(
+            vk_device,
+            "shaders/debug_line.spv",
+            vk::ShaderStageFlags::FRAGMENT,
+            c"fragMain",
+            vk_shader_loader,
+        )?;
+
+        let push_constant_ranges = [push_constant_range::<DebugDrawConstants>(
+            vk::ShaderStageFlags::VERTEX,
+            0,
+        )];
+        let pipeline_layout = unsafe {
+            vk_device.device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .push_constant_ranges(&push_constant_ranges),
+                None,
+            )?
+        };
+
+        let stages = [vertex_shader.shader_info, fragment_shader.shader_info];
+        let pipeline = create_line_pipeline(
+            vk_device,
+            vk_swapchain,
+            &stages,
+            pipeline_layout,
+            depth_convention,
+        )?;
+
+        let mut vertex_buffers = Vec::with_capacity(frames_in_flight as usize);
+        let capacity = INITIAL_LINE_CAPACITY * 2;
+        for _ in 0..frames_in_flight {
+            vertex_buffers.push(create_line_buffer(vk_device, capacity)?);
+        }
+
+        Ok(Self {
+            vertex_shader,
+            fragment_shader,
+            pipeline_layout,
+            pipeline,
+            vertex_buffers,
+            vertex_capacities: vec![capacity; frames_in_flight as usize],
+            vertex_count: 0,
+            depth_convention,
+        })
+    }
+
+    /// Copies debug_draw into frame_in_flight's vertex buffer, growing it when the lines don't fit
+    /// # Safety
+    /// The gpu must be done with frame_in_flight
+    pub unsafe fn prepare(
+        &mut self,
+        vk_device: &mut VKDevice,
+        frame_in_flight: usize,
+        debug_draw: &DebugDraw,
+    ) -> Result<(), vk::Result> {
+        self.vertex_count = 0;
+
+        let needed = debug_draw.vertices.len();
+        if needed > self.vertex_capacities[frame_in_flight] {
+            let capacity = needed.next_power_of_two();
+            let buffer = create_line_buffer(vk_device, capacity)?;
+            let mut old_buffer =
+                std::mem::replace(&mut self.vertex_buffers[frame_in_flight], buffer);
+            unsafe { old_buffer.destroy(vk_device) };
+            self.vertex_capacities[frame_in_flight] = capacity;
+        }
+
+        self.vertex_buffers[frame_in_flight].write(0, &debug_draw.vertices)?;
+        self.vertex_count = needed as u32;
+        Ok(())
+    }
+
+    /// Draws the prepared lines into the current rendering
+    /// # Safety
+    /// cmd_buffer must be inside rendering to the scene formats, after prepare for frame_in_flight
+    pub unsafe fn record(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        frame_in_flight: usize,
+        camera: &CameraUniform,
+    ) {
+        if self.vertex_count == 0 {
+            return;
+        }
+
+        unsafe {
+            vk_device.device.cmd_bind_pipeline(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            vk_device.cmd_push_constants(
+                cmd_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                &DebugDrawConstants {
+                    view_projection: camera.view_projection,
+                },
+            );
+            vk_device.device.cmd_bind_vertex_buffers(
+                cmd_buffer,
+                0,
+                &[self.vertex_buffers[frame_in_flight].buffer],
+                &[0u64],
+            );
+            vk_device
+                .device
+                .cmd_draw(cmd_buffer, self.vertex_count, 1, 0, 0);
+        }
+    }
+
+    /// Rebuilds the pipeline when one of its shaders is in changed, see VKShaderLoader::changed_shaders
+    /// # Safety
+    /// The gpu must not be using the debug lines
+    pub unsafe fn reload_shaders(
+        &mut self,
+        vk_device: &VKDevice,
+        vk_swapchain: &VKSwapchain,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        changed: &[&str],
+    ) -> Result<(), Box<dyn error::Error>> {
+        let shaders = &mut [&mut self.vertex_shader, &mut self.fragment_shader];
+        if !unsafe { reload_shaders(vk_device, vk_shader_loader, shaders, changed)? } {
+            return Ok(());
+        }
+        unsafe { self.rebuild_pipeline(vk_device, vk_swapchain)? };
+        Ok(())
+    }
+
+    /// Rebuilds the pipeline for vk_swapchain's current scene format
+    /// # Safety
+    /// The gpu must not be using the debug lines
+    pub unsafe fn rebuild_pipeline(
+        &mut self,
+        vk_device: &VKDevice,
+        vk_swapchain: &VKSwapchain,
+    ) -> Result<(), vk::Result> {
+        let stages = [
+            self.vertex_shader.shader_info,
+            self.fragment_shader.shader_info,
+        ];
+        let pipeline = create_line_pipeline(
+            vk_device,
+            vk_swapchain,
+            &stages,
+            self.pipeline_layout,
+            self.depth_convention,
+        )?;
+        unsafe { vk_device.device.destroy_pipeline(self.pipeline, None) };
+        self.pipeline = pipeline;
+        Ok(())
+    }
+
+    /// # Safety
+    /// The gpu must not be using the debug lines
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            for mut buffer in self.vertex_buffers.drain(..) {
+                buffer.destroy(vk_device);
+            }
+            vk_device.device.destroy_pipeline(self.pipeline, None);
+            vk_device
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            self.fragment_shader.destroy(vk_device);
+            self.vertex_shader.destroy(vk_device);
+        }
+    }
+}
+
+fn create_line_buffer(vk_device: &mut VKDevice, vertices: usize) -> Result<VKBuffer, vk::Result> {
+    let buffer = VKBuffer::new(
+        vk_device,
+        (vertices * size_of::<DebugVertex>()) as u64,
+        vk::BufferUsageFlags::VERTEX_BUFFER,
+        MemoryLocation::CpuToGpu,
+        "Debug Lines",
+    )?;
+    if !buffer.is_mapped() {
+        warn!("Debug Line Buffer Not Mapped");
+    }
+    Ok(buffer)
+}
+
+// line list alpha blended over the scene, hidden behind geometry but never hiding anything itself
+fn create_line_pipeline(
+    vk_device: &VKDevice,
+    vk_swapchain: &VKSwapchain,
+    stages: &[vk::PipelineShaderStageCreateInfo],
+    pipeline_layout: vk::PipelineLayout,
+    depth_convention: DepthConvention,
+) -> Result<vk::Pipeline, vk::Result> {
+    GraphicsPipelineBuilder::new(stages, pipeline_layout)
+        .swapchain(vk_swapchain)
+        .vertex_layout::<DebugVertex>()
+        .topology(vk::PrimitiveTopology::LINE_LIST)
+        .depth(DepthState::read_only(depth_convention))
+        .blend(BlendMode::Alpha)
+        .build(vk_device)
+}
+
+#[test]
+fn debug_draw_frustum_test() {
+    let camera = Camera::perspective(90.0_f32.to_radians(), 0.5).look_at(
+        Vec3::new(0.0, 0.0, 10.0),
+        Vec3::ZERO,
+        Vec3::Y,
+    );
+    let mut debug_draw = DebugDraw::default();
+    debug_draw.frustum(&camera, 1.0, 4.0, LinearRgba::WHITE);
+    assert_eq!(debug_draw.line_count(), 12);
+
+    // near plane 0.5 in front of the camera, far plane 4 in front, 90 degrees wide
+    let (min, max) = debug_draw.vertices.iter().fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), vertex| (min.min(vertex.position), max.max(vertex.position)),
+    );
+    assert!(min.abs_diff_eq(Vec3::new(-4.0, -4.0, 6.0), 1e-3));
+    assert!(max.abs_diff_eq(Vec3::new(4.0, 4.0, 9.5), 1e-3));
+}
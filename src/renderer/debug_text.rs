@@ -0,0 +1,290 @@
+use glam::Vec2;
+
+use crate::color::LinearRgba;
+use crate::renderer::renderer2d::{Sprite, SpriteTextureId, TextureAtlas, WHITE_TEXTURE};
+
+/// Size of a glyph of the debug font in texels, every glyph is as wide as the others
+pub const DEBUG_GLYPH_WIDTH: u32 = 8;
+pub const DEBUG_GLYPH_HEIGHT: u32 = 16;
+
+// glyphs per row of the atlas
+const ATLAS_COLUMNS: u32 = 16;
+// the font covers printable ascii, from ' ' to '~'
+const FIRST_GLYPH: u8 = b' ';
+const LAST_GLYPH: u8 = b'~';
+// drawn in place of characters the font doesn't have
+const MISSING_GLYPH: u8 = b'?';
+const TAB_WIDTH: usize = 4;
+
+// DejaVu Sans Mono rasterized at 13px onto the glyph grid, one byte per row from the top with the
+// leftmost texel in the high bit
+#[rustfmt::skip]
+const GLYPHS: [[u8; DEBUG_GLYPH_HEIGHT as usize]; (LAST_GLYPH - FIRST_GLYPH + 1) as usize] = [
+[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00, 0x00], // '!'
+    [0x00, 0x00, 0x00, 0x00, 0x3c, 0x3c, 0x3c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x00, 0x00, 0x00, 0x00, 0x16, 0x14, 0x7e, 0x34, 0x3c, 0x7e, 0x28, 0x68, 0x00, 0x00, 0x00, 0x00], // '#'
+    [0x00, 0x00, 0x00, 0x00, 0x08, 0x3c, 0x28, 0x38, 0x1c, 0x0e, 0x0e, 0x3c, 0x08, 0x00, 0x00, 0x00], // '$'
+    [0x00, 0x00, 0x00, 0x00, 0x70, 0x50, 0x50, 0x3e, 0x3c, 0x0e, 0x0a, 0x0e, 0x00, 0x00, 0x00, 0x00], // '%'
+    [0x00, 0x00, 0x00, 0x00, 0x3c, 0x20, 0x20, 0x30, 0x5a, 0x4e, 0x46, 0x3e, 0x00, 0x00, 0x00, 0x00], // '&'
+    [0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '\''
+    [0x00, 0x00, 0x00, 0x00, 0x08, 0x18, 0x18, 0x10, 0x10, 0x10, 0x18, 0x18, 0x08, 0x00, 0x00, 0x00], // '('
+    [0x00, 0x00, 0x00, 0x00, 0x10, 0x18, 0x18, 0x08, 0x08, 0x08, 0x18, 0x18, 0x10, 0x00, 0x00, 0x00], // ')'
+    [0x00, 0x00, 0x00, 0x00, 0x18, 0x3c, 0x18, 0x3c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '*'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x7e, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x10, 0x10, 0x00, 0x00], // ','
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // '.'
+    [0x00, 0x00, 0x00, 0x00, 0x04, 0x04, 0x08, 0x08, 0x18, 0x10, 0x30, 0x20, 0x60, 0x00, 0x00, 0x00], // '/'
+    [0x00, 0x00, 0x00, 0x00, 0x3c, 0x24, 0x66, 0x7e, 0x7e, 0x66, 0x24, 0x3c, 0x00, 0x00, 0x00, 0x00], // '0'
+    [0x00, 0x00, 0x00, 0x00, 0x38, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x3e, 0x00, 0x00, 0x00, 0x00], // '1'
+    [0x00, 0x00, 0x00, 0x00, 0x7c, 0x04, 0x04, 0x0c, 0x08, 0x10, 0x20, 0x7e, 0x00, 0x00, 0x00, 0x00], // '2'
+    [0x00, 0x00, 0x00, 0x00, 0x7c, 0x04, 0x04, 0x1c, 0x0c, 0x06, 0x06, 0x7c, 0x00, 0x00, 0x00, 0x00], // '3'
+    [0x00, 0x00, 0x00, 0x00, 0x0c, 0x1c, 0x1c, 0x2c, 0x6c, 0x7e, 0x0c, 0x0c, 0x00, 0x00, 0x00, 0x00], // '4'
+    [0x00, 0x00, 0x00, 0x00, 0x3c, 0x20, 0x30, 0x3c, 0x06, 0x06, 0x04, 0x7c, 0x00, 0x00, 0x00, 0x00], // '5'
+    [0x00, 0x00, 0x00, 0x00, 0x3c, 0x20, 0x68, 0x7c, 0x66, 0x66, 0x26, 0x3c, 0x00, 0x00, 0x00, 0x00], // '6'
+    [0x00, 0x00, 0x00, 0x00, 0x7e, 0x04, 0x0c, 0x0c, 0x08, 0x18, 0x10, 0x30, 0x00, 0x00, 0x00, 0x00], // '7'
+    [0x00, 0x00, 0x00, 0x00, 0x3c, 0x66, 0x24, 0x3c, 0x3c, 0x66, 0x66, 0x3c, 0x00, 0x00, 0x00, 0x00], // '8'
+    [0x00, 0x00, 0x00, 0x00, 0x3c, 0x64, 0x66, 0x66, 0x3e, 0x06, 0x04, 0x3c, 0x00, 0x00, 0x00, 0x00], // '9'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // ':'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x18, 0x18, 0x10, 0x10, 0x00, 0x00], // ';'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x38, 0x60, 0x38, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00], // '<'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7e, 0x00, 0x7e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '='
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x60, 0x1c, 0x06, 0x1c, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00], // '>'
+    [0x00, 0x00, 0x00, 0x00, 0x3c, 0x04, 0x04, 0x08, 0x18, 0x10, 0x00, 0x18, 0x00, 0x00, 0x00, 0x00], // '?'
+    [0x00, 0x00, 0x00, 0x00, 0x1c, 0x36, 0x42, 0x5e, 0x52, 0x52, 0x5e, 0x40, 0x30, 0x1c, 0x00, 0x00], // '@'
+    [0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x3c, 0x24, 0x24, 0x7e, 0x66, 0x42, 0x00, 0x00, 0x00, 0x00], // 'A'
+    [0x00, 0x00, 0x00, 0x00, 0x7c, 0x66, 0x66, 0x7c, 0x66, 0x66, 0x66, 0x7c, 0x00, 0x00, 0x00, 0x00], // 'B'
+    [0x00, 0x00, 0x00, 0x00, 0x3e, 0x20, 0x60, 0x60, 0x60, 0x60, 0x20, 0x1e, 0x00, 0x00, 0x00, 0x00], // 'C'
+    [0x00, 0x00, 0x00, 0x00, 0x7c, 0x64, 0x66, 0x66, 0x66, 0x66, 0x64, 0x78, 0x00, 0x00, 0x00, 0x00], // 'D'
+    [0x00, 0x00, 0x00, 0x00, 0x3e, 0x20, 0x20, 0x3e, 0x20, 0x20, 0x20, 0x3e, 0x00, 0x00, 0x00, 0x00], // 'E'
+    [0x00, 0x00, 0x00, 0x00, 0x3e, 0x20, 0x20, 0x3c, 0x20, 0x20, 0x20, 0x20, 0x00, 0x00, 0x00, 0x00], // 'F'
+    [0x00, 0x00, 0x00, 0x00, 0x3e, 0x20, 0x60, 0x60, 0x6e, 0x66, 0x26, 0x3e, 0x00, 0x00, 0x00, 0x00], // 'G'
+    [0x00, 0x00, 0x00, 0x00, 0x66, 0x66, 0x66, 0x7e, 0x66, 0x66, 0x66, 0x66, 0x00, 0x00, 0x00, 0x00], // 'H'
+    [0x00, 0x00, 0x00, 0x00, 0x3c, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3c, 0x00, 0x00, 0x00, 0x00], // 'I'
+    [0x00, 0x00, 0x00, 0x00, 0x1c, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0c, 0x78, 0x00, 0x00, 0x00, 0x00], // 'J'
+    [0x00, 0x00, 0x00, 0x00, 0x66, 0x6c, 0x78, 0x70, 0x78, 0x6c, 0x66, 0x66, 0x00, 0x00, 0x00, 0x00], // 'K'
+    [0x00, 0x00, 0x00, 0x00, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x3e, 0x00, 0x00, 0x00, 0x00], // 'L'
+    [0x00, 0x00, 0x00, 0x00, 0x66, 0x66, 0x7e, 0x5a, 0x5a, 0x42, 0x42, 0x42, 0x00, 0x00, 0x00, 0x00], // 'M'
+    [0x00, 0x00, 0x00, 0x00, 0x66, 0x76, 0x76, 0x76, 0x6e, 0x6e, 0x6e, 0x66, 0x00, 0x00, 0x00, 0x00], // 'N'
+    [0x00, 0x00, 0x00, 0x00, 0x3c, 0x66, 0x66, 0x66, 0x66, 0x66, 0x24, 0x3c, 0x00, 0x00, 0x00, 0x00], // 'O'
+    [0x00, 0x00, 0x00, 0x00, 0x3c, 0x26, 0x26, 0x2e, 0x3c, 0x20, 0x20, 0x20, 0x00, 0x00, 0x00, 0x00], // 'P'
+    [0x00, 0x00, 0x00, 0x00, 0x3c, 0x66, 0x66, 0x66, 0x66, 0x66, 0x26, 0x3c, 0x0c, 0x00, 0x00, 0x00], // 'Q'
+    [0x00, 0x00, 0x00, 0x00, 0x7c, 0x66, 0x66, 0x7c, 0x7c, 0x64, 0x66, 0x62, 0x00, 0x00, 0x00, 0x00], // 'R'
+    [0x00, 0x00, 0x00, 0x00, 0x3c, 0x60, 0x60, 0x38, 0x0c, 0x06, 0x06, 0x7c, 0x00, 0x00, 0x00, 0x00], // 'S'
+    [0x00, 0x00, 0x00, 0x00, 0x7e, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // 'T'
+    [0x00, 0x00, 0x00, 0x00, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x3c, 0x00, 0x00, 0x00, 0x00], // 'U'
+    [0x00, 0x00, 0x00, 0x00, 0x42, 0x66, 0x24, 0x24, 0x3c, 0x3c, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // 'V'
+    [0x00, 0x00, 0x00, 0x00, 0x42, 0x42, 0x5a, 0x5a, 0x7e, 0x7e, 0x66, 0x24, 0x00, 0x00, 0x00, 0x00], // 'W'
+    [0x00, 0x00, 0x00, 0x00, 0x66, 0x24, 0x1c, 0x18, 0x18, 0x3c, 0x66, 0x42, 0x00, 0x00, 0x00, 0x00], // 'X'
+    [0x00, 0x00, 0x00, 0x00, 0x66, 0x24, 0x3c, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // 'Y'
+    [0x00, 0x00, 0x00, 0x00, 0x7e, 0x06, 0x0c, 0x08, 0x18, 0x30, 0x20, 0x7e, 0x00, 0x00, 0x00, 0x00], // 'Z'
+    [0x00, 0x00, 0x00, 0x18, 0x18, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x18, 0x18, 0x00, 0x00], // '['
+    [0x00, 0x00, 0x00, 0x00, 0x60, 0x20, 0x30, 0x10, 0x18, 0x08, 0x0c, 0x04, 0x04, 0x00, 0x00, 0x00], // '\\'
+    [0x00, 0x00, 0x00, 0x18, 0x18, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x18, 0x18, 0x00, 0x00], // ']'
+    [0x00, 0x00, 0x00, 0x00, 0x18, 0x3c, 0x66, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7e, 0x00], // '_'
+    [0x00, 0x00, 0x00, 0x30, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3c, 0x06, 0x3e, 0x66, 0x66, 0x3e, 0x00, 0x00, 0x00, 0x00], // 'a'
+    [0x00, 0x00, 0x00, 0x20, 0x60, 0x60, 0x7c, 0x66, 0x66, 0x66, 0x66, 0x7c, 0x00, 0x00, 0x00, 0x00], // 'b'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3e, 0x20, 0x20, 0x20, 0x20, 0x1e, 0x00, 0x00, 0x00, 0x00], // 'c'
+    [0x00, 0x00, 0x00, 0x04, 0x06, 0x06, 0x3e, 0x66, 0x66, 0x66, 0x66, 0x3e, 0x00, 0x00, 0x00, 0x00], // 'd'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3c, 0x66, 0x7e, 0x60, 0x60, 0x3e, 0x00, 0x00, 0x00, 0x00], // 'e'
+    [0x00, 0x00, 0x00, 0x0c, 0x1c, 0x18, 0x3c, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // 'f'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3e, 0x66, 0x66, 0x66, 0x66, 0x3e, 0x04, 0x3c, 0x10, 0x00], // 'g'
+    [0x00, 0x00, 0x00, 0x20, 0x20, 0x20, 0x3c, 0x24, 0x26, 0x26, 0x26, 0x26, 0x00, 0x00, 0x00, 0x00], // 'h'
+    [0x00, 0x00, 0x00, 0x00, 0x18, 0x00, 0x38, 0x18, 0x18, 0x18, 0x18, 0x7e, 0x00, 0x00, 0x00, 0x00], // 'i'
+    [0x00, 0x00, 0x00, 0x08, 0x08, 0x00, 0x38, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x38, 0x20, 0x00], // 'j'
+    [0x00, 0x00, 0x00, 0x20, 0x20, 0x20, 0x24, 0x28, 0x38, 0x2c, 0x24, 0x26, 0x00, 0x00, 0x00, 0x00], // 'k'
+    [0x00, 0x00, 0x00, 0x30, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x18, 0x0c, 0x00, 0x00, 0x00, 0x00], // 'l'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7e, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x00, 0x00, 0x00, 0x00], // 'm'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3c, 0x24, 0x26, 0x26, 0x26, 0x26, 0x00, 0x00, 0x00, 0x00], // 'n'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3c, 0x66, 0x66, 0x66, 0x66, 0x3c, 0x00, 0x00, 0x00, 0x00], // 'o'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7c, 0x66, 0x66, 0x66, 0x66, 0x7c, 0x60, 0x60, 0x00, 0x00], // 'p'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3e, 0x66, 0x66, 0x66, 0x66, 0x3e, 0x06, 0x06, 0x00, 0x00], // 'q'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3e, 0x30, 0x30, 0x30, 0x30, 0x30, 0x00, 0x00, 0x00, 0x00], // 'r'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3c, 0x20, 0x38, 0x0c, 0x04, 0x3c, 0x00, 0x00, 0x00, 0x00], // 's'
+    [0x00, 0x00, 0x00, 0x00, 0x10, 0x10, 0x7c, 0x10, 0x10, 0x10, 0x10, 0x1c, 0x00, 0x00, 0x00, 0x00], // 't'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x26, 0x26, 0x26, 0x26, 0x26, 0x3e, 0x00, 0x00, 0x00, 0x00], // 'u'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x24, 0x24, 0x3c, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // 'v'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x42, 0x42, 0x5a, 0x7e, 0x3c, 0x24, 0x00, 0x00, 0x00, 0x00], // 'w'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x24, 0x3c, 0x18, 0x18, 0x3c, 0x66, 0x00, 0x00, 0x00, 0x00], // 'x'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x26, 0x24, 0x3c, 0x18, 0x18, 0x18, 0x30, 0x00, 0x00], // 'y'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3c, 0x0c, 0x08, 0x10, 0x30, 0x3c, 0x00, 0x00, 0x00, 0x00], // 'z'
+    [0x00, 0x00, 0x00, 0x04, 0x0c, 0x18, 0x18, 0x18, 0x30, 0x18, 0x18, 0x18, 0x18, 0x0c, 0x00, 0x00], // '{'
+    [0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00], // '|'
+    [0x00, 0x00, 0x00, 0x20, 0x30, 0x18, 0x18, 0x18, 0x0c, 0x18, 0x18, 0x18, 0x18, 0x30, 0x00, 0x00], // '}'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
+
+/// Small monospace ascii font drawn through renderer2d, for debug overlays that can't rely on
+/// the game loading a font
+/// Example Use:
+/// ```ignore
+/// let (width, height, pixels) = DebugFont::atlas_pixels();
+/// let atlas = VKTexture::from_rgba8(vk_device, vk_command_pool, width, height, &pixels)?;
+/// let font = DebugFont::new(renderer.renderer2d.add_texture(vk_device, atlas)?);
+/// renderer.sprites.extend(font.layout("hello", Vec2::new(8.0, 8.0), 1.0, LinearRgba::WHITE, 0));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugFont {
+    pub texture: SpriteTextureId,
+    pub atlas: TextureAtlas,
+}
+
+impl DebugFont {
+    /// texture is the atlas_pixels uploaded for renderer2d
+    pub fn new(texture: SpriteTextureId) -> Self {
+        let (width, height, _) = Self::atlas_size();
+        Self {
+            texture,
+            atlas: TextureAtlas::from_grid(width, height, DEBUG_GLYPH_WIDTH, DEBUG_GLYPH_HEIGHT),
+        }
+    }
+
+    fn atlas_size() -> (u32, u32, usize) {
+        let rows = (GLYPHS.len() as u32).div_ceil(ATLAS_COLUMNS);
+        let width = ATLAS_COLUMNS * DEBUG_GLYPH_WIDTH;
+        let height = rows * DEBUG_GLYPH_HEIGHT;
+        (width, height, (width * height) as usize * 4)
+    }
+
+    /// Every glyph in rows of ATLAS_COLUMNS as 8bit RGBA, white with the glyph in alpha
+    pub fn atlas_pixels() -> (u32, u32, Vec<u8>) {
+        let (width, height, size) = Self::atlas_size();
+        let mut pixels = vec![0; size];
+        for (index, glyph) in GLYPHS.iter().enumerate() {
+            let left = index as u32 % ATLAS_COLUMNS * DEBUG_GLYPH_WIDTH;
+            let top = index as u32 / ATLAS_COLUMNS * DEBUG_GLYPH_HEIGHT;
+            for (y, row) in glyph.iter().enumerate() {
+                for x in 0..DEBUG_GLYPH_WIDTH {
+                    let covered = row & (0x80 >> x) != 0;
+                    let texel = ((top + y as u32) * width + left + x) as usize * 4;
+                    pixels[texel..texel + 4].copy_from_slice(&[255, 255, 255, covered as u8 * 255]);
+                }
+            }
+        }
+        (width, height, pixels)
+    }
+
+    /// Sprites drawing text with its top left at position, scale is the size of a texel
+    /// '\n' starts a new line, tabs are spaced to every TAB_WIDTH columns and anything outside
+    /// printable ascii is drawn as '?'
+    pub fn layout(
+        &self,
+        text: &str,
+        position: Vec2,
+        scale: f32,
+        color: LinearRgba,
+        layer: i32,
+    ) -> Vec<Sprite> {
+        let glyph_size = Vec2::new(DEBUG_GLYPH_WIDTH as f32, DEBUG_GLYPH_HEIGHT as f32) * scale;
+        let mut sprites = Vec::with_capacity(text.len());
+        for (line, characters) in text.lines().enumerate() {
+            let mut column = 0;
+            for character in characters.chars() {
+                if character == '\t' {
+                    column += TAB_WIDTH - column % TAB_WIDTH;
+                    continue;
+                }
+                let glyph = match u8::try_from(character) {
+                    Ok(glyph @ FIRST_GLYPH..=LAST_GLYPH) => glyph,
+                    _ => MISSING_GLYPH,
+                };
+                if glyph != b' '
+                    && let Some(uv) = self.atlas.region((glyph - FIRST_GLYPH) as usize)
+                {
+                    let offset = Vec2::new(column as f32, line as f32) * glyph_size;
+                    sprites.push(
+                        Sprite::new(self.texture, position + offset, glyph_size)
+                            .with_uv(uv)
+                            .with_color(color)
+                            .with_layer(layer),
+                    );
+                }
+                column += 1;
+            }
+        }
+        sprites
+    }
+
+    /// Size text takes up when laid out at scale, as wide as its longest line
+    pub fn measure(text: &str, scale: f32) -> Vec2 {
+        let columns = text
+            .lines()
+            .map(|line| {
+                line.chars().fold(0, |column, character| match character {
+                    '\t' => column + TAB_WIDTH - column % TAB_WIDTH,
+                    _ => column + 1,
+                })
+            })
+            .max()
+            .unwrap_or(0);
+        Vec2::new(
+            (columns as u32 * DEBUG_GLYPH_WIDTH) as f32,
+            (text.lines().count() as u32 * DEBUG_GLYPH_HEIGHT) as f32,
+        ) * scale
+    }
+
+    /// A solid box behind text laid out with layout, padding texels wider on every side
+    pub fn backdrop(
+        text: &str,
+        position: Vec2,
+        scale: f32,
+        padding: f32,
+        color: LinearRgba,
+        layer: i32,
+    ) -> Sprite {
+        let padding = Vec2::splat(padding * scale);
+        Sprite::new(
+            WHITE_TEXTURE,
+            position - padding,
+            Self::measure(text, scale) + padding * 2.0,
+        )
+        .with_color(color)
+        .with_layer(layer)
+    }
+}
+
+#[test]
+fn debug_font_test() {
+    let (width, height, pixels) = DebugFont::atlas_pixels();
+    assert_eq!((width, height), (128, 96));
+    assert_eq!(pixels.len(), 128 * 96 * 4);
+    // the space is empty, the top left of '!' is its first texel with a glyph
+    assert!(
+        pixels[..DEBUG_GLYPH_WIDTH as usize * 4]
+            .chunks(4)
+            .all(|texel| texel[3] == 0)
+    );
+
+    let font = DebugFont::new(1);
+    let sprites = font.layout(
+        "ab c\n\té",
+        Vec2::new(10.0, 20.0),
+        2.0,
+        LinearRgba::WHITE,
+        3,
+    );
+    // spaces and tabs draw nothing, é falls back to '?'
+    assert_eq!(sprites.len(), 4);
+    assert_eq!(sprites[2].position, Vec2::new(10.0 + 3.0 * 16.0, 20.0));
+    assert_eq!(
+        sprites[3].position,
+        Vec2::new(10.0 + 4.0 * 16.0, 20.0 + 32.0)
+    );
+    assert_eq!(
+        sprites[3].uv,
+        font.atlas.region((b'?' - FIRST_GLYPH) as usize).unwrap()
+    );
+    assert!(
+        sprites
+            .iter()
+            .all(|sprite| sprite.layer == 3 && sprite.size == Vec2::new(16.0, 32.0))
+    );
+
+    assert_eq!(
+        DebugFont::measure("ab c\n\té", 2.0),
+        Vec2::new(5.0 * 16.0, 2.0 * 32.0)
+    );
+}
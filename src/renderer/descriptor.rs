@@ -0,0 +1,327 @@
+use ash::vk;
+use log::warn;
+use std::collections::HashMap;
+
+use crate::renderer::device::VKDevice;
+
+// sets the first pool holds, each pool after that holds twice as many up to MAX_SETS_PER_POOL
+const FIRST_POOL_SETS: u32 = 32;
+const MAX_SETS_PER_POOL: u32 = 4096;
+
+/// Descriptors of each type a pool reserves per set, enough for the renderer's own layouts
+/// a set needing more than this of a type still fits as long as the others use less
+pub const DEFAULT_POOL_RATIOS: [vk::DescriptorPoolSize; 6] = [
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        descriptor_count: 4,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::UNIFORM_BUFFER,
+        descriptor_count: 4,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+        descriptor_count: 1,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::SAMPLED_IMAGE,
+        descriptor_count: 2,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::STORAGE_BUFFER,
+        descriptor_count: 4,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::STORAGE_IMAGE,
+        descriptor_count: 1,
+    },
+];
+
+// what makes two layouts the same, immutable samplers aren't supported
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct LayoutKey(Vec<(u32, vk::DescriptorType, u32, vk::ShaderStageFlags)>);
+
+impl LayoutKey {
+    fn new(bindings: &[vk::DescriptorSetLayoutBinding]) -> Self {
+        let mut key: Vec<_> = bindings
+            .iter()
+            .map(|binding| {
+                (
+                    binding.binding,
+                    binding.descriptor_type,
+                    binding.descriptor_count,
+                    binding.stage_flags,
+                )
+            })
+            .collect();
+        // the same bindings listed in another order are the same layout
+        key.sort_by_key(|&(binding, ..)| binding);
+        Self(key)
+    }
+}
+
+// freed sets wait out the frames that might still bind them before being handed out again
+#[derive(Debug, Default)]
+struct SetRecycler {
+    free: HashMap<vk::DescriptorSetLayout, Vec<vk::DescriptorSet>>,
+    /// sets freed while each frame in flight was the current one
+    retired: Vec<Vec<vk::DescriptorSet>>,
+    layouts: HashMap<vk::DescriptorSet, vk::DescriptorSetLayout>,
+    frame_in_flight: usize,
+}
+
+impl SetRecycler {
+    fn new(frames_in_flight: usize) -> Self {
+        Self {
+            retired: vec![Vec::new(); frames_in_flight.max(1)],
+            ..Default::default()
+        }
+    }
+
+    fn take(&mut self, layout: vk::DescriptorSetLayout) -> Option<vk::DescriptorSet> {
+        self.free.get_mut(&layout)?.pop()
+    }
+
+    fn allocated(&mut self, set: vk::DescriptorSet, layout: vk::DescriptorSetLayout) {
+        self.layouts.insert(set, layout);
+    }
+
+    // false for sets that didn't come from the allocator
+    fn retire(&mut self, set: vk::DescriptorSet) -> bool {
+        if !self.layouts.contains_key(&set) {
+            return false;
+        }
+        self.retired[self.frame_in_flight].push(set);
+        true
+    }
+
+    fn begin_frame(&mut self, frame_in_flight: usize) {
+        self.frame_in_flight = frame_in_flight % self.retired.len();
+        for set in self.retired[self.frame_in_flight].drain(..) {
+            let layout = self.layouts[&set];
+            self.free.entry(layout).or_default().push(set);
+        }
+    }
+
+    fn free_count(&self) -> usize {
+        self.free.values().map(Vec::len).sum()
+    }
+
+    fn clear(&mut self) {
+        self.free.clear();
+        self.retired.iter_mut().for_each(Vec::clear);
+        self.layouts.clear();
+    }
+}
+
+/// Hands out descriptor sets from pools it grows as they fill, so nothing has to size pools itself
+/// layouts are cached by their bindings and freed sets are recycled once no frame in flight can
+/// still be using them
+/// Example Use:
+/// ```ignore
+/// let layout = allocator.layout(vk_device, &bindings)?;
+/// let set = allocator.allocate(vk_device, layout)?;
+/// // write every binding, a recycled set still points at whatever it pointed at before
+/// vk_device.device.update_descriptor_sets(&writes, &[]);
+///
+/// // later, nothing recorded from now on binds set
+/// allocator.free(set);
+/// ```
+pub struct VKDescriptorAllocator {
+    layouts: HashMap<LayoutKey, vk::DescriptorSetLayout>,
+    /// descriptors of each type per set, multiplied by a pool's set count when it's created
+    pool_ratios: Vec<vk::DescriptorPoolSize>,
+    /// the last one is allocated from, the rest are full
+    pools: Vec<vk::DescriptorPool>,
+    next_pool_sets: u32,
+    recycler: SetRecycler,
+}
+
+impl VKDescriptorAllocator {
+    /// No pool is created until the first set is allocated, see DEFAULT_POOL_RATIOS
+    pub fn new(pool_ratios: &[vk::DescriptorPoolSize], frames_in_flight: usize) -> Self {
+        Self {
+            layouts: HashMap::new(),
+            pool_ratios: pool_ratios.to_vec(),
+            pools: Vec::new(),
+            next_pool_sets: FIRST_POOL_SETS,
+            recycler: SetRecycler::new(frames_in_flight),
+        }
+    }
+
+    /// Layout with bindings, created the first time it's asked for and owned by the allocator
+    pub fn layout(
+        &mut self,
+        vk_device: &VKDevice,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+    ) -> Result<vk::DescriptorSetLayout, vk::Result> {
+        let key = LayoutKey::new(bindings);
+        if let Some(&layout) = self.layouts.get(&key) {
+            return Ok(layout);
+        }
+        let layout = unsafe {
+            vk_device.device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(bindings),
+                None,
+            )?
+        };
+        self.layouts.insert(key, layout);
+        Ok(layout)
+    }
+
+    /// A set of layout, recycled when one has been freed for long enough
+    /// recycled sets keep their old descriptors so every binding has to be written again
+    pub fn allocate(
+        &mut self,
+        vk_device: &VKDevice,
+        layout: vk::DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, vk::Result> {
+        if let Some(set) = self.recycler.take(layout) {
+            return Ok(set);
+        }
+
+        let layouts = [layout];
+        let allocate = |pool| {
+            let alloc_info = vk::DescriptorSetAllocateInfo::default()
+                .descriptor_pool(pool)
+                .set_layouts(&layouts);
+            unsafe { vk_device.device.allocate_descriptor_sets(&alloc_info) }
+        };
+
+        let sets = match self.pools.last() {
+            Some(&pool) => match allocate(pool) {
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {
+                    allocate(self.grow(vk_device)?)?
+                }
+                result => result?,
+            },
+            None => allocate(self.grow(vk_device)?)?,
+        };
+        self.recycler.allocated(sets[0], layout);
+        Ok(sets[0])
+    }
+
+    /// A set of layout for each frame in flight or anything else wanting several at once
+    pub fn allocate_many(
+        &mut self,
+        vk_device: &VKDevice,
+        layout: vk::DescriptorSetLayout,
+        count: usize,
+    ) -> Result<Vec<vk::DescriptorSet>, vk::Result> {
+        let mut sets = Vec::with_capacity(count);
+        for _ in 0..count {
+            match self.allocate(vk_device, layout) {
+                Ok(set) => sets.push(set),
+                Err(error) => {
+                    sets.into_iter().for_each(|set| self.free(set));
+                    return Err(error);
+                }
+            }
+        }
+        Ok(sets)
+    }
+
+    /// Hands set back, it is given out again once every frame in flight has come round
+    /// set may still be bound by frames the gpu hasn't finished, but not by ones recorded later
+    pub fn free(&mut self, set: vk::DescriptorSet) {
+        if !self.recycler.retire(set) {
+            warn!("Freed Descriptor Set {set:?} Not From This Allocator");
+        }
+    }
+
+    /// Sets freed the last time frame_in_flight was current are free again
+    /// call once frame_in_flight's fence has signalled
+    pub fn begin_frame(&mut self, frame_in_flight: usize) {
+        self.recycler.begin_frame(frame_in_flight);
+    }
+
+    /// Number of vulkan pools created so far
+    pub fn pool_count(&self) -> usize {
+        self.pools.len()
+    }
+
+    /// Freed sets ready to be handed out again
+    pub fn free_count(&self) -> usize {
+        self.recycler.free_count()
+    }
+
+    // adds a pool larger than the last and makes it the one allocated from
+    fn grow(&mut self, vk_device: &VKDevice) -> Result<vk::DescriptorPool, vk::Result> {
+        let sets = self.next_pool_sets;
+        let pool_sizes = pool_sizes(&self.pool_ratios, sets);
+        let pool = unsafe {
+            vk_device.device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .max_sets(sets)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )?
+        };
+        self.pools.push(pool);
+        self.next_pool_sets = (sets * 2).min(MAX_SETS_PER_POOL);
+        Ok(pool)
+    }
+
+    /// # Safety
+    /// No set or layout from the allocator may be in use by the gpu
+    pub unsafe fn destroy(&mut self, vk_device: &VKDevice) {
+        // sets allocated from the pools are freed with them
+        for pool in self.pools.drain(..) {
+            unsafe { vk_device.device.destroy_descriptor_pool(pool, None) };
+        }
+        for (_, layout) in self.layouts.drain() {
+            unsafe { vk_device.device.destroy_descriptor_set_layout(layout, None) };
+        }
+        self.recycler.clear();
+        self.next_pool_sets = FIRST_POOL_SETS;
+    }
+}
+
+// ratios scaled to a pool of sets
+fn pool_sizes(ratios: &[vk::DescriptorPoolSize], sets: u32) -> Vec<vk::DescriptorPoolSize> {
+    ratios
+        .iter()
+        .map(|ratio| ratio.descriptor_count(ratio.descriptor_count * sets))
+        .collect()
+}
+
+#[test]
+fn descriptor_allocator_test() {
+    use ash::vk::Handle;
+
+    let binding = |binding, ty| {
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(binding)
+            .descriptor_type(ty)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+    };
+    let texture = binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER);
+    let uniforms = binding(1, vk::DescriptorType::UNIFORM_BUFFER);
+    assert_eq!(
+        LayoutKey::new(&[texture, uniforms]),
+        LayoutKey::new(&[uniforms, texture])
+    );
+    assert_ne!(
+        LayoutKey::new(&[texture]),
+        LayoutKey::new(&[texture.stage_flags(vk::ShaderStageFlags::VERTEX)])
+    );
+
+    let sizes = pool_sizes(&DEFAULT_POOL_RATIOS, FIRST_POOL_SETS);
+    assert_eq!(sizes[0].descriptor_count, 4 * FIRST_POOL_SETS);
+
+    // with two frames in flight a set freed in frame 0 comes back when frame 0 comes round again
+    let layout = vk::DescriptorSetLayout::from_raw(1);
+    let set = vk::DescriptorSet::from_raw(2);
+    let mut recycler = SetRecycler::new(2);
+    recycler.allocated(set, layout);
+    recycler.begin_frame(0);
+    assert!(recycler.retire(set));
+    assert!(!recycler.retire(vk::DescriptorSet::from_raw(3)));
+    recycler.begin_frame(1);
+    assert_eq!(recycler.take(layout), None);
+    recycler.begin_frame(0);
+    assert_eq!(recycler.free_count(), 1);
+    assert_eq!(recycler.take(vk::DescriptorSetLayout::from_raw(4)), None);
+    assert_eq!(recycler.take(layout), Some(set));
+}
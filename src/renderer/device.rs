@@ -1,17 +1,60 @@
 use ash::vk::QueueFlags;
-use ash::{Device, Instance, khr, vk};
-use gpu_allocator::vulkan;
-use log::info;
+use ash::{Device, Instance, ext, khr, vk};
+use log::{info, warn};
 use std::error;
 use std::ffi::CStr;
 
+use crate::crash_report;
 use crate::renderer::VKInstance;
+use crate::renderer::allocator::{
+    AllocationDesc, AllocationScheme, AllocatorContext, AllocatorError, AllocatorFactory,
+    GpuAllocator, VKAllocation, VKAllocator,
+};
+use crate::renderer::benchmark::{DeviceScores, MEASURED_SCORE_BASE};
+use crate::renderer::pipeline_cache::VKPipelineCache;
 use crate::renderer::presentation::{VKSurface, VKSwapchainCapabilities};
+use crate::renderer::quirks::{QUIRK_RULES, QuirkOverrides, Quirks, matching_quirks};
+use crate::renderer::{CUBE_FACES, CUBE_SUBRESOURCE_RANGE};
 pub struct VKDevice {
-    pub mem_allocator: vulkan::Allocator, //drop order must be first
+    pub mem_allocator: Box<dyn VKAllocator>, //drop order must be first
     pub p_device: vk::PhysicalDevice,
     pub graphics_queue: vk::Queue,
     pub queue_index: u32,
+    /// queue from a transfer only family when the device has one, uploads run on it
+    /// alongside rendering, otherwise the graphics queue again
+    pub transfer_queue: vk::Queue,
+    pub transfer_queue_index: u32,
+    pub limits: vk::PhysicalDeviceLimits,
+    pub memory_properties: vk::PhysicalDeviceMemoryProperties,
+    /// loaded when the driver lets allocations be paged out by priority, see set_memory_priority
+    pub pageable_memory: Option<ext::pageable_device_local_memory::Device>,
+    /// loaded with VK_KHR_draw_indirect_count, lets the gpu decide how many indirect draws run
+    pub draw_indirect_count: Option<khr::draw_indirect_count::Device>,
+    /// loaded with VK_KHR_push_descriptor, bindings can be written straight into the command buffer
+    pub push_descriptor: Option<khr::push_descriptor::Device>,
+    /// indirect draws can run more than one command per call, see indirect::cmd_draw_indexed_indirect
+    pub multi_draw_indirect: bool,
+    /// indirect draws can start past instance 0, needed for first_instance to pick per object data
+    pub draw_indirect_first_instance: bool,
+    /// loaded with VK_KHR_performance_query, query pools are reset from the host
+    pub performance_query: Option<khr::performance_query::Device>,
+    /// loaded with VK_EXT_conditional_rendering, lets draws be skipped by a value the gpu wrote
+    pub conditional_rendering: Option<ext::conditional_rendering::Device>,
+    /// loaded with VK_KHR_acceleration_structure, mesh buffers can then be built into BVHs
+    pub acceleration_structure: Option<khr::acceleration_structure::Device>,
+    /// loaded with VK_KHR_ray_tracing_pipeline alongside acceleration_structure
+    pub ray_tracing_pipeline: Option<khr::ray_tracing_pipeline::Device>,
+    /// VK_KHR_ray_query is enabled alongside acceleration_structure, shaders can trace inline rays
+    pub ray_query: bool,
+    /// sizes and alignments for acceleration structures and shader binding tables
+    pub ray_tracing_properties: RayTracingProperties,
+    /// resources that ran out of vram and live in host visible memory instead
+    pub demoted: Vec<DemotedResource>,
+    pub enabled_extensions: Vec<&'static CStr>,
+    /// driver workarounds in effect, extensions they disable are left out of enabled_extensions
+    pub quirks: Quirks,
+    /// passed to every pipeline creation, saved to disk when the device is destroyed
+    pub pipeline_cache: VKPipelineCache,
     pub device: Device,
 }
 
@@ -19,6 +62,15 @@ impl VKDevice {
     pub fn new(
         instance: &VKInstance,
         vulkan_surface: &VKSurface,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        Self::with_allocator(instance, vulkan_surface, GpuAllocator::create)
+    }
+
+    /// Like new but memory comes from the allocator create_allocator builds
+    pub fn with_allocator(
+        instance: &VKInstance,
+        vulkan_surface: &VKSurface,
+        create_allocator: AllocatorFactory,
     ) -> Result<Self, Box<dyn error::Error>> {
         // Device Requirments should probably be initialised in the Vulkan CTX.
         // With the possibility for the Engine user to append their own-
@@ -26,11 +78,24 @@ impl VKDevice {
         // base extentions before device setup.
         let mut dev_requirments = VKDeviceRequirments::default()
             .add_queue_flag(vk::QueueFlags::GRAPHICS)
+            // compute utilities are recorded alongside rendering on the same queue
+            .add_queue_flag(vk::QueueFlags::COMPUTE)
             .push_ext(khr::swapchain::NAME)
             .push_ext(khr::dynamic_rendering::NAME)
             .push_ext(khr::synchronization2::NAME)
             .push_ext(khr::timeline_semaphore::NAME)
             .push_ext(khr::buffer_device_address::NAME)
+            .push_optional_ext(ash::google::display_timing::NAME)
+            .push_optional_ext(ext::memory_priority::NAME)
+            .push_optional_ext(ext::pageable_device_local_memory::NAME)
+            .push_optional_ext(khr::draw_indirect_count::NAME)
+            .push_optional_ext(khr::push_descriptor::NAME)
+            .push_optional_ext(ext::conditional_rendering::NAME)
+            .push_optional_ext(khr::performance_query::NAME)
+            .push_optional_ext(khr::deferred_host_operations::NAME)
+            .push_optional_ext(khr::acceleration_structure::NAME)
+            .push_optional_ext(khr::ray_tracing_pipeline::NAME)
+            .push_optional_ext(khr::ray_query::NAME)
             .push_info(
                 vk::PhysicalDeviceDynamicRenderingFeatures::default().dynamic_rendering(true),
             )
@@ -63,16 +128,36 @@ impl VKDevice {
                     true
                 }
             });
-        // there is no way for the scoring function to be changed by the user then why have it passed as an argument.
-        // possibly make device picking a struct with changable defaults.
+        // measured devices are scored by their benchmarks, the heuristic orders any that couldn't be
+        let cache_directory = instance.pipeline_cache_directory.as_deref();
+        let mut device_scores = instance
+            .device_benchmarks
+            .then(|| DeviceScores::load(cache_directory));
         let (p_device, ideal_graphics_queue) = Self::pick_device(
             &instance.instance,
-            score_physical_device,
+            |physical_device, instance| match device_scores
+                .as_mut()
+                .and_then(|scores| scores.score(instance, *physical_device))
+            {
+                Some(score) => MEASURED_SCORE_BASE + score,
+                None => score_physical_device(physical_device, instance),
+            },
             &dev_requirments,
             vulkan_surface,
         )?;
+        if let (Some(scores), Some(directory)) = (&device_scores, cache_directory)
+            && scores.is_changed()
+            && let Err(error) = scores.save(directory)
+        {
+            warn!("Failed to Save Device Scores: {error}");
+        }
+
+        let mut enabled_extensions =
+            dev_requirments.enabled_extentions(&p_device, &instance.instance);
 
-        let mut device_properties_two = vk::PhysicalDeviceProperties2::default();
+        let mut driver_properties = vk::PhysicalDeviceDriverProperties::default();
+        let mut device_properties_two =
+            vk::PhysicalDeviceProperties2::default().push_next(&mut driver_properties);
 
         unsafe {
             instance
@@ -99,32 +184,232 @@ impl VKDevice {
             physical_device_memory_size(&p_device, &instance.instance)
         );
 
+        let quirks = device_quirks(&device_properties_two.properties, instance.quirk_overrides);
+        let disabled_extensions = quirks.disabled_extensions();
+        enabled_extensions.retain(|extension| !disabled_extensions.contains(extension));
+
+        let memory_properties = unsafe {
+            instance
+                .instance
+                .get_physical_device_memory_properties(p_device)
+        };
+        if has_resizable_bar(&memory_properties) {
+            info!("VK Resizable BAR: per frame data is written straight to device memory");
+        }
+
+        let device_properties = device_properties_two.properties;
+        crash_report::update_context(|context| {
+            context.device_name = device_properties
+                .device_name_as_c_str()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            context.driver = format!(
+                "{} {}",
+                driver_properties
+                    .driver_name_as_c_str()
+                    .unwrap_or_default()
+                    .to_string_lossy(),
+                driver_properties
+                    .driver_info_as_c_str()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+            );
+            context.api_version = format!(
+                "{}.{}.{}",
+                vk::api_version_major(instance_version),
+                vk::api_version_minor(instance_version),
+                vk::api_version_patch(instance_version)
+            );
+            context.device_extensions = enabled_extensions
+                .iter()
+                .map(|name| name.to_string_lossy().into_owned())
+                .collect();
+        });
+
         // Setup Logical Device (Set Features, Enable Extentions, Configure Extentions)
 
         let priorities = [1.0f32];
 
-        let queue_create_infos = vk::DeviceQueueCreateInfo::default()
-            .queue_family_index(ideal_graphics_queue)
-            .queue_priorities(&priorities);
+        let queue_families = unsafe {
+            instance
+                .instance
+                .get_physical_device_queue_family_properties(p_device)
+        };
+        let transfer_family = transfer_queue_family(&queue_families, ideal_graphics_queue);
+
+        let queue_create_infos: Vec<vk::DeviceQueueCreateInfo> =
+            std::iter::once(ideal_graphics_queue)
+                .chain(transfer_family)
+                .map(|family| {
+                    vk::DeviceQueueCreateInfo::default()
+                        .queue_family_index(family)
+                        .queue_priorities(&priorities)
+                })
+                .collect();
 
         // features should probably be in requirments
-        let features = vk::PhysicalDeviceFeatures::default();
+        let supported_features =
+            unsafe { instance.instance.get_physical_device_features(p_device) };
+        let multi_draw_indirect = supported_features.multi_draw_indirect == vk::TRUE;
+        let draw_indirect_first_instance =
+            supported_features.draw_indirect_first_instance == vk::TRUE;
+        // the ray tracing hit shader reads vertices through 64 bit buffer addresses
+        let shader_int64 = supported_features.shader_int64 == vk::TRUE;
+        let features = vk::PhysicalDeviceFeatures::default()
+            .multi_draw_indirect(multi_draw_indirect)
+            .draw_indirect_first_instance(draw_indirect_first_instance)
+            .shader_int64(shader_int64);
 
         // array of Requested Device extension_names as c string ptr
-        let device_extension_names = dev_requirments.get_requirments_raw();
+        let device_extension_names: Vec<*const std::ffi::c_char> = enabled_extensions
+            .iter()
+            .map(|extension| extension.as_ptr())
+            .collect();
 
         let device_create_info = vk::DeviceCreateInfo::default()
             .enabled_extension_names(&device_extension_names)
             .enabled_features(&features)
-            .queue_create_infos(std::slice::from_ref(&queue_create_infos));
+            .queue_create_infos(&queue_create_infos);
 
-        let device_create_info = dev_requirments
+        let mut device_create_info = dev_requirments
             .device_extended_info
             .iter_mut()
             .fold(device_create_info, |dev_info, info| {
                 dev_info.push_next(info.as_mut())
             });
 
+        // the extension alone doesn't allow setting priorities, the feature has to be there too
+        let mut pageable_features =
+            vk::PhysicalDevicePageableDeviceLocalMemoryFeaturesEXT::default();
+        let pageable_memory_supported =
+            enabled_extensions.contains(&ext::pageable_device_local_memory::NAME) && {
+                let mut features_two =
+                    vk::PhysicalDeviceFeatures2::default().push_next(&mut pageable_features);
+                unsafe {
+                    instance
+                        .instance
+                        .get_physical_device_features2(p_device, &mut features_two)
+                };
+                pageable_features.pageable_device_local_memory == vk::TRUE
+            };
+        if pageable_memory_supported {
+            device_create_info = device_create_info.push_next(&mut pageable_features);
+        }
+
+        // same again for conditional rendering
+        let mut conditional_features = vk::PhysicalDeviceConditionalRenderingFeaturesEXT::default();
+        let conditional_rendering_supported =
+            enabled_extensions.contains(&ext::conditional_rendering::NAME) && {
+                let mut features_two =
+                    vk::PhysicalDeviceFeatures2::default().push_next(&mut conditional_features);
+                unsafe {
+                    instance
+                        .instance
+                        .get_physical_device_features2(p_device, &mut features_two)
+                };
+                conditional_features.conditional_rendering == vk::TRUE
+            };
+        if conditional_rendering_supported {
+            conditional_features.inherited_conditional_rendering = vk::FALSE;
+            device_create_info = device_create_info.push_next(&mut conditional_features);
+        }
+
+        // performance queries can't be reset by the command buffers that begin them
+        let performance_query_supported =
+            enabled_extensions.contains(&khr::performance_query::NAME) && {
+                let mut performance_features =
+                    vk::PhysicalDevicePerformanceQueryFeaturesKHR::default();
+                let mut host_reset_features = vk::PhysicalDeviceHostQueryResetFeatures::default();
+                let mut features_two = vk::PhysicalDeviceFeatures2::default()
+                    .push_next(&mut performance_features)
+                    .push_next(&mut host_reset_features);
+                unsafe {
+                    instance
+                        .instance
+                        .get_physical_device_features2(p_device, &mut features_two)
+                };
+                performance_features.performance_counter_query_pools == vk::TRUE
+                    && host_reset_features.host_query_reset == vk::TRUE
+            };
+        let mut performance_features = vk::PhysicalDevicePerformanceQueryFeaturesKHR::default()
+            .performance_counter_query_pools(true);
+        let mut host_reset_features =
+            vk::PhysicalDeviceHostQueryResetFeatures::default().host_query_reset(true);
+        if performance_query_supported {
+            device_create_info = device_create_info
+                .push_next(&mut performance_features)
+                .push_next(&mut host_reset_features);
+        }
+
+        // every kind of ray tracing needs acceleration structures
+        let acceleration_supported = [
+            khr::deferred_host_operations::NAME,
+            khr::acceleration_structure::NAME,
+        ]
+        .iter()
+        .all(|name| enabled_extensions.contains(name))
+            && {
+                let mut acceleration_features =
+                    vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default();
+                let mut features_two =
+                    vk::PhysicalDeviceFeatures2::default().push_next(&mut acceleration_features);
+                unsafe {
+                    instance
+                        .instance
+                        .get_physical_device_features2(p_device, &mut features_two)
+                };
+                acceleration_features.acceleration_structure == vk::TRUE
+            };
+        let ray_tracing_supported = acceleration_supported
+            && shader_int64
+            && enabled_extensions.contains(&khr::ray_tracing_pipeline::NAME)
+            && {
+                let mut pipeline_features =
+                    vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default();
+                let mut features_two =
+                    vk::PhysicalDeviceFeatures2::default().push_next(&mut pipeline_features);
+                unsafe {
+                    instance
+                        .instance
+                        .get_physical_device_features2(p_device, &mut features_two)
+                };
+                pipeline_features.ray_tracing_pipeline == vk::TRUE
+            };
+        let ray_query_supported =
+            acceleration_supported && enabled_extensions.contains(&khr::ray_query::NAME) && {
+                let mut ray_query_features = vk::PhysicalDeviceRayQueryFeaturesKHR::default();
+                let mut features_two =
+                    vk::PhysicalDeviceFeatures2::default().push_next(&mut ray_query_features);
+                unsafe {
+                    instance
+                        .instance
+                        .get_physical_device_features2(p_device, &mut features_two)
+                };
+                ray_query_features.ray_query == vk::TRUE
+            };
+        let mut acceleration_features =
+            vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default()
+                .acceleration_structure(true);
+        let mut pipeline_features =
+            vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default().ray_tracing_pipeline(true);
+        let mut ray_query_features =
+            vk::PhysicalDeviceRayQueryFeaturesKHR::default().ray_query(true);
+        if acceleration_supported {
+            device_create_info = device_create_info.push_next(&mut acceleration_features);
+        }
+        if ray_tracing_supported {
+            device_create_info = device_create_info.push_next(&mut pipeline_features);
+        }
+        if ray_query_supported {
+            device_create_info = device_create_info.push_next(&mut ray_query_features);
+        }
+        let ray_tracing_properties = if acceleration_supported {
+            RayTracingProperties::query(&instance.instance, p_device, ray_tracing_supported)
+        } else {
+            RayTracingProperties::default()
+        };
+
         //Create Logical Device
         let device = unsafe {
             instance
@@ -132,37 +417,93 @@ impl VKDevice {
                 .create_device(p_device, &device_create_info, None)?
         };
 
+        let pageable_memory = pageable_memory_supported.then(|| {
+            info!("VK Pageable Device Memory: render targets are kept resident first");
+            ext::pageable_device_local_memory::Device::new(&instance.instance, &device)
+        });
+
+        let draw_indirect_count = enabled_extensions
+            .contains(&khr::draw_indirect_count::NAME)
+            .then(|| khr::draw_indirect_count::Device::new(&instance.instance, &device));
+
+        let push_descriptor = enabled_extensions
+            .contains(&khr::push_descriptor::NAME)
+            .then(|| khr::push_descriptor::Device::new(&instance.instance, &device));
+
+        let conditional_rendering = conditional_rendering_supported
+            .then(|| ext::conditional_rendering::Device::new(&instance.instance, &device));
+
+        let performance_query = performance_query_supported
+            .then(|| khr::performance_query::Device::new(&instance.instance, &device));
+
+        let acceleration_structure = acceleration_supported.then(|| {
+            info!(
+                "VK Ray Tracing: acceleration structures, pipelines {}, ray queries {}",
+                ray_tracing_supported, ray_query_supported
+            );
+            khr::acceleration_structure::Device::new(&instance.instance, &device)
+        });
+        let ray_tracing_pipeline = ray_tracing_supported
+            .then(|| khr::ray_tracing_pipeline::Device::new(&instance.instance, &device));
+
         // Get Graphics queue for logical devices
         let graphics_queue = unsafe { device.get_device_queue(ideal_graphics_queue, 0u32) };
 
-        let alloc_desc = vulkan::AllocatorCreateDesc {
-            instance: instance.instance.clone(),
-            device: device.clone(),
+        let transfer_queue_index = transfer_family.unwrap_or(ideal_graphics_queue);
+        let transfer_queue = unsafe { device.get_device_queue(transfer_queue_index, 0u32) };
+        if transfer_family.is_some() {
+            info!("VK Transfer Queue: uploads run on queue family {transfer_queue_index}");
+        }
+
+        let mem_allocator = create_allocator(&AllocatorContext {
+            instance: &instance.instance,
+            device: &device,
             physical_device: p_device,
-            debug_settings: Default::default(),
             buffer_device_address: true,
-            allocation_sizes: Default::default(),
-        };
+        })?;
 
-        let mem_allocator = vulkan::Allocator::new(&alloc_desc)?;
+        let pipeline_cache = VKPipelineCache::new(
+            &device,
+            &device_properties,
+            instance.pipeline_cache_directory.as_deref(),
+        );
 
         Ok(Self {
             p_device,
+            pipeline_cache,
             device,
             graphics_queue,
             queue_index: ideal_graphics_queue,
+            transfer_queue,
+            transfer_queue_index,
+            limits: device_properties.limits,
+            memory_properties,
+            pageable_memory,
+            draw_indirect_count,
+            push_descriptor,
+            multi_draw_indirect,
+            draw_indirect_first_instance,
+            performance_query,
+            conditional_rendering,
+            acceleration_structure,
+            ray_tracing_pipeline,
+            ray_query: ray_query_supported,
+            ray_tracing_properties,
+            demoted: Vec::new(),
+            enabled_extensions,
+            quirks,
             mem_allocator,
         })
     }
 
     fn pick_device<F>(
         instance: &Instance,
-        score_function: F,
+        mut score_function: F,
         dev_requirments: &VKDeviceRequirments,
         vulkan_surface: &VKSurface,
     ) -> Result<(vk::PhysicalDevice, u32 /* queue_index */), Box<dyn error::Error>>
     where
-        F: Fn(&vk::PhysicalDevice, &Instance) -> u64,
+        F: FnMut(&vk::PhysicalDevice, &Instance) -> u64,
     {
         let physical_devices = unsafe { instance.enumerate_physical_devices()? };
 
@@ -206,8 +547,9 @@ impl VKDevice {
         image_format: vk::Format,
         image_tiling: vk::ImageTiling,
         image_usage: vk::ImageUsageFlags,
+        image_samples: vk::SampleCountFlags,
         mem_location: gpu_allocator::MemoryLocation,
-    ) -> Result<(vk::Image, vulkan::Allocation), vk::Result> {
+    ) -> Result<(vk::Image, VKAllocation), vk::Result> {
         let image_create_info = vk::ImageCreateInfo::default()
             .image_type(vk::ImageType::TYPE_2D)
             .extent(
@@ -222,29 +564,303 @@ impl VKDevice {
             .initial_layout(vk::ImageLayout::UNDEFINED)
             .tiling(image_tiling)
             .usage(image_usage)
-            .samples(vk::SampleCountFlags::TYPE_1)
+            .samples(image_samples)
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
         let image = unsafe { self.device.create_image(&image_create_info, None)? };
         let mem_req = unsafe { self.device.get_image_memory_requirements(image) };
 
         let linear: bool = image_tiling == vk::ImageTiling::LINEAR;
 
-        let allocation = self
-            .mem_allocator
-            .allocate(&vulkan::AllocationCreateDesc {
-                name: "Depth Image",
+        let allocation = match self.allocate(
+            &AllocationDesc {
+                name: "Image",
                 requirements: mem_req,
                 location: mem_location,
-                linear: linear,
-                allocation_scheme: vulkan::AllocationScheme::DedicatedImage(image),
-            })
-            .unwrap();
+                linear,
+                scheme: AllocationScheme::DedicatedImage(image),
+            },
+            DemotedHandle::Image(image),
+        ) {
+            Ok(allocation) => allocation,
+            Err(error) => {
+                log::error!("Failed to Allocate Image: {error}");
+                unsafe { self.device.destroy_image(image, None) };
+                return Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY);
+            }
+        };
+        self.set_memory_priority(&allocation, MemoryPriority::for_image_usage(image_usage));
+
+        if let Err(error) = unsafe {
+            self.device
+                .bind_image_memory(image, allocation.memory(), allocation.offset())
+        } {
+            unsafe { self.destroy_image(image, allocation) };
+            return Err(error);
+        }
+        Ok((image, allocation))
+    }
+
+    /// Typed wrapper around cmd_push_constants, writes the bytes of constants
+    /// # Safety
+    /// cmd_buffer must be recording and T must match the layout declared in the shader
+    pub unsafe fn cmd_push_constants<T: Copy>(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        pipeline_layout: vk::PipelineLayout,
+        stage_flags: vk::ShaderStageFlags,
+        offset: u32,
+        constants: &T,
+    ) {
+        unsafe {
+            let constants_bytes =
+                std::slice::from_raw_parts(constants as *const T as *const u8, size_of::<T>());
+            self.device.cmd_push_constants(
+                cmd_buffer,
+                pipeline_layout,
+                stage_flags,
+                offset,
+                constants_bytes,
+            );
+        }
+    }
+
+    /// Writes bindings of set straight into cmd_buffer with VK_KHR_push_descriptor
+    /// returns false without recording anything when the extension isn't loaded,
+    /// the caller binds a regular set instead
+    /// # Safety
+    /// cmd_buffer must be recording and set in pipeline_layout must have been created
+    /// with DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR
+    pub unsafe fn cmd_push_descriptor_set(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        bind_point: vk::PipelineBindPoint,
+        pipeline_layout: vk::PipelineLayout,
+        set: u32,
+        writes: &[vk::WriteDescriptorSet],
+    ) -> bool {
+        let Some(push_descriptor) = &self.push_descriptor else {
+            return false;
+        };
+        unsafe {
+            push_descriptor.cmd_push_descriptor_set(
+                cmd_buffer,
+                bind_point,
+                pipeline_layout,
+                set,
+                writes,
+            )
+        };
+        true
+    }
+
+    /// Flags for a descriptor set layout that is pushed when push descriptors are available
+    pub fn push_descriptor_layout_flags(&self) -> vk::DescriptorSetLayoutCreateFlags {
+        if self.push_descriptor.is_some() {
+            vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR
+        } else {
+            vk::DescriptorSetLayoutCreateFlags::empty()
+        }
+    }
+
+    /// Highest sample count usable for both colour and depth attachments
+    pub fn max_sample_count(&self) -> vk::SampleCountFlags {
+        pick_sample_count(
+            self.limits.framebuffer_color_sample_counts
+                & self.limits.framebuffer_depth_sample_counts,
+            vk::SampleCountFlags::TYPE_64,
+        )
+    }
+
+    /// Closest sample count to desired the device supports, never higher than desired
+    pub fn supported_sample_count(&self, desired: vk::SampleCountFlags) -> vk::SampleCountFlags {
+        pick_sample_count(
+            self.limits.framebuffer_color_sample_counts
+                & self.limits.framebuffer_depth_sample_counts,
+            desired,
+        )
+    }
+
+    /// True if the extension was enabled on device creation
+    pub fn extension_enabled(&self, extension_name: &CStr) -> bool {
+        self.enabled_extensions.contains(&extension_name)
+    }
+
+    /// Whether the cpu can map all of device local memory, see has_resizable_bar
+    pub fn resizable_bar(&self) -> bool {
+        has_resizable_bar(&self.memory_properties)
+    }
 
+    /// Hints which memory the driver should move to system memory first when vram runs out
+    /// only takes effect with VK_EXT_pageable_device_local_memory, the whole allocation is affected
+    /// so it is meant for dedicated allocations like the ones create_buffer and create_image make
+    pub fn set_memory_priority(&self, allocation: &VKAllocation, priority: MemoryPriority) {
+        let Some(pageable_memory) = &self.pageable_memory else {
+            return;
+        };
+        unsafe {
+            (pageable_memory.fp().set_device_memory_priority_ext)(
+                pageable_memory.device(),
+                allocation.memory(),
+                priority.value(),
+            )
+        };
+    }
+
+    /// Summary of allocator memory use for logging and crash reports
+    pub fn allocator_stats(&self) -> String {
+        let report = self.mem_allocator.report();
+        format!(
+            "{} allocations in {} blocks, {:.1} MiB used of {:.1} MiB, {} demoted to system memory",
+            report.allocations,
+            report.blocks,
+            report.allocated_bytes as f64 / (1024.0 * 1024.0),
+            report.capacity_bytes as f64 / (1024.0 * 1024.0),
+            self.demoted.len()
+        )
+    }
+
+    // gpu only allocations that don't fit in vram retry in host visible memory, slower but still usable
+    fn allocate(
+        &mut self,
+        desc: &AllocationDesc,
+        handle: DemotedHandle,
+    ) -> Result<VKAllocation, AllocatorError> {
+        match self.mem_allocator.allocate(desc) {
+            Err(AllocatorError::OutOfMemory)
+                if desc.location == gpu_allocator::MemoryLocation::GpuOnly =>
+            {
+                let allocation = self.mem_allocator.allocate(&AllocationDesc {
+                    location: gpu_allocator::MemoryLocation::CpuToGpu,
+                    ..*desc
+                })?;
+                log::warn!(
+                    "Out of Device Memory, {} ({} KiB) Demoted to Host Visible Memory",
+                    desc.name,
+                    desc.requirements.size / 1024
+                );
+                self.demoted.push(DemotedResource {
+                    handle,
+                    name: desc.name.to_owned(),
+                    size: desc.requirements.size,
+                });
+                Ok(allocation)
+            }
+            result => result,
+        }
+    }
+
+    fn forget_demoted(&mut self, handle: DemotedHandle) {
+        self.demoted.retain(|demoted| demoted.handle != handle);
+    }
+
+    /// Whether a resource from create_buffer or create_image ended up outside vram
+    /// its owner can recreate it once memory has been freed to move it back
+    pub fn is_demoted(&self, handle: DemotedHandle) -> bool {
+        self.demoted.iter().any(|demoted| demoted.handle == handle)
+    }
+
+    /// Address of a buffer created with SHADER_DEVICE_ADDRESS usage
+    pub fn buffer_address(&self, buffer: vk::Buffer) -> vk::DeviceAddress {
         unsafe {
             self.device
-                .bind_image_memory(image, allocation.memory(), allocation.offset())?
+                .get_buffer_device_address(&vk::BufferDeviceAddressInfo::default().buffer(buffer))
+        }
+    }
+
+    /// Creates a buffer with dedicated memory bound to it
+    pub fn create_buffer(
+        &mut self,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+        mem_location: gpu_allocator::MemoryLocation,
+        name: &str,
+    ) -> Result<(vk::Buffer, VKAllocation), vk::Result> {
+        let buffer_create_info = vk::BufferCreateInfo::default()
+            .usage(usage)
+            .size(size)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        self.create_buffer_from_info(&buffer_create_info, mem_location, name)
+    }
+
+    /// Like create_buffer but usable from both the graphics and transfer queues
+    /// without ownership transfers, for buffers filled by VKUploader
+    pub fn create_shared_buffer(
+        &mut self,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+        mem_location: gpu_allocator::MemoryLocation,
+        name: &str,
+    ) -> Result<(vk::Buffer, VKAllocation), vk::Result> {
+        let queue_families = [self.queue_index, self.transfer_queue_index];
+        let mut buffer_create_info = vk::BufferCreateInfo::default()
+            .usage(usage)
+            .size(size)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        if self.queue_index != self.transfer_queue_index {
+            buffer_create_info = buffer_create_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&queue_families);
+        }
+
+        self.create_buffer_from_info(&buffer_create_info, mem_location, name)
+    }
+
+    fn create_buffer_from_info(
+        &mut self,
+        buffer_create_info: &vk::BufferCreateInfo,
+        mem_location: gpu_allocator::MemoryLocation,
+        name: &str,
+    ) -> Result<(vk::Buffer, VKAllocation), vk::Result> {
+        let buffer = unsafe { self.device.create_buffer(buffer_create_info, None)? };
+        let requirements = unsafe { self.device.get_buffer_memory_requirements(buffer) };
+
+        let allocation = match self.allocate(
+            &AllocationDesc {
+                name,
+                requirements,
+                location: mem_location,
+                linear: true,
+                scheme: AllocationScheme::DedicatedBuffer(buffer),
+            },
+            DemotedHandle::Buffer(buffer),
+        ) {
+            Ok(allocation) => allocation,
+            Err(_) => {
+                unsafe { self.device.destroy_buffer(buffer, None) };
+                return Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY);
+            }
         };
-        Ok((image, allocation))
+
+        if let Err(error) = unsafe {
+            self.device
+                .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
+        } {
+            unsafe { self.destroy_buffer(buffer, allocation) };
+            return Err(error);
+        }
+
+        Ok((buffer, allocation))
+    }
+
+    /// # Safety
+    /// Buffer must not be in use by the gpu
+    pub unsafe fn destroy_buffer(&mut self, buffer: vk::Buffer, allocation: VKAllocation) {
+        self.forget_demoted(DemotedHandle::Buffer(buffer));
+        if let Err(error) = self.mem_allocator.free(allocation) {
+            log::error!("Failed to Free Buffer Memory: {error}");
+        }
+        unsafe { self.device.destroy_buffer(buffer, None) };
+    }
+
+    /// # Safety
+    /// Image and any views of it must not be in use by the gpu
+    pub unsafe fn destroy_image(&mut self, image: vk::Image, allocation: VKAllocation) {
+        self.forget_demoted(DemotedHandle::Image(image));
+        if let Err(error) = self.mem_allocator.free(allocation) {
+            log::error!("Failed to Free Image Memory: {error}");
+        }
+        unsafe { self.device.destroy_image(image, None) };
     }
 
     pub fn create_image_view(
@@ -275,29 +891,209 @@ impl VKDevice {
         unsafe { self.device.create_image_view(&image_view_create_info, None) }
     }
 
+    /// Square 6 layer image that can be viewed as a cube, layers are faces in +X -X +Y -Y +Z -Z order
+    pub fn create_cube_image(
+        &mut self,
+        size: u32,
+        image_format: vk::Format,
+        image_usage: vk::ImageUsageFlags,
+    ) -> Result<(vk::Image, VKAllocation), vk::Result> {
+        let image_create_info = vk::ImageCreateInfo::default()
+            .flags(vk::ImageCreateFlags::CUBE_COMPATIBLE)
+            .image_type(vk::ImageType::TYPE_2D)
+            .extent(vk::Extent3D::default().width(size).height(size).depth(1))
+            .mip_levels(1)
+            .array_layers(CUBE_FACES)
+            .format(image_format)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(image_usage)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let image = unsafe { self.device.create_image(&image_create_info, None)? };
+        let mem_req = unsafe { self.device.get_image_memory_requirements(image) };
+
+        let allocation = match self.allocate(
+            &AllocationDesc {
+                name: "Cube Image",
+                requirements: mem_req,
+                location: gpu_allocator::MemoryLocation::GpuOnly,
+                linear: false,
+                scheme: AllocationScheme::DedicatedImage(image),
+            },
+            DemotedHandle::Image(image),
+        ) {
+            Ok(allocation) => allocation,
+            Err(error) => {
+                log::error!("Failed to Allocate Cube Image: {error}");
+                unsafe { self.device.destroy_image(image, None) };
+                return Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY);
+            }
+        };
+
+        if let Err(error) = unsafe {
+            self.device
+                .bind_image_memory(image, allocation.memory(), allocation.offset())
+        } {
+            unsafe { self.destroy_image(image, allocation) };
+            return Err(error);
+        }
+        Ok((image, allocation))
+    }
+
+    /// View of every face of an image from create_cube_image, sampled with a direction
+    pub fn create_cube_image_view(
+        &self,
+        vk_image: vk::Image,
+        image_format: vk::Format,
+    ) -> Result<vk::ImageView, vk::Result> {
+        let image_view_create_info = vk::ImageViewCreateInfo::default()
+            .image(vk_image)
+            .view_type(vk::ImageViewType::CUBE)
+            .format(image_format)
+            .subresource_range(CUBE_SUBRESOURCE_RANGE);
+        unsafe { self.device.create_image_view(&image_view_create_info, None) }
+    }
+
     /// # Safety
     /// Read VK Docs For Destruction Order
     /// Device must be destroyed before the instance
     pub unsafe fn destroy(&mut self) {
         unsafe {
             self.device.device_wait_idle().unwrap();
+            self.pipeline_cache.destroy(&self.device);
             self.device.destroy_device(None);
         }
     }
 }
 
+/// Subset of device operations used to create and destroy gpu resources
+/// code written against this can be tested with MockDevice on machines without vulkan drivers
+pub trait GpuDevice {
+    fn create_buffer(
+        &mut self,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+        mem_location: gpu_allocator::MemoryLocation,
+        name: &str,
+    ) -> Result<(vk::Buffer, VKAllocation), vk::Result>;
+
+    fn create_image(
+        &mut self,
+        image_extent: vk::Extent2D,
+        image_format: vk::Format,
+        image_tiling: vk::ImageTiling,
+        image_usage: vk::ImageUsageFlags,
+        image_samples: vk::SampleCountFlags,
+        mem_location: gpu_allocator::MemoryLocation,
+    ) -> Result<(vk::Image, VKAllocation), vk::Result>;
+
+    fn create_image_view(
+        &mut self,
+        vk_image: vk::Image,
+        image_format: vk::Format,
+        aspect_mask: vk::ImageAspectFlags,
+    ) -> Result<vk::ImageView, vk::Result>;
+
+    /// # Safety
+    /// Buffer must not be in use by the gpu
+    unsafe fn destroy_buffer(&mut self, buffer: vk::Buffer, allocation: VKAllocation);
+
+    /// # Safety
+    /// Image must not be in use by the gpu, destroy its views first
+    unsafe fn destroy_image(&mut self, image: vk::Image, allocation: VKAllocation);
+
+    /// # Safety
+    /// Image view must not be in use by the gpu
+    unsafe fn destroy_image_view(&mut self, image_view: vk::ImageView);
+}
+
+impl GpuDevice for VKDevice {
+    fn create_buffer(
+        &mut self,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+        mem_location: gpu_allocator::MemoryLocation,
+        name: &str,
+    ) -> Result<(vk::Buffer, VKAllocation), vk::Result> {
+        VKDevice::create_buffer(self, size, usage, mem_location, name)
+    }
+
+    fn create_image(
+        &mut self,
+        image_extent: vk::Extent2D,
+        image_format: vk::Format,
+        image_tiling: vk::ImageTiling,
+        image_usage: vk::ImageUsageFlags,
+        image_samples: vk::SampleCountFlags,
+        mem_location: gpu_allocator::MemoryLocation,
+    ) -> Result<(vk::Image, VKAllocation), vk::Result> {
+        VKDevice::create_image(
+            self,
+            image_extent,
+            image_format,
+            image_tiling,
+            image_usage,
+            image_samples,
+            mem_location,
+        )
+    }
+
+    fn create_image_view(
+        &mut self,
+        vk_image: vk::Image,
+        image_format: vk::Format,
+        aspect_mask: vk::ImageAspectFlags,
+    ) -> Result<vk::ImageView, vk::Result> {
+        VKDevice::create_image_view(self, vk_image, image_format, aspect_mask)
+    }
+
+    unsafe fn destroy_buffer(&mut self, buffer: vk::Buffer, allocation: VKAllocation) {
+        unsafe { VKDevice::destroy_buffer(self, buffer, allocation) }
+    }
+
+    unsafe fn destroy_image(&mut self, image: vk::Image, allocation: VKAllocation) {
+        unsafe { VKDevice::destroy_image(self, image, allocation) }
+    }
+
+    unsafe fn destroy_image_view(&mut self, image_view: vk::ImageView) {
+        unsafe { self.device.destroy_image_view(image_view, None) }
+    }
+}
+
+/// Highest sample count in supported that is not above desired
+/// TYPE_1 is always supported
+pub fn pick_sample_count(
+    supported: vk::SampleCountFlags,
+    desired: vk::SampleCountFlags,
+) -> vk::SampleCountFlags {
+    [
+        vk::SampleCountFlags::TYPE_64,
+        vk::SampleCountFlags::TYPE_32,
+        vk::SampleCountFlags::TYPE_16,
+        vk::SampleCountFlags::TYPE_8,
+        vk::SampleCountFlags::TYPE_4,
+        vk::SampleCountFlags::TYPE_2,
+    ]
+    .into_iter()
+    .find(|&samples| samples.as_raw() <= desired.as_raw() && supported.contains(samples))
+    .unwrap_or(vk::SampleCountFlags::TYPE_1)
+}
+
 /// Function for Checking Requirments
 type ReqFn<'a> = Box<dyn Fn(&vk::PhysicalDevice, &Instance, Option<&VKSurface>) -> bool + 'a>;
 
 /// Struct for holding and testing Device Requirments
 /// Example Use:
-/// ```
+/// ```ignore
 /// let physical_device = ...;
 /// let DeviceRequirments = DeviceRequirments::default().push_ext(ash::khr::dynamic_rendering::NAME);
 /// printf("Compatible {:?}", DeviceRequirments.check_device(physical_device));
 /// ```
 pub struct VKDeviceRequirments<'a> {
     pub required_extentions: Vec<&'static CStr>,
+    /// enabled when the picked device supports them, never affect device compatibility
+    pub optional_extentions: Vec<&'static CStr>,
     pub device_extended_info: Vec<Box<dyn vk::ExtendsDeviceCreateInfo + 'a>>,
     pub requirement_functions: Vec<ReqFn<'a>>,
     pub required_queue_flags: vk::QueueFlags,
@@ -310,6 +1106,12 @@ impl<'a> VKDeviceRequirments<'a> {
         self
     }
 
+    /// Adds a vulkan extention that is enabled only if the device supports it
+    pub fn push_optional_ext(mut self, ext_name: &'static CStr) -> Self {
+        self.optional_extentions.push(ext_name);
+        self
+    }
+
     /// Adds Structures that extend the creation of logical Devices to the requirments
     /// This is so they can be used on logical Device creation
     pub fn push_info<T>(mut self, dev_ext_info: T) -> Self
@@ -383,11 +1185,11 @@ impl<'a> VKDeviceRequirments<'a> {
                         .queue_supports_surface(*physical_device, queue_prop.0 as u32)
                         .unwrap_or(false);
             }
-            if let Some(queue_index) = checked_queue.as_mut() {
-                if suported {
-                    // set supported queue_index to be passed back
-                    **queue_index = queue_prop.0 as u32;
-                }
+            if let Some(queue_index) = checked_queue.as_mut()
+                && suported
+            {
+                // set supported queue_index to be passed back
+                **queue_index = queue_prop.0 as u32;
             }
             suported
         });
@@ -395,6 +1197,31 @@ impl<'a> VKDeviceRequirments<'a> {
         has_extentions && funcs_passes && queue_passes
     }
 
+    /// Required extentions plus the optional extentions supported by physical_device
+    pub fn enabled_extentions(
+        &self,
+        physical_device: &vk::PhysicalDevice,
+        instance: &Instance,
+    ) -> Vec<&'static CStr> {
+        let device_extentions = unsafe {
+            instance
+                .enumerate_device_extension_properties(*physical_device)
+                .unwrap_or_default()
+        };
+
+        let optional_extentions = self.optional_extentions.iter().filter(|optional| {
+            device_extentions.iter().any(|ext_prop| {
+                ext_prop.extension_name_as_c_str().unwrap_or_default() == **optional
+            })
+        });
+
+        self.required_extentions
+            .iter()
+            .chain(optional_extentions)
+            .copied()
+            .collect()
+    }
+
     pub fn get_requirments(&self) -> &[&'static CStr] {
         self.required_extentions.as_slice()
     }
@@ -411,6 +1238,7 @@ impl Default for VKDeviceRequirments<'_> {
     fn default() -> Self {
         Self {
             required_extentions: Vec::new(),
+            optional_extentions: Vec::new(),
             device_extended_info: Vec::new(),
             requirement_functions: Vec::new(),
             required_queue_flags: QueueFlags::empty(),
@@ -420,6 +1248,49 @@ impl Default for VKDeviceRequirments<'_> {
 
 // calculate a capability score for a physical device
 // score improvment should go down as importance of property goes down
+// workarounds from QUIRK_RULES for the device, adjusted by the user's overrides
+fn device_quirks(properties: &vk::PhysicalDeviceProperties, overrides: QuirkOverrides) -> Quirks {
+    let (matched, reasons) = matching_quirks(
+        QUIRK_RULES,
+        properties.vendor_id,
+        properties.device_id,
+        properties.driver_version,
+    );
+    for reason in reasons {
+        info!("VK Driver Quirk: {reason}");
+    }
+
+    let quirks = overrides.apply(matched);
+    if quirks != matched {
+        info!("VK Driver Quirks Overridden: {:?}", quirks.names());
+    }
+    quirks
+}
+
+/// Queue family uploads should use instead of graphics_family, None when there isn't a separate one
+/// families with only transfer are the copy engines, transfer with compute is the next best thing
+pub fn transfer_queue_family(
+    queue_families: &[vk::QueueFamilyProperties],
+    graphics_family: u32,
+) -> Option<u32> {
+    let candidates = || {
+        queue_families
+            .iter()
+            .enumerate()
+            .filter(move |(index, family)| {
+                *index as u32 != graphics_family
+                    && family.queue_count > 0
+                    && family.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                    && !family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+            })
+    };
+
+    candidates()
+        .find(|(_, family)| !family.queue_flags.contains(vk::QueueFlags::COMPUTE))
+        .or_else(|| candidates().next())
+        .map(|(index, _)| index as u32)
+}
+
 fn score_physical_device(physical_device: &vk::PhysicalDevice, instance: &Instance) -> u64 {
     let mut score: u64 = 0;
     let device_properties = unsafe { instance.get_physical_device_properties(*physical_device) };
@@ -481,6 +1352,111 @@ fn score_physical_device(physical_device: &vk::PhysicalDevice, instance: &Instan
 }
 
 // get device memory in MiB
+/// Buffer or image that was meant for vram
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DemotedHandle {
+    Buffer(vk::Buffer),
+    Image(vk::Image),
+}
+
+/// A gpu only resource that was given host visible memory because vram ran out
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DemotedResource {
+    pub handle: DemotedHandle,
+    /// allocation name it was created with
+    pub name: String,
+    pub size: u64,
+}
+
+/// Limits ray tracing has to respect, all zero without ray tracing support
+/// the shader group members stay zero without VK_KHR_ray_tracing_pipeline
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RayTracingProperties {
+    pub shader_group_handle_size: u32,
+    pub shader_group_handle_alignment: u32,
+    pub shader_group_base_alignment: u32,
+    pub max_ray_recursion_depth: u32,
+    pub min_scratch_offset_alignment: u32,
+}
+
+impl RayTracingProperties {
+    pub fn query(
+        instance: &Instance,
+        p_device: vk::PhysicalDevice,
+        ray_tracing_pipeline: bool,
+    ) -> Self {
+        let mut pipeline_properties = vk::PhysicalDeviceRayTracingPipelinePropertiesKHR::default();
+        let mut acceleration_properties =
+            vk::PhysicalDeviceAccelerationStructurePropertiesKHR::default();
+        let mut properties_two =
+            vk::PhysicalDeviceProperties2::default().push_next(&mut acceleration_properties);
+        if ray_tracing_pipeline {
+            properties_two = properties_two.push_next(&mut pipeline_properties);
+        }
+        unsafe { instance.get_physical_device_properties2(p_device, &mut properties_two) };
+        Self {
+            shader_group_handle_size: pipeline_properties.shader_group_handle_size,
+            shader_group_handle_alignment: pipeline_properties.shader_group_handle_alignment,
+            shader_group_base_alignment: pipeline_properties.shader_group_base_alignment,
+            max_ray_recursion_depth: pipeline_properties.max_ray_recursion_depth,
+            min_scratch_offset_alignment: acceleration_properties
+                .min_acceleration_structure_scratch_offset_alignment,
+        }
+    }
+}
+
+/// How much an allocation should stay in device memory under vram pressure
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemoryPriority {
+    /// first to be paged out, e.g. streaming mips that can be reloaded
+    Low,
+    #[default]
+    Normal,
+    /// render targets, paging them out costs every frame
+    High,
+}
+
+impl MemoryPriority {
+    /// VK_EXT_memory_priority value, 0.5 is what allocations get without one
+    pub fn value(self) -> f32 {
+        match self {
+            MemoryPriority::Low => 0.0,
+            MemoryPriority::Normal => 0.5,
+            MemoryPriority::High => 1.0,
+        }
+    }
+
+    /// Priority an image should get from how it is used
+    pub fn for_image_usage(usage: vk::ImageUsageFlags) -> Self {
+        if usage.intersects(
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        ) {
+            MemoryPriority::High
+        } else {
+            MemoryPriority::Normal
+        }
+    }
+}
+
+/// Device local heaps bigger than this that the cpu can map mean resizable BAR is on
+/// without it only a 256 MiB window is mappable
+pub const RESIZABLE_BAR_MIN_HEAP: u64 = 256 * 1024 * 1024;
+
+/// True when a device local heap larger than RESIZABLE_BAR_MIN_HEAP has host visible memory
+pub fn has_resizable_bar(memory_properties: &vk::PhysicalDeviceMemoryProperties) -> bool {
+    let heaps = memory_properties.memory_heaps_as_slice();
+    memory_properties
+        .memory_types_as_slice()
+        .iter()
+        .any(|memory_type| {
+            let heap = heaps[memory_type.heap_index as usize];
+            memory_type.property_flags.contains(
+                vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::HOST_VISIBLE,
+            ) && heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL)
+                && heap.size > RESIZABLE_BAR_MIN_HEAP
+        })
+}
+
 pub fn physical_device_memory_size(
     physical_device: &vk::PhysicalDevice,
     instance: &Instance,
@@ -499,3 +1475,101 @@ pub fn physical_device_memory_size(
             }
         })
 }
+
+#[test]
+fn pick_sample_count_test() {
+    let supported = vk::SampleCountFlags::TYPE_1
+        | vk::SampleCountFlags::TYPE_2
+        | vk::SampleCountFlags::TYPE_4
+        | vk::SampleCountFlags::TYPE_8;
+
+    assert_eq!(
+        pick_sample_count(supported, vk::SampleCountFlags::TYPE_4),
+        vk::SampleCountFlags::TYPE_4
+    );
+    // clamped to the highest supported
+    assert_eq!(
+        pick_sample_count(supported, vk::SampleCountFlags::TYPE_64),
+        vk::SampleCountFlags::TYPE_8
+    );
+    assert_eq!(
+        pick_sample_count(vk::SampleCountFlags::TYPE_1, vk::SampleCountFlags::TYPE_8),
+        vk::SampleCountFlags::TYPE_1
+    );
+}
+
+#[test]
+fn has_resizable_bar_test() {
+    let mut memory_properties = vk::PhysicalDeviceMemoryProperties {
+        memory_heap_count: 2,
+        memory_type_count: 2,
+        ..Default::default()
+    };
+    memory_properties.memory_heaps[0] = vk::MemoryHeap {
+        size: 8 * 1024 * 1024 * 1024,
+        flags: vk::MemoryHeapFlags::DEVICE_LOCAL,
+    };
+    memory_properties.memory_heaps[1] = vk::MemoryHeap {
+        size: RESIZABLE_BAR_MIN_HEAP,
+        flags: vk::MemoryHeapFlags::DEVICE_LOCAL,
+    };
+    memory_properties.memory_types[0] = vk::MemoryType {
+        property_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        heap_index: 0,
+    };
+    // the usual 256 MiB window
+    memory_properties.memory_types[1] = vk::MemoryType {
+        property_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL
+            | vk::MemoryPropertyFlags::HOST_VISIBLE
+            | vk::MemoryPropertyFlags::HOST_COHERENT,
+        heap_index: 1,
+    };
+    assert!(!has_resizable_bar(&memory_properties));
+
+    memory_properties.memory_types[1].heap_index = 0;
+    assert!(has_resizable_bar(&memory_properties));
+}
+
+#[test]
+fn memory_priority_test() {
+    assert_eq!(
+        MemoryPriority::for_image_usage(
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC
+        ),
+        MemoryPriority::High
+    );
+    assert_eq!(
+        MemoryPriority::for_image_usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT),
+        MemoryPriority::High
+    );
+    assert_eq!(
+        MemoryPriority::for_image_usage(
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST
+        ),
+        MemoryPriority::Normal
+    );
+    assert!(MemoryPriority::Low.value() < MemoryPriority::default().value());
+}
+
+#[test]
+fn transfer_queue_family_test() {
+    let family = |queue_flags| vk::QueueFamilyProperties {
+        queue_flags,
+        queue_count: 1,
+        ..Default::default()
+    };
+    let graphics =
+        family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER);
+    let compute = family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER);
+    let transfer = family(vk::QueueFlags::TRANSFER);
+
+    // copy engine beats async compute
+    assert_eq!(
+        transfer_queue_family(&[graphics, compute, transfer], 0),
+        Some(2)
+    );
+    assert_eq!(transfer_queue_family(&[graphics, compute], 0), Some(1));
+    // a single do everything family has nothing to spare
+    assert_eq!(transfer_queue_family(&[graphics], 0), None);
+    assert_eq!(transfer_queue_family(&[graphics, graphics], 0), None);
+}
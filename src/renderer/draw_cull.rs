@@ -0,0 +1,786 @@
+use ash::vk;
+use glam::{Mat4, Vec3, Vec4};
+use gpu_allocator::MemoryLocation;
+use log::warn;
+use std::collections::HashMap;
+use std::error;
+
+use crate::camera::DepthConvention;
+use crate::math::{Aabb, Frustum};
+use crate::renderer::MeshInstance;
+use crate::renderer::buffer::VKBuffer;
+use crate::renderer::compute::{VKComputePipeline, cmd_compute_barrier, group_count};
+use crate::renderer::device::VKDevice;
+use crate::renderer::indirect::VKIndirectBuffer;
+use crate::renderer::material::{DEFAULT_MATERIAL, MaterialId};
+use crate::renderer::mesh::{MeshId, VKMesh};
+use crate::renderer::occlusion::{CullDraw, VKOcclusionCuller};
+use crate::renderer::scan::{VKScan, scratch_len};
+use crate::renderer::shader::VKShaderLoader;
+use crate::renderer::sort::VKGpuSort;
+
+/// Threads per workgroup, matches numthreads in draw_cull.slang
+pub const DRAW_CULL_GROUP_SIZE: u32 = 64;
+
+/// Batches a culler can sort, the batch takes the top 16 bits of the sort key
+pub const MAX_DRAW_BATCHES: usize = 1 << 16;
+
+// depth of hidden objects in draw_sort_key, matches draw_cull.slang
+const HIDDEN_DEPTH: u32 = 0xFFFF;
+
+/// What the cull pass sorts objects by, their batch then how far in front of the camera they are
+/// None for culled objects, which sort after every visible one of their batch
+pub fn draw_sort_key(batch: u32, depth: Option<f32>) -> u32 {
+    // the float bits of a positive depth sort like the depth
+    let depth = depth.map_or(HIDDEN_DEPTH, |depth| {
+        (depth.max(0.0).to_bits() >> 15).min(HIDDEN_DEPTH - 1)
+    });
+    batch << 16 | depth
+}
+
+/// An object for VKDrawCuller, one indexed draw of instance_count instances
+/// matches CullObject in draw_cull.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CullObject {
+    /// world space bounds of every instance together
+    pub min: Vec3,
+    pub index_count: u32,
+    pub max: Vec3,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    /// objects with no instances are skipped
+    pub instance_count: u32,
+    /// passed to the draw as first_instance, shaders find the object's data with it
+    pub object: u32,
+    /// which of set_batched_objects' batches the command goes into
+    pub batch: u32,
+}
+
+impl CullObject {
+    /// Draws command's indices, command.first_instance is replaced by object
+    pub fn new(bounds: Aabb, command: vk::DrawIndexedIndirectCommand, object: u32) -> Self {
+        Self {
+            min: bounds.min,
+            index_count: command.index_count,
+            max: bounds.max,
+            first_index: command.first_index,
+            vertex_offset: command.vertex_offset,
+            instance_count: command.instance_count,
+            object,
+            batch: 0,
+        }
+    }
+
+    /// Puts the object's command in batch instead of the first one
+    pub fn batch(mut self, batch: u32) -> Self {
+        self.batch = batch;
+        self
+    }
+
+    /// The command the cull pass writes when the object is visible
+    pub fn command(&self) -> vk::DrawIndexedIndirectCommand {
+        vk::DrawIndexedIndirectCommand {
+            index_count: self.index_count,
+            instance_count: self.instance_count,
+            first_index: self.first_index,
+            vertex_offset: self.vertex_offset,
+            first_instance: self.object,
+        }
+    }
+
+    /// What the cull pass decides, for checking it on the cpu
+    pub fn visible(&self, frustum: &Frustum) -> bool {
+        self.instance_count > 0 && frustum.intersects_aabb(&Aabb::new(self.min, self.max))
+    }
+}
+
+// matches DrawCullPass in draw_cull.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct DrawCullPass {
+    planes: [Vec4; 6],
+    depth_row: Vec4,
+    object_count: u32,
+    padding: [u32; 3],
+}
+
+impl DrawCullPass {
+    fn new(view_projection: Mat4, object_count: u32) -> Self {
+        let frustum = Frustum::from_view_projection(view_projection);
+        Self {
+            planes: frustum
+                .planes
+                .map(|plane| plane.normal.extend(plane.distance)),
+            depth_row: view_projection.row(3),
+            object_count,
+            padding: [0; 3],
+        }
+    }
+}
+
+/// Frustum culls objects on the gpu and compacts the visible ones into a multi draw indirect buffer
+/// the cpu cost stays the same however many objects there are, meant for large scenes
+/// visible objects are sorted front to back within their batch and scanned into its slots
+/// every object of a batch is drawn from the same bound vertex and index buffers, e.g. one mesh's
+/// instances or meshes merged into shared buffers, with a pipeline that reads per object data at
+/// SV_StartInstanceLocation
+/// Example Use:
+/// ```ignore
+/// let mut culler = VKDrawCuller::new(vk_device, &mut renderer.vulkan_shader_loader, 100_000)?;
+/// culler.set_objects(&objects);
+/// // each frame, outside rendering
+/// unsafe { culler.record_cull(vk_device, cmd_buffer, camera.view_projection) };
+/// // inside rendering with the shared buffers bound
+/// unsafe { culler.cmd_draw(vk_device, cmd_buffer) };
+/// ```
+/// VKRenderer::set_gpu_culling draws the scene this way, a batch per mesh and material
+pub struct VKDrawCuller<'a> {
+    pub pipeline: VKComputePipeline<'a>,
+    /// host visible CullObjects written by set_objects
+    pub object_buffer: VKBuffer,
+    /// visible objects' commands, packed at the front
+    pub commands: VKIndirectBuffer,
+    /// number of commands the last cull wrote into each batch
+    pub count_buffer: VKBuffer,
+    /// host visible slot each batch's commands start at, then where the last one ends
+    pub batch_buffer: VKBuffer,
+    /// visible flag of each object, scanned into how many visible objects come before it
+    pub position_buffer: VKBuffer,
+    pub scan: VKScan<'a>,
+    pub scan_scratch: VKBuffer,
+    /// draw_sort_key of each object and the object it belongs to, sorted by key
+    pub key_buffer: VKBuffer,
+    pub value_buffer: VKBuffer,
+    pub sort: VKGpuSort<'a>,
+    pub max_objects: u32,
+    pub object_count: u32,
+    pub batch_count: u32,
+    set: vk::DescriptorSet,
+    scan_set: vk::DescriptorSet,
+    sort_set: vk::DescriptorSet,
+}
+
+impl VKDrawCuller<'_> {
+    pub fn new(
+        vk_device: &mut VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        max_objects: u32,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let pipeline = VKComputePipeline::new::<DrawCullPass>(
+            vk_device,
+            vk_shader_loader,
+            "shaders/draw_cull.spv",
+            &[c"cull", c"flag", c"compact"],
+            7,
+            1,
+        )?;
+        let scan = VKScan::new(vk_device, vk_shader_loader, 1)?;
+        let sort = VKGpuSort::new(vk_device, vk_shader_loader, 1)?;
+
+        let max_objects = max_objects.max(1);
+        let object_buffer = VKBuffer::new(
+            vk_device,
+            (max_objects as usize * size_of::<CullObject>()) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::CpuToGpu,
+            "Cull Objects",
+        )?;
+        let commands = VKIndirectBuffer::new(vk_device, max_objects, MemoryLocation::GpuOnly)?;
+        // there are never more batches than objects
+        let count_buffer = VKBuffer::new(
+            vk_device,
+            (max_objects as usize * size_of::<u32>()) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+            MemoryLocation::GpuOnly,
+            "Visible Object Counts",
+        )?;
+        let batch_buffer = VKBuffer::new(
+            vk_device,
+            ((max_objects as usize + 1) * size_of::<u32>()) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::CpuToGpu,
+            "Draw Batches",
+        )?;
+        // one past the objects so the last one's end can be read
+        let position_buffer = VKBuffer::new(
+            vk_device,
+            ((max_objects as usize + 1) * size_of::<u32>()) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::GpuOnly,
+            "Visible Object Positions",
+        )?;
+        let scan_scratch = VKBuffer::new(
+            vk_device,
+            (scratch_len(max_objects + 1) as usize * size_of::<u32>()) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::GpuOnly,
+            "Visible Object Scan Scratch",
+        )?;
+        let key_buffer = VKBuffer::new(
+            vk_device,
+            (max_objects as usize * size_of::<u32>()) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::GpuOnly,
+            "Cull Sort Keys",
+        )?;
+        let value_buffer = VKBuffer::new(
+            vk_device,
+            (max_objects as usize * size_of::<u32>()) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::GpuOnly,
+            "Cull Sort Values",
+        )?;
+        let set = pipeline.allocate_set(
+            vk_device,
+            &[
+                object_buffer.buffer,
+                commands.buffer.buffer,
+                count_buffer.buffer,
+                batch_buffer.buffer,
+                position_buffer.buffer,
+                key_buffer.buffer,
+                value_buffer.buffer,
+            ],
+        )?;
+        let scan_set = scan.bind_buffers(vk_device, position_buffer.buffer, scan_scratch.buffer)?;
+        let sort_set = sort.bind_buffers(vk_device, key_buffer.buffer, value_buffer.buffer)?;
+
+        Ok(Self {
+            pipeline,
+            object_buffer,
+            commands,
+            count_buffer,
+            batch_buffer,
+            position_buffer,
+            scan,
+            scan_scratch,
+            key_buffer,
+            value_buffer,
+            sort,
+            max_objects,
+            object_count: 0,
+            batch_count: 0,
+            set,
+            scan_set,
+            sort_set,
+        })
+    }
+
+    /// Objects to cull from now on, all in one batch, only the first max_objects are kept
+    /// the gpu must be done with the previous record_cull
+    pub fn set_objects(&mut self, objects: &[CullObject]) {
+        self.set_batched_objects(objects, &[0]);
+    }
+
+    /// Objects to cull from now on, sorted by batch, batch_firsts is the first object of each
+    /// batch which is also the slot its commands start at, past MAX_DRAW_BATCHES are dropped
+    /// the gpu must be done with the previous record_cull
+    pub fn set_batched_objects(&mut self, objects: &[CullObject], batch_firsts: &[u32]) {
+        if objects.len() > self.max_objects as usize {
+            warn!(
+                "{} Objects Given to the Draw Culler, Only {} Fit",
+                objects.len(),
+                self.max_objects
+            );
+        }
+        let mut objects = &objects[..objects.len().min(self.max_objects as usize)];
+        if let Some(dropped) = batch_firsts.get(MAX_DRAW_BATCHES) {
+            warn!(
+                "{} Batches Given to the Draw Culler, Only {MAX_DRAW_BATCHES} Are Drawn",
+                batch_firsts.len()
+            );
+            objects = &objects[..objects.len().min(*dropped as usize)];
+        }
+        let batch_firsts = &batch_firsts[..batch_firsts.len().min(MAX_DRAW_BATCHES)];
+        // the last batch ends with the objects
+        let batch_ends = [objects.len() as u32];
+        if self.object_buffer.write(0, objects).is_err()
+            || self.batch_buffer.write(0, batch_firsts).is_err()
+            || self
+                .batch_buffer
+                .write(size_of_val(batch_firsts), &batch_ends)
+                .is_err()
+        {
+            warn!("Failed to Copy Objects to the Draw Culler");
+            self.object_count = 0;
+            self.batch_count = 0;
+            self.commands.set_len(0);
+            return;
+        }
+        self.object_count = objects.len() as u32;
+        self.batch_count = batch_firsts.len() as u32;
+        self.commands.set_len(self.object_count);
+    }
+
+    /// Writes the commands of the objects inside the view projection's frustum and their count
+    /// objects are keyed and sorted, the visible ones flagged, the flags scanned into slots and
+    /// then the commands compacted
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering
+    pub unsafe fn record_cull(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        view_projection: Mat4,
+    ) {
+        let pass = DrawCullPass::new(view_projection, self.object_count);
+
+        // last frame's draws have to be done with the commands before they are overwritten
+        let commands_free = [vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::DRAW_INDIRECT)
+            .dst_stage_mask(vk::PipelineStageFlags2::CLEAR)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)];
+        let cleared = [vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::CLEAR)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+            .dst_access_mask(
+                vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            )];
+        let culled = [vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+            .src_access_mask(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::DRAW_INDIRECT)
+            .dst_access_mask(vk::AccessFlags2::INDIRECT_COMMAND_READ)];
+
+        unsafe {
+            vk_device.device.cmd_pipeline_barrier2(
+                cmd_buffer,
+                &vk::DependencyInfo::default().memory_barriers(&commands_free),
+            );
+            // zeroed commands draw nothing, so without a count every slot can be drawn
+            vk_device.device.cmd_fill_buffer(
+                cmd_buffer,
+                self.commands.buffer.buffer,
+                0,
+                vk::WHOLE_SIZE,
+                0,
+            );
+            vk_device.device.cmd_pipeline_barrier2(
+                cmd_buffer,
+                &vk::DependencyInfo::default().memory_barriers(&cleared),
+            );
+
+            self.pipeline.cmd_dispatch(
+                vk_device,
+                cmd_buffer,
+                0,
+                self.set,
+                &pass,
+                group_count(self.object_count, DRAW_CULL_GROUP_SIZE),
+            );
+            cmd_compute_barrier(vk_device, cmd_buffer);
+            self.sort
+                .record(vk_device, cmd_buffer, self.sort_set, self.object_count);
+            // one past the objects so positions ends with the visible total
+            self.pipeline.cmd_dispatch(
+                vk_device,
+                cmd_buffer,
+                1,
+                self.set,
+                &pass,
+                group_count(self.object_count + 1, DRAW_CULL_GROUP_SIZE),
+            );
+            cmd_compute_barrier(vk_device, cmd_buffer);
+            self.scan
+                .record(vk_device, cmd_buffer, self.scan_set, self.object_count + 1);
+            self.pipeline.cmd_dispatch(
+                vk_device,
+                cmd_buffer,
+                2,
+                self.set,
+                &pass,
+                group_count(self.object_count, DRAW_CULL_GROUP_SIZE),
+            );
+            vk_device.device.cmd_pipeline_barrier2(
+                cmd_buffer,
+                &vk::DependencyInfo::default().memory_barriers(&culled),
+            );
+        }
+    }
+
+    /// Draws what record_cull kept in a single call with VK_KHR_draw_indirect_count
+    /// otherwise every slot is drawn and the culled ones are empty
+    /// # Safety
+    /// cmd_buffer must be inside rendering after record_cull, with the objects' buffers bound
+    pub unsafe fn cmd_draw(&self, vk_device: &VKDevice, cmd_buffer: vk::CommandBuffer) {
+        unsafe { self.cmd_draw_batch(vk_device, cmd_buffer, 0, 0, self.object_count) };
+    }
+
+    /// cmd_draw for one of set_batched_objects' batches, its slots are first..first + len
+    /// # Safety
+    /// same as cmd_draw, with the batch's buffers and pipeline bound
+    pub unsafe fn cmd_draw_batch(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        batch: u32,
+        first: u32,
+        len: u32,
+    ) {
+        unsafe {
+            self.commands.cmd_draw_count_range(
+                vk_device,
+                cmd_buffer,
+                first..first + len,
+                self.count_buffer.buffer,
+                (batch as usize * size_of::<u32>()) as u64,
+            )
+        };
+    }
+
+    /// # Safety
+    /// The gpu must not be using the culler
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            if let Err(error) = self.pipeline.free_set(vk_device, self.set) {
+                warn!("Failed to Free Draw Cull Set: {error}");
+            }
+            if let Err(error) = self.scan.pipeline.free_set(vk_device, self.scan_set) {
+                warn!("Failed to Free Draw Cull Scan Set: {error}");
+            }
+            if let Err(error) = self.sort.pipeline.free_set(vk_device, self.sort_set) {
+                warn!("Failed to Free Draw Cull Sort Set: {error}");
+            }
+            self.object_buffer.destroy(vk_device);
+            self.commands.destroy(vk_device);
+            self.count_buffer.destroy(vk_device);
+            self.batch_buffer.destroy(vk_device);
+            self.position_buffer.destroy(vk_device);
+            self.scan_scratch.destroy(vk_device);
+            self.key_buffer.destroy(vk_device);
+            self.value_buffer.destroy(vk_device);
+            self.scan.destroy(vk_device);
+            self.sort.destroy(vk_device);
+            self.pipeline.destroy(vk_device);
+        }
+    }
+}
+
+/// Objects drawn with one cmd_draw_batch, they share a mesh and a material
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DrawBatch {
+    pub mesh: MeshId,
+    pub material: MaterialId,
+    /// where the batch's slots start, also where its objects start in the culler
+    pub first: u32,
+    pub len: u32,
+}
+
+/// Groups objects by mesh and material in the order each pair first shows up
+/// returns the batches and the objects in batch order
+pub fn batch_objects(
+    objects: impl IntoIterator<Item = (u32, MeshId, MaterialId)>,
+) -> (Vec<DrawBatch>, Vec<u32>) {
+    let mut groups: HashMap<(MeshId, MaterialId), usize> = HashMap::new();
+    let mut grouped: Vec<((MeshId, MaterialId), Vec<u32>)> = Vec::new();
+    for (object, mesh, material) in objects {
+        let group = *groups.entry((mesh, material)).or_insert_with(|| {
+            grouped.push(((mesh, material), Vec::new()));
+            grouped.len() - 1
+        });
+        grouped[group].1.push(object);
+    }
+
+    let mut batches = Vec::with_capacity(grouped.len());
+    let mut order = Vec::new();
+    for ((mesh, material), group) in grouped {
+        batches.push(DrawBatch {
+            mesh,
+            material,
+            first: order.len() as u32,
+            len: group.len() as u32,
+        });
+        order.extend(group);
+    }
+    (batches, order)
+}
+
+/// The scene pass culled and drawn through a VKDrawCuller, see VKRenderer::set_gpu_culling
+/// instances of meshes without an index buffer are left for the cpu to draw, each predicated on
+/// an occlusion test against the depth of the last frame that used the same frame in flight
+pub struct VKGpuCulling<'a> {
+    /// one per frame in flight, each is rewritten when its frame is prepared
+    pub cullers: Vec<VKDrawCuller<'a>>,
+    /// one per frame in flight, tests cpu_objects in the same order
+    pub occlusion: Vec<VKOcclusionCuller<'a>>,
+    /// batches of the last prepare, in slot order
+    pub batches: Vec<DrawBatch>,
+    /// instances of the last prepare the cpu draws
+    pub cpu_objects: Vec<u32>,
+    // camera each frame in flight's next pyramid is built from, None when its frame builds none
+    pyramid_cameras: Vec<Option<Mat4>>,
+}
+
+impl VKGpuCulling<'_> {
+    /// extent is the size of the scene's depth, see prepare
+    pub fn new(
+        vk_device: &mut VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        frames_in_flight: usize,
+        max_objects: u32,
+        extent: vk::Extent2D,
+        depth_convention: DepthConvention,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let mut gpu_culling = Self {
+            cullers: Vec::with_capacity(frames_in_flight),
+            occlusion: Vec::with_capacity(frames_in_flight),
+            batches: Vec::new(),
+            cpu_objects: Vec::new(),
+            pyramid_cameras: vec![None; frames_in_flight],
+        };
+        for _ in 0..frames_in_flight {
+            let created = VKDrawCuller::new(vk_device, vk_shader_loader, max_objects)
+                .map(|culler| gpu_culling.cullers.push(culler))
+                .and_then(|_| {
+                    VKOcclusionCuller::new(
+                        vk_device,
+                        vk_shader_loader,
+                        extent,
+                        max_objects,
+                        depth_convention,
+                    )
+                })
+                .map(|occlusion| gpu_culling.occlusion.push(occlusion));
+            if let Err(error) = created {
+                unsafe { gpu_culling.destroy(vk_device) };
+                return Err(error);
+            }
+        }
+        Ok(gpu_culling)
+    }
+
+    /// Whether frame_in_flight's scene pass is followed by building its occlusion pyramid
+    pub fn builds_pyramid(&self, frame_in_flight: usize) -> bool {
+        self.pyramid_cameras[frame_in_flight].is_some()
+    }
+
+    /// Batches instances for frame_in_flight's culler, the object of an instance is its index
+    /// unknown materials are drawn with the default like the cpu path does
+    /// pyramid is the extent of the scene's depth and the camera it's drawn from, when the frame
+    /// leaves depth for the next occlusion test, without it cpu_objects aren't occlusion tested
+    /// the gpu must be done with frame_in_flight
+    pub fn prepare(
+        &mut self,
+        vk_device: &mut VKDevice,
+        frame_in_flight: usize,
+        instances: &[MeshInstance],
+        meshes: &[VKMesh],
+        material_count: usize,
+        pyramid: Option<(vk::Extent2D, Mat4)>,
+    ) -> Result<(), vk::Result> {
+        self.cpu_objects.clear();
+        let mut gpu_objects = Vec::with_capacity(instances.len());
+        for (instance, object) in instances.iter().zip(0..) {
+            let Some(mesh) = meshes.get(instance.mesh) else {
+                continue;
+            };
+            if !mesh.is_indexed() {
+                self.cpu_objects.push(object);
+                continue;
+            }
+            let material = if instance.material < material_count {
+                instance.material
+            } else {
+                DEFAULT_MATERIAL
+            };
+            gpu_objects.push((object, instance.mesh, material));
+        }
+
+        let (mut batches, order) = batch_objects(gpu_objects);
+        if batches.len() > MAX_DRAW_BATCHES {
+            warn!(
+                "{} Mesh And Material Pairs, Only The First {MAX_DRAW_BATCHES} Are Culled On The GPU",
+                batches.len()
+            );
+            batches.truncate(MAX_DRAW_BATCHES);
+        }
+        let mut objects = Vec::with_capacity(order.len());
+        for (batch, draw_batch) in (0..).zip(&batches) {
+            let mesh = &meshes[draw_batch.mesh];
+            for &object in &order[draw_batch.first as usize..][..draw_batch.len as usize] {
+                let instance = &instances[object as usize];
+                objects.push(
+                    CullObject::new(
+                        mesh.bounds.transformed(&instance.transform),
+                        mesh.indexed_indirect_command(1, object),
+                        object,
+                    )
+                    .batch(batch),
+                );
+            }
+        }
+        let batch_firsts: Vec<u32> = batches.iter().map(|batch| batch.first).collect();
+        self.cullers[frame_in_flight].set_batched_objects(&objects, &batch_firsts);
+        self.batches = batches;
+
+        let occlusion = &mut self.occlusion[frame_in_flight];
+        let Some((extent, view_projection)) = pyramid else {
+            // a capture's camera can see what the frame's couldn't, so nothing is tested
+            occlusion.set_draws(&[]);
+            self.pyramid_cameras[frame_in_flight] = None;
+            return Ok(());
+        };
+        if occlusion.extent != extent {
+            unsafe { occlusion.resize(vk_device, extent)? };
+            self.pyramid_cameras[frame_in_flight] = None;
+        }
+        // the last pyramid of this frame in flight is the one the cull pass reads
+        occlusion.pyramid_view_projection =
+            self.pyramid_cameras[frame_in_flight].replace(view_projection);
+        let draws: Vec<CullDraw> = self
+            .cpu_objects
+            .iter()
+            .map(|object| {
+                let instance = &instances[*object as usize];
+                let mesh = &meshes[instance.mesh];
+                CullDraw::new(
+                    mesh.bounds.transformed(&instance.transform),
+                    mesh.vertex_count,
+                    0,
+                )
+            })
+            .collect();
+        occlusion.set_draws(&draws);
+        Ok(())
+    }
+
+    /// # Safety
+    /// The gpu must not be using any of the cullers
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        for culler in &mut self.cullers {
+            unsafe { culler.destroy(vk_device) };
+        }
+        for occlusion in &mut self.occlusion {
+            unsafe { occlusion.destroy(vk_device) };
+        }
+        self.cullers.clear();
+        self.occlusion.clear();
+    }
+}
+
+#[test]
+fn cull_object_test() {
+    // the shader reads these with std430 layout
+    assert_eq!(size_of::<CullObject>(), 48);
+    assert_eq!(size_of::<DrawCullPass>(), 128);
+
+    let command = vk::DrawIndexedIndirectCommand {
+        index_count: 36,
+        instance_count: 2,
+        first_index: 6,
+        vertex_offset: -3,
+        first_instance: 99,
+    };
+    let inside = CullObject::new(
+        Aabb::new(Vec3::new(-1.0, -1.0, -6.0), Vec3::new(1.0, 1.0, -4.0)),
+        command,
+        7,
+    );
+    let written = inside.batch(2).command();
+    assert_eq!(
+        (
+            written.index_count,
+            written.first_instance,
+            written.vertex_offset
+        ),
+        (36, 7, -3)
+    );
+
+    let projection = Mat4::perspective_infinite_reverse_rh(90f32.to_radians(), 1.0, 0.1);
+    let frustum = Frustum::from_view_projection(projection);
+    assert!(inside.visible(&frustum));
+    let behind = CullObject {
+        min: Vec3::new(-1.0, -1.0, 4.0),
+        max: Vec3::new(1.0, 1.0, 6.0),
+        ..inside
+    };
+    assert!(!behind.visible(&frustum));
+    let empty = CullObject {
+        instance_count: 0,
+        ..inside
+    };
+    assert!(!empty.visible(&frustum));
+
+    // planes go to the shader as normal and distance
+    let pass = DrawCullPass::new(projection, 1);
+    assert_eq!(pass.planes[0].truncate(), frustum.planes[0].normal);
+    assert_eq!(pass.planes[0].w, frustum.planes[0].distance);
+}
+
+#[test]
+fn batch_objects_test() {
+    let (batches, order) = batch_objects([(0, 1, 0), (1, 2, 0), (2, 1, 0), (3, 1, 3), (4, 2, 0)]);
+    assert_eq!(order, vec![0, 2, 1, 4, 3]);
+    assert_eq!(
+        batches,
+        vec![
+            DrawBatch {
+                mesh: 1,
+                material: 0,
+                first: 0,
+                len: 2
+            },
+            DrawBatch {
+                mesh: 2,
+                material: 0,
+                first: 2,
+                len: 2
+            },
+            DrawBatch {
+                mesh: 1,
+                material: 3,
+                first: 4,
+                len: 1
+            },
+        ]
+    );
+    assert_eq!(batch_objects([]), (Vec::new(), Vec::new()));
+}
+
+#[test]
+fn compact_test() {
+    // keys, sorts, flags, scans and compacts the same way draw_cull.slang does
+    let depths = [Some(9.0), None, Some(2.0), Some(4.0), None, None, Some(0.5)];
+    let batch_firsts = [0, 3, 5, depths.len() as u32];
+    let batches = [0, 0, 0, 1, 1, 2, 2];
+
+    let mut keyed: Vec<(u32, usize)> = (0..depths.len())
+        .map(|object| (draw_sort_key(batches[object], depths[object]), object))
+        .collect();
+    keyed.sort();
+
+    let mut positions: Vec<u32> = keyed
+        .iter()
+        .map(|(key, _)| (key & 0xFFFF != HIDDEN_DEPTH) as u32)
+        .collect();
+    positions.push(0);
+    let mut total = 0;
+    for position in &mut positions {
+        (*position, total) = (total, total + *position);
+    }
+
+    let mut slots = [None; 7];
+    let mut counts = [0; 3];
+    for (index, (key, object)) in keyed.iter().enumerate() {
+        let batch = (key >> 16) as usize;
+        let first = batch_firsts[batch] as usize;
+        if index == first {
+            counts[batch] = positions[batch_firsts[batch + 1] as usize] - positions[index];
+        }
+        if positions[index + 1] != positions[index] {
+            slots[first + (positions[index] - positions[first]) as usize] = Some(*object);
+        }
+    }
+
+    // each batch's visible objects are packed at its start, nearest first
+    assert_eq!(counts, [2, 1, 1]);
+    assert_eq!(
+        slots,
+        [Some(2), Some(0), None, Some(3), None, Some(6), None]
+    );
+    assert!(draw_sort_key(0, Some(-1.0)) < draw_sort_key(0, Some(0.001)));
+    assert!(draw_sort_key(0, Some(f32::INFINITY)) < draw_sort_key(0, None));
+    assert!(draw_sort_key(0, None) < draw_sort_key(1, Some(0.0)));
+}
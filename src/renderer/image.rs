@@ -0,0 +1,197 @@
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+
+use crate::renderer::allocator::VKAllocation;
+use crate::renderer::device::VKDevice;
+use crate::renderer::render_graph::{Access, ImageHandle, RenderGraph};
+
+/// A 2d image with its memory and a view of it, plus the layout it was last left in
+/// the layout is only what this image's own transitions recorded, after a render graph has
+/// used it set_access tells it what the graph exported it as
+/// Example Use:
+/// ```ignore
+/// let mut image = VKImage::new(
+///     vk_device,
+///     extent,
+///     vk::Format::R8G8B8A8_UNORM,
+///     vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+///     vk::SampleCountFlags::TYPE_1,
+///     vk::ImageAspectFlags::COLOR,
+/// )?;
+/// unsafe { image.cmd_transition(vk_device, cmd_buffer, Access::TransferDst) };
+/// // copy into it
+/// unsafe { image.cmd_transition(vk_device, cmd_buffer, Access::FragmentSampled) };
+/// ```
+#[derive(Debug, Default)]
+pub struct VKImage {
+    pub image: vk::Image,
+    pub allocation: VKAllocation,
+    pub view: vk::ImageView,
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    pub aspect: vk::ImageAspectFlags,
+    pub samples: vk::SampleCountFlags,
+    /// None while the image is still in UNDEFINED
+    access: Option<Access>,
+}
+
+impl VKImage {
+    /// A gpu only image with optimal tiling, a single mip and layer, and a view of all of it
+    pub fn new(
+        vk_device: &mut VKDevice,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        samples: vk::SampleCountFlags,
+        aspect: vk::ImageAspectFlags,
+    ) -> Result<Self, vk::Result> {
+        let (image, allocation) = vk_device.create_image(
+            extent,
+            format,
+            vk::ImageTiling::OPTIMAL,
+            usage,
+            samples,
+            MemoryLocation::GpuOnly,
+        )?;
+        let view = match vk_device.create_image_view(image, format, aspect) {
+            Ok(view) => view,
+            Err(error) => {
+                unsafe { vk_device.destroy_image(image, allocation) };
+                return Err(error);
+            }
+        };
+
+        Ok(Self {
+            image,
+            allocation,
+            view,
+            extent,
+            format,
+            aspect,
+            samples,
+            access: None,
+        })
+    }
+
+    /// Null images come from Default and own nothing
+    pub fn is_null(&self) -> bool {
+        self.image == vk::Image::null()
+    }
+
+    pub fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange::default()
+            .aspect_mask(self.aspect)
+            .level_count(1)
+            .layer_count(1)
+    }
+
+    /// Layout the image was left in
+    pub fn layout(&self) -> vk::ImageLayout {
+        self.access
+            .map_or(vk::ImageLayout::UNDEFINED, Access::layout)
+    }
+
+    /// How the image was last used, None while it is still in UNDEFINED
+    pub fn access(&self) -> Option<Access> {
+        self.access
+    }
+
+    /// Records that something else, like a render graph export, left the image ready for access
+    pub fn set_access(&mut self, access: Option<Access>) {
+        self.access = access;
+    }
+
+    /// Barrier from the last access to access, the image is taken to be in access afterwards
+    /// the previous contents are kept unless the image is still in UNDEFINED
+    pub fn transition_barrier(&mut self, access: Access) -> vk::ImageMemoryBarrier2<'static> {
+        let (src_stage, src_access) = match self.access {
+            Some(previous) => (previous.stage(), previous.access()),
+            None => (vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE),
+        };
+        let barrier = vk::ImageMemoryBarrier2::default()
+            .old_layout(self.layout())
+            .new_layout(access.layout())
+            .src_stage_mask(src_stage)
+            .src_access_mask(src_access)
+            .dst_stage_mask(access.stage())
+            .dst_access_mask(access.access())
+            .image(self.image)
+            .subresource_range(self.subresource_range());
+        self.access = Some(access);
+        barrier
+    }
+
+    /// Records transition_barrier on its own
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering, the image must actually be in layout
+    pub unsafe fn cmd_transition(
+        &mut self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        access: Access,
+    ) {
+        let barriers = [self.transition_barrier(access)];
+        unsafe {
+            vk_device.device.cmd_pipeline_barrier2(
+                cmd_buffer,
+                &vk::DependencyInfo::default().image_memory_barriers(&barriers),
+            )
+        };
+    }
+
+    /// Adds the image to graph starting from its last access
+    pub fn import(&self, graph: &mut RenderGraph) -> ImageHandle {
+        let previous: &[Access] = match &self.access {
+            Some(access) => std::slice::from_ref(access),
+            None => &[],
+        };
+        graph.import_image(self.image, self.subresource_range(), previous)
+    }
+
+    /// # Safety
+    /// The gpu must not be using the image or its view
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        if self.is_null() {
+            return;
+        }
+        unsafe {
+            vk_device.device.destroy_image_view(self.view, None);
+            vk_device.destroy_image(self.image, std::mem::take(&mut self.allocation));
+        }
+        self.image = vk::Image::null();
+        self.view = vk::ImageView::null();
+        self.access = None;
+    }
+}
+
+#[test]
+fn image_transition_test() {
+    let mut image = VKImage {
+        aspect: vk::ImageAspectFlags::COLOR,
+        ..Default::default()
+    };
+    assert!(image.is_null());
+    assert_eq!(image.layout(), vk::ImageLayout::UNDEFINED);
+
+    let upload = image.transition_barrier(Access::TransferDst);
+    assert_eq!(upload.old_layout, vk::ImageLayout::UNDEFINED);
+    assert_eq!(upload.new_layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+    assert_eq!(upload.src_stage_mask, vk::PipelineStageFlags2::NONE);
+
+    // the copy has to finish before sampling
+    let sample = image.transition_barrier(Access::FragmentSampled);
+    assert_eq!(sample.old_layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+    assert_eq!(sample.src_access_mask, vk::AccessFlags2::TRANSFER_WRITE);
+    assert_eq!(
+        sample.dst_access_mask,
+        vk::AccessFlags2::SHADER_SAMPLED_READ
+    );
+    assert_eq!(image.layout(), vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    assert_eq!(
+        sample.subresource_range.aspect_mask,
+        vk::ImageAspectFlags::COLOR
+    );
+
+    image.set_access(None);
+    assert_eq!(image.layout(), vk::ImageLayout::UNDEFINED);
+}
@@ -0,0 +1,222 @@
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use log::warn;
+use std::ops::Range;
+
+use crate::renderer::buffer::VKBuffer;
+use crate::renderer::device::VKDevice;
+
+const INDEXED_STRIDE: u32 = size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+const STRIDE: u32 = size_of::<vk::DrawIndirectCommand>() as u32;
+
+/// vk::DrawIndexedIndirectCommands in a buffer the draw parameters are read from at draw time
+/// commands are either written once from the cpu and drawn every frame, or generated by a compute shader
+/// Example Use:
+/// ```ignore
+/// let mut commands = VKIndirectBuffer::new(vk_device, 1024, MemoryLocation::CpuToGpu)?;
+/// commands.set_commands(&[rock.indexed_indirect_command(rock_count, 0)]);
+/// // every frame, inside rendering with the rock's pipeline and buffers bound
+/// unsafe {
+///     rock.cmd_bind(vk_device, cmd_buffer);
+///     commands.cmd_draw(vk_device, cmd_buffer);
+/// }
+/// ```
+pub struct VKIndirectBuffer {
+    pub buffer: VKBuffer,
+    /// commands the buffer holds, fixed at creation
+    pub capacity: u32,
+    /// commands drawn by cmd_draw
+    pub len: u32,
+}
+
+impl VKIndirectBuffer {
+    /// CpuToGpu buffers are filled with set_commands, GpuOnly ones by shaders or transfers
+    pub fn new(
+        vk_device: &mut VKDevice,
+        capacity: u32,
+        location: MemoryLocation,
+    ) -> Result<Self, vk::Result> {
+        let capacity = capacity.max(1);
+        let buffer = VKBuffer::new(
+            vk_device,
+            (capacity * INDEXED_STRIDE) as u64,
+            vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            location,
+            "Indirect Draws",
+        )?;
+        Ok(Self {
+            buffer,
+            capacity,
+            len: 0,
+        })
+    }
+
+    /// Writes commands from the start of a host visible buffer, only the first capacity are kept
+    /// the gpu must be done with the previous commands
+    pub fn set_commands(&mut self, commands: &[vk::DrawIndexedIndirectCommand]) {
+        if commands.len() > self.capacity as usize {
+            warn!(
+                "{} Indirect Draws Given, Only {} Fit",
+                commands.len(),
+                self.capacity
+            );
+        }
+        let commands = &commands[..commands.len().min(self.capacity as usize)];
+        if self.buffer.write(0, commands).is_err() {
+            warn!("Failed to Copy Indirect Draws, Is the Buffer Host Visible?");
+            self.len = 0;
+            return;
+        }
+        self.len = commands.len() as u32;
+    }
+
+    /// For gpu written commands, how many cmd_draw runs
+    pub fn set_len(&mut self, len: u32) {
+        self.len = len.min(self.capacity);
+    }
+
+    /// Range of the buffer for a storage buffer descriptor
+    pub fn descriptor_buffer_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffer.buffer)
+            .range(vk::WHOLE_SIZE)
+    }
+
+    /// Draws the first len commands with the bound pipeline, vertex and index buffers
+    /// # Safety
+    /// cmd_buffer must be inside rendering, writes to the commands must be visible to DRAW_INDIRECT
+    pub unsafe fn cmd_draw(&self, vk_device: &VKDevice, cmd_buffer: vk::CommandBuffer) {
+        unsafe {
+            cmd_draw_indexed_indirect(vk_device, cmd_buffer, self.buffer.buffer, 0, self.len)
+        };
+    }
+
+    /// Draws as many commands as the u32 at count_offset in count_buffer says, up to len
+    /// needs VK_KHR_draw_indirect_count and multi draw indirect, otherwise all len are drawn
+    /// and the commands past the count have to be zeroed
+    /// # Safety
+    /// same as cmd_draw, count_buffer needs INDIRECT_BUFFER usage
+    pub unsafe fn cmd_draw_count(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        count_buffer: vk::Buffer,
+        count_offset: u64,
+    ) {
+        unsafe {
+            self.cmd_draw_count_range(
+                vk_device,
+                cmd_buffer,
+                0..self.len,
+                count_buffer,
+                count_offset,
+            )
+        };
+    }
+
+    /// cmd_draw_count for the commands in range, the count is of commands from range.start
+    /// # Safety
+    /// same as cmd_draw_count, range must be within capacity
+    pub unsafe fn cmd_draw_count_range(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        range: Range<u32>,
+        count_buffer: vk::Buffer,
+        count_offset: u64,
+    ) {
+        let offset = range.start as u64 * INDEXED_STRIDE as u64;
+        unsafe {
+            match &vk_device.draw_indirect_count {
+                Some(draw_indirect_count) if vk_device.multi_draw_indirect => draw_indirect_count
+                    .cmd_draw_indexed_indirect_count(
+                        cmd_buffer,
+                        self.buffer.buffer,
+                        offset,
+                        count_buffer,
+                        count_offset,
+                        range.len() as u32,
+                        INDEXED_STRIDE,
+                    ),
+                _ => cmd_draw_indexed_indirect(
+                    vk_device,
+                    cmd_buffer,
+                    self.buffer.buffer,
+                    offset,
+                    range.len() as u32,
+                ),
+            }
+        }
+    }
+
+    /// # Safety
+    /// The gpu must not be using the buffer
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe { self.buffer.destroy(vk_device) };
+    }
+}
+
+/// Runs draw_count tightly packed vk::DrawIndexedIndirectCommands starting at offset
+/// devices without multi draw indirect get one call per command
+/// # Safety
+/// cmd_buffer must be inside rendering with an index buffer bound
+pub unsafe fn cmd_draw_indexed_indirect(
+    vk_device: &VKDevice,
+    cmd_buffer: vk::CommandBuffer,
+    buffer: vk::Buffer,
+    offset: u64,
+    draw_count: u32,
+) {
+    unsafe {
+        if vk_device.multi_draw_indirect {
+            vk_device.device.cmd_draw_indexed_indirect(
+                cmd_buffer,
+                buffer,
+                offset,
+                draw_count,
+                INDEXED_STRIDE,
+            );
+        } else {
+            for draw in 0..draw_count as u64 {
+                vk_device.device.cmd_draw_indexed_indirect(
+                    cmd_buffer,
+                    buffer,
+                    offset + draw * INDEXED_STRIDE as u64,
+                    1,
+                    INDEXED_STRIDE,
+                );
+            }
+        }
+    }
+}
+
+/// cmd_draw_indexed_indirect for tightly packed vk::DrawIndirectCommands
+/// # Safety
+/// cmd_buffer must be inside rendering
+pub unsafe fn cmd_draw_indirect(
+    vk_device: &VKDevice,
+    cmd_buffer: vk::CommandBuffer,
+    buffer: vk::Buffer,
+    offset: u64,
+    draw_count: u32,
+) {
+    unsafe {
+        if vk_device.multi_draw_indirect {
+            vk_device
+                .device
+                .cmd_draw_indirect(cmd_buffer, buffer, offset, draw_count, STRIDE);
+        } else {
+            for draw in 0..draw_count as u64 {
+                vk_device.device.cmd_draw_indirect(
+                    cmd_buffer,
+                    buffer,
+                    offset + draw * STRIDE as u64,
+                    1,
+                    STRIDE,
+                );
+            }
+        }
+    }
+}
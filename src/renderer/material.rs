@@ -0,0 +1,407 @@
+use ash::vk;
+use glam::Vec4;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::{BitOr, BitOrAssign};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+use thiserror::Error;
+
+use crate::color::LinearRgba;
+use crate::renderer::device::VKDevice;
+use crate::renderer::render_texture::RenderTextureId;
+use crate::renderer::texture::VKTexture;
+
+/// Index of a material in VKRenderer::materials
+pub type MaterialId = usize;
+
+/// Material every renderer starts with, vertex colours and the fallback texture
+pub const DEFAULT_MATERIAL: MaterialId = 0;
+
+/// Bits of the uber-shader's materialFeatures specialization constant (constant_id 0)
+/// matches the constants in triangle.slang
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MaterialFeatures(pub u32);
+
+impl MaterialFeatures {
+    pub const NONE: Self = Self(0);
+    pub const VERTEX_COLOR: Self = Self(1);
+    pub const ALBEDO_TEXTURE: Self = Self(1 << 1);
+    pub const ALPHA_TEST: Self = Self(1 << 2);
+    pub const EMISSIVE: Self = Self(1 << 3);
+    pub const LIT: Self = Self(1 << 4);
+    pub const NORMAL_MAP: Self = Self(1 << 5);
+    /// added to lit materials by the renderer while ray query shadows are on
+    pub const RAY_QUERY_SHADOWS: Self = Self(1 << 6);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for MaterialFeatures {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for MaterialFeatures {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+/// On/off switches in a material file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum MaterialFlag {
+    /// multiply by the mesh's vertex colours
+    VertexColor,
+    /// discard fragments with alpha below alpha_cutoff
+    AlphaTest,
+    /// draw back faces too
+    DoubleSided,
+    /// shaded by the renderer's lights
+    Lit,
+}
+
+/// Value of a named material parameter
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum MaterialParam {
+    Scalar(f32),
+    Vector([f32; 4]),
+}
+
+/// Data driven material, usually loaded from a RON file
+/// Example Use:
+/// ```
+/// use vulkan_engine::renderer::material::MaterialDesc;
+///
+/// let desc = MaterialDesc::from_ron(r#"(
+///     name: "glowing_leaves",
+///     textures: { "albedo": "leaves.png" },
+///     params: {
+///         "base_color": (0.4, 0.8, 0.3, 1.0),
+///         "emissive": (0.0, 0.1, 0.0, 1.0),
+///         "alpha_cutoff": 0.4,
+///     },
+///     flags: [AlphaTest, DoubleSided],
+/// )"#)
+/// .unwrap();
+/// let material = desc.compile().unwrap();
+/// ```
+/// textures: albedo, normal (tangent space, green pointing up the image)
+/// params: base_color (vector, linear), emissive (vector, linear rgb), alpha_cutoff (scalar)
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialDesc {
+    pub name: String,
+    #[serde(default)]
+    pub textures: BTreeMap<String, PathBuf>,
+    #[serde(default)]
+    pub params: BTreeMap<String, MaterialParam>,
+    #[serde(default)]
+    pub flags: Vec<MaterialFlag>,
+}
+
+#[derive(Debug, Error)]
+pub enum MaterialError {
+    #[error("failed to read material: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to parse material: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("material {material} has unknown texture {texture}")]
+    UnknownTexture { material: String, texture: String },
+    #[error("material {material} has unknown parameter {param}")]
+    UnknownParam { material: String, param: String },
+    #[error("material {material} parameter {param} should be a {expected}")]
+    ParamType {
+        material: String,
+        param: String,
+        expected: &'static str,
+    },
+    #[error("material {material} parameter {param} contains NaN or infinity")]
+    NonFiniteParam { material: String, param: String },
+}
+
+/// Material parameters pushed for the fragment stage, per object data lives in the scene buffer
+/// matches the material members of PushConstants in triangle.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MaterialParams {
+    pub base_color: Vec4,
+    pub emissive: Vec4,
+    pub alpha_cutoff: f32,
+    pub _padding: [f32; 3],
+}
+
+impl Default for MaterialParams {
+    fn default() -> Self {
+        Self {
+            base_color: LinearRgba::WHITE.to_vec4(),
+            emissive: Vec4::ZERO,
+            alpha_cutoff: 0.5,
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+/// A material desc checked and mapped onto the uber-shader
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CompiledMaterial {
+    pub name: String,
+    pub features: MaterialFeatures,
+    pub params: MaterialParams,
+    pub albedo: Option<PathBuf>,
+    pub normal_map: Option<PathBuf>,
+    pub double_sided: bool,
+}
+
+impl MaterialDesc {
+    /// What the renderer draws with when no material is given
+    pub fn fallback() -> Self {
+        Self {
+            name: "default".to_string(),
+            flags: vec![MaterialFlag::VertexColor],
+            ..Default::default()
+        }
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, MaterialError> {
+        Ok(ron::from_str(source)?)
+    }
+
+    /// Reads a RON material file, texture paths are relative to the file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MaterialError> {
+        let path = path.as_ref();
+        let mut desc = Self::from_ron(&fs::read_to_string(path)?)?;
+
+        let directory = path.parent().unwrap_or(Path::new(""));
+        for texture in desc.textures.values_mut() {
+            *texture = directory.join(&*texture);
+        }
+        Ok(desc)
+    }
+
+    /// Checks the textures and parameters and picks the shader features they need
+    pub fn compile(&self) -> Result<CompiledMaterial, MaterialError> {
+        let mut features = MaterialFeatures::NONE;
+        let mut params = MaterialParams::default();
+        let mut albedo = None;
+        let mut normal_map = None;
+
+        for (texture, path) in &self.textures {
+            match texture.as_str() {
+                "albedo" => {
+                    features |= MaterialFeatures::ALBEDO_TEXTURE;
+                    albedo = Some(path.clone());
+                }
+                "normal" => {
+                    features |= MaterialFeatures::NORMAL_MAP;
+                    normal_map = Some(path.clone());
+                }
+                _ => {
+                    return Err(MaterialError::UnknownTexture {
+                        material: self.name.clone(),
+                        texture: texture.clone(),
+                    });
+                }
+            }
+        }
+
+        for (param, value) in &self.params {
+            let param_error = |expected| MaterialError::ParamType {
+                material: self.name.clone(),
+                param: param.clone(),
+                expected,
+            };
+
+            let finite = match *value {
+                MaterialParam::Scalar(scalar) => scalar.is_finite(),
+                MaterialParam::Vector(vector) => Vec4::from_array(vector).is_finite(),
+            };
+            if !finite {
+                return Err(MaterialError::NonFiniteParam {
+                    material: self.name.clone(),
+                    param: param.clone(),
+                });
+            }
+
+            match (param.as_str(), *value) {
+                ("base_color", MaterialParam::Vector(vector)) => {
+                    params.base_color = Vec4::from_array(vector);
+                }
+                ("emissive", MaterialParam::Vector(vector)) => {
+                    features |= MaterialFeatures::EMISSIVE;
+                    params.emissive = Vec4::from_array(vector);
+                }
+                ("alpha_cutoff", MaterialParam::Scalar(scalar)) => {
+                    params.alpha_cutoff = scalar;
+                }
+                ("base_color" | "emissive", _) => return Err(param_error("vector")),
+                ("alpha_cutoff", _) => return Err(param_error("scalar")),
+                _ => {
+                    return Err(MaterialError::UnknownParam {
+                        material: self.name.clone(),
+                        param: param.clone(),
+                    });
+                }
+            }
+        }
+
+        let mut double_sided = false;
+        for flag in &self.flags {
+            match flag {
+                MaterialFlag::VertexColor => features |= MaterialFeatures::VERTEX_COLOR,
+                MaterialFlag::AlphaTest => features |= MaterialFeatures::ALPHA_TEST,
+                MaterialFlag::DoubleSided => double_sided = true,
+                MaterialFlag::Lit => features |= MaterialFeatures::LIT,
+            }
+        }
+
+        Ok(CompiledMaterial {
+            name: self.name.clone(),
+            features,
+            params,
+            albedo,
+            normal_map,
+            double_sided,
+        })
+    }
+}
+
+/// Pipeline state a material needs, materials with the same variant share a pipeline
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineVariant {
+    pub features: MaterialFeatures,
+    pub double_sided: bool,
+    /// vertex stage from animated.slang, for meshes with skins or baked clips
+    pub animated: bool,
+}
+
+impl CompiledMaterial {
+    pub fn variant(&self) -> PipelineVariant {
+        PipelineVariant {
+            features: self.features,
+            double_sided: self.double_sided,
+            animated: false,
+        }
+    }
+}
+
+/// A compiled material on the gpu
+/// instances refer to it by MaterialId, so any number of meshes can share it
+pub struct VKMaterial {
+    pub name: String,
+    pub variant: PipelineVariant,
+    pub params: MaterialParams,
+    /// owned by the renderer's pipeline cache and shared with every material of the same variant
+    pub pipeline: vk::Pipeline,
+    /// the same for animated meshes, null until the renderer has one
+    pub animated_pipeline: vk::Pipeline,
+    /// None when the material samples the renderer's fallback texture
+    pub texture: Option<VKTexture>,
+    /// None when the material samples the renderer's flat normal texture
+    pub normal_texture: Option<VKTexture>,
+    /// files texture and normal_texture were loaded from
+    pub albedo: Option<PathBuf>,
+    pub normal_map: Option<PathBuf>,
+    /// sampled in place of texture, see VKRenderer::set_material_render_texture
+    pub render_texture: Option<RenderTextureId>,
+    /// set 0 for each frame in flight, they only differ by the frame uniform buffers
+    /// allocated from the renderer's VKDescriptorAllocator
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+impl VKMaterial {
+    /// What the material was compiled from, for adding it to another renderer
+    pub fn compiled(&self) -> CompiledMaterial {
+        CompiledMaterial {
+            name: self.name.clone(),
+            features: self.variant.features,
+            params: self.params,
+            albedo: self.albedo.clone(),
+            normal_map: self.normal_map.clone(),
+            double_sided: self.variant.double_sided,
+        }
+    }
+
+    /// # Safety
+    /// Material must not be in use by the gpu
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        // the pipeline and descriptor sets belong to the renderer
+        for texture in [&mut self.texture, &mut self.normal_texture]
+            .into_iter()
+            .flatten()
+        {
+            unsafe { texture.destroy(vk_device) };
+        }
+    }
+}
+
+#[test]
+fn material_compile_test() {
+    let desc = MaterialDesc::from_ron(
+        r#"(
+            name: "test",
+            textures: { "albedo": "test.png" },
+            params: { "base_color": (0.5, 0.5, 0.5, 1.0), "alpha_cutoff": 0.25 },
+            flags: [AlphaTest, DoubleSided],
+        )"#,
+    )
+    .unwrap();
+    let material = desc.compile().unwrap();
+
+    assert_eq!(
+        material.features,
+        MaterialFeatures::ALBEDO_TEXTURE | MaterialFeatures::ALPHA_TEST
+    );
+    assert_eq!(material.params.base_color, Vec4::new(0.5, 0.5, 0.5, 1.0));
+    assert_eq!(material.params.alpha_cutoff, 0.25);
+    assert_eq!(material.albedo, Some(PathBuf::from("test.png")));
+    assert!(material.double_sided);
+
+    let bumpy = MaterialDesc::from_ron(
+        r#"(name: "bumpy", textures: { "normal": "bumps.png" }, flags: [Lit])"#,
+    )
+    .unwrap()
+    .compile()
+    .unwrap();
+    assert_eq!(
+        bumpy.features,
+        MaterialFeatures::NORMAL_MAP | MaterialFeatures::LIT
+    );
+    assert_eq!(bumpy.normal_map, Some(PathBuf::from("bumps.png")));
+    assert_eq!(bumpy.albedo, None);
+
+    let fallback = MaterialDesc::fallback().compile().unwrap();
+    assert_eq!(fallback.features, MaterialFeatures::VERTEX_COLOR);
+    assert_eq!(fallback.params, MaterialParams::default());
+
+    // parameters alone don't need another pipeline
+    let tinted = MaterialDesc::from_ron(
+        r#"(name: "tinted", params: { "base_color": (1.0, 0.0, 0.0, 1.0) }, flags: [VertexColor])"#,
+    )
+    .unwrap()
+    .compile()
+    .unwrap();
+    assert_eq!(tinted.variant(), fallback.variant());
+    assert_ne!(material.variant(), fallback.variant());
+
+    let wrong_type = MaterialDesc::from_ron(r#"(name: "a", params: { "emissive": 1.0 })"#);
+    assert!(matches!(
+        wrong_type.unwrap().compile(),
+        Err(MaterialError::ParamType {
+            expected: "vector",
+            ..
+        })
+    ));
+    let unknown = MaterialDesc::from_ron(r#"(name: "b", params: { "roughness": 1.0 })"#);
+    assert!(matches!(
+        unknown.unwrap().compile(),
+        Err(MaterialError::UnknownParam { .. })
+    ));
+    assert!(MaterialDesc::from_ron(r#"(name: "c", shininess: 2.0)"#).is_err());
+}
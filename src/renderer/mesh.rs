@@ -0,0 +1,689 @@
+use ash::vk;
+use glam::{Vec2, Vec3, Vec4};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::mem::offset_of;
+
+use crate::math::Aabb;
+use crate::renderer::buffer::VKBuffer;
+use crate::renderer::device::VKDevice;
+use crate::renderer::upload::VKUploader;
+use crate::renderer::vertex::{VertexAttribute, VertexLayout};
+use crate::validation::{SceneIssue, SceneValidationError, validate_triangles, validate_vertices};
+
+/// Index of a mesh in VKRenderer::meshes
+pub type MeshId = usize;
+
+/// Mesh every renderer starts with, CUBE_VERTICES
+pub const CUBE_MESH: MeshId = 0;
+
+/// Triangle list in its own vertex buffer, optionally indexed
+pub struct VKMesh {
+    pub vertex_buffer: VKBuffer,
+    pub vertex_count: u32,
+    /// null for meshes that aren't indexed
+    pub index_buffer: VKBuffer,
+    /// u32 indices, 0 for meshes that aren't indexed
+    pub index_count: u32,
+    /// local space bounds used for culling
+    pub bounds: Aabb,
+}
+
+impl VKMesh {
+    /// Uploads a triangle list, NaN or infinite positions and normals fail with
+    /// ERROR_INITIALIZATION_FAILED, other problems with the triangles are only logged
+    /// Missing normals and tangents are generated before upload
+    /// the copy starts on uploader's next flush
+    /// Example Use:
+    /// ```ignore
+    /// let mesh = VKMesh::new(&mut vk_device, &mut uploader, &CUBE_VERTICES)?;
+    /// ```
+    pub fn new(
+        vk_device: &mut VKDevice,
+        uploader: &mut VKUploader,
+        vertices: &[Vertex],
+    ) -> Result<Self, vk::Result> {
+        let positions: Vec<Vec3> = vertices.iter().map(|vertex| vertex.pos).collect();
+        check_mesh_issues(validate_triangles(&positions))?;
+        let bounds =
+            Aabb::from_points(&positions).ok_or(vk::Result::ERROR_INITIALIZATION_FAILED)?;
+
+        let mut vertices = vertices.to_vec();
+        generate_normals(&mut vertices);
+        generate_tangents(&mut vertices);
+        check_mesh_issues(validate_vertices(&vertices))?;
+
+        let vertex_buffer = uploader.upload_buffer(
+            vk_device,
+            &vertices,
+            vk::BufferUsageFlags::VERTEX_BUFFER | mesh_buffer_usage(vk_device),
+            "Vertices",
+        )?;
+
+        Ok(Self {
+            vertex_buffer,
+            vertex_count: vertices.len() as u32,
+            index_buffer: VKBuffer::default(),
+            index_count: 0,
+            bounds,
+        })
+    }
+
+    /// Uploads a triangle list of any vertex type as it is, for pipelines built around V
+    /// nothing is generated or validated, bounds are the caller's since only it knows where positions are
+    /// Example Use:
+    /// ```ignore
+    /// let bounds = Aabb::from_points(&terrain.iter().map(|vertex| vertex.position).collect::<Vec<_>>()).unwrap();
+    /// let mesh = VKMesh::from_vertices(&mut vk_device, &mut uploader, &terrain, bounds)?;
+    /// ```
+    pub fn from_vertices<V: VertexLayout>(
+        vk_device: &mut VKDevice,
+        uploader: &mut VKUploader,
+        vertices: &[V],
+        bounds: Aabb,
+    ) -> Result<Self, vk::Result> {
+        if vertices.is_empty() {
+            return Err(vk::Result::ERROR_INITIALIZATION_FAILED);
+        }
+        let vertex_buffer = uploader.upload_buffer(
+            vk_device,
+            vertices,
+            vk::BufferUsageFlags::VERTEX_BUFFER | mesh_buffer_usage(vk_device),
+            "Vertices",
+        )?;
+
+        Ok(Self {
+            vertex_buffer,
+            vertex_count: vertices.len() as u32,
+            index_buffer: VKBuffer::default(),
+            index_count: 0,
+            bounds,
+        })
+    }
+
+    /// Uploads vertices shared between triangles, every 3 indices make a triangle
+    /// Missing normals and tangents are averaged over the triangles sharing a vertex
+    /// rejected like new when a triangle or vertex isn't finite
+    /// Example Use:
+    /// ```ignore
+    /// // a quad from 4 vertices instead of 6
+    /// let mesh = VKMesh::new_indexed(&mut vk_device, &mut uploader, &corners, &[0, 1, 2, 2, 3, 0])?;
+    /// ```
+    pub fn new_indexed(
+        vk_device: &mut VKDevice,
+        uploader: &mut VKUploader,
+        vertices: &[Vertex],
+        indices: &[u32],
+    ) -> Result<Self, vk::Result> {
+        if indices.is_empty()
+            || indices
+                .iter()
+                .any(|&index| index as usize >= vertices.len())
+        {
+            warn!("Mesh: Indices Outside of the {} Vertices", vertices.len());
+            return Err(vk::Result::ERROR_INITIALIZATION_FAILED);
+        }
+        let positions: Vec<Vec3> = vertices.iter().map(|vertex| vertex.pos).collect();
+        let triangle_positions: Vec<Vec3> = indices
+            .iter()
+            .map(|&index| positions[index as usize])
+            .collect();
+        check_mesh_issues(validate_triangles(&triangle_positions))?;
+        let bounds =
+            Aabb::from_points(&positions).ok_or(vk::Result::ERROR_INITIALIZATION_FAILED)?;
+
+        let mut vertices = vertices.to_vec();
+        generate_indexed_normals(&mut vertices, indices);
+        generate_indexed_tangents(&mut vertices, indices);
+        check_mesh_issues(validate_vertices(&vertices))?;
+
+        let mut vertex_buffer = uploader.upload_buffer(
+            vk_device,
+            &vertices,
+            vk::BufferUsageFlags::VERTEX_BUFFER | mesh_buffer_usage(vk_device),
+            "Vertices",
+        )?;
+        let index_buffer = match uploader.upload_buffer(
+            vk_device,
+            indices,
+            vk::BufferUsageFlags::INDEX_BUFFER | mesh_buffer_usage(vk_device),
+            "Indices",
+        ) {
+            Ok(index_buffer) => index_buffer,
+            Err(error) => {
+                unsafe { vertex_buffer.destroy(vk_device) };
+                return Err(error);
+            }
+        };
+
+        Ok(Self {
+            vertex_buffer,
+            vertex_count: vertices.len() as u32,
+            index_buffer,
+            index_count: indices.len() as u32,
+            bounds,
+        })
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.index_buffer.buffer != vk::Buffer::null()
+    }
+
+    /// Binds the vertex buffer and the index buffer if there is one
+    /// # Safety
+    /// cmd_buffer must be recording
+    pub unsafe fn cmd_bind(&self, vk_device: &VKDevice, cmd_buffer: vk::CommandBuffer) {
+        unsafe {
+            vk_device.device.cmd_bind_vertex_buffers(
+                cmd_buffer,
+                0,
+                &[self.vertex_buffer.buffer],
+                &[0u64],
+            );
+            if self.is_indexed() {
+                vk_device.device.cmd_bind_index_buffer(
+                    cmd_buffer,
+                    self.index_buffer.buffer,
+                    0,
+                    vk::IndexType::UINT32,
+                );
+            }
+        }
+    }
+
+    /// Draws the whole mesh, indexed or not
+    /// # Safety
+    /// cmd_buffer must be inside rendering with the mesh bound by cmd_bind
+    pub unsafe fn cmd_draw(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        instance_count: u32,
+        first_instance: u32,
+    ) {
+        unsafe {
+            if self.is_indexed() {
+                vk_device.device.cmd_draw_indexed(
+                    cmd_buffer,
+                    self.index_count,
+                    instance_count,
+                    0,
+                    0,
+                    first_instance,
+                );
+            } else {
+                vk_device.device.cmd_draw(
+                    cmd_buffer,
+                    self.vertex_count,
+                    instance_count,
+                    0,
+                    first_instance,
+                );
+            }
+        }
+    }
+
+    /// Indirect command drawing the whole mesh, meshes that aren't indexed draw their vertices in order
+    /// with no index buffer bound the command has to go through cmd_draw_indirect instead
+    pub fn indexed_indirect_command(
+        &self,
+        instance_count: u32,
+        first_instance: u32,
+    ) -> vk::DrawIndexedIndirectCommand {
+        vk::DrawIndexedIndirectCommand {
+            index_count: if self.is_indexed() {
+                self.index_count
+            } else {
+                self.vertex_count
+            },
+            instance_count,
+            first_index: 0,
+            vertex_offset: 0,
+            first_instance,
+        }
+    }
+
+    /// # Safety
+    /// Mesh must not be in use by the gpu
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            self.vertex_buffer.destroy(vk_device);
+            if self.is_indexed() {
+                self.index_buffer.destroy(vk_device);
+            }
+        };
+    }
+}
+
+// Repr C here so that rust does not change the order on compile and it is what vulkan expects
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Vertex {
+    pub pos: Vec3,
+    pub color: Vec3,
+    pub uv: Vec2,
+    /// zero until generated or set
+    pub normal: Vec3,
+    /// xyz points along increasing u, w is the handedness of the bitangent
+    pub tangent: Vec4,
+}
+
+impl Vertex {
+    pub const fn new(pos: Vec3, color: Vec3, uv: Vec2) -> Self {
+        Self {
+            pos,
+            color,
+            uv,
+            normal: Vec3::ZERO,
+            tangent: Vec4::ZERO,
+        }
+    }
+
+    pub const fn with_normal(mut self, normal: Vec3) -> Self {
+        self.normal = normal;
+        self
+    }
+
+    pub const fn with_tangent(mut self, tangent: Vec4) -> Self {
+        self.tangent = tangent;
+        self
+    }
+}
+
+// what triangle.slang reads, Vec4 is 16 byte aligned so offset_of works out the padding
+impl VertexLayout for Vertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute::new(vk::Format::R32G32B32_SFLOAT, offset_of!(Vertex, pos)),
+        VertexAttribute::new(vk::Format::R32G32B32_SFLOAT, offset_of!(Vertex, color)),
+        VertexAttribute::new(vk::Format::R32G32_SFLOAT, offset_of!(Vertex, uv)),
+        VertexAttribute::new(vk::Format::R32G32B32_SFLOAT, offset_of!(Vertex, normal)),
+        VertexAttribute::new(vk::Format::R32G32B32A32_SFLOAT, offset_of!(Vertex, tangent)),
+    ];
+}
+
+/// Gives every vertex of a triangle list without a normal its face normal
+pub fn generate_normals(vertices: &mut [Vertex]) {
+    let indices = triangle_list_indices(vertices.len());
+    generate_indexed_normals(vertices, &indices);
+}
+
+/// Gives every vertex without a normal the area weighted average of the faces it is part of
+pub fn generate_indexed_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut normals = vec![Vec3::ZERO; vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|corner| triangle[corner] as usize);
+        // left long so bigger faces count for more
+        let face_normal =
+            (vertices[b].pos - vertices[a].pos).cross(vertices[c].pos - vertices[a].pos);
+        for vertex in [a, b, c] {
+            normals[vertex] += face_normal;
+        }
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        if vertex.normal == Vec3::ZERO {
+            vertex.normal = normal.normalize_or_zero();
+        }
+    }
+}
+
+/// Works out tangents from the uvs of a triangle list for vertices without one
+/// the bitangent points up the image so normal maps follow the opengl convention
+/// Triangles with degenerate uvs are left alone and are drawn without normal mapping
+pub fn generate_tangents(vertices: &mut [Vertex]) {
+    let indices = triangle_list_indices(vertices.len());
+    generate_indexed_tangents(vertices, &indices);
+}
+
+/// generate_tangents for shared vertices, the tangents of the triangles using a vertex are summed
+pub fn generate_indexed_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents = vec![(Vec3::ZERO, Vec3::ZERO); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|corner| triangle[corner] as usize);
+        let edge1 = vertices[b].pos - vertices[a].pos;
+        let edge2 = vertices[c].pos - vertices[a].pos;
+        let delta1 = vertices[b].uv - vertices[a].uv;
+        let delta2 = vertices[c].uv - vertices[a].uv;
+
+        let determinant = delta1.x * delta2.y - delta2.x * delta1.y;
+        if determinant.abs() <= f32::EPSILON {
+            continue;
+        }
+        let tangent = (edge1 * delta2.y - edge2 * delta1.y) / determinant;
+        let bitangent = (edge2 * delta1.x - edge1 * delta2.x) / determinant;
+        for vertex in [a, b, c] {
+            tangents[vertex].0 += tangent;
+            tangents[vertex].1 += bitangent;
+        }
+    }
+
+    for (vertex, (tangent, bitangent)) in vertices.iter_mut().zip(tangents) {
+        if vertex.tangent != Vec4::ZERO {
+            continue;
+        }
+        // keep the tangent at a right angle to this vertex normal
+        let direction = (tangent - vertex.normal * vertex.normal.dot(tangent)).normalize_or_zero();
+        if direction == Vec3::ZERO {
+            continue;
+        }
+        // v grows down the image, so up is the negative bitangent
+        let handedness = if vertex.normal.cross(direction).dot(-bitangent) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        vertex.tangent = direction.extend(handedness);
+    }
+}
+
+// indices of an unindexed triangle list, leftover vertices aren't part of a triangle
+fn triangle_list_indices(vertex_count: usize) -> Vec<u32> {
+    (0..(vertex_count - vertex_count % 3) as u32).collect()
+}
+
+pub static CUBE_VERTICES: [Vertex; 36] = [
+    // FRONT FACE (Z = 0.5) - RED
+    Vertex::new(
+        Vec3::new(-0.5, -0.5, 0.5),
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec2::new(0.0, 1.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, -0.5, 0.5),
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec2::new(1.0, 1.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, 0.5, 0.5),
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec2::new(1.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, 0.5, 0.5),
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec2::new(1.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(-0.5, 0.5, 0.5),
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec2::new(0.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(-0.5, -0.5, 0.5),
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec2::new(0.0, 1.0),
+    ),
+    // BACK FACE (Z = -0.5) - GREEN
+    Vertex::new(
+        Vec3::new(0.5, -0.5, -0.5),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec2::new(0.0, 1.0),
+    ),
+    Vertex::new(
+        Vec3::new(-0.5, -0.5, -0.5),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec2::new(1.0, 1.0),
+    ),
+    Vertex::new(
+        Vec3::new(-0.5, 0.5, -0.5),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec2::new(1.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(-0.5, 0.5, -0.5),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec2::new(1.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, 0.5, -0.5),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec2::new(0.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, -0.5, -0.5),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec2::new(0.0, 1.0),
+    ),
+    // LEFT FACE (X = -0.5) - BLUE
+    Vertex::new(
+        Vec3::new(-0.5, -0.5, -0.5),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec2::new(0.0, 1.0),
+    ),
+    Vertex::new(
+        Vec3::new(-0.5, -0.5, 0.5),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec2::new(1.0, 1.0),
+    ),
+    Vertex::new(
+        Vec3::new(-0.5, 0.5, 0.5),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec2::new(1.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(-0.5, 0.5, 0.5),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec2::new(1.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(-0.5, 0.5, -0.5),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec2::new(0.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(-0.5, -0.5, -0.5),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec2::new(0.0, 1.0),
+    ),
+    // RIGHT FACE (X = 0.5) - YELLOW
+    Vertex::new(
+        Vec3::new(0.5, -0.5, 0.5),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec2::new(0.0, 1.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, -0.5, -0.5),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec2::new(1.0, 1.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, 0.5, -0.5),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec2::new(1.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, 0.5, -0.5),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec2::new(1.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, 0.5, 0.5),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec2::new(0.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, -0.5, 0.5),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec2::new(0.0, 1.0),
+    ),
+    // TOP FACE (Y = 0.5) - CYAN
+    Vertex::new(
+        Vec3::new(-0.5, 0.5, 0.5),
+        Vec3::new(0.0, 1.0, 1.0),
+        Vec2::new(0.0, 1.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, 0.5, 0.5),
+        Vec3::new(0.0, 1.0, 1.0),
+        Vec2::new(1.0, 1.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, 0.5, -0.5),
+        Vec3::new(0.0, 1.0, 1.0),
+        Vec2::new(1.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, 0.5, -0.5),
+        Vec3::new(0.0, 1.0, 1.0),
+        Vec2::new(1.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(-0.5, 0.5, -0.5),
+        Vec3::new(0.0, 1.0, 1.0),
+        Vec2::new(0.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(-0.5, 0.5, 0.5),
+        Vec3::new(0.0, 1.0, 1.0),
+        Vec2::new(0.0, 1.0),
+    ),
+    // BOTTOM FACE (Y = -0.5) - MAGENTA
+    Vertex::new(
+        Vec3::new(-0.5, -0.5, -0.5),
+        Vec3::new(1.0, 0.0, 1.0),
+        Vec2::new(0.0, 1.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, -0.5, -0.5),
+        Vec3::new(1.0, 0.0, 1.0),
+        Vec2::new(1.0, 1.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, -0.5, 0.5),
+        Vec3::new(1.0, 0.0, 1.0),
+        Vec2::new(1.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(0.5, -0.5, 0.5),
+        Vec3::new(1.0, 0.0, 1.0),
+        Vec2::new(1.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(-0.5, -0.5, 0.5),
+        Vec3::new(1.0, 0.0, 1.0),
+        Vec2::new(0.0, 0.0),
+    ),
+    Vertex::new(
+        Vec3::new(-0.5, -0.5, -0.5),
+        Vec3::new(1.0, 0.0, 1.0),
+        Vec2::new(0.0, 1.0),
+    ),
+];
+
+// logs every issue, failing instead when one would put NaN or infinity in the vertex buffer
+fn check_mesh_issues(issues: Vec<SceneIssue>) -> Result<(), vk::Result> {
+    if let Err(error) = SceneValidationError::check_finite(&issues) {
+        warn!("Mesh Rejected, {error}");
+        return Err(vk::Result::ERROR_INITIALIZATION_FAILED);
+    }
+    for issue in issues {
+        warn!("Mesh: {issue}");
+    }
+    Ok(())
+}
+
+// mesh buffers are copied back when recording a frame, see replay::FrameRecording
+fn mesh_buffer_usage(vk_device: &VKDevice) -> vk::BufferUsageFlags {
+    vk::BufferUsageFlags::TRANSFER_SRC | acceleration_input_usage(vk_device)
+}
+
+// with ray tracing, meshes can be built into acceleration structures and read by address from hit shaders
+fn acceleration_input_usage(vk_device: &VKDevice) -> vk::BufferUsageFlags {
+    if vk_device.acceleration_structure.is_some() {
+        vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+            | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+    } else {
+        vk::BufferUsageFlags::empty()
+    }
+}
+
+#[test]
+fn generate_tangents_test() {
+    // front face of the cube, u along +x and v down -y
+    let mut vertices = [
+        Vertex::new(Vec3::new(-0.5, -0.5, 0.5), Vec3::ONE, Vec2::new(0.0, 1.0)),
+        Vertex::new(Vec3::new(0.5, -0.5, 0.5), Vec3::ONE, Vec2::new(1.0, 1.0)),
+        Vertex::new(Vec3::new(0.5, 0.5, 0.5), Vec3::ONE, Vec2::new(1.0, 0.0)),
+    ];
+    generate_normals(&mut vertices);
+    generate_tangents(&mut vertices);
+    for vertex in &vertices {
+        assert!(vertex.normal.abs_diff_eq(Vec3::Z, 1e-6));
+        assert!(
+            vertex
+                .tangent
+                .abs_diff_eq(Vec4::new(1.0, 0.0, 0.0, 1.0), 1e-6)
+        );
+    }
+
+    // mirrored uvs flip the handedness
+    for vertex in &mut vertices {
+        vertex.uv.x = 1.0 - vertex.uv.x;
+        vertex.tangent = Vec4::ZERO;
+    }
+    generate_tangents(&mut vertices);
+    assert!(
+        vertices[0]
+            .tangent
+            .abs_diff_eq(Vec4::new(-1.0, 0.0, 0.0, -1.0), 1e-6)
+    );
+
+    // normals that were already set are kept
+    let mut vertices = vertices.map(|vertex| vertex.with_normal(Vec3::Y));
+    generate_normals(&mut vertices);
+    assert_eq!(vertices[0].normal, Vec3::Y);
+}
+
+#[test]
+fn indexed_mesh_test() {
+    // two triangles folded along the x axis share the edge's vertices
+    let mut vertices = [
+        Vertex::new(Vec3::ZERO, Vec3::ONE, Vec2::ZERO),
+        Vertex::new(Vec3::X, Vec3::ONE, Vec2::X),
+        Vertex::new(Vec3::Y, Vec3::ONE, Vec2::Y),
+        Vertex::new(Vec3::NEG_Z, Vec3::ONE, Vec2::Y),
+    ];
+    generate_indexed_normals(&mut vertices, &[0, 1, 2, 0, 1, 3]);
+    // faces point +z and +y, the shared edge averages them
+    assert!(vertices[2].normal.abs_diff_eq(Vec3::Z, 1e-6));
+    assert!(vertices[3].normal.abs_diff_eq(Vec3::Y, 1e-6));
+    let between = Vec3::new(0.0, 1.0, 1.0).normalize();
+    assert!(vertices[0].normal.abs_diff_eq(between, 1e-6));
+    assert!(vertices[1].normal.abs_diff_eq(between, 1e-6));
+
+    // the unindexed helpers give the same result as before on a triangle list
+    let mut list =
+        [vertices[0], vertices[1], vertices[2]].map(|vertex| vertex.with_normal(Vec3::ZERO));
+    generate_normals(&mut list);
+    assert!(list[0].normal.abs_diff_eq(Vec3::Z, 1e-6));
+
+    let mesh = VKMesh {
+        vertex_buffer: VKBuffer::default(),
+        vertex_count: 4,
+        index_buffer: VKBuffer::default(),
+        index_count: 0,
+        bounds: Aabb::new(Vec3::ZERO, Vec3::ONE),
+    };
+    assert!(!mesh.is_indexed());
+    let command = mesh.indexed_indirect_command(3, 7);
+    assert_eq!(
+        (
+            command.index_count,
+            command.instance_count,
+            command.first_instance
+        ),
+        (4, 3, 7)
+    );
+}
+
+#[test]
+fn vertex_layout_test() {
+    let attributes = Vertex::attribute_descriptions();
+    let locations: Vec<_> = attributes
+        .iter()
+        .map(|attribute| (attribute.location, attribute.offset))
+        .collect();
+    // tangent is pushed up to the next 16 bytes
+    assert_eq!(locations, vec![(0, 0), (1, 12), (2, 24), (3, 32), (4, 48)]);
+    assert_eq!(Vertex::binding_description().stride, 64);
+    assert_eq!(attributes[4].format, vk::Format::R32G32B32A32_SFLOAT);
+}
@@ -0,0 +1,237 @@
+use ash::vk::{self, Handle};
+use std::collections::HashSet;
+
+use crate::renderer::allocator::VKAllocation;
+use crate::renderer::device::GpuDevice;
+
+/// Device operation recorded by MockDevice
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MockCall {
+    CreateBuffer {
+        buffer: vk::Buffer,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+    },
+    CreateImage {
+        image: vk::Image,
+        extent: vk::Extent2D,
+        format: vk::Format,
+    },
+    CreateImageView {
+        image_view: vk::ImageView,
+        image: vk::Image,
+    },
+    DestroyBuffer {
+        buffer: vk::Buffer,
+    },
+    DestroyImage {
+        image: vk::Image,
+    },
+    DestroyImageView {
+        image_view: vk::ImageView,
+    },
+}
+
+/// GpuDevice that hands out fake handles and records every call
+/// so resource lifetime logic can be tested without a gpu
+/// panics if a resource is destroyed twice or was never created
+/// Example Use:
+/// ```
+/// use ash::vk;
+/// use gpu_allocator::MemoryLocation;
+/// use vulkan_engine::renderer::device::GpuDevice;
+/// use vulkan_engine::renderer::mock::MockDevice;
+///
+/// let mut device = MockDevice::default();
+/// let (buffer, allocation) = device
+///     .create_buffer(64, vk::BufferUsageFlags::VERTEX_BUFFER, MemoryLocation::GpuOnly, "Test")
+///     .unwrap();
+/// assert_eq!(device.live_resources(), 1);
+///
+/// unsafe { device.destroy_buffer(buffer, allocation) };
+/// assert_eq!(device.live_resources(), 0);
+/// ```
+#[derive(Debug, Default)]
+pub struct MockDevice {
+    pub calls: Vec<MockCall>,
+    live: HashSet<u64>,
+    next_handle: u64,
+    remaining_creations: Option<usize>,
+}
+
+impl MockDevice {
+    /// Creations after the first count fail with ERROR_OUT_OF_DEVICE_MEMORY
+    /// for testing error paths
+    pub fn fail_after(mut self, count: usize) -> Self {
+        self.remaining_creations = Some(count);
+        self
+    }
+
+    /// Number of resources created and not yet destroyed
+    pub fn live_resources(&self) -> usize {
+        self.live.len()
+    }
+
+    fn create_handle(&mut self) -> Result<u64, vk::Result> {
+        if let Some(remaining) = self.remaining_creations.as_mut() {
+            if *remaining == 0 {
+                return Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY);
+            }
+            *remaining -= 1;
+        }
+
+        // 0 is a null handle
+        self.next_handle += 1;
+        self.live.insert(self.next_handle);
+        Ok(self.next_handle)
+    }
+
+    fn destroy_handle(&mut self, handle: u64, kind: &str) {
+        assert!(
+            self.live.remove(&handle),
+            "{kind} {handle:#x} destroyed twice or never created"
+        );
+    }
+}
+
+impl GpuDevice for MockDevice {
+    fn create_buffer(
+        &mut self,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+        _mem_location: gpu_allocator::MemoryLocation,
+        _name: &str,
+    ) -> Result<(vk::Buffer, VKAllocation), vk::Result> {
+        let buffer = vk::Buffer::from_raw(self.create_handle()?);
+        self.calls.push(MockCall::CreateBuffer {
+            buffer,
+            size,
+            usage,
+        });
+        Ok((buffer, VKAllocation::default()))
+    }
+
+    fn create_image(
+        &mut self,
+        image_extent: vk::Extent2D,
+        image_format: vk::Format,
+        _image_tiling: vk::ImageTiling,
+        _image_usage: vk::ImageUsageFlags,
+        _image_samples: vk::SampleCountFlags,
+        _mem_location: gpu_allocator::MemoryLocation,
+    ) -> Result<(vk::Image, VKAllocation), vk::Result> {
+        let image = vk::Image::from_raw(self.create_handle()?);
+        self.calls.push(MockCall::CreateImage {
+            image,
+            extent: image_extent,
+            format: image_format,
+        });
+        Ok((image, VKAllocation::default()))
+    }
+
+    fn create_image_view(
+        &mut self,
+        vk_image: vk::Image,
+        _image_format: vk::Format,
+        _aspect_mask: vk::ImageAspectFlags,
+    ) -> Result<vk::ImageView, vk::Result> {
+        let image_view = vk::ImageView::from_raw(self.create_handle()?);
+        self.calls.push(MockCall::CreateImageView {
+            image_view,
+            image: vk_image,
+        });
+        Ok(image_view)
+    }
+
+    unsafe fn destroy_buffer(&mut self, buffer: vk::Buffer, _allocation: VKAllocation) {
+        self.destroy_handle(buffer.as_raw(), "Buffer");
+        self.calls.push(MockCall::DestroyBuffer { buffer });
+    }
+
+    unsafe fn destroy_image(&mut self, image: vk::Image, _allocation: VKAllocation) {
+        self.destroy_handle(image.as_raw(), "Image");
+        self.calls.push(MockCall::DestroyImage { image });
+    }
+
+    unsafe fn destroy_image_view(&mut self, image_view: vk::ImageView) {
+        self.destroy_handle(image_view.as_raw(), "Image View");
+        self.calls.push(MockCall::DestroyImageView { image_view });
+    }
+}
+
+#[test]
+fn mock_device_test() {
+    let mut device = MockDevice::default().fail_after(2);
+
+    let extent = vk::Extent2D::default().width(4).height(4);
+    let (image, allocation) = device
+        .create_image(
+            extent,
+            vk::Format::R8G8B8A8_UNORM,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::SAMPLED,
+            vk::SampleCountFlags::TYPE_1,
+            gpu_allocator::MemoryLocation::GpuOnly,
+        )
+        .unwrap();
+    let image_view = device
+        .create_image_view(
+            image,
+            vk::Format::R8G8B8A8_UNORM,
+            vk::ImageAspectFlags::COLOR,
+        )
+        .unwrap();
+
+    // out of creations
+    assert_eq!(
+        device
+            .create_buffer(
+                16,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                gpu_allocator::MemoryLocation::CpuToGpu,
+                "Test",
+            )
+            .unwrap_err(),
+        vk::Result::ERROR_OUT_OF_DEVICE_MEMORY
+    );
+    assert_eq!(device.live_resources(), 2);
+
+    unsafe {
+        device.destroy_image_view(image_view);
+        device.destroy_image(image, allocation);
+    }
+
+    assert_eq!(device.live_resources(), 0);
+    assert_eq!(
+        device.calls,
+        vec![
+            MockCall::CreateImage {
+                image,
+                extent,
+                format: vk::Format::R8G8B8A8_UNORM,
+            },
+            MockCall::CreateImageView { image_view, image },
+            MockCall::DestroyImageView { image_view },
+            MockCall::DestroyImage { image },
+        ]
+    );
+}
+
+#[test]
+#[should_panic]
+fn mock_device_double_destroy_test() {
+    let mut device = MockDevice::default();
+    let (buffer, _) = device
+        .create_buffer(
+            16,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+            "Test",
+        )
+        .unwrap();
+
+    unsafe {
+        device.destroy_buffer(buffer, VKAllocation::default());
+        device.destroy_buffer(buffer, VKAllocation::default());
+    }
+}
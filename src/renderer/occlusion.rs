@@ -0,0 +1,745 @@
+use ash::vk;
+use glam::{Mat4, Vec2, Vec3, Vec3Swizzles, Vec4Swizzles};
+use gpu_allocator::MemoryLocation;
+use log::warn;
+use std::error;
+
+use crate::camera::DepthConvention;
+use crate::math::Aabb;
+use crate::renderer::RenderTarget;
+use crate::renderer::buffer::VKBuffer;
+use crate::renderer::compute::{VKComputePipeline, cmd_compute_barrier, group_count};
+use crate::renderer::device::VKDevice;
+use crate::renderer::indirect::cmd_draw_indirect;
+use crate::renderer::shader::VKShaderLoader;
+
+/// Threads per workgroup for both entry points, matches numthreads in occlusion.slang
+pub const OCCLUSION_GROUP_SIZE: u32 = 64;
+
+/// One mip of the depth pyramid, stored one after another in a single buffer
+/// texel x of a level covers texels 2x and 2x + 1 of the level above, the last texel also covers any odd one out
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HizLevel {
+    pub width: u32,
+    pub height: u32,
+    /// first texel of the level in the pyramid buffer
+    pub offset: u32,
+}
+
+impl HizLevel {
+    pub fn len(&self) -> u32 {
+        self.width * self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Levels from the full depth buffer down to 1x1
+pub fn hiz_levels(extent: vk::Extent2D) -> Vec<HizLevel> {
+    let mut levels = Vec::new();
+    if extent.width == 0 || extent.height == 0 {
+        return levels;
+    }
+
+    let mut level = HizLevel {
+        width: extent.width,
+        height: extent.height,
+        offset: 0,
+    };
+    loop {
+        levels.push(level);
+        if level.width == 1 && level.height == 1 {
+            return levels;
+        }
+        level = HizLevel {
+            width: (level.width / 2).max(1),
+            height: (level.height / 2).max(1),
+            offset: level.offset + level.len(),
+        };
+    }
+}
+
+/// Texels in every level
+pub fn hiz_len(levels: &[HizLevel]) -> u32 {
+    levels.last().map_or(0, |level| level.offset + level.len())
+}
+
+/// Pixels an aabb covers and its nearest depth, worked out the same way the cull shader does
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScreenBounds {
+    /// top left corner in pixels, may be off screen
+    pub min: Vec2,
+    pub max: Vec2,
+    /// depth of the corner nearest the camera
+    pub depth: f32,
+}
+
+impl ScreenBounds {
+    /// None when the box reaches behind the camera, it can't be tested and has to be drawn
+    pub fn project(
+        aabb: &Aabb,
+        view_projection: Mat4,
+        extent: vk::Extent2D,
+        depth_convention: DepthConvention,
+    ) -> Option<Self> {
+        let size = Vec2::new(extent.width as f32, extent.height as f32);
+        let mut min = Vec2::MAX;
+        let mut max = Vec2::MIN;
+        let mut depth = depth_convention.far_depth();
+        for corner in 0..8 {
+            let point = Vec3::new(
+                if corner & 1 == 0 {
+                    aabb.min.x
+                } else {
+                    aabb.max.x
+                },
+                if corner & 2 == 0 {
+                    aabb.min.y
+                } else {
+                    aabb.max.y
+                },
+                if corner & 4 == 0 {
+                    aabb.min.z
+                } else {
+                    aabb.max.z
+                },
+            );
+            let clip = view_projection * point.extend(1.0);
+            if clip.w <= 0.0 {
+                return None;
+            }
+            let ndc = clip.xyz() / clip.w;
+            // ndc y of -1 is the top row of the image
+            let pixel = (ndc.xy() * 0.5 + 0.5) * size;
+            min = min.min(pixel);
+            max = max.max(pixel);
+            depth = depth_convention.nearer(depth, ndc.z);
+        }
+        Some(Self { min, max, depth })
+    }
+
+    /// Completely outside the image
+    pub fn offscreen(&self, extent: vk::Extent2D) -> bool {
+        self.max.x < 0.0
+            || self.max.y < 0.0
+            || self.min.x > extent.width as f32
+            || self.min.y > extent.height as f32
+    }
+
+    /// Level where the on screen part of the bounds spans at most 3 texels each way
+    pub fn hiz_level(&self, extent: vk::Extent2D, level_count: u32) -> u32 {
+        let size = Vec2::new(extent.width as f32, extent.height as f32);
+        let span = self.max.min(size) - self.min.max(Vec2::ZERO);
+        let level = span.max_element().max(1.0).log2().ceil() as u32;
+        level.min(level_count.saturating_sub(1))
+    }
+}
+
+/// A draw the cull shader can keep or drop, bounds are in world space
+/// visible draws are written out with first_instance set to the draw's index
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CullDraw {
+    pub min: Vec3,
+    pub vertex_count: u32,
+    pub max: Vec3,
+    pub first_vertex: u32,
+}
+
+impl CullDraw {
+    pub fn new(bounds: Aabb, vertex_count: u32, first_vertex: u32) -> Self {
+        Self {
+            min: bounds.min,
+            vertex_count,
+            max: bounds.max,
+            first_vertex,
+        }
+    }
+}
+
+// matches HizPass in occlusion.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct HizPass {
+    source: HizLevel,
+    dest: HizLevel,
+    /// DepthConvention::sign, depth times it is greater the nearer it is
+    depth_sign: f32,
+}
+
+// matches CullPass in occlusion.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct CullPass {
+    view_projection: Mat4,
+    draw_count: u32,
+    /// 0 before the first pyramid, draws are then only frustum culled
+    level_count: u32,
+    width: u32,
+    height: u32,
+    depth_sign: f32,
+}
+
+/// Culls draws against the depth of the previous frame on the gpu and writes the survivors out
+/// as indirect draw commands, meant for scenes where most objects are hidden behind others
+/// record_pyramid runs after the scene pass, record_cull before the next one
+/// VKRenderer::set_gpu_culling predicates the draws of meshes without indices on it
+/// Example Use:
+/// ```ignore
+/// let mut culler = VKOcclusionCuller::new(
+///     vk_device,
+///     &mut renderer.vulkan_shader_loader,
+///     extent,
+///     4096,
+///     renderer.depth_convention(),
+/// )?;
+/// culler.set_draws(&draws);
+/// // before rendering
+/// unsafe { culler.record_cull(vk_device, cmd_buffer) };
+/// // inside rendering, with a pipeline that reads per draw data at SV_StartInstanceLocation
+/// unsafe { culler.cmd_draw(vk_device, cmd_buffer) };
+/// // after rendering, for the next frame
+/// if unsafe { culler.record_pyramid(vk_device, cmd_buffer, &target) } {
+///     culler.pyramid_view_projection = Some(camera.view_projection);
+/// }
+/// ```
+pub struct VKOcclusionCuller<'a> {
+    pub hiz_pipeline: VKComputePipeline<'a>,
+    pub cull_pipeline: VKComputePipeline<'a>,
+    pub extent: vk::Extent2D,
+    pub levels: Vec<HizLevel>,
+    pub pyramid_buffer: VKBuffer,
+    /// host visible CullDraws written by set_draws
+    pub draw_buffer: VKBuffer,
+    /// vk::DrawIndirectCommand for every visible draw, packed at the front
+    pub indirect_buffer: VKBuffer,
+    /// number of visible draws in indirect_buffer
+    pub count_buffer: VKBuffer,
+    /// a u32 per draw, 1 when it survived culling, read by cmd_if_visible
+    pub visibility_buffer: VKBuffer,
+    pub max_draws: u32,
+    pub draw_count: u32,
+    /// view projection the pyramid was built with, draws are tested against it
+    /// set it once record_pyramid is recorded, the cull passes recorded after it use it
+    pub pyramid_view_projection: Option<Mat4>,
+    /// has to match the depth images given to record_pyramid
+    pub depth_convention: DepthConvention,
+    hiz_set: vk::DescriptorSet,
+    cull_set: vk::DescriptorSet,
+}
+
+impl VKOcclusionCuller<'_> {
+    /// extent must match the depth images given to record_pyramid
+    pub fn new(
+        vk_device: &mut VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        extent: vk::Extent2D,
+        max_draws: u32,
+        depth_convention: DepthConvention,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let hiz_pipeline = VKComputePipeline::new::<HizPass>(
+            vk_device,
+            vk_shader_loader,
+            "shaders/occlusion.spv",
+            &[c"downsample"],
+            1,
+            1,
+        )?;
+        let cull_pipeline = VKComputePipeline::new::<CullPass>(
+            vk_device,
+            vk_shader_loader,
+            "shaders/occlusion.spv",
+            &[c"cull"],
+            5,
+            1,
+        )?;
+
+        let max_draws = max_draws.max(1);
+        let draw_buffer = VKBuffer::new(
+            vk_device,
+            (max_draws as usize * size_of::<CullDraw>()) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::CpuToGpu,
+            "Cull Draws",
+        )?;
+        let indirect_buffer = VKBuffer::new(
+            vk_device,
+            (max_draws as usize * size_of::<vk::DrawIndirectCommand>()) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+            "Visible Draws",
+        )?;
+        let count_buffer = VKBuffer::new(
+            vk_device,
+            size_of::<u32>() as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+            "Visible Draw Count",
+        )?;
+        let mut visibility_usage = vk::BufferUsageFlags::STORAGE_BUFFER;
+        if vk_device.conditional_rendering.is_some() {
+            visibility_usage |= vk::BufferUsageFlags::CONDITIONAL_RENDERING_EXT;
+        }
+        let visibility_buffer = VKBuffer::new(
+            vk_device,
+            (max_draws as usize * size_of::<u32>()) as u64,
+            visibility_usage,
+            MemoryLocation::GpuOnly,
+            "Draw Visibility",
+        )?;
+
+        let mut culler = Self {
+            hiz_pipeline,
+            cull_pipeline,
+            extent,
+            levels: Vec::new(),
+            pyramid_buffer: VKBuffer::default(),
+            draw_buffer,
+            indirect_buffer,
+            count_buffer,
+            visibility_buffer,
+            max_draws,
+            draw_count: 0,
+            pyramid_view_projection: None,
+            depth_convention,
+            hiz_set: vk::DescriptorSet::null(),
+            cull_set: vk::DescriptorSet::null(),
+        };
+        culler.create_pyramid(vk_device, extent)?;
+        Ok(culler)
+    }
+
+    fn create_pyramid(
+        &mut self,
+        vk_device: &mut VKDevice,
+        extent: vk::Extent2D,
+    ) -> Result<(), vk::Result> {
+        let levels = hiz_levels(extent);
+        let pyramid_buffer = VKBuffer::new(
+            vk_device,
+            (hiz_len(&levels).max(1) as usize * size_of::<f32>()) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+            "Depth Pyramid",
+        )?;
+
+        self.hiz_set = self
+            .hiz_pipeline
+            .allocate_set(vk_device, &[pyramid_buffer.buffer])?;
+        self.cull_set = self.cull_pipeline.allocate_set(
+            vk_device,
+            &[
+                pyramid_buffer.buffer,
+                self.draw_buffer.buffer,
+                self.indirect_buffer.buffer,
+                self.count_buffer.buffer,
+                self.visibility_buffer.buffer,
+            ],
+        )?;
+
+        self.extent = extent;
+        self.levels = levels;
+        self.pyramid_buffer = pyramid_buffer;
+        self.pyramid_view_projection = None;
+        Ok(())
+    }
+
+    /// Rebuilds the pyramid for depth images of a new size, call after the swapchain is recreated
+    /// # Safety
+    /// The gpu must not be using the culler
+    pub unsafe fn resize(
+        &mut self,
+        vk_device: &mut VKDevice,
+        extent: vk::Extent2D,
+    ) -> Result<(), vk::Result> {
+        unsafe { self.destroy_pyramid(vk_device) };
+        self.create_pyramid(vk_device, extent)
+    }
+
+    unsafe fn destroy_pyramid(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            if let Err(error) = self.hiz_pipeline.free_set(vk_device, self.hiz_set) {
+                warn!("Failed to Free Depth Pyramid Set: {error}");
+            }
+            if let Err(error) = self.cull_pipeline.free_set(vk_device, self.cull_set) {
+                warn!("Failed to Free Cull Set: {error}");
+            }
+            self.pyramid_buffer.destroy(vk_device);
+        }
+    }
+
+    /// Draws to cull next, only the first max_draws are kept
+    /// the gpu must be done with the previous record_cull
+    pub fn set_draws(&mut self, draws: &[CullDraw]) {
+        if draws.len() > self.max_draws as usize {
+            warn!(
+                "{} Draws Given to the Occlusion Culler, Only {} Fit",
+                draws.len(),
+                self.max_draws
+            );
+        }
+        let draws = &draws[..draws.len().min(self.max_draws as usize)];
+        if self.draw_buffer.write(0, draws).is_err() {
+            warn!("Failed to Copy Draws to the Occlusion Culler");
+            self.draw_count = 0;
+            return;
+        }
+        self.draw_count = draws.len() as u32;
+    }
+
+    /// Whether record_pyramid can use target's depth, multisampled depth can't be copied
+    pub fn accepts(&self, target: &RenderTarget) -> bool {
+        target.samples == vk::SampleCountFlags::TYPE_1 && target.extent == self.extent
+    }
+
+    /// Copies target's depth into the pyramid and reduces it down to 1x1
+    /// returns false when the target isn't accepted
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering, after the scene pass wrote target's depth
+    /// depth is left in DEPTH_ATTACHMENT_OPTIMAL
+    pub unsafe fn record_pyramid(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        target: &RenderTarget,
+    ) -> bool {
+        if !self.accepts(target) {
+            return false;
+        }
+
+        let depth_range = vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::DEPTH)
+            .level_count(1)
+            .layer_count(1);
+        let to_transfer = [vk::ImageMemoryBarrier2::default()
+            .old_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+            .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .src_stage_mask(vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS)
+            .src_access_mask(vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::COPY)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_READ)
+            .image(target.depth_image)
+            .subresource_range(depth_range)];
+        // the last cull pass may still be reading the pyramid
+        let pyramid_free = [vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+            .dst_stage_mask(vk::PipelineStageFlags2::COPY)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)];
+        let copy = vk::BufferImageCopy::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::DEPTH)
+                    .layer_count(1),
+            )
+            .image_extent(vk::Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            });
+        let from_transfer = [vk::ImageMemoryBarrier2::default()
+            .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .new_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .dst_stage_mask(
+                vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+            )
+            .image(target.depth_image)
+            .subresource_range(depth_range)];
+        let copied = [vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+            .dst_access_mask(vk::AccessFlags2::SHADER_STORAGE_READ)];
+
+        unsafe {
+            vk_device.device.cmd_pipeline_barrier2(
+                cmd_buffer,
+                &vk::DependencyInfo::default()
+                    .memory_barriers(&pyramid_free)
+                    .image_memory_barriers(&to_transfer),
+            );
+            vk_device.device.cmd_copy_image_to_buffer(
+                cmd_buffer,
+                target.depth_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.pyramid_buffer.buffer,
+                &[copy],
+            );
+            vk_device.device.cmd_pipeline_barrier2(
+                cmd_buffer,
+                &vk::DependencyInfo::default()
+                    .memory_barriers(&copied)
+                    .image_memory_barriers(&from_transfer),
+            );
+
+            for pair in self.levels.windows(2) {
+                let pass = HizPass {
+                    source: pair[0],
+                    dest: pair[1],
+                    depth_sign: self.depth_convention.sign(),
+                };
+                self.hiz_pipeline.cmd_dispatch(
+                    vk_device,
+                    cmd_buffer,
+                    0,
+                    self.hiz_set,
+                    &pass,
+                    group_count(pass.dest.len(), OCCLUSION_GROUP_SIZE),
+                );
+                cmd_compute_barrier(vk_device, cmd_buffer);
+            }
+        }
+        true
+    }
+
+    /// Writes the visible draws and their count, until the first pyramid only the frustum is tested
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering
+    pub unsafe fn record_cull(&self, vk_device: &VKDevice, cmd_buffer: vk::CommandBuffer) {
+        let (view_projection, level_count) = match self.pyramid_view_projection {
+            Some(view_projection) => (view_projection, self.levels.len() as u32),
+            None => (Mat4::IDENTITY, 0),
+        };
+        let pass = CullPass {
+            view_projection,
+            draw_count: self.draw_count,
+            level_count,
+            width: self.extent.width,
+            height: self.extent.height,
+            depth_sign: self.depth_convention.sign(),
+        };
+
+        // last frame's draws have to be done with the commands before they are overwritten
+        let indirect_free = [vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::DRAW_INDIRECT)
+            .dst_stage_mask(vk::PipelineStageFlags2::CLEAR)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)];
+        let cleared = [vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::CLEAR)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+            .dst_access_mask(
+                vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            )];
+        let mut culled_stages = vk::PipelineStageFlags2::DRAW_INDIRECT;
+        let mut culled_access = vk::AccessFlags2::INDIRECT_COMMAND_READ;
+        if vk_device.conditional_rendering.is_some() {
+            culled_stages |= vk::PipelineStageFlags2::CONDITIONAL_RENDERING_EXT;
+            culled_access |= vk::AccessFlags2::CONDITIONAL_RENDERING_READ_EXT;
+        }
+        let culled = [vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+            .src_access_mask(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+            .dst_stage_mask(culled_stages)
+            .dst_access_mask(culled_access)];
+
+        unsafe {
+            vk_device.device.cmd_pipeline_barrier2(
+                cmd_buffer,
+                &vk::DependencyInfo::default().memory_barriers(&indirect_free),
+            );
+            // zeroed commands draw nothing, so without a count the whole buffer can be drawn
+            vk_device.device.cmd_fill_buffer(
+                cmd_buffer,
+                self.indirect_buffer.buffer,
+                0,
+                vk::WHOLE_SIZE,
+                0,
+            );
+            vk_device.device.cmd_fill_buffer(
+                cmd_buffer,
+                self.count_buffer.buffer,
+                0,
+                vk::WHOLE_SIZE,
+                0,
+            );
+            vk_device.device.cmd_pipeline_barrier2(
+                cmd_buffer,
+                &vk::DependencyInfo::default().memory_barriers(&cleared),
+            );
+
+            self.cull_pipeline.cmd_dispatch(
+                vk_device,
+                cmd_buffer,
+                0,
+                self.cull_set,
+                &pass,
+                group_count(self.draw_count, OCCLUSION_GROUP_SIZE),
+            );
+            vk_device.device.cmd_pipeline_barrier2(
+                cmd_buffer,
+                &vk::DependencyInfo::default().memory_barriers(&culled),
+            );
+        }
+    }
+
+    /// Draws what record_cull kept with the bound pipeline and vertex buffers
+    /// uses the gpu written count with VK_KHR_draw_indirect_count, otherwise every slot is drawn
+    /// # Safety
+    /// cmd_buffer must be inside rendering, after record_cull
+    pub unsafe fn cmd_draw(&self, vk_device: &VKDevice, cmd_buffer: vk::CommandBuffer) {
+        unsafe {
+            match &vk_device.draw_indirect_count {
+                Some(draw_indirect_count) if vk_device.multi_draw_indirect => draw_indirect_count
+                    .cmd_draw_indirect_count(
+                        cmd_buffer,
+                        self.indirect_buffer.buffer,
+                        0,
+                        self.count_buffer.buffer,
+                        0,
+                        self.draw_count,
+                        size_of::<vk::DrawIndirectCommand>() as u32,
+                    ),
+                _ => cmd_draw_indirect(
+                    vk_device,
+                    cmd_buffer,
+                    self.indirect_buffer.buffer,
+                    0,
+                    self.draw_count,
+                ),
+            }
+        }
+    }
+
+    /// Records commands that only run when draw survived the last record_cull
+    /// draws, dispatches and clears are skipped on the gpu without reading the result back
+    /// without VK_EXT_conditional_rendering the commands always run, returns whether they are predicated
+    /// Example Use:
+    /// ```ignore
+    /// // an expensive decal pass that only matters if the wall it sits on is visible
+    /// unsafe {
+    ///     culler.cmd_if_visible(vk_device, cmd_buffer, wall_index, || {
+    ///         vk_device.device.cmd_draw(cmd_buffer, decal_vertices, 1, 0, 0);
+    ///     })
+    /// };
+    /// ```
+    /// # Safety
+    /// cmd_buffer must be recording after record_cull, rendering must not begin or end inside record
+    pub unsafe fn cmd_if_visible(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        draw: u32,
+        record: impl FnOnce(),
+    ) -> bool {
+        let conditional_rendering = match &vk_device.conditional_rendering {
+            Some(conditional_rendering) if draw < self.draw_count => conditional_rendering,
+            _ => {
+                record();
+                return false;
+            }
+        };
+        unsafe {
+            // ash only has the raw function pointers for this extension
+            (conditional_rendering
+                .fp()
+                .cmd_begin_conditional_rendering_ext)(
+                cmd_buffer,
+                &vk::ConditionalRenderingBeginInfoEXT::default()
+                    .buffer(self.visibility_buffer.buffer)
+                    .offset(draw as u64 * size_of::<u32>() as u64),
+            );
+            record();
+            (conditional_rendering.fp().cmd_end_conditional_rendering_ext)(cmd_buffer);
+        }
+        true
+    }
+
+    /// # Safety
+    /// The gpu must not be using the culler
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            self.destroy_pyramid(vk_device);
+            self.draw_buffer.destroy(vk_device);
+            self.indirect_buffer.destroy(vk_device);
+            self.count_buffer.destroy(vk_device);
+            self.visibility_buffer.destroy(vk_device);
+            self.hiz_pipeline.destroy(vk_device);
+            self.cull_pipeline.destroy(vk_device);
+        }
+    }
+}
+
+#[test]
+fn hiz_levels_test() {
+    let extent = vk::Extent2D::default().width(5).height(3);
+    let levels = hiz_levels(extent);
+    assert_eq!(
+        levels,
+        vec![
+            HizLevel {
+                width: 5,
+                height: 3,
+                offset: 0
+            },
+            HizLevel {
+                width: 2,
+                height: 1,
+                offset: 15
+            },
+            HizLevel {
+                width: 1,
+                height: 1,
+                offset: 17
+            },
+        ]
+    );
+    assert_eq!(hiz_len(&levels), 18);
+    assert!(hiz_levels(vk::Extent2D::default()).is_empty());
+    assert_eq!(
+        hiz_levels(vk::Extent2D::default().width(1920).height(1080)).len(),
+        11
+    );
+}
+
+#[test]
+fn screen_bounds_test() {
+    use crate::camera::Camera;
+
+    let extent = vk::Extent2D::default().width(800).height(600);
+    let view_projection = Camera::perspective(90.0_f32.to_radians(), 0.1)
+        .look_at(Vec3::new(0.0, 0.0, 5.0), Vec3::ZERO, Vec3::Y)
+        .view_projection(800.0 / 600.0);
+
+    let cube = Aabb::new(Vec3::splat(-1.0), Vec3::splat(1.0));
+    let reverse = DepthConvention::ReverseZ;
+    let bounds = ScreenBounds::project(&cube, view_projection, extent, reverse).unwrap();
+    // centred, nearest face is closer than the centre
+    assert!((bounds.min + bounds.max - Vec2::new(800.0, 600.0)).length() < 0.01);
+    let centre = view_projection * Vec3::ZERO.extend(1.0);
+    assert!(bounds.depth > centre.z / centre.w);
+    assert!(!bounds.offscreen(extent));
+    let span = (bounds.max - bounds.min).max_element();
+    let level = bounds.hiz_level(extent, 10);
+    assert!(span <= (1 << level) as f32 && span > (1 << (level - 1)) as f32);
+    assert_eq!(bounds.hiz_level(extent, 2), 1);
+
+    let behind = Aabb::new(Vec3::new(-1.0, -1.0, 6.0), Vec3::new(1.0, 1.0, 8.0));
+    assert_eq!(
+        ScreenBounds::project(&behind, view_projection, extent, reverse),
+        None
+    );
+
+    let aside = Aabb::new(Vec3::new(50.0, -1.0, -1.0), Vec3::new(52.0, 1.0, 1.0));
+    assert!(
+        ScreenBounds::project(&aside, view_projection, extent, reverse)
+            .unwrap()
+            .offscreen(extent)
+    );
+
+    // forward depth keeps the smallest depth instead
+    let forward = DepthConvention::Forward;
+    let forward_projection = forward.clip_transform() * view_projection;
+    let forward_bounds = ScreenBounds::project(&cube, forward_projection, extent, forward).unwrap();
+    assert!((forward_bounds.depth - (1.0 - bounds.depth)).abs() < 1e-5);
+    let centre = forward_projection * Vec3::ZERO.extend(1.0);
+    assert!(forward_bounds.depth < centre.z / centre.w);
+}
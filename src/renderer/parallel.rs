@@ -0,0 +1,245 @@
+use ash::vk;
+use std::ops::Range;
+use std::thread;
+
+use crate::renderer::device::VKDevice;
+
+/// Splits draw recording across threads into secondary command buffers
+/// every thread has its own command pool per frame in flight, pools can only be used by one
+/// thread at a time and are reset once the frame's fence has been waited on
+/// turned on with RendererOptions::recording_threads
+/// Example Use:
+/// ```ignore
+/// let secondaries = recorder.record(&vk_device, frame_in_flight, &inheritance, draws.len(), |cmd_buffer, range| {
+///     record_draws(cmd_buffer, &draws[range])
+/// })?;
+/// ```
+pub struct VKParallelRecorder {
+    pub threads: usize,
+    /// fewer draws than this per thread aren't worth a thread of their own
+    pub min_draws_per_thread: usize,
+    /// [frame in flight][thread]
+    pub command_pools: Vec<Vec<vk::CommandPool>>,
+    /// [frame in flight][thread], the extra last buffer of a frame comes from the first thread's
+    /// pool and is recorded on the calling thread after the workers have finished
+    pub cmd_buffers: Vec<Vec<vk::CommandBuffer>>,
+}
+
+/// What secondary buffers need to know about the rendering they are executed in
+#[derive(Clone, Copy, Debug)]
+pub struct RenderingInheritance {
+    pub color_format: vk::Format,
+    pub depth_format: vk::Format,
+    pub samples: vk::SampleCountFlags,
+}
+
+impl VKParallelRecorder {
+    pub fn new(
+        vk_device: &VKDevice,
+        frames_in_flight: usize,
+        threads: usize,
+    ) -> Result<Self, vk::Result> {
+        let mut recorder = Self {
+            threads: threads.max(1),
+            min_draws_per_thread: 256,
+            command_pools: Vec::with_capacity(frames_in_flight),
+            cmd_buffers: Vec::with_capacity(frames_in_flight),
+        };
+        if let Err(error) = recorder.create_frames(vk_device, frames_in_flight) {
+            unsafe { recorder.destroy(vk_device) };
+            return Err(error);
+        }
+        Ok(recorder)
+    }
+
+    fn create_frames(
+        &mut self,
+        vk_device: &VKDevice,
+        frames_in_flight: usize,
+    ) -> Result<(), vk::Result> {
+        let pool_info = vk::CommandPoolCreateInfo::default()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(vk_device.queue_index);
+
+        for _ in 0..frames_in_flight {
+            let mut pools = Vec::with_capacity(self.threads);
+            let mut cmd_buffers = Vec::with_capacity(self.threads + 1);
+            for thread in 0..self.threads {
+                let pool = unsafe { vk_device.device.create_command_pool(&pool_info, None) };
+                let pool = match pool {
+                    Ok(pool) => pool,
+                    Err(error) => {
+                        self.command_pools.push(pools);
+                        return Err(error);
+                    }
+                };
+                pools.push(pool);
+
+                let alloc_info = vk::CommandBufferAllocateInfo::default()
+                    .level(vk::CommandBufferLevel::SECONDARY)
+                    .command_pool(pool)
+                    .command_buffer_count(if thread == 0 { 2 } else { 1 });
+                match unsafe { vk_device.device.allocate_command_buffers(&alloc_info) } {
+                    Ok(buffers) => cmd_buffers.extend(buffers),
+                    Err(error) => {
+                        self.command_pools.push(pools);
+                        return Err(error);
+                    }
+                }
+            }
+            // the first pool's second buffer goes last
+            cmd_buffers[1..].rotate_left(1);
+            self.command_pools.push(pools);
+            self.cmd_buffers.push(cmd_buffers);
+        }
+        Ok(())
+    }
+
+    /// Threads worth splitting draws across
+    pub fn thread_count(&self, draws: usize) -> usize {
+        draws
+            .div_ceil(self.min_draws_per_thread.max(1))
+            .clamp(1, self.threads)
+    }
+
+    /// Records draws split into contiguous ranges, one per thread, returns the secondary
+    /// buffers in draw order with what record returned for them
+    /// # Safety
+    /// The gpu must be done with frame_in_flight's earlier recordings, the buffers must be
+    /// executed inside rendering matching inheritance
+    pub unsafe fn record<R, F>(
+        &self,
+        vk_device: &VKDevice,
+        frame_in_flight: usize,
+        inheritance: &RenderingInheritance,
+        draws: usize,
+        record: F,
+    ) -> Result<Vec<(vk::CommandBuffer, R)>, vk::Result>
+    where
+        R: Send,
+        F: Fn(vk::CommandBuffer, Range<usize>) -> R + Sync,
+    {
+        for pool in &self.command_pools[frame_in_flight] {
+            unsafe {
+                vk_device
+                    .device
+                    .reset_command_pool(*pool, vk::CommandPoolResetFlags::empty())?
+            };
+        }
+
+        let ranges = split_draws(draws, self.thread_count(draws));
+        let cmd_buffers = &self.cmd_buffers[frame_in_flight];
+        let record = &record;
+        let record_range = |(cmd_buffer, range): (vk::CommandBuffer, Range<usize>)| unsafe {
+            begin_secondary(vk_device, cmd_buffer, inheritance)?;
+            let result = record(cmd_buffer, range);
+            vk_device.device.end_command_buffer(cmd_buffer)?;
+            Ok((cmd_buffer, result))
+        };
+
+        let mut work = cmd_buffers.iter().copied().zip(ranges);
+        let first = work.next();
+        thread::scope(|scope| {
+            // the calling thread takes the first range instead of waiting
+            let workers: Vec<_> = work
+                .map(|work| scope.spawn(move || record_range(work)))
+                .collect();
+            let mut recorded = Vec::with_capacity(workers.len() + 1);
+            recorded.extend(first.map(record_range));
+            for worker in workers {
+                match worker.join() {
+                    Ok(result) => recorded.push(result),
+                    Err(panic) => std::panic::resume_unwind(panic),
+                }
+            }
+            recorded.into_iter().collect()
+        })
+    }
+
+    /// Records one more secondary buffer on the calling thread, for commands after the draws
+    /// # Safety
+    /// Same as record, and must follow it for the same frame_in_flight
+    pub unsafe fn record_last(
+        &self,
+        vk_device: &VKDevice,
+        frame_in_flight: usize,
+        inheritance: &RenderingInheritance,
+        record: impl FnOnce(vk::CommandBuffer),
+    ) -> Result<vk::CommandBuffer, vk::Result> {
+        let Some(&cmd_buffer) = self.cmd_buffers[frame_in_flight].last() else {
+            return Err(vk::Result::ERROR_INITIALIZATION_FAILED);
+        };
+        unsafe {
+            begin_secondary(vk_device, cmd_buffer, inheritance)?;
+            record(cmd_buffer);
+            vk_device.device.end_command_buffer(cmd_buffer)?;
+        }
+        Ok(cmd_buffer)
+    }
+
+    /// # Safety
+    /// The gpu must not be using any of the recorded buffers
+    pub unsafe fn destroy(&mut self, vk_device: &VKDevice) {
+        for pool in self.command_pools.drain(..).flatten() {
+            // frees the pool's buffers with it
+            unsafe { vk_device.device.destroy_command_pool(pool, None) };
+        }
+        self.cmd_buffers.clear();
+    }
+}
+
+unsafe fn begin_secondary(
+    vk_device: &VKDevice,
+    cmd_buffer: vk::CommandBuffer,
+    inheritance: &RenderingInheritance,
+) -> Result<(), vk::Result> {
+    let color_formats = [inheritance.color_format];
+    let mut rendering_info = vk::CommandBufferInheritanceRenderingInfo::default()
+        .color_attachment_formats(&color_formats)
+        .depth_attachment_format(inheritance.depth_format)
+        .rasterization_samples(inheritance.samples);
+    let inheritance_info =
+        vk::CommandBufferInheritanceInfo::default().push_next(&mut rendering_info);
+    let begin_info = vk::CommandBufferBeginInfo::default()
+        .flags(
+            vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT
+                | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE,
+        )
+        .inheritance_info(&inheritance_info);
+    unsafe {
+        vk_device
+            .device
+            .begin_command_buffer(cmd_buffer, &begin_info)
+    }
+}
+
+/// count draws cut into parts contiguous ranges, sizes differ by at most one
+pub fn split_draws(count: usize, parts: usize) -> Vec<Range<usize>> {
+    let parts = parts.max(1);
+    let (size, remainder) = (count / parts, count % parts);
+    (0..parts)
+        .scan(0, |start, part| {
+            let end = *start + size + usize::from(part < remainder);
+            let range = *start..end;
+            *start = end;
+            Some(range)
+        })
+        .collect()
+}
+
+#[test]
+fn parallel_recorder_test() {
+    assert_eq!(split_draws(10, 3), [0..4, 4..7, 7..10]);
+    assert_eq!(split_draws(2, 4), [0..1, 1..2, 2..2, 2..2]);
+    assert_eq!(split_draws(5, 0), vec![0..5]);
+
+    let recorder = VKParallelRecorder {
+        threads: 8,
+        min_draws_per_thread: 100,
+        command_pools: Vec::new(),
+        cmd_buffers: Vec::new(),
+    };
+    assert_eq!(recorder.thread_count(0), 1);
+    assert_eq!(recorder.thread_count(250), 3);
+    assert_eq!(recorder.thread_count(50_000), 8);
+}
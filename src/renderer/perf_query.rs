@@ -0,0 +1,372 @@
+use ash::khr::performance_query;
+use ash::vk;
+use log::{info, warn};
+use std::ffi::CStr;
+
+use crate::renderer::VKInstance;
+use crate::renderer::device::VKDevice;
+
+/// Names looked for by default, vendors name their counters differently so these match loosely
+pub const DEFAULT_COUNTERS: [&str; 3] = ["bandwidth", "alu", "cache hit"];
+
+/// A hardware counter exposed by VK_KHR_performance_query
+#[derive(Clone, Debug, PartialEq)]
+pub struct PerfCounter {
+    pub name: String,
+    pub category: String,
+    pub description: String,
+    pub unit: vk::PerformanceCounterUnitKHR,
+    pub storage: vk::PerformanceCounterStorageKHR,
+    pub scope: vk::PerformanceCounterScopeKHR,
+}
+
+impl PerfCounter {
+    /// Counters with a command buffer scope can't be limited to a pass
+    pub fn per_pass(&self) -> bool {
+        self.scope != vk::PerformanceCounterScopeKHR::COMMAND_BUFFER
+    }
+
+    /// Whether any of names is part of the counter's name, ignoring case
+    pub fn matches(&self, names: &[&str]) -> bool {
+        let name = self.name.to_lowercase();
+        names
+            .iter()
+            .any(|wanted| name.contains(&wanted.to_lowercase()))
+    }
+
+    /// Reads the counter's value out of a query result
+    pub fn value(&self, result: vk::PerformanceCounterResultKHR) -> f64 {
+        unsafe {
+            match self.storage {
+                vk::PerformanceCounterStorageKHR::INT32 => result.int32 as f64,
+                vk::PerformanceCounterStorageKHR::INT64 => result.int64 as f64,
+                vk::PerformanceCounterStorageKHR::UINT32 => result.uint32 as f64,
+                vk::PerformanceCounterStorageKHR::UINT64 => result.uint64 as f64,
+                vk::PerformanceCounterStorageKHR::FLOAT32 => result.float32 as f64,
+                _ => result.float64,
+            }
+        }
+    }
+}
+
+/// A counter's value over one pass
+#[derive(Clone, Debug, PartialEq)]
+pub struct PerfCounterValue {
+    pub name: String,
+    pub unit: vk::PerformanceCounterUnitKHR,
+    pub value: f64,
+}
+
+/// Counters measured over one pass of a frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct PassCounters {
+    pub pass: &'static str,
+    pub values: Vec<PerfCounterValue>,
+}
+
+/// Hardware counters measured per pass with VK_KHR_performance_query, for deep optimisation work
+/// only counter sets the driver can collect in a single submission are used, extra counters are dropped
+/// holds the device's profiling lock until destroyed
+/// Example Use:
+/// ```ignore
+/// let mut perf = VKPerfQueries::new(vk_instance, vk_device, &DEFAULT_COUNTERS, &["Scene", "Post"], 2)?
+///     .expect("no performance counters");
+/// // each frame once its fence has signalled, before submitting
+/// let counters = perf.read(vk_device, frame_in_flight);
+/// unsafe { perf.reset(vk_device, frame_in_flight) };
+/// // outside rendering
+/// unsafe { perf.cmd_begin(vk_device, cmd_buffer, frame_in_flight, 0) };
+/// // record the scene
+/// unsafe { perf.cmd_end(vk_device, cmd_buffer, frame_in_flight, 0) };
+/// ```
+pub struct VKPerfQueries {
+    /// one per frame in flight, a query per pass
+    pub query_pools: Vec<vk::QueryPool>,
+    pub counters: Vec<PerfCounter>,
+    pub passes: Vec<&'static str>,
+    /// pools that were reset and submitted since, reading any other is undefined
+    submitted: Vec<bool>,
+    loader: performance_query::Device,
+}
+
+impl VKPerfQueries {
+    /// Every counter the graphics queue can measure, empty without VK_KHR_performance_query
+    pub fn available_counters(vk_instance: &VKInstance, vk_device: &VKDevice) -> Vec<PerfCounter> {
+        if vk_device.performance_query.is_none() {
+            return Vec::new();
+        }
+        let loader = performance_query::Instance::new(&vk_instance.entry, &vk_instance.instance);
+        let result = unsafe {
+            loader
+                .enumerate_physical_device_queue_family_performance_query_counters_len(
+                    vk_device.p_device,
+                    vk_device.queue_index,
+                )
+                .and_then(|len| {
+                    let mut counters = vec![vk::PerformanceCounterKHR::default(); len];
+                    let mut descriptions =
+                        vec![vk::PerformanceCounterDescriptionKHR::default(); len];
+                    loader
+                        .enumerate_physical_device_queue_family_performance_query_counters(
+                            vk_device.p_device,
+                            vk_device.queue_index,
+                            &mut counters,
+                            &mut descriptions,
+                        )
+                        .map(|_| (counters, descriptions))
+                })
+        };
+        let (counters, descriptions) = match result {
+            Ok(counters) => counters,
+            Err(error) => {
+                warn!("Failed to List Performance Counters: {error}");
+                return Vec::new();
+            }
+        };
+
+        let text = |text: Result<&CStr, _>| text.unwrap_or_default().to_string_lossy().into_owned();
+        counters
+            .iter()
+            .zip(&descriptions)
+            .map(|(counter, description)| PerfCounter {
+                name: text(description.name_as_c_str()),
+                category: text(description.category_as_c_str()),
+                description: text(description.description_as_c_str()),
+                unit: counter.unit,
+                storage: counter.storage,
+                scope: counter.scope,
+            })
+            .collect()
+    }
+
+    /// Measures the per pass counters matching counter_names, None when there are none
+    /// passes name the queries cmd_begin and cmd_end are given the index of
+    pub fn new(
+        vk_instance: &VKInstance,
+        vk_device: &VKDevice,
+        counter_names: &[&str],
+        passes: &[&'static str],
+        frames_in_flight: u32,
+    ) -> Result<Option<Self>, vk::Result> {
+        let Some(loader) = vk_device.performance_query.clone() else {
+            return Ok(None);
+        };
+        let instance_loader =
+            performance_query::Instance::new(&vk_instance.entry, &vk_instance.instance);
+
+        let (mut indices, mut counters): (Vec<u32>, Vec<PerfCounter>) =
+            Self::available_counters(vk_instance, vk_device)
+                .into_iter()
+                .enumerate()
+                .filter(|(_, counter)| counter.per_pass() && counter.matches(counter_names))
+                .map(|(index, counter)| (index as u32, counter))
+                .unzip();
+        // the renderer submits each frame once, so everything has to fit in one pass
+        while !indices.is_empty() {
+            let pass_count = unsafe {
+                instance_loader.get_physical_device_queue_family_performance_query_passes(
+                    vk_device.p_device,
+                    &vk::QueryPoolPerformanceCreateInfoKHR::default()
+                        .queue_family_index(vk_device.queue_index)
+                        .counter_indices(&indices),
+                )
+            };
+            if pass_count <= 1 {
+                break;
+            }
+            let dropped = counters.pop().unwrap();
+            indices.pop();
+            warn!(
+                "Performance Counter {} Dropped, It Needs Another Submission",
+                dropped.name
+            );
+        }
+        if indices.is_empty() {
+            return Ok(None);
+        }
+
+        unsafe {
+            loader.acquire_profiling_lock(
+                &vk::AcquireProfilingLockInfoKHR::default().timeout(u64::MAX),
+            )?
+        };
+        let mut query_pools = Vec::new();
+        for _ in 0..frames_in_flight {
+            let mut performance_info = vk::QueryPoolPerformanceCreateInfoKHR::default()
+                .queue_family_index(vk_device.queue_index)
+                .counter_indices(&indices);
+            let query_pool = unsafe {
+                vk_device.device.create_query_pool(
+                    &vk::QueryPoolCreateInfo::default()
+                        .query_type(vk::QueryType::PERFORMANCE_QUERY_KHR)
+                        .query_count(passes.len().max(1) as u32)
+                        .push_next(&mut performance_info),
+                    None,
+                )
+            };
+            match query_pool {
+                Ok(query_pool) => query_pools.push(query_pool),
+                Err(error) => unsafe {
+                    for query_pool in query_pools {
+                        vk_device.device.destroy_query_pool(query_pool, None);
+                    }
+                    loader.release_profiling_lock();
+                    return Err(error);
+                },
+            }
+        }
+
+        info!(
+            "VK Performance Counters: {}",
+            counters
+                .iter()
+                .map(|counter| counter.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        Ok(Some(Self {
+            submitted: vec![false; query_pools.len()],
+            query_pools,
+            counters,
+            passes: passes.to_vec(),
+            loader,
+        }))
+    }
+
+    /// Counters of the passes frame_in_flight's last submission measured
+    /// its fence must have signalled, passes that weren't recorded are left out
+    pub fn read(&self, vk_device: &VKDevice, frame_in_flight: usize) -> Vec<PassCounters> {
+        if !self.submitted[frame_in_flight] {
+            return Vec::new();
+        }
+        let mut pass_counters = Vec::new();
+        for (query, pass) in self.passes.iter().enumerate() {
+            let mut results = vec![vk::PerformanceCounterResultKHR::default(); self.counters.len()];
+            // a query's result is the whole array, ash's wrapper expects one value per query
+            let size = size_of_val(results.as_slice());
+            let read = unsafe {
+                (vk_device.device.fp_v1_0().get_query_pool_results)(
+                    vk_device.device.handle(),
+                    self.query_pools[frame_in_flight],
+                    query as u32,
+                    1,
+                    size,
+                    results.as_mut_ptr().cast(),
+                    size as u64,
+                    vk::QueryResultFlags::empty(),
+                )
+            };
+            // NOT_READY for passes that weren't recorded
+            if read != vk::Result::SUCCESS {
+                continue;
+            }
+            pass_counters.push(PassCounters {
+                pass,
+                values: self
+                    .counters
+                    .iter()
+                    .zip(results)
+                    .map(|(counter, result)| PerfCounterValue {
+                        name: counter.name.clone(),
+                        unit: counter.unit,
+                        value: counter.value(result),
+                    })
+                    .collect(),
+            });
+        }
+        pass_counters
+    }
+
+    /// Readies frame_in_flight's queries for its next submission, even one of reused commands
+    /// # Safety
+    /// The gpu must be done with frame_in_flight's queries
+    pub unsafe fn reset(&mut self, vk_device: &VKDevice, frame_in_flight: usize) {
+        unsafe {
+            vk_device.device.reset_query_pool(
+                self.query_pools[frame_in_flight],
+                0,
+                self.passes.len().max(1) as u32,
+            )
+        };
+        self.submitted[frame_in_flight] = true;
+    }
+
+    /// Starts measuring pass
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering
+    pub unsafe fn cmd_begin(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        frame_in_flight: usize,
+        pass: usize,
+    ) {
+        unsafe {
+            vk_device.device.cmd_begin_query(
+                cmd_buffer,
+                self.query_pools[frame_in_flight],
+                pass as u32,
+                vk::QueryControlFlags::empty(),
+            )
+        };
+    }
+
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering after cmd_begin for pass
+    pub unsafe fn cmd_end(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        frame_in_flight: usize,
+        pass: usize,
+    ) {
+        unsafe {
+            vk_device.device.cmd_end_query(
+                cmd_buffer,
+                self.query_pools[frame_in_flight],
+                pass as u32,
+            )
+        };
+    }
+
+    /// # Safety
+    /// The gpu must not be using the queries
+    pub unsafe fn destroy(&mut self, vk_device: &VKDevice) {
+        unsafe {
+            for query_pool in self.query_pools.drain(..) {
+                vk_device.device.destroy_query_pool(query_pool, None);
+            }
+            self.loader.release_profiling_lock();
+        }
+    }
+}
+
+#[test]
+fn perf_counter_test() {
+    let counter = PerfCounter {
+        name: "L2 Cache Hit Rate".into(),
+        category: "Memory".into(),
+        description: String::new(),
+        unit: vk::PerformanceCounterUnitKHR::PERCENTAGE,
+        storage: vk::PerformanceCounterStorageKHR::FLOAT32,
+        scope: vk::PerformanceCounterScopeKHR::COMMAND,
+    };
+    assert!(counter.matches(&DEFAULT_COUNTERS));
+    assert!(!counter.matches(&["bandwidth"]));
+    assert!(counter.per_pass());
+    assert_eq!(
+        counter.value(vk::PerformanceCounterResultKHR { float32: 87.5 }),
+        87.5
+    );
+
+    let counter = PerfCounter {
+        storage: vk::PerformanceCounterStorageKHR::UINT64,
+        scope: vk::PerformanceCounterScopeKHR::COMMAND_BUFFER,
+        ..counter
+    };
+    assert!(!counter.per_pass());
+    assert_eq!(
+        counter.value(vk::PerformanceCounterResultKHR { uint64: 1 << 40 }),
+        (1u64 << 40) as f64
+    );
+}
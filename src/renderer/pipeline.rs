@@ -0,0 +1,347 @@
+use ash::vk;
+
+use crate::camera::DepthConvention;
+use crate::renderer::device::VKDevice;
+use crate::renderer::presentation::VKSwapchain;
+use crate::renderer::vertex::{VertexInput, VertexLayout};
+use crate::renderer::{DEPTH_FORMAT, depth_compare_op};
+
+/// How a pipeline's output is combined with what is already in its colour attachments
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Overwrites the attachment
+    #[default]
+    Opaque,
+    /// Straight alpha, src * a + dst * (1 - a)
+    Alpha,
+    /// src * a + dst, for glows and particles
+    Additive,
+    /// Colour already multiplied by alpha, src + dst * (1 - a)
+    Premultiplied,
+    /// dst * (1 - src) per channel, masks out subpixel text before its colour is added
+    Coverage,
+}
+
+impl BlendMode {
+    pub fn attachment_state(self) -> vk::PipelineColorBlendAttachmentState {
+        let state = vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .alpha_blend_op(vk::BlendOp::ADD);
+
+        let (src_color, dst_color, src_alpha, dst_alpha) = match self {
+            BlendMode::Opaque => return state.blend_enable(false),
+            BlendMode::Alpha => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            BlendMode::Additive => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::ONE,
+            ),
+            BlendMode::Premultiplied => (
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            BlendMode::Coverage => (
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::ONE_MINUS_SRC_COLOR,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+        };
+
+        state
+            .blend_enable(true)
+            .src_color_blend_factor(src_color)
+            .dst_color_blend_factor(dst_color)
+            .src_alpha_blend_factor(src_alpha)
+            .dst_alpha_blend_factor(dst_alpha)
+    }
+}
+
+/// Depth testing and writing for a pipeline
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DepthState {
+    pub test: bool,
+    pub write: bool,
+    pub compare_op: vk::CompareOp,
+}
+
+impl DepthState {
+    /// Ignores depth, the attachment can still be bound
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Opaque geometry, nearer wins whichever way depth runs
+    pub fn read_write(depth_convention: DepthConvention) -> Self {
+        Self {
+            test: true,
+            write: true,
+            compare_op: depth_compare_op(depth_convention),
+        }
+    }
+
+    /// Hidden behind geometry without hiding anything itself, for transparent and overlay passes
+    pub fn read_only(depth_convention: DepthConvention) -> Self {
+        Self {
+            write: false,
+            ..Self::read_write(depth_convention)
+        }
+    }
+
+    pub fn state(&self) -> vk::PipelineDepthStencilStateCreateInfo<'static> {
+        vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(self.test)
+            .depth_write_enable(self.write)
+            .depth_compare_op(self.compare_op)
+            .depth_bounds_test_enable(false)
+            .stencil_test_enable(false)
+    }
+}
+
+/// Graphics pipelines for dynamic rendering without writing out every create info
+/// defaults to a filled, unculled triangle list with no vertex buffers, opaque single sample output,
+/// no depth and a dynamic viewport and scissor so window resizes don't need a new pipeline
+/// Example Use:
+/// ```ignore
+/// let pipeline = GraphicsPipelineBuilder::new(&stages, pipeline_layout)
+///     .swapchain(vk_swapchain)
+///     .vertex_layout::<Vertex>()
+///     .cull_mode(vk::CullModeFlags::BACK)
+///     .depth(DepthState::read_only(depth_convention))
+///     .blend(BlendMode::Alpha)
+///     .build(vk_device)?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct GraphicsPipelineBuilder<'a> {
+    pub stages: &'a [vk::PipelineShaderStageCreateInfo<'a>],
+    pub layout: vk::PipelineLayout,
+    pub vertex_input: VertexInput,
+    pub topology: vk::PrimitiveTopology,
+    pub polygon_mode: vk::PolygonMode,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+    pub samples: vk::SampleCountFlags,
+    pub depth: DepthState,
+    /// applied to every colour attachment
+    pub blend: BlendMode,
+    pub color_formats: Vec<vk::Format>,
+    /// UNDEFINED for pipelines rendering without a depth attachment
+    pub depth_format: vk::Format,
+    pub dynamic_states: Vec<vk::DynamicState>,
+}
+
+impl<'a> GraphicsPipelineBuilder<'a> {
+    pub fn new(
+        stages: &'a [vk::PipelineShaderStageCreateInfo<'a>],
+        layout: vk::PipelineLayout,
+    ) -> Self {
+        Self {
+            stages,
+            layout,
+            vertex_input: VertexInput::none(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            samples: vk::SampleCountFlags::TYPE_1,
+            depth: DepthState::disabled(),
+            blend: BlendMode::Opaque,
+            color_formats: Vec::new(),
+            depth_format: vk::Format::UNDEFINED,
+            dynamic_states: vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR],
+        }
+    }
+
+    /// Renders into the scene pass attachments, the scene format with the swapchain's depth and msaa
+    pub fn swapchain(self, vk_swapchain: &VKSwapchain) -> Self {
+        self.color_formats(&[vk_swapchain.scene_format()])
+            .depth_format(DEPTH_FORMAT)
+            .samples(vk_swapchain.samples)
+    }
+
+    /// Reads V from a vertex buffer at binding 0
+    pub fn vertex_layout<V: VertexLayout>(mut self) -> Self {
+        self.vertex_input = VertexInput::of::<V>();
+        self
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Anything but FILL needs the fillModeNonSolid feature
+    pub fn polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: vk::FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    pub fn depth(mut self, depth: DepthState) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn blend(mut self, blend: BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn color_formats(mut self, color_formats: &[vk::Format]) -> Self {
+        self.color_formats = color_formats.to_vec();
+        self
+    }
+
+    pub fn depth_format(mut self, depth_format: vk::Format) -> Self {
+        self.depth_format = depth_format;
+        self
+    }
+
+    /// Replaces the default viewport and scissor, include them if the pipeline still needs them
+    pub fn dynamic_states(mut self, dynamic_states: &[vk::DynamicState]) -> Self {
+        self.dynamic_states = dynamic_states.to_vec();
+        self
+    }
+
+    /// Adds to the dynamic states already set
+    pub fn dynamic_state(mut self, dynamic_state: vk::DynamicState) -> Self {
+        if !self.dynamic_states.contains(&dynamic_state) {
+            self.dynamic_states.push(dynamic_state);
+        }
+        self
+    }
+
+    fn color_blend_attachments(&self) -> Vec<vk::PipelineColorBlendAttachmentState> {
+        vec![self.blend.attachment_state(); self.color_formats.len()]
+    }
+
+    /// Creates the pipeline through the device's pipeline cache
+    pub fn build(&self, vk_device: &VKDevice) -> Result<vk::Pipeline, vk::Result> {
+        let dynamic_state =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&self.dynamic_states);
+
+        let vertex_input_state = self.vertex_input.state();
+
+        let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(self.topology)
+            .primitive_restart_enable(false);
+
+        // only counts, the viewport and scissor are set while recording
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+
+        // wide lines are an optional feature
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .depth_clamp_enable(false)
+            .rasterizer_discard_enable(false)
+            .polygon_mode(self.polygon_mode)
+            .line_width(1.0)
+            .cull_mode(self.cull_mode)
+            .front_face(self.front_face)
+            .depth_bias_enable(false);
+
+        let multisample_state = vk::PipelineMultisampleStateCreateInfo::default()
+            .sample_shading_enable(false)
+            .rasterization_samples(self.samples);
+
+        let depth_stencil_state = self.depth.state();
+
+        let color_blend_attachments = self.color_blend_attachments();
+        let color_blend_state =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&color_blend_attachments);
+
+        let mut rendering_info = vk::PipelineRenderingCreateInfo::default()
+            .color_attachment_formats(&self.color_formats)
+            .depth_attachment_format(self.depth_format);
+
+        let create_infos = &[vk::GraphicsPipelineCreateInfo::default()
+            .dynamic_state(&dynamic_state)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_state)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample_state)
+            .depth_stencil_state(&depth_stencil_state)
+            .color_blend_state(&color_blend_state)
+            .layout(self.layout)
+            .push_next(&mut rendering_info)
+            .stages(self.stages)];
+
+        // the error can come with the pipelines that did get created, there is only one here
+        unsafe {
+            vk_device
+                .device
+                .create_graphics_pipelines(vk_device.pipeline_cache.cache, create_infos, None)
+                .map(|pipelines| pipelines[0])
+                .map_err(|(_, error)| error)
+        }
+    }
+}
+
+#[test]
+fn graphics_pipeline_builder_test() {
+    let builder = GraphicsPipelineBuilder::new(&[], vk::PipelineLayout::null())
+        .color_formats(&[vk::Format::B8G8R8A8_SRGB, vk::Format::R16G16B16A16_SFLOAT])
+        .blend(BlendMode::Alpha)
+        .depth(DepthState::read_only(DepthConvention::ReverseZ))
+        .dynamic_state(vk::DynamicState::LINE_WIDTH)
+        .dynamic_state(vk::DynamicState::SCISSOR);
+
+    assert_eq!(builder.topology, vk::PrimitiveTopology::TRIANGLE_LIST);
+    assert_eq!(
+        builder.dynamic_states,
+        [
+            vk::DynamicState::VIEWPORT,
+            vk::DynamicState::SCISSOR,
+            vk::DynamicState::LINE_WIDTH
+        ]
+    );
+
+    let depth = builder.depth.state();
+    assert_eq!(depth.depth_test_enable, vk::TRUE);
+    assert_eq!(depth.depth_write_enable, vk::FALSE);
+    assert_eq!(depth.depth_compare_op, vk::CompareOp::GREATER_OR_EQUAL);
+
+    // one blend state per colour attachment
+    let attachments = builder.color_blend_attachments();
+    assert_eq!(attachments.len(), 2);
+    assert_eq!(attachments[1].blend_enable, vk::TRUE);
+    assert_eq!(
+        attachments[1].dst_color_blend_factor,
+        vk::BlendFactor::ONE_MINUS_SRC_ALPHA
+    );
+
+    let opaque = BlendMode::Opaque.attachment_state();
+    assert_eq!(opaque.blend_enable, vk::FALSE);
+    assert_eq!(opaque.color_write_mask, vk::ColorComponentFlags::RGBA);
+    assert_eq!(
+        BlendMode::Premultiplied
+            .attachment_state()
+            .src_color_blend_factor,
+        vk::BlendFactor::ONE
+    );
+}
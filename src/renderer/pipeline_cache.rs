@@ -0,0 +1,179 @@
+use ash::{Device, vk};
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Where pipeline caches are kept unless InstanceOptions::pipeline_cache_directory says otherwise
+pub const PIPELINE_CACHE_DIRECTORY: &str = "cache";
+
+// length, header version, vendor id, device id and the cache uuid
+const PIPELINE_CACHE_HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+#[derive(Debug, Error)]
+pub enum PipelineCacheError {
+    #[error("failed to read pipeline cache data: {0}")]
+    Vulkan(#[from] vk::Result),
+    #[error("failed to write pipeline cache: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Compiled pipelines kept between runs so shaders aren't recompiled on every start
+/// one file per device and driver, a driver update changes the uuid and starts a new cache
+/// Example Use:
+/// ```ignore
+/// vk_device.device.create_graphics_pipelines(vk_device.pipeline_cache.cache, &create_infos, None)
+/// ```
+pub struct VKPipelineCache {
+    pub cache: vk::PipelineCache,
+    /// None keeps the cache in memory only
+    pub path: Option<PathBuf>,
+}
+
+impl VKPipelineCache {
+    /// Creates the cache seeded from directory when it holds one written for this device
+    /// falls back to an empty cache on bad data and to a null cache if even that can't be created
+    pub fn new(
+        device: &Device,
+        properties: &vk::PhysicalDeviceProperties,
+        directory: Option<&Path>,
+    ) -> Self {
+        let path = directory.map(|directory| directory.join(pipeline_cache_file_name(properties)));
+        let initial_data = path
+            .as_deref()
+            .and_then(|path| fs::read(path).ok())
+            .filter(|data| {
+                let matches = header_matches(data, properties);
+                if !matches {
+                    warn!("Pipeline Cache Discarded: written for another device or driver");
+                }
+                matches
+            })
+            .unwrap_or_default();
+
+        let create = |data: &[u8]| unsafe {
+            device.create_pipeline_cache(
+                &vk::PipelineCacheCreateInfo::default().initial_data(data),
+                None,
+            )
+        };
+        let cache = match create(&initial_data) {
+            Ok(cache) => {
+                if !initial_data.is_empty() {
+                    info!("VK Pipeline Cache: loaded {} bytes", initial_data.len());
+                }
+                cache
+            }
+            Err(error) => {
+                warn!("Failed To Create Pipeline Cache: {error}");
+                create(&[]).unwrap_or_default()
+            }
+        };
+
+        Self { cache, path }
+    }
+
+    /// Writes the cache to path, replacing the file only once the new one is complete
+    /// # Safety
+    /// cache must have been created from device
+    pub unsafe fn save(&self, device: &Device) -> Result<(), PipelineCacheError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if self.cache == vk::PipelineCache::null() {
+            return Ok(());
+        }
+        let data = unsafe { device.get_pipeline_cache_data(self.cache)? };
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, &data)?;
+        fs::rename(&temp_path, path)?;
+        info!("VK Pipeline Cache: saved {} bytes", data.len());
+        Ok(())
+    }
+
+    /// Saves and then destroys the cache
+    /// # Safety
+    /// No pipeline creation may be using the cache
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        unsafe {
+            if let Err(error) = self.save(device) {
+                warn!("Failed To Save Pipeline Cache: {error}");
+            }
+            device.destroy_pipeline_cache(self.cache, None);
+        }
+        self.cache = vk::PipelineCache::null();
+    }
+}
+
+/// Vendor, device and the driver's cache uuid, caches from other drivers are never loaded
+pub fn pipeline_cache_file_name(properties: &vk::PhysicalDeviceProperties) -> String {
+    let uuid: String = properties
+        .pipeline_cache_uuid
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!(
+        "pipelines-{:04x}-{:04x}-{uuid}.bin",
+        properties.vendor_id, properties.device_id
+    )
+}
+
+/// Whether data starts with a VK_PIPELINE_CACHE_HEADER_VERSION_ONE header for this device
+/// drivers are meant to reject foreign caches but some crash on them instead
+pub fn header_matches(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+    if data.len() < PIPELINE_CACHE_HEADER_SIZE {
+        return false;
+    }
+    let word = |index: usize| {
+        u32::from_ne_bytes([
+            data[index * 4],
+            data[index * 4 + 1],
+            data[index * 4 + 2],
+            data[index * 4 + 3],
+        ])
+    };
+    word(0) as usize >= PIPELINE_CACHE_HEADER_SIZE
+        && word(1) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && word(2) == properties.vendor_id
+        && word(3) == properties.device_id
+        && data[16..PIPELINE_CACHE_HEADER_SIZE] == properties.pipeline_cache_uuid
+}
+
+#[test]
+fn pipeline_cache_test() {
+    let properties = vk::PhysicalDeviceProperties {
+        vendor_id: 0x10de,
+        device_id: 0x2684,
+        pipeline_cache_uuid: [7; vk::UUID_SIZE],
+        ..Default::default()
+    };
+    assert_eq!(
+        pipeline_cache_file_name(&properties),
+        format!("pipelines-10de-2684-{}.bin", "07".repeat(vk::UUID_SIZE))
+    );
+
+    let mut data = Vec::new();
+    data.extend((PIPELINE_CACHE_HEADER_SIZE as u32).to_ne_bytes());
+    data.extend(1u32.to_ne_bytes());
+    data.extend(0x10deu32.to_ne_bytes());
+    data.extend(0x2684u32.to_ne_bytes());
+    data.extend([7; vk::UUID_SIZE]);
+    data.extend([0xab; 64]);
+    assert!(header_matches(&data, &properties));
+    assert!(!header_matches(&data[..20], &properties));
+
+    // a driver update changes the uuid
+    let updated = vk::PhysicalDeviceProperties {
+        pipeline_cache_uuid: [8; vk::UUID_SIZE],
+        ..properties
+    };
+    assert!(!header_matches(&data, &updated));
+    let other_device = vk::PhysicalDeviceProperties {
+        device_id: 0x2704,
+        ..properties
+    };
+    assert!(!header_matches(&data, &other_device));
+}
@@ -1,17 +1,28 @@
 use crate::renderer::VKInstance;
+use crate::renderer::debug::instance_extension_available;
+use crate::renderer::image::VKImage;
+use crate::renderer::quirks::Quirks;
+use crate::renderer::timing::{FramePacer, MAX_SWAP_INTERVAL, PresentStats, VKDisplayTiming};
 use crate::utils::ReplaceWith;
 use ash::{
-    khr::{surface, swapchain},
+    Entry,
+    khr::{
+        android_surface, surface, swapchain, wayland_surface, win32_surface, xcb_surface,
+        xlib_surface,
+    },
     vk::{self, Handle},
 };
-use gpu_allocator::vulkan;
+use log::warn;
 use std::error;
+use std::ffi::CStr;
+use std::time::{Duration, Instant};
+use thiserror::Error;
 use winit::{
-    raw_window_handle::{HasDisplayHandle, HasWindowHandle},
+    raw_window_handle::{HasDisplayHandle, HasWindowHandle, RawDisplayHandle},
     window::Window,
 };
 
-use crate::renderer::{VKContext, device::VKDevice};
+use crate::renderer::{DEPTH_FORMAT, HDR_FORMAT, device::VKDevice, is_quarter_rotation};
 
 pub struct VKSurface {
     pub surface: vk::SurfaceKHR,
@@ -19,15 +30,35 @@ pub struct VKSurface {
 }
 
 impl VKSurface {
+    /// Creates the surface with the extension display_vk_ext picked
+    /// xlib windows go through VK_KHR_xcb_surface when VK_KHR_xlib_surface is missing
     pub fn new(vk_instance: &VKInstance, window: &Window) -> Result<Self, Box<dyn error::Error>> {
-        let surface = unsafe {
-            ash_window::create_surface(
-                &vk_instance.entry,
-                &vk_instance.instance,
-                window.display_handle()?.as_raw(),
-                window.window_handle()?.as_raw(),
-                None,
-            )?
+        let display_handle = window.display_handle()?.as_raw();
+        let window_handle = window.window_handle()?.as_raw();
+        let extension = choose_surface_extension(display_handle, |name| {
+            instance_extension_available(&vk_instance.entry, name)
+        })?;
+
+        let surface = match (display_handle, window_handle) {
+            #[cfg(all(
+                unix,
+                not(any(target_os = "android", target_os = "macos", target_os = "ios"))
+            ))]
+            (
+                RawDisplayHandle::Xlib(display),
+                winit::raw_window_handle::RawWindowHandle::Xlib(window),
+            ) if extension == xcb_surface::NAME => unsafe {
+                xlib_window_xcb_surface(vk_instance, display, window)?
+            },
+            _ => unsafe {
+                ash_window::create_surface(
+                    &vk_instance.entry,
+                    &vk_instance.instance,
+                    display_handle,
+                    window_handle,
+                    None,
+                )?
+            },
         };
 
         let surface_loader = surface::Instance::new(&vk_instance.entry, &vk_instance.instance);
@@ -62,6 +93,146 @@ impl VKSurface {
     }
 }
 
+#[derive(Debug, Error)]
+pub enum SurfaceError {
+    #[error("{display} windows can't be presented to, {extension} is not available")]
+    MissingExtension {
+        display: &'static str,
+        extension: String,
+    },
+    #[error("{0} windows are not supported")]
+    UnsupportedDisplay(&'static str),
+}
+
+/// Name of the windowing system behind display, used in errors and logs
+pub fn display_name(display: RawDisplayHandle) -> &'static str {
+    match display {
+        RawDisplayHandle::Windows(_) => "Win32",
+        RawDisplayHandle::Wayland(_) => "Wayland",
+        RawDisplayHandle::Xlib(_) => "Xlib",
+        RawDisplayHandle::Xcb(_) => "XCB",
+        RawDisplayHandle::Android(_) => "Android",
+        RawDisplayHandle::AppKit(_) => "AppKit",
+        RawDisplayHandle::UiKit(_) => "UIKit",
+        _ => "Unknown",
+    }
+}
+
+/// Platform surface extensions that can present to display, most preferred first
+/// VK_KHR_surface is needed alongside whichever gets used
+pub fn surface_extension_options(
+    display: RawDisplayHandle,
+) -> Result<&'static [&'static CStr], SurfaceError> {
+    Ok(match display {
+        RawDisplayHandle::Windows(_) => &[win32_surface::NAME],
+        RawDisplayHandle::Wayland(_) => &[wayland_surface::NAME],
+        // some drivers and XWayland setups only expose one of the two X11 extensions
+        // an xlib display can reach xcb through libX11-xcb, the other way round is not possible
+        #[cfg(all(
+            unix,
+            not(any(target_os = "android", target_os = "macos", target_os = "ios"))
+        ))]
+        RawDisplayHandle::Xlib(_) => &[xlib_surface::NAME, xcb_surface::NAME],
+        #[cfg(not(all(
+            unix,
+            not(any(target_os = "android", target_os = "macos", target_os = "ios"))
+        )))]
+        RawDisplayHandle::Xlib(_) => &[xlib_surface::NAME],
+        RawDisplayHandle::Xcb(_) => &[xcb_surface::NAME],
+        RawDisplayHandle::Android(_) => &[android_surface::NAME],
+        RawDisplayHandle::AppKit(_) | RawDisplayHandle::UiKit(_) => {
+            &[ash::ext::metal_surface::NAME]
+        }
+        _ => return Err(SurfaceError::UnsupportedDisplay(display_name(display))),
+    })
+}
+
+/// Picks the first platform surface extension for display that available reports
+/// errors name what is missing instead of failing later at instance creation
+/// Example Use:
+/// ```ignore
+/// let extension = choose_surface_extension(display_handle, |name| {
+///     instance_extension_available(&entry, name)
+/// })?;
+/// ```
+pub fn choose_surface_extension(
+    display: RawDisplayHandle,
+    available: impl Fn(&CStr) -> bool,
+) -> Result<&'static CStr, SurfaceError> {
+    let missing = |extension: String| SurfaceError::MissingExtension {
+        display: display_name(display),
+        extension,
+    };
+
+    if !available(surface::NAME) {
+        return Err(missing(surface::NAME.to_string_lossy().into_owned()));
+    }
+
+    let options = surface_extension_options(display)?;
+    options
+        .iter()
+        .copied()
+        .find(|extension| available(extension))
+        .ok_or_else(|| {
+            missing(
+                options
+                    .iter()
+                    .map(|extension| extension.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(" or "),
+            )
+        })
+}
+
+/// Xlib and xcb share the X server, the window id is the same in both
+/// # Safety
+/// display and window must outlive the surface
+#[cfg(all(
+    unix,
+    not(any(target_os = "android", target_os = "macos", target_os = "ios"))
+))]
+unsafe fn xlib_window_xcb_surface(
+    vk_instance: &VKInstance,
+    display: winit::raw_window_handle::XlibDisplayHandle,
+    window: winit::raw_window_handle::XlibWindowHandle,
+) -> Result<vk::SurfaceKHR, Box<dyn error::Error>> {
+    let xlib_xcb = x11_dl::xlib_xcb::Xlib_xcb::open()?;
+    let display = display
+        .display
+        .ok_or("Xlib Display Handle Has No Display")?;
+    let connection = unsafe { (xlib_xcb.XGetXCBConnection)(display.as_ptr().cast()) };
+    if connection.is_null() {
+        return Err("Failed to Get XCB Connection From Xlib Display".into());
+    }
+
+    let create_info = vk::XcbSurfaceCreateInfoKHR::default()
+        .connection(connection)
+        .window(window.window as vk::xcb_window_t);
+    let xcb_loader = xcb_surface::Instance::new(&vk_instance.entry, &vk_instance.instance);
+
+    Ok(unsafe { xcb_loader.create_xcb_surface(&create_info, None)? })
+}
+
+/// Instance extensions needed to create a surface for window, see choose_surface_extension
+pub fn surface_instance_extensions(
+    entry: &Entry,
+    window: &Window,
+) -> Result<[&'static CStr; 2], Box<dyn error::Error>> {
+    let display = window.display_handle()?.as_raw();
+    let extension =
+        choose_surface_extension(display, |name| instance_extension_available(entry, name))?;
+
+    if Some(&extension) != surface_extension_options(display)?.first() {
+        warn!(
+            "{} Surface Extension Not Found, Falling Back to {}",
+            display_name(display),
+            extension.to_string_lossy()
+        );
+    }
+
+    Ok([surface::NAME, extension])
+}
+
 pub struct VKSwapchainCapabilities {
     pub surface_capibilities: vk::SurfaceCapabilitiesKHR,
     pub surface_formats: Vec<vk::SurfaceFormatKHR>,
@@ -96,23 +267,57 @@ impl VKSwapchainCapabilities {
     }
 
     // if Mailbox Supporeted Return Mailbox else FIFO
-    pub fn ideal_present_mode(&self) -> vk::PresentModeKHR {
+    // drivers with the NO_MAILBOX quirk always get FIFO
+    pub fn ideal_present_mode(&self, quirks: Quirks) -> vk::PresentModeKHR {
         self.present_modes
             .iter()
             .cloned()
+            .filter(|_| !quirks.contains(Quirks::NO_MAILBOX))
             .find(|present_mode| *present_mode == vk::PresentModeKHR::MAILBOX)
             .unwrap_or(vk::PresentModeKHR::FIFO)
     }
 
-    // if 8bit BGRA in SRGB Colour Space pick it Else first Option
+    // if 8bit BGRA or RGBA in SRGB Colour Space pick it Else first Option
+    // both keep an alpha channel for transparent windows
     pub fn ideal_surface_format(&self) -> vk::SurfaceFormatKHR {
-        self.surface_formats
+        [vk::Format::B8G8R8A8_SRGB, vk::Format::R8G8B8A8_SRGB]
             .iter()
+            .find_map(|format| {
+                self.surface_formats
+                    .iter()
+                    .find(|surface_format| surface_format.format == *format)
+            })
             .cloned()
-            .find(|surface_format| surface_format.format == vk::Format::B8G8R8A8_SRGB)
             .unwrap_or(self.surface_formats[0])
     }
 
+    /// How the swapchain images get blended with what is behind the window
+    /// transparent windows prefer premultiplied alpha, anything else stays opaque where it can
+    pub fn ideal_composite_alpha(&self, transparent: bool) -> vk::CompositeAlphaFlagsKHR {
+        let preferred = if transparent {
+            [
+                vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+                vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+                vk::CompositeAlphaFlagsKHR::INHERIT,
+                vk::CompositeAlphaFlagsKHR::OPAQUE,
+            ]
+        } else {
+            [
+                vk::CompositeAlphaFlagsKHR::OPAQUE,
+                vk::CompositeAlphaFlagsKHR::INHERIT,
+                vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+                vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+            ]
+        };
+
+        // every surface supports at least one mode
+        let supported = self.surface_capibilities.supported_composite_alpha;
+        preferred
+            .into_iter()
+            .find(|composite_alpha| supported.contains(*composite_alpha))
+            .unwrap_or(vk::CompositeAlphaFlagsKHR::OPAQUE)
+    }
+
     // Tries to return number of images for tripple buffering if that does not work then tries double buffering else min
     pub fn ideal_n_images(&self) -> u32 {
         let mut image_count = 3;
@@ -157,10 +362,19 @@ pub struct VKSwapchain {
     pub swapchain: vk::SwapchainKHR,
     pub image_views: Vec<vk::ImageView>,
     pub images: Vec<vk::Image>,
-    pub depth_image_view: vk::ImageView,
-    pub depth_image: vk::Image,
-    pub depth_allocation: vulkan::Allocation,
+    pub depth_image: VKImage,
+    /// multisampled colour image resolved into the swapchain images, null without msaa
+    pub msaa_image: VKImage,
+    /// sample count of the depth and msaa images
+    pub samples: vk::SampleCountFlags,
+    /// whether the scene pass draws in HDR_FORMAT instead of the swapchain format, see scene_format
+    pub hdr_scene: bool,
+    /// whether the window was asked to show what is behind it where alpha is below 1
+    pub transparent: bool,
+    /// blending with other windows the surface ended up with, OPAQUE ignores alpha
+    pub composite_alpha: vk::CompositeAlphaFlagsKHR,
     pub image_extent: vk::Extent2D,
+    pub pre_transform: vk::SurfaceTransformFlagsKHR,
     pub swapchain_loader: swapchain::Device,
     pub capibilities: VKSwapchainCapabilities,
 }
@@ -172,6 +386,8 @@ impl VKSwapchain {
         vk_surface: &VKSurface,
         window: &Window,
         vk_swapchain_old: Option<vk::SwapchainKHR>,
+        samples: vk::SampleCountFlags,
+        transparent: bool,
     ) -> Result<Self, vk::Result> {
         let physical_device = vk_device.p_device;
         let instance = &vk_instance.instance;
@@ -180,8 +396,15 @@ impl VKSwapchain {
         let capibilities = VKSwapchainCapabilities::new(vk_surface, physical_device)?;
 
         let ideal_surface_format = capibilities.ideal_surface_format();
-
-        let image_extent = capibilities.get_extent(window);
+        let composite_alpha = capibilities.ideal_composite_alpha(transparent);
+
+        // swapchain images are in the display's native orientation
+        // the surface extent is reported in the current orientation
+        let pre_transform = capibilities.surface_capibilities.current_transform;
+        let mut image_extent = capibilities.get_extent(window);
+        if is_quarter_rotation(pre_transform) {
+            std::mem::swap(&mut image_extent.width, &mut image_extent.height);
+        }
 
         let mut swapchain_create_info = vk::SwapchainCreateInfoKHR::default()
             .surface(vk_surface.surface)
@@ -192,9 +415,9 @@ impl VKSwapchain {
             .image_array_layers(1) // always 1 for non sterioscopic displays
             .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST) // opperations to be used on image can also be transfer
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE) // single queue can access image
-            .pre_transform(capibilities.surface_capibilities.current_transform) // Don't Rotate Image
-            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE) // Alpha Blending with other windows = Opaque
-            .present_mode(capibilities.ideal_present_mode())
+            .pre_transform(pre_transform) // renderer rotates the image itself, see pre_rotation
+            .composite_alpha(composite_alpha) // Alpha Blending with other windows, see ideal_composite_alpha
+            .present_mode(capibilities.ideal_present_mode(vk_device.quirks))
             .clipped(true); // ignore Pixel covered by other windows
 
         if let Some(vk_swapchain_old) = vk_swapchain_old {
@@ -214,31 +437,83 @@ impl VKSwapchain {
             vk::ImageAspectFlags::COLOR,
         )?;
 
-        let (depth_image, depth_allocation) = vk_device.create_image(
-            image_extent,
-            vk::Format::D32_SFLOAT,
-            vk::ImageTiling::OPTIMAL,
-            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
-            gpu_allocator::MemoryLocation::GpuOnly,
-        )?;
-
-        let depth_image_view = vk_device.create_image_view(
-            depth_image,
-            vk::Format::D32_SFLOAT,
-            vk::ImageAspectFlags::DEPTH,
-        )?;
-
-        Ok(Self {
+        let mut vk_swapchain = Self {
             swapchain,
             image_views,
             images,
-            depth_image_view,
-            depth_image,
-            depth_allocation,
+            depth_image: VKImage::default(),
+            msaa_image: VKImage::default(),
+            samples,
+            hdr_scene: false,
+            transparent,
+            composite_alpha,
             image_extent,
+            pre_transform,
             swapchain_loader,
             capibilities,
-        })
+        };
+        vk_swapchain.create_attachments(vk_device)?;
+
+        Ok(vk_swapchain)
+    }
+
+    /// Colour format the scene pass and every pipeline drawing in it use
+    pub fn scene_format(&self) -> vk::Format {
+        if self.hdr_scene {
+            HDR_FORMAT
+        } else {
+            self.capibilities.ideal_surface_format().format
+        }
+    }
+
+    /// Recreates the depth and msaa images with a new sample count
+    /// pipelines rendering to the swapchain need to be recreated to match
+    /// # Safety
+    /// The attachments must not be in use by the gpu
+    pub unsafe fn set_samples(
+        &mut self,
+        vk_device: &mut VKDevice,
+        samples: vk::SampleCountFlags,
+    ) -> Result<(), vk::Result> {
+        unsafe { self.destroy_attachments(vk_device) };
+        self.samples = samples;
+        self.create_attachments(vk_device)
+    }
+
+    // depth and msaa images are shared by every swapchain image
+    fn create_attachments(&mut self, vk_device: &mut VKDevice) -> Result<(), vk::Result> {
+        self.depth_image = VKImage::new(
+            vk_device,
+            self.image_extent,
+            DEPTH_FORMAT,
+            // copied out to build the occlusion culling pyramid
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+            self.samples,
+            vk::ImageAspectFlags::DEPTH,
+        )?;
+
+        if self.samples != vk::SampleCountFlags::TYPE_1 {
+            let format = self.capibilities.ideal_surface_format().format;
+
+            // only lives until it is resolved at the end of the pass
+            self.msaa_image = VKImage::new(
+                vk_device,
+                self.image_extent,
+                format,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                self.samples,
+                vk::ImageAspectFlags::COLOR,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    unsafe fn destroy_attachments(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            self.depth_image.destroy(vk_device);
+            self.msaa_image.destroy(vk_device);
+        }
     }
 
     fn create_image_views(
@@ -261,14 +536,7 @@ impl VKSwapchain {
             self.image_views
                 .iter()
                 .for_each(|iv| vk_device.device.destroy_image_view(*iv, None));
-            vk_device
-                .device
-                .destroy_image_view(self.depth_image_view, None);
-            vk_device
-                .mem_allocator
-                .free(std::mem::take(&mut self.depth_allocation))
-                .unwrap_unchecked();
-            vk_device.device.destroy_image(self.depth_image, None);
+            self.destroy_attachments(vk_device);
             self.swapchain_loader
                 .destroy_swapchain(self.swapchain, None);
         }
@@ -293,9 +561,12 @@ impl VKSwapchain {
             vk_surface,
             window,
             Some(old_swapchain),
+            self.samples,
+            self.transparent,
         ) {
             // if succesfull replace old swapchain with new
-            Ok(new_swap) => {
+            Ok(mut new_swap) => {
+                new_swap.hdr_scene = self.hdr_scene;
                 self.replace_with(|mut old_swap| unsafe {
                     old_swap.destroy(vk_device);
                     new_swap
@@ -310,6 +581,15 @@ impl VKSwapchain {
 
 impl<F> ReplaceWith<F> for VKSwapchain {}
 
+/// Window a VKPresent presents to, every window has its own surface and swapchain
+/// on the instance and device they share, see VKContext::present_target
+pub struct PresentTarget<'a> {
+    pub instance: &'a VKInstance,
+    pub device: &'a mut VKDevice,
+    pub surface: &'a VKSurface,
+    pub swapchain: &'a mut VKSwapchain,
+}
+
 /// Manages Syncronisation objects and part of algo for presenting to screen
 /// when rendering a frame
 /// use in this order:
@@ -328,6 +608,18 @@ pub struct VKPresent {
     img_in_flight: Vec<vk::Fence>,
 
     swap_invalid: bool,
+    // resizes arrive many times a frame while dragging, the swapchain is rebuilt once per frame
+    rebuilt_this_frame: bool,
+    swapchain_rebuilds: u64,
+
+    display_timing: Option<VKDisplayTiming>,
+    present_stats: PresentStats,
+    frame_pacer: FramePacer,
+    // cpu side present times are measured from here without display timing
+    created_time: Option<Instant>,
+    // when the cpu started on the frame being presented and when the last one was presented
+    frame_started: Option<Instant>,
+    last_present: Option<Instant>,
 }
 
 pub struct ToRenderInfo {
@@ -352,26 +644,145 @@ impl VKPresent {
     pub unsafe fn max_frames(
         mut self,
         frames: u32,
-        vk_ctx: &VKContext,
+        target: PresentTarget<'_>,
     ) -> Result<Self, vk::Result> {
         self.max_frames = frames;
-        if self.max_frames > vk_ctx.vulkan_swapchain.images.len() as u32 {
-            self.max_frames = vk_ctx.vulkan_swapchain.images.len() as u32
+        if self.max_frames > target.swapchain.images.len() as u32 {
+            self.max_frames = target.swapchain.images.len() as u32
         }
         self.frame %= self.max_frames;
-        self.img_aquired_index = (vk_ctx.vulkan_swapchain.images.len() as u32) - 1;
-        unsafe { self.recreate_sync(vk_ctx)? };
+        self.img_aquired_index = (target.swapchain.images.len() as u32) - 1;
+        unsafe { self.recreate_sync(&target)? };
         Ok(self)
     }
 
+    /// Starts collecting present statistics
+    /// uses VK_GOOGLE_display_timing if it was enabled, else measures presents on the cpu
+    pub fn enable_present_timing(&mut self, target: PresentTarget<'_>, window: &Window) {
+        self.display_timing = VKDisplayTiming::new(target.instance, target.device);
+        self.created_time = Some(Instant::now());
+        self.update_refresh_duration(&target, window);
+    }
+
+    pub fn present_stats(&self) -> PresentStats {
+        self.present_stats
+    }
+
+    pub fn frame_pacer(&self) -> FramePacer {
+        self.frame_pacer
+    }
+
+    /// Lets frames stay up for more than one refresh when they keep missing vsync, on by default
+    /// needs present timing, see enable_present_timing
+    pub fn set_frame_pacing(&mut self, enabled: bool) {
+        self.frame_pacer =
+            FramePacer::default().max_swap_interval(if enabled { MAX_SWAP_INTERVAL } else { 1 });
+    }
+
+    /// When the next frame should start to keep the pacer's cadence, None if it can start now
+    /// with display timing the driver holds frames back instead, so this is only for the cpu fallback
+    pub fn paced_frame_start(&self, now: Instant) -> Option<Instant> {
+        if self.display_timing.is_some() || self.frame_pacer.swap_interval < 2 {
+            return None;
+        }
+        let refresh = self.present_stats.refresh_duration?;
+        // presenting waits for the vsync, so start a refresh before the frame is due
+        self.last_present
+            .map(|last_present| last_present + refresh * (self.frame_pacer.swap_interval - 1))
+            .filter(|&frame_start| frame_start > now)
+    }
+
+    // the window can move to a display with a different refresh rate, so this is redone on rebuild
+    fn update_refresh_duration(&mut self, target: &PresentTarget<'_>, window: &Window) {
+        let driver_refresh = self.display_timing.as_ref().and_then(|display_timing| {
+            display_timing
+                .refresh_duration(target.swapchain.swapchain)
+                .ok()
+        });
+
+        self.present_stats.refresh_duration = driver_refresh.or_else(|| {
+            window
+                .current_monitor()
+                .and_then(|monitor| monitor.refresh_rate_millihertz())
+                .map(|millihertz| Duration::from_secs_f64(1000.0 / millihertz as f64))
+        });
+    }
+
+    fn record_present_timing(&mut self, target: &PresentTarget<'_>) {
+        let swap_interval = self.frame_pacer.swap_interval;
+        // the pacer needs to know how long frames stay up
+        let refresh = self.present_stats.refresh_duration;
+        match self.display_timing.as_mut() {
+            Some(display_timing) => {
+                let past_timings = display_timing
+                    .past_timings(target.swapchain.swapchain)
+                    .unwrap_or_default();
+                for timing in past_timings {
+                    let missed = self.present_stats.record_paced_present(
+                        timing.actual_present_time,
+                        timing.present_margin,
+                        swap_interval,
+                    );
+                    if let Some(refresh) = refresh {
+                        self.frame_pacer.record_present(
+                            missed > 0,
+                            Duration::from_nanos(timing.present_margin),
+                            refresh,
+                        );
+                    }
+                }
+            }
+            None => {
+                if let Some(created_time) = self.created_time {
+                    let now = Instant::now();
+                    let present_time = now.duration_since(created_time).as_nanos() as u64;
+                    let missed =
+                        self.present_stats
+                            .record_paced_present(present_time, 0, swap_interval);
+                    // without a margin from the driver, headroom is what the cpu left of the frame
+                    if let (Some(refresh), Some(frame_started)) = (refresh, self.frame_started) {
+                        let frame_time = now.duration_since(frame_started);
+                        self.frame_pacer.record_present(
+                            missed > 0,
+                            self.frame_pacer
+                                .frame_duration(refresh)
+                                .saturating_sub(frame_time),
+                            refresh,
+                        );
+                    }
+                    self.last_present = Some(now);
+                }
+            }
+        }
+    }
+
+    /// Starts a new frame, the swapchain can be rebuilt once between calls
+    /// resizes reported in the meantime are coalesced into that rebuild
+    pub fn begin_frame(&mut self) {
+        self.rebuilt_this_frame = false;
+    }
+
+    /// Times the swapchain has been rebuilt, at most once per begin_frame
+    pub fn swapchain_rebuilds(&self) -> u64 {
+        self.swapchain_rebuilds
+    }
+
     /// returns aquired image and semaphore
     /// for when image is ready
-    #[must_use]
     pub fn aquire_img(
         &mut self,
-        vk_ctx: &mut VKContext,
+        mut target: PresentTarget<'_>,
         window: &Window,
     ) -> Result<ToRenderInfo, vk::Result> {
+        // swap was invalidated eg. by a resize, rebuild it before aquiring from it
+        // stays invalid while the window is minimised
+        if self.swap_invalid {
+            unsafe { self.invalid_rebuild_swap(&mut target, window)? };
+            if self.swap_invalid {
+                return Err(vk::Result::ERROR_OUT_OF_DATE_KHR);
+            }
+        }
+
         let img_rendered_cpu = *self
             .img_rendered_cpu
             .get(self.frame as usize)
@@ -389,16 +800,17 @@ impl VKPresent {
 
         // wait on cpu for currently rendering frame to finish
         unsafe {
-            vk_ctx
-                .vulkan_device
+            target
+                .device
                 .device
                 .wait_for_fences(&[img_rendered_cpu], true, u64::MAX)?;
         }
+        self.frame_started = Some(Instant::now());
 
         // request img from swapchain
         let aquire_image_result = unsafe {
-            vk_ctx.vulkan_swapchain.swapchain_loader.acquire_next_image(
-                vk_ctx.vulkan_swapchain.swapchain,
+            target.swapchain.swapchain_loader.acquire_next_image(
+                target.swapchain.swapchain,
                 u64::MAX,
                 img_aquired_gpu,
                 vk::Fence::null(),
@@ -410,17 +822,17 @@ impl VKPresent {
                 self.img_aquired_index = img_index;
                 if subopt {
                     self.swap_invalid = true;
-                    // unsafe { self.invalid_rebuild_swap(vk_ctx, window)? };
+                    // unsafe { self.invalid_rebuild_swap(&mut target, window)? };
                     // return Err(vk::Result::ERROR_OUT_OF_DATE_KHR);
                 }
             }
             Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
                 self.swap_invalid = true;
-                unsafe { self.invalid_rebuild_swap(vk_ctx, window)? };
+                unsafe { self.invalid_rebuild_swap(&mut target, window)? };
                 return Err(vk::Result::ERROR_OUT_OF_DATE_KHR);
             }
             Err(error) => {
-                unsafe { self.invalid_rebuild_swap(vk_ctx, window)? };
+                unsafe { self.invalid_rebuild_swap(&mut target, window)? };
                 return Err(error);
             }
         }
@@ -428,15 +840,14 @@ impl VKPresent {
         // Store the aquired image index for presentation
 
         // Waits on Swapchain img in use, usually only occurs if the swapchain hands us a img out of order
-        if let Some(img_in_flight) = self.img_in_flight.get(self.img_aquired_index as usize) {
-            if !img_in_flight.is_null() {
-                unsafe {
-                    vk_ctx.vulkan_device.device.wait_for_fences(
-                        &[*img_in_flight],
-                        true,
-                        u64::MAX,
-                    )?;
-                }
+        if let Some(img_in_flight) = self.img_in_flight.get(self.img_aquired_index as usize)
+            && !img_in_flight.is_null()
+        {
+            unsafe {
+                target
+                    .device
+                    .device
+                    .wait_for_fences(&[*img_in_flight], true, u64::MAX)?;
             }
         }
 
@@ -450,12 +861,7 @@ impl VKPresent {
         self.img_in_flight[self.img_aquired_index as usize] = img_rendered_cpu;
 
         // make sure fence is not signaled before command buffer would be submitted
-        unsafe {
-            vk_ctx
-                .vulkan_device
-                .device
-                .reset_fences(&[img_rendered_cpu])?
-        };
+        unsafe { target.device.device.reset_fences(&[img_rendered_cpu])? };
 
         Ok(ToRenderInfo {
             frame_in_flight: self.frame,
@@ -469,74 +875,98 @@ impl VKPresent {
     /// waits on rendered semaphore
     /// and then submits frame
     /// image_index is index of image obtained from aquire_image
-    /// if swap is invalid it will be recreated
-
-    #[must_use]
-    pub fn present_frame(
-        &mut self,
-        vk_ctx: &mut VKContext,
-        window: &Window,
-    ) -> Result<(), vk::Result> {
-        let swapchains = &[vk_ctx.vulkan_swapchain.swapchain];
+    /// if swap becomes invalid it is recreated by the next frame's aquire_img
+    pub fn present_frame(&mut self, target: PresentTarget<'_>) -> Result<(), vk::Result> {
+        let swapchains = &[target.swapchain.swapchain];
         let semaphores = &[*self
             .img_rendered_gpu
             .get(self.frame as usize)
             .ok_or(vk::Result::INCOMPLETE)?];
         let image_indices = &[self.img_aquired_index];
 
-        let present_info = vk::PresentInfoKHR::default()
+        let refresh = self.present_stats.refresh_duration.unwrap_or_default();
+        let frame_duration = self.frame_pacer.frame_duration(refresh);
+        let present_times = self
+            .display_timing
+            .as_mut()
+            .map(|display_timing| [display_timing.next_present_time(frame_duration, refresh)]);
+        let mut present_times_info = present_times
+            .as_ref()
+            .map(|present_times| vk::PresentTimesInfoGOOGLE::default().times(present_times));
+
+        let mut present_info = vk::PresentInfoKHR::default()
             .swapchains(swapchains)
             .wait_semaphores(semaphores)
             .image_indices(image_indices);
+        if let Some(present_times_info) = present_times_info.as_mut() {
+            present_info = present_info.push_next(present_times_info);
+        }
 
         let img_suboptimal = unsafe {
-            vk_ctx
-                .vulkan_swapchain
+            target
+                .swapchain
                 .swapchain_loader
-                .queue_present(vk_ctx.vulkan_device.graphics_queue, &present_info)
+                .queue_present(target.device.graphics_queue, &present_info)
         };
 
+        // rebuilding here as well as in aquire_img could rebuild twice a frame while resizing
+        self.frame = (self.frame + 1) % self.max_frames;
         match img_suboptimal {
             Ok(subopt) => {
+                self.record_present_timing(&target);
                 if subopt {
                     self.swap_invalid = true;
                 }
-                unsafe { self.invalid_rebuild_swap(vk_ctx, window)? };
-            }
-            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                self.swap_invalid = true;
-                unsafe { self.invalid_rebuild_swap(vk_ctx, window)? };
-                return Err(vk::Result::ERROR_OUT_OF_DATE_KHR);
             }
             Err(error) => {
-                unsafe { self.invalid_rebuild_swap(vk_ctx, window)? };
+                self.swap_invalid = true;
                 return Err(error);
             }
         }
 
-        self.frame = (self.frame + 1) % self.max_frames;
-
         Ok(())
     }
 
     unsafe fn invalid_rebuild_swap(
         &mut self,
-        vk_ctx: &mut VKContext,
+        target: &mut PresentTarget<'_>,
         window: &Window,
     ) -> Result<(), vk::Result> {
-        if self.swap_invalid {
-            let rebuild_status = vk_ctx.vulkan_swapchain.rebuild_swapchain(
-                &vk_ctx.vulkan_instance,
-                &mut vk_ctx.vulkan_device,
-                &vk_ctx.vulkan_surface,
-                &window,
+        // can't create a swapchain with a zero sized extent, wait for the window to be restored
+        let window_size = window.inner_size();
+        if window_size.width == 0 || window_size.height == 0 {
+            return Ok(());
+        }
+
+        if self.swap_invalid && !self.rebuilt_this_frame {
+            // frames in flight may still be using swapchain images
+            if !self.img_rendered_cpu.is_empty() {
+                unsafe {
+                    target
+                        .device
+                        .device
+                        .wait_for_fences(&self.img_rendered_cpu, true, u64::MAX)?;
+                }
+            }
+
+            let rebuild_status = target.swapchain.rebuild_swapchain(
+                target.instance,
+                target.device,
+                target.surface,
+                window,
             );
 
+            // a failed rebuild waits for the next frame too
+            self.rebuilt_this_frame = true;
             if rebuild_status.is_ok() {
+                self.swapchain_rebuilds += 1;
                 self.swap_invalid = false;
+                if self.created_time.is_some() {
+                    self.update_refresh_duration(target, window);
+                }
                 unsafe {
-                    self.recreate_sync(vk_ctx)?;
-                    self.img_aquired_index = (vk_ctx.vulkan_swapchain.images.len() as u32) - 1;
+                    self.recreate_sync(target)?;
+                    self.img_aquired_index = (target.swapchain.images.len() as u32) - 1;
                 }
             }
         }
@@ -544,13 +974,13 @@ impl VKPresent {
     }
 
     /// Recreates Sync Objects Such as Semaphores and Fences
-    unsafe fn recreate_sync(&mut self, vk_ctx: &VKContext) -> Result<(), vk::Result> {
+    unsafe fn recreate_sync(&mut self, target: &PresentTarget<'_>) -> Result<(), vk::Result> {
         unsafe {
-            let vk_device = &vk_ctx.vulkan_device;
-            self.destroy(vk_ctx);
+            let vk_device = &*target.device;
+            self.destroy(vk_device);
 
             let semaphore_create_info = vk::SemaphoreCreateInfo::default();
-            for _ in &vk_ctx.vulkan_swapchain.images {
+            for _ in &target.swapchain.images {
                 let img_semaphore = vk_device
                     .device
                     .create_semaphore(&semaphore_create_info, None)?;
@@ -588,9 +1018,7 @@ impl VKPresent {
     /// Destroy Before Vulkan Device
     /// Read VK Docs For Destruction Order
     /// Don't use any destroyed Sync Handles
-    pub unsafe fn destroy(&mut self, vk_ctx: &VKContext) {
-        let vk_device = &vk_ctx.vulkan_device;
-
+    pub unsafe fn destroy(&mut self, vk_device: &VKDevice) {
         unsafe {
             vk_device.device.device_wait_idle().unwrap_unchecked();
             self.img_aquired_gpu.iter().for_each(|semaphore| {
@@ -618,3 +1046,79 @@ impl VKPresent {
         self.img_in_flight.clear();
     }
 }
+
+#[test]
+fn composite_alpha_test() {
+    let mut capabilities = VKSwapchainCapabilities {
+        surface_capibilities: vk::SurfaceCapabilitiesKHR::default(),
+        surface_formats: vec![
+            vk::SurfaceFormatKHR::default().format(vk::Format::A2B10G10R10_UNORM_PACK32),
+            vk::SurfaceFormatKHR::default().format(vk::Format::R8G8B8A8_SRGB),
+        ],
+        present_modes: Vec::new(),
+    };
+    assert_eq!(
+        capabilities.ideal_surface_format().format,
+        vk::Format::R8G8B8A8_SRGB
+    );
+
+    capabilities.surface_capibilities.supported_composite_alpha =
+        vk::CompositeAlphaFlagsKHR::OPAQUE | vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED;
+    assert_eq!(
+        capabilities.ideal_composite_alpha(true),
+        vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED
+    );
+    assert_eq!(
+        capabilities.ideal_composite_alpha(false),
+        vk::CompositeAlphaFlagsKHR::OPAQUE
+    );
+
+    // wayland style surfaces can only inherit from the window
+    capabilities.surface_capibilities.supported_composite_alpha =
+        vk::CompositeAlphaFlagsKHR::INHERIT;
+    assert_eq!(
+        capabilities.ideal_composite_alpha(false),
+        vk::CompositeAlphaFlagsKHR::INHERIT
+    );
+}
+
+#[test]
+fn surface_extension_test() {
+    use winit::raw_window_handle::{WaylandDisplayHandle, XlibDisplayHandle};
+
+    let wayland =
+        RawDisplayHandle::Wayland(WaylandDisplayHandle::new(std::ptr::NonNull::dangling()));
+    let xlib = RawDisplayHandle::Xlib(XlibDisplayHandle::new(None, 0));
+
+    assert_eq!(
+        choose_surface_extension(wayland, |_| true).unwrap(),
+        wayland_surface::NAME
+    );
+    assert_eq!(
+        choose_surface_extension(xlib, |_| true).unwrap(),
+        xlib_surface::NAME
+    );
+
+    // without VK_KHR_surface nothing can be presented
+    let error = choose_surface_extension(xlib, |name| name != surface::NAME).unwrap_err();
+    assert!(error.to_string().contains("VK_KHR_surface"));
+
+    let error = choose_surface_extension(wayland, |name| name == surface::NAME).unwrap_err();
+    assert!(matches!(
+        error,
+        SurfaceError::MissingExtension {
+            display: "Wayland",
+            ..
+        }
+    ));
+    assert!(error.to_string().contains("VK_KHR_wayland_surface"));
+
+    #[cfg(all(
+        unix,
+        not(any(target_os = "android", target_os = "macos", target_os = "ios"))
+    ))]
+    assert_eq!(
+        choose_surface_extension(xlib, |name| name != xlib_surface::NAME).unwrap(),
+        xcb_surface::NAME
+    );
+}
@@ -0,0 +1,283 @@
+use ash::{ext, google, khr, vk};
+use std::ffi::CStr;
+use std::ops::{BitOr, BitOrAssign};
+
+pub const VENDOR_AMD: u32 = 0x1002;
+pub const VENDOR_ARM: u32 = 0x13B5;
+pub const VENDOR_INTEL: u32 = 0x8086;
+pub const VENDOR_NVIDIA: u32 = 0x10DE;
+pub const VENDOR_QUALCOMM: u32 = 0x5143;
+
+/// Driver workarounds the engine applies, see QUIRK_RULES for when they turn on
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Quirks(pub u32);
+
+impl Quirks {
+    pub const NONE: Self = Self(0);
+    /// present with FIFO even when MAILBOX is offered
+    pub const NO_MAILBOX: Self = Self(1);
+    /// leave VK_EXT_conditional_rendering off
+    pub const NO_CONDITIONAL_RENDERING: Self = Self(1 << 1);
+    /// leave VK_KHR_draw_indirect_count off, draws fall back to fixed counts
+    pub const NO_DRAW_INDIRECT_COUNT: Self = Self(1 << 2);
+    /// leave acceleration structures, ray tracing pipelines and ray queries off
+    pub const NO_RAY_TRACING: Self = Self(1 << 3);
+    /// leave VK_GOOGLE_display_timing off
+    pub const NO_DISPLAY_TIMING: Self = Self(1 << 4);
+    /// leave VK_EXT_pageable_device_local_memory and VK_EXT_memory_priority off
+    pub const NO_MEMORY_PRIORITY: Self = Self(1 << 5);
+
+    /// Every quirk and its name, names are what QuirkOverrides::parse accepts
+    pub const NAMED: [(&'static str, Self); 6] = [
+        ("no_mailbox", Self::NO_MAILBOX),
+        ("no_conditional_rendering", Self::NO_CONDITIONAL_RENDERING),
+        ("no_draw_indirect_count", Self::NO_DRAW_INDIRECT_COUNT),
+        ("no_ray_tracing", Self::NO_RAY_TRACING),
+        ("no_display_timing", Self::NO_DISPLAY_TIMING),
+        ("no_memory_priority", Self::NO_MEMORY_PRIORITY),
+    ];
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn without(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    pub fn names(self) -> Vec<&'static str> {
+        Self::NAMED
+            .iter()
+            .filter(|(_, quirk)| self.contains(*quirk))
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    /// Device extensions these quirks keep from being enabled
+    pub fn disabled_extensions(self) -> Vec<&'static CStr> {
+        let mut extensions = Vec::new();
+        if self.contains(Self::NO_CONDITIONAL_RENDERING) {
+            extensions.push(ext::conditional_rendering::NAME);
+        }
+        if self.contains(Self::NO_DRAW_INDIRECT_COUNT) {
+            extensions.push(khr::draw_indirect_count::NAME);
+        }
+        if self.contains(Self::NO_RAY_TRACING) {
+            extensions.extend([
+                khr::deferred_host_operations::NAME,
+                khr::acceleration_structure::NAME,
+                khr::ray_tracing_pipeline::NAME,
+                khr::ray_query::NAME,
+            ]);
+        }
+        if self.contains(Self::NO_DISPLAY_TIMING) {
+            extensions.push(google::display_timing::NAME);
+        }
+        if self.contains(Self::NO_MEMORY_PRIORITY) {
+            extensions.extend([
+                ext::pageable_device_local_memory::NAME,
+                ext::memory_priority::NAME,
+            ]);
+        }
+        extensions
+    }
+}
+
+impl BitOr for Quirks {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Quirks {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+/// Turns quirks on for matching devices, None matches anything
+#[derive(Clone, Copy, Debug)]
+pub struct QuirkRule {
+    pub vendor_id: u32,
+    pub device_id: Option<u32>,
+    /// matches drivers older than this, in the vendor's own driverVersion encoding
+    pub driver_below: Option<u32>,
+    pub quirks: Quirks,
+    /// logged when the rule matches
+    pub reason: &'static str,
+}
+
+impl QuirkRule {
+    pub fn matches(&self, vendor_id: u32, device_id: u32, driver_version: u32) -> bool {
+        self.vendor_id == vendor_id
+            && self.device_id.is_none_or(|id| id == device_id)
+            && self
+                .driver_below
+                .is_none_or(|version| driver_version < version)
+    }
+}
+
+/// NVIDIA packs driverVersion as 10.8.8.6 bits instead of the vulkan api version layout
+pub const fn nvidia_driver_version(major: u32, minor: u32) -> u32 {
+    (major << 22) | (minor << 14)
+}
+
+/// Qualcomm's proprietary driver reports its build number through the api version layout
+pub const fn qualcomm_driver_version(major: u32, minor: u32) -> u32 {
+    vk::make_api_version(0, major, minor, 0)
+}
+
+/// Known driver problems the engine works around, checked when the device is created
+pub const QUIRK_RULES: &[QuirkRule] = &[
+    QuirkRule {
+        vendor_id: VENDOR_QUALCOMM,
+        device_id: None,
+        driver_below: Some(qualcomm_driver_version(512, 600)),
+        quirks: Quirks::NO_RAY_TRACING,
+        reason: "early Adreno ray tracing drivers build unreliable acceleration structures",
+    },
+    QuirkRule {
+        vendor_id: VENDOR_ARM,
+        device_id: None,
+        driver_below: None,
+        quirks: Quirks::NO_MAILBOX,
+        reason: "Mali drivers treat MAILBOX like FIFO with an extra frame of latency",
+    },
+    QuirkRule {
+        vendor_id: VENDOR_NVIDIA,
+        device_id: None,
+        driver_below: Some(nvidia_driver_version(470, 0)),
+        quirks: Quirks::NO_DISPLAY_TIMING,
+        reason: "older NVIDIA drivers report display timing values that never advance",
+    },
+];
+
+/// Quirks every rule matching the device asks for, with the reasons of the rules that matched
+pub fn matching_quirks(
+    rules: &[QuirkRule],
+    vendor_id: u32,
+    device_id: u32,
+    driver_version: u32,
+) -> (Quirks, Vec<&'static str>) {
+    rules
+        .iter()
+        .filter(|rule| rule.matches(vendor_id, device_id, driver_version))
+        .fold((Quirks::NONE, Vec::new()), |(quirks, mut reasons), rule| {
+            reasons.push(rule.reason);
+            (quirks | rule.quirks, reasons)
+        })
+}
+
+/// User changes to the quirks picked from QUIRK_RULES, see InstanceOptions::quirk_overrides
+/// Example Use:
+/// ```ignore
+/// // ray tracing works fine on this driver, and mailbox misbehaves
+/// let overrides = QuirkOverrides::parse("-no_ray_tracing,+no_mailbox")?;
+/// let options = InstanceOptions::default().quirk_overrides(overrides);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QuirkOverrides {
+    pub enable: Quirks,
+    pub disable: Quirks,
+}
+
+impl QuirkOverrides {
+    /// Reads comma separated quirk names, + or no prefix enables and - disables
+    pub fn parse(source: &str) -> Result<Self, String> {
+        source
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .try_fold(Self::default(), |mut overrides, entry| {
+                let (enable, name) = match entry.strip_prefix('-') {
+                    Some(name) => (false, name),
+                    None => (true, entry.strip_prefix('+').unwrap_or(entry)),
+                };
+                let (_, quirk) = Quirks::NAMED
+                    .iter()
+                    .find(|(quirk_name, _)| *quirk_name == name)
+                    .ok_or_else(|| format!("Unknown Quirk: {name}"))?;
+                if enable {
+                    overrides.enable |= *quirk;
+                    overrides.disable = overrides.disable.without(*quirk);
+                } else {
+                    overrides.disable |= *quirk;
+                    overrides.enable = overrides.enable.without(*quirk);
+                }
+                Ok(overrides)
+            })
+    }
+
+    pub fn apply(&self, quirks: Quirks) -> Quirks {
+        (quirks | self.enable).without(self.disable)
+    }
+}
+
+#[test]
+fn quirks_test() {
+    let rules = [
+        QuirkRule {
+            vendor_id: VENDOR_NVIDIA,
+            device_id: None,
+            driver_below: Some(nvidia_driver_version(470, 0)),
+            quirks: Quirks::NO_DISPLAY_TIMING,
+            reason: "old",
+        },
+        QuirkRule {
+            vendor_id: VENDOR_NVIDIA,
+            device_id: Some(0x2484),
+            driver_below: None,
+            quirks: Quirks::NO_MAILBOX,
+            reason: "one card",
+        },
+    ];
+
+    let old_driver = nvidia_driver_version(460, 91);
+    let new_driver = nvidia_driver_version(535, 54);
+    assert_eq!(
+        matching_quirks(&rules, VENDOR_NVIDIA, 0x2484, old_driver),
+        (
+            Quirks::NO_DISPLAY_TIMING | Quirks::NO_MAILBOX,
+            vec!["old", "one card"]
+        )
+    );
+    assert_eq!(
+        matching_quirks(&rules, VENDOR_NVIDIA, 0x1E87, new_driver).0,
+        Quirks::NONE
+    );
+    assert!(
+        matching_quirks(&rules, VENDOR_AMD, 0x2484, old_driver)
+            .0
+            .is_empty()
+    );
+
+    let overrides = QuirkOverrides::parse("no_mailbox, -no_display_timing").unwrap();
+    assert_eq!(
+        overrides.apply(Quirks::NO_DISPLAY_TIMING | Quirks::NO_RAY_TRACING),
+        Quirks::NO_MAILBOX | Quirks::NO_RAY_TRACING
+    );
+    // the last mention of a quirk wins
+    assert_eq!(
+        QuirkOverrides::parse("-no_mailbox,+no_mailbox")
+            .unwrap()
+            .apply(Quirks::NONE),
+        Quirks::NO_MAILBOX
+    );
+    assert!(QuirkOverrides::parse("no_such_quirk").is_err());
+
+    assert!(
+        Quirks::NO_RAY_TRACING
+            .disabled_extensions()
+            .contains(&khr::ray_query::NAME)
+    );
+    assert_eq!(
+        (Quirks::NO_MAILBOX | Quirks::NO_RAY_TRACING).names(),
+        ["no_mailbox", "no_ray_tracing"]
+    );
+}
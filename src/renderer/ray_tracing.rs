@@ -0,0 +1,1365 @@
+use ash::vk;
+use glam::{Mat4, Vec4};
+use gpu_allocator::MemoryLocation;
+use std::error;
+use std::path::Path;
+
+use crate::camera::{CameraUniform, DepthConvention};
+use crate::color::LinearRgba;
+use crate::lighting::LightUniform;
+use crate::renderer::allocator::VKAllocation;
+use crate::renderer::buffer::VKBuffer;
+use crate::renderer::device::{RayTracingProperties, VKDevice};
+use crate::renderer::material::{DEFAULT_MATERIAL, VKMaterial};
+use crate::renderer::mesh::{VKMesh, Vertex};
+use crate::renderer::shader::{VKShader, VKShaderLoader};
+use crate::renderer::{
+    COLOR_SUBRESOURCE_RANGE, MeshInstance, push_constant_range, submit_one_time,
+};
+
+/// Ray tracing stages, loaded when the renderer switches to them
+/// rebuild with `slangc shaders/raytrace.slang -target spirv -fvk-use-entrypoint-name -o shaders/raytrace.spv`
+pub const RAY_TRACE_SHADER: &str = "shaders/raytrace.spv";
+/// Compiled with slangc at runtime by dev builds when RAY_TRACE_SHADER is missing
+pub const RAY_TRACE_SOURCE: &str = "shaders/raytrace.slang";
+/// triangle.slang built with ray queries for VKRayQueryShadows
+pub const RAY_QUERY_SHADER: &str = "shaders/triangle_shadows.spv";
+
+// linear colour the rays write, blitted into the target afterwards
+const OUTPUT_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// Everything the hit shader knows about an instance, matches RayInstance in raytrace.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RayInstance {
+    pub vertex_address: vk::DeviceAddress,
+    /// 0 for meshes that aren't indexed
+    pub index_address: vk::DeviceAddress,
+    /// material base colour times the instance tint
+    pub color: Vec4,
+    pub emissive: Vec4,
+    /// MaterialFeatures bits
+    pub features: u32,
+    padding: [u32; 3],
+}
+
+// matches RayTracePass in raytrace.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct RayTracePass {
+    inverse_view_projection: Mat4,
+    clear_color: Vec4,
+    // rays start where this depth unprojects to
+    near_depth: f32,
+    padding: [f32; 3],
+}
+
+/// Row major 3x4 matrix acceleration structure instances are placed with
+pub fn instance_transform(transform: &Mat4) -> vk::TransformMatrixKHR {
+    let rows = transform.transpose();
+    let mut matrix = [0.0; 12];
+    matrix[..4].copy_from_slice(&rows.x_axis.to_array());
+    matrix[4..8].copy_from_slice(&rows.y_axis.to_array());
+    matrix[8..].copy_from_slice(&rows.z_axis.to_array());
+    vk::TransformMatrixKHR { matrix }
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment.max(1)) * alignment.max(1)
+}
+
+/// Where each group's records sit in a shader binding table
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShaderBindingLayout {
+    /// bytes between records, handles padded to their alignment
+    pub stride: u64,
+    pub raygen: (u64, u64),
+    pub miss: (u64, u64),
+    pub hit: (u64, u64),
+    /// bytes the table needs, regions start at multiples of shader_group_base_alignment
+    pub size: u64,
+}
+
+impl ShaderBindingLayout {
+    /// One raygen record followed by miss_count miss and hit_count hit group records
+    /// offsets and sizes of each region
+    pub fn new(properties: &RayTracingProperties, miss_count: u64, hit_count: u64) -> Self {
+        let base = properties.shader_group_base_alignment as u64;
+        let stride = align_up(
+            properties.shader_group_handle_size as u64,
+            properties.shader_group_handle_alignment as u64,
+        );
+        // the raygen region's stride has to equal its size
+        let raygen_size = align_up(stride, base);
+        let miss_size = align_up(stride * miss_count, base);
+        let hit_size = align_up(stride * hit_count, base);
+        Self {
+            stride,
+            raygen: (0, raygen_size),
+            miss: (raygen_size, miss_size),
+            hit: (raygen_size + miss_size, hit_size),
+            size: raygen_size + miss_size + hit_size,
+        }
+    }
+}
+
+// a buffer the gpu addresses directly, address is aligned and may be past the start of buffer
+struct AddressedBuffer {
+    buffer: VKBuffer,
+    address: vk::DeviceAddress,
+}
+
+impl AddressedBuffer {
+    fn new(
+        vk_device: &mut VKDevice,
+        size: u64,
+        alignment: u64,
+        usage: vk::BufferUsageFlags,
+        location: MemoryLocation,
+        name: &str,
+    ) -> Result<Self, vk::Result> {
+        let buffer = VKBuffer::new(
+            vk_device,
+            size + alignment,
+            usage | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            location,
+            name,
+        )?;
+        let address = align_up(vk_device.buffer_address(buffer.buffer), alignment);
+        Ok(Self { buffer, address })
+    }
+
+    // offset of address from the start of the buffer
+    fn offset(&self, vk_device: &VKDevice) -> usize {
+        (self.address - vk_device.buffer_address(self.buffer.buffer)) as usize
+    }
+
+    unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe { self.buffer.destroy(vk_device) };
+    }
+}
+
+/// A bottom or top level BVH and the buffer it lives in
+pub struct VKAccelerationStructure {
+    pub handle: vk::AccelerationStructureKHR,
+    pub buffer: VKBuffer,
+    /// what top level instances refer to it by
+    pub address: vk::DeviceAddress,
+}
+
+impl VKAccelerationStructure {
+    fn new(
+        vk_device: &mut VKDevice,
+        ty: vk::AccelerationStructureTypeKHR,
+        size: u64,
+    ) -> Result<Self, vk::Result> {
+        let loader = vk_device
+            .acceleration_structure
+            .clone()
+            .ok_or(vk::Result::ERROR_EXTENSION_NOT_PRESENT)?;
+        let mut buffer = VKBuffer::new(
+            vk_device,
+            size,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            MemoryLocation::GpuOnly,
+            "Acceleration Structure",
+        )?;
+        let handle = match unsafe {
+            loader.create_acceleration_structure(
+                &vk::AccelerationStructureCreateInfoKHR::default()
+                    .ty(ty)
+                    .buffer(buffer.buffer)
+                    .size(size),
+                None,
+            )
+        } {
+            Ok(handle) => handle,
+            Err(error) => {
+                unsafe { buffer.destroy(vk_device) };
+                return Err(error);
+            }
+        };
+        let address = unsafe {
+            loader.get_acceleration_structure_device_address(
+                &vk::AccelerationStructureDeviceAddressInfoKHR::default()
+                    .acceleration_structure(handle),
+            )
+        };
+        Ok(Self {
+            handle,
+            buffer,
+            address,
+        })
+    }
+
+    /// Bottom level BVH of a mesh's triangles, built before returning
+    /// the mesh has to be created while ray tracing is supported so its buffers can be build inputs
+    pub fn from_mesh(
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+        mesh: &VKMesh,
+    ) -> Result<Self, vk::Result> {
+        let loader = vk_device
+            .acceleration_structure
+            .clone()
+            .ok_or(vk::Result::ERROR_EXTENSION_NOT_PRESENT)?;
+
+        let indexed = mesh.is_indexed();
+        let mut triangles = vk::AccelerationStructureGeometryTrianglesDataKHR::default()
+            .vertex_format(vk::Format::R32G32B32_SFLOAT)
+            .vertex_data(vk::DeviceOrHostAddressConstKHR {
+                device_address: vk_device.buffer_address(mesh.vertex_buffer.buffer),
+            })
+            .vertex_stride(size_of::<Vertex>() as u64)
+            .max_vertex(mesh.vertex_count.saturating_sub(1))
+            .index_type(vk::IndexType::NONE_KHR);
+        if indexed {
+            triangles = triangles.index_type(vk::IndexType::UINT32).index_data(
+                vk::DeviceOrHostAddressConstKHR {
+                    device_address: vk_device.buffer_address(mesh.index_buffer.buffer),
+                },
+            );
+        }
+        let geometry = vk::AccelerationStructureGeometryKHR::default()
+            .geometry_type(vk::GeometryTypeKHR::TRIANGLES)
+            .geometry(vk::AccelerationStructureGeometryDataKHR { triangles })
+            .flags(vk::GeometryFlagsKHR::OPAQUE);
+        let triangle_count = if indexed {
+            mesh.index_count / 3
+        } else {
+            mesh.vertex_count / 3
+        };
+
+        let geometries = [geometry];
+        let build_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+            .ty(vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL)
+            .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+            .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+            .geometries(&geometries);
+        let mut sizes = vk::AccelerationStructureBuildSizesInfoKHR::default();
+        unsafe {
+            loader.get_acceleration_structure_build_sizes(
+                vk::AccelerationStructureBuildTypeKHR::DEVICE,
+                &build_info,
+                &[triangle_count],
+                &mut sizes,
+            )
+        };
+
+        let mut structure = Self::new(
+            vk_device,
+            vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
+            sizes.acceleration_structure_size,
+        )?;
+        let mut scratch = match AddressedBuffer::new(
+            vk_device,
+            sizes.build_scratch_size,
+            vk_device
+                .ray_tracing_properties
+                .min_scratch_offset_alignment as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::GpuOnly,
+            "Acceleration Structure Scratch",
+        ) {
+            Ok(scratch) => scratch,
+            Err(error) => {
+                unsafe { structure.destroy(vk_device) };
+                return Err(error);
+            }
+        };
+
+        let build_info = build_info
+            .dst_acceleration_structure(structure.handle)
+            .scratch_data(vk::DeviceOrHostAddressKHR {
+                device_address: scratch.address,
+            });
+        let range =
+            vk::AccelerationStructureBuildRangeInfoKHR::default().primitive_count(triangle_count);
+        let built = submit_one_time(vk_device, vk_command_pool, |cmd_buffer| unsafe {
+            loader.cmd_build_acceleration_structures(cmd_buffer, &[build_info], &[&[range]]);
+        });
+
+        unsafe { scratch.destroy(vk_device) };
+        if let Err(error) = built {
+            unsafe { structure.destroy(vk_device) };
+            return Err(error);
+        }
+        Ok(structure)
+    }
+
+    /// # Safety
+    /// The gpu must not be using the structure or anything built from it
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            if let Some(loader) = &vk_device.acceleration_structure {
+                loader.destroy_acceleration_structure(self.handle, None);
+            }
+            self.buffer.destroy(vk_device);
+        }
+    }
+}
+
+/// Top level BVH of a frame in flight, rebuilt from the scene's instances every frame
+pub struct VKTopLevel {
+    pub structure: VKAccelerationStructure,
+    /// instances the structure and buffers were sized for
+    pub capacity: u32,
+    /// instances written by the last write
+    pub len: u32,
+    instances: AddressedBuffer,
+    /// RayInstances in the same order, read by the hit shader
+    pub data_buffer: VKBuffer,
+    scratch: AddressedBuffer,
+}
+
+impl VKTopLevel {
+    fn geometry(address: vk::DeviceAddress) -> vk::AccelerationStructureGeometryKHR<'static> {
+        vk::AccelerationStructureGeometryKHR::default()
+            .geometry_type(vk::GeometryTypeKHR::INSTANCES)
+            .geometry(vk::AccelerationStructureGeometryDataKHR {
+                instances: vk::AccelerationStructureGeometryInstancesDataKHR::default().data(
+                    vk::DeviceOrHostAddressConstKHR {
+                        device_address: address,
+                    },
+                ),
+            })
+    }
+
+    pub fn new(vk_device: &mut VKDevice, capacity: u32) -> Result<Self, vk::Result> {
+        let loader = vk_device
+            .acceleration_structure
+            .clone()
+            .ok_or(vk::Result::ERROR_EXTENSION_NOT_PRESENT)?;
+        let capacity = capacity.max(1);
+
+        let geometries = [Self::geometry(0)];
+        let build_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+            .ty(vk::AccelerationStructureTypeKHR::TOP_LEVEL)
+            .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_BUILD)
+            .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+            .geometries(&geometries);
+        let mut sizes = vk::AccelerationStructureBuildSizesInfoKHR::default();
+        unsafe {
+            loader.get_acceleration_structure_build_sizes(
+                vk::AccelerationStructureBuildTypeKHR::DEVICE,
+                &build_info,
+                &[capacity],
+                &mut sizes,
+            )
+        };
+
+        let mut structure = Self::new_structure(vk_device, sizes.acceleration_structure_size)?;
+        let buffers = (|| {
+            let instances = AddressedBuffer::new(
+                vk_device,
+                capacity as u64 * size_of::<vk::AccelerationStructureInstanceKHR>() as u64,
+                16,
+                vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
+                MemoryLocation::CpuToGpu,
+                "Ray Traced Instances",
+            )?;
+            let scratch = AddressedBuffer::new(
+                vk_device,
+                sizes.build_scratch_size,
+                vk_device
+                    .ray_tracing_properties
+                    .min_scratch_offset_alignment as u64,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                MemoryLocation::GpuOnly,
+                "Acceleration Structure Scratch",
+            )?;
+            let data_buffer = VKBuffer::new(
+                vk_device,
+                capacity as u64 * size_of::<RayInstance>() as u64,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                MemoryLocation::CpuToGpu,
+                "Ray Traced Instance Data",
+            )?;
+            Ok((instances, scratch, data_buffer))
+        })();
+        let (instances, scratch, data_buffer) = match buffers {
+            Ok(buffers) => buffers,
+            Err(error) => {
+                // buffers made before the failure are leaked, only out of memory gets here
+                unsafe { structure.destroy(vk_device) };
+                return Err(error);
+            }
+        };
+
+        Ok(Self {
+            structure,
+            capacity,
+            len: 0,
+            instances,
+            data_buffer,
+            scratch,
+        })
+    }
+
+    fn new_structure(
+        vk_device: &mut VKDevice,
+        size: u64,
+    ) -> Result<VKAccelerationStructure, vk::Result> {
+        VKAccelerationStructure::new(vk_device, vk::AccelerationStructureTypeKHR::TOP_LEVEL, size)
+    }
+
+    /// Instances and their shading data for the next cmd_build, only the first capacity are kept
+    /// the gpu must be done with the frame's last build
+    pub fn write(
+        &mut self,
+        vk_device: &VKDevice,
+        instances: &[vk::AccelerationStructureInstanceKHR],
+        data: &[RayInstance],
+    ) {
+        let len = instances.len().min(data.len()).min(self.capacity as usize);
+        let offset = self.instances.offset(vk_device);
+        let written = self
+            .instances
+            .buffer
+            .write(offset, &instances[..len])
+            .and_then(|_| self.data_buffer.write(0, &data[..len]));
+        self.len = if written.is_ok() {
+            len as u32
+        } else {
+            log::warn!("Failed to Copy Ray Traced Instances");
+            0
+        };
+    }
+
+    /// Rebuilds the structure from the written instances and makes it visible to ray tracing and
+    /// fragment shaders
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering
+    pub unsafe fn cmd_build(&self, vk_device: &VKDevice, cmd_buffer: vk::CommandBuffer) {
+        let Some(loader) = &vk_device.acceleration_structure else {
+            return;
+        };
+        let geometries = [Self::geometry(self.instances.address)];
+        let build_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+            .ty(vk::AccelerationStructureTypeKHR::TOP_LEVEL)
+            .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_BUILD)
+            .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+            .geometries(&geometries)
+            .dst_acceleration_structure(self.structure.handle)
+            .scratch_data(vk::DeviceOrHostAddressKHR {
+                device_address: self.scratch.address,
+            });
+        let range = vk::AccelerationStructureBuildRangeInfoKHR::default().primitive_count(self.len);
+        // ray query shadows read it without the ray tracing stage being available
+        let mut dst_stage_mask = vk::PipelineStageFlags2::FRAGMENT_SHADER;
+        if vk_device.ray_tracing_pipeline.is_some() {
+            dst_stage_mask |= vk::PipelineStageFlags2::RAY_TRACING_SHADER_KHR;
+        }
+        let built = [vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::ACCELERATION_STRUCTURE_BUILD_KHR)
+            .src_access_mask(vk::AccessFlags2::ACCELERATION_STRUCTURE_WRITE_KHR)
+            .dst_stage_mask(dst_stage_mask)
+            .dst_access_mask(vk::AccessFlags2::ACCELERATION_STRUCTURE_READ_KHR)];
+        unsafe {
+            loader.cmd_build_acceleration_structures(cmd_buffer, &[build_info], &[&[range]]);
+            vk_device.device.cmd_pipeline_barrier2(
+                cmd_buffer,
+                &vk::DependencyInfo::default().memory_barriers(&built),
+            );
+        }
+    }
+
+    /// # Safety
+    /// The gpu must not be using the structure
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            self.structure.destroy(vk_device);
+            self.instances.destroy(vk_device);
+            self.scratch.destroy(vk_device);
+            self.data_buffer.destroy(vk_device);
+        }
+    }
+}
+
+/// BVHs of the scene's instances shared by the ray tracer and ray query shadows
+/// bottom levels are built once per mesh, the frame's top level is rewritten every frame
+pub struct VKSceneBvh {
+    /// BVH per MeshId, built the first time an instance uses the mesh
+    pub bottom_levels: Vec<Option<VKAccelerationStructure>>,
+    /// per frame in flight, None until the frame is first updated
+    pub top_levels: Vec<Option<VKTopLevel>>,
+}
+
+impl VKSceneBvh {
+    pub fn new(frames_in_flight: usize) -> Self {
+        Self {
+            bottom_levels: Vec::new(),
+            top_levels: (0..frames_in_flight).map(|_| None).collect(),
+        }
+    }
+
+    /// Builds BVHs for meshes seen for the first time and writes the frame's instances
+    /// returns true when the frame's top level was replaced and descriptors need rewriting
+    /// # Safety
+    /// frame_in_flight's fence must have signalled
+    pub unsafe fn update(
+        &mut self,
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+        frame_in_flight: usize,
+        meshes: &[VKMesh],
+        materials: &[VKMaterial],
+        instances: &[MeshInstance],
+    ) -> Result<bool, vk::Result> {
+        self.bottom_levels.resize_with(meshes.len(), || None);
+        for instance in instances {
+            let Some(mesh) = meshes.get(instance.mesh) else {
+                continue;
+            };
+            if self.bottom_levels[instance.mesh].is_none() {
+                self.bottom_levels[instance.mesh] = Some(VKAccelerationStructure::from_mesh(
+                    vk_device,
+                    vk_command_pool,
+                    mesh,
+                )?);
+            }
+        }
+
+        let (structures, data): (Vec<_>, Vec<_>) = instances
+            .iter()
+            .filter_map(|instance| {
+                let mesh = meshes.get(instance.mesh)?;
+                let bottom_level = self.bottom_levels[instance.mesh].as_ref()?;
+                let material = materials
+                    .get(instance.material)
+                    .or_else(|| materials.get(DEFAULT_MATERIAL))?;
+                Some((mesh, bottom_level, material, instance))
+            })
+            .enumerate()
+            .map(|(index, (mesh, bottom_level, material, instance))| {
+                let structure = vk::AccelerationStructureInstanceKHR {
+                    transform: instance_transform(&instance.transform),
+                    instance_custom_index_and_mask: vk::Packed24_8::new(index as u32, 0xFF),
+                    instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(
+                        0,
+                        vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw() as u8,
+                    ),
+                    acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
+                        device_handle: bottom_level.address,
+                    },
+                };
+                let data = RayInstance {
+                    vertex_address: vk_device.buffer_address(mesh.vertex_buffer.buffer),
+                    index_address: if mesh.is_indexed() {
+                        vk_device.buffer_address(mesh.index_buffer.buffer)
+                    } else {
+                        0
+                    },
+                    color: material.params.base_color * instance.tint.to_vec4(),
+                    emissive: material.params.emissive,
+                    features: material.variant.features.0,
+                    padding: [0; 3],
+                };
+                (structure, data)
+            })
+            .unzip();
+
+        let mut replaced = false;
+        let top_level = &mut self.top_levels[frame_in_flight];
+        if top_level
+            .as_ref()
+            .is_none_or(|top_level| (top_level.capacity as usize) < structures.len())
+        {
+            // room to grow so a few more instances don't rebuild it again
+            let capacity = (structures.len() as u32).next_power_of_two().max(64);
+            if let Some(mut old) = top_level.replace(VKTopLevel::new(vk_device, capacity)?) {
+                unsafe { old.destroy(vk_device) };
+            }
+            replaced = true;
+        }
+        let top_level = self.top_levels[frame_in_flight]
+            .as_mut()
+            .expect("top level was just created");
+        top_level.write(vk_device, &structures, &data);
+        Ok(replaced)
+    }
+
+    pub fn top_level(&self, frame_in_flight: usize) -> Option<&VKTopLevel> {
+        self.top_levels.get(frame_in_flight)?.as_ref()
+    }
+
+    /// Builds the frame's top level, see VKTopLevel::cmd_build
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering after update for frame_in_flight
+    pub unsafe fn cmd_build(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        frame_in_flight: usize,
+    ) {
+        if let Some(top_level) = self.top_level(frame_in_flight) {
+            unsafe { top_level.cmd_build(vk_device, cmd_buffer) };
+        }
+    }
+
+    /// # Safety
+    /// The gpu must not be using any of the BVHs
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            for mut structure in self.bottom_levels.drain(..).flatten() {
+                structure.destroy(vk_device);
+            }
+            for mut top_level in self.top_levels.drain(..).flatten() {
+                top_level.destroy(vk_device);
+            }
+        }
+    }
+}
+
+/// Shader group handles laid out by ShaderBindingLayout
+pub struct VKShaderBindingTable {
+    buffer: AddressedBuffer,
+    pub raygen: vk::StridedDeviceAddressRegionKHR,
+    pub miss: vk::StridedDeviceAddressRegionKHR,
+    pub hit: vk::StridedDeviceAddressRegionKHR,
+    /// no callable shaders are used
+    pub callable: vk::StridedDeviceAddressRegionKHR,
+}
+
+impl VKShaderBindingTable {
+    /// Groups of pipeline have to be the raygen group, then the miss groups, then the hit groups
+    pub fn new(
+        vk_device: &mut VKDevice,
+        pipeline: vk::Pipeline,
+        miss_count: u32,
+        hit_count: u32,
+    ) -> Result<Self, vk::Result> {
+        let loader = vk_device
+            .ray_tracing_pipeline
+            .clone()
+            .ok_or(vk::Result::ERROR_EXTENSION_NOT_PRESENT)?;
+        let properties = vk_device.ray_tracing_properties;
+        let layout = ShaderBindingLayout::new(&properties, miss_count as u64, hit_count as u64);
+        let handle_size = properties.shader_group_handle_size as usize;
+        let group_count = 1 + miss_count + hit_count;
+        let handles = unsafe {
+            loader.get_ray_tracing_shader_group_handles(
+                pipeline,
+                0,
+                group_count,
+                group_count as usize * handle_size,
+            )?
+        };
+
+        let mut buffer = AddressedBuffer::new(
+            vk_device,
+            layout.size,
+            properties.shader_group_base_alignment as u64,
+            vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR,
+            MemoryLocation::CpuToGpu,
+            "Shader Binding Table",
+        )?;
+        let start = buffer.offset(vk_device);
+        let region_starts = std::iter::once(layout.raygen.0)
+            .chain((0..miss_count as u64).map(|miss| layout.miss.0 + miss * layout.stride))
+            .chain((0..hit_count as u64).map(|hit| layout.hit.0 + hit * layout.stride));
+        for (handle, offset) in handles.chunks_exact(handle_size).zip(region_starts) {
+            if buffer
+                .buffer
+                .write(start + offset as usize, handle)
+                .is_err()
+            {
+                unsafe { buffer.destroy(vk_device) };
+                return Err(vk::Result::ERROR_MEMORY_MAP_FAILED);
+            }
+        }
+
+        let region = |(offset, size): (u64, u64), stride: u64| {
+            vk::StridedDeviceAddressRegionKHR::default()
+                .device_address(buffer.address + offset)
+                .stride(stride)
+                .size(size)
+        };
+        Ok(Self {
+            raygen: region(layout.raygen, layout.raygen.1),
+            miss: region(layout.miss, layout.stride),
+            hit: region(layout.hit, layout.stride),
+            callable: vk::StridedDeviceAddressRegionKHR::default(),
+            buffer,
+        })
+    }
+
+    /// # Safety
+    /// The gpu must not be tracing with the table
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe { self.buffer.destroy(vk_device) };
+    }
+}
+
+// storage image the rays write, shared by every frame in flight
+struct RayTraceOutput {
+    image: vk::Image,
+    view: vk::ImageView,
+    allocation: VKAllocation,
+    extent: vk::Extent2D,
+}
+
+/// Ray traced render mode, see VKRenderer::set_render_mode
+/// one ray per pixel is traced through the VKSceneBvh into a storage image with a shadow ray per
+/// light, then the image is blitted into the target
+pub struct VKRayTracer<'a> {
+    pub shaders: Vec<VKShader<'a>>,
+    pub descriptor_layout: vk::DescriptorSetLayout,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub shader_binding_table: VKShaderBindingTable,
+    pub descriptor_pool: vk::DescriptorPool,
+    /// set per frame in flight
+    pub sets: Vec<vk::DescriptorSet>,
+    output: Option<RayTraceOutput>,
+    // sets that point at the current top level and output
+    written: Vec<bool>,
+    depth_convention: DepthConvention,
+}
+
+impl VKRayTracer<'_> {
+    pub fn new(
+        vk_device: &mut VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        frames_in_flight: usize,
+        depth_convention: DepthConvention,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let Some(loader) = vk_device.ray_tracing_pipeline.clone() else {
+            return Err("Ray Tracing Not Supported by the Device".into());
+        };
+
+        let stages = [
+            (vk::ShaderStageFlags::RAYGEN_KHR, c"rayGen"),
+            (vk::ShaderStageFlags::MISS_KHR, c"miss"),
+            (vk::ShaderStageFlags::MISS_KHR, c"shadowMiss"),
+            (vk::ShaderStageFlags::CLOSEST_HIT_KHR, c"closestHit"),
+        ];
+        // release builds only load the compiled stages, slangc is a dev shell tool
+        let path = if Path::new(RAY_TRACE_SHADER).exists() || !cfg!(debug_assertions) {
+            RAY_TRACE_SHADER
+        } else {
+            RAY_TRACE_SOURCE
+        };
+        let shaders = stages
+            .iter()
+            .map(|(stage, entry)| VKShader::new(vk_device, path, *stage, entry, vk_shader_loader))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| {
+                format!("Ray Tracing Shaders Unavailable, Failed to Load {path}: {error}")
+            })?;
+
+        let binding = |binding: u32, ty: vk::DescriptorType, stages: vk::ShaderStageFlags| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(ty)
+                .descriptor_count(1)
+                .stage_flags(stages)
+        };
+        let set_bindings = [
+            binding(
+                0,
+                vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+                vk::ShaderStageFlags::RAYGEN_KHR,
+            ),
+            binding(
+                1,
+                vk::DescriptorType::STORAGE_IMAGE,
+                vk::ShaderStageFlags::RAYGEN_KHR,
+            ),
+            binding(
+                2,
+                vk::DescriptorType::UNIFORM_BUFFER,
+                vk::ShaderStageFlags::RAYGEN_KHR,
+            ),
+            binding(
+                3,
+                vk::DescriptorType::STORAGE_BUFFER,
+                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+            ),
+        ];
+        let descriptor_layout = unsafe {
+            vk_device.device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(&set_bindings),
+                None,
+            )?
+        };
+
+        let descriptor_layouts = [descriptor_layout];
+        let push_constant_ranges = [push_constant_range::<RayTracePass>(
+            vk::ShaderStageFlags::RAYGEN_KHR,
+            0,
+        )];
+        let pipeline_layout = unsafe {
+            vk_device.device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&descriptor_layouts)
+                    .push_constant_ranges(&push_constant_ranges),
+                None,
+            )?
+        };
+
+        let general = |shader: u32| {
+            vk::RayTracingShaderGroupCreateInfoKHR::default()
+                .ty(vk::RayTracingShaderGroupTypeKHR::GENERAL)
+                .general_shader(shader)
+                .closest_hit_shader(vk::SHADER_UNUSED_KHR)
+                .any_hit_shader(vk::SHADER_UNUSED_KHR)
+                .intersection_shader(vk::SHADER_UNUSED_KHR)
+        };
+        let groups = [
+            general(0),
+            general(1),
+            general(2),
+            vk::RayTracingShaderGroupCreateInfoKHR::default()
+                .ty(vk::RayTracingShaderGroupTypeKHR::TRIANGLES_HIT_GROUP)
+                .general_shader(vk::SHADER_UNUSED_KHR)
+                .closest_hit_shader(3)
+                .any_hit_shader(vk::SHADER_UNUSED_KHR)
+                .intersection_shader(vk::SHADER_UNUSED_KHR),
+        ];
+        let shader_infos: Vec<_> = shaders.iter().map(|shader| shader.shader_info).collect();
+        // shadow rays are traced from raygen so nothing recurses
+        let create_info = vk::RayTracingPipelineCreateInfoKHR::default()
+            .stages(&shader_infos)
+            .groups(&groups)
+            .max_pipeline_ray_recursion_depth(1)
+            .layout(pipeline_layout);
+        let pipeline = unsafe {
+            loader
+                .create_ray_tracing_pipelines(
+                    vk::DeferredOperationKHR::null(),
+                    vk_device.pipeline_cache.cache,
+                    &[create_info],
+                    None,
+                )
+                .map_err(|(_, error)| error)?[0]
+        };
+
+        let shader_binding_table = VKShaderBindingTable::new(vk_device, pipeline, 2, 1)?;
+
+        let frames = frames_in_flight as u32;
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+                descriptor_count: frames,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_IMAGE,
+                descriptor_count: frames,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: frames,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: frames,
+            },
+        ];
+        let descriptor_pool = unsafe {
+            vk_device.device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .max_sets(frames)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )?
+        };
+        let set_layouts = vec![descriptor_layout; frames_in_flight];
+        let sets = unsafe {
+            vk_device.device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(descriptor_pool)
+                    .set_layouts(&set_layouts),
+            )?
+        };
+
+        Ok(Self {
+            shaders,
+            descriptor_layout,
+            pipeline_layout,
+            pipeline,
+            shader_binding_table,
+            descriptor_pool,
+            sets,
+            output: None,
+            written: vec![false; frames_in_flight],
+            depth_convention,
+        })
+    }
+
+    /// Sizes the output to extent and points the frame's descriptor set at bvh's top level
+    /// bvh_replaced is what VKSceneBvh::update returned, returns true when the set was rewritten
+    /// # Safety
+    /// frame_in_flight's fence must have signalled, light_buffer is the frame's LightUniform
+    pub unsafe fn prepare(
+        &mut self,
+        vk_device: &mut VKDevice,
+        frame_in_flight: usize,
+        bvh: &VKSceneBvh,
+        bvh_replaced: bool,
+        light_buffer: vk::Buffer,
+        extent: vk::Extent2D,
+    ) -> Result<bool, vk::Result> {
+        if self
+            .output
+            .as_ref()
+            .is_none_or(|output| output.extent != extent)
+        {
+            // every frame traces into the output
+            unsafe { vk_device.device.device_wait_idle()? };
+            if let Some(mut old) = self.output.take() {
+                unsafe {
+                    vk_device.device.destroy_image_view(old.view, None);
+                    vk_device.destroy_image(old.image, std::mem::take(&mut old.allocation));
+                }
+            }
+            let (image, allocation) = vk_device.create_image(
+                extent,
+                OUTPUT_FORMAT,
+                vk::ImageTiling::OPTIMAL,
+                vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC,
+                vk::SampleCountFlags::TYPE_1,
+                MemoryLocation::GpuOnly,
+            )?;
+            let view =
+                vk_device.create_image_view(image, OUTPUT_FORMAT, vk::ImageAspectFlags::COLOR)?;
+            self.output = Some(RayTraceOutput {
+                image,
+                view,
+                allocation,
+                extent,
+            });
+            self.written.fill(false);
+        }
+
+        if bvh_replaced || !self.written[frame_in_flight] {
+            self.write_set(vk_device, frame_in_flight, bvh, light_buffer);
+            self.written[frame_in_flight] = true;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn write_set(
+        &self,
+        vk_device: &VKDevice,
+        frame_in_flight: usize,
+        bvh: &VKSceneBvh,
+        light_buffer: vk::Buffer,
+    ) {
+        let (Some(top_level), Some(output)) = (bvh.top_level(frame_in_flight), &self.output) else {
+            return;
+        };
+        let set = self.sets[frame_in_flight];
+        let structures = [top_level.structure.handle];
+        let mut structure_write = vk::WriteDescriptorSetAccelerationStructureKHR::default()
+            .acceleration_structures(&structures);
+        let image_info = [vk::DescriptorImageInfo::default()
+            .image_view(output.view)
+            .image_layout(vk::ImageLayout::GENERAL)];
+        let light_info = [vk::DescriptorBufferInfo::default()
+            .buffer(light_buffer)
+            .range(size_of::<LightUniform>() as u64)];
+        let data_info = [vk::DescriptorBufferInfo::default()
+            .buffer(top_level.data_buffer.buffer)
+            .range(vk::WHOLE_SIZE)];
+        let writes = [
+            vk::WriteDescriptorSet::default()
+                .dst_set(set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::ACCELERATION_STRUCTURE_KHR)
+                .descriptor_count(1)
+                .push_next(&mut structure_write),
+            vk::WriteDescriptorSet::default()
+                .dst_set(set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(&image_info),
+            vk::WriteDescriptorSet::default()
+                .dst_set(set)
+                .dst_binding(2)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(&light_info),
+            vk::WriteDescriptorSet::default()
+                .dst_set(set)
+                .dst_binding(3)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&data_info),
+        ];
+        unsafe { vk_device.device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    /// Traces camera rays and blits the result into target_image
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering after prepare for frame_in_flight and the
+    /// frame's VKSceneBvh::cmd_build, target_image must be in TRANSFER_DST_OPTIMAL and extent must
+    /// be the one given to prepare
+    pub unsafe fn record(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        frame_in_flight: usize,
+        target_image: vk::Image,
+        camera: &CameraUniform,
+        clear_color: LinearRgba,
+    ) {
+        let (Some(loader), Some(output)) = (&vk_device.ray_tracing_pipeline, &self.output) else {
+            return;
+        };
+        let pass = RayTracePass {
+            inverse_view_projection: camera.view_projection.inverse(),
+            clear_color: clear_color.to_vec4(),
+            near_depth: self.depth_convention.near_depth(),
+            padding: [0.0; 3],
+        };
+
+        // the output is shared with the last frame, whose blit has to finish first
+        let writable = [vk::ImageMemoryBarrier2::default()
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::GENERAL)
+            .src_stage_mask(vk::PipelineStageFlags2::BLIT)
+            .dst_stage_mask(vk::PipelineStageFlags2::RAY_TRACING_SHADER_KHR)
+            .dst_access_mask(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+            .image(output.image)
+            .subresource_range(COLOR_SUBRESOURCE_RANGE)];
+        let blit_ready = [vk::ImageMemoryBarrier2::default()
+            .old_layout(vk::ImageLayout::GENERAL)
+            .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .src_stage_mask(vk::PipelineStageFlags2::RAY_TRACING_SHADER_KHR)
+            .src_access_mask(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::BLIT)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_READ)
+            .image(output.image)
+            .subresource_range(COLOR_SUBRESOURCE_RANGE)];
+        let corner = vk::Offset3D {
+            x: output.extent.width as i32,
+            y: output.extent.height as i32,
+            z: 1,
+        };
+        let layers = vk::ImageSubresourceLayers::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .layer_count(1);
+        let blit = [vk::ImageBlit2::default()
+            .src_subresource(layers)
+            .src_offsets([vk::Offset3D::default(), corner])
+            .dst_subresource(layers)
+            .dst_offsets([vk::Offset3D::default(), corner])];
+
+        unsafe {
+            vk_device.device.cmd_pipeline_barrier2(
+                cmd_buffer,
+                &vk::DependencyInfo::default().image_memory_barriers(&writable),
+            );
+
+            vk_device.device.cmd_bind_pipeline(
+                cmd_buffer,
+                vk::PipelineBindPoint::RAY_TRACING_KHR,
+                self.pipeline,
+            );
+            vk_device.device.cmd_bind_descriptor_sets(
+                cmd_buffer,
+                vk::PipelineBindPoint::RAY_TRACING_KHR,
+                self.pipeline_layout,
+                0,
+                &[self.sets[frame_in_flight]],
+                &[],
+            );
+            vk_device.cmd_push_constants(
+                cmd_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::RAYGEN_KHR,
+                0,
+                &pass,
+            );
+            let table = &self.shader_binding_table;
+            loader.cmd_trace_rays(
+                cmd_buffer,
+                &table.raygen,
+                &table.miss,
+                &table.hit,
+                &table.callable,
+                output.extent.width,
+                output.extent.height,
+                1,
+            );
+
+            vk_device.device.cmd_pipeline_barrier2(
+                cmd_buffer,
+                &vk::DependencyInfo::default().image_memory_barriers(&blit_ready),
+            );
+            vk_device.device.cmd_blit_image2(
+                cmd_buffer,
+                &vk::BlitImageInfo2::default()
+                    .src_image(output.image)
+                    .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .dst_image(target_image)
+                    .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .regions(&blit)
+                    .filter(vk::Filter::NEAREST),
+            );
+        }
+    }
+
+    /// # Safety
+    /// The gpu must not be using the ray tracer
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            if let Some(mut output) = self.output.take() {
+                vk_device.device.destroy_image_view(output.view, None);
+                vk_device.destroy_image(output.image, std::mem::take(&mut output.allocation));
+            }
+            self.shader_binding_table.destroy(vk_device);
+            vk_device
+                .device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            vk_device.device.destroy_pipeline(self.pipeline, None);
+            vk_device
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            vk_device
+                .device
+                .destroy_descriptor_set_layout(self.descriptor_layout, None);
+            for shader in &mut self.shaders {
+                shader.destroy(vk_device);
+            }
+        }
+    }
+}
+
+/// Fragment stage of the uber-shader with a shadow ray per light against the VKSceneBvh
+/// see VKRenderer::set_ray_query_shadows, the scene's top level is bound to set 1
+pub struct VKRayQueryShadows<'a> {
+    pub fragment_shader: VKShader<'a>,
+    pub descriptor_layout: vk::DescriptorSetLayout,
+    /// the renderer's pipeline layout with descriptor_layout added as set 1
+    pub pipeline_layout: vk::PipelineLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    /// set per frame in flight
+    pub sets: Vec<vk::DescriptorSet>,
+    // sets that point at the frame's current top level
+    written: Vec<bool>,
+}
+
+impl VKRayQueryShadows<'_> {
+    /// scene_layout and push_constant_ranges are what the renderer's pipeline layout was made from
+    pub fn new(
+        vk_device: &mut VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        scene_layout: vk::DescriptorSetLayout,
+        push_constant_ranges: &[vk::PushConstantRange],
+        frames_in_flight: usize,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        if !vk_device.ray_query {
+            return Err("Ray Queries Not Supported by the Device".into());
+        }
+
+        let fragment_shader = VKShader::new(
+            vk_device,
+            RAY_QUERY_SHADER,
+            vk::ShaderStageFlags::FRAGMENT,
+            c"fragMain",
+            vk_shader_loader,
+        )?;
+
+        let set_bindings = [vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::ACCELERATION_STRUCTURE_KHR)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)];
+        let descriptor_layout = unsafe {
+            vk_device.device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(&set_bindings),
+                None,
+            )?
+        };
+
+        // set 0 and the push constants stay compatible with the renderer's other pipelines
+        let descriptor_layouts = [scene_layout, descriptor_layout];
+        let pipeline_layout = unsafe {
+            vk_device.device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&descriptor_layouts)
+                    .push_constant_ranges(push_constant_ranges),
+                None,
+            )?
+        };
+
+        let frames = frames_in_flight as u32;
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            descriptor_count: frames,
+        }];
+        let descriptor_pool = unsafe {
+            vk_device.device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .max_sets(frames)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )?
+        };
+        let set_layouts = vec![descriptor_layout; frames_in_flight];
+        let sets = unsafe {
+            vk_device.device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(descriptor_pool)
+                    .set_layouts(&set_layouts),
+            )?
+        };
+
+        Ok(Self {
+            fragment_shader,
+            descriptor_layout,
+            pipeline_layout,
+            descriptor_pool,
+            sets,
+            written: vec![false; frames_in_flight],
+        })
+    }
+
+    /// Points the frame's set at bvh's top level when it was replaced or never written
+    /// returns true when the set was rewritten
+    /// # Safety
+    /// frame_in_flight's fence must have signalled
+    pub unsafe fn prepare(
+        &mut self,
+        vk_device: &VKDevice,
+        frame_in_flight: usize,
+        bvh: &VKSceneBvh,
+        bvh_replaced: bool,
+    ) -> bool {
+        if !bvh_replaced && self.written[frame_in_flight] {
+            return false;
+        }
+        let Some(top_level) = bvh.top_level(frame_in_flight) else {
+            return false;
+        };
+
+        let structures = [top_level.structure.handle];
+        let mut structure_write = vk::WriteDescriptorSetAccelerationStructureKHR::default()
+            .acceleration_structures(&structures);
+        let writes = [vk::WriteDescriptorSet::default()
+            .dst_set(self.sets[frame_in_flight])
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::ACCELERATION_STRUCTURE_KHR)
+            .descriptor_count(1)
+            .push_next(&mut structure_write)];
+        unsafe { vk_device.device.update_descriptor_sets(&writes, &[]) };
+        self.written[frame_in_flight] = true;
+        true
+    }
+
+    /// Binds the frame's top level as set 1, material sets have to be bound with pipeline_layout
+    /// afterwards so it isn't disturbed
+    /// # Safety
+    /// cmd_buffer must be recording after prepare for frame_in_flight
+    pub unsafe fn cmd_bind(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        frame_in_flight: usize,
+    ) {
+        unsafe {
+            vk_device.device.cmd_bind_descriptor_sets(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                1,
+                &[self.sets[frame_in_flight]],
+                &[],
+            )
+        };
+    }
+
+    /// # Safety
+    /// The gpu must not be using the shadows and pipelines made with pipeline_layout are destroyed
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            vk_device
+                .device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            vk_device
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            vk_device
+                .device
+                .destroy_descriptor_set_layout(self.descriptor_layout, None);
+            self.fragment_shader.destroy(vk_device);
+        }
+    }
+}
+
+#[test]
+fn ray_tracing_layout_test() {
+    // the hit shader reads vertices as floats and instances with std430 layout
+    assert_eq!(size_of::<Vertex>(), 16 * 4);
+    assert_eq!(std::mem::offset_of!(Vertex, color), 3 * 4);
+    assert_eq!(std::mem::offset_of!(Vertex, normal), 8 * 4);
+    assert_eq!(size_of::<RayInstance>(), 64);
+    assert_eq!(size_of::<vk::AccelerationStructureInstanceKHR>(), 64);
+
+    let transform = Mat4::from_translation(glam::Vec3::new(1.0, 2.0, 3.0))
+        * Mat4::from_scale(glam::Vec3::splat(2.0));
+    let matrix = instance_transform(&transform).matrix;
+    assert_eq!(
+        matrix,
+        [2.0, 0.0, 0.0, 1.0, 0.0, 2.0, 0.0, 2.0, 0.0, 0.0, 2.0, 3.0]
+    );
+
+    // common desktop values, 32 byte handles and 64 byte regions
+    let properties = RayTracingProperties {
+        shader_group_handle_size: 32,
+        shader_group_handle_alignment: 32,
+        shader_group_base_alignment: 64,
+        max_ray_recursion_depth: 31,
+        min_scratch_offset_alignment: 128,
+    };
+    let layout = ShaderBindingLayout::new(&properties, 2, 1);
+    assert_eq!(layout.stride, 32);
+    assert_eq!(layout.raygen, (0, 64));
+    assert_eq!(layout.miss, (64, 64));
+    assert_eq!(layout.hit, (128, 64));
+    assert_eq!(layout.size, 192);
+
+    let layout = ShaderBindingLayout::new(
+        &RayTracingProperties {
+            shader_group_handle_size: 48,
+            ..properties
+        },
+        3,
+        1,
+    );
+    assert_eq!(layout.stride, 64);
+    assert_eq!(layout.miss, (64, 192));
+    assert_eq!(layout.hit.0 % 64, 0);
+}
+
+#[test]
+fn ray_trace_shader_test() {
+    use crate::renderer::shader::{spirv_entry_points, spirv_instructions};
+
+    // the compiled stages ship with the engine, release builds can't fall back to slangc
+    let bytes = std::fs::read(RAY_TRACE_SHADER).unwrap();
+    let spirv = ash::util::read_spv(&mut std::io::Cursor::new(bytes)).unwrap();
+    let instructions = spirv_instructions(&spirv);
+    let entry_points = spirv_entry_points(&spirv);
+    for entry in ["rayGen", "miss", "shadowMiss", "closestHit"] {
+        assert!(entry_points.iter().any(|name| name == entry), "{entry}");
+    }
+
+    // a module built before the depth convention option has no near depth in its push
+    // constants, OpTypePointer in the PushConstant storage class then OpMemberDecorate Offset
+    let push_constants = instructions
+        .iter()
+        .filter(|words| words[0] & 0xffff == 32 && words[2] == 9)
+        .map(|words| words[3])
+        .collect::<Vec<_>>();
+    let near_depth = std::mem::offset_of!(RayTracePass, near_depth) as u32;
+    assert!(
+        instructions.iter().any(|words| words[0] & 0xffff == 72
+            && push_constants.contains(&words[1])
+            && words[3] == 35
+            && words[4] == near_depth),
+        "{RAY_TRACE_SHADER} is older than {RAY_TRACE_SOURCE}, rebuild it with slangc"
+    );
+}
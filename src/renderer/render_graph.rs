@@ -0,0 +1,569 @@
+use ash::vk;
+use std::ffi::CStr;
+
+use crate::renderer::device::VKDevice;
+
+/// How a pass uses an image or buffer, decides the layout and the barriers around the pass
+/// layouts only matter for images
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Access {
+    ColorAttachment,
+    DepthAttachment,
+    /// sampled from a fragment shader
+    FragmentSampled,
+    /// read from a compute shader, images are in GENERAL
+    ComputeRead,
+    /// storage written from a compute shader, images are in GENERAL
+    ComputeWrite,
+    TransferSrc,
+    TransferDst,
+    VertexRead,
+    IndirectRead,
+    /// uniform buffer read by vertex and fragment shaders
+    UniformRead,
+    /// mapped and read by the cpu once the frame's fence is signalled
+    HostRead,
+    /// handed to the presentation engine, the acquire semaphore is waited on at colour output
+    /// so barriers after acquiring chain off that stage
+    Present,
+}
+
+impl Access {
+    pub fn layout(self) -> vk::ImageLayout {
+        match self {
+            Self::ColorAttachment => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            Self::DepthAttachment => vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            Self::FragmentSampled => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            Self::ComputeRead | Self::ComputeWrite | Self::HostRead => vk::ImageLayout::GENERAL,
+            Self::TransferSrc => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            Self::TransferDst => vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            Self::VertexRead | Self::IndirectRead | Self::UniformRead => vk::ImageLayout::UNDEFINED,
+            Self::Present => vk::ImageLayout::PRESENT_SRC_KHR,
+        }
+    }
+
+    pub fn stage(self) -> vk::PipelineStageFlags2 {
+        match self {
+            Self::ColorAttachment | Self::Present => {
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT
+            }
+            Self::DepthAttachment => {
+                vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS
+            }
+            Self::FragmentSampled => vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            Self::ComputeRead | Self::ComputeWrite => vk::PipelineStageFlags2::COMPUTE_SHADER,
+            Self::TransferSrc | Self::TransferDst => vk::PipelineStageFlags2::ALL_TRANSFER,
+            Self::VertexRead => {
+                vk::PipelineStageFlags2::VERTEX_ATTRIBUTE_INPUT
+                    | vk::PipelineStageFlags2::INDEX_INPUT
+            }
+            Self::IndirectRead => vk::PipelineStageFlags2::DRAW_INDIRECT,
+            Self::UniformRead => {
+                vk::PipelineStageFlags2::VERTEX_SHADER | vk::PipelineStageFlags2::FRAGMENT_SHADER
+            }
+            Self::HostRead => vk::PipelineStageFlags2::HOST,
+        }
+    }
+
+    pub fn access(self) -> vk::AccessFlags2 {
+        match self {
+            Self::ColorAttachment => {
+                vk::AccessFlags2::COLOR_ATTACHMENT_READ | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+            }
+            Self::DepthAttachment => {
+                vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
+            }
+            Self::FragmentSampled => vk::AccessFlags2::SHADER_SAMPLED_READ,
+            Self::ComputeRead => vk::AccessFlags2::SHADER_READ,
+            Self::ComputeWrite => {
+                vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE
+            }
+            Self::TransferSrc => vk::AccessFlags2::TRANSFER_READ,
+            Self::TransferDst => vk::AccessFlags2::TRANSFER_WRITE,
+            Self::VertexRead => {
+                vk::AccessFlags2::VERTEX_ATTRIBUTE_READ | vk::AccessFlags2::INDEX_READ
+            }
+            Self::IndirectRead => vk::AccessFlags2::INDIRECT_COMMAND_READ,
+            Self::UniformRead => vk::AccessFlags2::UNIFORM_READ,
+            Self::HostRead => vk::AccessFlags2::HOST_READ,
+            Self::Present => vk::AccessFlags2::NONE,
+        }
+    }
+
+    pub fn is_write(self) -> bool {
+        matches!(
+            self,
+            Self::ColorAttachment | Self::DepthAttachment | Self::ComputeWrite | Self::TransferDst
+        )
+    }
+}
+
+/// Image added to a RenderGraph with import_image
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ImageHandle(usize);
+
+/// Buffer added to a RenderGraph with import_buffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BufferHandle(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Resource {
+    Image(ImageHandle),
+    Buffer(BufferHandle),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Use {
+    resource: Resource,
+    access: Access,
+    /// old contents aren't needed, images go from UNDEFINED
+    discard: bool,
+}
+
+// what the graph knows about a resource between passes
+#[derive(Clone, Copy, Debug)]
+struct ResourceState {
+    layout: vk::ImageLayout,
+    // newest write, NONE when the last barrier already made it visible to the readers
+    write_stage: vk::PipelineStageFlags2,
+    write_access: vk::AccessFlags2,
+    // reads since the newest write that a barrier has been recorded for
+    read_stage: vk::PipelineStageFlags2,
+    read_access: vk::AccessFlags2,
+}
+
+impl ResourceState {
+    fn new(previous: &[Access]) -> Self {
+        let mut state = Self {
+            layout: previous
+                .first()
+                .map_or(vk::ImageLayout::UNDEFINED, |access| access.layout()),
+            write_stage: vk::PipelineStageFlags2::NONE,
+            write_access: vk::AccessFlags2::NONE,
+            read_stage: vk::PipelineStageFlags2::NONE,
+            read_access: vk::AccessFlags2::NONE,
+        };
+        for access in previous {
+            if access.is_write() {
+                state.write_stage |= access.stage();
+                state.write_access |= access.access();
+            } else {
+                state.read_stage |= access.stage();
+            }
+        }
+        state
+    }
+
+    // barrier needed before access, None when an earlier barrier already covers it
+    // returns (src stage, src access, old layout) and moves the state on to after access
+    fn transition(
+        &mut self,
+        access: Access,
+        discard: bool,
+        image: bool,
+    ) -> Option<(vk::PipelineStageFlags2, vk::AccessFlags2, vk::ImageLayout)> {
+        let layout = if image {
+            access.layout()
+        } else {
+            vk::ImageLayout::UNDEFINED
+        };
+        let old_layout = if discard {
+            vk::ImageLayout::UNDEFINED
+        } else {
+            self.layout
+        };
+        let layout_changes = image && (discard || layout != self.layout);
+
+        if !access.is_write() && !layout_changes {
+            // another read of something that's already visible to this stage
+            if self.read_stage.contains(access.stage())
+                && self.read_access.contains(access.access())
+            {
+                return None;
+            }
+            // chain off the earlier readers when their barrier already took care of the write
+            let src_stage = if self.write_stage.is_empty() {
+                self.read_stage
+            } else {
+                self.write_stage
+            };
+            let barrier = (src_stage, self.write_access, old_layout);
+            self.read_stage |= access.stage();
+            self.read_access |= access.access();
+            return Some(barrier);
+        }
+
+        // writes and layout changes wait for every earlier use
+        let barrier = (
+            self.write_stage | self.read_stage,
+            self.write_access,
+            old_layout,
+        );
+        self.layout = layout;
+        if access.is_write() {
+            self.write_stage = access.stage();
+            self.write_access = access.access();
+            self.read_stage = vk::PipelineStageFlags2::NONE;
+            self.read_access = vk::AccessFlags2::NONE;
+        } else {
+            // the layout transition is visible to access, later readers chain off it
+            self.write_stage = vk::PipelineStageFlags2::NONE;
+            self.write_access = vk::AccessFlags2::NONE;
+            self.read_stage = access.stage();
+            self.read_access = access.access();
+        }
+        Some(barrier)
+    }
+}
+
+struct GraphImage {
+    image: vk::Image,
+    range: vk::ImageSubresourceRange,
+    previous: Vec<Access>,
+}
+
+struct GraphBuffer {
+    buffer: vk::Buffer,
+    previous: Vec<Access>,
+}
+
+/// Commands recorded by a RenderGraph, along with the images and buffers they use
+/// Example Use:
+/// ```ignore
+/// let pass = RenderPass::new(c"Bloom")
+///     .read_image(scene_color, Access::FragmentSampled)
+///     .discard_image(bloom, Access::ColorAttachment)
+///     .record(move |cmd_buffer| unsafe { bloom_pass.record(vk_device, cmd_buffer) });
+/// graph.add_pass(pass);
+/// ```
+pub struct RenderPass<'a> {
+    pub name: &'static CStr,
+    uses: Vec<Use>,
+    record: Option<Box<dyn FnOnce(vk::CommandBuffer) + 'a>>,
+}
+
+impl<'a> RenderPass<'a> {
+    pub fn new(name: &'static CStr) -> Self {
+        Self {
+            name,
+            uses: Vec::new(),
+            record: None,
+        }
+    }
+
+    fn with_use(mut self, resource: Resource, access: Access, discard: bool) -> Self {
+        debug_assert!(
+            self.uses.iter().all(|used| used.resource != resource),
+            "{:?} uses a resource twice",
+            self.name
+        );
+        self.uses.push(Use {
+            resource,
+            access,
+            discard,
+        });
+        self
+    }
+
+    /// Uses image, keeping what earlier passes left in it
+    /// writes are declared the same way, whether access writes decides the barriers
+    pub fn read_image(self, image: ImageHandle, access: Access) -> Self {
+        self.with_use(Resource::Image(image), access, false)
+    }
+
+    /// Same as read_image, named for passes that add to an image
+    pub fn write_image(self, image: ImageHandle, access: Access) -> Self {
+        self.with_use(Resource::Image(image), access, false)
+    }
+
+    /// Uses image without keeping its contents, for passes that clear or overwrite all of it
+    pub fn discard_image(self, image: ImageHandle, access: Access) -> Self {
+        self.with_use(Resource::Image(image), access, true)
+    }
+
+    pub fn read_buffer(self, buffer: BufferHandle, access: Access) -> Self {
+        self.with_use(Resource::Buffer(buffer), access, false)
+    }
+
+    pub fn write_buffer(self, buffer: BufferHandle, access: Access) -> Self {
+        self.with_use(Resource::Buffer(buffer), access, false)
+    }
+
+    /// Commands of the pass, recorded after the barriers the graph derived for it
+    pub fn record(mut self, record: impl FnOnce(vk::CommandBuffer) + 'a) -> Self {
+        self.record = Some(Box::new(record));
+        self
+    }
+}
+
+/// Barriers recorded before a pass, in one cmd_pipeline_barrier2
+#[derive(Clone, Debug, Default)]
+pub struct PassBarriers {
+    pub image_barriers: Vec<vk::ImageMemoryBarrier2<'static>>,
+    pub buffer_barriers: Vec<vk::BufferMemoryBarrier2<'static>>,
+}
+
+impl PassBarriers {
+    pub fn is_empty(&self) -> bool {
+        self.image_barriers.is_empty() && self.buffer_barriers.is_empty()
+    }
+}
+
+/// Passes of a frame in the order they are recorded, each declares the images and buffers it
+/// uses and the graph works out the layout transitions and barriers in between
+/// images and buffers are imported with what last used them, usually the previous frame
+/// Example Use:
+/// ```ignore
+/// let mut graph = RenderGraph::default();
+/// let swap_image = graph.import_image(target.image, COLOR_SUBRESOURCE_RANGE, &[Access::Present]);
+/// graph.add_pass(
+///     RenderPass::new(c"Scene")
+///         .discard_image(swap_image, Access::ColorAttachment)
+///         .record(|cmd_buffer| unsafe { record_scene(cmd_buffer) }),
+/// );
+/// graph.export_image(swap_image, Access::Present);
+/// unsafe { graph.execute(vk_device, cmd_buffer) };
+/// ```
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    images: Vec<GraphImage>,
+    buffers: Vec<GraphBuffer>,
+    passes: Vec<RenderPass<'a>>,
+    exports: Vec<Use>,
+}
+
+impl<'a> RenderGraph<'a> {
+    /// Adds an image passes can use, previous is everything that may still be using it
+    /// when the graph starts, images are assumed to be in the layout of the first
+    /// an empty previous is a fresh image in UNDEFINED
+    pub fn import_image(
+        &mut self,
+        image: vk::Image,
+        range: vk::ImageSubresourceRange,
+        previous: &[Access],
+    ) -> ImageHandle {
+        self.images.push(GraphImage {
+            image,
+            range,
+            previous: previous.to_vec(),
+        });
+        ImageHandle(self.images.len() - 1)
+    }
+
+    /// Adds a buffer passes can use, the whole buffer is covered by its barriers
+    pub fn import_buffer(&mut self, buffer: vk::Buffer, previous: &[Access]) -> BufferHandle {
+        self.buffers.push(GraphBuffer {
+            buffer,
+            previous: previous.to_vec(),
+        });
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    /// Passes are recorded in the order they are added
+    pub fn add_pass(&mut self, pass: RenderPass<'a>) {
+        self.passes.push(pass);
+    }
+
+    /// Leaves image ready for access once every pass has been recorded
+    pub fn export_image(&mut self, image: ImageHandle, access: Access) {
+        self.exports.push(Use {
+            resource: Resource::Image(image),
+            access,
+            discard: false,
+        });
+    }
+
+    /// Makes every pass's writes to buffer visible to access once the graph has been recorded
+    pub fn export_buffer(&mut self, buffer: BufferHandle, access: Access) {
+        self.exports.push(Use {
+            resource: Resource::Buffer(buffer),
+            access,
+            discard: false,
+        });
+    }
+
+    /// Barriers before each pass, with one extra entry after the last pass for the exports
+    pub fn barriers(&self) -> Vec<PassBarriers> {
+        let mut image_states: Vec<ResourceState> = self
+            .images
+            .iter()
+            .map(|image| ResourceState::new(&image.previous))
+            .collect();
+        let mut buffer_states: Vec<ResourceState> = self
+            .buffers
+            .iter()
+            .map(|buffer| ResourceState::new(&buffer.previous))
+            .collect();
+
+        self.passes
+            .iter()
+            .map(|pass| &pass.uses[..])
+            .chain([&self.exports[..]])
+            .map(|uses| {
+                let mut barriers = PassBarriers::default();
+                for used in uses {
+                    match used.resource {
+                        Resource::Image(ImageHandle(index)) => {
+                            let image = &self.images[index];
+                            if let Some((src_stage, src_access, old_layout)) =
+                                image_states[index].transition(used.access, used.discard, true)
+                            {
+                                barriers.image_barriers.push(
+                                    vk::ImageMemoryBarrier2::default()
+                                        .old_layout(old_layout)
+                                        .new_layout(used.access.layout())
+                                        .src_stage_mask(src_stage)
+                                        .src_access_mask(src_access)
+                                        .dst_stage_mask(used.access.stage())
+                                        .dst_access_mask(used.access.access())
+                                        .image(image.image)
+                                        .subresource_range(image.range),
+                                );
+                            }
+                        }
+                        Resource::Buffer(BufferHandle(index)) => {
+                            if let Some((src_stage, src_access, _)) =
+                                buffer_states[index].transition(used.access, used.discard, false)
+                            {
+                                barriers.buffer_barriers.push(
+                                    vk::BufferMemoryBarrier2::default()
+                                        .src_stage_mask(src_stage)
+                                        .src_access_mask(src_access)
+                                        .dst_stage_mask(used.access.stage())
+                                        .dst_access_mask(used.access.access())
+                                        .buffer(self.buffers[index].buffer)
+                                        .size(vk::WHOLE_SIZE),
+                                );
+                            }
+                        }
+                    }
+                }
+                barriers
+            })
+            .collect()
+    }
+
+    /// Records every pass with the barriers before it, then the barriers for the exports
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering, imported images and buffers must
+    /// stay alive until the commands have finished
+    pub unsafe fn execute(self, vk_device: &VKDevice, cmd_buffer: vk::CommandBuffer) {
+        let mut barriers = self.barriers().into_iter();
+        for pass in self.passes {
+            if let Some(barriers) = barriers.next() {
+                unsafe { cmd_barriers(vk_device, cmd_buffer, &barriers) };
+            }
+            if let Some(record) = pass.record {
+                record(cmd_buffer);
+            }
+        }
+        if let Some(barriers) = barriers.next() {
+            unsafe { cmd_barriers(vk_device, cmd_buffer, &barriers) };
+        }
+    }
+}
+
+unsafe fn cmd_barriers(
+    vk_device: &VKDevice,
+    cmd_buffer: vk::CommandBuffer,
+    barriers: &PassBarriers,
+) {
+    if barriers.is_empty() {
+        return;
+    }
+    let dependency_info = vk::DependencyInfo::default()
+        .image_memory_barriers(&barriers.image_barriers)
+        .buffer_memory_barriers(&barriers.buffer_barriers);
+    unsafe {
+        vk_device
+            .device
+            .cmd_pipeline_barrier2(cmd_buffer, &dependency_info)
+    };
+}
+
+#[test]
+fn render_graph_test() {
+    use ash::vk::Handle;
+
+    let range = vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .level_count(1)
+        .layer_count(1);
+    let mut graph = RenderGraph::default();
+    let swap_image = graph.import_image(vk::Image::from_raw(1), range, &[Access::Present]);
+    let scene = graph.import_image(
+        vk::Image::from_raw(2),
+        range,
+        &[Access::FragmentSampled, Access::TransferSrc],
+    );
+    let readback = graph.import_buffer(vk::Buffer::from_raw(3), &[]);
+
+    graph.add_pass(RenderPass::new(c"Scene").discard_image(scene, Access::ColorAttachment));
+    graph.add_pass(RenderPass::new(c"Post").read_image(scene, Access::FragmentSampled));
+    // a second read in the same stage needs nothing more
+    graph.add_pass(RenderPass::new(c"Post 2").read_image(scene, Access::FragmentSampled));
+    graph.add_pass(
+        RenderPass::new(c"Blit")
+            .read_image(scene, Access::TransferSrc)
+            .discard_image(swap_image, Access::TransferDst),
+    );
+    graph.add_pass(
+        RenderPass::new(c"Copy")
+            .read_image(scene, Access::TransferSrc)
+            .write_buffer(readback, Access::TransferDst),
+    );
+    graph.export_image(swap_image, Access::Present);
+    graph.export_buffer(readback, Access::HostRead);
+
+    let barriers = graph.barriers();
+    assert_eq!(barriers.len(), 6);
+
+    // waits for the previous frame's readers before throwing the contents away
+    let clear = &barriers[0].image_barriers[0];
+    assert_eq!(clear.old_layout, vk::ImageLayout::UNDEFINED);
+    assert_eq!(clear.new_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    assert_eq!(
+        clear.src_stage_mask,
+        vk::PipelineStageFlags2::FRAGMENT_SHADER | vk::PipelineStageFlags2::ALL_TRANSFER
+    );
+    assert_eq!(clear.src_access_mask, vk::AccessFlags2::NONE);
+
+    let sample = &barriers[1].image_barriers[0];
+    assert_eq!(sample.old_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    assert_eq!(sample.new_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    assert!(
+        sample
+            .src_access_mask
+            .contains(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE)
+    );
+    assert!(barriers[2].is_empty());
+
+    let [blit_source, blit_target] = [0, 1].map(|index| barriers[3].image_barriers[index]);
+    assert_eq!(
+        blit_source.old_layout,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+    );
+    assert_eq!(
+        blit_source.src_stage_mask,
+        vk::PipelineStageFlags2::FRAGMENT_SHADER
+    );
+    assert_eq!(blit_target.old_layout, vk::ImageLayout::UNDEFINED);
+    assert_eq!(
+        blit_target.src_stage_mask,
+        vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT
+    );
+
+    // the image is already a transfer source, only the fresh buffer needs a barrier
+    assert!(barriers[4].image_barriers.is_empty());
+    assert_eq!(barriers[4].buffer_barriers.len(), 1);
+
+    let present = &barriers[5].image_barriers[0];
+    assert_eq!(present.old_layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+    assert_eq!(present.new_layout, vk::ImageLayout::PRESENT_SRC_KHR);
+    assert_eq!(present.src_access_mask, vk::AccessFlags2::TRANSFER_WRITE);
+    let host = &barriers[5].buffer_barriers[0];
+    assert_eq!(host.src_access_mask, vk::AccessFlags2::TRANSFER_WRITE);
+    assert_eq!(host.dst_access_mask, vk::AccessFlags2::HOST_READ);
+}
@@ -0,0 +1,237 @@
+use ash::vk;
+
+use crate::camera::{Camera, CameraUniform};
+use crate::renderer::device::VKDevice;
+use crate::renderer::render_graph::Access;
+use crate::renderer::scaling::{InternalResolution, VKInternalTarget};
+
+/// Index of a render texture in VKRenderer::render_textures
+pub type RenderTextureId = usize;
+
+/// How often a render texture's camera redraws the scene
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UpdateRate {
+    #[default]
+    EveryFrame,
+    /// once every this many frames, distant monitors don't need to keep up with the window
+    Interval(u32),
+    /// only when VKRenderer::refresh_render_texture asks for it, e.g. a photo on a wall
+    Manual,
+}
+
+impl UpdateRate {
+    /// Whether a texture last drawn frames_since_update frames ago is drawn this frame
+    /// None means it hasn't been drawn or a refresh was asked for, which is always drawn
+    pub fn due(self, frames_since_update: Option<u32>) -> bool {
+        let Some(frames) = frames_since_update else {
+            return true;
+        };
+        match self {
+            UpdateRate::EveryFrame => true,
+            UpdateRate::Interval(interval) => frames + 1 >= interval,
+            UpdateRate::Manual => false,
+        }
+    }
+}
+
+/// A camera whose view is rendered into a texture materials can sample
+/// for security monitors, portals and mirrors
+/// Example Use:
+/// ```
+/// use glam::Vec3;
+/// use vulkan_engine::camera::Camera;
+/// use vulkan_engine::renderer::render_texture::{RenderCamera, UpdateRate};
+///
+/// // a monitor showing the hallway at 15 fps when the window runs at 60
+/// let hallway = Camera::default().look_at(Vec3::new(0.0, 3.0, 8.0), Vec3::ZERO, Vec3::Y);
+/// let monitor = RenderCamera::new(hallway, 320, 240).update_rate(UpdateRate::Interval(4));
+/// assert_eq!(monitor.aspect_ratio(), 4.0 / 3.0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderCamera {
+    pub camera: Camera,
+    pub extent: vk::Extent2D,
+    pub update_rate: UpdateRate,
+}
+
+impl RenderCamera {
+    pub fn new(camera: Camera, width: u32, height: u32) -> Self {
+        Self {
+            camera,
+            extent: vk::Extent2D {
+                width: width.max(1),
+                height: height.max(1),
+            },
+            update_rate: UpdateRate::default(),
+        }
+    }
+
+    pub fn update_rate(mut self, update_rate: UpdateRate) -> Self {
+        self.update_rate = update_rate;
+        self
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.extent.width as f32 / self.extent.height as f32
+    }
+}
+
+/// Offscreen target a RenderCamera is drawn into, sampled by materials bound to it with
+/// VKRenderer::set_material_render_texture
+/// the image is shared between frames in flight like the internal target, between updates it
+/// keeps the last view
+pub struct VKRenderTexture {
+    /// moved freely between frames, the texture follows on its next update
+    pub camera: Camera,
+    pub update_rate: UpdateRate,
+    /// resized with VKRenderer::set_render_texture_resolution
+    pub target: VKInternalTarget,
+    pub sampler: vk::Sampler,
+    frames_since_update: Option<u32>,
+    // times the image has been scheduled for drawing, it has no contents before the first
+    updates: u64,
+    due: bool,
+}
+
+impl VKRenderTexture {
+    /// format and samples have to match the swapchain, the scene pipelines are built for those
+    pub fn new(
+        vk_device: &mut VKDevice,
+        camera: RenderCamera,
+        format: vk::Format,
+        samples: vk::SampleCountFlags,
+    ) -> Result<Self, vk::Result> {
+        let resolution = InternalResolution::new(camera.extent.width, camera.extent.height);
+        let mut target = VKInternalTarget::new(vk_device, resolution, format, samples)?;
+
+        // clamped so the edges of a screen don't bleed into each other
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = match unsafe { vk_device.device.create_sampler(&sampler_info, None) } {
+            Ok(sampler) => sampler,
+            Err(error) => {
+                unsafe { target.destroy(vk_device) };
+                return Err(error);
+            }
+        };
+
+        Ok(Self {
+            camera: camera.camera,
+            update_rate: camera.update_rate,
+            target,
+            sampler,
+            frames_since_update: None,
+            updates: 0,
+            due: false,
+        })
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.target.resolution.extent
+    }
+
+    /// Matrices the scene is drawn with, never pre-rotated since the texture isn't presented
+    pub fn uniform(&self) -> CameraUniform {
+        let extent = self.extent();
+        self.camera
+            .uniform(extent.width as f32 / extent.height as f32)
+    }
+
+    /// Replaces the target with one of extent, the old view is lost so it's drawn again next frame
+    /// # Safety
+    /// The gpu must not be using the texture, materials sampling it have to be written again
+    pub unsafe fn resize(
+        &mut self,
+        vk_device: &mut VKDevice,
+        extent: vk::Extent2D,
+        format: vk::Format,
+    ) -> Result<(), vk::Result> {
+        let resolution = InternalResolution::new(extent.width, extent.height);
+        let target = VKInternalTarget::new(vk_device, resolution, format, self.target.samples)?;
+        unsafe { std::mem::replace(&mut self.target, target).destroy(vk_device) };
+        self.frames_since_update = None;
+        self.updates = 0;
+        Ok(())
+    }
+
+    /// Draws the texture next frame whatever its update rate
+    pub fn refresh(&mut self) {
+        self.frames_since_update = None;
+    }
+
+    /// Decides whether the frame about to be recorded draws the texture, called once per frame
+    pub fn advance(&mut self) -> bool {
+        self.due = self.update_rate.due(self.frames_since_update);
+        if self.due {
+            self.frames_since_update = Some(0);
+            self.updates += 1;
+        } else {
+            self.frames_since_update = self.frames_since_update.map(|frames| frames + 1);
+        }
+        self.due
+    }
+
+    /// Whether the frame being recorded draws the texture
+    pub fn is_due(&self) -> bool {
+        self.due
+    }
+
+    /// What may still be using the image when the frame starts, for importing it into a graph
+    /// the last frame's materials may still be sampling it, a new image has nothing to wait for
+    pub fn previous_access(&self) -> &'static [Access] {
+        if self.updates > 1 {
+            &[Access::FragmentSampled]
+        } else {
+            &[]
+        }
+    }
+
+    /// For writing into a material's albedo binding, valid once the texture has been drawn
+    pub fn descriptor_image_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo::default()
+            .sampler(self.sampler)
+            .image_view(self.target.image.view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+
+    /// # Safety
+    /// The gpu must not be using the texture
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            vk_device.device.destroy_sampler(self.sampler, None);
+            self.target.destroy(vk_device);
+        }
+    }
+}
+
+#[test]
+fn render_texture_update_rate_test() {
+    assert!(UpdateRate::EveryFrame.due(Some(0)));
+    assert!(UpdateRate::Manual.due(None));
+    assert!(!UpdateRate::Manual.due(Some(100)));
+
+    // drawn on frames 0, 3 and 6
+    let rate = UpdateRate::Interval(3);
+    let mut frames_since_update = None;
+    let mut drawn = Vec::new();
+    for frame in 0..8 {
+        if rate.due(frames_since_update) {
+            drawn.push(frame);
+            frames_since_update = Some(0);
+        } else {
+            frames_since_update = frames_since_update.map(|frames| frames + 1);
+        }
+    }
+    assert_eq!(drawn, [0, 3, 6]);
+
+    // an interval of 0 or 1 is every frame
+    assert!(UpdateRate::Interval(0).due(Some(0)));
+    assert!(UpdateRate::Interval(1).due(Some(0)));
+
+    let camera = RenderCamera::new(Camera::default(), 0, 240);
+    assert_eq!(camera.extent.width, 1);
+}
@@ -0,0 +1,861 @@
+use ash::vk;
+use glam::{Mat4, Vec2, Vec4};
+use gpu_allocator::MemoryLocation;
+use log::warn;
+use std::error;
+use std::mem::offset_of;
+
+use crate::color::LinearRgba;
+use crate::renderer::buffer::VKBuffer;
+use crate::renderer::device::VKDevice;
+use crate::renderer::pipeline::{BlendMode, GraphicsPipelineBuilder};
+use crate::renderer::presentation::VKSwapchain;
+use crate::renderer::shader::{VKShader, VKShaderLoader, reload_shaders};
+use crate::renderer::texture::VKTexture;
+use crate::renderer::vertex::{VertexAttribute, VertexLayout};
+use crate::renderer::{RenderTarget, pre_rotation, push_constant_range};
+
+/// Index of a texture in VKRenderer2D::textures
+pub type SpriteTextureId = usize;
+
+/// Plain white texel every renderer2d starts with, for solid coloured sprites
+pub const WHITE_TEXTURE: SpriteTextureId = 0;
+
+/// Textures that fit in the descriptor pool, there is no limit when push descriptors are used
+pub const MAX_SPRITE_TEXTURES: u32 = 64;
+
+// sprites each frame's vertex buffer holds before it has to grow
+const INITIAL_SPRITE_CAPACITY: usize = 256;
+
+const VERTICES_PER_SPRITE: usize = 6;
+
+/// How a sprite is drawn over what's under it, each has its own pipeline
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SpriteBlend {
+    /// straight alpha
+    #[default]
+    Alpha,
+    /// colour already multiplied by alpha, an alpha of 0 adds the colour
+    Premultiplied,
+    /// darkens what's under it per channel by the texture times colour, see BlendMode::Coverage
+    Coverage,
+}
+
+impl SpriteBlend {
+    pub const ALL: [SpriteBlend; 3] = [
+        SpriteBlend::Alpha,
+        SpriteBlend::Premultiplied,
+        SpriteBlend::Coverage,
+    ];
+
+    pub fn blend_mode(self) -> BlendMode {
+        match self {
+            SpriteBlend::Alpha => BlendMode::Alpha,
+            SpriteBlend::Premultiplied => BlendMode::Premultiplied,
+            SpriteBlend::Coverage => BlendMode::Coverage,
+        }
+    }
+}
+
+/// Area of a texture in uv coordinates, (0, 0) is the top left
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvRect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl UvRect {
+    pub const FULL: Self = Self {
+        min: Vec2::ZERO,
+        max: Vec2::ONE,
+    };
+
+    pub fn new(min: Vec2, max: Vec2) -> Self {
+        Self { min, max }
+    }
+}
+
+impl Default for UvRect {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+/// Regions of a texture packed with several images, looked up by index
+/// Example Use:
+/// ```
+/// use glam::Vec2;
+/// use vulkan_engine::renderer::renderer2d::TextureAtlas;
+///
+/// // 128x64 sheet of 16x16 tiles, 8 per row
+/// let atlas = TextureAtlas::from_grid(128, 64, 16, 16);
+/// let tile = atlas.region(9).unwrap();
+/// assert_eq!(tile.min, Vec2::new(16.0 / 128.0, 16.0 / 64.0));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextureAtlas {
+    /// size of the texture in pixels
+    pub width: u32,
+    pub height: u32,
+    pub regions: Vec<UvRect>,
+}
+
+impl TextureAtlas {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width: width.max(1),
+            height: height.max(1),
+            regions: Vec::new(),
+        }
+    }
+
+    /// Tiles of tile_width x tile_height in rows from the top left, partial tiles are left out
+    pub fn from_grid(width: u32, height: u32, tile_width: u32, tile_height: u32) -> Self {
+        let mut atlas = Self::new(width, height);
+        let columns = width / tile_width.max(1);
+        let rows = height / tile_height.max(1);
+        for row in 0..rows {
+            for column in 0..columns {
+                atlas.add_region(
+                    column * tile_width,
+                    row * tile_height,
+                    tile_width,
+                    tile_height,
+                );
+            }
+        }
+        atlas
+    }
+
+    /// Adds a region in pixels, returns its index
+    pub fn add_region(&mut self, x: u32, y: u32, width: u32, height: u32) -> usize {
+        let size = Vec2::new(self.width as f32, self.height as f32);
+        self.regions.push(UvRect::new(
+            Vec2::new(x as f32, y as f32) / size,
+            Vec2::new((x + width) as f32, (y + height) as f32) / size,
+        ));
+        self.regions.len() - 1
+    }
+
+    pub fn region(&self, index: usize) -> Option<UvRect> {
+        self.regions.get(index).copied()
+    }
+}
+
+/// A textured quad drawn by renderer2d
+/// Example Use:
+/// ```ignore
+/// let ship = renderer.load_sprite_texture("sprites/ship.png")?;
+/// renderer.sprites.push(
+///     Sprite::new(ship, Vec2::new(100.0, 80.0), Vec2::new(32.0, 32.0))
+///         .with_origin(Vec2::splat(0.5))
+///         .with_rotation(std::f32::consts::FRAC_PI_4)
+///         .with_layer(1),
+/// );
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+    pub texture: SpriteTextureId,
+    /// where origin ends up, in pixels with the default camera
+    pub position: Vec2,
+    pub size: Vec2,
+    /// point rotated around and placed at position, (0, 0) is the top left and (1, 1) the bottom right
+    pub origin: Vec2,
+    /// radians, clockwise on screen
+    pub rotation: f32,
+    pub uv: UvRect,
+    /// multiplied with the texture, linear
+    pub color: LinearRgba,
+    /// lower layers are drawn first, sprites on the same layer keep their order
+    pub layer: i32,
+    pub blend: SpriteBlend,
+}
+
+impl Sprite {
+    pub fn new(texture: SpriteTextureId, position: Vec2, size: Vec2) -> Self {
+        Self {
+            texture,
+            position,
+            size,
+            origin: Vec2::ZERO,
+            rotation: 0.0,
+            uv: UvRect::FULL,
+            color: LinearRgba::WHITE,
+            layer: 0,
+            blend: SpriteBlend::Alpha,
+        }
+    }
+
+    pub fn with_origin(mut self, origin: Vec2) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_uv(mut self, uv: UvRect) -> Self {
+        self.uv = uv;
+        self
+    }
+
+    pub fn with_color(mut self, color: LinearRgba) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn with_blend(mut self, blend: SpriteBlend) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Corners clockwise from the top left, with their uvs
+    pub fn corners(&self) -> [(Vec2, Vec2); 4] {
+        let rotation = Vec2::from_angle(self.rotation);
+        let uv = self.uv;
+        [
+            (Vec2::new(0.0, 0.0), uv.min),
+            (Vec2::new(1.0, 0.0), Vec2::new(uv.max.x, uv.min.y)),
+            (Vec2::new(1.0, 1.0), uv.max),
+            (Vec2::new(0.0, 1.0), Vec2::new(uv.min.x, uv.max.y)),
+        ]
+        .map(|(corner, uv)| {
+            let local = (corner - self.origin) * self.size;
+            (self.position + rotation.rotate(local), uv)
+        })
+    }
+}
+
+/// Maps the 2d world onto the screen, the default shows pixels with (0, 0) at the top left
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera2D {
+    /// world point shown at the top left corner
+    pub offset: Vec2,
+    /// screen pixels per world unit
+    pub zoom: f32,
+}
+
+impl Default for Camera2D {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl Camera2D {
+    /// World to clip space for a screen of size extent
+    pub fn projection(&self, extent: vk::Extent2D) -> Mat4 {
+        // vulkan clip space has y pointing down already
+        let pixels = Mat4::orthographic_rh(
+            0.0,
+            extent.width as f32,
+            0.0,
+            extent.height as f32,
+            -1.0,
+            1.0,
+        );
+        pixels
+            * Mat4::from_scale(Vec2::splat(self.zoom).extend(1.0))
+            * Mat4::from_translation(-self.offset.extend(0.0))
+    }
+
+    /// Zoomed in by scale more, offset stays at the top left corner
+    pub fn scaled(mut self, scale: f32) -> Self {
+        self.zoom *= scale;
+        self
+    }
+}
+
+/// Vertex layout of sprite.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteVertex {
+    pub position: Vec2,
+    pub uv: Vec2,
+    pub color: Vec4,
+}
+
+impl VertexLayout for SpriteVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute::new(
+            vk::Format::R32G32_SFLOAT,
+            offset_of!(SpriteVertex, position),
+        ),
+        VertexAttribute::new(vk::Format::R32G32_SFLOAT, offset_of!(SpriteVertex, uv)),
+        VertexAttribute::new(
+            vk::Format::R32G32B32A32_SFLOAT,
+            offset_of!(SpriteVertex, color),
+        ),
+    ];
+}
+
+/// Run of vertices drawn with one texture and blend
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteDraw {
+    pub texture: SpriteTextureId,
+    pub blend: SpriteBlend,
+    pub first_vertex: u32,
+    pub vertex_count: u32,
+}
+
+/// Sprites turned into vertices and as few draws as their layers, textures and blends allow
+#[derive(Clone, Debug, Default)]
+pub struct SpriteBatch {
+    pub vertices: Vec<SpriteVertex>,
+    pub draws: Vec<SpriteDraw>,
+}
+
+impl SpriteBatch {
+    /// Replaces the batch with sprites, sorted by layer
+    pub fn build(&mut self, sprites: &[Sprite]) {
+        self.vertices.clear();
+        self.draws.clear();
+
+        let mut order: Vec<&Sprite> = sprites.iter().collect();
+        // stable so sprites on a layer blend in the order they were given
+        order.sort_by_key(|sprite| sprite.layer);
+
+        for sprite in order {
+            let color = sprite.color.to_vec4();
+            let corners = sprite.corners();
+            self.vertices
+                .extend([0, 1, 2, 0, 2, 3].map(|corner| SpriteVertex {
+                    position: corners[corner].0,
+                    uv: corners[corner].1,
+                    color,
+                }));
+
+            match self.draws.last_mut() {
+                Some(draw) if draw.texture == sprite.texture && draw.blend == sprite.blend => {
+                    draw.vertex_count += VERTICES_PER_SPRITE as u32;
+                }
+                _ => self.draws.push(SpriteDraw {
+                    texture: sprite.texture,
+                    blend: sprite.blend,
+                    first_vertex: (self.vertices.len() - VERTICES_PER_SPRITE) as u32,
+                    vertex_count: VERTICES_PER_SPRITE as u32,
+                }),
+            }
+        }
+    }
+}
+
+// pushed before drawing sprites, matches SpriteConstants in sprite.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct SpriteConstants {
+    projection: Mat4,
+}
+
+/// Batches VKRenderer::sprites into a vertex buffer per frame in flight and draws them over the 3d scene
+/// with no mesh instances and no skybox it is a plain 2d renderer
+pub struct VKRenderer2D<'a> {
+    pub vertex_shader: VKShader<'a>,
+    pub fragment_shader: VKShader<'a>,
+    pub descriptor_layout: vk::DescriptorSetLayout,
+    pub pipeline_layout: vk::PipelineLayout,
+    /// one per SpriteBlend, indexed by it
+    pub pipelines: [vk::Pipeline; SpriteBlend::ALL.len()],
+    /// null when textures are pushed with VK_KHR_push_descriptor
+    pub descriptor_pool: vk::DescriptorPool,
+    /// WHITE_TEXTURE is always present
+    pub textures: Vec<VKTexture>,
+    /// one per texture, empty when textures are pushed
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    /// host visible and mapped, rewritten each frame once its fence has signalled
+    pub vertex_buffers: Vec<VKBuffer>,
+    /// vertices each frame's buffer holds
+    pub vertex_capacities: Vec<usize>,
+    /// the last batch prepared, recorded straight after
+    pub batch: SpriteBatch,
+    pub camera: Camera2D,
+    /// multiplies the camera's zoom, an accessibility setting for larger ui that games leave
+    /// to the player, 1 draws sprites at their size
+    pub ui_scale: f32,
+    /// false draws no sprites without clearing VKRenderer::sprites, for hiding the ui
+    pub visible: bool,
+}
+
+impl VKRenderer2D<'_> {
+    pub fn new(
+        vk_device: &mut VKDevice,
+        vk_swapchain: &VKSwapchain,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        vk_command_pool: vk::CommandPool,
+        frames_in_flight: u32,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let vertex_shader = VKShader::new(
+            vk_device,
+            "shaders/sprite.spv",
+            vk::ShaderStageFlags::VERTEX,
+            c"vertexMain",
+            vk_shader_loader,
+        )?;
+
+        let fragment_shader = VKShader::new(
+            vk_device,
+            "shaders/sprite.spv",
+            vk::ShaderStageFlags::FRAGMENT,
+            c"fragMain",
+            vk_shader_loader,
+        )?;
+
+        let set_bindings = [vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)];
+
+        let descriptor_layout = unsafe {
+            vk_device.device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default()
+                    .flags(vk_device.push_descriptor_layout_flags())
+                    .bindings(&set_bindings),
+                None,
+            )?
+        };
+
+        let descriptor_layouts = [descriptor_layout];
+        let push_constant_ranges = [push_constant_range::<SpriteConstants>(
+            vk::ShaderStageFlags::VERTEX,
+            0,
+        )];
+        let pipeline_layout = unsafe {
+            vk_device.device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&descriptor_layouts)
+                    .push_constant_ranges(&push_constant_ranges),
+                None,
+            )?
+        };
+
+        let stages = [vertex_shader.shader_info, fragment_shader.shader_info];
+        let pipelines = create_sprite_pipelines(vk_device, vk_swapchain, &stages, pipeline_layout)?;
+
+        // pushed textures are written per draw, there is nothing to allocate
+        let descriptor_pool = if vk_device.push_descriptor.is_some() {
+            vk::DescriptorPool::null()
+        } else {
+            let pool_sizes = [vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: MAX_SPRITE_TEXTURES,
+            }];
+            unsafe {
+                vk_device.device.create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::default()
+                        .max_sets(MAX_SPRITE_TEXTURES)
+                        .pool_sizes(&pool_sizes),
+                    None,
+                )?
+            }
+        };
+
+        let mut vertex_buffers = Vec::with_capacity(frames_in_flight as usize);
+        let capacity = INITIAL_SPRITE_CAPACITY * VERTICES_PER_SPRITE;
+        for _ in 0..frames_in_flight {
+            vertex_buffers.push(create_sprite_buffer(vk_device, capacity)?);
+        }
+
+        let mut renderer2d = Self {
+            vertex_shader,
+            fragment_shader,
+            descriptor_layout,
+            pipeline_layout,
+            pipelines,
+            descriptor_pool,
+            textures: Vec::new(),
+            descriptor_sets: Vec::new(),
+            vertex_buffers,
+            vertex_capacities: vec![capacity; frames_in_flight as usize],
+            batch: SpriteBatch::default(),
+            camera: Camera2D::default(),
+            ui_scale: 1.0,
+            visible: true,
+        };
+
+        let white = VKTexture::from_rgba8(vk_device, vk_command_pool, 1, 1, &[255, 255, 255, 255])?;
+        renderer2d.add_texture(vk_device, white)?;
+
+        Ok(renderer2d)
+    }
+
+    /// Takes ownership of texture, sprites draw with it through the returned id
+    pub fn add_texture(
+        &mut self,
+        vk_device: &mut VKDevice,
+        texture: VKTexture,
+    ) -> Result<SpriteTextureId, vk::Result> {
+        if self.descriptor_pool == vk::DescriptorPool::null() {
+            self.textures.push(texture);
+            return Ok(self.textures.len() - 1);
+        }
+
+        let descriptor_layouts = [self.descriptor_layout];
+        let descriptor_set = match unsafe {
+            vk_device.device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(self.descriptor_pool)
+                    .set_layouts(&descriptor_layouts),
+            )
+        } {
+            Ok(descriptor_sets) => descriptor_sets[0],
+            Err(error) => {
+                let mut texture = texture;
+                unsafe { texture.destroy(vk_device) };
+                return Err(error);
+            }
+        };
+
+        let image_infos = [texture.descriptor_image_info()];
+        let writes = [vk::WriteDescriptorSet::default()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_infos)];
+        unsafe { vk_device.device.update_descriptor_sets(&writes, &[]) };
+
+        self.textures.push(texture);
+        self.descriptor_sets.push(descriptor_set);
+        Ok(self.textures.len() - 1)
+    }
+
+    /// Swaps the texture behind id for texture and destroys the old one, sprites keep their id
+    /// # Safety
+    /// The gpu must not be using the old texture
+    pub unsafe fn replace_texture(
+        &mut self,
+        vk_device: &mut VKDevice,
+        id: SpriteTextureId,
+        texture: VKTexture,
+    ) {
+        if self.descriptor_pool != vk::DescriptorPool::null() {
+            let image_infos = [texture.descriptor_image_info()];
+            let writes = [vk::WriteDescriptorSet::default()
+                .dst_set(self.descriptor_sets[id])
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&image_infos)];
+            unsafe { vk_device.device.update_descriptor_sets(&writes, &[]) };
+        }
+        let mut old = std::mem::replace(&mut self.textures[id], texture);
+        unsafe { old.destroy(vk_device) };
+    }
+
+    /// Batches sprites into frame_in_flight's vertex buffer, growing it when they don't fit
+    /// # Safety
+    /// The gpu must be done with frame_in_flight
+    pub unsafe fn prepare(
+        &mut self,
+        vk_device: &mut VKDevice,
+        frame_in_flight: usize,
+        sprites: &[Sprite],
+    ) -> Result<(), vk::Result> {
+        self.batch.build(sprites);
+
+        let needed = self.batch.vertices.len();
+        if needed > self.vertex_capacities[frame_in_flight] {
+            let capacity = needed.next_power_of_two();
+            let buffer = create_sprite_buffer(vk_device, capacity)?;
+            let mut old_buffer =
+                std::mem::replace(&mut self.vertex_buffers[frame_in_flight], buffer);
+            unsafe { old_buffer.destroy(vk_device) };
+            self.vertex_capacities[frame_in_flight] = capacity;
+        }
+
+        if let Err(error) = self.vertex_buffers[frame_in_flight].write(0, &self.batch.vertices) {
+            self.batch.draws.clear();
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// Camera sprites are drawn with, ui_scale applied
+    pub fn scaled_camera(&self) -> Camera2D {
+        self.camera.scaled(self.ui_scale)
+    }
+
+    /// Draws the prepared batch into the current rendering
+    /// # Safety
+    /// cmd_buffer must be inside rendering to target, after prepare for frame_in_flight
+    pub unsafe fn record(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        frame_in_flight: usize,
+        target: &RenderTarget,
+    ) {
+        if self.batch.draws.is_empty() {
+            return;
+        }
+
+        // sprites are laid out for the display orientation like the 3d camera
+        let constants = SpriteConstants {
+            projection: pre_rotation(target.pre_transform)
+                * self.scaled_camera().projection(target.display_extent()),
+        };
+
+        unsafe {
+            vk_device.cmd_push_constants(
+                cmd_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                &constants,
+            );
+            vk_device.device.cmd_bind_vertex_buffers(
+                cmd_buffer,
+                0,
+                &[self.vertex_buffers[frame_in_flight].buffer],
+                &[0u64],
+            );
+
+            let mut bound = None;
+            for draw in &self.batch.draws {
+                if bound != Some(draw.blend) {
+                    vk_device.device.cmd_bind_pipeline(
+                        cmd_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.pipelines[draw.blend as usize],
+                    );
+                    bound = Some(draw.blend);
+                }
+                // unknown textures draw white rather than not at all
+                let texture = if draw.texture < self.textures.len() {
+                    draw.texture
+                } else {
+                    WHITE_TEXTURE
+                };
+                let image_infos = [self.textures[texture].descriptor_image_info()];
+                let writes = [vk::WriteDescriptorSet::default()
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&image_infos)];
+                if !vk_device.cmd_push_descriptor_set(
+                    cmd_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipeline_layout,
+                    0,
+                    &writes,
+                ) {
+                    vk_device.device.cmd_bind_descriptor_sets(
+                        cmd_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.pipeline_layout,
+                        0,
+                        &[self.descriptor_sets[texture]],
+                        &[],
+                    );
+                }
+                vk_device
+                    .device
+                    .cmd_draw(cmd_buffer, draw.vertex_count, 1, draw.first_vertex, 0);
+            }
+        }
+    }
+
+    /// Rebuilds the pipelines when one of their shaders is in changed, see VKShaderLoader::changed_shaders
+    /// # Safety
+    /// The gpu must not be using renderer2d
+    pub unsafe fn reload_shaders(
+        &mut self,
+        vk_device: &VKDevice,
+        vk_swapchain: &VKSwapchain,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        changed: &[&str],
+    ) -> Result<(), Box<dyn error::Error>> {
+        let shaders = &mut [&mut self.vertex_shader, &mut self.fragment_shader];
+        if !unsafe { reload_shaders(vk_device, vk_shader_loader, shaders, changed)? } {
+            return Ok(());
+        }
+        unsafe { self.rebuild_pipeline(vk_device, vk_swapchain)? };
+        Ok(())
+    }
+
+    /// Rebuilds the pipelines for vk_swapchain's current scene format
+    /// # Safety
+    /// The gpu must not be using renderer2d
+    pub unsafe fn rebuild_pipeline(
+        &mut self,
+        vk_device: &VKDevice,
+        vk_swapchain: &VKSwapchain,
+    ) -> Result<(), vk::Result> {
+        let stages = [
+            self.vertex_shader.shader_info,
+            self.fragment_shader.shader_info,
+        ];
+        let pipelines =
+            create_sprite_pipelines(vk_device, vk_swapchain, &stages, self.pipeline_layout)?;
+        for pipeline in std::mem::replace(&mut self.pipelines, pipelines) {
+            unsafe { vk_device.device.destroy_pipeline(pipeline, None) };
+        }
+        Ok(())
+    }
+
+    /// # Safety
+    /// The gpu must not be using renderer2d
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            for mut buffer in self.vertex_buffers.drain(..) {
+                buffer.destroy(vk_device);
+            }
+            for texture in &mut self.textures {
+                texture.destroy(vk_device);
+            }
+            vk_device
+                .device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            for pipeline in self.pipelines {
+                vk_device.device.destroy_pipeline(pipeline, None);
+            }
+            vk_device
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            vk_device
+                .device
+                .destroy_descriptor_set_layout(self.descriptor_layout, None);
+            self.fragment_shader.destroy(vk_device);
+            self.vertex_shader.destroy(vk_device);
+        }
+    }
+}
+
+fn create_sprite_buffer(vk_device: &mut VKDevice, vertices: usize) -> Result<VKBuffer, vk::Result> {
+    let buffer = VKBuffer::new(
+        vk_device,
+        (vertices * size_of::<SpriteVertex>()) as u64,
+        vk::BufferUsageFlags::VERTEX_BUFFER,
+        MemoryLocation::CpuToGpu,
+        "Sprite Vertices",
+    )?;
+    if !buffer.is_mapped() {
+        warn!("Sprite Vertex Buffer Not Mapped");
+    }
+    Ok(buffer)
+}
+
+// blended over the scene, shares the scene pass attachments but ignores depth
+fn create_sprite_pipelines(
+    vk_device: &VKDevice,
+    vk_swapchain: &VKSwapchain,
+    stages: &[vk::PipelineShaderStageCreateInfo],
+    pipeline_layout: vk::PipelineLayout,
+) -> Result<[vk::Pipeline; SpriteBlend::ALL.len()], vk::Result> {
+    let mut pipelines = [vk::Pipeline::null(); SpriteBlend::ALL.len()];
+    for blend in SpriteBlend::ALL {
+        // mirrored sprites have negative sizes, so both windings are drawn
+        let pipeline = GraphicsPipelineBuilder::new(stages, pipeline_layout)
+            .swapchain(vk_swapchain)
+            .vertex_layout::<SpriteVertex>()
+            .blend(blend.blend_mode())
+            .build(vk_device);
+        match pipeline {
+            Ok(pipeline) => pipelines[blend as usize] = pipeline,
+            Err(error) => {
+                for pipeline in pipelines {
+                    unsafe { vk_device.device.destroy_pipeline(pipeline, None) };
+                }
+                return Err(error);
+            }
+        }
+    }
+    Ok(pipelines)
+}
+
+#[test]
+fn sprite_batch_test() {
+    let sprites = [
+        Sprite::new(1, Vec2::ZERO, Vec2::ONE).with_layer(2),
+        Sprite::new(1, Vec2::ZERO, Vec2::ONE),
+        Sprite::new(2, Vec2::ZERO, Vec2::ONE),
+        Sprite::new(2, Vec2::ZERO, Vec2::ONE),
+    ];
+    let mut batch = SpriteBatch::default();
+    batch.build(&sprites);
+
+    // layer 0 in order, then the layer 2 sprite can't join the texture 1 draw
+    assert_eq!(batch.vertices.len(), 24);
+    assert_eq!(
+        batch.draws,
+        [
+            SpriteDraw {
+                texture: 1,
+                blend: SpriteBlend::Alpha,
+                first_vertex: 0,
+                vertex_count: 6
+            },
+            SpriteDraw {
+                texture: 2,
+                blend: SpriteBlend::Alpha,
+                first_vertex: 6,
+                vertex_count: 12
+            },
+            SpriteDraw {
+                texture: 1,
+                blend: SpriteBlend::Alpha,
+                first_vertex: 18,
+                vertex_count: 6
+            },
+        ]
+    );
+
+    // a change of blend needs another pipeline, so it starts a draw even with the same texture
+    batch.build(&[
+        Sprite::new(1, Vec2::ZERO, Vec2::ONE).with_blend(SpriteBlend::Coverage),
+        Sprite::new(1, Vec2::ZERO, Vec2::ONE).with_blend(SpriteBlend::Coverage),
+        Sprite::new(1, Vec2::ZERO, Vec2::ONE).with_blend(SpriteBlend::Premultiplied),
+    ]);
+    let blends: Vec<_> = batch
+        .draws
+        .iter()
+        .map(|draw| (draw.blend, draw.vertex_count))
+        .collect();
+    assert_eq!(
+        blends,
+        [(SpriteBlend::Coverage, 12), (SpriteBlend::Premultiplied, 6)]
+    );
+
+    // centred and turned a quarter clockwise, the top left corner ends up at the top right
+    let sprite = Sprite::new(0, Vec2::new(10.0, 10.0), Vec2::new(4.0, 2.0))
+        .with_origin(Vec2::splat(0.5))
+        .with_rotation(std::f32::consts::FRAC_PI_2);
+    let (top_left, uv) = sprite.corners()[0];
+    assert!(top_left.abs_diff_eq(Vec2::new(11.0, 8.0), 1e-5));
+    assert_eq!(uv, Vec2::ZERO);
+
+    let projection = Camera2D::default().projection(vk::Extent2D {
+        width: 800,
+        height: 600,
+    });
+    let clip = |point: Vec2| projection.project_point3(point.extend(0.0));
+    assert!(
+        clip(Vec2::ZERO)
+            .truncate()
+            .abs_diff_eq(Vec2::new(-1.0, -1.0), 1e-6)
+    );
+    assert!(
+        clip(Vec2::new(800.0, 600.0))
+            .truncate()
+            .abs_diff_eq(Vec2::ONE, 1e-6)
+    );
+
+    // twice the ui scale fills the screen with a quarter of the pixels
+    let projection = Camera2D::default().scaled(2.0).projection(vk::Extent2D {
+        width: 800,
+        height: 600,
+    });
+    assert!(
+        projection
+            .project_point3(Vec2::new(400.0, 300.0).extend(0.0))
+            .truncate()
+            .abs_diff_eq(Vec2::ONE, 1e-6)
+    );
+}
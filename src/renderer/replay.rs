@@ -0,0 +1,468 @@
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::{error, fs, io};
+use thiserror::Error;
+
+use crate::camera::{Camera, DepthConvention};
+use crate::color::LinearRgba;
+use crate::crash_report;
+use crate::lighting::Lighting;
+use crate::renderer::buffer::VKBuffer;
+use crate::renderer::debug_draw::DebugVertex;
+use crate::renderer::material::{CompiledMaterial, MaterialFeatures, MaterialId};
+use crate::renderer::mesh::{MeshId, VKMesh, Vertex};
+use crate::renderer::render_graph::{Access, RenderGraph, RenderPass};
+use crate::renderer::{MeshInstance, RenderMode, VKRenderer, submit_one_time};
+
+/// Bumped whenever FrameRecording changes in a way old files can't be read as
+pub const REPLAY_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("failed to access frame recording: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to parse frame recording: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("failed to write frame recording: {0}")]
+    Serialize(#[from] ron::Error),
+    #[error("frame recording version {found} can't be replayed, expected {REPLAY_VERSION}")]
+    Version { found: u32 },
+    #[error("instance {instance} uses mesh {mesh} which isn't in the recording")]
+    MissingMesh { instance: usize, mesh: MeshId },
+    #[error("instance {instance} uses material {material} which isn't in the recording")]
+    MissingMaterial {
+        instance: usize,
+        material: MaterialId,
+    },
+}
+
+/// Gpu and driver a frame was recorded on, from the crash report context
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecordedDevice {
+    pub device_name: String,
+    pub driver: String,
+    pub api_version: String,
+}
+
+/// A mesh as it was on the gpu, normals and tangents already generated
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedMesh {
+    pub vertices: Vec<Vertex>,
+    /// empty for meshes that aren't indexed
+    pub indices: Vec<u32>,
+}
+
+/// What the renderer was asked to draw for one frame, replayable on another machine
+/// holds the meshes and materials the instances use rather than raw vulkan commands, so the
+/// replaying renderer picks its own pipelines and passes for its gpu
+/// texture paths are stored as is, a texture missing on the replaying machine is swapped for
+/// the fallback texture. Sprites, render textures and post effects aren't recorded
+/// Example Use:
+/// ```ignore
+/// // on the machine that renders wrong, before render
+/// renderer.record_frame()?.save("frame.ron")?;
+///
+/// // on a maintainer's machine
+/// renderer.replay(&FrameRecording::load("frame.ron")?)?;
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FrameRecording {
+    pub version: u32,
+    pub device: RecordedDevice,
+    /// swapchain size in pixels
+    pub extent: [u32; 2],
+    /// raw vk::SampleCountFlags
+    pub msaa_samples: u32,
+    pub depth_convention: DepthConvention,
+    pub render_mode: RenderMode,
+    pub camera: Camera,
+    pub clear_color: LinearRgba,
+    pub lighting: Lighting,
+    /// only the meshes and materials instances use, instance ids index these
+    pub meshes: Vec<RecordedMesh>,
+    pub materials: Vec<CompiledMaterial>,
+    pub instances: Vec<MeshInstance>,
+    /// pairs of line ends, see DebugDraw
+    pub debug_lines: Vec<DebugVertex>,
+}
+
+impl FrameRecording {
+    pub fn to_ron(&self) -> Result<String, ReplayError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    /// Fails on recordings from another REPLAY_VERSION
+    pub fn from_ron(source: &str) -> Result<Self, ReplayError> {
+        let recording: Self = ron::from_str(source)?;
+        if recording.version != REPLAY_VERSION {
+            return Err(ReplayError::Version {
+                found: recording.version,
+            });
+        }
+        Ok(recording)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+        Ok(fs::write(path, self.to_ron()?)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        Self::from_ron(&fs::read_to_string(path)?)
+    }
+}
+
+// the meshes and materials instances use in the order they are first used, with instances
+// pointing into those lists, instances using ids that don't exist are left out as render skips them
+fn compact_instances(
+    instances: &[MeshInstance],
+    mesh_count: usize,
+    material_count: usize,
+) -> (Vec<MeshId>, Vec<MaterialId>, Vec<MeshInstance>) {
+    let mut meshes = Vec::new();
+    let mut materials = Vec::new();
+    let compacted = instances
+        .iter()
+        .filter(|instance| instance.mesh < mesh_count && instance.material < material_count)
+        .map(|instance| {
+            let position = |ids: &mut Vec<usize>, id| match ids.iter().position(|&used| used == id)
+            {
+                Some(index) => index,
+                None => {
+                    ids.push(id);
+                    ids.len() - 1
+                }
+            };
+            MeshInstance {
+                mesh: position(&mut meshes, instance.mesh),
+                material: position(&mut materials, instance.material),
+                ..*instance
+            }
+        })
+        .collect();
+    (meshes, materials, compacted)
+}
+
+// recorded instances pointing at the ids their meshes and materials were added as
+fn remap_instances(
+    instances: &[MeshInstance],
+    meshes: &[MeshId],
+    materials: &[MaterialId],
+) -> Result<Vec<MeshInstance>, ReplayError> {
+    instances
+        .iter()
+        .enumerate()
+        .map(|(index, instance)| {
+            Ok(MeshInstance {
+                mesh: *meshes.get(instance.mesh).ok_or(ReplayError::MissingMesh {
+                    instance: index,
+                    mesh: instance.mesh,
+                })?,
+                material: *materials.get(instance.material).ok_or(
+                    ReplayError::MissingMaterial {
+                        instance: index,
+                        material: instance.material,
+                    },
+                )?,
+                ..*instance
+            })
+        })
+        .collect()
+}
+
+// copies out plain data the gpu wrote, the mapped bytes have no alignment guarantees
+fn read_unaligned<T: Copy>(bytes: &[u8]) -> Vec<T> {
+    bytes
+        .chunks_exact(size_of::<T>())
+        .map(|chunk| unsafe { chunk.as_ptr().cast::<T>().read_unaligned() })
+        .collect()
+}
+
+impl VKRenderer<'_> {
+    /// Everything this frame draws, call before render so this frame's debug lines are included
+    /// waits for uploads and copies every mesh the instances use back from the gpu
+    pub fn record_frame(&mut self) -> Result<FrameRecording, Box<dyn error::Error>> {
+        let (mesh_ids, material_ids, instances) =
+            compact_instances(&self.instances, self.meshes.len(), self.materials.len());
+
+        self.uploader.wait(&mut self.vulkan_ctx.vulkan_device)?;
+        let meshes = mesh_ids
+            .iter()
+            .map(|&mesh| self.read_back_mesh(mesh))
+            .collect::<Result<Vec<_>, _>>()?;
+        let materials = material_ids
+            .iter()
+            .map(|&material| self.materials[material].compiled())
+            .collect();
+
+        let context = crash_report::context();
+        let swapchain = &self.vulkan_ctx.vulkan_swapchain;
+        info!(
+            "Recorded Frame With {} Instances, {} Meshes and {} Materials",
+            instances.len(),
+            meshes.len(),
+            material_ids.len()
+        );
+        Ok(FrameRecording {
+            version: REPLAY_VERSION,
+            device: RecordedDevice {
+                device_name: context.device_name,
+                driver: context.driver,
+                api_version: context.api_version,
+            },
+            extent: [swapchain.image_extent.width, swapchain.image_extent.height],
+            msaa_samples: swapchain.samples.as_raw(),
+            depth_convention: self.depth_convention,
+            render_mode: self.render_mode,
+            camera: self.camera,
+            clear_color: self.clear_color,
+            lighting: self.lighting.clone(),
+            meshes,
+            materials,
+            instances,
+            debug_lines: self.debug_draw.vertices.clone(),
+        })
+    }
+
+    /// Adds recording's meshes and materials and draws its instances from its camera
+    /// the debug lines are immediate mode, push them into debug_draw every frame to keep them
+    /// settings fixed when the renderer was created are only compared, differences are logged
+    pub fn replay(&mut self, recording: &FrameRecording) -> Result<(), Box<dyn error::Error>> {
+        info!(
+            "Replaying Frame Recorded On {} ({})",
+            recording.device.device_name, recording.device.driver
+        );
+        if recording.depth_convention != self.depth_convention {
+            warn!(
+                "Replay Recorded With {:?} Depth, Replaying With {:?}",
+                recording.depth_convention, self.depth_convention
+            );
+        }
+        let samples = self.vulkan_ctx.vulkan_swapchain.samples.as_raw();
+        if recording.msaa_samples != samples {
+            warn!(
+                "Replay Recorded With {}x MSAA, Replaying With {}x",
+                recording.msaa_samples, samples
+            );
+        }
+
+        let meshes = recording
+            .meshes
+            .iter()
+            .map(|mesh| match mesh.indices.is_empty() {
+                true => self.add_mesh(&mesh.vertices),
+                false => self.add_indexed_mesh(&mesh.vertices, &mesh.indices),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let materials = recording
+            .materials
+            .iter()
+            .map(|material| self.add_replayed_material(material))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.instances = remap_instances(&recording.instances, &meshes, &materials)?;
+
+        self.camera = recording.camera;
+        self.clear_color = recording.clear_color;
+        self.lighting = recording.lighting.clone();
+        if recording.render_mode != self.render_mode
+            && let Err(error) = self.set_render_mode(recording.render_mode)
+        {
+            warn!(
+                "Replay Can't Use {:?} Render Mode: {error}",
+                recording.render_mode
+            );
+        }
+        Ok(())
+    }
+
+    // textures that aren't on this machine are replaced by the fallback ones
+    fn add_replayed_material(
+        &mut self,
+        material: &CompiledMaterial,
+    ) -> Result<MaterialId, Box<dyn error::Error>> {
+        let mut material = material.clone();
+        for (path, feature) in [
+            (&mut material.albedo, MaterialFeatures::ALBEDO_TEXTURE),
+            (&mut material.normal_map, MaterialFeatures::NORMAL_MAP),
+        ] {
+            if let Some(missing) = path.take_if(|path| !path.exists()) {
+                warn!(
+                    "Replay Texture {} Missing, Using The Fallback",
+                    missing.display()
+                );
+                material.features.0 &= !feature.0;
+            }
+        }
+        self.add_compiled_material(material)
+    }
+
+    // vertices and indices of mesh as they are on the gpu, blocks until they are copied
+    fn read_back_mesh(&mut self, mesh: MeshId) -> Result<RecordedMesh, Box<dyn error::Error>> {
+        let VKMesh {
+            vertex_buffer:
+                VKBuffer {
+                    buffer: vertex_buffer,
+                    ..
+                },
+            vertex_count,
+            index_buffer:
+                VKBuffer {
+                    buffer: index_buffer,
+                    ..
+                },
+            index_count,
+            ..
+        } = self.meshes[mesh];
+        let vertex_size = vertex_count as u64 * size_of::<Vertex>() as u64;
+        let index_size = index_count as u64 * size_of::<u32>() as u64;
+
+        let mut readback_buffer = VKBuffer::new(
+            &mut self.vulkan_ctx.vulkan_device,
+            vertex_size + index_size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+            "Replay Readback",
+        )?;
+
+        let vk_device = &self.vulkan_ctx.vulkan_device;
+        let submit_result = submit_one_time(vk_device, self.vulkan_cmd_pool, |cmd_buffer| unsafe {
+            let mut graph = RenderGraph::default();
+            let readback = graph.import_buffer(readback_buffer.buffer, &[]);
+            let vertices = graph.import_buffer(vertex_buffer, &[Access::VertexRead]);
+            let mut pass = RenderPass::new(c"Replay Readback")
+                .read_buffer(vertices, Access::TransferSrc)
+                .write_buffer(readback, Access::TransferDst);
+            if index_count > 0 {
+                let indices = graph.import_buffer(index_buffer, &[Access::VertexRead]);
+                pass = pass.read_buffer(indices, Access::TransferSrc);
+            }
+            graph.add_pass(pass.record(|cmd_buffer| {
+                let vertex_region = vk::BufferCopy::default().size(vertex_size);
+                vk_device.device.cmd_copy_buffer(
+                    cmd_buffer,
+                    vertex_buffer,
+                    readback_buffer.buffer,
+                    &[vertex_region],
+                );
+                if index_count > 0 {
+                    let index_region = vk::BufferCopy::default()
+                        .dst_offset(vertex_size)
+                        .size(index_size);
+                    vk_device.device.cmd_copy_buffer(
+                        cmd_buffer,
+                        index_buffer,
+                        readback_buffer.buffer,
+                        &[index_region],
+                    );
+                }
+            }));
+            // make the copies visible to the host
+            graph.export_buffer(readback, Access::HostRead);
+            graph.execute(vk_device, cmd_buffer);
+        });
+
+        let recorded = readback_buffer.map().map(|mapped| {
+            let (vertices, indices) = mapped.split_at(vertex_size as usize);
+            RecordedMesh {
+                vertices: read_unaligned(vertices),
+                indices: read_unaligned(indices),
+            }
+        });
+
+        let vk_device = &mut self.vulkan_ctx.vulkan_device;
+        unsafe { readback_buffer.destroy(vk_device) };
+
+        submit_result?;
+
+        Ok(recorded.ok_or("Replay Readback Memory Not Mapped")?)
+    }
+}
+
+#[test]
+fn frame_recording_test() {
+    use glam::{Mat4, Vec2, Vec3};
+
+    // unused and out of range ids are dropped, the rest renumbered in the order they're used
+    let instance = |mesh, material| MeshInstance {
+        mesh,
+        material,
+        ..Default::default()
+    };
+    let instances = [
+        instance(4, 2),
+        instance(1, 2),
+        instance(4, 0),
+        instance(9, 0),
+    ];
+    let (meshes, materials, compacted) = compact_instances(&instances, 5, 3);
+    assert_eq!(meshes, vec![4, 1]);
+    assert_eq!(materials, vec![2, 0]);
+    assert_eq!(compacted.len(), 3);
+    assert_eq!((compacted[2].mesh, compacted[2].material), (0, 1));
+
+    // replayed ids map back onto wherever the meshes and materials were added
+    let remapped = remap_instances(&compacted, &[10, 11], &[20, 21]).unwrap();
+    assert_eq!((remapped[1].mesh, remapped[1].material), (11, 20));
+    assert!(matches!(
+        remap_instances(&compacted, &[10], &[20, 21]),
+        Err(ReplayError::MissingMesh {
+            instance: 1,
+            mesh: 1
+        })
+    ));
+
+    let recording = FrameRecording {
+        version: REPLAY_VERSION,
+        device: RecordedDevice::default(),
+        extent: [800, 600],
+        msaa_samples: 4,
+        depth_convention: DepthConvention::ReverseZ,
+        render_mode: RenderMode::Rasterized,
+        camera: Camera::default(),
+        clear_color: LinearRgba::BLACK,
+        lighting: Lighting::default(),
+        meshes: vec![RecordedMesh {
+            vertices: vec![Vertex::new(Vec3::ONE, Vec3::X, Vec2::Y); 3],
+            indices: Vec::new(),
+        }],
+        materials: vec![CompiledMaterial {
+            name: "default".to_string(),
+            features: MaterialFeatures::VERTEX_COLOR,
+            params: Default::default(),
+            albedo: None,
+            normal_map: None,
+            double_sided: false,
+        }],
+        instances: vec![MeshInstance {
+            transform: Mat4::from_translation(Vec3::Y),
+            ..Default::default()
+        }],
+        debug_lines: Vec::new(),
+    };
+    let restored = FrameRecording::from_ron(&recording.to_ron().unwrap()).unwrap();
+    assert_eq!(restored.meshes[0].vertices[0].pos, Vec3::ONE);
+    assert_eq!(restored.materials, recording.materials);
+    assert_eq!(restored.instances, recording.instances);
+
+    let old = recording.to_ron().unwrap().replacen(
+        &format!("version: {REPLAY_VERSION}"),
+        "version: 0",
+        1,
+    );
+    assert!(matches!(
+        FrameRecording::from_ron(&old),
+        Err(ReplayError::Version { found: 0 })
+    ));
+
+    // mapped bytes come back as the vertices they were written from
+    let bytes: Vec<u8> = [1.5f32, -2.0]
+        .iter()
+        .flat_map(|f| f.to_ne_bytes())
+        .collect();
+    assert_eq!(read_unaligned::<f32>(&bytes[..]), vec![1.5, -2.0]);
+}
@@ -0,0 +1,584 @@
+use ash::vk;
+use glam::Vec4;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::{error, fs, io};
+use thiserror::Error;
+
+use crate::renderer::color_filter::{ColorFilter, IDENTITY_ROWS};
+use crate::renderer::device::VKDevice;
+use crate::renderer::image::VKImage;
+use crate::renderer::pipeline::GraphicsPipelineBuilder;
+use crate::renderer::presentation::VKSwapchain;
+use crate::renderer::push_constant_range;
+use crate::renderer::shader::{VKShader, VKShaderLoader, reload_shaders};
+use crate::renderer::texture::{NORMAL_MAP_FORMAT, VKTexture};
+
+/// Largest palette the shader searches, one colour per texel
+pub const MAX_PALETTE_COLORS: u32 = 256;
+
+/// How colours are spread before quantizing, hides banding at the cost of a pattern
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Dither {
+    #[default]
+    None,
+    /// 4x4 bayer matrix, the classic crosshatch look
+    Ordered,
+    /// tiled threshold texture, usually blue noise, only the red channel is read
+    BlueNoise(PathBuf),
+}
+
+/// Palette and dithering applied to the scene at its internal resolution, usually loaded from a RON file
+/// Example Use:
+/// ```
+/// use vulkan_engine::renderer::retro::{Dither, RetroSettings};
+///
+/// let settings = RetroSettings::from_ron(r#"(
+///     palette: Some("palettes/gameboy.png"),
+///     dither: Ordered,
+///     dither_spread: 0.2,
+/// )"#)
+/// .unwrap();
+/// assert_eq!(settings.dither, Dither::Ordered);
+///
+/// // no palette, 4 shades per channel
+/// let posterized = RetroSettings::default().levels(4).dither(Dither::Ordered);
+/// ```
+/// Needs an internal resolution, see VKRenderer::set_internal_resolution
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct RetroSettings {
+    /// image whose pixels are the palette, read left to right then top to bottom
+    pub palette: Option<PathBuf>,
+    /// steps per channel when there is no palette, below 2 leaves colours alone
+    pub levels: u32,
+    pub dither: Dither,
+    /// how far dithering can move a colour, 0 to 1 in display space
+    pub dither_spread: f32,
+}
+
+impl Default for RetroSettings {
+    fn default() -> Self {
+        Self {
+            palette: None,
+            levels: 0,
+            dither: Dither::None,
+            dither_spread: 0.125,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RetroError {
+    #[error("failed to read retro settings: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to parse retro settings: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("palette has {colors} colours, at most {MAX_PALETTE_COLORS} are supported")]
+    PaletteTooLarge { colors: u32 },
+}
+
+impl RetroSettings {
+    pub fn from_ron(source: &str) -> Result<Self, RetroError> {
+        Ok(ron::from_str(source)?)
+    }
+
+    /// Reads a RON settings file, texture paths are relative to the file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RetroError> {
+        let path = path.as_ref();
+        let mut settings = Self::from_ron(&fs::read_to_string(path)?)?;
+
+        let directory = path.parent().unwrap_or(Path::new(""));
+        if let Some(palette) = &mut settings.palette {
+            *palette = directory.join(&*palette);
+        }
+        if let Dither::BlueNoise(noise) = &mut settings.dither {
+            *noise = directory.join(&*noise);
+        }
+        Ok(settings)
+    }
+
+    pub fn palette(mut self, palette: impl Into<PathBuf>) -> Self {
+        self.palette = Some(palette.into());
+        self
+    }
+
+    pub fn levels(mut self, levels: u32) -> Self {
+        self.levels = levels;
+        self
+    }
+
+    pub fn dither(mut self, dither: Dither) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    pub fn dither_spread(mut self, dither_spread: f32) -> Self {
+        self.dither_spread = dither_spread;
+        self
+    }
+}
+
+/// Pushed before the retro pass, matches RetroConstants in retro.slang
+/// the default leaves colours alone
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetroConstants {
+    pub palette_size: u32,
+    pub levels: u32,
+    pub dither: u32,
+    pub dither_spread: f32,
+    /// rows of the colour filter applied last, see ColorFilter::rows
+    pub color_filter: [Vec4; 3],
+}
+
+impl Default for RetroConstants {
+    fn default() -> Self {
+        Self {
+            palette_size: 0,
+            levels: 0,
+            dither: 0,
+            dither_spread: 0.0,
+            color_filter: IDENTITY_ROWS,
+        }
+    }
+}
+
+impl RetroConstants {
+    /// palette_size is the number of colours in the loaded palette texture, 0 without one
+    pub fn new(settings: &RetroSettings, palette_size: u32) -> Self {
+        Self {
+            palette_size,
+            levels: settings.levels,
+            dither: match settings.dither {
+                Dither::None => 0,
+                Dither::Ordered => 1,
+                Dither::BlueNoise(_) => 2,
+            },
+            dither_spread: settings.dither_spread,
+            color_filter: IDENTITY_ROWS,
+        }
+    }
+
+    pub fn color_filter(mut self, color_filter: Option<&ColorFilter>) -> Self {
+        self.color_filter = color_filter.map(ColorFilter::rows).unwrap_or(IDENTITY_ROWS);
+        self
+    }
+}
+
+/// Fullscreen pass quantizing and colour filtering the tonemapped scene into its own image,
+/// which is then scaled to the window
+pub struct VKRetroPass<'a> {
+    pub vertex_shader: VKShader<'a>,
+    pub fragment_shader: VKShader<'a>,
+    pub descriptor_layout: vk::DescriptorSetLayout,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub descriptor_pool: vk::DescriptorPool,
+    /// points at the tonemapped scene and textures, only written while the gpu is idle
+    pub descriptor_set: vk::DescriptorSet,
+    /// None and no color_filter disables the pass
+    pub settings: Option<RetroSettings>,
+    pub color_filter: Option<ColorFilter>,
+    pub constants: RetroConstants,
+    pub palette: Option<VKTexture>,
+    pub noise: Option<VKTexture>,
+    /// swapchain format, the output is blitted onto it
+    pub format: vk::Format,
+    /// output at the internal resolution, None while the pass has nothing to draw into
+    pub output: Option<VKImage>,
+}
+
+impl VKRetroPass<'_> {
+    pub fn new(
+        vk_device: &VKDevice,
+        vk_swapchain: &VKSwapchain,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let vertex_shader = VKShader::new(
+            vk_device,
+            "shaders/retro.spv",
+            vk::ShaderStageFlags::VERTEX,
+            c"vertexMain",
+            vk_shader_loader,
+        )?;
+
+        let fragment_shader = VKShader::new(
+            vk_device,
+            "shaders/retro.spv",
+            vk::ShaderStageFlags::FRAGMENT,
+            c"fragMain",
+            vk_shader_loader,
+        )?;
+
+        // scene, palette and noise, all read a texel at a time
+        let set_bindings = [0, 1, 2].map(|binding| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        });
+
+        let descriptor_layout = unsafe {
+            vk_device.device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(&set_bindings),
+                None,
+            )?
+        };
+
+        let descriptor_layouts = [descriptor_layout];
+        let push_constant_ranges = [push_constant_range::<RetroConstants>(
+            vk::ShaderStageFlags::FRAGMENT,
+            0,
+        )];
+        let pipeline_layout = unsafe {
+            vk_device.device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&descriptor_layouts)
+                    .push_constant_ranges(&push_constant_ranges),
+                None,
+            )?
+        };
+
+        let format = vk_swapchain.capibilities.ideal_surface_format().format;
+        let stages = [vertex_shader.shader_info, fragment_shader.shader_info];
+        let pipeline = create_retro_pipeline(vk_device, format, &stages, pipeline_layout)?;
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::SAMPLED_IMAGE,
+            descriptor_count: set_bindings.len() as u32,
+        }];
+        let descriptor_pool = unsafe {
+            vk_device.device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .max_sets(1)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )?
+        };
+
+        let descriptor_set = unsafe {
+            vk_device.device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(descriptor_pool)
+                    .set_layouts(&descriptor_layouts),
+            )?[0]
+        };
+
+        Ok(Self {
+            vertex_shader,
+            fragment_shader,
+            descriptor_layout,
+            pipeline_layout,
+            pipeline,
+            descriptor_pool,
+            descriptor_set,
+            settings: None,
+            color_filter: None,
+            constants: RetroConstants::default(),
+            palette: None,
+            noise: None,
+            format,
+            output: None,
+        })
+    }
+
+    /// Loads the textures settings needs, on error the previous settings are kept
+    /// call set_target afterwards so the descriptors point at the new textures
+    /// # Safety
+    /// The gpu must not be using the pass
+    pub unsafe fn set_settings(
+        &mut self,
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+        settings: Option<&RetroSettings>,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let mut palette = None;
+        let mut noise = None;
+        let mut palette_size = 0;
+
+        if let Some(settings) = settings {
+            if let Some(path) = &settings.palette {
+                let (width, height) = image::image_dimensions(path)?;
+                palette_size = width * height;
+                if palette_size > MAX_PALETTE_COLORS {
+                    return Err(RetroError::PaletteTooLarge {
+                        colors: palette_size,
+                    }
+                    .into());
+                }
+                palette = Some(VKTexture::from_file(vk_device, vk_command_pool, path)?);
+            }
+
+            // thresholds, not colours
+            if let Dither::BlueNoise(path) = &settings.dither {
+                match VKTexture::from_file_with_format(
+                    vk_device,
+                    vk_command_pool,
+                    path,
+                    NORMAL_MAP_FORMAT,
+                ) {
+                    Ok(texture) => noise = Some(texture),
+                    Err(error) => {
+                        if let Some(mut palette) = palette {
+                            unsafe { palette.destroy(vk_device) };
+                        }
+                        return Err(error);
+                    }
+                }
+            }
+        }
+
+        for mut texture in [self.palette.take(), self.noise.take()]
+            .into_iter()
+            .flatten()
+        {
+            unsafe { texture.destroy(vk_device) };
+        }
+
+        self.constants = settings
+            .map(|settings| RetroConstants::new(settings, palette_size))
+            .unwrap_or_default()
+            .color_filter(self.color_filter.as_ref());
+        self.settings = settings.cloned();
+        self.palette = palette;
+        self.noise = noise;
+        Ok(())
+    }
+
+    /// Colour blindness filter applied after quantizing, call set_target afterwards as turning
+    /// the filter on or off can turn the pass on or off
+    pub fn set_color_filter(&mut self, color_filter: Option<ColorFilter>) {
+        self.color_filter = color_filter;
+        self.constants = self.constants.color_filter(color_filter.as_ref());
+    }
+
+    /// Whether there is anything for the pass to do given a target
+    pub fn is_enabled(&self) -> bool {
+        self.settings.is_some() || self.color_filter.is_some()
+    }
+
+    /// Recreates the output for scene, the tonemap pass output, and points the descriptors at it
+    /// without settings or a colour filter or without a scene the output is destroyed and the pass does nothing
+    /// fallback is bound in place of missing textures, it is never read
+    /// # Safety
+    /// The gpu must not be using the pass
+    pub unsafe fn set_target(
+        &mut self,
+        vk_device: &mut VKDevice,
+        scene: Option<&VKImage>,
+        fallback: &VKTexture,
+    ) -> Result<(), vk::Result> {
+        unsafe { self.destroy_output(vk_device) };
+
+        let Some(scene) = scene.filter(|_| self.is_enabled()) else {
+            return Ok(());
+        };
+
+        let extent = scene.extent;
+        self.output = Some(VKImage::new(
+            vk_device,
+            extent,
+            self.format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::SampleCountFlags::TYPE_1,
+            vk::ImageAspectFlags::COLOR,
+        )?);
+
+        let image_infos = [
+            scene.view,
+            self.palette.as_ref().unwrap_or(fallback).image.view,
+            self.noise.as_ref().unwrap_or(fallback).image.view,
+        ]
+        .map(|image_view| {
+            [vk::DescriptorImageInfo::default()
+                .image_view(image_view)
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]
+        });
+        let writes: Vec<_> = image_infos
+            .iter()
+            .zip(0..)
+            .map(|(image_info, binding)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(self.descriptor_set)
+                    .dst_binding(binding)
+                    .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                    .image_info(image_info)
+            })
+            .collect();
+        unsafe { vk_device.device.update_descriptor_sets(&writes, &[]) };
+
+        Ok(())
+    }
+
+    /// Output image the pass draws into, None while the pass is disabled
+    pub fn output_image(&self) -> Option<vk::Image> {
+        self.output.as_ref().map(|output| output.image)
+    }
+
+    /// Quantizes and filters the tonemapped scene into the pass output, does nothing while the pass is disabled
+    /// the tonemapped scene must be in SHADER_READ_ONLY_OPTIMAL and the output in
+    /// COLOR_ATTACHMENT_OPTIMAL, the render graph transitions both
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering, after the tonemap pass
+    pub unsafe fn record(&self, vk_device: &VKDevice, cmd_buffer: vk::CommandBuffer) {
+        let Some(output) = &self.output else {
+            return;
+        };
+
+        // every pixel is written so the old contents don't matter
+        let color_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(output.view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::STORE)];
+
+        let render_area = vk::Rect2D::default().extent(output.extent);
+        let rendering_info = vk::RenderingInfo::default()
+            .color_attachments(&color_attachments)
+            .layer_count(1)
+            .render_area(render_area);
+
+        let viewport = [vk::Viewport::default()
+            .width(output.extent.width as f32)
+            .height(output.extent.height as f32)
+            .max_depth(1.0)];
+
+        unsafe {
+            vk_device
+                .device
+                .cmd_begin_rendering(cmd_buffer, &rendering_info);
+            vk_device.device.cmd_set_viewport(cmd_buffer, 0, &viewport);
+            vk_device
+                .device
+                .cmd_set_scissor(cmd_buffer, 0, &[render_area]);
+            vk_device.device.cmd_bind_pipeline(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            vk_device.device.cmd_bind_descriptor_sets(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            vk_device.cmd_push_constants(
+                cmd_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                &self.constants,
+            );
+            vk_device.device.cmd_draw(cmd_buffer, 3, 1, 0, 0);
+            vk_device.device.cmd_end_rendering(cmd_buffer);
+        }
+    }
+
+    // output image only, the pipeline and textures stay
+    unsafe fn destroy_output(&mut self, vk_device: &mut VKDevice) {
+        if let Some(mut output) = self.output.take() {
+            unsafe { output.destroy(vk_device) };
+        }
+    }
+
+    /// Rebuilds the pipeline when one of its shaders is in changed, see VKShaderLoader::changed_shaders
+    /// # Safety
+    /// The gpu must not be using the pass
+    pub unsafe fn reload_shaders(
+        &mut self,
+        vk_device: &VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        changed: &[&str],
+    ) -> Result<(), Box<dyn error::Error>> {
+        let shaders = &mut [&mut self.vertex_shader, &mut self.fragment_shader];
+        if !unsafe { reload_shaders(vk_device, vk_shader_loader, shaders, changed)? } {
+            return Ok(());
+        }
+        let stages = [
+            self.vertex_shader.shader_info,
+            self.fragment_shader.shader_info,
+        ];
+        let pipeline =
+            create_retro_pipeline(vk_device, self.format, &stages, self.pipeline_layout)?;
+        unsafe { vk_device.device.destroy_pipeline(self.pipeline, None) };
+        self.pipeline = pipeline;
+        Ok(())
+    }
+
+    /// # Safety
+    /// The gpu must not be using the pass
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            self.destroy_output(vk_device);
+            for texture in [&mut self.palette, &mut self.noise].into_iter().flatten() {
+                texture.destroy(vk_device);
+            }
+            vk_device
+                .device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            vk_device.device.destroy_pipeline(self.pipeline, None);
+            vk_device
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            vk_device
+                .device
+                .destroy_descriptor_set_layout(self.descriptor_layout, None);
+            self.fragment_shader.destroy(vk_device);
+            self.vertex_shader.destroy(vk_device);
+        }
+    }
+}
+
+// fullscreen triangle without vertex input or depth, single sampled
+fn create_retro_pipeline(
+    vk_device: &VKDevice,
+    format: vk::Format,
+    stages: &[vk::PipelineShaderStageCreateInfo],
+    pipeline_layout: vk::PipelineLayout,
+) -> Result<vk::Pipeline, vk::Result> {
+    GraphicsPipelineBuilder::new(stages, pipeline_layout)
+        .color_formats(&[format])
+        .build(vk_device)
+}
+
+#[test]
+fn retro_settings_test() {
+    use crate::renderer::color_filter::ColorBlindness;
+
+    let settings = RetroSettings::from_ron(
+        r#"(palette: Some("pico8.png"), dither: BlueNoise("noise.png"), dither_spread: 0.3)"#,
+    )
+    .unwrap();
+    assert_eq!(settings.palette, Some(PathBuf::from("pico8.png")));
+    assert_eq!(settings.levels, 0);
+
+    let constants = RetroConstants::new(&settings, 16);
+    assert_eq!(
+        constants,
+        RetroConstants {
+            palette_size: 16,
+            levels: 0,
+            dither: 2,
+            dither_spread: 0.3,
+            color_filter: IDENTITY_ROWS,
+        }
+    );
+    let filter = ColorFilter::correct(ColorBlindness::Protanopia);
+    let filtered = constants.color_filter(Some(&filter));
+    assert_eq!(filtered.color_filter, filter.rows());
+    assert_eq!(filtered.color_filter(None), constants);
+
+    // left out fields keep their defaults
+    let posterize = RetroSettings::from_ron("(levels: 4)").unwrap();
+    assert_eq!(posterize, RetroSettings::default().levels(4));
+    assert_eq!(RetroConstants::new(&posterize, 0).dither, 0);
+
+    assert!(matches!(
+        RetroSettings::from_ron("(colours: 4)"),
+        Err(RetroError::Parse(_))
+    ));
+}
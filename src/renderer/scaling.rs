@@ -0,0 +1,358 @@
+use ash::vk;
+
+use crate::color::LinearRgba;
+use crate::renderer::device::VKDevice;
+use crate::renderer::image::VKImage;
+use crate::renderer::{COLOR_SUBRESOURCE_RANGE, DEPTH_FORMAT, RenderTarget};
+
+/// How an internal resolution image is fitted into the window
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScaleMode {
+    /// fills the window, the image is distorted when the aspect ratios differ
+    Stretch,
+    /// as large as fits while keeping the aspect ratio, bars fill the rest
+    #[default]
+    Letterbox,
+    /// largest whole multiple that fits, always nearest filtered so every pixel is the same size
+    /// falls back to Letterbox when the window is smaller than the image
+    Integer,
+}
+
+/// Fixed resolution the scene renders at whatever size the window is
+/// Example Use:
+/// ```
+/// use ash::vk;
+/// use vulkan_engine::renderer::scaling::{InternalResolution, ScaleMode};
+///
+/// // chunky pixels, scaled up without blurring
+/// let resolution = InternalResolution::new(320, 180)
+///     .mode(ScaleMode::Letterbox)
+///     .filter(vk::Filter::NEAREST);
+/// let area = resolution.destination(vk::Extent2D { width: 1280, height: 800 });
+/// assert_eq!(area.extent, vk::Extent2D { width: 1280, height: 720 });
+/// assert_eq!(area.offset.y, 40);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InternalResolution {
+    pub extent: vk::Extent2D,
+    pub mode: ScaleMode,
+    /// NEAREST keeps pixel art sharp, LINEAR smooths it
+    pub filter: vk::Filter,
+}
+
+impl InternalResolution {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            extent: vk::Extent2D {
+                width: width.max(1),
+                height: height.max(1),
+            },
+            mode: ScaleMode::default(),
+            filter: vk::Filter::LINEAR,
+        }
+    }
+
+    pub fn mode(mut self, mode: ScaleMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn filter(mut self, filter: vk::Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Integer scaled and nearest filtered, for pixel art
+    pub fn pixel_perfect(width: u32, height: u32) -> Self {
+        Self::new(width, height)
+            .mode(ScaleMode::Integer)
+            .filter(vk::Filter::NEAREST)
+    }
+
+    /// Filter the blit uses, Integer scaling ignores filter
+    pub fn blit_filter(&self) -> vk::Filter {
+        match self.mode {
+            ScaleMode::Integer => vk::Filter::NEAREST,
+            ScaleMode::Stretch | ScaleMode::Letterbox => self.filter,
+        }
+    }
+
+    /// Area of a window of size window the image is scaled into
+    pub fn destination(&self, window: vk::Extent2D) -> vk::Rect2D {
+        let factor = (window.width / self.extent.width).min(window.height / self.extent.height);
+        let extent = match self.mode {
+            ScaleMode::Stretch => window,
+            ScaleMode::Integer if factor >= 1 => vk::Extent2D {
+                width: self.extent.width * factor,
+                height: self.extent.height * factor,
+            },
+            ScaleMode::Letterbox | ScaleMode::Integer => {
+                let scale = (window.width as f32 / self.extent.width as f32)
+                    .min(window.height as f32 / self.extent.height as f32);
+                vk::Extent2D {
+                    width: ((self.extent.width as f32 * scale).round() as u32).min(window.width),
+                    height: ((self.extent.height as f32 * scale).round() as u32).min(window.height),
+                }
+            }
+        };
+        centered(extent, window)
+    }
+}
+
+// extent placed in the middle of window
+fn centered(extent: vk::Extent2D, window: vk::Extent2D) -> vk::Rect2D {
+    vk::Rect2D {
+        offset: vk::Offset2D {
+            x: ((window.width - extent.width) / 2) as i32,
+            y: ((window.height - extent.height) / 2) as i32,
+        },
+        extent,
+    }
+}
+
+/// Colour, depth and msaa images the scene renders into at an internal resolution
+/// tonemapped and blitted onto the swapchain image every frame, shared between frames in flight like
+/// the depth image
+pub struct VKInternalTarget {
+    pub resolution: InternalResolution,
+    pub image: VKImage,
+    pub depth_image: VKImage,
+    /// None when samples is TYPE_1
+    pub msaa_image: Option<VKImage>,
+    pub samples: vk::SampleCountFlags,
+}
+
+impl VKInternalTarget {
+    /// format has to be the swapchain's scene format and samples its sample count, the
+    /// pipelines are built for those
+    pub fn new(
+        vk_device: &mut VKDevice,
+        resolution: InternalResolution,
+        format: vk::Format,
+        samples: vk::SampleCountFlags,
+    ) -> Result<Self, vk::Result> {
+        let extent = resolution.extent;
+
+        let mut image = VKImage::new(
+            vk_device,
+            extent,
+            format,
+            // sampled by post passes before the blit, ray tracing blits into it
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::TRANSFER_DST
+                | vk::ImageUsageFlags::SAMPLED,
+            vk::SampleCountFlags::TYPE_1,
+            vk::ImageAspectFlags::COLOR,
+        )?;
+
+        let mut depth_image = match VKImage::new(
+            vk_device,
+            extent,
+            DEPTH_FORMAT,
+            // copied out to build the occlusion culling pyramid, read by ambient occlusion
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                | vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::SAMPLED,
+            samples,
+            vk::ImageAspectFlags::DEPTH,
+        ) {
+            Ok(depth_image) => depth_image,
+            Err(error) => {
+                unsafe { image.destroy(vk_device) };
+                return Err(error);
+            }
+        };
+
+        let msaa_image = if samples != vk::SampleCountFlags::TYPE_1 {
+            match VKImage::new(
+                vk_device,
+                extent,
+                format,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                samples,
+                vk::ImageAspectFlags::COLOR,
+            ) {
+                Ok(msaa_image) => Some(msaa_image),
+                Err(error) => {
+                    unsafe {
+                        depth_image.destroy(vk_device);
+                        image.destroy(vk_device);
+                    }
+                    return Err(error);
+                }
+            }
+        } else {
+            None
+        };
+
+        Ok(Self {
+            resolution,
+            image,
+            depth_image,
+            msaa_image,
+            samples,
+        })
+    }
+
+    pub fn render_target(&self) -> RenderTarget {
+        let msaa_image = self.msaa_image.as_ref();
+        RenderTarget {
+            image: self.image.image,
+            image_view: self.image.view,
+            depth_image: self.depth_image.image,
+            depth_image_view: self.depth_image.view,
+            msaa_image: msaa_image.map_or(vk::Image::null(), |msaa_image| msaa_image.image),
+            msaa_image_view: msaa_image.map_or(vk::ImageView::null(), |msaa_image| msaa_image.view),
+            samples: self.samples,
+            extent: self.resolution.extent,
+            // rotating is left to the presentation engine, blits can't rotate
+            pre_transform: vk::SurfaceTransformFlagsKHR::IDENTITY,
+        }
+    }
+
+    /// Scales source onto swap_image and clears the bars around it to bar_color
+    /// source is the rendered image or a post pass output of the same size, in TRANSFER_SRC_OPTIMAL
+    /// swap_image must be in TRANSFER_DST_OPTIMAL, the render graph transitions both
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering, after the scene pass into render_target
+    pub unsafe fn record_blit(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        source: vk::Image,
+        swap_image: vk::Image,
+        swap_extent: vk::Extent2D,
+        bar_color: LinearRgba,
+    ) {
+        // the blit writes over part of the clear
+        let clear_barriers = [vk::ImageMemoryBarrier2::default()
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .src_stage_mask(vk::PipelineStageFlags2::CLEAR)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::BLIT)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .image(swap_image)
+            .subresource_range(COLOR_SUBRESOURCE_RANGE)];
+
+        let source_extent = self.resolution.extent;
+        let destination = self.resolution.destination(swap_extent);
+
+        let subresource = vk::ImageSubresourceLayers::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .layer_count(1);
+
+        let region = vk::ImageBlit::default()
+            .src_subresource(subresource)
+            .src_offsets([
+                vk::Offset3D::default(),
+                vk::Offset3D {
+                    x: source_extent.width as i32,
+                    y: source_extent.height as i32,
+                    z: 1,
+                },
+            ])
+            .dst_subresource(subresource)
+            .dst_offsets([
+                vk::Offset3D {
+                    x: destination.offset.x,
+                    y: destination.offset.y,
+                    z: 0,
+                },
+                vk::Offset3D {
+                    x: destination.offset.x + destination.extent.width as i32,
+                    y: destination.offset.y + destination.extent.height as i32,
+                    z: 1,
+                },
+            ]);
+
+        unsafe {
+            vk_device.device.cmd_clear_color_image(
+                cmd_buffer,
+                swap_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &bar_color.into(),
+                &[COLOR_SUBRESOURCE_RANGE],
+            );
+
+            vk_device.device.cmd_pipeline_barrier2(
+                cmd_buffer,
+                &vk::DependencyInfo::default().image_memory_barriers(&clear_barriers),
+            );
+
+            vk_device.device.cmd_blit_image(
+                cmd_buffer,
+                source,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                swap_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+                self.resolution.blit_filter(),
+            );
+        }
+    }
+
+    /// # Safety
+    /// The gpu must not be using the target
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            self.image.destroy(vk_device);
+            self.depth_image.destroy(vk_device);
+            if let Some(mut msaa_image) = self.msaa_image.take() {
+                msaa_image.destroy(vk_device);
+            }
+        }
+    }
+}
+
+#[test]
+fn internal_resolution_destination_test() {
+    let window = vk::Extent2D {
+        width: 1000,
+        height: 1000,
+    };
+
+    // wide image in a square window gets bars top and bottom
+    let letterbox = InternalResolution::new(200, 100).destination(window);
+    assert_eq!(
+        letterbox.extent,
+        vk::Extent2D {
+            width: 1000,
+            height: 500
+        }
+    );
+    assert_eq!(letterbox.offset, vk::Offset2D { x: 0, y: 250 });
+
+    let stretch = InternalResolution::new(200, 100)
+        .mode(ScaleMode::Stretch)
+        .destination(window);
+    assert_eq!(stretch.extent, window);
+    assert_eq!(stretch.offset, vk::Offset2D::default());
+
+    // 3x fits but 4x doesn't, the rest is bars on every side
+    let pixel_perfect = InternalResolution::pixel_perfect(320, 180);
+    let integer = pixel_perfect.destination(window);
+    assert_eq!(
+        integer.extent,
+        vk::Extent2D {
+            width: 960,
+            height: 540
+        }
+    );
+    assert_eq!(integer.offset, vk::Offset2D { x: 20, y: 230 });
+    assert_eq!(pixel_perfect.blit_filter(), vk::Filter::NEAREST);
+
+    // too small for even 1x, shrink to fit instead of cropping
+    let small = pixel_perfect.destination(vk::Extent2D {
+        width: 160,
+        height: 160,
+    });
+    assert_eq!(
+        small.extent,
+        vk::Extent2D {
+            width: 160,
+            height: 90
+        }
+    );
+}
@@ -0,0 +1,370 @@
+use ash::vk;
+use std::error;
+use std::ffi::CStr;
+
+use crate::renderer::compute::{VKComputePipeline, cmd_compute_barrier, group_count};
+use crate::renderer::device::VKDevice;
+use crate::renderer::shader::VKShaderLoader;
+use crate::renderer::submit_one_time;
+
+/// Workgroup sizes compiled into scan.spv, smallest first
+pub const SCAN_GROUP_SIZES: [u32; 5] = [64, 128, 256, 512, 1024];
+
+// scanBlocks then addOffsets for each of SCAN_GROUP_SIZES
+const SCAN_ENTRIES: [[&CStr; 2]; 5] = [
+    [c"scanBlocks64", c"addOffsets64"],
+    [c"scanBlocks128", c"addOffsets128"],
+    [c"scanBlocks256", c"addOffsets256"],
+    [c"scanBlocks512", c"addOffsets512"],
+    [c"scanBlocks1024", c"addOffsets1024"],
+];
+
+// runs per variant when autotuning, the fastest run counts
+const AUTOTUNE_RUNS: usize = 3;
+
+/// One level of the scan, matches ScanPass in scan.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScanPass {
+    pub count: u32,
+    /// 0 scans the data buffer, higher levels scan block totals in scratch
+    pub level: u32,
+    /// where the elements start in scratch above level 0
+    pub offset: u32,
+    /// where each workgroup total is written and read back
+    pub sums_offset: u32,
+}
+
+/// Levels needed to scan count elements, each scans the block totals of the one before
+/// and the last fits in a single workgroup
+pub fn scan_passes(count: u32, group_size: u32) -> Vec<ScanPass> {
+    let mut passes = Vec::new();
+    if count == 0 {
+        return passes;
+    }
+
+    let mut pass = ScanPass {
+        count,
+        level: 0,
+        offset: 0,
+        sums_offset: 0,
+    };
+    loop {
+        passes.push(pass);
+        let groups = group_count(pass.count, group_size);
+        if groups == 1 {
+            return passes;
+        }
+        pass = ScanPass {
+            count: groups,
+            level: pass.level + 1,
+            offset: pass.sums_offset,
+            sums_offset: pass.sums_offset + groups,
+        };
+    }
+}
+
+/// u32s of scratch needed to scan count elements with any of SCAN_GROUP_SIZES
+pub fn scratch_len(count: u32) -> u32 {
+    scan_passes(count, SCAN_GROUP_SIZES[0])
+        .iter()
+        .map(|pass| group_count(pass.count, SCAN_GROUP_SIZES[0]))
+        .sum()
+}
+
+/// Exclusive prefix sum of u32s in place, each element becomes the total of the ones before it
+/// the workgroup size can be tuned per device, see autotune
+/// Example Use:
+/// ```ignore
+/// let mut scan = VKScan::new(vk_device, &mut renderer.vulkan_shader_loader, 4)?;
+/// // scratch needs scratch_len(max_count) u32s, both buffers need STORAGE_BUFFER usage
+/// let set = scan.bind_buffers(vk_device, visible_counts, scratch)?;
+/// scan.autotune(vk_device, renderer.vulkan_cmd_pool, set, max_count)?;
+/// // inside a frame, visible_counts now holds where each draw starts
+/// unsafe { scan.record(vk_device, cmd_buffer, set, draw_count) };
+/// ```
+pub struct VKScan<'a> {
+    /// scanBlocks and addOffsets for each entry in group_sizes
+    pub pipeline: VKComputePipeline<'a>,
+    /// the sizes this device can run
+    pub group_sizes: Vec<u32>,
+    /// index into group_sizes used by record
+    pub variant: usize,
+}
+
+impl VKScan<'_> {
+    /// max_sets is how many data and scratch pairs of buffers can be bound at once
+    pub fn new(
+        vk_device: &VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        max_sets: u32,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let max_size = vk_device.limits.max_compute_work_group_size[0]
+            .min(vk_device.limits.max_compute_work_group_invocations);
+        let supported: Vec<usize> = (0..SCAN_GROUP_SIZES.len())
+            .filter(|variant| SCAN_GROUP_SIZES[*variant] <= max_size)
+            .collect();
+        let entries: Vec<&'static CStr> = supported
+            .iter()
+            .flat_map(|variant| SCAN_ENTRIES[*variant])
+            .collect();
+
+        let pipeline = VKComputePipeline::new::<ScanPass>(
+            vk_device,
+            vk_shader_loader,
+            "shaders/scan.spv",
+            &entries,
+            2,
+            max_sets,
+        )?;
+
+        let group_sizes: Vec<u32> = supported
+            .iter()
+            .map(|variant| SCAN_GROUP_SIZES[*variant])
+            .collect();
+        // a good guess on most hardware until autotune says otherwise
+        let variant = group_sizes
+            .iter()
+            .position(|size| *size == 256)
+            .unwrap_or(group_sizes.len() - 1);
+
+        Ok(Self {
+            pipeline,
+            group_sizes,
+            variant,
+        })
+    }
+
+    pub fn group_size(&self) -> u32 {
+        self.group_sizes[self.variant]
+    }
+
+    /// data and scratch need STORAGE_BUFFER usage, scratch needs scratch_len u32s
+    pub fn bind_buffers(
+        &self,
+        vk_device: &VKDevice,
+        data: vk::Buffer,
+        scratch: vk::Buffer,
+    ) -> Result<vk::DescriptorSet, vk::Result> {
+        self.pipeline.allocate_set(vk_device, &[data, scratch])
+    }
+
+    /// Records the whole scan of the first count elements, with a barrier after every pass
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering and set must come from bind_buffers
+    pub unsafe fn record(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        set: vk::DescriptorSet,
+        count: u32,
+    ) {
+        unsafe { self.record_variant(vk_device, cmd_buffer, self.variant, set, count) };
+    }
+
+    unsafe fn record_variant(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        variant: usize,
+        set: vk::DescriptorSet,
+        count: u32,
+    ) {
+        let group_size = self.group_sizes[variant];
+        let passes = scan_passes(count, group_size);
+
+        unsafe {
+            // up the levels scanning each block and its totals
+            for pass in &passes {
+                self.pipeline.cmd_dispatch(
+                    vk_device,
+                    cmd_buffer,
+                    variant * 2,
+                    set,
+                    pass,
+                    group_count(pass.count, group_size),
+                );
+                cmd_compute_barrier(vk_device, cmd_buffer);
+            }
+            // back down adding the scanned totals onto their blocks, the top level is already done
+            for pass in passes.iter().rev().skip(1) {
+                self.pipeline.cmd_dispatch(
+                    vk_device,
+                    cmd_buffer,
+                    variant * 2 + 1,
+                    set,
+                    pass,
+                    group_count(pass.count, group_size),
+                );
+                cmd_compute_barrier(vk_device, cmd_buffer);
+            }
+        }
+    }
+
+    /// Scans straight away and waits for it, for one off work outside the frame loop
+    pub fn scan_now(
+        &self,
+        vk_device: &VKDevice,
+        vk_command_pool: vk::CommandPool,
+        set: vk::DescriptorSet,
+        count: u32,
+    ) -> Result<(), vk::Result> {
+        submit_one_time(vk_device, vk_command_pool, |cmd_buffer| unsafe {
+            self.record(vk_device, cmd_buffer, set, count)
+        })
+    }
+
+    /// Times every workgroup size scanning count elements of set and keeps the fastest
+    /// overwrites the data in set, does nothing without timestamp support
+    /// returns the chosen workgroup size
+    pub fn autotune(
+        &mut self,
+        vk_device: &VKDevice,
+        vk_command_pool: vk::CommandPool,
+        set: vk::DescriptorSet,
+        count: u32,
+    ) -> Result<u32, vk::Result> {
+        if vk_device.limits.timestamp_compute_and_graphics == vk::FALSE || count == 0 {
+            return Ok(self.group_size());
+        }
+
+        let query_pool = unsafe {
+            vk_device.device.create_query_pool(
+                &vk::QueryPoolCreateInfo::default()
+                    .query_type(vk::QueryType::TIMESTAMP)
+                    .query_count(2),
+                None,
+            )?
+        };
+
+        let result = self.time_variants(vk_device, vk_command_pool, query_pool, set, count);
+        unsafe { vk_device.device.destroy_query_pool(query_pool, None) };
+
+        let timings = result?;
+        if let Some((variant, _)) = timings.iter().enumerate().min_by_key(|(_, ticks)| **ticks) {
+            self.variant = variant;
+        }
+        log::debug!(
+            "scan of {count} autotuned to workgroups of {}, took {:.3}ms",
+            self.group_size(),
+            timings[self.variant] as f64 * vk_device.limits.timestamp_period as f64 / 1_000_000.0
+        );
+        Ok(self.group_size())
+    }
+
+    // fastest run of each variant in timestamp ticks
+    fn time_variants(
+        &self,
+        vk_device: &VKDevice,
+        vk_command_pool: vk::CommandPool,
+        query_pool: vk::QueryPool,
+        set: vk::DescriptorSet,
+        count: u32,
+    ) -> Result<Vec<u64>, vk::Result> {
+        let mut timings = Vec::with_capacity(self.group_sizes.len());
+        for variant in 0..self.group_sizes.len() {
+            let mut fastest = u64::MAX;
+            for _ in 0..AUTOTUNE_RUNS {
+                submit_one_time(vk_device, vk_command_pool, |cmd_buffer| unsafe {
+                    vk_device
+                        .device
+                        .cmd_reset_query_pool(cmd_buffer, query_pool, 0, 2);
+                    vk_device.device.cmd_write_timestamp2(
+                        cmd_buffer,
+                        vk::PipelineStageFlags2::ALL_COMMANDS,
+                        query_pool,
+                        0,
+                    );
+                    self.record_variant(vk_device, cmd_buffer, variant, set, count);
+                    vk_device.device.cmd_write_timestamp2(
+                        cmd_buffer,
+                        vk::PipelineStageFlags2::ALL_COMMANDS,
+                        query_pool,
+                        1,
+                    );
+                })?;
+
+                let mut timestamps = [0_u64; 2];
+                unsafe {
+                    vk_device.device.get_query_pool_results(
+                        query_pool,
+                        0,
+                        &mut timestamps,
+                        vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT,
+                    )?
+                };
+                fastest = fastest.min(timestamps[1].wrapping_sub(timestamps[0]));
+            }
+            timings.push(fastest);
+        }
+        Ok(timings)
+    }
+
+    /// # Safety
+    /// The gpu must not be using the scan
+    pub unsafe fn destroy(&mut self, vk_device: &VKDevice) {
+        unsafe { self.pipeline.destroy(vk_device) };
+    }
+}
+
+#[test]
+fn scan_passes_test() {
+    // runs the passes the same way scan.slang does
+    fn scan(data: &mut [u32], group_size: u32) {
+        let count = data.len() as u32;
+        let mut scratch = vec![0; scratch_len(count) as usize];
+        let passes = scan_passes(count, group_size);
+
+        for pass in &passes {
+            for group in 0..group_count(pass.count, group_size) {
+                let mut total = 0;
+                for local in 0..group_size {
+                    let index = group * group_size + local;
+                    if index >= pass.count {
+                        break;
+                    }
+                    let element = if pass.level == 0 {
+                        &mut data[index as usize]
+                    } else {
+                        &mut scratch[(pass.offset + index) as usize]
+                    };
+                    let value = *element;
+                    *element = total;
+                    total += value;
+                }
+                scratch[(pass.sums_offset + group) as usize] = total;
+            }
+        }
+        for pass in passes.iter().rev().skip(1) {
+            for index in 0..pass.count {
+                let offset = scratch[(pass.sums_offset + index / group_size) as usize];
+                if pass.level == 0 {
+                    data[index as usize] += offset;
+                } else {
+                    scratch[(pass.offset + index) as usize] += offset;
+                }
+            }
+        }
+    }
+
+    assert!(scan_passes(0, 64).is_empty());
+    assert_eq!(scan_passes(64, 64).len(), 1);
+    assert_eq!(scan_passes(65, 64).len(), 2);
+    assert_eq!(scan_passes(64 * 64 + 1, 64).len(), 3);
+
+    for group_size in SCAN_GROUP_SIZES {
+        for count in [0, 1, 7, 63, 64, 65, 1000, 4096, 4097, 70_000] {
+            let mut data: Vec<u32> = (0..count).map(|index| index % 5).collect();
+            let expected: Vec<u32> = data
+                .iter()
+                .scan(0, |total, value| {
+                    let before = *total;
+                    *total += value;
+                    Some(before)
+                })
+                .collect();
+            scan(&mut data, group_size);
+            assert_eq!(data, expected, "{count} elements in groups of {group_size}");
+        }
+    }
+}
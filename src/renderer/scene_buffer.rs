@@ -0,0 +1,422 @@
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use log::warn;
+use std::ops::Range;
+
+use crate::renderer::buffer::VKBuffer;
+use crate::renderer::device::VKDevice;
+
+/// Clean objects between two dirty ones that are still copied to keep them in one region
+pub const MERGE_GAP: usize = 4;
+
+/// Which objects changed since the last upload
+#[derive(Clone, Debug, Default)]
+pub struct DirtyObjects {
+    flags: Vec<bool>,
+    count: usize,
+}
+
+impl DirtyObjects {
+    pub fn mark(&mut self, index: usize) {
+        if index >= self.flags.len() {
+            self.flags.resize(index + 1, false);
+        }
+        if !self.flags[index] {
+            self.flags[index] = true;
+            self.count += 1;
+        }
+    }
+
+    pub fn is_dirty(&self, index: usize) -> bool {
+        self.flags.get(index).copied().unwrap_or(false)
+    }
+
+    /// Number of dirty objects
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Runs of dirty objects in order, runs separated by merge_gap or fewer clean objects are joined
+    pub fn ranges(&self, merge_gap: usize) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for (index, _) in self.flags.iter().enumerate().filter(|(_, dirty)| **dirty) {
+            match ranges.last_mut() {
+                Some(range) if index - range.end <= merge_gap => range.end = index + 1,
+                _ => ranges.push(index..index + 1),
+            }
+        }
+        ranges
+    }
+
+    pub fn clear(&mut self) {
+        self.flags.iter_mut().for_each(|dirty| *dirty = false);
+        self.count = 0;
+    }
+}
+
+/// Per object data in a device local storage buffer, only objects changed since the last upload are written
+/// with resizable BAR each frame in flight has its own mapped copy written directly,
+/// otherwise changes go through a staging buffer per frame in flight into one buffer
+/// T must match the std430 layout the shaders read it with
+/// Example Use:
+/// ```ignore
+/// let mut objects = VKSceneBuffer::<ObjectData>::new(vk_device, 4096, frames_in_flight)?;
+/// let rock = objects.push(ObjectData::new(rock_transform)).unwrap();
+/// // later, only the rock is sent next upload
+/// objects.set(rock, ObjectData::new(moved_transform));
+/// // each frame, before anything reads the buffer
+/// let uploaded = unsafe { objects.record_upload(vk_device, cmd_buffer, frame_in_flight)? };
+/// let info = objects.descriptor_buffer_info(frame_in_flight);
+/// ```
+pub struct VKSceneBuffer<T> {
+    /// one per frame in flight when direct, otherwise a single gpu only buffer
+    pub buffers: Vec<VKBuffer>,
+    /// empty when direct
+    pub staging_buffers: Vec<VKBuffer>,
+    /// objects the buffer holds, fixed at creation
+    pub capacity: usize,
+    /// writes go straight into mapped device local memory, no copies are recorded
+    pub direct: bool,
+    objects: Vec<T>,
+    /// one per buffer, each copy has to catch up on its own
+    dirty: Vec<DirtyObjects>,
+    /// copies out of each staging buffer that stage_upload left for cmd_copy_staged
+    pending: Vec<Vec<vk::BufferCopy>>,
+}
+
+impl<T: Copy> VKSceneBuffer<T> {
+    /// Writes directly when the device has resizable BAR, staging otherwise
+    pub fn new(
+        vk_device: &mut VKDevice,
+        capacity: usize,
+        frames_in_flight: u32,
+    ) -> Result<Self, vk::Result> {
+        if vk_device.resizable_bar() {
+            match Self::new_direct(vk_device, capacity, frames_in_flight) {
+                Ok(scene_buffer) => return Ok(scene_buffer),
+                Err(error) => warn!("Scene Objects Falling Back To Staging: {error}"),
+            }
+        }
+        Self::new_staged(vk_device, capacity, frames_in_flight)
+    }
+
+    /// A mapped device local buffer per frame in flight
+    pub fn new_direct(
+        vk_device: &mut VKDevice,
+        capacity: usize,
+        frames_in_flight: u32,
+    ) -> Result<Self, vk::Result> {
+        let size = (capacity.max(1) * size_of::<T>()) as u64;
+        let mut buffers = create_buffers(
+            vk_device,
+            frames_in_flight,
+            size,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::CpuToGpu,
+            "Scene Objects",
+        )?;
+
+        // gpu-allocator only prefers device local for mapped memory, host memory would be slower than staging
+        let device_local = buffers.iter().all(|buffer| {
+            buffer.is_mapped()
+                && buffer
+                    .allocation
+                    .memory_properties()
+                    .contains(vk::MemoryPropertyFlags::DEVICE_LOCAL)
+        });
+        if !device_local {
+            unsafe { destroy_buffers(vk_device, &mut buffers) };
+            return Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY);
+        }
+
+        Ok(Self {
+            dirty: vec![DirtyObjects::default(); buffers.len()],
+            buffers,
+            staging_buffers: Vec::new(),
+            capacity,
+            direct: true,
+            objects: Vec::with_capacity(capacity),
+            pending: Vec::new(),
+        })
+    }
+
+    /// One gpu only buffer filled through a staging buffer per frame in flight
+    pub fn new_staged(
+        vk_device: &mut VKDevice,
+        capacity: usize,
+        frames_in_flight: u32,
+    ) -> Result<Self, vk::Result> {
+        let size = (capacity.max(1) * size_of::<T>()) as u64;
+        let mut buffers = create_buffers(
+            vk_device,
+            1,
+            size,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+            "Scene Objects",
+        )?;
+
+        // every object changing at once still fits
+        let staging_buffers = match create_buffers(
+            vk_device,
+            frames_in_flight,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+            "Scene Objects Staging",
+        ) {
+            Ok(staging) => staging,
+            Err(error) => {
+                unsafe { destroy_buffers(vk_device, &mut buffers) };
+                return Err(error);
+            }
+        };
+        if staging_buffers.iter().any(|buffer| !buffer.is_mapped()) {
+            warn!("Scene Objects Staging Buffer Not Mapped");
+        }
+
+        Ok(Self {
+            buffers,
+            staging_buffers,
+            capacity,
+            direct: false,
+            objects: Vec::with_capacity(capacity),
+            dirty: vec![DirtyObjects::default()],
+            pending: vec![Vec::new(); frames_in_flight.max(1) as usize],
+        })
+    }
+
+    /// Adds an object, returns its index or None when the buffer is full
+    pub fn push(&mut self, object: T) -> Option<usize> {
+        if self.objects.len() >= self.capacity {
+            return None;
+        }
+        let index = self.objects.len();
+        self.objects.push(object);
+        self.mark_dirty(index);
+        Some(index)
+    }
+
+    /// Replaces the object at index, it is sent with the next upload
+    pub fn set(&mut self, index: usize, object: T) {
+        self.objects[index] = object;
+        self.mark_dirty(index);
+    }
+
+    fn mark_dirty(&mut self, index: usize) {
+        self.dirty.iter_mut().for_each(|dirty| dirty.mark(index));
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.objects.get(index)
+    }
+
+    pub fn objects(&self) -> &[T] {
+        &self.objects
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Objects frame_in_flight's next upload will write
+    pub fn dirty(&self, frame_in_flight: usize) -> &DirtyObjects {
+        &self.dirty[frame_in_flight % self.dirty.len()]
+    }
+
+    /// The buffer shaders read during frame_in_flight
+    pub fn buffer(&self, frame_in_flight: usize) -> vk::Buffer {
+        self.buffers[frame_in_flight % self.buffers.len()].buffer
+    }
+
+    /// Whole of frame_in_flight's buffer for a STORAGE_BUFFER descriptor
+    pub fn descriptor_buffer_info(&self, frame_in_flight: usize) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffer(frame_in_flight))
+            .offset(0)
+            .range(vk::WHOLE_SIZE)
+    }
+
+    /// Writes dirty objects for frame_in_flight, returns the bytes written
+    /// when direct they go straight into its buffer and nothing is recorded,
+    /// otherwise they go through its staging buffer with one copy and a region per range
+    /// and barriers on both sides keep the copy clear of shader reads
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering
+    /// and the gpu must be done with frame_in_flight's last upload
+    pub unsafe fn record_upload(
+        &mut self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        frame_in_flight: usize,
+    ) -> Result<u64, vk::Result> {
+        let written = self.stage_upload(frame_in_flight)?;
+        unsafe { self.cmd_copy_staged(vk_device, cmd_buffer, frame_in_flight) };
+        Ok(written)
+    }
+
+    /// The host half of record_upload, for when recording only has a shared reference
+    /// dirty objects are written into frame_in_flight's buffer or staging buffer,
+    /// cmd_copy_staged then records the copies staging needs
+    /// the gpu must be done with frame_in_flight's last upload
+    pub fn stage_upload(&mut self, frame_in_flight: usize) -> Result<u64, vk::Result> {
+        let copy = frame_in_flight % self.dirty.len();
+        let staging = frame_in_flight % self.pending.len().max(1);
+        // regions staged for an earlier frame would copy back over newer objects
+        if let Some(pending) = self.pending.get_mut(staging) {
+            pending.clear();
+        }
+        if self.dirty[copy].is_empty() {
+            return Ok(0);
+        }
+
+        let object_size = size_of::<T>();
+        let ranges = self.dirty[copy].ranges(MERGE_GAP);
+        self.dirty[copy].clear();
+
+        if self.direct {
+            let mut written = 0;
+            for range in ranges {
+                self.buffers[copy]
+                    .write(range.start * object_size, &self.objects[range.clone()])?;
+                written += range.len() * object_size;
+            }
+            // submitting makes host writes visible, there is nothing to record
+            return Ok(written as u64);
+        }
+
+        let mut regions = Vec::new();
+        let mut staging_offset = 0;
+        for range in ranges {
+            let bytes = range.len() * object_size;
+            self.staging_buffers[staging].write(staging_offset, &self.objects[range.clone()])?;
+            regions.push(
+                vk::BufferCopy::default()
+                    .src_offset(staging_offset as u64)
+                    .dst_offset((range.start * object_size) as u64)
+                    .size(bytes as u64),
+            );
+            staging_offset += bytes;
+        }
+        self.pending[staging] = regions;
+        Ok(staging_offset as u64)
+    }
+
+    /// Records the copies the last stage_upload of frame_in_flight left, nothing when direct
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering
+    pub unsafe fn cmd_copy_staged(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        frame_in_flight: usize,
+    ) {
+        let Some(regions) = self
+            .pending
+            .get(frame_in_flight % self.pending.len().max(1))
+            .filter(|regions| !regions.is_empty())
+        else {
+            return;
+        };
+
+        let readers = vk::PipelineStageFlags2::VERTEX_SHADER
+            | vk::PipelineStageFlags2::FRAGMENT_SHADER
+            | vk::PipelineStageFlags2::COMPUTE_SHADER;
+        let before = [vk::MemoryBarrier2::default()
+            .src_stage_mask(readers | vk::PipelineStageFlags2::COPY)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::COPY)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)];
+        let after = [vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(readers)
+            .dst_access_mask(vk::AccessFlags2::SHADER_STORAGE_READ)];
+
+        unsafe {
+            vk_device.device.cmd_pipeline_barrier2(
+                cmd_buffer,
+                &vk::DependencyInfo::default().memory_barriers(&before),
+            );
+            vk_device.device.cmd_copy_buffer(
+                cmd_buffer,
+                self.staging_buffers[frame_in_flight % self.staging_buffers.len()].buffer,
+                self.buffers[0].buffer,
+                regions,
+            );
+            vk_device.device.cmd_pipeline_barrier2(
+                cmd_buffer,
+                &vk::DependencyInfo::default().memory_barriers(&after),
+            );
+        }
+    }
+
+    /// # Safety
+    /// The gpu must not be using the scene buffer
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            destroy_buffers(vk_device, &mut self.staging_buffers);
+            destroy_buffers(vk_device, &mut self.buffers);
+        }
+    }
+}
+
+// count buffers alike, none are left behind on failure
+fn create_buffers(
+    vk_device: &mut VKDevice,
+    count: u32,
+    size: u64,
+    usage: vk::BufferUsageFlags,
+    mem_location: MemoryLocation,
+    name: &str,
+) -> Result<Vec<VKBuffer>, vk::Result> {
+    let mut buffers = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        match VKBuffer::new(vk_device, size, usage, mem_location, name) {
+            Ok(buffer) => buffers.push(buffer),
+            Err(error) => {
+                unsafe { destroy_buffers(vk_device, &mut buffers) };
+                return Err(error);
+            }
+        }
+    }
+    Ok(buffers)
+}
+
+// buffers must not be in use by the gpu
+unsafe fn destroy_buffers(vk_device: &mut VKDevice, buffers: &mut Vec<VKBuffer>) {
+    for mut buffer in buffers.drain(..) {
+        unsafe { buffer.destroy(vk_device) };
+    }
+}
+
+#[test]
+fn dirty_objects_test() {
+    let mut dirty = DirtyObjects::default();
+    assert!(dirty.ranges(MERGE_GAP).is_empty());
+
+    for index in [0, 1, 2, 5, 20, 21, 40] {
+        dirty.mark(index);
+    }
+    // marking twice doesn't count twice
+    dirty.mark(5);
+    assert_eq!(dirty.len(), 7);
+    assert!(dirty.is_dirty(21) && !dirty.is_dirty(3) && !dirty.is_dirty(1000));
+
+    // 3 and 4 are cheaper to copy than a second region
+    assert_eq!(dirty.ranges(MERGE_GAP), vec![0..6, 20..22, 40..41]);
+    assert_eq!(dirty.ranges(0), vec![0..3, 5..6, 20..22, 40..41]);
+    assert_eq!(dirty.ranges(usize::MAX), vec![0..41]);
+
+    dirty.clear();
+    assert!(dirty.is_empty());
+    assert!(dirty.ranges(MERGE_GAP).is_empty());
+}
@@ -1,16 +1,25 @@
 use ash::util::read_spv;
 use ash::vk;
+use log::{error, warn};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use std::collections::HashMap;
 use std::ffi::CStr;
 use std::fs::File;
 use std::hash::Hash;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::{env, fmt, fs, io};
+use thiserror::Error;
 
 use crate::renderer::device::VKDevice;
 
 pub struct VKShader<'a> {
     pub shader_module: vk::ShaderModule,
     pub shader_info: vk::PipelineShaderStageCreateInfo<'a>,
+    /// file the module was loaded from, reloaded from it when it changes
+    pub path: &'static str,
 }
 
 impl VKShader<'_> {
@@ -33,9 +42,27 @@ impl VKShader<'_> {
         Ok(Self {
             shader_module,
             shader_info: create_info,
+            path: shader_path,
         })
     }
 
+    /// Swaps in a module made from the current contents of path, the old one is kept on failure
+    /// # Safety
+    /// The gpu must be done with pipelines using the old module
+    pub unsafe fn reload(
+        &mut self,
+        vk_device: &VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let file_data = vk_shader_loader.load_shader(self.path)?;
+        let create_info = vk::ShaderModuleCreateInfo::default().code(file_data);
+        let shader_module = unsafe { vk_device.device.create_shader_module(&create_info, None)? };
+        unsafe { self.destroy(vk_device) };
+        self.shader_module = shader_module;
+        self.shader_info.module = shader_module;
+        Ok(())
+    }
+
     /// # Safety
     /// Destroy Before Vulkan Device
     /// Read VK Docs For Destruction Order
@@ -54,27 +81,958 @@ pub struct VKShaderLoader<P>
 where
     P: AsRef<Path> + Eq + Hash,
 {
-    pub files: HashMap<P, Result<Vec<u32>, std::io::Error>>,
+    pub files: HashMap<P, Result<Vec<u32>, ShaderError>>,
+    /// Diagnostics of every shader whose last compile failed and the path it was loaded from,
+    /// only kept in dev builds, VKRenderer draws them over the frame until the shader compiles
+    pub diagnostics: Vec<(P, ShaderDiagnostic)>,
+    /// set by watch, reports files written since the last changed_shaders
+    pub watcher: Option<ShaderWatcher>,
+    /// searched for GLSL and HLSL #include files not found next to the including file
+    pub include_directories: Vec<PathBuf>,
+}
+
+/// File watcher for shader hot reloading
+pub struct ShaderWatcher {
+    // events stop once this is dropped
+    _watcher: RecommendedWatcher,
+    // behind a mutex so the loader stays Sync
+    events: Mutex<Receiver<notify::Result<Event>>>,
 }
 
 impl<P> VKShaderLoader<P>
 where
     P: AsRef<Path> + Eq + Hash + Clone,
 {
-    pub fn load_shader(&mut self, path: P) -> Result<&Vec<u32>, std::io::Error> {
-        if path.as_ref().extension().and_then(|ext| ext.to_str()) == Some("spv") {
-            let file_data = self.files.entry(path).or_insert_with_key(|path| {
-                let mut file = File::open(path)?;
-                read_spv(&mut file)
-            });
-            file_data
-                .as_ref()
-                .map_err(|err| std::io::Error::new(err.kind(), err.to_string()))
-        } else {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Wrong File Extention",
-            ))
+    /// Loads compiled .spv files, .slang files are compiled with slangc from the dev shell,
+    /// HLSL .vert.hlsl, .frag.hlsl and .comp.hlsl files with dxc
+    /// and GLSL .vert, .frag and .comp and WGSL .wgsl files are compiled in process
+    pub fn load_shader(&mut self, path: P) -> Result<&Vec<u32>, ShaderError> {
+        let diagnostics = &mut self.diagnostics;
+        let include_directories = &self.include_directories;
+        let file_data = self.files.entry(path).or_insert_with_key(|key| {
+            // whatever the last compile reported is replaced by this one
+            diagnostics.retain(|(failed, _)| failed != key);
+            let path = key.as_ref();
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("spv") => {
+                    let mut file = File::open(path)?;
+                    Ok(read_spv(&mut file)?)
+                }
+                Some(extension @ ("slang" | "hlsl" | "wgsl" | "vert" | "frag" | "comp")) => {
+                    let spirv = match extension {
+                        "slang" => compile_slang(path),
+                        "hlsl" => compile_hlsl(path, include_directories),
+                        "wgsl" => compile_wgsl(path),
+                        _ => compile_glsl(path, include_directories),
+                    };
+                    if let Err(ShaderError::Compile {
+                        diagnostics: errors,
+                        ..
+                    }) = &spirv
+                    {
+                        for diagnostic in errors {
+                            error!("Shader Compilation Failed\n{diagnostic}");
+                        }
+                        // dev builds keep them around for the overlay
+                        if cfg!(debug_assertions) {
+                            diagnostics
+                                .extend(errors.iter().map(|error| (key.clone(), error.clone())));
+                        }
+                    }
+                    spirv
+                }
+                _ => Err(ShaderError::Extension(path.to_path_buf())),
+            }
+        });
+        file_data.as_ref().map_err(Clone::clone)
+    }
+
+    /// Adds a directory GLSL and HLSL includes are looked up in, after the including file's own
+    pub fn include_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.include_directories.push(directory.into());
+        self
+    }
+
+    /// Starts watching directory and everything below it for shader changes
+    pub fn watch(&mut self, directory: impl AsRef<Path>) -> notify::Result<()> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(directory.as_ref(), RecursiveMode::Recursive)?;
+        self.watcher = Some(ShaderWatcher {
+            _watcher: watcher,
+            events: Mutex::new(events),
+        });
+        Ok(())
+    }
+
+    /// Loaded shaders whose file was written since the last call, their cached data is dropped
+    /// so the next load_shader reads the new contents
+    pub fn changed_shaders(&mut self) -> Vec<P> {
+        let Some(watcher) = &self.watcher else {
+            return Vec::new();
+        };
+        let Ok(events) = watcher.events.lock() else {
+            return Vec::new();
+        };
+
+        let mut changed: Vec<P> = Vec::new();
+        for event in events.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(error) => {
+                    warn!("Shader Watcher Error: {error}");
+                    continue;
+                }
+            };
+            // compilers tend to write a new file and rename it over the old one
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+            for event_path in &event.paths {
+                for path in self.files.keys() {
+                    // watched paths are absolute, loaded ones usually relative to the working directory
+                    if event_path.ends_with(path.as_ref()) && !changed.contains(path) {
+                        changed.push(path.clone());
+                    }
+                }
+            }
+        }
+
+        for path in &changed {
+            self.files.remove(path);
+        }
+        changed
+    }
+}
+
+/// Reloads the shaders whose path is in changed, returns whether any were so pipelines made
+/// from them can be rebuilt
+/// # Safety
+/// The gpu must be done with pipelines using the shaders
+pub unsafe fn reload_shaders(
+    vk_device: &VKDevice,
+    vk_shader_loader: &mut VKShaderLoader<&str>,
+    shaders: &mut [&mut VKShader],
+    changed: &[&str],
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut reloaded = false;
+    for shader in shaders {
+        if changed.contains(&shader.path) {
+            unsafe { shader.reload(vk_device, vk_shader_loader)? };
+            reloaded = true;
+        }
+    }
+    Ok(reloaded)
+}
+
+#[derive(Clone, Debug, Error)]
+pub enum ShaderError {
+    #[error("failed to read shader: {0}")]
+    Io(Arc<io::Error>),
+    #[error(
+        "wrong file extention for shader {0}, expected .spv, .slang, .hlsl, .wgsl, .vert, .frag or .comp"
+    )]
+    Extension(PathBuf),
+    #[error("failed to compile {}:\n{}", path.display(), format_diagnostics(diagnostics, output))]
+    Compile {
+        path: PathBuf,
+        diagnostics: Vec<ShaderDiagnostic>,
+        /// everything the compiler printed
+        output: String,
+    },
+}
+
+impl From<io::Error> for ShaderError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(Arc::new(error))
+    }
+}
+
+fn format_diagnostics(diagnostics: &[ShaderDiagnostic], output: &str) -> String {
+    if diagnostics.is_empty() {
+        return output.trim_end().to_string();
+    }
+    diagnostics
+        .iter()
+        .map(ShaderDiagnostic::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+/// A compiler message mapped back to the file it came from
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderDiagnostic {
+    pub severity: Severity,
+    pub file: PathBuf,
+    /// 1 based like the compiler reports them
+    pub line: u32,
+    pub column: Option<u32>,
+    pub message: String,
+    /// where file was included from, innermost first
+    pub included_from: Vec<(PathBuf, u32)>,
+    /// numbered source lines leading up to and including line
+    pub excerpt: Vec<(u32, String)>,
+}
+
+impl fmt::Display for ShaderDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        };
+        write!(f, "{}:{}", self.file.display(), self.line)?;
+        if let Some(column) = self.column {
+            write!(f, ":{column}")?;
+        }
+        writeln!(f, ": {severity}: {}", self.message)?;
+        for (file, line) in &self.included_from {
+            writeln!(f, "    included from {}:{line}", file.display())?;
+        }
+        let width = self
+            .excerpt
+            .last()
+            .map_or(1, |(number, _)| number.to_string().len());
+        for (number, source) in &self.excerpt {
+            writeln!(f, "{number:>width$} | {source}")?;
+        }
+        if let Some(column) = self.column.filter(|_| !self.excerpt.is_empty()) {
+            let indent = " ".repeat(column.saturating_sub(1) as usize);
+            writeln!(f, "{:width$} | {indent}^", "")?;
+        }
+        Ok(())
+    }
+}
+
+/// Every file a shader pulls in through #include and import, with where it was pulled in from
+#[derive(Debug, Default)]
+pub struct ShaderSourceMap {
+    files: Vec<SourceFile>,
+    /// file index and line of each line of an expanded source, see compile_glsl
+    lines: Vec<(usize, u32)>,
+}
+
+#[derive(Debug)]
+struct SourceFile {
+    path: PathBuf,
+    source: String,
+    /// index and line of the file that included this one, the root has none
+    parent: Option<(usize, u32)>,
+}
+
+impl ShaderSourceMap {
+    /// Reads root and follows includes relative to the including file, missing files are skipped
+    pub fn load(root: &Path) -> Self {
+        let mut map = Self::default();
+        map.add(root.to_path_buf(), None);
+        map
+    }
+
+    fn add(&mut self, path: PathBuf, parent: Option<(usize, u32)>) {
+        if self.find(&path).is_some() {
+            return;
+        }
+        let Ok(source) = fs::read_to_string(&path) else {
+            return;
+        };
+        let index = self.files.len();
+        let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let includes: Vec<_> = source
+            .lines()
+            .zip(1..)
+            .filter_map(|(line, number)| Some((included_path(line)?, number)))
+            .collect();
+        self.files.push(SourceFile {
+            path,
+            source,
+            parent,
+        });
+        for (include, number) in includes {
+            self.add(directory.join(include), Some((index, number)));
         }
     }
+
+    // appends path to expanded with its includes pasted in place of the #include lines
+    fn expand(
+        &mut self,
+        path: PathBuf,
+        parent: Option<(usize, u32)>,
+        include_directories: &[PathBuf],
+        expanded: &mut String,
+    ) -> Result<(), ShaderError> {
+        let source = fs::read_to_string(&path)?;
+        let index = self.files.len();
+        let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        self.files.push(SourceFile {
+            path: path.clone(),
+            source: source.clone(),
+            parent,
+        });
+
+        for (line, number) in source.lines().zip(1..) {
+            // naga's preprocessor doesn't know the extension, the includes are already resolved
+            let line = if line
+                .trim_start()
+                .starts_with("#extension GL_GOOGLE_include_directive")
+            {
+                ""
+            } else {
+                line
+            };
+            let Some(include) = included_path(line).filter(|_| line.trim_start().starts_with('#'))
+            else {
+                expanded.push_str(line);
+                expanded.push('\n');
+                self.lines.push((index, number));
+                continue;
+            };
+
+            let Some(include_path) = std::iter::once(&directory)
+                .chain(include_directories)
+                .map(|directory| directory.join(&include))
+                .find(|include_path| include_path.is_file())
+            else {
+                let mut diagnostic = ShaderDiagnostic {
+                    severity: Severity::Error,
+                    file: path.clone(),
+                    line: number,
+                    column: None,
+                    message: format!("can't find included file {include}"),
+                    included_from: Vec::new(),
+                    excerpt: Vec::new(),
+                };
+                self.map(&mut diagnostic, 2);
+                return Err(ShaderError::Compile {
+                    path: path.clone(),
+                    output: diagnostic.message.clone(),
+                    diagnostics: vec![diagnostic],
+                });
+            };
+            // keeps the line count so the including file's lines still line up when it's skipped
+            if self.files.iter().any(|file| file.path == include_path) {
+                expanded.push('\n');
+                self.lines.push((index, number));
+                continue;
+            }
+            self.expand(
+                include_path,
+                Some((index, number)),
+                include_directories,
+                expanded,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// File and line a line of the expanded source came from, 1 based
+    pub fn origin(&self, line: u32) -> Option<(PathBuf, u32)> {
+        let &(index, line) = self.lines.get((line as usize).checked_sub(1)?)?;
+        Some((self.files[index].path.clone(), line))
+    }
+
+    fn find(&self, path: &Path) -> Option<usize> {
+        self.files
+            .iter()
+            .position(|file| file.path == path)
+            // compilers print paths their own way, fall back to the file name
+            .or_else(|| {
+                self.files
+                    .iter()
+                    .position(|file| path.file_name() == file.path.file_name())
+            })
+    }
+
+    /// Fills in the include chain and source excerpt of a diagnostic
+    pub fn map(&self, diagnostic: &mut ShaderDiagnostic, context: u32) {
+        let Some(index) = self.find(&diagnostic.file) else {
+            return;
+        };
+        let file = &self.files[index];
+        let mut parent = file.parent;
+        diagnostic.file = file.path.clone();
+        let first = diagnostic.line.saturating_sub(context).max(1);
+        diagnostic.excerpt = file
+            .source
+            .lines()
+            .zip(1..)
+            .skip(first as usize - 1)
+            .take_while(|(_, number)| *number <= diagnostic.line)
+            .map(|(line, number)| (number, line.to_string()))
+            .collect();
+        diagnostic.included_from.clear();
+        while let Some((index, line)) = parent {
+            let file = &self.files[index];
+            diagnostic.included_from.push((file.path.clone(), line));
+            parent = file.parent;
+        }
+    }
+}
+
+// #include "file" or slang's import module;
+fn included_path(line: &str) -> Option<String> {
+    let line = line.trim();
+    if let Some(rest) = line.strip_prefix("#include") {
+        let rest = rest.trim();
+        let rest = rest.strip_prefix('"').or_else(|| rest.strip_prefix('<'))?;
+        let end = rest.find(['"', '>'])?;
+        return Some(rest[..end].to_string());
+    }
+    let module = line
+        .strip_prefix("import ")?
+        .trim()
+        .strip_suffix(';')?
+        .trim();
+    let module = module.trim_matches('"');
+    if module.ends_with(".slang") {
+        return Some(module.to_string());
+    }
+    // slang looks modules up with . as / and _ as -
+    Some(format!(
+        "{}.slang",
+        module.replace('.', "/").replace('_', "-")
+    ))
+}
+
+/// Reads diagnostics from slangc output, lines like
+/// `shaders/a.slang(12): error 30015: undefined identifier 'foo'.`
+/// the compiler's own excerpt lines are skipped
+pub fn parse_slang_diagnostics(output: &str) -> Vec<ShaderDiagnostic> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let open = line.find('(')?;
+            let (location, rest) = line[open + 1..].split_once("):")?;
+            let mut location = location.split(',').map(|n| n.trim().parse::<u32>());
+            let line_number = location.next()?.ok()?;
+            let column = location.next().and_then(Result::ok);
+            let rest = rest.trim_start();
+            let (severity, rest) = [
+                ("error", Severity::Error),
+                ("fatal error", Severity::Error),
+                ("warning", Severity::Warning),
+                ("note", Severity::Note),
+            ]
+            .into_iter()
+            .find_map(|(name, severity)| Some((severity, rest.strip_prefix(name)?)))?;
+            // error codes sit between the severity and the message
+            let message = rest.split_once(':').map_or(rest, |(_, message)| message);
+            Some(ShaderDiagnostic {
+                severity,
+                file: PathBuf::from(&line[..open]),
+                line: line_number,
+                column,
+                message: message.trim().to_string(),
+                included_from: Vec::new(),
+                excerpt: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+/// Compiles every entry point of a slang file to SPIR-V with slangc
+pub fn compile_slang(path: &Path) -> Result<Vec<u32>, ShaderError> {
+    let output_path = env::temp_dir().join(format!(
+        "{}-{}.spv",
+        path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("shader"),
+        process::id()
+    ));
+    let output = Command::new("slangc")
+        .arg(path)
+        .args(["-target", "spirv", "-fvk-use-entrypoint-name", "-o"])
+        .arg(&output_path)
+        .output()?;
+    let printed = String::from_utf8_lossy(&output.stderr).into_owned();
+
+    if !output.status.success() {
+        let sources = ShaderSourceMap::load(path);
+        let mut diagnostics = parse_slang_diagnostics(&printed);
+        for diagnostic in &mut diagnostics {
+            sources.map(diagnostic, 2);
+        }
+        return Err(ShaderError::Compile {
+            path: path.to_path_buf(),
+            diagnostics,
+            output: printed,
+        });
+    }
+    if !printed.trim().is_empty() {
+        warn!("{}", printed.trim_end());
+    }
+
+    let spirv = read_spv(&mut File::open(&output_path)?);
+    let _ = fs::remove_file(&output_path);
+    Ok(spirv?)
+}
+
+/// Reads diagnostics from dxc output, clang style lines like
+/// `shaders/a.frag.hlsl:12:5: error: use of undeclared identifier 'y'`
+/// the compiler's own excerpt lines are skipped
+pub fn parse_dxc_diagnostics(output: &str) -> Vec<ShaderDiagnostic> {
+    output
+        .lines()
+        .filter_map(|line| {
+            // windows paths have a colon of their own, so the numbers are found from the severity
+            let (location, rest) = [": error:", ": fatal error:", ": warning:", ": note:"]
+                .into_iter()
+                .find_map(|severity| {
+                    let start = line.find(severity)?;
+                    Some((&line[..start], &line[start + 2..]))
+                })?;
+            let (severity, message) = rest.split_once(':')?;
+            let severity = match severity {
+                "error" | "fatal error" => Severity::Error,
+                "warning" => Severity::Warning,
+                _ => Severity::Note,
+            };
+            let (file, line_number, column) = match location.rsplitn(3, ':').collect::<Vec<_>>()[..]
+            {
+                [column, line_number, file] => {
+                    (file, line_number.parse().ok()?, column.parse().ok())
+                }
+                _ => return None,
+            };
+            Some(ShaderDiagnostic {
+                severity,
+                file: PathBuf::from(file),
+                line: line_number,
+                column,
+                message: message.trim().to_string(),
+                included_from: Vec::new(),
+                excerpt: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+/// Shader model profile dxc compiles a file for, from the stage before .hlsl
+/// e.g. `lighting.frag.hlsl` is a pixel shader
+pub fn hlsl_profile(path: &Path) -> Option<&'static str> {
+    let stem = Path::new(path.file_stem()?);
+    match stem.extension()?.to_str()? {
+        "vert" => Some("vs_6_0"),
+        "frag" => Some("ps_6_0"),
+        "comp" => Some("cs_6_0"),
+        _ => None,
+    }
+}
+
+/// Compiles the main function of a .vert.hlsl, .frag.hlsl or .comp.hlsl file to SPIR-V with dxc
+/// includes are looked up next to the including file and then in include_directories
+pub fn compile_hlsl(path: &Path, include_directories: &[PathBuf]) -> Result<Vec<u32>, ShaderError> {
+    let profile = hlsl_profile(path).ok_or_else(|| ShaderError::Extension(path.to_path_buf()))?;
+    let output_path = env::temp_dir().join(format!(
+        "{}-{}.spv",
+        path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("shader"),
+        process::id()
+    ));
+    let mut command = Command::new("dxc");
+    command
+        .args([
+            "-spirv",
+            "-fspv-target-env=vulkan1.3",
+            "-E",
+            "main",
+            "-T",
+            profile,
+        ])
+        .arg("-Fo")
+        .arg(&output_path);
+    for directory in include_directories {
+        command.arg("-I").arg(directory);
+    }
+    let output = command.arg(path).output()?;
+    let printed = String::from_utf8_lossy(&output.stderr).into_owned();
+
+    if !output.status.success() {
+        let sources = ShaderSourceMap::load(path);
+        let mut diagnostics = parse_dxc_diagnostics(&printed);
+        for diagnostic in &mut diagnostics {
+            sources.map(diagnostic, 2);
+        }
+        return Err(ShaderError::Compile {
+            path: path.to_path_buf(),
+            diagnostics,
+            output: printed,
+        });
+    }
+    if !printed.trim().is_empty() {
+        warn!("{}", printed.trim_end());
+    }
+
+    let spirv = read_spv(&mut File::open(&output_path)?);
+    let _ = fs::remove_file(&output_path);
+    Ok(spirv?)
+}
+
+/// Compiles a GLSL .vert, .frag or .comp file to SPIR-V with naga, the entry point is main
+/// #include "file" is looked up next to the including file and then in include_directories,
+/// every file is included once so include guards are optional
+pub fn compile_glsl(path: &Path, include_directories: &[PathBuf]) -> Result<Vec<u32>, ShaderError> {
+    let stage = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("vert") => naga::ShaderStage::Vertex,
+        Some("frag") => naga::ShaderStage::Fragment,
+        Some("comp") => naga::ShaderStage::Compute,
+        _ => return Err(ShaderError::Extension(path.to_path_buf())),
+    };
+    let (source, sources) = expand_glsl(path, include_directories)?;
+
+    // where in the included files a span of the expanded source came from
+    let diagnostic = |location: Option<naga::SourceLocation>, message: String| {
+        let (file, line) = location
+            .and_then(|location| sources.origin(location.line_number))
+            .unwrap_or((path.to_path_buf(), 1));
+        let mut diagnostic = ShaderDiagnostic {
+            severity: Severity::Error,
+            file,
+            line,
+            column: location.map(|location| location.line_position),
+            message,
+            included_from: Vec::new(),
+            excerpt: Vec::new(),
+        };
+        sources.map(&mut diagnostic, 2);
+        diagnostic
+    };
+    let compile_error = |diagnostics: Vec<ShaderDiagnostic>, output: String| ShaderError::Compile {
+        path: path.to_path_buf(),
+        diagnostics,
+        output,
+    };
+
+    let module = naga::front::glsl::Frontend::default()
+        .parse(&naga::front::glsl::Options::from(stage), &source)
+        .map_err(|errors| {
+            let diagnostics = errors
+                .errors
+                .iter()
+                .map(|error| diagnostic(error.location(&source), error.kind.to_string()))
+                .collect();
+            compile_error(diagnostics, errors.to_string())
+        })?;
+
+    write_spirv(&module, &source, diagnostic, compile_error)
+}
+
+/// Compiles a WGSL file to SPIR-V with naga, every entry point in it ends up in the module
+/// so one file can hold all of a pipeline's stages like the .slang shaders do
+/// positions are written as they are, the engine's projections already point y down
+pub fn compile_wgsl(path: &Path) -> Result<Vec<u32>, ShaderError> {
+    let source = fs::read_to_string(path)?;
+    let sources = ShaderSourceMap::load(path);
+
+    let diagnostic = |location: Option<naga::SourceLocation>, message: String| {
+        let mut diagnostic = ShaderDiagnostic {
+            severity: Severity::Error,
+            file: path.to_path_buf(),
+            line: location.map_or(1, |location| location.line_number),
+            column: location.map(|location| location.line_position),
+            message,
+            included_from: Vec::new(),
+            excerpt: Vec::new(),
+        };
+        sources.map(&mut diagnostic, 2);
+        diagnostic
+    };
+    let compile_error = |diagnostics: Vec<ShaderDiagnostic>, output: String| ShaderError::Compile {
+        path: path.to_path_buf(),
+        diagnostics,
+        output,
+    };
+
+    let module = naga::front::wgsl::parse_str(&source).map_err(|error| {
+        compile_error(
+            vec![diagnostic(
+                error.location(&source),
+                error.message().to_string(),
+            )],
+            error.emit_to_string_with_path(&source, path),
+        )
+    })?;
+
+    write_spirv(&module, &source, diagnostic, compile_error)
+}
+
+// validates a module naga parsed from source and writes it out for vulkan
+fn write_spirv(
+    module: &naga::Module,
+    source: &str,
+    diagnostic: impl Fn(Option<naga::SourceLocation>, String) -> ShaderDiagnostic,
+    compile_error: impl Fn(Vec<ShaderDiagnostic>, String) -> ShaderError,
+) -> Result<Vec<u32>, ShaderError> {
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(module)
+    .map_err(|error| {
+        let message = error.as_inner().to_string();
+        compile_error(
+            vec![diagnostic(error.location(source), message.clone())],
+            message,
+        )
+    })?;
+
+    // shaders written for vulkan already have y pointing down
+    let mut options = naga::back::spv::Options::default();
+    options
+        .flags
+        .remove(naga::back::spv::WriterFlags::ADJUST_COORDINATE_SPACE);
+    naga::back::spv::write_vec(module, &info, &options, None)
+        .map_err(|error| compile_error(Vec::new(), error.to_string()))
+}
+
+// source of path with its includes pasted in, and the map from its lines back to the files
+fn expand_glsl(
+    path: &Path,
+    include_directories: &[PathBuf],
+) -> Result<(String, ShaderSourceMap), ShaderError> {
+    let mut sources = ShaderSourceMap::default();
+    let mut expanded = String::new();
+    sources.expand(path.to_path_buf(), None, include_directories, &mut expanded)?;
+    Ok((expanded, sources))
+}
+
+#[cfg(test)]
+pub(crate) fn spirv_instructions(spirv: &[u32]) -> Vec<&[u32]> {
+    // the word count sits in the high half of each instruction's first word
+    let mut instructions = Vec::new();
+    let mut words = &spirv[5..];
+    while let Some(&first) = words.first() {
+        let count = ((first >> 16) as usize).clamp(1, words.len());
+        instructions.push(&words[..count]);
+        words = &words[count..];
+    }
+    instructions
+}
+
+// OpEntryPoint, the execution model and id are followed by the nul terminated name
+#[cfg(test)]
+pub(crate) fn spirv_entry_points(spirv: &[u32]) -> Vec<String> {
+    spirv_instructions(spirv)
+        .iter()
+        .filter(|words| words[0] & 0xffff == 15)
+        .map(|words| {
+            let name = words[3..]
+                .iter()
+                .flat_map(|word| word.to_le_bytes())
+                .take_while(|&byte| byte != 0)
+                .collect::<Vec<_>>();
+            String::from_utf8_lossy(&name).into_owned()
+        })
+        .collect()
+}
+
+#[test]
+fn shader_diagnostic_test() {
+    let directory = env::temp_dir().join(format!("shader_diagnostic_test-{}", process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(
+        directory.join("main.slang"),
+        "// main\n#include \"common.slang\"\nvoid main() {}\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("common.slang"),
+        "float one() {\n    float x = 1.0;\n    return y;\n}\n",
+    )
+    .unwrap();
+
+    let output = format!(
+        "{0}(3): error 30015: undefined identifier 'y'.\n    return y;\n           ^\n\
+         {1}(3, 5): warning 15205: unused\n",
+        directory.join("common.slang").display(),
+        directory.join("main.slang").display(),
+    );
+    let mut diagnostics = parse_slang_diagnostics(&output);
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+    assert_eq!(diagnostics[0].message, "undefined identifier 'y'.");
+    assert_eq!((diagnostics[1].line, diagnostics[1].column), (3, Some(5)));
+
+    let sources = ShaderSourceMap::load(&directory.join("main.slang"));
+    sources.map(&mut diagnostics[0], 1);
+    assert_eq!(
+        diagnostics[0].included_from,
+        [(directory.join("main.slang"), 2)]
+    );
+    assert_eq!(
+        diagnostics[0].excerpt,
+        [
+            (2, "    float x = 1.0;".to_string()),
+            (3, "    return y;".to_string())
+        ]
+    );
+    assert!(diagnostics[0].to_string().contains("3 |     return y;"));
+    assert_eq!(
+        included_path("import lighting.common;").unwrap(),
+        "lighting/common.slang"
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn shader_hot_reload_test() {
+    use std::time::{Duration, Instant};
+
+    let directory = env::temp_dir().join(format!("shader_hot_reload_test-{}", process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("lit.spv");
+    let other = directory.join("unlit.spv");
+    // just the spirv magic number and a word of old or new code
+    let spirv = |word: u32| [0x0723_0203u32, word].map(u32::to_le_bytes).concat();
+    fs::write(&path, spirv(1)).unwrap();
+    fs::write(&other, spirv(1)).unwrap();
+
+    let mut loader = VKShaderLoader::<PathBuf>::default();
+    assert!(loader.changed_shaders().is_empty());
+    assert_eq!(loader.load_shader(path.clone()).unwrap()[1], 1);
+    loader.load_shader(other.clone()).unwrap();
+    loader.watch(&directory).unwrap();
+
+    fs::write(&path, spirv(2)).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut changed = Vec::new();
+    while changed.is_empty() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+        changed = loader.changed_shaders();
+    }
+    assert_eq!(changed, std::slice::from_ref(&path));
+    assert_eq!(loader.load_shader(path).unwrap()[1], 2);
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn glsl_compile_test() {
+    let directory = env::temp_dir().join(format!("glsl_compile_test-{}", process::id()));
+    fs::create_dir_all(directory.join("include")).unwrap();
+    fs::write(
+        directory.join("include/lighting.glsl"),
+        "float diffuse(vec3 normal) {\n    return max(dot(normal, vec3(0.0, 1.0, 0.0)), 0.0);\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("shaded.frag"),
+        "#version 450\n\
+         #extension GL_GOOGLE_include_directive : require\n\
+         #include \"lighting.glsl\"\n\
+         #include \"lighting.glsl\"\n\
+         layout(location = 0) in vec3 normal;\n\
+         layout(location = 0) out vec4 color;\n\
+         void main() {\n    color = vec4(vec3(diffuse(normal)), 1.0);\n}\n",
+    )
+    .unwrap();
+
+    let mut loader = VKShaderLoader::default().include_directory(directory.join("include"));
+    let spirv = loader.load_shader(directory.join("shaded.frag")).unwrap();
+    assert_eq!(spirv[0], 0x0723_0203);
+
+    // errors in an include point at the include, not the pasted together source
+    fs::write(
+        directory.join("include/lighting.glsl"),
+        "float diffuse(vec3 normal) {\n    return undefined;\n}\n",
+    )
+    .unwrap();
+    let Err(ShaderError::Compile { diagnostics, .. }) =
+        compile_glsl(&directory.join("shaded.frag"), &[directory.join("include")])
+    else {
+        panic!("undefined identifier compiled");
+    };
+    assert_eq!(diagnostics[0].file, directory.join("include/lighting.glsl"));
+    assert_eq!(diagnostics[0].line, 2);
+    assert_eq!(
+        diagnostics[0].included_from,
+        [(directory.join("shaded.frag"), 3)]
+    );
+
+    // without the include directory the file can't be found
+    let Err(ShaderError::Compile { diagnostics, .. }) =
+        compile_glsl(&directory.join("shaded.frag"), &[])
+    else {
+        panic!("missing include compiled");
+    };
+    assert_eq!(
+        (diagnostics[0].file.clone(), diagnostics[0].line),
+        (directory.join("shaded.frag"), 3)
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn wgsl_compile_test() {
+    let directory = env::temp_dir().join(format!("wgsl_compile_test-{}", process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("fullscreen.wgsl");
+    let valid = "@vertex\n\
+         fn vertexMain(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {\n    \
+         let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));\n    \
+         return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);\n\
+         }\n\
+         @fragment\n\
+         fn fragMain() -> @location(0) vec4<f32> {\n    return vec4<f32>(1.0);\n}\n";
+    fs::write(&path, valid).unwrap();
+
+    let mut loader = VKShaderLoader::default();
+    let spirv = loader.load_shader(path.clone()).unwrap();
+    assert_eq!(spirv[0], 0x0723_0203);
+
+    fs::write(
+        &path,
+        "@fragment\nfn fragMain() -> @location(0) vec4<f32> {\n    return colour;\n}\n",
+    )
+    .unwrap();
+    let Err(ShaderError::Compile { diagnostics, .. }) = compile_wgsl(&path) else {
+        panic!("undefined identifier compiled");
+    };
+    assert_eq!(
+        (diagnostics[0].file.clone(), diagnostics[0].line),
+        (path.clone(), 3)
+    );
+    assert_eq!(diagnostics[0].column, Some(12));
+
+    // the loader keeps the errors for the overlay until the shader compiles again, as on hot reload
+    loader.files.remove(&path);
+    assert!(loader.load_shader(path.clone()).is_err());
+    assert_eq!(loader.diagnostics.len(), 1);
+    assert_eq!(loader.diagnostics[0].0, path);
+    fs::write(&path, valid).unwrap();
+    loader.files.remove(&path);
+    loader.load_shader(path.clone()).unwrap();
+    assert!(loader.diagnostics.is_empty());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn hlsl_diagnostic_test() {
+    assert_eq!(
+        hlsl_profile(Path::new("shaders/lit.frag.hlsl")),
+        Some("ps_6_0")
+    );
+    assert_eq!(hlsl_profile(Path::new("shaders/lit.hlsl")), None);
+
+    let output = "C:\\shaders\\lit.frag.hlsl:12:5: error: use of undeclared identifier 'y'\n    \
+                  return y;\n           ^\n\
+                  shaders/common.hlsl:3:1: warning: unused variable 'x' [-Wunused-variable]\n";
+    let diagnostics = parse_dxc_diagnostics(output);
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(
+        diagnostics[0].file,
+        PathBuf::from("C:\\shaders\\lit.frag.hlsl")
+    );
+    assert_eq!((diagnostics[0].line, diagnostics[0].column), (12, Some(5)));
+    assert_eq!(diagnostics[0].message, "use of undeclared identifier 'y'");
+    assert_eq!(diagnostics[1].severity, Severity::Warning);
 }
@@ -0,0 +1,92 @@
+use glam::Vec4;
+
+/// Most floats shaders can be given per frame
+pub const MAX_SHADER_INPUTS: usize = 64;
+
+/// Arbitrary floats handed to shaders every frame, e.g. FFT bins for a music visualizer
+/// shaders read them from set 0 binding 2, matches ShaderInputs in triangle.slang
+/// Example Use:
+/// ```
+/// use vulkan_engine::renderer::shader_inputs::ShaderInputs;
+///
+/// let spectrum = [0.9, 0.7, 0.4, 0.2, 0.1];
+/// let inputs = ShaderInputs::from_slice(&spectrum);
+/// assert_eq!(inputs.len(), 5);
+/// assert_eq!(inputs.get(2), Some(0.4));
+/// ```
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShaderInputs {
+    /// number of values that were set, the rest are 0
+    pub count: u32,
+    pub _padding: [u32; 3],
+    /// packed four to a vec4 so the array has no std140 padding, value i is values[i / 4][i % 4]
+    pub values: [Vec4; MAX_SHADER_INPUTS / 4],
+}
+
+impl Default for ShaderInputs {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            _padding: [0; 3],
+            values: [Vec4::ZERO; MAX_SHADER_INPUTS / 4],
+        }
+    }
+}
+
+impl ShaderInputs {
+    /// Values past MAX_SHADER_INPUTS are dropped
+    pub fn from_slice(values: &[f32]) -> Self {
+        let mut inputs = Self::default();
+        inputs.set(values);
+        inputs
+    }
+
+    /// Replaces every value, values past MAX_SHADER_INPUTS are dropped
+    /// returns how many were kept
+    pub fn set(&mut self, values: &[f32]) -> usize {
+        let count = values.len().min(MAX_SHADER_INPUTS);
+        self.values = [Vec4::ZERO; MAX_SHADER_INPUTS / 4];
+        for (index, &value) in values[..count].iter().enumerate() {
+            self.values[index / 4][index % 4] = value;
+        }
+        self.count = count as u32;
+        count
+    }
+
+    pub fn get(&self, index: usize) -> Option<f32> {
+        (index < self.len()).then(|| self.values[index / 4][index % 4])
+    }
+
+    pub fn len(&self) -> usize {
+        self.count as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[test]
+fn shader_inputs_test() {
+    // layout the shader expects, count then 16 vec4s at offset 16
+    assert_eq!(size_of::<ShaderInputs>(), 16 + MAX_SHADER_INPUTS * 4);
+    assert_eq!(std::mem::offset_of!(ShaderInputs, values), 16);
+
+    let bins: Vec<f32> = (0..100).map(|bin| bin as f32).collect();
+    let mut inputs = ShaderInputs::default();
+    assert_eq!(inputs.set(&bins), MAX_SHADER_INPUTS);
+    assert_eq!(inputs.len(), MAX_SHADER_INPUTS);
+    assert_eq!(inputs.values[1], Vec4::new(4.0, 5.0, 6.0, 7.0));
+    assert_eq!(inputs.get(MAX_SHADER_INPUTS), None);
+
+    // fewer values leave no stale ones behind
+    inputs.set(&[1.0, 2.0]);
+    assert_eq!(inputs.values[0], Vec4::new(1.0, 2.0, 0.0, 0.0));
+    assert_eq!(inputs.values[1], Vec4::ZERO);
+    assert_eq!(inputs.get(2), None);
+}
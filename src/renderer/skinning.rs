@@ -0,0 +1,452 @@
+use ash::vk;
+use glam::{Mat4, Vec4};
+use gpu_allocator::MemoryLocation;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error;
+
+use crate::animation::{AnimationError, VertexAnimation, VertexSkin, baked_frames, validate_skins};
+use crate::renderer::MeshInstance;
+use crate::renderer::buffer::VKBuffer;
+use crate::renderer::device::VKDevice;
+use crate::renderer::mesh::MeshId;
+use crate::renderer::shader::{VKShader, VKShaderLoader};
+use crate::renderer::upload::VKUploader;
+
+/// Modes of ObjectAnimation, matches the ANIMATION_ constants in animated.slang
+pub const ANIMATION_NONE: u32 = 0;
+pub const ANIMATION_SKINNED: u32 = 1;
+pub const ANIMATION_BAKED: u32 = 2;
+
+/// How an instance of an animated mesh is posed, instances without one are drawn at rest
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InstanceAnimation {
+    /// skin matrices from VKSkinning::poses, needs VKRenderer::add_mesh_skin
+    Skinned { pose: usize },
+    /// a clip from VKRenderer::add_vertex_animation at time seconds
+    Baked { clip: usize, time: f32 },
+}
+
+/// What the animated vertex stage poses an instance with, found at binding 7 by its object
+/// matches ObjectAnimation in animated.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ObjectAnimation {
+    pub mode: u32,
+    /// first texel of the skin or clip in VKSkinning::data
+    pub data_offset: u32,
+    /// first matrix of the instance's pose in this frame's joints
+    pub joint_offset: u32,
+    pub vertex_count: u32,
+    pub frame: u32,
+    pub next_frame: u32,
+    /// how far towards next_frame
+    pub blend: f32,
+    pub frame_count: u32,
+}
+
+/// Where a mesh's skin is in VKSkinning::data, two texels a vertex
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SkinData {
+    pub offset: u32,
+    /// poses need at least this many matrices to skin the mesh
+    pub joint_count: u32,
+}
+
+/// Where a baked clip is in VKSkinning::data, laid out like VertexAnimation::texels
+#[derive(Clone, Debug, PartialEq)]
+pub struct BakedClip {
+    pub name: String,
+    pub offset: u32,
+    pub vertex_count: u32,
+    pub frame_count: u32,
+    pub frame_rate: f32,
+    pub looping: bool,
+}
+
+/// Skin and baked clips of one mesh
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshAnimation {
+    pub skin: Option<SkinData>,
+    pub clips: Vec<BakedClip>,
+}
+
+impl MeshAnimation {
+    /// What animation poses an instance with, pose_offsets are where each pose starts in
+    /// the frame's joints, None for poses that didn't fit
+    /// anything the mesh can't play is drawn at rest
+    pub fn object_animation(
+        &self,
+        animation: &InstanceAnimation,
+        poses: &[Vec<Mat4>],
+        pose_offsets: &[Option<u32>],
+    ) -> ObjectAnimation {
+        match *animation {
+            InstanceAnimation::Skinned { pose } => {
+                let (Some(skin), Some(Some(joint_offset))) = (self.skin, pose_offsets.get(pose))
+                else {
+                    return ObjectAnimation::default();
+                };
+                // a skin weighted to joints the pose doesn't have would read other poses
+                if poses[pose].len() < skin.joint_count as usize {
+                    return ObjectAnimation::default();
+                }
+                ObjectAnimation {
+                    mode: ANIMATION_SKINNED,
+                    data_offset: skin.offset,
+                    joint_offset: *joint_offset,
+                    ..Default::default()
+                }
+            }
+            InstanceAnimation::Baked { clip, time } => {
+                let Some(clip) = self.clips.get(clip) else {
+                    return ObjectAnimation::default();
+                };
+                let (frame, next_frame, blend) =
+                    baked_frames(clip.frame_count, clip.frame_rate, clip.looping, time);
+                ObjectAnimation {
+                    mode: ANIMATION_BAKED,
+                    data_offset: clip.offset,
+                    vertex_count: clip.vertex_count,
+                    frame,
+                    next_frame,
+                    blend,
+                    frame_count: clip.frame_count,
+                    ..Default::default()
+                }
+            }
+        }
+    }
+}
+
+/// Skinned and vertex animated meshes, drawn with the animated vertex stage of animated.slang
+/// skins are posed by matrices from the cpu each frame, baked clips only need a time so
+/// crowds can play them without evaluating skeletons
+/// ray tracing and ray query shadows see animated meshes at rest
+pub struct VKSkinning<'a> {
+    /// animatedVertexMain, pipelines pair it with the uber-shader's fragment stage
+    pub vertex_shader: VKShader<'a>,
+    /// animation data of every mesh with some
+    pub meshes: HashMap<MeshId, MeshAnimation>,
+    /// skin matrices InstanceAnimation::Skinned refers to, uploaded with every frame
+    /// usually from Skeleton::skin_matrices
+    pub poses: Vec<Vec<Mat4>>,
+    /// skins and baked clips of every mesh, uploaded whole whenever some are added
+    pub data: Vec<Vec4>,
+    /// gpu copy of data, never empty so the binding is always valid
+    pub data_buffer: VKBuffer,
+    /// an ObjectAnimation per instance for each frame in flight
+    pub animation_buffers: Vec<VKBuffer>,
+    /// poses for each frame in flight, max_joints matrices each
+    pub joint_buffers: Vec<VKBuffer>,
+    /// matrices all poses of a frame share, poses past it are drawn at rest
+    pub max_joints: usize,
+    // so running out of joints warns once instead of every frame
+    warned_joints: bool,
+}
+
+impl VKSkinning<'_> {
+    pub fn new(
+        vk_device: &mut VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        frames_in_flight: u32,
+        max_instances: usize,
+        max_joints: usize,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let vertex_shader = VKShader::new(
+            vk_device,
+            "shaders/animated.spv",
+            vk::ShaderStageFlags::VERTEX,
+            c"animatedVertexMain",
+            vk_shader_loader,
+        )?;
+
+        let mut skinning = Self {
+            vertex_shader,
+            meshes: HashMap::new(),
+            poses: Vec::new(),
+            data: Vec::new(),
+            data_buffer: VKBuffer::default(),
+            animation_buffers: Vec::new(),
+            joint_buffers: Vec::new(),
+            max_joints,
+            warned_joints: false,
+        };
+        if let Err(error) = skinning.create_buffers(vk_device, frames_in_flight, max_instances) {
+            unsafe { skinning.destroy(vk_device) };
+            return Err(error.into());
+        }
+        Ok(skinning)
+    }
+
+    fn create_buffers(
+        &mut self,
+        vk_device: &mut VKDevice,
+        frames_in_flight: u32,
+        max_instances: usize,
+    ) -> Result<(), vk::Result> {
+        // storage buffers can't be empty
+        self.data_buffer = VKBuffer::new(
+            vk_device,
+            size_of::<Vec4>() as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::CpuToGpu,
+            "Animation Data",
+        )?;
+        for _ in 0..frames_in_flight {
+            self.animation_buffers.push(VKBuffer::new(
+                vk_device,
+                (size_of::<ObjectAnimation>() * max_instances.max(1)) as u64,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                MemoryLocation::CpuToGpu,
+                "Object Animations",
+            )?);
+            self.joint_buffers.push(VKBuffer::new(
+                vk_device,
+                (size_of::<Mat4>() * self.max_joints.max(1)) as u64,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                MemoryLocation::CpuToGpu,
+                "Joints",
+            )?);
+        }
+        Ok(())
+    }
+
+    /// Whether mesh has a skin or baked clips, it's then drawn with the animated pipelines
+    pub fn is_animated(&self, mesh: MeshId) -> bool {
+        self.meshes.contains_key(&mesh)
+    }
+
+    /// Gives a mesh of vertex_count vertices a skin for skeletons of joint_count joints
+    /// replaces any skin it had, the old one stays in data unused
+    /// # Safety
+    /// The gpu must not be using data_buffer, descriptor sets pointing at it must be rewritten
+    pub unsafe fn add_skin(
+        &mut self,
+        vk_device: &mut VKDevice,
+        uploader: &mut VKUploader,
+        mesh: MeshId,
+        vertex_count: usize,
+        skins: &[VertexSkin],
+        joint_count: usize,
+    ) -> Result<(), Box<dyn error::Error>> {
+        validate_skins(vertex_count, skins, joint_count)?;
+        let texels: Vec<Vec4> = skins.iter().flat_map(VertexSkin::texels).collect();
+        let offset = unsafe { self.add_data(vk_device, uploader, &texels)? };
+        self.meshes.entry(mesh).or_default().skin = Some(SkinData {
+            offset,
+            joint_count: joint_count as u32,
+        });
+        Ok(())
+    }
+
+    /// Adds a baked clip to a mesh of vertex_count vertices, returns its index in the mesh's clips
+    /// # Safety
+    /// The gpu must not be using data_buffer, descriptor sets pointing at it must be rewritten
+    pub unsafe fn add_clip(
+        &mut self,
+        vk_device: &mut VKDevice,
+        uploader: &mut VKUploader,
+        mesh: MeshId,
+        vertex_count: usize,
+        animation: &VertexAnimation,
+    ) -> Result<usize, Box<dyn error::Error>> {
+        animation.validate()?;
+        if animation.vertex_count as usize != vertex_count {
+            return Err(AnimationError::VertexCount {
+                animation: animation.vertex_count as usize,
+                mesh: vertex_count,
+            }
+            .into());
+        }
+        let offset = unsafe { self.add_data(vk_device, uploader, &animation.texels)? };
+        let clips = &mut self.meshes.entry(mesh).or_default().clips;
+        clips.push(BakedClip {
+            name: animation.name.clone(),
+            offset,
+            vertex_count: animation.vertex_count,
+            frame_count: animation.frame_count,
+            frame_rate: animation.frame_rate,
+            looping: animation.looping,
+        });
+        Ok(clips.len() - 1)
+    }
+
+    // appends texels to data and uploads all of it again, returns where they start
+    unsafe fn add_data(
+        &mut self,
+        vk_device: &mut VKDevice,
+        uploader: &mut VKUploader,
+        texels: &[Vec4],
+    ) -> Result<u32, vk::Result> {
+        let offset = self.data.len();
+        if u32::try_from(offset + texels.len()).is_err() {
+            return Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY);
+        }
+        self.data.extend_from_slice(texels);
+        match uploader.upload_buffer(
+            vk_device,
+            &self.data,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            "Animation Data",
+        ) {
+            Ok(data_buffer) => {
+                let mut old = std::mem::replace(&mut self.data_buffer, data_buffer);
+                unsafe { old.destroy(vk_device) };
+                Ok(offset as u32)
+            }
+            Err(error) => {
+                self.data.truncate(offset);
+                Err(error)
+            }
+        }
+    }
+
+    /// Writes poses and the ObjectAnimation of each of the first capacity instances for
+    /// frame_in_flight, nothing is written while no mesh is animated
+    /// the gpu must be done with frame_in_flight
+    pub fn write_frame(
+        &mut self,
+        frame_in_flight: usize,
+        instances: &[MeshInstance],
+        capacity: usize,
+    ) {
+        if self.meshes.is_empty() {
+            return;
+        }
+
+        let mut pose_offsets = Vec::with_capacity(self.poses.len());
+        let mut joints = Vec::new();
+        for pose in &self.poses {
+            if joints.len() + pose.len() > self.max_joints {
+                pose_offsets.push(None);
+                continue;
+            }
+            pose_offsets.push(Some(joints.len() as u32));
+            joints.extend_from_slice(pose);
+        }
+        if pose_offsets.contains(&None) && !self.warned_joints {
+            warn!(
+                "Poses Need More Than {} Joints, The Rest Are Drawn At Rest",
+                self.max_joints
+            );
+            self.warned_joints = true;
+        }
+
+        let animations: Vec<_> = instances
+            .iter()
+            .take(capacity)
+            .map(
+                |instance| match (self.meshes.get(&instance.mesh), &instance.animation) {
+                    (Some(mesh), Some(animation)) => {
+                        mesh.object_animation(animation, &self.poses, &pose_offsets)
+                    }
+                    _ => ObjectAnimation::default(),
+                },
+            )
+            .collect();
+
+        let written = self.joint_buffers[frame_in_flight]
+            .write(0, &joints)
+            .and_then(|_| self.animation_buffers[frame_in_flight].write(0, &animations));
+        if let Err(error) = written {
+            warn!("Failed To Write Animations: {error}");
+        }
+    }
+
+    /// Buffers of frame_in_flight for bindings 7, 8 and 9 of a material's set
+    pub fn descriptor_buffer_infos(&self, frame_in_flight: usize) -> [vk::DescriptorBufferInfo; 3] {
+        [
+            self.animation_buffers[frame_in_flight].descriptor_buffer_info(),
+            self.joint_buffers[frame_in_flight].descriptor_buffer_info(),
+            self.data_buffer.descriptor_buffer_info(),
+        ]
+    }
+
+    /// # Safety
+    /// The gpu must not be using the buffers or the shader
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            self.vertex_shader.destroy(vk_device);
+            self.data_buffer.destroy(vk_device);
+            for buffer in self
+                .animation_buffers
+                .iter_mut()
+                .chain(&mut self.joint_buffers)
+            {
+                buffer.destroy(vk_device);
+            }
+        }
+    }
+}
+
+#[test]
+fn object_animation_test() {
+    let mesh = MeshAnimation {
+        skin: Some(SkinData {
+            offset: 12,
+            joint_count: 2,
+        }),
+        clips: vec![BakedClip {
+            name: "walk".to_string(),
+            offset: 40,
+            vertex_count: 3,
+            frame_count: 4,
+            frame_rate: 2.0,
+            looping: true,
+        }],
+    };
+    let poses = vec![vec![Mat4::IDENTITY; 2], vec![Mat4::IDENTITY; 1]];
+    let offsets = [Some(0), Some(2)];
+
+    let skinned = mesh.object_animation(&InstanceAnimation::Skinned { pose: 0 }, &poses, &offsets);
+    assert_eq!(skinned.mode, ANIMATION_SKINNED);
+    assert_eq!((skinned.data_offset, skinned.joint_offset), (12, 0));
+    // too few joints, out of range and poses that didn't fit are drawn at rest
+    for pose in [1, 2] {
+        let animation = InstanceAnimation::Skinned { pose };
+        assert_eq!(
+            mesh.object_animation(&animation, &poses, &offsets),
+            ObjectAnimation::default()
+        );
+    }
+    let animation = InstanceAnimation::Skinned { pose: 0 };
+    assert_eq!(
+        mesh.object_animation(&animation, &poses, &[None, Some(2)]),
+        ObjectAnimation::default()
+    );
+
+    let baked = InstanceAnimation::Baked {
+        clip: 0,
+        time: 1.75,
+    };
+    assert_eq!(
+        mesh.object_animation(&baked, &poses, &offsets),
+        ObjectAnimation {
+            mode: ANIMATION_BAKED,
+            data_offset: 40,
+            joint_offset: 0,
+            vertex_count: 3,
+            frame: 3,
+            next_frame: 0,
+            blend: 0.5,
+            frame_count: 4,
+        }
+    );
+    let missing = InstanceAnimation::Baked { clip: 1, time: 0.0 };
+    assert_eq!(
+        mesh.object_animation(&missing, &poses, &offsets).mode,
+        ANIMATION_NONE
+    );
+}
+
+#[test]
+fn animated_shader_test() {
+    let mut vk_shader_loader = VKShaderLoader::<&str>::default();
+    let spirv = vk_shader_loader
+        .load_shader("shaders/animated.spv")
+        .unwrap();
+    let entry_points = crate::renderer::shader::spirv_entry_points(spirv);
+    assert!(entry_points.iter().any(|name| name == "animatedVertexMain"));
+}
@@ -0,0 +1,344 @@
+use ash::vk;
+use glam::{Mat3, Mat4, Vec4};
+use std::error;
+
+use crate::camera::{CameraUniform, DepthConvention};
+use crate::color::LinearRgba;
+use crate::renderer::cubemap::VKCubemap;
+use crate::renderer::device::VKDevice;
+use crate::renderer::pipeline::{DepthState, GraphicsPipelineBuilder};
+use crate::renderer::presentation::VKSwapchain;
+use crate::renderer::push_constant_range;
+use crate::renderer::shader::{VKShader, VKShaderLoader, reload_shaders};
+
+/// Per frame data pushed before drawing the sky, matches SkyboxConstants in skybox.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SkyboxConstants {
+    /// inverse of the projection times the view without translation
+    pub inverse_view_projection: Mat4,
+    pub tint: Vec4,
+    /// depth the triangle is drawn at, the near plane is unprojected at the other end
+    pub far_depth: f32,
+    padding: [f32; 3],
+}
+
+impl SkyboxConstants {
+    /// The sky only turns with the camera, moving it never gets any closer
+    pub fn new(
+        camera: &CameraUniform,
+        tint: LinearRgba,
+        depth_convention: DepthConvention,
+    ) -> Self {
+        let rotation = Mat4::from_mat3(Mat3::from_mat4(camera.view));
+        Self {
+            inverse_view_projection: (camera.projection * rotation).inverse(),
+            tint: tint.to_vec4(),
+            far_depth: depth_convention.far_depth(),
+            padding: [0.0; 3],
+        }
+    }
+}
+
+/// Cubemap drawn behind everything, after opaque geometry so only uncovered pixels are shaded
+/// Example Use:
+/// ```ignore
+/// let sky = VKCubemap::from_equirectangular_file(
+///     &mut renderer.vulkan_ctx.vulkan_device,
+///     renderer.vulkan_cmd_pool,
+///     "sky/sunset.hdr",
+///     512,
+/// )?;
+/// renderer.set_skybox(Some(sky));
+/// // hdr skies usually need bringing down to the display range
+/// renderer.skybox.tint = LinearRgba::rgb(0.5, 0.5, 0.5);
+/// ```
+pub struct VKSkybox<'a> {
+    pub vertex_shader: VKShader<'a>,
+    pub fragment_shader: VKShader<'a>,
+    pub descriptor_layout: vk::DescriptorSetLayout,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub descriptor_pool: vk::DescriptorPool,
+    /// points at cubemap, only written while the gpu is idle
+    pub descriptor_set: vk::DescriptorSet,
+    /// nothing is drawn without one
+    pub cubemap: Option<VKCubemap>,
+    /// multiplied with the sky colour
+    pub tint: LinearRgba,
+    // the pipeline is built for it and the sky is pushed to its far plane
+    depth_convention: DepthConvention,
+}
+
+impl VKSkybox<'_> {
+    pub fn new(
+        vk_device: &VKDevice,
+        vk_swapchain: &VKSwapchain,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        depth_convention: DepthConvention,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let vertex_shader = VKShader::new(
+            vk_device,
+            "shaders/skybox.spv",
+            vk::ShaderStageFlags::VERTEX,
+            c"vertexMain",
+            vk_shader_loader,
+        )?;
+
+        let fragment_shader = VKShader::new(
+            vk_device,
+            "shaders/skybox.spv",
+            vk::ShaderStageFlags::FRAGMENT,
+            c"fragMain",
+            vk_shader_loader,
+        )?;
+
+        let set_bindings = [vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)];
+
+        let descriptor_layout = unsafe {
+            vk_device.device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(&set_bindings),
+                None,
+            )?
+        };
+
+        let descriptor_layouts = [descriptor_layout];
+        let push_constant_ranges = [push_constant_range::<SkyboxConstants>(
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            0,
+        )];
+        let pipeline_layout = unsafe {
+            vk_device.device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&descriptor_layouts)
+                    .push_constant_ranges(&push_constant_ranges),
+                None,
+            )?
+        };
+
+        let stages = [vertex_shader.shader_info, fragment_shader.shader_info];
+        let pipeline = create_skybox_pipeline(
+            vk_device,
+            vk_swapchain,
+            &stages,
+            pipeline_layout,
+            depth_convention,
+        )?;
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 1,
+        }];
+        let descriptor_pool = unsafe {
+            vk_device.device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .max_sets(1)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )?
+        };
+
+        let descriptor_set = unsafe {
+            vk_device.device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(descriptor_pool)
+                    .set_layouts(&descriptor_layouts),
+            )?[0]
+        };
+
+        Ok(Self {
+            vertex_shader,
+            fragment_shader,
+            descriptor_layout,
+            pipeline_layout,
+            pipeline,
+            descriptor_pool,
+            descriptor_set,
+            cubemap: None,
+            tint: LinearRgba::WHITE,
+            depth_convention,
+        })
+    }
+
+    /// Swaps the sky for cubemap, returns the old one for the caller to destroy
+    /// # Safety
+    /// The gpu must not be using the skybox
+    pub unsafe fn set_cubemap(
+        &mut self,
+        vk_device: &VKDevice,
+        cubemap: Option<VKCubemap>,
+    ) -> Option<VKCubemap> {
+        if let Some(cubemap) = &cubemap {
+            let image_infos = [cubemap.descriptor_image_info()];
+            let writes = [vk::WriteDescriptorSet::default()
+                .dst_set(self.descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&image_infos)];
+            unsafe { vk_device.device.update_descriptor_sets(&writes, &[]) };
+        }
+        std::mem::replace(&mut self.cubemap, cubemap)
+    }
+
+    /// Draws the sky into the current rendering, does nothing without a cubemap
+    /// # Safety
+    /// cmd_buffer must be inside rendering to the swapchain formats with opaque geometry already drawn
+    pub unsafe fn record(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        camera: &CameraUniform,
+    ) {
+        if self.cubemap.is_none() {
+            return;
+        }
+
+        unsafe {
+            vk_device.device.cmd_bind_pipeline(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            vk_device.device.cmd_bind_descriptor_sets(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            vk_device.cmd_push_constants(
+                cmd_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                &SkyboxConstants::new(camera, self.tint, self.depth_convention),
+            );
+            vk_device.device.cmd_draw(cmd_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    /// Rebuilds the pipeline when one of its shaders is in changed, see VKShaderLoader::changed_shaders
+    /// # Safety
+    /// The gpu must not be using the skybox
+    pub unsafe fn reload_shaders(
+        &mut self,
+        vk_device: &VKDevice,
+        vk_swapchain: &VKSwapchain,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        changed: &[&str],
+    ) -> Result<(), Box<dyn error::Error>> {
+        let shaders = &mut [&mut self.vertex_shader, &mut self.fragment_shader];
+        if !unsafe { reload_shaders(vk_device, vk_shader_loader, shaders, changed)? } {
+            return Ok(());
+        }
+        unsafe { self.rebuild_pipeline(vk_device, vk_swapchain)? };
+        Ok(())
+    }
+
+    /// Rebuilds the pipeline for vk_swapchain's current scene format
+    /// # Safety
+    /// The gpu must not be using the skybox
+    pub unsafe fn rebuild_pipeline(
+        &mut self,
+        vk_device: &VKDevice,
+        vk_swapchain: &VKSwapchain,
+    ) -> Result<(), vk::Result> {
+        let stages = [
+            self.vertex_shader.shader_info,
+            self.fragment_shader.shader_info,
+        ];
+        let pipeline = create_skybox_pipeline(
+            vk_device,
+            vk_swapchain,
+            &stages,
+            self.pipeline_layout,
+            self.depth_convention,
+        )?;
+        unsafe { vk_device.device.destroy_pipeline(self.pipeline, None) };
+        self.pipeline = pipeline;
+        Ok(())
+    }
+
+    /// # Safety
+    /// The gpu must not be using the skybox
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            if let Some(mut cubemap) = self.cubemap.take() {
+                cubemap.destroy(vk_device);
+            }
+            vk_device
+                .device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            vk_device.device.destroy_pipeline(self.pipeline, None);
+            vk_device
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            vk_device
+                .device
+                .destroy_descriptor_set_layout(self.descriptor_layout, None);
+            self.fragment_shader.destroy(vk_device);
+            self.vertex_shader.destroy(vk_device);
+        }
+    }
+}
+
+// fullscreen triangle without vertex input, depth tested but never written
+fn create_skybox_pipeline(
+    vk_device: &VKDevice,
+    vk_swapchain: &VKSwapchain,
+    stages: &[vk::PipelineShaderStageCreateInfo],
+    pipeline_layout: vk::PipelineLayout,
+    depth_convention: DepthConvention,
+) -> Result<vk::Pipeline, vk::Result> {
+    // the triangle sits on the far plane, only pixels nothing was drawn to pass
+    GraphicsPipelineBuilder::new(stages, pipeline_layout)
+        .swapchain(vk_swapchain)
+        .depth(DepthState::read_only(depth_convention))
+        .build(vk_device)
+}
+
+#[test]
+fn skybox_constants_test() {
+    use crate::camera::Camera;
+    use glam::Vec3;
+
+    let camera = Camera::perspective(90.0_f32.to_radians(), 0.1).look_at(
+        Vec3::new(3.0, 1.0, 5.0),
+        Vec3::new(3.0, 1.0, 0.0),
+        Vec3::Y,
+    );
+    let constants = SkyboxConstants::new(
+        &camera.uniform(1.0),
+        LinearRgba::WHITE,
+        DepthConvention::ReverseZ,
+    );
+
+    // centre of the screen looks where the camera does, wherever it is
+    let direction = |x: f32, y: f32| {
+        constants
+            .inverse_view_projection
+            .project_point3(Vec3::new(x, y, 1.0))
+            .normalize()
+    };
+    assert!(direction(0.0, 0.0).abs_diff_eq(Vec3::NEG_Z, 1e-5));
+    // 90 degree fov puts the right edge 45 degrees off
+    assert!(direction(1.0, 0.0).abs_diff_eq(Vec3::new(1.0, 0.0, -1.0).normalize(), 1e-5));
+
+    // forward depth unprojects the near plane at 0 instead
+    let forward = DepthConvention::Forward;
+    let uniform = camera
+        .uniform(1.0)
+        .with_clip_transform(forward.clip_transform());
+    let constants = SkyboxConstants::new(&uniform, LinearRgba::WHITE, forward);
+    assert_eq!(constants.far_depth, 1.0);
+    let near =
+        constants
+            .inverse_view_projection
+            .project_point3(Vec3::new(0.0, 0.0, forward.near_depth()));
+    assert!(near.normalize().abs_diff_eq(Vec3::NEG_Z, 1e-5));
+}
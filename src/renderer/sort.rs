@@ -0,0 +1,218 @@
+use ash::vk;
+use std::error;
+
+use crate::renderer::compute::{VKComputePipeline, cmd_compute_barrier, group_count};
+use crate::renderer::device::VKDevice;
+use crate::renderer::shader::VKShaderLoader;
+use crate::renderer::submit_one_time;
+
+/// Threads per workgroup, matches numthreads in sort.slang
+pub const SORT_GROUP_SIZE: u32 = 256;
+
+/// One pass over the keys, matches SortStep in sort.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SortStep {
+    pub count: u32,
+    /// size of the blocks being merged
+    pub block_size: u32,
+    /// gap between compared elements when not flipping
+    pub distance: u32,
+    /// 1 on the first step of each block, compares mirrored pairs instead
+    pub flip: u32,
+}
+
+/// Every step of a bitonic sort over count keys, each one a dispatch of sort_threads(count)
+pub fn bitonic_steps(count: u32) -> Vec<SortStep> {
+    let mut steps = Vec::new();
+    if count < 2 {
+        return steps;
+    }
+
+    let padded = count.next_power_of_two();
+    let mut block_size = 2;
+    while block_size <= padded {
+        steps.push(SortStep {
+            count,
+            block_size,
+            distance: block_size / 2,
+            flip: 1,
+        });
+        let mut distance = block_size / 4;
+        while distance >= 1 {
+            steps.push(SortStep {
+                count,
+                block_size,
+                distance,
+                flip: 0,
+            });
+            distance /= 2;
+        }
+        block_size *= 2;
+    }
+    steps
+}
+
+/// One thread per compared pair of the padded keys
+pub fn sort_threads(count: u32) -> u32 {
+    count.next_power_of_two() / 2
+}
+
+/// Maps a float onto a u32 that sorts in the same order, negatives and all
+/// sort back to front with !float_sort_key(depth)
+pub fn float_sort_key(value: f32) -> u32 {
+    let bits = value.to_bits();
+    if bits & 0x8000_0000 != 0 {
+        !bits
+    } else {
+        bits | 0x8000_0000
+    }
+}
+
+/// Sorts u32 keys ascending on the gpu, moving a u32 value along with each key
+/// keys and values live in separate storage buffers of at least count elements
+/// Example Use:
+/// ```ignore
+/// let mut sort = VKGpuSort::new(vk_device, &mut renderer.vulkan_shader_loader, 4)?;
+/// let set = sort.bind_buffers(vk_device, depth_keys, particle_indices)?;
+/// // inside a frame, before the particles are drawn
+/// unsafe { sort.record(vk_device, cmd_buffer, set, particle_count) };
+/// ```
+/// VKDrawCuller sorts the draws of each batch front to back with it
+pub struct VKGpuSort<'a> {
+    pub pipeline: VKComputePipeline<'a>,
+}
+
+impl VKGpuSort<'_> {
+    /// max_sets is how many key value pairs of buffers can be bound at once
+    pub fn new(
+        vk_device: &VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        max_sets: u32,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let pipeline = VKComputePipeline::new::<SortStep>(
+            vk_device,
+            vk_shader_loader,
+            "shaders/sort.spv",
+            &[c"sortStep"],
+            2,
+            max_sets,
+        )?;
+        Ok(Self { pipeline })
+    }
+
+    /// keys and values need STORAGE_BUFFER usage
+    pub fn bind_buffers(
+        &self,
+        vk_device: &VKDevice,
+        keys: vk::Buffer,
+        values: vk::Buffer,
+    ) -> Result<vk::DescriptorSet, vk::Result> {
+        self.pipeline.allocate_set(vk_device, &[keys, values])
+    }
+
+    /// Records the whole sort, with a barrier after every step including the last
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering and set must come from bind_buffers
+    pub unsafe fn record(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        set: vk::DescriptorSet,
+        count: u32,
+    ) {
+        let groups = group_count(sort_threads(count), SORT_GROUP_SIZE);
+        for step in bitonic_steps(count) {
+            unsafe {
+                self.pipeline
+                    .cmd_dispatch(vk_device, cmd_buffer, 0, set, &step, groups);
+                cmd_compute_barrier(vk_device, cmd_buffer);
+            }
+        }
+    }
+
+    /// Sorts straight away and waits for it, for one off work outside the frame loop
+    pub fn sort_now(
+        &self,
+        vk_device: &VKDevice,
+        vk_command_pool: vk::CommandPool,
+        set: vk::DescriptorSet,
+        count: u32,
+    ) -> Result<(), vk::Result> {
+        submit_one_time(vk_device, vk_command_pool, |cmd_buffer| unsafe {
+            self.record(vk_device, cmd_buffer, set, count)
+        })
+    }
+
+    /// # Safety
+    /// The gpu must not be using the sort
+    pub unsafe fn destroy(&mut self, vk_device: &VKDevice) {
+        unsafe { self.pipeline.destroy(vk_device) };
+    }
+}
+
+#[test]
+fn bitonic_sort_test() {
+    // runs the steps the same way sort.slang does
+    fn sort(keys: &mut [u32], values: &mut [u32]) {
+        let count = keys.len() as u32;
+        for step in bitonic_steps(count) {
+            for thread in 0..sort_threads(count) {
+                let (first, second) = if step.flip != 0 {
+                    let half = step.block_size / 2;
+                    let block_start = (thread / half) * step.block_size;
+                    let offset = thread % half;
+                    (
+                        block_start + offset,
+                        block_start + step.block_size - 1 - offset,
+                    )
+                } else {
+                    let first =
+                        (thread / step.distance) * step.distance * 2 + thread % step.distance;
+                    (first, first + step.distance)
+                };
+                if second >= count {
+                    continue;
+                }
+                let (first, second) = (first as usize, second as usize);
+                if keys[first] > keys[second] {
+                    keys.swap(first, second);
+                    values.swap(first, second);
+                }
+            }
+        }
+    }
+
+    let mut seed = 12345_u32;
+    for count in 0..100 {
+        let mut keys: Vec<u32> = (0..count)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                seed >> 24
+            })
+            .collect();
+        let mut values: Vec<u32> = (0..count).collect();
+        let original = keys.clone();
+        sort(&mut keys, &mut values);
+
+        assert!(keys.windows(2).all(|pair| pair[0] <= pair[1]));
+        // values still point at where their key came from
+        assert!(
+            keys.iter()
+                .zip(&values)
+                .all(|(key, value)| original[*value as usize] == *key)
+        );
+    }
+
+    let mut floats = [3.5_f32, -0.0, -2.0, 0.0, 1.0, -f32::INFINITY, 100.0];
+    let mut keys: Vec<u32> = floats.iter().map(|value| float_sort_key(*value)).collect();
+    keys.sort();
+    floats.sort_by(|a, b| a.total_cmp(b));
+    assert_eq!(
+        keys,
+        floats
+            .iter()
+            .map(|value| float_sort_key(*value))
+            .collect::<Vec<_>>()
+    );
+}
@@ -0,0 +1,589 @@
+use ash::vk;
+use glam::{Mat4, UVec2, Vec2, Vec3, Vec4Swizzles};
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::ffi::CStr;
+
+use crate::camera::DepthConvention;
+use crate::renderer::compute::group_count;
+use crate::renderer::device::VKDevice;
+use crate::renderer::image::VKImage;
+use crate::renderer::render_graph::Access;
+use crate::renderer::scaling::VKInternalTarget;
+use crate::renderer::shader::{VKShader, VKShaderLoader, reload_shaders};
+use crate::renderer::{push_constant_range, submit_one_time};
+
+/// Format of the occlusion images, occlusion in x and one over clip w in y
+/// half floats can always be written from compute, two channel formats need an extra feature
+pub const AMBIENT_OCCLUSION_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// Threads per workgroup along each side, matches numthreads in ssao.slang
+pub const AMBIENT_OCCLUSION_GROUP_SIZE: u32 = 8;
+
+/// How much nearby geometry darkens creases and corners
+/// Example Use:
+/// ```
+/// use vulkan_engine::renderer::ssao::AmbientOcclusionSettings;
+///
+/// // wide and soft, for large scenes
+/// let settings = AmbientOcclusionSettings::default().radius(2.0).intensity(0.6);
+/// assert_eq!(settings.radius, 2.0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AmbientOcclusionSettings {
+    /// world space distance geometry occludes from
+    pub radius: f32,
+    /// how dark fully occluded pixels get
+    pub intensity: f32,
+    /// fraction of the distance to the camera surfaces have to stick out by to occlude,
+    /// raise it if flat surfaces darken themselves
+    pub bias: f32,
+    /// depth samples per pixel, more is smoother and slower
+    pub samples: u32,
+}
+
+impl Default for AmbientOcclusionSettings {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            intensity: 1.0,
+            bias: 0.01,
+            samples: 12,
+        }
+    }
+}
+
+impl AmbientOcclusionSettings {
+    pub fn radius(mut self, radius: f32) -> Self {
+        self.radius = radius.max(0.001);
+        self
+    }
+
+    pub fn intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity.max(0.0);
+        self
+    }
+
+    pub fn bias(mut self, bias: f32) -> Self {
+        self.bias = bias.max(0.0);
+        self
+    }
+
+    pub fn samples(mut self, samples: u32) -> Self {
+        self.samples = samples.clamp(1, 64);
+        self
+    }
+}
+
+/// Pushed before the occlusion dispatch, matches AmbientOcclusionConstants in ssao.slang
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmbientOcclusionConstants {
+    pub inverse_projection: Mat4,
+    pub extent: UVec2,
+    pub radius: f32,
+    pub intensity: f32,
+    pub bias: f32,
+    /// pixels one view space unit covers where clip w is 1
+    pub pixel_scale: f32,
+    /// depth the scene is cleared to, nothing was drawn there
+    pub far_depth: f32,
+    pub sample_count: u32,
+}
+
+impl AmbientOcclusionConstants {
+    /// projection is the camera's with any clip transform, extent the size of the depth image
+    pub fn new(
+        settings: &AmbientOcclusionSettings,
+        projection: Mat4,
+        extent: vk::Extent2D,
+        depth_convention: DepthConvention,
+    ) -> Self {
+        Self {
+            inverse_projection: projection.inverse(),
+            extent: UVec2::new(extent.width, extent.height),
+            radius: settings.radius,
+            intensity: settings.intensity,
+            bias: settings.bias,
+            // view space up lands on clip y, or x when the clip transform rotates
+            pixel_scale: projection.y_axis.xy().length() * extent.height as f32 * 0.5,
+            far_depth: depth_convention.far_depth(),
+            sample_count: settings.samples,
+        }
+    }
+
+    /// View space position at uv on the depth image, what viewPosition in ssao.slang computes
+    pub fn view_position(&self, uv: Vec2, depth: f32) -> Vec3 {
+        let ndc = uv * 2.0 - 1.0;
+        let position = self.inverse_projection * ndc.extend(depth).extend(1.0);
+        position.xyz() / position.w.max(0.000001)
+    }
+}
+
+/// Screen space ambient occlusion over the internal target, worked out from its depth and blurred by
+/// compute passes after the scene pass, lit materials sample it the frame after through the camera
+/// it was worked out for, see LightUniform::occlusion_view_projection
+/// the internal target has to be single sampled, multisampled depth can't be read per pixel
+pub struct VKAmbientOcclusion<'a> {
+    pub occlusion_shader: VKShader<'a>,
+    pub blur_shader: VKShader<'a>,
+    /// depth, the occlusion image as storage then sampled, the blurred image as storage
+    pub descriptor_layout: vk::DescriptorSetLayout,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub occlusion_pipeline: vk::Pipeline,
+    pub blur_pipeline: vk::Pipeline,
+    /// nearest and clamped, lit materials load single texels anyway
+    pub sampler: vk::Sampler,
+    /// null while the pass has no target
+    pub descriptor_pool: vk::DescriptorPool,
+    /// only written while the gpu is idle
+    pub descriptor_set: vk::DescriptorSet,
+    /// None disables the pass
+    pub settings: Option<AmbientOcclusionSettings>,
+    pub depth_convention: DepthConvention,
+    /// occlusion before and after the blur, None while the pass has no target
+    pub images: Option<[VKImage; 2]>,
+    // camera of the occlusion the frame being recorded works out, and of the one lit materials
+    // sample, None until a frame has worked one out
+    view_projection: Option<Mat4>,
+    reprojection: Option<Mat4>,
+}
+
+impl VKAmbientOcclusion<'_> {
+    pub fn new(
+        vk_device: &VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        depth_convention: DepthConvention,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let occlusion_shader = VKShader::new(
+            vk_device,
+            "shaders/ssao.spv",
+            vk::ShaderStageFlags::COMPUTE,
+            c"occlusionMain",
+            vk_shader_loader,
+        )?;
+        let blur_shader = VKShader::new(
+            vk_device,
+            "shaders/ssao.spv",
+            vk::ShaderStageFlags::COMPUTE,
+            c"blurMain",
+            vk_shader_loader,
+        )?;
+
+        let set_bindings = DESCRIPTOR_TYPES
+            .iter()
+            .enumerate()
+            .map(|(binding, &descriptor_type)| {
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(binding as u32)
+                    .descriptor_type(descriptor_type)
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            })
+            .collect::<Vec<_>>();
+
+        let descriptor_layout = unsafe {
+            vk_device.device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(&set_bindings),
+                None,
+            )?
+        };
+
+        let descriptor_layouts = [descriptor_layout];
+        let push_constant_ranges = [push_constant_range::<AmbientOcclusionConstants>(
+            vk::ShaderStageFlags::COMPUTE,
+            0,
+        )];
+        let pipeline_layout = unsafe {
+            vk_device.device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&descriptor_layouts)
+                    .push_constant_ranges(&push_constant_ranges),
+                None,
+            )?
+        };
+
+        let [occlusion_pipeline, blur_pipeline] = create_ambient_occlusion_pipelines(
+            vk_device,
+            &[occlusion_shader.shader_info, blur_shader.shader_info],
+            pipeline_layout,
+        )?;
+
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = unsafe { vk_device.device.create_sampler(&sampler_info, None)? };
+
+        Ok(Self {
+            occlusion_shader,
+            blur_shader,
+            descriptor_layout,
+            pipeline_layout,
+            occlusion_pipeline,
+            blur_pipeline,
+            sampler,
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_set: vk::DescriptorSet::null(),
+            settings: None,
+            depth_convention,
+            images: None,
+            view_projection: None,
+            reprojection: None,
+        })
+    }
+
+    /// Whether the passes do anything, needs settings and a single sampled target
+    pub fn is_active(&self) -> bool {
+        self.settings.is_some() && self.images.is_some()
+    }
+
+    /// Whether target's depth can be read per pixel
+    pub fn accepts(target: &VKInternalTarget) -> bool {
+        target.samples == vk::SampleCountFlags::TYPE_1
+    }
+
+    /// The blurred occlusion lit materials sample, in SHADER_READ_ONLY_OPTIMAL between frames
+    pub fn descriptor_image_info(&self) -> Option<vk::DescriptorImageInfo> {
+        let [_, blurred] = self.images.as_ref()?;
+        Some(
+            vk::DescriptorImageInfo::default()
+                .sampler(self.sampler)
+                .image_view(blurred.view)
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        )
+    }
+
+    /// Moves on to a frame whose occlusion is worked out through view_projection, None when the frame
+    /// doesn't rasterize the scene
+    /// the occlusion the last frame worked out is what this one samples
+    pub fn begin_frame(&mut self, view_projection: Option<Mat4>) {
+        let view_projection = view_projection.filter(|_| self.is_active());
+        self.reprojection = std::mem::replace(&mut self.view_projection, view_projection);
+    }
+
+    /// Camera the occlusion lit materials sample this frame was worked out through, None when there
+    /// is nothing to sample
+    pub fn reprojection(&self) -> Option<Mat4> {
+        self.reprojection
+    }
+
+    /// Recreates the images for target and points the descriptors at them and the target's depth
+    /// without settings or without an accepted target the images are destroyed and the pass does
+    /// nothing, the blurred image is left ready for sampling
+    /// # Safety
+    /// The gpu must not be using the pass
+    pub unsafe fn set_target(
+        &mut self,
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+        target: Option<&VKInternalTarget>,
+    ) -> Result<(), vk::Result> {
+        unsafe { self.destroy_images(vk_device) };
+
+        let (Some(_), Some(target)) = (self.settings, target.filter(|t| Self::accepts(t))) else {
+            return Ok(());
+        };
+
+        let mut images = Vec::with_capacity(2);
+        for _ in 0..2 {
+            let image = VKImage::new(
+                vk_device,
+                target.resolution.extent,
+                AMBIENT_OCCLUSION_FORMAT,
+                vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+                vk::SampleCountFlags::TYPE_1,
+                vk::ImageAspectFlags::COLOR,
+            );
+            match image {
+                Ok(image) => images.push(image),
+                Err(error) => {
+                    for mut image in images {
+                        unsafe { image.destroy(vk_device) };
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        let Ok(images) = <[VKImage; 2]>::try_from(images) else {
+            unreachable!("two images were just created");
+        };
+        self.images = Some(images);
+
+        // lit materials bind the blurred image before any frame has written it
+        let [_, blurred] = self.images.as_mut().expect("images were just set");
+        let result = submit_one_time(vk_device, vk_command_pool, |cmd_buffer| unsafe {
+            blurred.cmd_transition(vk_device, cmd_buffer, Access::FragmentSampled)
+        })
+        .and_then(|_| unsafe { self.allocate_set(vk_device, target.depth_image.view) });
+        if result.is_err() {
+            unsafe { self.destroy_images(vk_device) };
+        }
+        result
+    }
+
+    unsafe fn allocate_set(
+        &mut self,
+        vk_device: &VKDevice,
+        depth_view: vk::ImageView,
+    ) -> Result<(), vk::Result> {
+        let Some([image, blurred]) = &self.images else {
+            return Ok(());
+        };
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: 2,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_IMAGE,
+                descriptor_count: 2,
+            },
+        ];
+        self.descriptor_pool = unsafe {
+            vk_device.device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .max_sets(1)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )?
+        };
+        self.descriptor_set = unsafe {
+            vk_device.device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(self.descriptor_pool)
+                    .set_layouts(&[self.descriptor_layout]),
+            )?[0]
+        };
+
+        // both passes read and write in GENERAL
+        let image_infos = [depth_view, image.view, image.view, blurred.view].map(|image_view| {
+            [vk::DescriptorImageInfo::default()
+                .image_view(image_view)
+                .image_layout(vk::ImageLayout::GENERAL)]
+        });
+        let writes: Vec<_> = image_infos
+            .iter()
+            .zip(DESCRIPTOR_TYPES)
+            .enumerate()
+            .map(|(binding, (image_info, descriptor_type))| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(self.descriptor_set)
+                    .dst_binding(binding as u32)
+                    .descriptor_type(descriptor_type)
+                    .image_info(image_info)
+            })
+            .collect();
+        unsafe { vk_device.device.update_descriptor_sets(&writes, &[]) };
+        Ok(())
+    }
+
+    /// Works out the occlusion of every pixel of the target's depth seen through projection, then
+    /// blurs it, does nothing while the pass is inactive
+    /// the depth must be in GENERAL before step Occlusion, the render graph transitions the images
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering, after the scene pass wrote the depth and
+    /// the steps before step
+    pub unsafe fn record(
+        &self,
+        vk_device: &VKDevice,
+        cmd_buffer: vk::CommandBuffer,
+        step: AmbientOcclusionStep,
+        projection: Mat4,
+    ) {
+        let (Some(settings), Some([image, _])) = (&self.settings, &self.images) else {
+            return;
+        };
+        let constants = AmbientOcclusionConstants::new(
+            settings,
+            projection,
+            image.extent,
+            self.depth_convention,
+        );
+        let pipeline = match step {
+            AmbientOcclusionStep::Occlusion => self.occlusion_pipeline,
+            AmbientOcclusionStep::Blur => self.blur_pipeline,
+        };
+
+        unsafe {
+            vk_device.device.cmd_bind_pipeline(
+                cmd_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                pipeline,
+            );
+            vk_device.device.cmd_bind_descriptor_sets(
+                cmd_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            vk_device.cmd_push_constants(
+                cmd_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                &constants,
+            );
+            vk_device.device.cmd_dispatch(
+                cmd_buffer,
+                group_count(image.extent.width, AMBIENT_OCCLUSION_GROUP_SIZE),
+                group_count(image.extent.height, AMBIENT_OCCLUSION_GROUP_SIZE),
+                1,
+            );
+        }
+    }
+
+    // the images and their descriptors only, the pipelines stay
+    unsafe fn destroy_images(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            for mut image in self.images.take().into_iter().flatten() {
+                image.destroy(vk_device);
+            }
+            // the set goes with its pool
+            vk_device
+                .device
+                .destroy_descriptor_pool(std::mem::take(&mut self.descriptor_pool), None);
+        }
+        self.descriptor_set = vk::DescriptorSet::null();
+        // whatever was worked out went with the images
+        self.view_projection = None;
+        self.reprojection = None;
+    }
+
+    /// Rebuilds the pipelines when one of their shaders is in changed, see VKShaderLoader::changed_shaders
+    /// # Safety
+    /// The gpu must not be using the pass
+    pub unsafe fn reload_shaders(
+        &mut self,
+        vk_device: &VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        changed: &[&str],
+    ) -> Result<(), Box<dyn error::Error>> {
+        let shaders = &mut [&mut self.occlusion_shader, &mut self.blur_shader];
+        if !unsafe { reload_shaders(vk_device, vk_shader_loader, shaders, changed)? } {
+            return Ok(());
+        }
+        let stages = [
+            self.occlusion_shader.shader_info,
+            self.blur_shader.shader_info,
+        ];
+        let pipelines =
+            create_ambient_occlusion_pipelines(vk_device, &stages, self.pipeline_layout)?;
+        let old = [
+            std::mem::replace(&mut self.occlusion_pipeline, pipelines[0]),
+            std::mem::replace(&mut self.blur_pipeline, pipelines[1]),
+        ];
+        for pipeline in old {
+            unsafe { vk_device.device.destroy_pipeline(pipeline, None) };
+        }
+        Ok(())
+    }
+
+    /// # Safety
+    /// The gpu must not be using the pass
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            self.destroy_images(vk_device);
+            vk_device.device.destroy_sampler(self.sampler, None);
+            vk_device.device.destroy_pipeline(self.blur_pipeline, None);
+            vk_device
+                .device
+                .destroy_pipeline(self.occlusion_pipeline, None);
+            vk_device
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            vk_device
+                .device
+                .destroy_descriptor_set_layout(self.descriptor_layout, None);
+            self.blur_shader.destroy(vk_device);
+            self.occlusion_shader.destroy(vk_device);
+        }
+    }
+}
+
+/// One dispatch of the pass, in the order they are recorded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AmbientOcclusionStep {
+    /// depth into the occlusion image
+    Occlusion,
+    /// the occlusion image into the blurred image
+    Blur,
+}
+
+impl AmbientOcclusionStep {
+    pub fn name(self) -> &'static CStr {
+        match self {
+            Self::Occlusion => c"Ambient Occlusion",
+            Self::Blur => c"Ambient Occlusion Blur",
+        }
+    }
+}
+
+// bindings of the set in ssao.slang, depth, the occlusion image written then read, the blurred image
+const DESCRIPTOR_TYPES: [vk::DescriptorType; 4] = [
+    vk::DescriptorType::SAMPLED_IMAGE,
+    vk::DescriptorType::STORAGE_IMAGE,
+    vk::DescriptorType::SAMPLED_IMAGE,
+    vk::DescriptorType::STORAGE_IMAGE,
+];
+
+// stages are the occlusion then the blur compute stage
+fn create_ambient_occlusion_pipelines(
+    vk_device: &VKDevice,
+    stages: &[vk::PipelineShaderStageCreateInfo; 2],
+    pipeline_layout: vk::PipelineLayout,
+) -> Result<[vk::Pipeline; 2], vk::Result> {
+    let create_infos = stages.map(|stage| {
+        vk::ComputePipelineCreateInfo::default()
+            .stage(stage)
+            .layout(pipeline_layout)
+    });
+    let pipelines = unsafe {
+        vk_device
+            .device
+            .create_compute_pipelines(vk_device.pipeline_cache.cache, &create_infos, None)
+            .map_err(|(_, error)| error)?
+    };
+    Ok([pipelines[0], pipelines[1]])
+}
+
+#[test]
+fn ambient_occlusion_constants_test() {
+    use crate::camera::Camera;
+
+    let extent = vk::Extent2D {
+        width: 320,
+        height: 180,
+    };
+    let depth_convention = DepthConvention::ReverseZ;
+    let projection = Camera::perspective(60.0_f32.to_radians(), 0.1)
+        .uniform(extent.width as f32 / extent.height as f32)
+        .with_clip_transform(depth_convention.clip_transform())
+        .projection;
+    let constants = AmbientOcclusionConstants::new(
+        &AmbientOcclusionSettings::default(),
+        projection,
+        extent,
+        depth_convention,
+    );
+    assert_eq!(constants.far_depth, 0.0);
+
+    // a point projected onto the depth image comes back where it was
+    let uv_depth = |point: Vec3| {
+        let clip = projection * point.extend(1.0);
+        let ndc = clip.xyz() / clip.w;
+        ((ndc.truncate() + 1.0) * 0.5, ndc.z, clip.w)
+    };
+    let point = Vec3::new(0.5, -0.25, -4.0);
+    let (uv, depth, clip_w) = uv_depth(point);
+    assert!(constants.view_position(uv, depth).abs_diff_eq(point, 1e-3));
+
+    // one unit up covers pixel_scale over clip w pixels
+    let (above, _, _) = uv_depth(point + Vec3::Y);
+    let pixels = (above - uv).length() * extent.height as f32;
+    assert!((pixels - constants.pixel_scale / clip_w).abs() < 0.01);
+}
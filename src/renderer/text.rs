@@ -0,0 +1,709 @@
+use ab_glyph::{Font as _, FontVec, GlyphId, PxScale, point};
+use ash::vk;
+use glam::Vec2;
+use log::warn;
+use rustybuzz::{Direction, Face, Language, UnicodeBuffer};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+use unicode_bidi::{BidiInfo, Level};
+
+use crate::color::LinearRgba;
+use crate::renderer::renderer2d::{Sprite, SpriteBlend, SpriteTextureId, UvRect, WHITE_TEXTURE};
+
+/// Size of a font's glyph atlas unless Font::with_atlas_size picks another
+pub const DEFAULT_GLYPH_ATLAS_SIZE: u32 = 1024;
+
+/// Format atlases are uploaded in, coverage is linear so an srgb atlas would darken subpixel edges
+pub const GLYPH_ATLAS_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+/// Gamma TextRendering::for_format picks for targets that blend in linear light
+pub const DEFAULT_TEXT_GAMMA: f32 = 1.8;
+
+// empty texels between glyphs so filtering never picks up a neighbour
+const GLYPH_PADDING: u32 = 1;
+
+// freetype's default lcd filter, spreads each subpixel into its neighbours so colour fringes stay faint
+const LCD_FILTER: [f32; 5] = [
+    8.0 / 256.0,
+    77.0 / 256.0,
+    86.0 / 256.0,
+    77.0 / 256.0,
+    8.0 / 256.0,
+];
+
+#[derive(Debug, Error)]
+pub enum FontError {
+    #[error("failed to read the font: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("not a truetype or opentype font")]
+    Invalid,
+}
+
+/// Direction a paragraph reads in, runs of the other direction inside it are still reordered
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextDirection {
+    /// from the first letter that has a direction, left to right when none do
+    #[default]
+    Auto,
+    LeftToRight,
+    RightToLeft,
+}
+
+/// How Font::layout shapes and draws text
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextStyle {
+    /// em size in pixels
+    pub size: f32,
+    pub color: LinearRgba,
+    pub layer: i32,
+    /// BCP 47 tag like "sr" or "ar-EG" picking the font's localised forms, None goes by script
+    pub language: Option<String>,
+    pub direction: TextDirection,
+    /// multiplies the font's line height
+    pub line_spacing: f32,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            size: 16.0,
+            color: LinearRgba::WHITE,
+            layer: 0,
+            language: None,
+            direction: TextDirection::Auto,
+            line_spacing: 1.0,
+        }
+    }
+}
+
+impl TextStyle {
+    pub fn new(size: f32) -> Self {
+        Self {
+            size,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_color(mut self, color: LinearRgba) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn with_language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_string());
+        self
+    }
+
+    pub fn with_direction(mut self, direction: TextDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn with_line_spacing(mut self, line_spacing: f32) -> Self {
+        self.line_spacing = line_spacing;
+        self
+    }
+}
+
+/// Order of the display's subpixels from left to right, for LCD subpixel text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SubpixelOrder {
+    /// greyscale coverage, for rotated, scaled and non LCD displays
+    #[default]
+    None,
+    Rgb,
+    Bgr,
+}
+
+/// How a font's glyphs are rasterized for the target its text is drawn in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextRendering {
+    /// coverage is raised to 1 / gamma, above 1 keeps stems from thinning when blended in linear light
+    pub gamma: f32,
+    /// subpixel text is drawn in two passes and ignores sprite alpha under it
+    pub subpixel: SubpixelOrder,
+}
+
+impl Default for TextRendering {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            subpixel: SubpixelOrder::None,
+        }
+    }
+}
+
+impl TextRendering {
+    /// Greyscale text suited to a target of format, srgb and float targets blend in linear light
+    /// which thins antialiased edges, unorm targets blend the encoded values and are left as is
+    pub fn for_format(format: vk::Format) -> Self {
+        let linear = matches!(
+            format,
+            vk::Format::R8G8B8A8_SRGB
+                | vk::Format::B8G8R8A8_SRGB
+                | vk::Format::A8B8G8R8_SRGB_PACK32
+                | vk::Format::R16G16B16A16_SFLOAT
+                | vk::Format::R32G32B32A32_SFLOAT
+                | vk::Format::B10G11R11_UFLOAT_PACK32
+        );
+        Self {
+            gamma: if linear { DEFAULT_TEXT_GAMMA } else { 1.0 },
+            ..Default::default()
+        }
+    }
+
+    pub fn with_gamma(mut self, gamma: f32) -> Self {
+        self.gamma = gamma;
+        self
+    }
+
+    pub fn with_subpixel(mut self, subpixel: SubpixelOrder) -> Self {
+        self.subpixel = subpixel;
+        self
+    }
+
+    /// Coverage from the rasterizer corrected by gamma
+    pub fn coverage(&self, coverage: f32) -> f32 {
+        coverage
+            .clamp(0.0, 1.0)
+            .powf(self.gamma.max(f32::EPSILON).recip())
+    }
+}
+
+/// A glyph placed by shaping, in pixels from the start of its line on the baseline
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShapedGlyph {
+    pub glyph: u16,
+    pub position: Vec2,
+    /// byte index in the line of the first character the glyph draws
+    pub cluster: usize,
+}
+
+/// One line of shaped glyphs in the order they're drawn, left to right
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShapedLine {
+    pub glyphs: Vec<ShapedGlyph>,
+    pub width: f32,
+    /// the paragraph reads right to left, right aligned text should line up with its end
+    pub right_to_left: bool,
+}
+
+/// Byte ranges of line in the order they're drawn and whether each reads right to left
+/// also returns whether the paragraph as a whole reads right to left
+pub fn visual_runs(line: &str, direction: TextDirection) -> (Vec<(Range<usize>, bool)>, bool) {
+    let level = match direction {
+        TextDirection::Auto => None,
+        TextDirection::LeftToRight => Some(Level::ltr()),
+        TextDirection::RightToLeft => Some(Level::rtl()),
+    };
+    let bidi = BidiInfo::new(line, level);
+    let Some(paragraph) = bidi.paragraphs.first() else {
+        return (Vec::new(), direction == TextDirection::RightToLeft);
+    };
+    let (levels, runs) = bidi.visual_runs(paragraph, paragraph.range.clone());
+    let runs = runs
+        .into_iter()
+        .map(|run| {
+            let right_to_left = levels[run.start].is_rtl();
+            (run, right_to_left)
+        })
+        .collect();
+    (runs, paragraph.level.is_rtl())
+}
+
+// filters a bitmap with three samples per pixel across into rgb coverage per pixel
+// first_sample is the bitmap's left edge in samples, returns the left edge in pixels and the width
+// with the filter's spread on both sides included
+fn filter_subpixels(
+    samples: &[f32],
+    sample_width: usize,
+    first_sample: i32,
+) -> (i32, usize, Vec<[f32; 3]>) {
+    let spread = (LCD_FILTER.len() / 2) as i32;
+    let first_pixel = (first_sample - spread).div_euclid(3);
+    let last_pixel = (first_sample + sample_width as i32 - 1 + spread).div_euclid(3);
+    let width = (last_pixel - first_pixel + 1) as usize;
+    let height = samples.len() / sample_width.max(1);
+
+    let mut pixels = vec![[0.0; 3]; width * height];
+    for (row, filtered) in samples
+        .chunks_exact(sample_width.max(1))
+        .zip(pixels.chunks_exact_mut(width))
+    {
+        let sample = |index: i32| usize::try_from(index).ok().and_then(|index| row.get(index));
+        for (x, pixel) in filtered.iter_mut().enumerate() {
+            for (channel, coverage) in pixel.iter_mut().enumerate() {
+                let center = (first_pixel + x as i32) * 3 + channel as i32 - first_sample;
+                *coverage = LCD_FILTER
+                    .iter()
+                    .zip(center - spread..)
+                    .filter_map(|(weight, index)| sample(index).map(|sample| weight * sample))
+                    .sum();
+            }
+        }
+    }
+    (first_pixel, width, pixels)
+}
+
+// where a rasterized glyph sits in the atlas
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct AtlasGlyph {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    /// top left of the bitmap from the pen position on the baseline
+    offset: Vec2,
+}
+
+/// Glyph bitmaps packed in rows, white with the coverage in alpha like the debug font
+/// subpixel glyphs hold the coverage of each subpixel in rgb instead
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlyphAtlas {
+    pub width: u32,
+    pub height: u32,
+    /// 8bit RGBA
+    pub pixels: Vec<u8>,
+    /// pixels changed since they were last uploaded
+    pub dirty: bool,
+    // next free spot in the current row and the row's height
+    cursor: (u32, u32),
+    row_height: u32,
+}
+
+impl GlyphAtlas {
+    pub fn new(width: u32, height: u32) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        Self {
+            width,
+            height,
+            pixels: vec![0; (width * height) as usize * 4],
+            dirty: true,
+            cursor: (0, 0),
+            row_height: 0,
+        }
+    }
+
+    /// Finds room for a width x height bitmap, None once the atlas is full
+    pub fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (padded_width, padded_height) = (width + GLYPH_PADDING, height + GLYPH_PADDING);
+        if self.cursor.0 + padded_width > self.width {
+            self.cursor = (0, self.cursor.1 + self.row_height);
+            self.row_height = 0;
+        }
+        if self.cursor.0 + padded_width > self.width || self.cursor.1 + padded_height > self.height
+        {
+            return None;
+        }
+        let spot = self.cursor;
+        self.cursor.0 += padded_width;
+        self.row_height = self.row_height.max(padded_height);
+        Some(spot)
+    }
+
+    /// Forgets every glyph, they are rasterized again as they're laid out
+    pub fn clear(&mut self) {
+        self.pixels.fill(0);
+        self.cursor = (0, 0);
+        self.row_height = 0;
+        self.dirty = true;
+    }
+
+    fn set_coverage(&mut self, x: u32, y: u32, coverage: f32) {
+        if x < self.width && y < self.height {
+            let texel = (y * self.width + x) as usize * 4;
+            let alpha = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+            self.pixels[texel..texel + 4].copy_from_slice(&[255, 255, 255, alpha]);
+        }
+    }
+
+    // alpha is the average so what's under the glyph is covered about as much as in greyscale
+    fn set_subpixel_coverage(&mut self, x: u32, y: u32, coverage: [f32; 3]) {
+        if x < self.width && y < self.height {
+            let texel = (y * self.width + x) as usize * 4;
+            let alpha = coverage.iter().sum::<f32>() / 3.0;
+            let [r, g, b, a] = [coverage[0], coverage[1], coverage[2], alpha]
+                .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+            self.pixels[texel..texel + 4].copy_from_slice(&[r, g, b, a]);
+        }
+    }
+
+    fn uv(&self, glyph: &AtlasGlyph) -> UvRect {
+        let size = Vec2::new(self.width as f32, self.height as f32);
+        UvRect::new(
+            Vec2::new(glyph.x as f32, glyph.y as f32) / size,
+            Vec2::new(
+                (glyph.x + glyph.width) as f32,
+                (glyph.y + glyph.height) as f32,
+            ) / size,
+        )
+    }
+}
+
+/// A truetype or opentype font shaped with rustybuzz, so ligatures, joined scripts like arabic and
+/// mixed left to right and right to left text come out right, drawn through renderer2d
+/// glyphs are rasterized into the atlas the first time they are laid out at a size, as the font's
+/// TextRendering says, VKRenderer::load_font picks one for the scene's format
+/// Example Use:
+/// ```ignore
+/// let mut font = renderer.load_font("fonts/NotoSansArabic-Regular.ttf")?;
+/// let style = TextStyle::new(24.0).with_language("ar");
+/// let sprites = font.layout("مرحبا بالعالم", Vec2::new(16.0, 16.0), &style);
+/// renderer.sprites.extend(sprites);
+/// // new glyphs have to reach the gpu before the sprites are drawn
+/// renderer.upload_glyphs(&mut font)?;
+/// ```
+pub struct Font {
+    font: FontVec,
+    pub atlas: GlyphAtlas,
+    /// renderer2d texture holding the atlas, see VKRenderer::add_font
+    pub texture: SpriteTextureId,
+    glyphs: HashMap<(u16, u32), Option<AtlasGlyph>>,
+    rendering: TextRendering,
+}
+
+impl Font {
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, FontError> {
+        let font = FontVec::try_from_vec(data).map_err(|_| FontError::Invalid)?;
+        // rustybuzz reads the same data, make sure it can before anything is laid out
+        Face::from_slice(font.as_slice(), 0).ok_or(FontError::Invalid)?;
+        Ok(Self {
+            font,
+            atlas: GlyphAtlas::new(DEFAULT_GLYPH_ATLAS_SIZE, DEFAULT_GLYPH_ATLAS_SIZE),
+            texture: WHITE_TEXTURE,
+            glyphs: HashMap::new(),
+            rendering: TextRendering::default(),
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, FontError> {
+        Self::from_bytes(std::fs::read(path)?)
+    }
+
+    /// Replaces the atlas with an empty one of width x height, for large sizes or many scripts
+    pub fn with_atlas_size(mut self, width: u32, height: u32) -> Self {
+        self.atlas = GlyphAtlas::new(width, height);
+        self.glyphs.clear();
+        self
+    }
+
+    pub fn with_rendering(mut self, rendering: TextRendering) -> Self {
+        self.set_rendering(rendering);
+        self
+    }
+
+    pub fn rendering(&self) -> TextRendering {
+        self.rendering
+    }
+
+    /// Changes how glyphs are rasterized, the ones already in the atlas are rasterized again
+    pub fn set_rendering(&mut self, rendering: TextRendering) {
+        if rendering != self.rendering {
+            self.rendering = rendering;
+            self.clear_glyphs();
+        }
+    }
+
+    fn units_per_em(&self) -> f32 {
+        self.font.units_per_em().unwrap_or(1000.0)
+    }
+
+    // ab_glyph scales by ascent minus descent rather than the em
+    fn px_scale(&self, size: f32) -> PxScale {
+        PxScale::from(size * self.font.height_unscaled() / self.units_per_em())
+    }
+
+    /// Distance from the top of a line to its baseline at size
+    pub fn ascent(&self, size: f32) -> f32 {
+        self.font.ascent_unscaled() * size / self.units_per_em()
+    }
+
+    /// Distance between the baselines of two lines at size, before TextStyle::line_spacing
+    pub fn line_height(&self, size: f32) -> f32 {
+        (self.font.height_unscaled() + self.font.line_gap_unscaled()) * size / self.units_per_em()
+    }
+
+    /// Shapes one line without drawing it, bidirectional text is reordered first
+    pub fn shape_line(&self, line: &str, style: &TextStyle) -> ShapedLine {
+        let (runs, right_to_left) = visual_runs(line, style.direction);
+        let mut shaped = ShapedLine {
+            right_to_left,
+            ..Default::default()
+        };
+        let Some(face) = Face::from_slice(self.font.as_slice(), 0) else {
+            return shaped;
+        };
+        let language = style
+            .language
+            .as_deref()
+            .and_then(|language| Language::from_str(language).ok());
+        let scale = style.size / self.units_per_em();
+
+        let mut pen = 0.0;
+        for (run, run_right_to_left) in runs {
+            let mut buffer = UnicodeBuffer::new();
+            buffer.push_str(&line[run.clone()]);
+            buffer.set_direction(if run_right_to_left {
+                Direction::RightToLeft
+            } else {
+                Direction::LeftToRight
+            });
+            if let Some(language) = &language {
+                buffer.set_language(language.clone());
+            }
+            buffer.guess_segment_properties();
+
+            // right to left runs come back in the order they're drawn
+            let glyphs = rustybuzz::shape(&face, &[], buffer);
+            for (info, position) in glyphs.glyph_infos().iter().zip(glyphs.glyph_positions()) {
+                shaped.glyphs.push(ShapedGlyph {
+                    glyph: info.glyph_id as u16,
+                    position: Vec2::new(
+                        pen + position.x_offset as f32 * scale,
+                        -position.y_offset as f32 * scale,
+                    ),
+                    cluster: run.start + info.cluster as usize,
+                });
+                pen += position.x_advance as f32 * scale;
+            }
+        }
+        shaped.width = pen;
+        shaped
+    }
+
+    /// Size text takes up when laid out with style, as wide as its longest line
+    pub fn measure(&self, text: &str, style: &TextStyle) -> Vec2 {
+        let width = text
+            .lines()
+            .map(|line| self.shape_line(line, style).width)
+            .fold(0.0, f32::max);
+        let lines = text.lines().count() as f32;
+        Vec2::new(
+            width,
+            lines * self.line_height(style.size) * style.line_spacing,
+        )
+    }
+
+    /// Sprites drawing text with its top left at position, '\n' starts a new line
+    /// lines that read right to left are drawn from position too, measure them to align them
+    /// subpixel text has two sprites a glyph, a Coverage one darkening what's under it and a
+    /// Premultiplied one adding the colour, the coverage sprites all come first
+    pub fn layout(&mut self, text: &str, position: Vec2, style: &TextStyle) -> Vec<Sprite> {
+        let ascent = self.ascent(style.size);
+        let line_height = self.line_height(style.size) * style.line_spacing;
+        let mut sprites = Vec::with_capacity(text.len());
+        let mut colors = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let baseline = position + Vec2::new(0.0, ascent + index as f32 * line_height);
+            for glyph in self.shape_line(line, style).glyphs {
+                let Some(placed) = self.rasterize(glyph.glyph, style.size) else {
+                    continue;
+                };
+                // whole pixels so the bitmap is sampled texel for pixel
+                let origin = (baseline + glyph.position).round();
+                let sprite = Sprite::new(
+                    self.texture,
+                    origin + placed.offset,
+                    Vec2::new(placed.width as f32, placed.height as f32),
+                )
+                .with_uv(self.atlas.uv(&placed))
+                .with_layer(style.layer);
+
+                if self.rendering.subpixel == SubpixelOrder::None {
+                    sprites.push(sprite.with_color(style.color));
+                    continue;
+                }
+                let LinearRgba { r, g, b, a } = style.color;
+                sprites.push(
+                    sprite
+                        .with_color(LinearRgba::new(a, a, a, a))
+                        .with_blend(SpriteBlend::Coverage),
+                );
+                colors.push(
+                    sprite
+                        .with_color(LinearRgba::new(r * a, g * a, b * a, 0.0))
+                        .with_blend(SpriteBlend::Premultiplied),
+                );
+            }
+        }
+        sprites.append(&mut colors);
+        sprites
+    }
+
+    // blank glyphs like spaces are None, so are glyphs that no longer fit in the atlas
+    fn rasterize(&mut self, glyph: u16, size: f32) -> Option<AtlasGlyph> {
+        let key = (glyph, size.to_bits());
+        if let Some(placed) = self.glyphs.get(&key) {
+            return *placed;
+        }
+        let rendering = self.rendering;
+        let subpixel = rendering.subpixel != SubpixelOrder::None;
+        let mut scale = self.px_scale(size);
+        // subpixel glyphs are outlined three times as wide, a sample for each subpixel
+        if subpixel {
+            scale.x *= 3.0;
+        }
+        let outlined = self
+            .font
+            .outline_glyph(GlyphId(glyph).with_scale_and_position(scale, point(0.0, 0.0)));
+        let atlas = &mut self.atlas;
+        let placed = outlined.and_then(|outlined| {
+            let bounds = outlined.px_bounds();
+            let (sample_width, height) = (bounds.width() as usize, bounds.height() as u32);
+            let mut samples = vec![0.0; sample_width * height as usize];
+            outlined.draw(|x, y, coverage| {
+                samples[y as usize * sample_width + x as usize] = coverage;
+            });
+
+            let (left, width, pixels) = if subpixel {
+                filter_subpixels(&samples, sample_width, bounds.min.x as i32)
+            } else {
+                let pixels = samples.iter().map(|&coverage| [coverage; 3]).collect();
+                (bounds.min.x as i32, sample_width, pixels)
+            };
+            let Some((x, y)) = atlas.allocate(width as u32, height) else {
+                warn!("Glyph Atlas Full, Glyph {glyph} at {size}px Skipped");
+                return None;
+            };
+            for (index, pixel) in pixels.iter().enumerate() {
+                let [red, green, blue] = pixel.map(|coverage| rendering.coverage(coverage));
+                let (texel_x, texel_y) = (x + (index % width) as u32, y + (index / width) as u32);
+                match rendering.subpixel {
+                    SubpixelOrder::None => atlas.set_coverage(texel_x, texel_y, red),
+                    SubpixelOrder::Rgb => {
+                        atlas.set_subpixel_coverage(texel_x, texel_y, [red, green, blue])
+                    }
+                    SubpixelOrder::Bgr => {
+                        atlas.set_subpixel_coverage(texel_x, texel_y, [blue, green, red])
+                    }
+                }
+            }
+            atlas.dirty = true;
+            Some(AtlasGlyph {
+                x,
+                y,
+                width: width as u32,
+                height,
+                offset: Vec2::new(left as f32, bounds.min.y),
+            })
+        });
+        self.glyphs.insert(key, placed);
+        placed
+    }
+
+    /// Forgets every rasterized glyph, for when the sizes in use have changed
+    pub fn clear_glyphs(&mut self) {
+        self.atlas.clear();
+        self.glyphs.clear();
+    }
+}
+
+#[test]
+fn visual_runs_test() {
+    // hebrew inside an english sentence is drawn right to left in place
+    let line = "abc אבג def";
+    let (runs, right_to_left) = visual_runs(line, TextDirection::Auto);
+    assert!(!right_to_left);
+    let hebrew = line.find('א').unwrap();
+    assert_eq!(
+        runs,
+        [
+            (0..hebrew, false),
+            (hebrew..hebrew + "אבג".len(), true),
+            (hebrew + "אבג".len()..line.len(), false),
+        ]
+    );
+
+    // a hebrew paragraph draws its english run to the left of the hebrew before it
+    let line = "אבג abc";
+    let (runs, right_to_left) = visual_runs(line, TextDirection::Auto);
+    assert!(right_to_left);
+    assert!(!runs.first().unwrap().1);
+    assert_eq!(runs.last().unwrap(), &(0.."אבג ".len(), true));
+
+    // a forced direction wins over the first letter
+    assert!(visual_runs("abc", TextDirection::RightToLeft).1);
+    assert_eq!(visual_runs("", TextDirection::Auto), (Vec::new(), false));
+}
+
+#[test]
+fn glyph_atlas_test() {
+    let mut atlas = GlyphAtlas::new(16, 8);
+    // rows fill left to right with a texel between glyphs
+    assert_eq!(atlas.allocate(5, 3), Some((0, 0)));
+    assert_eq!(atlas.allocate(5, 2), Some((6, 0)));
+    // too wide for the rest of the row, starts the next one under the tallest glyph
+    assert_eq!(atlas.allocate(5, 3), Some((0, 4)));
+    assert_eq!(atlas.allocate(15, 1), None);
+    assert_eq!(atlas.allocate(4, 5), None);
+
+    atlas.set_coverage(1, 1, 0.5);
+    assert_eq!(
+        &atlas.pixels[(16 + 1) * 4..(16 + 2) * 4],
+        &[255, 255, 255, 128]
+    );
+    let uv = atlas.uv(&AtlasGlyph {
+        x: 4,
+        y: 2,
+        width: 8,
+        height: 4,
+        offset: Vec2::ZERO,
+    });
+    assert_eq!(
+        uv,
+        UvRect::new(Vec2::new(0.25, 0.25), Vec2::new(0.75, 0.75))
+    );
+
+    atlas.clear();
+    assert_eq!(atlas.allocate(15, 7), Some((0, 0)));
+    assert!(atlas.pixels.iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn text_rendering_test() {
+    // srgb and hdr targets blend in linear light and get their edges thickened, unorm ones don't
+    let srgb = TextRendering::for_format(vk::Format::B8G8R8A8_SRGB);
+    assert_eq!(srgb.gamma, DEFAULT_TEXT_GAMMA);
+    assert_eq!(srgb.subpixel, SubpixelOrder::None);
+    let hdr = TextRendering::for_format(vk::Format::R16G16B16A16_SFLOAT);
+    assert_eq!(hdr.gamma, DEFAULT_TEXT_GAMMA);
+    let unorm = TextRendering::for_format(vk::Format::B8G8R8A8_UNORM);
+    assert_eq!(unorm, TextRendering::default());
+
+    // full and empty coverage stay put, partial coverage grows
+    assert_eq!(srgb.coverage(0.0), 0.0);
+    assert_eq!(srgb.coverage(1.0), 1.0);
+    assert!(srgb.coverage(0.25) > 0.25);
+    assert_eq!(unorm.coverage(0.25), 0.25);
+    assert_eq!(unorm.coverage(2.0), 1.0);
+}
+
+#[test]
+fn subpixel_filter_test() {
+    // one covered sample, the green subpixel of pixel 4, in a bitmap starting at sample 12
+    let samples = [0.0, 1.0, 0.0];
+    let (left, width, pixels) = filter_subpixels(&samples, 3, 12);
+    // the filter spreads two samples each way, reaching into pixels 3 and 5
+    assert_eq!((left, width), (3, 3));
+    assert_eq!(pixels[0], [0.0, 0.0, 8.0 / 256.0]);
+    assert_eq!(pixels[1], [77.0 / 256.0, 86.0 / 256.0, 77.0 / 256.0]);
+    assert_eq!(pixels[2], [8.0 / 256.0, 0.0, 0.0]);
+
+    // the filter keeps the total coverage, so text is as heavy as it is in greyscale
+    let samples = [0.5, 1.0, 1.0, 0.25, 0.0, 0.75, 1.0];
+    let (_, _, pixels) = filter_subpixels(&samples, samples.len(), 1);
+    let total: f32 = pixels.iter().flatten().sum();
+    assert!((total - samples.iter().sum::<f32>()).abs() < 1e-5);
+
+    let mut atlas = GlyphAtlas::new(2, 1);
+    atlas.set_subpixel_coverage(1, 0, [1.0, 0.5, 0.0]);
+    assert_eq!(&atlas.pixels[4..], &[255, 128, 0, 128]);
+}
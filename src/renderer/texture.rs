@@ -0,0 +1,277 @@
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use std::error;
+use std::path::Path;
+
+use crate::renderer::buffer::VKBuffer;
+use crate::renderer::device::{MemoryPriority, VKDevice};
+use crate::renderer::image::VKImage;
+use crate::renderer::render_graph::Access;
+use crate::renderer::submit_one_time;
+
+// textures are stored as srgb, sampling converts them to linear for the shader
+pub const TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+// normal maps hold directions not colours, so they are sampled as they are
+pub const NORMAL_MAP_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+/// A sampled 2d image with its own sampler
+/// bound to the fragment shader as a combined image sampler
+pub struct VKTexture {
+    /// left in SHADER_READ_ONLY_OPTIMAL once uploaded
+    pub image: VKImage,
+    pub sampler: vk::Sampler,
+}
+
+impl VKTexture {
+    /// Loads a PNG or JPEG and uploads it to the gpu
+    /// Example Use:
+    /// ```ignore
+    /// let texture = VKTexture::from_file(&mut vk_device, cmd_pool, "textures/crate.png")?;
+    /// ```
+    pub fn from_file(
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        Self::from_file_with_format(vk_device, vk_command_pool, path, TEXTURE_FORMAT)
+    }
+
+    /// Loads a tangent space normal map, see NORMAL_MAP_FORMAT
+    pub fn normal_map_from_file(
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        Self::from_file_with_format(vk_device, vk_command_pool, path, NORMAL_MAP_FORMAT)
+    }
+
+    /// Loads any image as 8bit RGBA in format, e.g. NORMAL_MAP_FORMAT for data that isn't colour
+    pub fn from_file_with_format(
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+        path: impl AsRef<Path>,
+        format: vk::Format,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let image = image::open(path)?.into_rgba8();
+        let (width, height) = image.dimensions();
+        Ok(Self::from_rgba8_with_format(
+            vk_device,
+            vk_command_pool,
+            width,
+            height,
+            image.as_raw(),
+            format,
+        )?)
+    }
+
+    /// Uploads tightly packed 8bit RGBA pixels through a staging buffer
+    pub fn from_rgba8(
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<Self, vk::Result> {
+        Self::from_rgba8_with_format(
+            vk_device,
+            vk_command_pool,
+            width,
+            height,
+            pixels,
+            TEXTURE_FORMAT,
+        )
+    }
+
+    /// Same as from_rgba8 but for any 4 byte per pixel format
+    pub fn from_rgba8_with_format(
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        format: vk::Format,
+    ) -> Result<Self, vk::Result> {
+        if width == 0 || height == 0 || pixels.len() != width as usize * height as usize * 4 {
+            return Err(vk::Result::ERROR_INITIALIZATION_FAILED);
+        }
+
+        let extent = vk::Extent2D { width, height };
+
+        let mut staging = VKBuffer::new(
+            vk_device,
+            pixels.len() as u64,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+            "Texture Staging",
+        )?;
+
+        if let Err(error) = staging.write(0, pixels) {
+            unsafe { staging.destroy(vk_device) };
+            return Err(error);
+        }
+
+        let mut image = match VKImage::new(
+            vk_device,
+            extent,
+            format,
+            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+            vk::SampleCountFlags::TYPE_1,
+            vk::ImageAspectFlags::COLOR,
+        ) {
+            Ok(image) => image,
+            Err(error) => {
+                unsafe { staging.destroy(vk_device) };
+                return Err(error);
+            }
+        };
+
+        let upload_result = submit_one_time(vk_device, vk_command_pool, |cmd_buffer| unsafe {
+            record_upload(vk_device, cmd_buffer, staging.buffer, &mut image);
+        });
+
+        // upload has finished or failed, either way the staging buffer is done with
+        unsafe { staging.destroy(vk_device) };
+
+        if let Err(error) = upload_result {
+            unsafe { image.destroy(vk_device) };
+            return Err(error);
+        }
+
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::REPEAT)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT)
+            .max_lod(vk::LOD_CLAMP_NONE);
+
+        let sampler = match unsafe { vk_device.device.create_sampler(&sampler_info, None) } {
+            Ok(sampler) => sampler,
+            Err(error) => {
+                unsafe { image.destroy(vk_device) };
+                return Err(error);
+            }
+        };
+
+        Ok(Self { image, sampler })
+    }
+
+    /// Black and white checkerboard, useful as a placeholder
+    pub fn checkerboard(
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+        size: u32,
+        squares: u32,
+    ) -> Result<Self, vk::Result> {
+        let pixels = checkerboard_pixels(size, squares);
+        Self::from_rgba8(vk_device, vk_command_pool, size, size, &pixels)
+    }
+
+    /// Single texel normal map pointing straight out of the surface
+    pub fn flat_normal_map(
+        vk_device: &mut VKDevice,
+        vk_command_pool: vk::CommandPool,
+    ) -> Result<Self, vk::Result> {
+        Self::from_rgba8_with_format(
+            vk_device,
+            vk_command_pool,
+            1,
+            1,
+            &[128, 128, 255, 255],
+            NORMAL_MAP_FORMAT,
+        )
+    }
+
+    /// Descriptor info for writing this texture into a combined image sampler binding
+    pub fn descriptor_image_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo::default()
+            .sampler(self.sampler)
+            .image_view(self.image.view)
+            .image_layout(self.image.layout())
+    }
+
+    /// Hints whether this texture should leave vram before others, Low suits streamed mips
+    /// Example Use:
+    /// ```ignore
+    /// let far_mips = VKTexture::from_file(&mut vk_device, cmd_pool, "terrain/far.png")?;
+    /// far_mips.set_memory_priority(&vk_device, MemoryPriority::Low);
+    /// ```
+    pub fn set_memory_priority(&self, vk_device: &VKDevice, priority: MemoryPriority) {
+        vk_device.set_memory_priority(&self.image.allocation, priority);
+    }
+
+    /// # Safety
+    /// Texture must not be in use by the gpu
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            vk_device.device.destroy_sampler(self.sampler, None);
+            self.image.destroy(vk_device);
+        }
+    }
+}
+
+// UNDEFINED -> TRANSFER_DST, copy, TRANSFER_DST -> SHADER_READ_ONLY
+unsafe fn record_upload(
+    vk_device: &VKDevice,
+    cmd_buffer: vk::CommandBuffer,
+    staging_buffer: vk::Buffer,
+    image: &mut VKImage,
+) {
+    // buffer is tightly packed so row length and image height are left at 0
+    let copy_region = vk::BufferImageCopy::default()
+        .image_subresource(
+            vk::ImageSubresourceLayers::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .layer_count(1),
+        )
+        .image_extent(vk::Extent3D {
+            width: image.extent.width,
+            height: image.extent.height,
+            depth: 1,
+        });
+
+    unsafe {
+        image.cmd_transition(vk_device, cmd_buffer, Access::TransferDst);
+
+        vk_device.device.cmd_copy_buffer_to_image(
+            cmd_buffer,
+            staging_buffer,
+            image.image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[copy_region],
+        );
+
+        image.cmd_transition(vk_device, cmd_buffer, Access::FragmentSampled);
+    }
+}
+
+fn checkerboard_pixels(size: u32, squares: u32) -> Vec<u8> {
+    let square_size = (size / squares.max(1)).max(1);
+    let mut pixels = Vec::with_capacity(size as usize * size as usize * 4);
+    for y in 0..size {
+        for x in 0..size {
+            let value = if (x / square_size + y / square_size).is_multiple_of(2) {
+                255
+            } else {
+                40
+            };
+            pixels.extend_from_slice(&[value, value, value, 255]);
+        }
+    }
+    pixels
+}
+
+#[test]
+fn checkerboard_pixels_test() {
+    let pixels = checkerboard_pixels(4, 2);
+    assert_eq!(pixels.len(), 4 * 4 * 4);
+
+    let value = |x: usize, y: usize| pixels[(y * 4 + x) * 4];
+    assert_eq!(value(0, 0), 255);
+    assert_eq!(value(1, 1), 255);
+    assert_eq!(value(2, 0), 40);
+    assert_eq!(value(0, 2), 40);
+    assert_eq!(value(3, 3), 255);
+}
@@ -0,0 +1,270 @@
+use ash::google::display_timing;
+use ash::vk;
+use std::time::Duration;
+
+use crate::renderer::VKInstance;
+use crate::renderer::device::VKDevice;
+
+/// Statistics about frames reaching the display
+/// with VK_GOOGLE_display_timing the times come from the driver
+/// otherwise they are measured on the cpu when frames are presented
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PresentStats {
+    /// time between display refreshes if known
+    pub refresh_duration: Option<Duration>,
+    pub presented_frames: u64,
+    /// refreshes where the previous frame was shown again because a new one wasn't ready
+    pub missed_vsyncs: u64,
+    /// time between the last two presented frames
+    pub last_present_interval: Duration,
+    /// how early the last frame was ready before its refresh, always zero without display timing
+    pub last_present_margin: Duration,
+    last_present_time: Option<u64>,
+}
+
+impl PresentStats {
+    /// Records a frame shown at present_time nanoseconds
+    pub fn record_present(&mut self, present_time: u64, present_margin: u64) {
+        self.record_paced_present(present_time, present_margin, 1);
+    }
+
+    /// record_present for a frame meant to stay on screen for swap_interval refreshes
+    /// returns the vsyncs it missed, refreshes past swap_interval the previous frame stayed up
+    pub fn record_paced_present(
+        &mut self,
+        present_time: u64,
+        present_margin: u64,
+        swap_interval: u32,
+    ) -> u64 {
+        let mut missed = 0;
+        if let Some(last_present_time) = self.last_present_time
+            && present_time > last_present_time
+        {
+            let interval = present_time - last_present_time;
+            self.last_present_interval = Duration::from_nanos(interval);
+
+            if let Some(refresh_duration) = self.refresh_duration {
+                let refresh = refresh_duration.as_nanos() as u64;
+                // number of refreshes the last frame stayed on screen, rounded to absorb jitter
+                if let Some(refreshes) = (interval + refresh / 2).checked_div(refresh) {
+                    missed = refreshes.saturating_sub(swap_interval.max(1) as u64);
+                }
+            }
+        }
+
+        self.missed_vsyncs += missed;
+        self.last_present_time = Some(present_time);
+        self.last_present_margin = Duration::from_nanos(present_margin);
+        self.presented_frames += 1;
+        missed
+    }
+}
+
+/// Longest swap interval FramePacer falls back to by default
+pub const MAX_SWAP_INTERVAL: u32 = 4;
+
+// presents the pacer looks at before changing its interval
+const PACER_WINDOW: u32 = 60;
+
+/// Keeps frames on a steady cadence of swap_interval refreshes each, fed by the present statistics
+/// when frames keep missing vsync every frame stays up for another refresh instead,
+/// a steady 30 fps looks smoother than jumping between 60 and 30
+/// it comes back down once frames are ready a whole refresh early
+/// Example Use:
+/// ```
+/// use std::time::Duration;
+/// use vulkan_engine::renderer::timing::FramePacer;
+///
+/// let refresh = Duration::from_micros(16_667);
+/// let mut pacer = FramePacer::default();
+/// // frames that keep missing vsync
+/// for _ in 0..60 {
+///     pacer.record_present(true, Duration::ZERO, refresh);
+/// }
+/// assert_eq!(pacer.swap_interval, 2);
+/// assert_eq!(pacer.frame_duration(refresh), refresh * 2);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FramePacer {
+    /// refreshes each frame stays on screen
+    pub swap_interval: u32,
+    /// longest swap_interval it falls back to, 1 turns pacing off
+    pub max_swap_interval: u32,
+    presents: u32,
+    misses: u32,
+    // least time to spare of any frame this window
+    min_headroom: Option<Duration>,
+}
+
+impl Default for FramePacer {
+    fn default() -> Self {
+        Self {
+            swap_interval: 1,
+            max_swap_interval: MAX_SWAP_INTERVAL,
+            presents: 0,
+            misses: 0,
+            min_headroom: None,
+        }
+    }
+}
+
+impl FramePacer {
+    pub fn max_swap_interval(mut self, max_swap_interval: u32) -> Self {
+        self.max_swap_interval = max_swap_interval.max(1);
+        self
+    }
+
+    /// Call for each presented frame with whether it missed vsync
+    /// headroom is how long before its refresh the frame was ready
+    pub fn record_present(&mut self, missed: bool, headroom: Duration, refresh: Duration) {
+        self.presents += 1;
+        self.misses += missed as u32;
+        self.min_headroom = Some(
+            self.min_headroom
+                .map_or(headroom, |min_headroom| min_headroom.min(headroom)),
+        );
+        if self.presents < PACER_WINDOW {
+            return;
+        }
+
+        // more than one in ten frames late
+        if self.misses * 10 > self.presents {
+            self.swap_interval = (self.swap_interval + 1).min(self.max_swap_interval.max(1));
+        } else if self.misses == 0
+            && self.swap_interval > 1
+            && self
+                .min_headroom
+                .is_some_and(|headroom| headroom >= refresh)
+        {
+            self.swap_interval -= 1;
+        }
+        self.presents = 0;
+        self.misses = 0;
+        self.min_headroom = None;
+    }
+
+    /// Time each frame should stay on screen
+    pub fn frame_duration(&self, refresh: Duration) -> Duration {
+        refresh * self.swap_interval
+    }
+}
+
+/// Wrapper around VK_GOOGLE_display_timing
+pub struct VKDisplayTiming {
+    pub loader: display_timing::Device,
+    next_present_id: u32,
+    // id and actual time of the latest present the driver reported
+    last_presented: Option<(u32, u64)>,
+}
+
+impl VKDisplayTiming {
+    /// None if the extension wasn't enabled on the device
+    pub fn new(vk_instance: &VKInstance, vk_device: &VKDevice) -> Option<Self> {
+        vk_device
+            .extension_enabled(display_timing::NAME)
+            .then(|| Self {
+                loader: display_timing::Device::new(&vk_instance.instance, &vk_device.device),
+                next_present_id: 0,
+                last_presented: None,
+            })
+    }
+
+    pub fn refresh_duration(&self, swapchain: vk::SwapchainKHR) -> Result<Duration, vk::Result> {
+        let refresh_cycle = unsafe { self.loader.get_refresh_cycle_duration(swapchain)? };
+        Ok(Duration::from_nanos(refresh_cycle.refresh_duration))
+    }
+
+    /// Present time to chain onto the next present
+    /// frames go frame_duration after the last reported one, or asap when pacing isn't needed
+    pub fn next_present_time(
+        &mut self,
+        frame_duration: Duration,
+        refresh: Duration,
+    ) -> vk::PresentTimeGOOGLE {
+        self.next_present_id = self.next_present_id.wrapping_add(1);
+        let desired_present_time = match self.last_presented {
+            Some((present_id, present_time)) if frame_duration > refresh => {
+                let frames = self.next_present_id.wrapping_sub(present_id) as u64;
+                // half a refresh early so jitter doesn't push it back a whole one
+                present_time + frames * frame_duration.as_nanos() as u64
+                    - refresh.as_nanos() as u64 / 2
+            }
+            _ => 0,
+        };
+        vk::PresentTimeGOOGLE::default()
+            .present_id(self.next_present_id)
+            .desired_present_time(desired_present_time)
+    }
+
+    /// Timings for presents that completed since the last call
+    pub fn past_timings(
+        &mut self,
+        swapchain: vk::SwapchainKHR,
+    ) -> Result<Vec<vk::PastPresentationTimingGOOGLE>, vk::Result> {
+        let timings = unsafe { self.loader.get_past_presentation_timing(swapchain)? };
+        if let Some(timing) = timings.last() {
+            self.last_presented = Some((timing.present_id, timing.actual_present_time));
+        }
+        Ok(timings)
+    }
+}
+
+#[test]
+fn frame_pacer_test() {
+    let refresh = Duration::from_nanos(16_666_667);
+    let mut pacer = FramePacer::default();
+
+    // the odd late frame is fine
+    for present in 0..PACER_WINDOW {
+        pacer.record_present(present == 0, refresh / 2, refresh);
+    }
+    assert_eq!(pacer.swap_interval, 1);
+
+    for _ in 0..PACER_WINDOW {
+        pacer.record_present(true, Duration::ZERO, refresh);
+    }
+    assert_eq!(pacer.swap_interval, 2);
+
+    // on time but without a refresh to spare, dropping back would miss again
+    for _ in 0..PACER_WINDOW {
+        pacer.record_present(false, refresh / 2, refresh);
+    }
+    assert_eq!(pacer.swap_interval, 2);
+
+    for _ in 0..PACER_WINDOW {
+        pacer.record_present(false, refresh + refresh / 4, refresh);
+    }
+    assert_eq!(pacer.swap_interval, 1);
+
+    let mut stats = PresentStats {
+        refresh_duration: Some(refresh),
+        ..Default::default()
+    };
+    stats.record_paced_present(0, 0, 2);
+    // two refreshes apart is on time at a swap interval of 2
+    assert_eq!(stats.record_paced_present(33_333_334, 0, 2), 0);
+    assert_eq!(stats.missed_vsyncs, 0);
+}
+
+#[test]
+fn present_stats_test() {
+    let refresh = 16_666_667;
+    let mut stats = PresentStats {
+        refresh_duration: Some(Duration::from_nanos(refresh)),
+        ..Default::default()
+    };
+
+    stats.record_present(1_000_000, 500);
+    // on time, with a little jitter
+    stats.record_present(1_000_000 + refresh + 200_000, 0);
+    assert_eq!(stats.missed_vsyncs, 0);
+
+    // next frame took three refreshes to arrive
+    stats.record_present(1_000_000 + refresh * 4, 0);
+    assert_eq!(stats.missed_vsyncs, 2);
+    assert_eq!(stats.presented_frames, 3);
+    assert_eq!(
+        stats.last_present_interval,
+        Duration::from_nanos(refresh * 3 - 200_000)
+    );
+}
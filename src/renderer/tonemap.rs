@@ -0,0 +1,319 @@
+use ash::vk;
+use std::error;
+
+use crate::renderer::device::VKDevice;
+use crate::renderer::image::VKImage;
+use crate::renderer::pipeline::GraphicsPipelineBuilder;
+use crate::renderer::presentation::VKSwapchain;
+use crate::renderer::scaling::VKInternalTarget;
+use crate::renderer::shader::{VKShader, VKShaderLoader, reload_shaders};
+
+/// Linear brightness the tonemap starts compressing at, matches KNEE in tonemap.slang
+pub const TONEMAP_KNEE: f32 = 0.8;
+
+/// What the tonemap pass does to one channel of a linear colour, the same curve as tonemap.slang
+/// Example Use:
+/// ```
+/// use vulkan_engine::renderer::tonemap::tonemap;
+///
+/// // dim colours are untouched, bright ones are squeezed in below white
+/// assert_eq!(tonemap(0.5), 0.5);
+/// assert!(tonemap(1.5) < 1.0);
+/// ```
+pub fn tonemap(value: f32) -> f32 {
+    let value = value.max(0.0);
+    if value <= TONEMAP_KNEE {
+        return value;
+    }
+    let range = 1.0 - TONEMAP_KNEE;
+    TONEMAP_KNEE + range * (1.0 - (-(value - TONEMAP_KNEE) / range).exp())
+}
+
+/// Fullscreen pass resolving the HDR internal target into an image in the swapchain format,
+/// which retro reads and which is otherwise scaled to the window
+pub struct VKTonemapPass<'a> {
+    pub vertex_shader: VKShader<'a>,
+    pub fragment_shader: VKShader<'a>,
+    pub descriptor_layout: vk::DescriptorSetLayout,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub descriptor_pool: vk::DescriptorPool,
+    /// points at the internal target, only written while the gpu is idle
+    pub descriptor_set: vk::DescriptorSet,
+    /// swapchain format, the output is blitted onto it
+    pub format: vk::Format,
+    /// output at the internal resolution, None while there is no internal target
+    pub output: Option<VKImage>,
+}
+
+impl VKTonemapPass<'_> {
+    pub fn new(
+        vk_device: &VKDevice,
+        vk_swapchain: &VKSwapchain,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let vertex_shader = VKShader::new(
+            vk_device,
+            "shaders/tonemap.spv",
+            vk::ShaderStageFlags::VERTEX,
+            c"vertexMain",
+            vk_shader_loader,
+        )?;
+
+        let fragment_shader = VKShader::new(
+            vk_device,
+            "shaders/tonemap.spv",
+            vk::ShaderStageFlags::FRAGMENT,
+            c"fragmentMain",
+            vk_shader_loader,
+        )?;
+
+        // the scene, read a texel at a time
+        let set_bindings = [vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)];
+
+        let descriptor_layout = unsafe {
+            vk_device.device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(&set_bindings),
+                None,
+            )?
+        };
+
+        let descriptor_layouts = [descriptor_layout];
+        let pipeline_layout = unsafe {
+            vk_device.device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default().set_layouts(&descriptor_layouts),
+                None,
+            )?
+        };
+
+        let format = vk_swapchain.capibilities.ideal_surface_format().format;
+        let stages = [vertex_shader.shader_info, fragment_shader.shader_info];
+        let pipeline = create_tonemap_pipeline(vk_device, format, &stages, pipeline_layout)?;
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::SAMPLED_IMAGE,
+            descriptor_count: 1,
+        }];
+        let descriptor_pool = unsafe {
+            vk_device.device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .max_sets(1)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )?
+        };
+
+        let descriptor_set = unsafe {
+            vk_device.device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(descriptor_pool)
+                    .set_layouts(&descriptor_layouts),
+            )?[0]
+        };
+
+        Ok(Self {
+            vertex_shader,
+            fragment_shader,
+            descriptor_layout,
+            pipeline_layout,
+            pipeline,
+            descriptor_pool,
+            descriptor_set,
+            format,
+            output: None,
+        })
+    }
+
+    /// Recreates the output for target and points the descriptor at target
+    /// without a target the output is destroyed and the pass does nothing
+    /// # Safety
+    /// The gpu must not be using the pass
+    pub unsafe fn set_target(
+        &mut self,
+        vk_device: &mut VKDevice,
+        target: Option<&VKInternalTarget>,
+    ) -> Result<(), vk::Result> {
+        unsafe { self.destroy_output(vk_device) };
+
+        let Some(target) = target else {
+            return Ok(());
+        };
+
+        self.output = Some(VKImage::new(
+            vk_device,
+            target.resolution.extent,
+            self.format,
+            // sampled by retro, blitted onto the window otherwise
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::SampleCountFlags::TYPE_1,
+            vk::ImageAspectFlags::COLOR,
+        )?);
+
+        let image_info = [vk::DescriptorImageInfo::default()
+            .image_view(target.image.view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+        let writes = [vk::WriteDescriptorSet::default()
+            .dst_set(self.descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(&image_info)];
+        unsafe { vk_device.device.update_descriptor_sets(&writes, &[]) };
+
+        Ok(())
+    }
+
+    /// Output image the pass draws into, None without an internal target
+    pub fn output_image(&self) -> Option<vk::Image> {
+        self.output.as_ref().map(|output| output.image)
+    }
+
+    /// Tonemaps the internal target into the pass output, does nothing without one
+    /// the internal target must be in SHADER_READ_ONLY_OPTIMAL and the output in
+    /// COLOR_ATTACHMENT_OPTIMAL, the render graph transitions both
+    /// # Safety
+    /// cmd_buffer must be recording outside of rendering, after the scene and bloom passes
+    pub unsafe fn record(&self, vk_device: &VKDevice, cmd_buffer: vk::CommandBuffer) {
+        let Some(output) = &self.output else {
+            return;
+        };
+
+        // every pixel is written so the old contents don't matter
+        let color_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(output.view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::STORE)];
+
+        let render_area = vk::Rect2D::default().extent(output.extent);
+        let rendering_info = vk::RenderingInfo::default()
+            .color_attachments(&color_attachments)
+            .layer_count(1)
+            .render_area(render_area);
+
+        let viewport = [vk::Viewport::default()
+            .width(output.extent.width as f32)
+            .height(output.extent.height as f32)
+            .max_depth(1.0)];
+
+        unsafe {
+            vk_device
+                .device
+                .cmd_begin_rendering(cmd_buffer, &rendering_info);
+            vk_device.device.cmd_set_viewport(cmd_buffer, 0, &viewport);
+            vk_device
+                .device
+                .cmd_set_scissor(cmd_buffer, 0, &[render_area]);
+            vk_device.device.cmd_bind_pipeline(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            vk_device.device.cmd_bind_descriptor_sets(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            vk_device.device.cmd_draw(cmd_buffer, 3, 1, 0, 0);
+            vk_device.device.cmd_end_rendering(cmd_buffer);
+        }
+    }
+
+    // output image only, the pipeline stays
+    unsafe fn destroy_output(&mut self, vk_device: &mut VKDevice) {
+        if let Some(mut output) = self.output.take() {
+            unsafe { output.destroy(vk_device) };
+        }
+    }
+
+    /// Rebuilds the pipeline when one of its shaders is in changed, see VKShaderLoader::changed_shaders
+    /// # Safety
+    /// The gpu must not be using the pass
+    pub unsafe fn reload_shaders(
+        &mut self,
+        vk_device: &VKDevice,
+        vk_shader_loader: &mut VKShaderLoader<&str>,
+        changed: &[&str],
+    ) -> Result<(), Box<dyn error::Error>> {
+        let shaders = &mut [&mut self.vertex_shader, &mut self.fragment_shader];
+        if !unsafe { reload_shaders(vk_device, vk_shader_loader, shaders, changed)? } {
+            return Ok(());
+        }
+        let stages = [
+            self.vertex_shader.shader_info,
+            self.fragment_shader.shader_info,
+        ];
+        let pipeline =
+            create_tonemap_pipeline(vk_device, self.format, &stages, self.pipeline_layout)?;
+        unsafe { vk_device.device.destroy_pipeline(self.pipeline, None) };
+        self.pipeline = pipeline;
+        Ok(())
+    }
+
+    /// # Safety
+    /// The gpu must not be using the pass
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe {
+            self.destroy_output(vk_device);
+            vk_device
+                .device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            vk_device.device.destroy_pipeline(self.pipeline, None);
+            vk_device
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            vk_device
+                .device
+                .destroy_descriptor_set_layout(self.descriptor_layout, None);
+            self.fragment_shader.destroy(vk_device);
+            self.vertex_shader.destroy(vk_device);
+        }
+    }
+}
+
+// fullscreen triangle without vertex input or depth, single sampled
+fn create_tonemap_pipeline(
+    vk_device: &VKDevice,
+    format: vk::Format,
+    stages: &[vk::PipelineShaderStageCreateInfo],
+    pipeline_layout: vk::PipelineLayout,
+) -> Result<vk::Pipeline, vk::Result> {
+    GraphicsPipelineBuilder::new(stages, pipeline_layout)
+        .color_formats(&[format])
+        .build(vk_device)
+}
+
+#[test]
+fn tonemap_curve_test() {
+    assert_eq!(tonemap(-1.0), 0.0);
+    assert_eq!(tonemap(TONEMAP_KNEE), TONEMAP_KNEE);
+
+    // no kink at the knee and never past white
+    let step = 1e-3;
+    let slope = (tonemap(TONEMAP_KNEE + step) - TONEMAP_KNEE) / step;
+    assert!((slope - 1.0).abs() < 1e-2);
+    let mut last = TONEMAP_KNEE;
+    for value in [0.9, 1.0, 1.5, 2.0] {
+        let mapped = tonemap(value);
+        assert!(mapped > last && mapped < 1.0);
+        last = mapped;
+    }
+    assert!(tonemap(f32::MAX) <= 1.0);
+}
+
+#[test]
+fn tonemap_shader_test() {
+    let mut vk_shader_loader = VKShaderLoader::<&str>::default();
+    let spirv = vk_shader_loader.load_shader("shaders/tonemap.spv").unwrap();
+    let entry_points = crate::renderer::shader::spirv_entry_points(spirv);
+    assert!(entry_points.iter().any(|name| name == "vertexMain"));
+    assert!(entry_points.iter().any(|name| name == "fragmentMain"));
+}
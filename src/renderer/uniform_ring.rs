@@ -0,0 +1,184 @@
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use log::warn;
+
+use crate::renderer::buffer::VKBuffer;
+use crate::renderer::device::VKDevice;
+
+// hands out aligned offsets inside one frame's region of the ring
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct RingCursor {
+    region_size: u64,
+    alignment: u64,
+    /// bytes the descriptor reads past each offset, every offset has to leave this much
+    range: u64,
+    region_start: u64,
+    offset: u64,
+}
+
+impl RingCursor {
+    fn new(region_size: u64, alignment: u64, range: u64) -> Self {
+        Self {
+            region_size,
+            alignment: alignment.max(1),
+            range,
+            region_start: 0,
+            offset: 0,
+        }
+    }
+
+    fn begin_frame(&mut self, frame_in_flight: usize) {
+        self.region_start = frame_in_flight as u64 * self.region_size;
+        self.offset = self.region_start;
+    }
+
+    // None once the region is full
+    fn allocate(&mut self, size: u64) -> Option<u64> {
+        if size > self.range {
+            return None;
+        }
+        let offset = self.offset.next_multiple_of(self.alignment);
+        if offset + self.range > self.region_start + self.region_size {
+            return None;
+        }
+        self.offset = offset + size;
+        Some(offset)
+    }
+
+    fn used(&self) -> u64 {
+        self.offset - self.region_start
+    }
+}
+
+/// Per draw constants for thousands of objects without an allocation each,
+/// one host visible uniform buffer split into a region per frame in flight that is refilled every frame
+/// draws bind a UNIFORM_BUFFER_DYNAMIC descriptor once and pick their constants with the offset push returned
+/// Example Use:
+/// ```ignore
+/// let mut ring = VKUniformRing::new(vk_device, size_of::<ObjectConstants>() as u64, 4096, frames_in_flight)?;
+/// let binding = VKUniformRing::layout_binding(0, vk::ShaderStageFlags::VERTEX);
+/// // write ring.descriptor_buffer_info() into a set with binding
+/// // each frame, once its fence has signalled
+/// ring.begin_frame(frame_in_flight);
+/// for object in &objects {
+///     let offset = ring.push(&object.constants).unwrap();
+///     vk_device.device.cmd_bind_descriptor_sets(cmd_buffer, bind_point, layout, 0, &[set], &[offset]);
+///     // draw object
+/// }
+/// ```
+/// VKRenderer::camera_ring holds the camera of the frame and of each render texture this way
+pub struct VKUniformRing {
+    pub buffer: VKBuffer,
+    /// bytes the descriptor covers, the largest constants push takes
+    pub range: u64,
+    /// bytes each frame in flight's region holds
+    pub region_size: u64,
+    cursor: RingCursor,
+}
+
+impl VKUniformRing {
+    /// A ring holding objects_per_frame constants of up to range bytes for each frame in flight
+    pub fn new(
+        vk_device: &mut VKDevice,
+        range: u64,
+        objects_per_frame: u64,
+        frames_in_flight: u32,
+    ) -> Result<Self, vk::Result> {
+        let limits = vk_device.limits;
+        if range > limits.max_uniform_buffer_range as u64 {
+            warn!(
+                "Uniform Ring Range of {range} Bytes Exceeds Device Limit of {} Bytes",
+                limits.max_uniform_buffer_range
+            );
+            return Err(vk::Result::ERROR_INITIALIZATION_FAILED);
+        }
+
+        let alignment = limits.min_uniform_buffer_offset_alignment;
+        let region_size = range.next_multiple_of(alignment.max(1)) * objects_per_frame.max(1);
+        let mut buffer = VKBuffer::new(
+            vk_device,
+            region_size * frames_in_flight.max(1) as u64,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            MemoryLocation::CpuToGpu,
+            "Uniform Ring",
+        )?;
+        if !buffer.is_mapped() {
+            unsafe { buffer.destroy(vk_device) };
+            return Err(vk::Result::ERROR_MEMORY_MAP_FAILED);
+        }
+
+        Ok(Self {
+            buffer,
+            range,
+            region_size,
+            cursor: RingCursor::new(region_size, alignment, range),
+        })
+    }
+
+    /// Layout binding for the ring's descriptor
+    pub fn layout_binding(
+        binding: u32,
+        stage_flags: vk::ShaderStageFlags,
+    ) -> vk::DescriptorSetLayoutBinding<'static> {
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(binding)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .descriptor_count(1)
+            .stage_flags(stage_flags)
+    }
+
+    /// For a UNIFORM_BUFFER_DYNAMIC descriptor, the same for every frame in flight
+    pub fn descriptor_buffer_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffer.buffer)
+            .offset(0)
+            .range(self.range)
+    }
+
+    /// Starts writing frame_in_flight's region over, whatever was pushed into it last time is gone
+    /// the gpu must be done with frame_in_flight
+    pub fn begin_frame(&mut self, frame_in_flight: usize) {
+        let regions = (self.buffer.size / self.region_size).max(1) as usize;
+        self.cursor.begin_frame(frame_in_flight % regions);
+    }
+
+    /// Writes constants into this frame's region, returns the dynamic offset to bind them with
+    /// or None when the region is full or T is larger than range
+    pub fn push<T: Copy>(&mut self, constants: &T) -> Option<u32> {
+        let offset = self.cursor.allocate(size_of::<T>() as u64)?;
+        self.buffer.write_value(offset as usize, constants).ok()?;
+        Some(offset as u32)
+    }
+
+    /// Bytes pushed this frame, alignment padding included
+    pub fn used(&self) -> u64 {
+        self.cursor.used()
+    }
+
+    /// # Safety
+    /// The gpu must not be using the ring
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        unsafe { self.buffer.destroy(vk_device) };
+    }
+}
+
+#[test]
+fn ring_cursor_test() {
+    // two frames of 256 bytes, 64 byte alignment and 48 byte constants
+    let mut cursor = RingCursor::new(256, 64, 48);
+
+    cursor.begin_frame(1);
+    assert_eq!(cursor.allocate(48), Some(256));
+    assert_eq!(cursor.allocate(16), Some(320));
+    assert_eq!(cursor.allocate(48), Some(384));
+    assert_eq!(cursor.allocate(48), Some(448));
+    // the next offset wouldn't leave room for range
+    assert_eq!(cursor.allocate(4), None);
+    assert_eq!(cursor.used(), 240);
+    assert_eq!(cursor.allocate(49), None);
+
+    // frame 0's region starts over without touching frame 1's
+    cursor.begin_frame(0);
+    assert_eq!(cursor.used(), 0);
+    assert_eq!(cursor.allocate(48), Some(0));
+}
@@ -0,0 +1,500 @@
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use log::{error, warn};
+
+use crate::renderer::buffer::VKBuffer;
+use crate::renderer::device::VKDevice;
+
+/// Bytes of the persistent staging ring uploads are copied through
+pub const STAGING_RING_SIZE: u64 = 32 * 1024 * 1024;
+
+// where a ring allocation may start, a multiple of the texel sizes image copies need
+const STAGING_ALIGNMENT: u64 = 16;
+
+// how far the ring had been written when a batch was flushed, everything before it
+// is free again once the batch has finished
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct RingMark {
+    head: u64,
+    allocated: u64,
+}
+
+// head and tail of the staging ring, batches finish in order so space is given back in order
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct RingSpace {
+    size: u64,
+    head: u64,
+    tail: u64,
+    /// bytes ever handed out, padding and space skipped when wrapping included
+    allocated: u64,
+    /// bytes ever given back
+    released: u64,
+}
+
+impl RingSpace {
+    fn new(size: u64) -> Self {
+        Self {
+            size,
+            head: 0,
+            tail: 0,
+            allocated: 0,
+            released: 0,
+        }
+    }
+
+    fn used(&self) -> u64 {
+        self.allocated - self.released
+    }
+
+    // offset of size free bytes, None when they don't fit until more batches finish
+    fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        if self.used() == 0 {
+            self.head = 0;
+            self.tail = 0;
+        }
+        let start = self.head.next_multiple_of(alignment);
+        let offset = if self.used() == 0 || self.head > self.tail {
+            // free space runs from head to the end then from the start to tail
+            if start + size <= self.size {
+                start
+            } else if size <= self.tail {
+                0
+            } else {
+                return None;
+            }
+        } else if start + size <= self.tail {
+            start
+        } else {
+            return None;
+        };
+
+        let skipped = if offset < self.head {
+            self.size - self.head
+        } else {
+            offset - self.head
+        };
+        self.allocated += skipped + size;
+        self.head = offset + size;
+        Some(offset)
+    }
+
+    fn mark(&self) -> RingMark {
+        RingMark {
+            head: self.head,
+            allocated: self.allocated,
+        }
+    }
+
+    fn release(&mut self, mark: RingMark) {
+        self.tail = mark.head;
+        self.released = mark.allocated;
+    }
+
+    // hands back everything allocated since mark, which must be the newest allocations
+    fn rollback(&mut self, mark: RingMark) {
+        self.head = mark.head;
+        self.allocated = mark.allocated;
+    }
+}
+
+// one mapped buffer data is staged in, reused for the uploader's lifetime
+struct StagingRing {
+    buffer: VKBuffer,
+    space: RingSpace,
+}
+
+/// Copies data into gpu only buffers from the transfer queue without waiting on it
+/// copies are batched into one submission, the graphics queue waits on a timeline semaphore
+/// for them instead of the cpu waiting for the queue to go idle
+/// data is staged in a persistent ring that batches hand back as they finish,
+/// uploads that don't fit get a staging buffer of their own
+/// Example Use:
+/// ```ignore
+/// let vertex_buffer = uploader.upload_buffer(
+///     &mut vk_device,
+///     &vertices,
+///     vk::BufferUsageFlags::VERTEX_BUFFER,
+///     "Vertices",
+/// )?;
+/// uploader.flush(&mut vk_device)?;
+/// // frame submit waits on the copies before drawing
+/// let upload_wait = uploader.take_wait();
+/// ```
+pub struct VKUploader {
+    /// on the transfer queue family, batches are recorded into buffers from it
+    pub command_pool: vk::CommandPool,
+    /// counts up once per batch, reaches a batch's value when its copies have finished
+    pub timeline: vk::Semaphore,
+    /// value of the newest submitted batch
+    pub submitted: u64,
+    // newest value a graphics submission has waited for
+    waited: u64,
+    recording: Option<UploadBatch>,
+    in_flight: Vec<UploadBatch>,
+    // None when it couldn't be created, every upload stages on its own
+    ring: Option<StagingRing>,
+}
+
+// a command buffer of copies and the staging buffers it reads from
+struct UploadBatch {
+    cmd_buffer: vk::CommandBuffer,
+    value: u64,
+    staging: Vec<VKBuffer>,
+    /// where the ring was when the batch started recording
+    ring_start: Option<RingMark>,
+    /// set when flushed
+    ring_mark: Option<RingMark>,
+}
+
+impl VKUploader {
+    pub fn new(vk_device: &mut VKDevice) -> Result<Self, vk::Result> {
+        let pool_info = vk::CommandPoolCreateInfo::default()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(vk_device.transfer_queue_index);
+        let command_pool = unsafe { vk_device.device.create_command_pool(&pool_info, None)? };
+
+        let mut timeline_info = vk::SemaphoreTypeCreateInfo::default()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+        let semaphore_info = vk::SemaphoreCreateInfo::default().push_next(&mut timeline_info);
+        let timeline = match unsafe { vk_device.device.create_semaphore(&semaphore_info, None) } {
+            Ok(timeline) => timeline,
+            Err(error) => {
+                unsafe { vk_device.device.destroy_command_pool(command_pool, None) };
+                return Err(error);
+            }
+        };
+
+        let ring = match create_staging_ring(vk_device, STAGING_RING_SIZE) {
+            Ok(ring) => Some(ring),
+            Err(error) => {
+                warn!("Staging Ring Unavailable, Uploads Stage Separately: {error}");
+                None
+            }
+        };
+
+        Ok(Self {
+            command_pool,
+            timeline,
+            submitted: 0,
+            waited: 0,
+            recording: None,
+            in_flight: Vec::new(),
+            ring,
+        })
+    }
+
+    /// Creates a gpu only buffer and records a copy of data into it
+    /// the copy starts on the next flush, the buffer can't be read before then
+    pub fn upload_buffer<T: Copy>(
+        &mut self,
+        vk_device: &mut VKDevice,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+        name: &str,
+    ) -> Result<VKBuffer, vk::Result> {
+        let size = size_of_val(data) as u64;
+        let cmd_buffer = self.recording_cmd_buffer(vk_device)?;
+
+        let usage = vk::BufferUsageFlags::TRANSFER_DST | usage;
+        let (buffer, allocation) =
+            vk_device.create_shared_buffer(size, usage, MemoryLocation::GpuOnly, name)?;
+        let mut buffer = VKBuffer::from_parts(buffer, allocation, size, usage);
+
+        let (staging_buffer, staging_offset) = match self.stage(vk_device, data, name) {
+            Ok(staged) => staged,
+            Err(error) => {
+                unsafe { buffer.destroy(vk_device) };
+                return Err(error);
+            }
+        };
+
+        let copy_region = vk::BufferCopy::default()
+            .src_offset(staging_offset)
+            .size(size);
+        unsafe {
+            vk_device.device.cmd_copy_buffer(
+                cmd_buffer,
+                staging_buffer,
+                buffer.buffer,
+                &[copy_region],
+            )
+        };
+
+        Ok(buffer)
+    }
+
+    // copies data into the ring, or a staging buffer of its own when the ring is full
+    // returns the buffer and offset the copy reads from, a batch must be recording
+    fn stage<T: Copy>(
+        &mut self,
+        vk_device: &mut VKDevice,
+        data: &[T],
+        name: &str,
+    ) -> Result<(vk::Buffer, u64), vk::Result> {
+        let size = size_of_val(data) as u64;
+        if let Some(ring) = &mut self.ring
+            && let Some(offset) = ring.space.allocate(size, STAGING_ALIGNMENT)
+        {
+            ring.buffer.write(offset as usize, data)?;
+            return Ok((ring.buffer.buffer, offset));
+        }
+
+        let mut staging = VKBuffer::new(
+            vk_device,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+            &format!("{name} Staging"),
+        )?;
+        if let Err(error) = staging.write(0, data) {
+            unsafe { staging.destroy(vk_device) };
+            return Err(error);
+        }
+        let staging_buffer = staging.buffer;
+        if let Some(batch) = &mut self.recording {
+            batch.staging.push(staging);
+        }
+        Ok((staging_buffer, 0))
+    }
+
+    // command buffer copies are recorded into, begins a new batch if there isn't one
+    fn recording_cmd_buffer(
+        &mut self,
+        vk_device: &VKDevice,
+    ) -> Result<vk::CommandBuffer, vk::Result> {
+        if let Some(batch) = &self.recording {
+            return Ok(batch.cmd_buffer);
+        }
+
+        let alloc_info = vk::CommandBufferAllocateInfo::default()
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_pool(self.command_pool)
+            .command_buffer_count(1);
+        let cmd_buffer = unsafe { vk_device.device.allocate_command_buffers(&alloc_info)?[0] };
+
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        if let Err(error) = unsafe {
+            vk_device
+                .device
+                .begin_command_buffer(cmd_buffer, &begin_info)
+        } {
+            unsafe {
+                vk_device
+                    .device
+                    .free_command_buffers(self.command_pool, &[cmd_buffer])
+            };
+            return Err(error);
+        }
+
+        self.recording = Some(UploadBatch {
+            cmd_buffer,
+            value: 0,
+            staging: Vec::new(),
+            ring_start: self.ring.as_ref().map(|ring| ring.space.mark()),
+            ring_mark: None,
+        });
+        Ok(cmd_buffer)
+    }
+
+    /// Submits the copies recorded since the last flush, does nothing when there are none
+    pub fn flush(&mut self, vk_device: &mut VKDevice) -> Result<(), vk::Result> {
+        let Some(mut batch) = self.recording.take() else {
+            return Ok(());
+        };
+        batch.value = self.submitted + 1;
+        batch.ring_mark = self.ring.as_ref().map(|ring| ring.space.mark());
+
+        let cmd_buffer_infos =
+            [vk::CommandBufferSubmitInfo::default().command_buffer(batch.cmd_buffer)];
+        let signal_infos = [vk::SemaphoreSubmitInfo::default()
+            .semaphore(self.timeline)
+            .value(batch.value)
+            .stage_mask(vk::PipelineStageFlags2::ALL_TRANSFER)];
+        let submit_info = vk::SubmitInfo2::default()
+            .command_buffer_infos(&cmd_buffer_infos)
+            .signal_semaphore_infos(&signal_infos);
+
+        let submitted = unsafe {
+            vk_device
+                .device
+                .end_command_buffer(batch.cmd_buffer)
+                .and_then(|_| {
+                    vk_device.device.queue_submit2(
+                        vk_device.transfer_queue,
+                        &[submit_info],
+                        vk::Fence::null(),
+                    )
+                })
+        };
+        if let Err(error) = submitted {
+            // never reached the gpu, the buffers it was filling stay uninitialised
+            // nothing has been staged since it started, so its ring space is free straight away
+            if let Some(ring) = &mut self.ring
+                && let Some(start) = batch.ring_start
+            {
+                ring.space.rollback(start);
+            }
+            batch.ring_mark = None;
+            unsafe { self.free_batch(vk_device, batch) };
+            return Err(error);
+        }
+
+        self.submitted = batch.value;
+        self.in_flight.push(batch);
+        Ok(())
+    }
+
+    /// Semaphore wait for the next graphics submission, None once every batch has been waited on
+    /// copies are visible to vertex input and shader reads after the wait
+    pub fn take_wait(&mut self) -> Option<vk::SemaphoreSubmitInfo<'static>> {
+        if self.waited == self.submitted {
+            return None;
+        }
+        self.waited = self.submitted;
+        Some(
+            vk::SemaphoreSubmitInfo::default()
+                .semaphore(self.timeline)
+                .value(self.submitted)
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS),
+        )
+    }
+
+    /// Whether the newest batch has finished
+    pub fn is_idle(&self, vk_device: &VKDevice) -> bool {
+        self.recording.is_none() && self.completed(vk_device) >= self.submitted
+    }
+
+    fn completed(&self, vk_device: &VKDevice) -> u64 {
+        unsafe { vk_device.device.get_semaphore_counter_value(self.timeline) }.unwrap_or(0)
+    }
+
+    /// Frees staging buffers of batches that have finished, cheap enough to call every frame
+    pub fn collect(&mut self, vk_device: &mut VKDevice) {
+        if self.in_flight.is_empty() {
+            return;
+        }
+        let completed = self.completed(vk_device);
+        let (finished, in_flight): (Vec<_>, Vec<_>) = self
+            .in_flight
+            .drain(..)
+            .partition(|batch| batch.value <= completed);
+        self.in_flight = in_flight;
+        if let Some(ring) = &mut self.ring
+            && let Some(mark) = finished.iter().rev().find_map(|batch| batch.ring_mark)
+        {
+            ring.space.release(mark);
+        }
+        for batch in finished {
+            unsafe { self.free_batch(vk_device, batch) };
+        }
+    }
+
+    /// Flushes and blocks until every copy has finished, for work submitted outside the frame
+    /// like acceleration structure builds reading fresh vertex buffers
+    pub fn wait(&mut self, vk_device: &mut VKDevice) -> Result<(), vk::Result> {
+        self.flush(vk_device)?;
+        if self.in_flight.is_empty() {
+            return Ok(());
+        }
+
+        let semaphores = [self.timeline];
+        let values = [self.submitted];
+        let wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(&semaphores)
+            .values(&values);
+        unsafe { vk_device.device.wait_semaphores(&wait_info, u64::MAX)? };
+
+        self.collect(vk_device);
+        Ok(())
+    }
+
+    unsafe fn free_batch(&self, vk_device: &mut VKDevice, batch: UploadBatch) {
+        unsafe {
+            vk_device
+                .device
+                .free_command_buffers(self.command_pool, &[batch.cmd_buffer]);
+            for mut staging in batch.staging {
+                staging.destroy(vk_device);
+            }
+        }
+    }
+
+    /// # Safety
+    /// Waits for outstanding copies, destroy before the device
+    pub unsafe fn destroy(&mut self, vk_device: &mut VKDevice) {
+        if let Err(error) = self.wait(vk_device) {
+            error!("Failed to Wait For Uploads: {error}");
+        }
+        unsafe {
+            if let Some(batch) = self.recording.take() {
+                self.free_batch(vk_device, batch);
+            }
+            for batch in std::mem::take(&mut self.in_flight) {
+                self.free_batch(vk_device, batch);
+            }
+            if let Some(mut ring) = self.ring.take() {
+                ring.buffer.destroy(vk_device);
+            }
+            vk_device.device.destroy_semaphore(self.timeline, None);
+            vk_device
+                .device
+                .destroy_command_pool(self.command_pool, None);
+        }
+    }
+}
+
+fn create_staging_ring(vk_device: &mut VKDevice, size: u64) -> Result<StagingRing, vk::Result> {
+    let mut buffer = VKBuffer::new(
+        vk_device,
+        size,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        MemoryLocation::CpuToGpu,
+        "Staging Ring",
+    )?;
+    if !buffer.is_mapped() {
+        unsafe { buffer.destroy(vk_device) };
+        return Err(vk::Result::ERROR_MEMORY_MAP_FAILED);
+    }
+    Ok(StagingRing {
+        buffer,
+        space: RingSpace::new(size),
+    })
+}
+
+#[test]
+fn ring_space_test() {
+    let mut space = RingSpace::new(256);
+    assert_eq!(space.allocate(100, 16), Some(0));
+    assert_eq!(space.allocate(100, 16), Some(112));
+    let first_batch = space.mark();
+    // 44 bytes are left at the end but nothing before the tail has been given back
+    assert_eq!(space.allocate(64, 16), None);
+    assert_eq!(space.used(), 212);
+
+    space.release(RingMark {
+        head: 100,
+        allocated: 100,
+    });
+    // too big for the end, wraps around to the freed start
+    assert_eq!(space.allocate(64, 16), Some(0));
+    assert_eq!(space.used(), 212 - 100 + 44 + 64);
+    assert_eq!(space.allocate(64, 16), None);
+
+    // once everything has finished the ring starts over from the beginning
+    space.release(first_batch);
+    space.release(space.mark());
+    assert_eq!(space.used(), 0);
+    assert_eq!(space.allocate(256, 16), Some(0));
+    assert_eq!(space.allocate(1, 16), None);
+
+    // a batch that failed to submit gives its space back without waiting on anything
+    space.release(space.mark());
+    assert_eq!(space.allocate(32, 16), Some(0));
+    let failed_start = space.mark();
+    assert_eq!(space.allocate(100, 16), Some(32));
+    space.rollback(failed_start);
+    assert_eq!(space.used(), 32);
+    assert_eq!(space.allocate(16, 16), Some(32));
+}
@@ -0,0 +1,100 @@
+use ash::vk;
+
+/// One vertex attribute, its shader location is its index in VertexLayout::ATTRIBUTES
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexAttribute {
+    pub format: vk::Format,
+    /// bytes from the start of the vertex, usually std::mem::offset_of!
+    pub offset: u32,
+}
+
+impl VertexAttribute {
+    pub const fn new(format: vk::Format, offset: usize) -> Self {
+        Self {
+            format,
+            offset: offset as u32,
+        }
+    }
+}
+
+/// A vertex type pipelines can read from a vertex buffer at binding 0
+/// the type should be repr(C) so the offsets match what the shader was compiled against
+/// Example Use:
+/// ```
+/// use ash::vk;
+/// use glam::{Vec2, Vec3};
+/// use std::mem::offset_of;
+/// use vulkan_engine::renderer::vertex::{VertexAttribute, VertexLayout};
+///
+/// #[repr(C)]
+/// #[derive(Clone, Copy)]
+/// struct TerrainVertex {
+///     position: Vec3,
+///     uv: Vec2,
+/// }
+///
+/// impl VertexLayout for TerrainVertex {
+///     const ATTRIBUTES: &'static [VertexAttribute] = &[
+///         VertexAttribute::new(vk::Format::R32G32B32_SFLOAT, offset_of!(TerrainVertex, position)),
+///         VertexAttribute::new(vk::Format::R32G32_SFLOAT, offset_of!(TerrainVertex, uv)),
+///     ];
+/// }
+///
+/// assert_eq!(TerrainVertex::binding_description().stride, 20);
+/// assert_eq!(TerrainVertex::attribute_descriptions()[1].location, 1);
+/// ```
+pub trait VertexLayout: Copy {
+    /// in shader location order starting at 0
+    const ATTRIBUTES: &'static [VertexAttribute];
+
+    /// Advanced per vertex, instanced data can use INSTANCE
+    const INPUT_RATE: vk::VertexInputRate = vk::VertexInputRate::VERTEX;
+
+    fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::default()
+            .binding(0)
+            .stride(size_of::<Self>() as u32)
+            .input_rate(Self::INPUT_RATE)
+    }
+
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+        Self::ATTRIBUTES
+            .iter()
+            .zip(0..)
+            .map(|(attribute, location)| {
+                vk::VertexInputAttributeDescription::default()
+                    .binding(0)
+                    .location(location)
+                    .format(attribute.format)
+                    .offset(attribute.offset)
+            })
+            .collect()
+    }
+}
+
+/// Binding and attribute descriptions of a VertexLayout, kept alive while a pipeline is created
+#[derive(Clone, Debug, Default)]
+pub struct VertexInput {
+    pub bindings: Vec<vk::VertexInputBindingDescription>,
+    pub attributes: Vec<vk::VertexInputAttributeDescription>,
+}
+
+impl VertexInput {
+    pub fn of<V: VertexLayout>() -> Self {
+        Self {
+            bindings: vec![V::binding_description()],
+            attributes: V::attribute_descriptions(),
+        }
+    }
+
+    /// No vertex buffers, for pipelines that generate their vertices in the shader
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> vk::PipelineVertexInputStateCreateInfo<'_> {
+        vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&self.bindings)
+            .vertex_attribute_descriptions(&self.attributes)
+    }
+}
@@ -0,0 +1,351 @@
+use std::error;
+use std::time::{Duration, Instant};
+
+use ash::vk;
+use thiserror::Error;
+use winit::window::{Window, WindowId};
+
+use crate::color::LinearRgba;
+use crate::renderer::device::VKDevice;
+use crate::renderer::presentation::{PresentTarget, VKPresent, VKSurface, VKSwapchain};
+use crate::renderer::render_graph::Access;
+use crate::renderer::{RenderTarget, VKContext};
+
+/// How often a window presents, independent of every other window
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameCadence {
+    /// None presents whenever asked, the present mode still limits it
+    pub interval: Option<Duration>,
+    next_frame: Option<Instant>,
+}
+
+impl FrameCadence {
+    /// A frame every 1 / rate seconds, rates of 0 or below are unlimited
+    pub fn from_rate(rate: f32) -> Self {
+        Self {
+            interval: (rate > 0.0).then(|| Duration::from_secs_f32(1.0 / rate)),
+            next_frame: None,
+        }
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.next_frame.is_none_or(|next_frame| now >= next_frame)
+    }
+
+    /// When the next frame is due, None if it already is or the cadence is unlimited
+    pub fn next_frame(&self) -> Option<Instant> {
+        self.interval.and(self.next_frame)
+    }
+
+    /// Schedules the next frame an interval after this one was due
+    /// a window that fell an interval behind starts again from now instead of catching up
+    pub fn frame_started(&mut self, now: Instant) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let next_frame = self.next_frame.unwrap_or(now) + interval;
+        self.next_frame = Some(if next_frame <= now {
+            now + interval
+        } else {
+            next_frame
+        });
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WindowTargetError {
+    /// the device was picked for the first window, another gpu's display can't share it
+    #[error("the renderer's graphics queue can't present to this window")]
+    UnsupportedSurface,
+}
+
+/// A window presented to beside the renderer's own, with its own surface, swapchain, sync
+/// objects and cadence on the shared instance and device, see VKRenderer::add_window
+/// frames are recorded and submitted on the thread that calls render
+pub struct VKWindowTarget {
+    pub window_id: WindowId,
+    pub surface: VKSurface,
+    pub swapchain: VKSwapchain,
+    pub present: VKPresent,
+    pub cadence: FrameCadence,
+    /// alpha below 1 shows through transparent windows, ignored by opaque ones
+    pub clear_color: LinearRgba,
+    cmd_pool: vk::CommandPool,
+    cmd_buffers: Vec<vk::CommandBuffer>,
+}
+
+impl VKWindowTarget {
+    pub fn new(
+        vk_ctx: &mut VKContext,
+        window: &Window,
+        frames_in_flight: u32,
+        transparent: bool,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let mut surface = VKSurface::new(&vk_ctx.vulkan_instance, window)?;
+        let vk_device = &mut vk_ctx.vulkan_device;
+        if !surface.queue_supports_surface(vk_device.p_device, vk_device.queue_index)? {
+            unsafe { surface.destroy() };
+            return Err(WindowTargetError::UnsupportedSurface.into());
+        }
+
+        let mut swapchain = VKSwapchain::new(
+            &vk_ctx.vulkan_instance,
+            vk_device,
+            &surface,
+            window,
+            None,
+            vk::SampleCountFlags::TYPE_1,
+            transparent,
+        )?;
+
+        let mut present = unsafe {
+            VKPresent::default().max_frames(
+                frames_in_flight,
+                present_target(vk_ctx, &surface, &mut swapchain),
+            )?
+        };
+        present.enable_present_timing(present_target(vk_ctx, &surface, &mut swapchain), window);
+
+        let vk_device = &vk_ctx.vulkan_device;
+        let cmd_pool_info = vk::CommandPoolCreateInfo::default()
+            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+            .queue_family_index(vk_device.queue_index);
+        let cmd_pool = unsafe { vk_device.device.create_command_pool(&cmd_pool_info, None)? };
+        let alloc_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(cmd_pool)
+            .command_buffer_count(frames_in_flight)
+            .level(vk::CommandBufferLevel::PRIMARY);
+        let cmd_buffers = unsafe { vk_device.device.allocate_command_buffers(&alloc_info)? };
+
+        Ok(Self {
+            window_id: window.id(),
+            surface,
+            swapchain,
+            present,
+            cadence: FrameCadence::default(),
+            clear_color: LinearRgba::BLACK,
+            cmd_pool,
+            cmd_buffers,
+        })
+    }
+
+    pub fn with_cadence(mut self, cadence: FrameCadence) -> Self {
+        self.cadence = cadence;
+        self
+    }
+
+    /// Presents a frame when the cadence is due, false when it isn't or the window can't be
+    /// drawn to right now, e.g. while minimised or after its swapchain went out of date
+    /// record draws inside dynamic rendering to the swapchain image, cleared to clear_color
+    /// Example Use:
+    /// ```ignore
+    /// // tool window at 30 Hz next to the game view
+    /// window_target.render(&mut renderer.vulkan_ctx, &tool_window, Instant::now(), |vk_device, cmd_buffer, target| {
+    ///     draw_tool_ui(vk_device, cmd_buffer, target.extent);
+    /// })?;
+    /// ```
+    pub fn render(
+        &mut self,
+        vk_ctx: &mut VKContext,
+        window: &Window,
+        now: Instant,
+        record: impl FnOnce(&VKDevice, vk::CommandBuffer, &RenderTarget),
+    ) -> Result<bool, vk::Result> {
+        // nothing to render to while minimised
+        let window_size = window.inner_size();
+        if window_size.width == 0 || window_size.height == 0 || !self.cadence.is_due(now) {
+            return Ok(false);
+        }
+
+        self.present.begin_frame();
+        let render_info = match self.present.aquire_img(
+            present_target(vk_ctx, &self.surface, &mut self.swapchain),
+            window,
+        ) {
+            Ok(render_info) => render_info,
+            // rebuilt by the next frame's aquire
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        self.cadence.frame_started(now);
+
+        let target = RenderTarget::from_swapchain(&self.swapchain, render_info.img_aquired_index);
+        let cmd_buffer = self.cmd_buffers[render_info.frame_in_flight as usize];
+        // compositors expect premultiplied colours from transparent windows
+        let clear_color = match self.swapchain.transparent {
+            true => self.clear_color.premultiplied(),
+            false => self.clear_color,
+        };
+        let vk_device = &vk_ctx.vulkan_device;
+        unsafe {
+            vk_device
+                .device
+                .begin_command_buffer(cmd_buffer, &vk::CommandBufferBeginInfo::default())?;
+            // the image is cleared, what was presented last time isn't kept
+            cmd_swapchain_barrier(
+                vk_device,
+                cmd_buffer,
+                target.image,
+                None,
+                Access::ColorAttachment,
+            );
+
+            let color_attachments = [vk::RenderingAttachmentInfo::default()
+                .image_view(target.image_view)
+                .image_layout(Access::ColorAttachment.layout())
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .clear_value(vk::ClearValue {
+                    color: clear_color.into(),
+                })];
+            let rendering_info = vk::RenderingInfo::default()
+                .color_attachments(&color_attachments)
+                .layer_count(1)
+                .render_area(vk::Rect2D::default().extent(target.extent));
+            vk_device
+                .device
+                .cmd_begin_rendering(cmd_buffer, &rendering_info);
+            record(vk_device, cmd_buffer, &target);
+            vk_device.device.cmd_end_rendering(cmd_buffer);
+
+            cmd_swapchain_barrier(
+                vk_device,
+                cmd_buffer,
+                target.image,
+                Some(Access::ColorAttachment),
+                Access::Present,
+            );
+            vk_device.device.end_command_buffer(cmd_buffer)?;
+        }
+
+        let command_buffer_infos =
+            &[vk::CommandBufferSubmitInfo::default().command_buffer(cmd_buffer)];
+        let wait_semaphore_infos = &[vk::SemaphoreSubmitInfo::default()
+            .semaphore(render_info.img_aquired_gpu)
+            .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)];
+        let signal_semaphore_infos = &[vk::SemaphoreSubmitInfo::default()
+            .semaphore(render_info.done_rendering_gpu)
+            .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)];
+        let submits = [vk::SubmitInfo2::default()
+            .wait_semaphore_infos(wait_semaphore_infos)
+            .signal_semaphore_infos(signal_semaphore_infos)
+            .command_buffer_infos(command_buffer_infos)];
+        unsafe {
+            vk_device.device.queue_submit2(
+                vk_device.graphics_queue,
+                &submits,
+                render_info.done_rendering_cpu,
+            )?
+        };
+
+        // required for wayland
+        window.pre_present_notify();
+
+        match self
+            .present
+            .present_frame(present_target(vk_ctx, &self.surface, &mut self.swapchain))
+        {
+            Ok(()) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(true),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// # Safety
+    /// Destroy before the context the window target was created with
+    /// waits for the device to finish the window's frames
+    pub unsafe fn destroy(&mut self, vk_ctx: &mut VKContext) {
+        let vk_device = &mut vk_ctx.vulkan_device;
+        unsafe {
+            self.present.destroy(vk_device);
+            vk_device.device.destroy_command_pool(self.cmd_pool, None);
+            self.swapchain.destroy(vk_device);
+            self.surface.destroy();
+        }
+        self.cmd_buffers.clear();
+    }
+}
+
+// the window's own surface and swapchain on the context's instance and device
+fn present_target<'a>(
+    vk_ctx: &'a mut VKContext,
+    surface: &'a VKSurface,
+    swapchain: &'a mut VKSwapchain,
+) -> PresentTarget<'a> {
+    PresentTarget {
+        instance: &vk_ctx.vulkan_instance,
+        device: &mut vk_ctx.vulkan_device,
+        surface,
+        swapchain,
+    }
+}
+
+// swapchain images aren't VKImages, so their transitions are recorded here
+unsafe fn cmd_swapchain_barrier(
+    vk_device: &VKDevice,
+    cmd_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    previous: Option<Access>,
+    access: Access,
+) {
+    let (src_stage, src_access, old_layout) = match previous {
+        Some(previous) => (previous.stage(), previous.access(), previous.layout()),
+        // the acquire semaphore is waited on at colour output, so the barrier chains off it
+        None => (
+            vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags2::NONE,
+            vk::ImageLayout::UNDEFINED,
+        ),
+    };
+    let barriers = [vk::ImageMemoryBarrier2::default()
+        .old_layout(old_layout)
+        .new_layout(access.layout())
+        .src_stage_mask(src_stage)
+        .src_access_mask(src_access)
+        .dst_stage_mask(access.stage())
+        .dst_access_mask(match access {
+            Access::Present => vk::AccessFlags2::NONE,
+            access => access.access(),
+        })
+        .image(image)
+        .subresource_range(
+            vk::ImageSubresourceRange::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .level_count(1)
+                .layer_count(1),
+        )];
+    unsafe {
+        vk_device.device.cmd_pipeline_barrier2(
+            cmd_buffer,
+            &vk::DependencyInfo::default().image_memory_barriers(&barriers),
+        )
+    };
+}
+
+#[test]
+fn frame_cadence_test() {
+    let start = Instant::now();
+    let mut cadence = FrameCadence::from_rate(50.0);
+    let interval = Duration::from_millis(20);
+    assert!(cadence.is_due(start));
+    assert_eq!(cadence.next_frame(), None);
+
+    cadence.frame_started(start);
+    assert_eq!(cadence.next_frame(), Some(start + interval));
+    assert!(!cadence.is_due(start + Duration::from_millis(10)));
+
+    // late frames keep to the schedule instead of drifting
+    let late = start + Duration::from_millis(25);
+    assert!(cadence.is_due(late));
+    cadence.frame_started(late);
+    assert_eq!(cadence.next_frame(), Some(start + interval * 2));
+
+    // more than an interval behind starts over from now
+    let stalled = start + Duration::from_millis(100);
+    cadence.frame_started(stalled);
+    assert_eq!(cadence.next_frame(), Some(stalled + interval));
+
+    let mut unlimited = FrameCadence::from_rate(0.0);
+    unlimited.frame_started(start);
+    assert!(unlimited.is_due(start));
+    assert_eq!(unlimited.next_frame(), None);
+}
@@ -0,0 +1,321 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+use crate::scene::{NodeId, Scene, Transform};
+
+/// Id of a replicated entity, agreed on by every peer
+/// node ids are local to each scene so they can't be sent over the network
+pub type NetworkId = u64;
+
+/// Transform of one entity at the time of a snapshot
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EntityState {
+    pub id: NetworkId,
+    pub transform: Transform,
+}
+
+/// Transforms of replicated entities at one point in time
+/// plain data for the networking layer to encode however it likes
+/// Example Use:
+/// ```ignore
+/// // authority, every network tick
+/// let snapshot = SceneSnapshot::capture(&scene, server_time, replicated.iter().copied());
+/// send(ron::to_string(&snapshot)?);
+///
+/// // remote, whenever one arrives
+/// replication.receive(&ron::from_str(&message)?);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SceneSnapshot {
+    /// seconds on the authority's clock
+    pub time: f64,
+    pub entities: Vec<EntityState>,
+}
+
+impl SceneSnapshot {
+    /// Local transforms of the given nodes, missing nodes are left out
+    pub fn capture(
+        scene: &Scene,
+        time: f64,
+        nodes: impl IntoIterator<Item = (NetworkId, NodeId)>,
+    ) -> Self {
+        let entities = nodes
+            .into_iter()
+            .filter_map(|(id, node)| {
+                scene.get(node).map(|node| EntityState {
+                    id,
+                    transform: node.transform,
+                })
+            })
+            .collect();
+        Self { time, entities }
+    }
+}
+
+/// Timestamped transforms of one entity, sampled between the two around the requested time
+#[derive(Clone, Debug)]
+pub struct InterpolationBuffer {
+    // sorted by time
+    states: VecDeque<(f64, Transform)>,
+    capacity: usize,
+}
+
+impl InterpolationBuffer {
+    /// Keeps at most capacity states, the oldest are dropped first
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            states: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Adds a state, out of order states are slotted in and duplicates replace the old one
+    pub fn push(&mut self, time: f64, transform: Transform) {
+        let index = self
+            .states
+            .partition_point(|&(state_time, _)| state_time < time);
+        match self.states.get_mut(index) {
+            Some(state) if state.0 == time => state.1 = transform,
+            _ => self.states.insert(index, (time, transform)),
+        }
+
+        while self.states.len() > self.capacity {
+            self.states.pop_front();
+        }
+    }
+
+    /// Transform at time, held at the first or last state outside the buffered range
+    /// None until a state arrives
+    pub fn sample(&self, time: f64) -> Option<Transform> {
+        let index = self
+            .states
+            .partition_point(|&(state_time, _)| state_time <= time);
+        let (before, after) = match (index.checked_sub(1), self.states.get(index)) {
+            (Some(before), Some(&after)) => (self.states[before], after),
+            // no extrapolation, a late packet is better than a wrong guess
+            (Some(before), None) => return Some(self.states[before].1),
+            (None, Some(&after)) => return Some(after.1),
+            (None, None) => return None,
+        };
+
+        let t = (time - before.0) / (after.0 - before.0);
+        Some(before.1.lerp(&after.1, t as f32))
+    }
+
+    /// Time of the newest state
+    pub fn latest_time(&self) -> Option<f64> {
+        self.states.back().map(|&(time, _)| time)
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+}
+
+// an entity the local scene mirrors
+#[derive(Clone, Debug)]
+struct RemoteEntity {
+    node: Option<NodeId>,
+    buffer: InterpolationBuffer,
+}
+
+/// Applies snapshots from an authority to the local scene
+/// entities are drawn interpolation_delay seconds in the past so there is usually a newer state to blend towards
+/// Example Use:
+/// ```
+/// use glam::Vec3;
+/// use vulkan_engine::replication::{EntityState, Replication, SceneSnapshot};
+/// use vulkan_engine::scene::{Node, Scene, Transform};
+///
+/// let mut scene = Scene::default();
+/// let mut replication = Replication::new(0.1);
+///
+/// // a late joiner gets the full state, spawn what it doesn't have yet
+/// replication.receive(&SceneSnapshot {
+///     time: 1.0,
+///     entities: vec![EntityState {
+///         id: 7,
+///         transform: Transform::from_translation(Vec3::X),
+///     }],
+/// });
+/// for id in replication.unbound().collect::<Vec<_>>() {
+///     let node = scene.add(Node::new(format!("remote {id}")), None).unwrap();
+///     replication.bind(id, node);
+/// }
+///
+/// // each frame, with time on the authority's clock
+/// replication.apply(&mut scene, 1.05);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Replication {
+    /// how far behind the latest snapshots entities are shown, in seconds
+    pub interpolation_delay: f64,
+    /// states kept per entity
+    pub buffer_capacity: usize,
+    entities: HashMap<NetworkId, RemoteEntity>,
+}
+
+impl Replication {
+    pub fn new(interpolation_delay: f64) -> Self {
+        Self {
+            interpolation_delay,
+            buffer_capacity: 32,
+            entities: HashMap::new(),
+        }
+    }
+
+    pub fn buffer_capacity(mut self, buffer_capacity: usize) -> Self {
+        self.buffer_capacity = buffer_capacity;
+        self
+    }
+
+    /// Buffers every entity in snapshot, unknown entities are remembered until they are bound
+    pub fn receive(&mut self, snapshot: &SceneSnapshot) {
+        for state in &snapshot.entities {
+            self.entities
+                .entry(state.id)
+                .or_insert_with(|| RemoteEntity {
+                    node: None,
+                    buffer: InterpolationBuffer::new(self.buffer_capacity),
+                })
+                .buffer
+                .push(snapshot.time, state.transform);
+        }
+    }
+
+    /// Drives node with the entity's states, states received before binding apply straight away
+    pub fn bind(&mut self, id: NetworkId, node: NodeId) {
+        self.entities
+            .entry(id)
+            .or_insert_with(|| RemoteEntity {
+                node: None,
+                buffer: InterpolationBuffer::new(self.buffer_capacity),
+            })
+            .node = Some(node);
+    }
+
+    /// Forgets the entity and its states, returns the node it drove so it can be removed
+    pub fn remove(&mut self, id: NetworkId) -> Option<NodeId> {
+        self.entities.remove(&id).and_then(|entity| entity.node)
+    }
+
+    /// Entities with states but no node, the game spawns these
+    pub fn unbound(&self) -> impl Iterator<Item = NetworkId> + '_ {
+        self.entities
+            .iter()
+            .filter(|(_, entity)| entity.node.is_none())
+            .map(|(&id, _)| id)
+    }
+
+    pub fn node(&self, id: NetworkId) -> Option<NodeId> {
+        self.entities.get(&id).and_then(|entity| entity.node)
+    }
+
+    /// Buffered states of an entity
+    pub fn buffer(&self, id: NetworkId) -> Option<&InterpolationBuffer> {
+        self.entities.get(&id).map(|entity| &entity.buffer)
+    }
+
+    /// Sets the local transform of every bound node, time is on the authority's clock
+    /// nodes that were removed from the scene are skipped
+    pub fn apply(&self, scene: &mut Scene, time: f64) {
+        let render_time = time - self.interpolation_delay;
+        for entity in self.entities.values() {
+            if let Some(node) = entity.node.and_then(|node| scene.get_mut(node))
+                && let Some(transform) = entity.buffer.sample(render_time)
+            {
+                node.transform = transform;
+            }
+        }
+    }
+}
+
+#[test]
+fn interpolation_buffer_test() {
+    use glam::{Quat, Vec3};
+
+    let mut buffer = InterpolationBuffer::new(3);
+    assert_eq!(buffer.sample(0.0), None);
+
+    buffer.push(1.0, Transform::from_translation(Vec3::ZERO));
+    // a lone state is held, e.g. right after joining
+    assert_eq!(buffer.sample(0.0), Some(Transform::IDENTITY));
+    assert_eq!(buffer.sample(5.0), Some(Transform::IDENTITY));
+
+    // arrives out of order
+    let rotated = Transform::from_translation(Vec3::X * 4.0)
+        .with_rotation(Quat::from_rotation_y(std::f32::consts::FRAC_PI_2));
+    buffer.push(3.0, rotated);
+    buffer.push(2.0, Transform::from_translation(Vec3::X * 2.0));
+
+    let halfway = buffer.sample(2.5).unwrap();
+    assert!(halfway.translation.abs_diff_eq(Vec3::X * 3.0, 1e-5));
+    assert!(
+        halfway
+            .rotation
+            .abs_diff_eq(Quat::from_rotation_y(std::f32::consts::FRAC_PI_4), 1e-5)
+    );
+    // no extrapolation past the newest state
+    assert_eq!(buffer.sample(10.0), Some(rotated));
+
+    // oldest state is dropped past capacity
+    buffer.push(4.0, Transform::IDENTITY);
+    assert_eq!(buffer.len(), 3);
+    assert_eq!(buffer.latest_time(), Some(4.0));
+    assert!(
+        buffer
+            .sample(0.0)
+            .unwrap()
+            .translation
+            .abs_diff_eq(Vec3::X * 2.0, 1e-5)
+    );
+}
+
+#[test]
+fn replication_test() {
+    use crate::scene::Node;
+    use glam::Vec3;
+
+    let mut authority = Scene::default();
+    let player = authority
+        .add(
+            Node::new("player").with_transform(Transform::from_translation(Vec3::Z)),
+            None,
+        )
+        .unwrap();
+    let first = SceneSnapshot::capture(&authority, 1.0, [(42, player), (43, 99)]);
+    assert_eq!(first.entities.len(), 1);
+
+    authority.get_mut(player).unwrap().transform = Transform::from_translation(Vec3::Z * 3.0);
+    let second = SceneSnapshot::capture(&authority, 2.0, [(42, player)]);
+
+    // snapshots survive a round trip through a text format
+    let encoded = ron::to_string(&second).unwrap();
+    let second: SceneSnapshot = ron::from_str(&encoded).unwrap();
+
+    let mut remote = Scene::default();
+    let mut replication = Replication::new(0.5);
+    replication.receive(&first);
+    replication.receive(&second);
+    assert_eq!(replication.unbound().collect::<Vec<_>>(), vec![42]);
+
+    let node = remote.add(Node::new("remote player"), None).unwrap();
+    replication.bind(42, node);
+    assert_eq!(replication.unbound().count(), 0);
+
+    // drawn half a second behind, halfway between the two snapshots
+    replication.apply(&mut remote, 2.0);
+    let transform = remote.get(node).unwrap().transform;
+    assert!(transform.translation.abs_diff_eq(Vec3::Z * 2.0, 1e-5));
+
+    assert_eq!(replication.remove(42), Some(node));
+    assert_eq!(replication.node(42), None);
+}
@@ -0,0 +1,230 @@
+use std::time::Duration;
+use thiserror::Error;
+use winit::dpi::PhysicalSize;
+
+use crate::renderer::allocator::AllocatorReport;
+
+/// Resizes the window every frame for a few thousand frames, then checks the swapchain was
+/// rebuilt at most once a frame, no frame hung and no device memory leaked
+/// see App::with_resize_stress
+/// Example Use:
+/// ```ignore
+/// let mut app = App::new(game_info).with_resize_stress(ResizeStress::default().frames(5000));
+/// app.start(&mut event_loop)?;
+/// std::process::exit(app.exit_code());
+/// ```
+#[derive(Clone, Debug)]
+pub struct ResizeStress {
+    /// frames spent resizing
+    pub frames: u32,
+    /// frames at the starting size before memory is measured, and again at the end before comparing
+    pub settle_frames: u32,
+    /// resize requests made each frame, live resizing on X11 sends many between frames
+    pub requests_per_frame: u32,
+    pub min_size: PhysicalSize<u32>,
+    pub max_size: PhysicalSize<u32>,
+    /// a single frame taking longer than this counts as a hang
+    pub hang_timeout: Duration,
+}
+
+impl Default for ResizeStress {
+    fn default() -> Self {
+        Self {
+            frames: 3000,
+            settle_frames: 30,
+            requests_per_frame: 4,
+            min_size: PhysicalSize::new(64, 64),
+            max_size: PhysicalSize::new(1280, 960),
+            hang_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl ResizeStress {
+    pub fn frames(mut self, frames: u32) -> Self {
+        self.frames = frames.max(1);
+        self
+    }
+
+    pub fn requests_per_frame(mut self, requests_per_frame: u32) -> Self {
+        self.requests_per_frame = requests_per_frame.max(1);
+        self
+    }
+
+    pub fn hang_timeout(mut self, hang_timeout: Duration) -> Self {
+        self.hang_timeout = hang_timeout;
+        self
+    }
+
+    /// Frames the whole run takes, settling at both ends included
+    pub fn total_frames(&self) -> u32 {
+        self.settle_frames * 2 + self.frames
+    }
+
+    /// Sizes to request on frame, empty while settling
+    /// width and height sweep back and forth at different rates so most frames change both
+    pub fn sizes_at(&self, frame: u32) -> Vec<PhysicalSize<u32>> {
+        let resizing = self.settle_frames..self.settle_frames + self.frames;
+        if !resizing.contains(&frame) {
+            return Vec::new();
+        }
+
+        let step = (frame - self.settle_frames) * self.requests_per_frame;
+        (step..step + self.requests_per_frame)
+            .map(|request| {
+                PhysicalSize::new(
+                    sweep(request * 7, self.min_size.width, self.max_size.width),
+                    sweep(request * 5, self.min_size.height, self.max_size.height),
+                )
+            })
+            .collect()
+    }
+
+    /// Whether the memory baseline should be taken after frame
+    pub fn is_baseline_frame(&self, frame: u32) -> bool {
+        frame + 1 == self.settle_frames
+    }
+
+    /// Whether the window should go back to its starting size on frame
+    pub fn is_restore_frame(&self, frame: u32) -> bool {
+        frame == self.settle_frames + self.frames
+    }
+
+    pub fn check(&self, stats: &ResizeStressStats) -> Result<(), ResizeStressError> {
+        if stats.frames < self.total_frames() {
+            return Err(ResizeStressError::Interrupted(stats.frames));
+        }
+        if stats.swapchain_rebuilds > stats.frames as u64 {
+            return Err(ResizeStressError::Uncoalesced {
+                rebuilds: stats.swapchain_rebuilds,
+                frames: stats.frames,
+            });
+        }
+        if stats.slowest_frame > self.hang_timeout {
+            return Err(ResizeStressError::Hang(stats.slowest_frame));
+        }
+        // back at the starting size everything should be the size it was before
+        if stats.memory.allocations > stats.baseline.allocations
+            || stats.memory.allocated_bytes > stats.baseline.allocated_bytes
+        {
+            return Err(ResizeStressError::Leak {
+                before: stats.baseline,
+                after: stats.memory,
+            });
+        }
+        Ok(())
+    }
+}
+
+// triangle wave between min and max
+fn sweep(step: u32, min: u32, max: u32) -> u32 {
+    let range = max.saturating_sub(min).max(1);
+    let position = step % (range * 2);
+    min + if position < range {
+        position
+    } else {
+        range * 2 - position
+    }
+}
+
+/// Measured while the stress test runs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResizeStressStats {
+    pub frames: u32,
+    pub swapchain_rebuilds: u64,
+    pub slowest_frame: Duration,
+    /// device memory after settling at the starting size
+    pub baseline: AllocatorReport,
+    /// window size when the baseline was taken, restored before the final measurement
+    pub baseline_size: PhysicalSize<u32>,
+    /// device memory when the run finished
+    pub memory: AllocatorReport,
+}
+
+#[derive(Debug, Error)]
+pub enum ResizeStressError {
+    #[error("swapchain was rebuilt {rebuilds} times in {frames} frames")]
+    Uncoalesced { rebuilds: u64, frames: u32 },
+    #[error("a frame took {0:?}")]
+    Hang(Duration),
+    #[error("device memory grew from {before:?} to {after:?}")]
+    Leak {
+        before: AllocatorReport,
+        after: AllocatorReport,
+    },
+    #[error("window closed after {0} frames, before the stress test finished")]
+    Interrupted(u32),
+}
+
+impl ResizeStressError {
+    /// Process exit code, follows on from SmokeTestError's codes
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Uncoalesced { .. } => 7,
+            Self::Hang(_) => 8,
+            Self::Leak { .. } => 9,
+            Self::Interrupted(_) => 10,
+        }
+    }
+}
+
+#[test]
+fn resize_stress_test() {
+    let stress = ResizeStress::default().frames(100).requests_per_frame(3);
+    assert!(stress.sizes_at(0).is_empty());
+    assert!(stress.sizes_at(stress.total_frames() - 1).is_empty());
+
+    let sizes = stress.sizes_at(stress.settle_frames);
+    assert_eq!(sizes.len(), 3);
+    assert_eq!(sizes[0], stress.min_size);
+    for frame in 0..stress.total_frames() {
+        for size in stress.sizes_at(frame) {
+            assert!((stress.min_size.width..=stress.max_size.width).contains(&size.width));
+            assert!((stress.min_size.height..=stress.max_size.height).contains(&size.height));
+        }
+    }
+    assert!(stress.is_restore_frame(stress.settle_frames + 100));
+    assert_eq!(sweep(15, 10, 20), 15);
+    assert_eq!(sweep(25, 10, 20), 15);
+
+    let memory = AllocatorReport {
+        allocations: 10,
+        allocated_bytes: 1024,
+        ..Default::default()
+    };
+    let mut stats = ResizeStressStats {
+        frames: stress.total_frames(),
+        swapchain_rebuilds: 100,
+        slowest_frame: Duration::from_millis(20),
+        baseline: memory,
+        baseline_size: PhysicalSize::new(800, 600),
+        memory,
+    };
+    assert!(stress.check(&stats).is_ok());
+
+    stats.memory.allocations += 1;
+    assert!(matches!(
+        stress.check(&stats),
+        Err(ResizeStressError::Leak { .. })
+    ));
+    stats.memory = memory;
+
+    stats.swapchain_rebuilds = stats.frames as u64 + 1;
+    assert!(matches!(
+        stress.check(&stats),
+        Err(ResizeStressError::Uncoalesced { .. })
+    ));
+    stats.swapchain_rebuilds = 0;
+
+    stats.slowest_frame = Duration::from_secs(10);
+    assert!(matches!(
+        stress.check(&stats),
+        Err(ResizeStressError::Hang(_))
+    ));
+
+    stats.frames = 5;
+    assert!(matches!(
+        stress.check(&stats),
+        Err(ResizeStressError::Interrupted(5))
+    ));
+}
@@ -0,0 +1,657 @@
+use glam::{DVec3, Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::color::LinearRgba;
+use crate::lod::{LodGroup, LodMeshInfo, LodSelector};
+use crate::renderer::MeshInstance;
+use crate::renderer::material::{DEFAULT_MATERIAL, MaterialId};
+use crate::renderer::mesh::MeshId;
+use crate::renderer::skinning::InstanceAnimation;
+
+/// Translation, rotation and scale relative to the parent node
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self {
+            translation,
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn from_rotation(rotation: Quat) -> Self {
+        Self {
+            rotation,
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn from_scale(scale: Vec3) -> Self {
+        Self {
+            scale,
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Decomposes an affine matrix, shear is lost
+    pub fn from_matrix(matrix: Mat4) -> Self {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn with_translation(mut self, translation: Vec3) -> Self {
+        self.translation = translation;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Blend towards other, t of 0 is self and 1 is other, rotation takes the shortest arc
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+
+    /// Scale, then rotate, then translate
+    pub fn to_matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Index of a node in a Scene, stays valid until the node is removed
+pub type NodeId = usize;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum SceneError {
+    #[error("node {0} does not exist")]
+    MissingNode(NodeId),
+    #[error("node {parent} is a descendant of node {node} and can't be its parent")]
+    Cycle { node: NodeId, parent: NodeId },
+    #[error("node {0} is already in use")]
+    Occupied(NodeId),
+}
+
+/// Something placed in the scene, optionally drawing a mesh
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Node {
+    pub name: String,
+    pub transform: Transform,
+    pub mesh: Option<MeshId>,
+    pub material: MaterialId,
+    pub tint: LinearRgba,
+    /// replaces mesh with a level picked per view, see Scene::mesh_instances_with_lod
+    #[serde(default)]
+    pub lods: Option<LodGroup>,
+    /// how an animated mesh is posed, see VKSkinning
+    #[serde(default)]
+    pub animation: Option<InstanceAnimation>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    world_matrix: Mat4,
+}
+
+impl Node {
+    /// Empty node, useful as a pivot for its children
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            transform: Transform::IDENTITY,
+            mesh: None,
+            material: DEFAULT_MATERIAL,
+            tint: LinearRgba::WHITE,
+            lods: None,
+            animation: None,
+            parent: None,
+            children: Vec::new(),
+            world_matrix: Mat4::IDENTITY,
+        }
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_mesh(mut self, mesh: MeshId, material: MaterialId) -> Self {
+        self.mesh = Some(mesh);
+        self.material = material;
+        self
+    }
+
+    /// mesh is set to the most detailed level for views that don't pick one
+    pub fn with_lods(mut self, lods: LodGroup, material: MaterialId) -> Self {
+        self.mesh = lods.levels.first().map(|level| level.mesh);
+        self.lods = Some(lods);
+        self.material = material;
+        self
+    }
+
+    pub fn with_tint(mut self, tint: LinearRgba) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_animation(mut self, animation: InstanceAnimation) -> Self {
+        self.animation = Some(animation);
+        self
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    /// Local to world matrix as of the last Scene::update_world_matrices
+    /// world here is relative to the scene's origin, see Scene::rebase
+    pub fn world_matrix(&self) -> Mat4 {
+        self.world_matrix
+    }
+}
+
+/// Nodes removed by Scene::take_subtree
+#[derive(Clone, Debug)]
+pub struct SceneSubtree {
+    root: NodeId,
+    parent: Option<NodeId>,
+    // a root is put back where it was even if the scene was rebased since
+    origin: DVec3,
+    // place among the parent's children
+    index: usize,
+    nodes: Vec<(NodeId, Node)>,
+}
+
+impl SceneSubtree {
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes.iter().map(|(id, node)| (*id, node))
+    }
+}
+
+/// Hierarchy of nodes, a child's transform is relative to its parent
+/// and a root's to the scene origin, which is kept in f64 so large worlds can be rebased around the camera
+/// Example Use:
+/// ```
+/// use glam::Vec3;
+/// use vulkan_engine::renderer::material::DEFAULT_MATERIAL;
+/// use vulkan_engine::renderer::mesh::CUBE_MESH;
+/// use vulkan_engine::scene::{Node, Scene, Transform};
+///
+/// let mut scene = Scene::default();
+/// let planet = Node::new("planet").with_mesh(CUBE_MESH, DEFAULT_MATERIAL);
+/// let planet = scene.add(planet, None).unwrap();
+/// let moon = Node::new("moon")
+///     .with_mesh(CUBE_MESH, DEFAULT_MATERIAL)
+///     .with_transform(Transform::from_translation(Vec3::X * 2.0));
+/// scene.add(moon, Some(planet)).unwrap();
+///
+/// // each frame, after moving nodes
+/// scene.update_world_matrices();
+/// let instances = scene.mesh_instances();
+/// assert_eq!(instances.len(), 2);
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Scene {
+    // removed nodes leave a hole so other ids stay valid
+    nodes: Vec<Option<Node>>,
+    roots: Vec<NodeId>,
+    #[serde(default)]
+    origin: DVec3,
+}
+
+impl Scene {
+    /// Adds node as a child of parent, or as a root when parent is None
+    pub fn add(&mut self, mut node: Node, parent: Option<NodeId>) -> Result<NodeId, SceneError> {
+        if let Some(parent) = parent {
+            self.get(parent).ok_or(SceneError::MissingNode(parent))?;
+        }
+
+        let id = self.nodes.len();
+        node.parent = parent;
+        node.children.clear();
+        self.nodes.push(Some(node));
+        self.attach(id, parent);
+        Ok(id)
+    }
+
+    /// Removes node and everything below it
+    pub fn remove(&mut self, node: NodeId) -> Result<(), SceneError> {
+        self.take_subtree(node).map(drop)
+    }
+
+    /// Removes node and everything below it, keeping them so restore_subtree can put them back
+    pub fn take_subtree(&mut self, node: NodeId) -> Result<SceneSubtree, SceneError> {
+        let parent = self.get(node).ok_or(SceneError::MissingNode(node))?.parent;
+        let siblings = match parent.and_then(|parent| self.get(parent)) {
+            Some(parent) => &parent.children,
+            None => &self.roots,
+        };
+        let index = siblings
+            .iter()
+            .position(|&sibling| sibling == node)
+            .unwrap_or(siblings.len());
+        self.detach(node, parent);
+
+        let mut nodes = Vec::new();
+        let mut stack = vec![node];
+        while let Some(id) = stack.pop() {
+            if let Some(removed) = self.nodes[id].take() {
+                stack.extend(removed.children.iter().copied());
+                nodes.push((id, removed));
+            }
+        }
+        Ok(SceneSubtree {
+            root: node,
+            parent,
+            origin: self.origin,
+            index,
+            nodes,
+        })
+    }
+
+    /// Puts nodes taken by take_subtree back with their old ids and place among their siblings
+    /// fails if an id was reused or the parent is gone since
+    pub fn restore_subtree(&mut self, subtree: &SceneSubtree) -> Result<NodeId, SceneError> {
+        if let Some(parent) = subtree.parent {
+            self.get(parent).ok_or(SceneError::MissingNode(parent))?;
+        }
+        if let Some(&(id, _)) = subtree.nodes.iter().find(|(id, _)| self.get(*id).is_some()) {
+            return Err(SceneError::Occupied(id));
+        }
+
+        for (id, node) in &subtree.nodes {
+            if *id >= self.nodes.len() {
+                self.nodes.resize_with(id + 1, || None);
+            }
+            self.nodes[*id] = Some(node.clone());
+        }
+        if subtree.parent.is_none()
+            && let Some(root) = self.nodes[subtree.root].as_mut()
+        {
+            root.transform.translation += (subtree.origin - self.origin).as_vec3();
+        }
+        let siblings = match subtree
+            .parent
+            .and_then(|parent| self.nodes[parent].as_mut())
+        {
+            Some(parent) => &mut parent.children,
+            None => &mut self.roots,
+        };
+        siblings.insert(subtree.index.min(siblings.len()), subtree.root);
+        Ok(subtree.root)
+    }
+
+    /// Moves node under parent, or to the roots when parent is None
+    /// the local transform is kept, so the node moves with its new parent
+    pub fn set_parent(&mut self, node: NodeId, parent: Option<NodeId>) -> Result<(), SceneError> {
+        let old_parent = self.get(node).ok_or(SceneError::MissingNode(node))?.parent;
+
+        if let Some(parent) = parent {
+            self.get(parent).ok_or(SceneError::MissingNode(parent))?;
+            // walk up from the new parent, finding node means it would become its own ancestor
+            let mut ancestor = Some(parent);
+            while let Some(id) = ancestor {
+                if id == node {
+                    return Err(SceneError::Cycle { node, parent });
+                }
+                ancestor = self.nodes[id].as_ref().and_then(|ancestor| ancestor.parent);
+            }
+        }
+
+        self.detach(node, old_parent);
+        self.attach(node, parent);
+        if let Some(node) = self.get_mut(node) {
+            node.parent = parent;
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, node: NodeId) -> Option<&Node> {
+        self.nodes.get(node).and_then(Option::as_ref)
+    }
+
+    pub fn get_mut(&mut self, node: NodeId) -> Option<&mut Node> {
+        self.nodes.get_mut(node).and_then(Option::as_mut)
+    }
+
+    /// Nodes without a parent
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    /// Every live node with its id
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(id, node)| node.as_ref().map(|node| (id, node)))
+    }
+
+    /// Where world space position zero is, in f64 world coordinates
+    pub fn origin(&self) -> DVec3 {
+        self.origin
+    }
+
+    /// Position relative to the origin for a root's translation, the camera and anything else drawn
+    pub fn to_local(&self, world: DVec3) -> Vec3 {
+        (world - self.origin).as_vec3()
+    }
+
+    pub fn to_world(&self, local: Vec3) -> DVec3 {
+        self.origin + local.as_dvec3()
+    }
+
+    /// Moves the origin to origin, snapped to whole metres, and shifts the roots so nothing moves
+    /// returns the shift, to be subtracted from everything else kept relative to the old origin
+    /// like the camera or root transforms in an undo history
+    pub fn rebase(&mut self, origin: DVec3) -> Vec3 {
+        let origin = origin.round();
+        let shift = (origin - self.origin).as_vec3();
+        self.origin = origin;
+        if shift == Vec3::ZERO {
+            return shift;
+        }
+
+        for &root in &self.roots {
+            if let Some(node) = self.nodes[root].as_mut() {
+                node.transform.translation -= shift;
+            }
+        }
+        // every world matrix is relative to the origin, the hierarchy doesn't need walking again
+        for node in self.nodes.iter_mut().flatten() {
+            node.world_matrix.w_axis -= shift.extend(0.0);
+        }
+        shift
+    }
+
+    /// Rebases onto focus once it's more than distance from the origin, keeping
+    /// what's drawn near it in the range where f32 is precise
+    /// focus is relative to the current origin, usually the camera position
+    /// Example Use:
+    /// ```
+    /// use glam::{DVec3, Vec3};
+    /// use vulkan_engine::camera::Camera;
+    /// use vulkan_engine::scene::{Node, Scene, Transform};
+    ///
+    /// let mut scene = Scene::default();
+    /// // 500km out, too far for f32 to place things to the millimetre
+    /// let station = DVec3::new(500_000.0, 0.0, 0.0);
+    /// scene.rebase(station);
+    /// let dock = Transform::from_translation(scene.to_local(station + DVec3::X * 3.0));
+    /// let dock = scene.add(Node::new("dock").with_transform(dock), None).unwrap();
+    ///
+    /// let mut camera = Camera::default();
+    /// camera.position += Vec3::X * 3000.0;
+    /// if let Some(shift) = scene.rebase_around(camera.position, 2048.0) {
+    ///     camera.position -= shift;
+    /// }
+    /// assert_eq!(camera.position, Vec3::ZERO);
+    /// assert_eq!(scene.get(dock).unwrap().transform.translation, Vec3::X * -2997.0);
+    /// ```
+    pub fn rebase_around(&mut self, focus: Vec3, distance: f32) -> Option<Vec3> {
+        if focus.length() <= distance {
+            return None;
+        }
+        Some(self.rebase(self.to_world(focus)))
+    }
+
+    /// Recomputes every world matrix from the local transforms, call once per frame after moving nodes
+    pub fn update_world_matrices(&mut self) {
+        // parents are always visited before their children
+        let mut stack: Vec<(NodeId, Mat4)> = self
+            .roots
+            .iter()
+            .map(|&root| (root, Mat4::IDENTITY))
+            .collect();
+
+        while let Some((id, parent_matrix)) = stack.pop() {
+            let Some(node) = self.nodes[id].as_mut() else {
+                continue;
+            };
+            node.world_matrix = parent_matrix * node.transform.to_matrix();
+            let world_matrix = node.world_matrix;
+            stack.extend(node.children.iter().map(|&child| (child, world_matrix)));
+        }
+    }
+
+    /// (mesh, world matrix) pair for every node with a mesh, what VKRenderer::instances takes
+    pub fn mesh_instances(&self) -> Vec<MeshInstance> {
+        self.iter()
+            .filter_map(|(_, node)| {
+                node.mesh.map(|mesh| MeshInstance {
+                    mesh,
+                    transform: node.world_matrix,
+                    tint: node.tint,
+                    material: node.material,
+                    animation: node.animation,
+                })
+            })
+            .collect()
+    }
+
+    /// Like mesh_instances but nodes with lods draw the level selector picks for them
+    /// mesh_info gives the bounds and vertex count of a mesh, unknown meshes are drawn without counting
+    pub fn mesh_instances_with_lod(
+        &self,
+        selector: &LodSelector,
+        mesh_info: impl Fn(MeshId) -> Option<LodMeshInfo>,
+    ) -> Vec<MeshInstance> {
+        let mut instances = Vec::new();
+        let mut fixed_vertices = 0u32;
+        let mut lod_nodes = Vec::new();
+        let mut groups = Vec::new();
+        let mut vertex_counts = Vec::new();
+        for (_, node) in self.iter() {
+            let Some(mesh) = node.mesh else {
+                continue;
+            };
+            let instance = MeshInstance {
+                mesh,
+                transform: node.world_matrix,
+                tint: node.tint,
+                material: node.material,
+                animation: node.animation,
+            };
+            match &node.lods {
+                Some(lods) if !lods.levels.is_empty() => {
+                    // every level is expected to fill roughly the same space
+                    let coverage = mesh_info(lods.levels[0].mesh).map_or(1.0, |info| {
+                        selector.coverage(&info.bounds, &node.world_matrix)
+                    });
+                    lod_nodes.push((instances.len(), lods));
+                    groups.push((lods, coverage));
+                    vertex_counts.push(
+                        lods.levels
+                            .iter()
+                            .map(|level| mesh_info(level.mesh).map_or(0, |info| info.vertex_count))
+                            .collect(),
+                    );
+                }
+                _ => {
+                    fixed_vertices += mesh_info(mesh).map_or(0, |info| info.vertex_count);
+                }
+            }
+            instances.push(instance);
+        }
+
+        let selector = selector.with_vertex_budget(
+            selector
+                .vertex_budget
+                .map(|vertex_budget| vertex_budget.saturating_sub(fixed_vertices)),
+        );
+        let selected = selector.select(&groups, &vertex_counts);
+        for ((index, lods), level) in lod_nodes.into_iter().zip(selected) {
+            instances[index].mesh = lods.levels[level].mesh;
+        }
+        instances
+    }
+
+    fn attach(&mut self, node: NodeId, parent: Option<NodeId>) {
+        match parent.and_then(|parent| self.get_mut(parent)) {
+            Some(parent) => parent.children.push(node),
+            None => self.roots.push(node),
+        }
+    }
+
+    fn detach(&mut self, node: NodeId, parent: Option<NodeId>) {
+        let siblings = match parent.and_then(|parent| self.nodes[parent].as_mut()) {
+            Some(parent) => &mut parent.children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|&sibling| sibling != node);
+    }
+}
+
+#[test]
+fn scene_hierarchy_test() {
+    use glam::Vec4;
+
+    let mut scene = Scene::default();
+    let root = scene
+        .add(
+            Node::new("root").with_transform(
+                Transform::from_translation(Vec3::X)
+                    .with_rotation(Quat::from_rotation_y(std::f32::consts::FRAC_PI_2)),
+            ),
+            None,
+        )
+        .unwrap();
+    let child = scene
+        .add(
+            Node::new("child")
+                .with_mesh(0, DEFAULT_MATERIAL)
+                .with_transform(Transform::from_translation(Vec3::Z)),
+            Some(root),
+        )
+        .unwrap();
+    let grandchild = scene
+        .add(
+            Node::new("grandchild")
+                .with_mesh(0, DEFAULT_MATERIAL)
+                .with_transform(Transform::from_scale(Vec3::splat(2.0))),
+            Some(child),
+        )
+        .unwrap();
+
+    scene.update_world_matrices();
+
+    // child's +z offset is turned to +x by the parent rotation
+    let child_origin = scene.get(child).unwrap().world_matrix() * Vec4::W;
+    assert!(child_origin.abs_diff_eq(Vec4::new(2.0, 0.0, 0.0, 1.0), 1e-5));
+    let grandchild_matrix = scene.get(grandchild).unwrap().world_matrix();
+    assert!((grandchild_matrix.determinant() - 8.0).abs() < 1e-4);
+    assert_eq!(scene.mesh_instances().len(), 2);
+
+    // reparenting can't make a node its own ancestor
+    assert_eq!(
+        scene.set_parent(root, Some(grandchild)),
+        Err(SceneError::Cycle {
+            node: root,
+            parent: grandchild
+        })
+    );
+
+    scene.set_parent(grandchild, None).unwrap();
+    scene.update_world_matrices();
+    assert_eq!(scene.roots(), &[root, grandchild]);
+    assert_eq!(
+        scene.get(grandchild).unwrap().world_matrix(),
+        Mat4::from_scale(Vec3::splat(2.0))
+    );
+
+    scene.remove(root).unwrap();
+    assert!(scene.get(child).is_none());
+    assert_eq!(scene.roots(), &[grandchild]);
+    assert_eq!(scene.remove(root), Err(SceneError::MissingNode(root)));
+}
+
+#[test]
+fn scene_rebase_test() {
+    let mut scene = Scene::default();
+    let far = DVec3::new(10_000_000.0, 0.0, -3_000_000.0);
+    scene.rebase(far);
+    // f32 alone would be a metre off out here
+    let placed = far + DVec3::new(0.25, 0.0, 0.001);
+    let root = scene
+        .add(
+            Node::new("beacon")
+                .with_mesh(0, DEFAULT_MATERIAL)
+                .with_transform(Transform::from_translation(scene.to_local(placed))),
+            None,
+        )
+        .unwrap();
+    let child = scene
+        .add(
+            Node::new("light").with_transform(Transform::from_translation(Vec3::Y)),
+            Some(root),
+        )
+        .unwrap();
+    scene.update_world_matrices();
+    assert!(
+        (scene.to_world(scene.get(root).unwrap().transform.translation) - placed).length() < 1e-6
+    );
+
+    // close to the origin nothing happens, further away the roots move instead of the camera
+    assert_eq!(scene.rebase_around(Vec3::X * 100.0, 1000.0), None);
+    let shift = scene
+        .rebase_around(Vec3::new(1500.4, 0.0, 0.0), 1000.0)
+        .unwrap();
+    assert_eq!(shift, Vec3::X * 1500.0);
+    assert_eq!(scene.origin(), far + DVec3::X * 1500.0);
+    let world = scene.get(child).unwrap().world_matrix();
+    let expected = scene.to_local(placed) + Vec3::Y;
+    assert!(world.w_axis.truncate().abs_diff_eq(expected, 1e-4));
+    scene.update_world_matrices();
+    assert!(
+        scene
+            .get(child)
+            .unwrap()
+            .world_matrix()
+            .abs_diff_eq(world, 1e-4)
+    );
+
+    // a removed root comes back where it was after a rebase
+    let subtree = scene.take_subtree(root).unwrap();
+    scene.rebase(DVec3::ZERO);
+    scene.restore_subtree(&subtree).unwrap();
+    let restored = scene.to_world(scene.get(root).unwrap().transform.translation);
+    assert!((restored - placed).length() < 1.0);
+}
@@ -0,0 +1,123 @@
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+use crate::renderer::capture::VKCapture;
+
+/// Renders a few frames, captures one and checks something was drawn, see App::with_smoke_test
+/// Example Use:
+/// ```ignore
+/// let mut app = App::with_demo_scene(game_info, DemoScene::cube_grid(3, 2.0))
+///     .with_smoke_test(SmokeTest::default());
+/// app.start(&mut event_loop)?;
+/// std::process::exit(app.exit_code());
+/// ```
+#[derive(Clone, Debug)]
+pub struct SmokeTest {
+    /// frames presented before the capture, gives the swapchain and caches time to settle
+    pub frames: u32,
+    /// where the capture is saved, kept when the check fails so it can be looked at
+    pub output: PathBuf,
+}
+
+impl Default for SmokeTest {
+    fn default() -> Self {
+        Self {
+            frames: 10,
+            output: PathBuf::from("smoke-test.ppm"),
+        }
+    }
+}
+
+impl SmokeTest {
+    pub fn frames(mut self, frames: u32) -> Self {
+        self.frames = frames.max(1);
+        self
+    }
+
+    pub fn output(mut self, output: impl Into<PathBuf>) -> Self {
+        self.output = output.into();
+        self
+    }
+
+    /// Saves capture and checks it, Ok holds how many distinct colours it has
+    pub fn check(&self, capture: &VKCapture) -> Result<usize, SmokeTestError> {
+        let rgb = capture.to_rgb8().map_err(SmokeTestError::Save)?;
+        capture
+            .save_ppm(&self.output)
+            .map_err(SmokeTestError::Save)?;
+        check_pixels(&rgb)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SmokeTestError {
+    #[error("failed to capture a frame: {0}")]
+    Capture(String),
+    #[error("failed to save the capture: {0}")]
+    Save(io::Error),
+    #[error("capture has no pixels")]
+    Empty,
+    #[error("every pixel of the capture is {0:?}, nothing was drawn")]
+    Uniform([u8; 3]),
+    #[error("window closed after {0} frames, before the capture")]
+    Interrupted(u32),
+}
+
+impl SmokeTestError {
+    /// Process exit code, distinct per failure so scripts can tell them apart
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Capture(_) => 2,
+            Self::Save(_) => 3,
+            Self::Empty => 4,
+            Self::Uniform(_) => 5,
+            Self::Interrupted(_) => 6,
+        }
+    }
+}
+
+/// Checks packed 8bit RGB isn't empty or a single colour, returns the number of distinct colours
+/// Example Use:
+/// ```
+/// use vulkan_engine::smoke_test::check_pixels;
+///
+/// assert!(check_pixels(&[0, 0, 0, 0, 0, 0]).is_err());
+/// assert_eq!(check_pixels(&[0, 0, 0, 255, 128, 0]).unwrap(), 2);
+/// ```
+pub fn check_pixels(rgb: &[u8]) -> Result<usize, SmokeTestError> {
+    let mut pixels = rgb
+        .chunks_exact(3)
+        .map(|pixel| [pixel[0], pixel[1], pixel[2]]);
+    let Some(first) = pixels.next() else {
+        return Err(SmokeTestError::Empty);
+    };
+
+    let mut colors = std::collections::HashSet::from([first]);
+    colors.extend(pixels);
+    if colors.len() == 1 {
+        return Err(SmokeTestError::Uniform(first));
+    }
+    Ok(colors.len())
+}
+
+#[test]
+fn smoke_test_test() {
+    assert!(matches!(check_pixels(&[]), Err(SmokeTestError::Empty)));
+    // a trailing partial pixel doesn't count
+    assert!(matches!(check_pixels(&[1, 2]), Err(SmokeTestError::Empty)));
+    assert!(matches!(
+        check_pixels(&[10, 20, 30].repeat(64)),
+        Err(SmokeTestError::Uniform([10, 20, 30]))
+    ));
+
+    let mut rgb = [0u8, 0, 0].repeat(63);
+    rgb.extend([0, 0, 1]);
+    assert_eq!(check_pixels(&rgb).unwrap(), 2);
+
+    assert_eq!(SmokeTest::default().frames(0).frames, 1);
+    assert_ne!(
+        SmokeTestError::Empty.exit_code(),
+        SmokeTestError::Uniform([0; 3]).exit_code()
+    );
+}
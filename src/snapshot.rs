@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::{fs, io};
+use thiserror::Error;
+
+use crate::camera::{Camera, DepthConvention};
+use crate::color::LinearRgba;
+use crate::lighting::Lighting;
+use crate::renderer::bloom::BloomSettings;
+use crate::renderer::ssao::AmbientOcclusionSettings;
+use crate::scene::Scene;
+
+/// Bumped whenever EngineSnapshot changes in a way old files can't be read as
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("failed to access snapshot: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to parse snapshot: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("failed to write snapshot: {0}")]
+    Serialize(#[from] ron::Error),
+    #[error("snapshot version {found} can't be restored, expected {SNAPSHOT_VERSION}")]
+    Version { found: u32 },
+}
+
+/// Renderer state that changes how a frame looks
+/// depth_convention and msaa_samples are fixed when the renderer is created,
+/// restoring only warns when they differ from the running renderer's
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RenderSettings {
+    pub clear_color: LinearRgba,
+    pub lighting: Lighting,
+    pub bloom: Option<BloomSettings>,
+    pub ambient_occlusion: Option<AmbientOcclusionSettings>,
+    pub ray_query_shadows: bool,
+    pub depth_convention: DepthConvention,
+    /// samples per pixel the scene was drawn with, 1 without msaa
+    pub msaa_samples: u32,
+}
+
+/// Runtime state of the engine for quick saves and reproducing bugs
+/// mesh and material ids are stored as is, so they only mean the same thing
+/// when assets are loaded in the same order as when the snapshot was taken
+/// Example Use:
+/// ```ignore
+/// app_ctx.snapshot().save("quicksave.ron")?;
+/// // later, or on another machine
+/// app_ctx.restore(EngineSnapshot::load("quicksave.ron")?);
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EngineSnapshot {
+    pub version: u32,
+    /// game clock seconds, what animations are driven by
+    pub time: f32,
+    pub scene: Scene,
+    pub camera: Camera,
+    pub render_settings: RenderSettings,
+    /// named seeds for the game's random number generators
+    /// re-seeding from these makes random events play out the same way again
+    pub rng_seeds: BTreeMap<String, u64>,
+}
+
+impl EngineSnapshot {
+    pub fn new(time: f32, scene: Scene, camera: Camera, render_settings: RenderSettings) -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            time,
+            scene,
+            camera,
+            render_settings,
+            rng_seeds: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_rng_seed(mut self, name: impl Into<String>, seed: u64) -> Self {
+        self.rng_seeds.insert(name.into(), seed);
+        self
+    }
+
+    pub fn to_ron(&self) -> Result<String, SnapshotError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    /// Fails on snapshots from another SNAPSHOT_VERSION
+    pub fn from_ron(source: &str) -> Result<Self, SnapshotError> {
+        let snapshot: Self = ron::from_str(source)?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::Version {
+                found: snapshot.version,
+            });
+        }
+        Ok(snapshot)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        Ok(fs::write(path, self.to_ron()?)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        Self::from_ron(&fs::read_to_string(path)?)
+    }
+}
+
+#[test]
+fn snapshot_test() {
+    use crate::lighting::Light;
+    use crate::scene::{Node, Transform};
+    use glam::Vec3;
+
+    let mut scene = Scene::default();
+    let parent = scene
+        .add(
+            Node::new("parent").with_transform(Transform::from_translation(Vec3::Y)),
+            None,
+        )
+        .unwrap();
+    scene
+        .add(Node::new("child").with_mesh(0, 0), Some(parent))
+        .unwrap();
+
+    let mut lighting = Lighting::default();
+    lighting.add(Light::point(Vec3::ONE, 4.0).with_intensity(2.0));
+
+    let camera = Camera::default().look_at(Vec3::new(0.0, 1.0, 5.0), Vec3::ZERO, Vec3::Y);
+    let snapshot = EngineSnapshot::new(
+        12.5,
+        scene,
+        camera,
+        RenderSettings {
+            clear_color: LinearRgba::BLACK,
+            lighting,
+            bloom: Some(BloomSettings::default().levels(3)),
+            ambient_occlusion: None,
+            ray_query_shadows: true,
+            depth_convention: DepthConvention::Forward,
+            msaa_samples: 4,
+        },
+    )
+    .with_rng_seed("loot", 42);
+
+    let restored = EngineSnapshot::from_ron(&snapshot.to_ron().unwrap()).unwrap();
+    assert_eq!(restored.time, 12.5);
+    assert_eq!(restored.camera, camera);
+    assert_eq!(restored.rng_seeds["loot"], 42);
+    let settings = &restored.render_settings;
+    assert_eq!(settings.lighting.iter().count(), 1);
+    assert_eq!(settings.bloom.map(|bloom| bloom.levels), Some(3));
+    assert_eq!(settings.ambient_occlusion, None);
+    assert!(settings.ray_query_shadows);
+    assert_eq!(settings.depth_convention, DepthConvention::Forward);
+    assert_eq!(settings.msaa_samples, 4);
+
+    // hierarchy survives, world matrices are recomputed as usual
+    let mut scene = restored.scene;
+    scene.update_world_matrices();
+    let (_, child) = scene.iter().find(|(_, node)| node.name == "child").unwrap();
+    assert_eq!(child.parent(), Some(parent));
+    assert!(
+        child
+            .world_matrix()
+            .w_axis
+            .truncate()
+            .abs_diff_eq(Vec3::Y, 1e-6)
+    );
+
+    let old = snapshot
+        .to_ron()
+        .unwrap()
+        .replace("version: 2", "version: 1");
+    assert!(matches!(
+        EngineSnapshot::from_ron(&old),
+        Err(SnapshotError::Version { found: 1 })
+    ));
+}
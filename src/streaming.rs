@@ -0,0 +1,416 @@
+use glam::{Vec2, Vec3};
+use log::warn;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, JoinHandle};
+
+/// A square column of the world on the xz plane, cells are cell_size wide
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CellCoord {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl CellCoord {
+    pub fn new(x: i32, z: i32) -> Self {
+        Self { x, z }
+    }
+
+    /// Cell position is inside, height is ignored
+    pub fn containing(position: Vec3, cell_size: f32) -> Self {
+        Self {
+            x: (position.x / cell_size).floor() as i32,
+            z: (position.z / cell_size).floor() as i32,
+        }
+    }
+
+    /// Corner with the smallest x and z
+    pub fn origin(&self, cell_size: f32) -> Vec3 {
+        Vec3::new(self.x as f32 * cell_size, 0.0, self.z as f32 * cell_size)
+    }
+
+    pub fn center(&self, cell_size: f32) -> Vec3 {
+        self.origin(cell_size) + Vec3::new(cell_size, 0.0, cell_size) * 0.5
+    }
+
+    /// Horizontal distance from position to the closest point of the cell, 0 inside it
+    pub fn distance(&self, position: Vec3, cell_size: f32) -> f32 {
+        let min = Vec2::new(self.x as f32, self.z as f32) * cell_size;
+        let point = Vec2::new(position.x, position.z);
+        point.distance(point.clamp(min, min + Vec2::splat(cell_size)))
+    }
+}
+
+/// An asset that may still be loading, shared between the streamer and whatever draws it
+/// resolves to a placeholder until the data arrives
+/// Example Use:
+/// ```ignore
+/// // draws the cube until the rock has been streamed in and uploaded
+/// let mesh = *rock.resolve(&CUBE_MESH);
+/// ```
+pub struct StreamHandle<T>(Arc<OnceLock<T>>);
+
+impl<T> StreamHandle<T> {
+    pub fn pending() -> Self {
+        Self(Arc::new(OnceLock::new()))
+    }
+
+    pub fn loaded(value: T) -> Self {
+        Self(Arc::new(OnceLock::from(value)))
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.0.get().is_some()
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.0.get()
+    }
+
+    /// The loaded value, or placeholder while it's pending
+    pub fn resolve<'a>(&'a self, placeholder: &'a T) -> &'a T {
+        self.0.get().unwrap_or(placeholder)
+    }
+
+    /// Fills a pending handle, every clone sees the value
+    /// gives value back if the handle was already loaded
+    pub fn set(&self, value: T) -> Result<(), T> {
+        self.0.set(value)
+    }
+
+    /// Whether both refer to the same asset
+    pub fn same(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// derive would require T: Clone
+impl<T> Clone for StreamHandle<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Default for StreamHandle<T> {
+    fn default() -> Self {
+        Self::pending()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellState {
+    Loading,
+    Loaded,
+    /// not retried until the cell is unloaded and comes back in range
+    Failed,
+}
+
+struct StreamedCell<T> {
+    state: CellState,
+    handle: StreamHandle<T>,
+}
+
+/// What changed in the last CellStreamer::update
+pub struct StreamUpdate<T> {
+    /// finished loading, their handles are filled
+    pub loaded: Vec<CellCoord>,
+    pub failed: Vec<CellCoord>,
+    /// out of range and forgotten, handed back so gpu resources made from them can be freed
+    pub unloaded: Vec<(CellCoord, StreamHandle<T>)>,
+}
+
+type CellRequest<T> = (CellCoord, StreamHandle<T>);
+
+/// Loads the cells around the camera on a worker thread and forgets the ones left behind
+/// cells unload further out than they load so walking along a border doesn't reload them
+/// loader runs on the worker, gpu uploads have to happen on the render thread after update
+/// Example Use:
+/// ```ignore
+/// let mut streamer = CellStreamer::new(64.0, |cell| CompressedMesh::read(&fs::read(cell_path(cell))?))
+///     .load_radius(128.0)
+///     .unload_radius(192.0);
+/// // every frame
+/// let update = streamer.update(camera.position);
+/// for cell in update.loaded {
+///     let mesh = streamer.get(cell).unwrap();
+///     cell_meshes.insert(cell, renderer.add_compressed_mesh(mesh)?);
+/// }
+/// for (cell, _) in update.unloaded {
+///     cell_meshes.remove(&cell);
+/// }
+/// ```
+pub struct CellStreamer<T> {
+    pub cell_size: f32,
+    /// cells closer than this are loaded
+    pub load_radius: f32,
+    /// loaded cells further than this are unloaded, kept at least load_radius
+    pub unload_radius: f32,
+    cells: HashMap<CellCoord, StreamedCell<T>>,
+    requests: Option<Sender<CellRequest<T>>>,
+    finished: Receiver<(CellCoord, StreamHandle<T>, bool)>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<T: Send + Sync + 'static> CellStreamer<T> {
+    /// Starts the worker, load radius defaults to one cell around the camera
+    pub fn new<E, F>(cell_size: f32, loader: F) -> Self
+    where
+        E: Display,
+        F: Fn(CellCoord) -> Result<T, E> + Send + 'static,
+    {
+        let (requests, pending) = mpsc::channel::<CellRequest<T>>();
+        let (finish, finished) = mpsc::channel();
+
+        let worker = thread::Builder::new()
+            .name("Cell Streamer".into())
+            .spawn(move || {
+                for (cell, handle) in pending {
+                    // the streamer dropped its handle, the cell went out of range while queued
+                    if Arc::strong_count(&handle.0) == 1 {
+                        continue;
+                    }
+                    let loaded = match loader(cell) {
+                        Ok(value) => handle.set(value).is_ok(),
+                        Err(error) => {
+                            warn!("Failed To Stream Cell {}, {}: {error}", cell.x, cell.z);
+                            false
+                        }
+                    };
+                    if finish.send((cell, handle, loaded)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .inspect_err(|error| warn!("Failed To Start Cell Streamer: {error}"))
+            .ok();
+
+        Self {
+            cell_size,
+            load_radius: cell_size,
+            unload_radius: cell_size * 1.5,
+            cells: HashMap::new(),
+            requests: Some(requests),
+            finished,
+            worker,
+        }
+    }
+
+    pub fn load_radius(mut self, load_radius: f32) -> Self {
+        self.load_radius = load_radius;
+        self.unload_radius = self.unload_radius.max(load_radius);
+        self
+    }
+
+    pub fn unload_radius(mut self, unload_radius: f32) -> Self {
+        self.unload_radius = unload_radius.max(self.load_radius);
+        self
+    }
+
+    /// Requests cells that came into range nearest first, unloads those out of range
+    /// and collects what the worker finished since the last update
+    pub fn update(&mut self, position: Vec3) -> StreamUpdate<T> {
+        let mut update = StreamUpdate {
+            loaded: Vec::new(),
+            failed: Vec::new(),
+            unloaded: Vec::new(),
+        };
+
+        while let Ok((cell, handle, loaded)) = self.finished.try_recv() {
+            // results for a handle that was unloaded since are stale
+            let Some(streamed) = self.cells.get_mut(&cell) else {
+                continue;
+            };
+            if !streamed.handle.same(&handle) {
+                continue;
+            }
+            if loaded {
+                streamed.state = CellState::Loaded;
+                update.loaded.push(cell);
+            } else {
+                streamed.state = CellState::Failed;
+                update.failed.push(cell);
+            }
+        }
+
+        let (cell_size, unload_radius) = (self.cell_size, self.unload_radius);
+        let mut unloaded: Vec<CellCoord> = self
+            .cells
+            .keys()
+            .filter(|cell| cell.distance(position, cell_size) > unload_radius)
+            .copied()
+            .collect();
+        unloaded.sort();
+        for cell in unloaded {
+            if let Some(streamed) = self.cells.remove(&cell) {
+                update.unloaded.push((cell, streamed.handle));
+            }
+        }
+
+        let mut wanted = self.cells_in_range(position);
+        wanted.retain(|cell| !self.cells.contains_key(cell));
+        wanted.sort_by(|a, b| {
+            a.distance(position, cell_size)
+                .total_cmp(&b.distance(position, cell_size))
+                .then(a.cmp(b))
+        });
+        for cell in wanted {
+            let handle = StreamHandle::pending();
+            let sent = self
+                .requests
+                .as_ref()
+                .is_some_and(|requests| requests.send((cell, handle.clone())).is_ok());
+            // without a worker the cell can never load
+            let state = if sent {
+                CellState::Loading
+            } else {
+                CellState::Failed
+            };
+            self.cells.insert(cell, StreamedCell { state, handle });
+        }
+
+        update
+    }
+
+    /// Cells whose closest point is within load_radius of position
+    pub fn cells_in_range(&self, position: Vec3) -> Vec<CellCoord> {
+        let center = CellCoord::containing(position, self.cell_size);
+        let reach = (self.load_radius / self.cell_size).ceil() as i32;
+        (-reach..=reach)
+            .flat_map(|z| (-reach..=reach).map(move |x| CellCoord::new(center.x + x, center.z + z)))
+            .filter(|cell| cell.distance(position, self.cell_size) <= self.load_radius)
+            .collect()
+    }
+
+    pub fn state(&self, cell: CellCoord) -> Option<CellState> {
+        self.cells.get(&cell).map(|streamed| streamed.state)
+    }
+
+    /// Handle of a cell in range, pending while it loads
+    pub fn handle(&self, cell: CellCoord) -> Option<StreamHandle<T>> {
+        self.cells
+            .get(&cell)
+            .map(|streamed| streamed.handle.clone())
+    }
+
+    /// Contents of a loaded cell
+    pub fn get(&self, cell: CellCoord) -> Option<&T> {
+        self.cells.get(&cell)?.handle.get()
+    }
+
+    /// Loaded cells with their contents
+    pub fn loaded(&self) -> impl Iterator<Item = (CellCoord, &T)> {
+        self.cells
+            .iter()
+            .filter_map(|(cell, streamed)| Some((*cell, streamed.handle.get()?)))
+    }
+
+    /// Cells requested and not finished yet
+    pub fn loading(&self) -> usize {
+        self.cells
+            .values()
+            .filter(|streamed| streamed.state == CellState::Loading)
+            .count()
+    }
+}
+
+impl<T> Drop for CellStreamer<T> {
+    fn drop(&mut self) {
+        // closing the channel ends the worker once it finishes the cell it's on
+        self.requests = None;
+        self.cells.clear();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[test]
+fn streaming_test() {
+    use std::time::{Duration, Instant};
+
+    assert_eq!(
+        CellCoord::containing(Vec3::new(-0.5, 3.0, 10.0), 10.0),
+        CellCoord::new(-1, 1)
+    );
+    assert_eq!(
+        CellCoord::new(0, 0).distance(Vec3::new(5.0, 100.0, 5.0), 10.0),
+        0.0
+    );
+    assert_eq!(
+        CellCoord::new(2, 0).distance(Vec3::new(5.0, 0.0, 5.0), 10.0),
+        15.0
+    );
+
+    let handle = StreamHandle::pending();
+    assert_eq!(*handle.resolve(&0), 0);
+    let shared = handle.clone();
+    handle.set(7).unwrap();
+    assert_eq!(*shared.resolve(&0), 7);
+    assert_eq!(handle.set(8), Err(8));
+
+    // cells with a negative x fail to load
+    let mut streamer = CellStreamer::new(10.0, |cell: CellCoord| {
+        if cell.x < 0 {
+            Err("missing")
+        } else {
+            Ok(cell.x * 100 + cell.z)
+        }
+    })
+    .load_radius(4.0)
+    .unload_radius(12.0);
+
+    // near the corner of the 0, 0 cell, its neighbours are within 4
+    let position = Vec3::new(1.0, 0.0, 1.0);
+    let mut expected = vec![
+        CellCoord::new(-1, -1),
+        CellCoord::new(-1, 0),
+        CellCoord::new(0, -1),
+        CellCoord::new(0, 0),
+    ];
+    let mut in_range = streamer.cells_in_range(position);
+    in_range.sort();
+    assert_eq!(in_range, expected);
+
+    let first = streamer.update(position);
+    assert!(first.loaded.is_empty() && first.unloaded.is_empty());
+    let origin = streamer.handle(CellCoord::new(0, 0)).unwrap();
+    let mut finished = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while streamer.loading() > 0 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(1));
+        let update = streamer.update(position);
+        finished.extend(update.loaded);
+        finished.extend(update.failed);
+    }
+    finished.sort();
+    assert_eq!(finished, expected);
+    assert_eq!(*origin.resolve(&-1), 0);
+    assert_eq!(streamer.get(CellCoord::new(0, -1)), Some(&-1));
+    assert_eq!(
+        streamer.state(CellCoord::new(-1, 0)),
+        Some(CellState::Failed)
+    );
+    assert_eq!(streamer.loaded().count(), 2);
+
+    // within the unload radius nothing is dropped
+    assert!(
+        streamer
+            .update(Vec3::new(-5.0, 0.0, 1.0))
+            .unloaded
+            .is_empty()
+    );
+
+    // far away everything is unloaded and the new cell is requested
+    let far = streamer.update(Vec3::new(105.0, 0.0, 105.0));
+    let mut unloaded: Vec<CellCoord> = far.unloaded.iter().map(|(cell, _)| *cell).collect();
+    unloaded.sort();
+    expected.sort();
+    assert_eq!(unloaded, expected);
+    assert_eq!(
+        streamer.state(CellCoord::new(10, 10)),
+        Some(CellState::Loading)
+    );
+    assert_eq!(streamer.state(CellCoord::new(0, 0)), None);
+}
@@ -0,0 +1,246 @@
+use winit::event::{ElementState, Ime, KeyEvent};
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
+
+/// Text being composed by an input method, shown in place before it's committed
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Preedit {
+    pub text: String,
+    /// byte range of the IME's cursor or selection in text, None hides the cursor
+    pub cursor: Option<(usize, usize)>,
+}
+
+/// Text typed while a text field has focus, from plain key presses or an input method
+/// the ui drains committed text each frame and draws the preedit at its cursor
+/// Example Use:
+/// ```
+/// use vulkan_engine::text_input::TextInput;
+/// use winit::event::Ime;
+///
+/// let mut input = TextInput::default();
+/// input.set_active(true);
+///
+/// // typing にほん with a japanese IME
+/// input.handle_ime(&Ime::Preedit("にほ".to_string(), Some((6, 6))));
+/// assert_eq!(input.preedit().unwrap().text, "にほ");
+/// input.handle_ime(&Ime::Commit("日本".to_string()));
+///
+/// let mut field = String::new();
+/// field.push_str(&input.take_committed());
+/// assert_eq!(field, "日本");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TextInput {
+    active: bool,
+    committed: String,
+    preedit: Option<Preedit>,
+    /// held modifiers, ctrl or the command key turn v into paste
+    modifiers: ModifiersState,
+    clipboard: Clipboard,
+}
+
+// the system clipboard, opened on first use
+// on x11 and wayland copied text only lasts as long as the handle, so it is kept
+#[derive(Default)]
+struct Clipboard {
+    #[cfg(feature = "clipboard")]
+    handle: Option<arboard::Clipboard>,
+}
+
+impl Clipboard {
+    #[cfg(feature = "clipboard")]
+    fn handle(&mut self) -> Option<&mut arboard::Clipboard> {
+        if self.handle.is_none() {
+            match arboard::Clipboard::new() {
+                Ok(handle) => self.handle = Some(handle),
+                Err(error) => log::warn!("Failed to Open Clipboard: {error}"),
+            }
+        }
+        self.handle.as_mut()
+    }
+
+    #[cfg(feature = "clipboard")]
+    fn get_text(&mut self) -> Option<String> {
+        self.handle()?
+            .get_text()
+            .inspect_err(|error| log::warn!("Failed to Paste: {error}"))
+            .ok()
+    }
+
+    #[cfg(not(feature = "clipboard"))]
+    fn get_text(&mut self) -> Option<String> {
+        None
+    }
+
+    #[cfg(feature = "clipboard")]
+    fn set_text(&mut self, text: &str) -> bool {
+        self.handle()
+            .map(|handle| handle.set_text(text))
+            .is_some_and(|result| {
+                result
+                    .inspect_err(|error| log::warn!("Failed to Copy: {error}"))
+                    .is_ok()
+            })
+    }
+
+    #[cfg(not(feature = "clipboard"))]
+    fn set_text(&mut self, _text: &str) -> bool {
+        false
+    }
+}
+
+// the handle is per process state, a copy opens its own
+impl Clone for Clipboard {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl std::fmt::Debug for Clipboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Clipboard")
+    }
+}
+
+impl TextInput {
+    /// Starts or stops taking text, e.g. when a text field gains or loses focus
+    /// stopping drops any composition in progress
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+        if !active {
+            self.preedit = None;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Whether an input method is composing text, key presses belong to it rather than the game
+    pub fn is_composing(&self) -> bool {
+        self.preedit.is_some()
+    }
+
+    /// Applies a winit IME event, returns true if it changed the text
+    pub fn handle_ime(&mut self, ime: &Ime) -> bool {
+        match ime {
+            Ime::Enabled => false,
+            // the input method went away mid composition
+            Ime::Disabled => self.preedit.take().is_some(),
+            _ if !self.active => false,
+            Ime::Preedit(text, cursor) => {
+                // an empty preedit ends the composition
+                self.preedit = (!text.is_empty()).then(|| Preedit {
+                    text: text.clone(),
+                    cursor: *cursor,
+                });
+                true
+            }
+            Ime::Commit(text) => {
+                self.preedit = None;
+                self.committed.push_str(text);
+                true
+            }
+        }
+    }
+
+    /// Tracks the held modifiers from winit's ModifiersChanged, for the clipboard shortcuts
+    pub fn set_modifiers(&mut self, modifiers: ModifiersState) {
+        self.modifiers = modifiers;
+    }
+
+    /// Whether ctrl, or the command key on macos, is held so keys are shortcuts rather than text
+    pub fn shortcut_held(&self) -> bool {
+        if cfg!(target_os = "macos") {
+            self.modifiers.super_key()
+        } else {
+            self.modifiers.control_key()
+        }
+    }
+
+    /// Takes text from a key press when no input method is composing, returns true if any was added
+    /// control characters like backspace and enter are left for the ui to handle as keys
+    /// the paste shortcut adds the clipboard's text instead
+    pub fn handle_key(&mut self, event: &KeyEvent) -> bool {
+        if !self.active || self.is_composing() || event.state != ElementState::Pressed {
+            return false;
+        }
+        if self.shortcut_held() {
+            return event.physical_key == PhysicalKey::Code(KeyCode::KeyV) && self.paste();
+        }
+        let Some(text) = &event.text else {
+            return false;
+        };
+
+        self.push_text(text)
+    }
+
+    /// Adds the clipboard's text as if it was typed, returns true if any was added
+    /// always false without the clipboard feature
+    pub fn paste(&mut self) -> bool {
+        if !self.active || self.is_composing() {
+            return false;
+        }
+        match self.clipboard.get_text() {
+            Some(text) => self.push_text(&text),
+            None => false,
+        }
+    }
+
+    /// Puts text on the system clipboard, returns true if it got there
+    /// always false without the clipboard feature
+    pub fn copy(&mut self, text: &str) -> bool {
+        self.clipboard.set_text(text)
+    }
+
+    // adds text without control characters, returns true if any was added
+    fn push_text(&mut self, text: &str) -> bool {
+        let before = self.committed.len();
+        self.committed
+            .extend(text.chars().filter(|character| !character.is_control()));
+        self.committed.len() != before
+    }
+
+    /// Text committed since the last call
+    pub fn take_committed(&mut self) -> String {
+        std::mem::take(&mut self.committed)
+    }
+
+    /// Composition to draw at the text cursor
+    pub fn preedit(&self) -> Option<&Preedit> {
+        self.preedit.as_ref()
+    }
+}
+
+#[test]
+fn text_input_test() {
+    let mut input = TextInput::default();
+
+    // ignored until a text field is focused
+    assert!(!input.handle_ime(&Ime::Commit("a".to_string())));
+    input.set_active(true);
+
+    assert!(!input.handle_ime(&Ime::Enabled));
+    input.handle_ime(&Ime::Preedit("한".to_string(), Some((0, 3))));
+    assert!(input.is_composing());
+    assert_eq!(input.preedit().unwrap().cursor, Some((0, 3)));
+
+    // cleared composition
+    input.handle_ime(&Ime::Preedit(String::new(), None));
+    assert!(input.preedit().is_none());
+
+    input.handle_ime(&Ime::Preedit("한국".to_string(), None));
+    input.handle_ime(&Ime::Commit("한국".to_string()));
+    assert!(!input.is_composing());
+    assert_eq!(input.take_committed(), "한국");
+    assert_eq!(input.take_committed(), "");
+
+    // losing focus drops the composition
+    input.handle_ime(&Ime::Preedit("x".to_string(), None));
+    input.set_active(false);
+    assert!(input.preedit().is_none());
+
+    // pasting needs a focused field, so no clipboard is opened here
+    assert!(!input.paste());
+    input.set_modifiers(ModifiersState::CONTROL | ModifiersState::SUPER);
+    assert!(input.shortcut_held());
+}
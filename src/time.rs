@@ -0,0 +1,143 @@
+use std::time::{Duration, Instant};
+
+// longest step a single frame can take, so a breakpoint or a stalled window doesn't teleport everything
+const MAX_FRAME_DELTA: Duration = Duration::from_millis(250);
+
+/// Game time that can be paused, slowed down and stepped a frame at a time
+/// animations and game logic read this instead of the wall clock, the renderer keeps presenting either way
+/// Example Use:
+/// ```
+/// use std::time::{Duration, Instant};
+/// use vulkan_engine::time::GameClock;
+///
+/// let start = Instant::now();
+/// let mut clock = GameClock::default();
+/// clock.tick(start);
+///
+/// clock.time_scale = 0.5;
+/// let delta = clock.tick(start + Duration::from_millis(100));
+/// assert!((delta - 0.05).abs() < 1e-6);
+///
+/// // paused frames don't advance time until a step is asked for
+/// clock.pause();
+/// clock.step();
+/// ```
+#[derive(Clone, Debug)]
+pub struct GameClock {
+    /// multiplies real time, 0.5 is half speed
+    pub time_scale: f32,
+    /// delta handed out for a step while paused, in game seconds
+    pub step_delta: f32,
+    paused: bool,
+    step_requested: bool,
+    elapsed: f64,
+    delta: f32,
+    last_tick: Option<Instant>,
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        Self {
+            time_scale: 1.0,
+            step_delta: 1.0 / 60.0,
+            paused: false,
+            step_requested: false,
+            elapsed: 0.0,
+            delta: 0.0,
+            last_tick: None,
+        }
+    }
+}
+
+impl GameClock {
+    /// Advances game time to now, call once per frame
+    /// returns the game time that passed, 0 while paused unless a step was asked for
+    pub fn tick(&mut self, now: Instant) -> f32 {
+        let real_delta = self
+            .last_tick
+            .map(|last_tick| {
+                now.saturating_duration_since(last_tick)
+                    .min(MAX_FRAME_DELTA)
+            })
+            .unwrap_or_default();
+        self.last_tick = Some(now);
+
+        self.delta = if !self.paused {
+            real_delta.as_secs_f32() * self.time_scale.max(0.0)
+        } else if self.step_requested {
+            self.step_delta
+        } else {
+            0.0
+        };
+        self.step_requested = false;
+
+        self.elapsed += self.delta as f64;
+        self.delta
+    }
+
+    /// Game seconds since the clock started
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Jumps to time, e.g. when restoring a snapshot
+    pub fn set_elapsed(&mut self, elapsed: f64) {
+        self.elapsed = elapsed.max(0.0);
+    }
+
+    /// Game time that passed in the last tick
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Advances the next tick by step_delta, only does anything while paused
+    pub fn step(&mut self) {
+        self.step_requested = self.paused;
+    }
+}
+
+#[test]
+fn game_clock_test() {
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+
+    let mut clock = GameClock::default();
+    assert_eq!(clock.tick(at(0)), 0.0);
+    assert!((clock.tick(at(100)) - 0.1).abs() < 1e-6);
+
+    clock.time_scale = 2.0;
+    assert!((clock.tick(at(200)) - 0.2).abs() < 1e-6);
+    assert!((clock.elapsed() - 0.3).abs() < 1e-6);
+
+    // long stalls are clamped
+    clock.time_scale = 1.0;
+    assert!((clock.tick(at(10_000)) - 0.25).abs() < 1e-6);
+
+    clock.pause();
+    assert_eq!(clock.tick(at(10_100)), 0.0);
+    clock.step();
+    assert_eq!(clock.tick(at(10_200)), clock.step_delta);
+    // one step is one frame
+    assert_eq!(clock.tick(at(10_300)), 0.0);
+
+    // stepping while running does nothing extra
+    clock.resume();
+    clock.step();
+    assert!((clock.tick(at(10_400)) - 0.1).abs() < 1e-6);
+}
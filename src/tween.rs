@@ -0,0 +1,510 @@
+use glam::{Quat, Vec2, Vec3, Vec4};
+use std::f32::consts::{PI, TAU};
+
+use crate::color::LinearRgba;
+use crate::scene::Transform;
+
+/// Easing curve, maps linear progress in 0..1 onto eased progress
+/// back and elastic overshoot outside 0..1 on purpose
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Ease {
+    #[default]
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineInOut,
+    ExpoOut,
+    /// pulls back before starting
+    BackIn,
+    /// overshoots then settles
+    BackOut,
+    ElasticOut,
+    BounceOut,
+}
+
+impl Ease {
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        // overshoot of the back curves
+        const BACK: f32 = 1.70158;
+        match self {
+            Ease::Linear => t,
+            Ease::QuadIn => t * t,
+            Ease::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Ease::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) * 0.5
+                }
+            }
+            Ease::CubicIn => t * t * t,
+            Ease::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Ease::CubicInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) * 0.5
+                }
+            }
+            Ease::SineInOut => -((PI * t).cos() - 1.0) * 0.5,
+            Ease::ExpoOut => {
+                if t >= 1.0 {
+                    1.0
+                } else {
+                    1.0 - 2.0_f32.powf(-10.0 * t)
+                }
+            }
+            Ease::BackIn => (BACK + 1.0) * t * t * t - BACK * t * t,
+            Ease::BackOut => {
+                let t = t - 1.0;
+                1.0 + (BACK + 1.0) * t * t * t + BACK * t * t
+            }
+            Ease::ElasticOut => {
+                if t <= 0.0 || t >= 1.0 {
+                    t
+                } else {
+                    2.0_f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * TAU / 3.0).sin() + 1.0
+                }
+            }
+            Ease::BounceOut => {
+                const N: f32 = 7.5625;
+                const D: f32 = 2.75;
+                if t < 1.0 / D {
+                    N * t * t
+                } else if t < 2.0 / D {
+                    let t = t - 1.5 / D;
+                    N * t * t + 0.75
+                } else if t < 2.5 / D {
+                    let t = t - 2.25 / D;
+                    N * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / D;
+                    N * t * t + 0.984375
+                }
+            }
+        }
+    }
+}
+
+/// Values a tween can blend between
+pub trait Tweenable: Copy {
+    /// self at t of 0, other at t of 1
+    fn tween(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Tweenable for f32 {
+    fn tween(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Tweenable for Vec2 {
+    fn tween(&self, other: &Self, t: f32) -> Self {
+        self.lerp(*other, t)
+    }
+}
+
+impl Tweenable for Vec3 {
+    fn tween(&self, other: &Self, t: f32) -> Self {
+        self.lerp(*other, t)
+    }
+}
+
+impl Tweenable for Vec4 {
+    fn tween(&self, other: &Self, t: f32) -> Self {
+        self.lerp(*other, t)
+    }
+}
+
+impl Tweenable for Quat {
+    fn tween(&self, other: &Self, t: f32) -> Self {
+        self.slerp(*other, t)
+    }
+}
+
+impl Tweenable for LinearRgba {
+    // linear space, so blends don't dip in brightness like srgb blends do
+    fn tween(&self, other: &Self, t: f32) -> Self {
+        let color = self.to_vec4().lerp(other.to_vec4(), t);
+        LinearRgba::new(color.x, color.y, color.z, color.w)
+    }
+}
+
+impl Tweenable for Transform {
+    fn tween(&self, other: &Self, t: f32) -> Self {
+        self.lerp(other, t)
+    }
+}
+
+/// Blends from one value to another over duration seconds
+/// Example Use:
+/// ```
+/// use glam::Vec3;
+/// use vulkan_engine::tween::{Ease, Tween};
+///
+/// let mut slide = Tween::new(Vec3::ZERO, Vec3::X * 4.0, 0.5).with_ease(Ease::CubicOut);
+/// // each frame, with the game clock's delta
+/// let position = slide.advance(1.0 / 60.0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tween<T: Tweenable> {
+    pub from: T,
+    pub to: T,
+    pub duration: f32,
+    pub ease: Ease,
+    elapsed: f32,
+}
+
+impl<T: Tweenable> Tween<T> {
+    pub fn new(from: T, to: T, duration: f32) -> Self {
+        Self {
+            from,
+            to,
+            duration,
+            ease: Ease::Linear,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn with_ease(mut self, ease: Ease) -> Self {
+        self.ease = ease;
+        self
+    }
+
+    /// Moves the tween on by delta seconds and returns the new value
+    pub fn advance(&mut self, delta: f32) -> T {
+        self.elapsed = (self.elapsed + delta.max(0.0)).min(self.duration.max(0.0));
+        self.value()
+    }
+
+    pub fn value(&self) -> T {
+        self.from.tween(&self.to, self.ease.apply(self.progress()))
+    }
+
+    /// Linear progress in 0..1
+    pub fn progress(&self) -> f32 {
+        if self.duration <= 0.0 {
+            1.0
+        } else {
+            self.elapsed / self.duration
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.progress() >= 1.0
+    }
+
+    /// Back to the start, e.g. to play it again
+    pub fn reset(&mut self) {
+        self.elapsed = 0.0;
+    }
+
+    // leftover time after the end, so sequences don't lose time between steps
+    fn overflow(&self, delta: f32) -> f32 {
+        (self.elapsed + delta - self.duration.max(0.0)).max(0.0)
+    }
+}
+
+/// Tweens played one after another, each starting where the last one ended
+/// Example Use:
+/// ```
+/// use vulkan_engine::color::LinearRgba;
+/// use vulkan_engine::tween::{Ease, Sequence};
+///
+/// // flash red, hold, then fade back
+/// let mut flash = Sequence::new(LinearRgba::WHITE)
+///     .then(LinearRgba::rgb(1.0, 0.0, 0.0), 0.1, Ease::QuadOut)
+///     .wait(0.2)
+///     .then(LinearRgba::WHITE, 0.5, Ease::SineInOut);
+///
+/// let tint = flash.advance(0.05);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sequence<T: Tweenable> {
+    start: T,
+    steps: Vec<Tween<T>>,
+    current: usize,
+    /// starts again from the first step after the last one
+    pub looping: bool,
+}
+
+impl<T: Tweenable> Sequence<T> {
+    pub fn new(start: T) -> Self {
+        Self {
+            start,
+            steps: Vec::new(),
+            current: 0,
+            looping: false,
+        }
+    }
+
+    /// Adds a tween from the end of the previous step to value
+    pub fn then(mut self, value: T, duration: f32, ease: Ease) -> Self {
+        let from = self.end();
+        self.steps
+            .push(Tween::new(from, value, duration).with_ease(ease));
+        self
+    }
+
+    /// Holds the current value for duration seconds
+    pub fn wait(self, duration: f32) -> Self {
+        let value = self.end();
+        self.then(value, duration, Ease::Linear)
+    }
+
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// Moves the sequence on by delta seconds and returns the new value
+    pub fn advance(&mut self, mut delta: f32) -> T {
+        let total: f32 = self.steps.iter().map(|step| step.duration.max(0.0)).sum();
+        // a looping sequence with no length would never use up delta
+        if self.looping && total <= 0.0 {
+            return self.value();
+        }
+        if self.looping {
+            delta %= total;
+        }
+
+        while let Some(step) = self.steps.get_mut(self.current) {
+            let overflow = step.overflow(delta);
+            step.advance(delta);
+            if !step.is_finished() {
+                break;
+            }
+            delta = overflow;
+
+            if self.current + 1 < self.steps.len() {
+                self.current += 1;
+            } else if self.looping && delta > 0.0 {
+                self.reset();
+            } else {
+                break;
+            }
+        }
+        self.value()
+    }
+
+    pub fn value(&self) -> T {
+        self.steps
+            .get(self.current)
+            .map_or(self.start, |step| step.value())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.looping
+            && self
+                .steps
+                .last()
+                .is_none_or(|step| self.current + 1 == self.steps.len() && step.is_finished())
+    }
+
+    pub fn reset(&mut self) {
+        self.current = 0;
+        self.steps.iter_mut().for_each(Tween::reset);
+    }
+
+    // value the sequence ends on
+    fn end(&self) -> T {
+        self.steps.last().map_or(self.start, |step| step.to)
+    }
+}
+
+/// How long a timer runs for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimerLength {
+    Seconds(f32),
+    /// frames where game time moved, paused frames don't count
+    Frames(u32),
+}
+
+/// Fires after a length of game time, optionally repeating
+/// Example Use:
+/// ```
+/// use vulkan_engine::tween::Timer;
+///
+/// let mut spawn = Timer::from_seconds(2.0).repeating(true);
+/// // each frame, with the game clock's delta
+/// for _ in 0..spawn.tick(1.0 / 60.0) {
+///     // spawn an enemy
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timer {
+    pub length: TimerLength,
+    pub repeating: bool,
+    elapsed: f32,
+    frames: u32,
+    finished: bool,
+}
+
+impl Timer {
+    pub fn from_seconds(seconds: f32) -> Self {
+        Self::new(TimerLength::Seconds(seconds))
+    }
+
+    pub fn from_frames(frames: u32) -> Self {
+        Self::new(TimerLength::Frames(frames))
+    }
+
+    fn new(length: TimerLength) -> Self {
+        Self {
+            length,
+            repeating: false,
+            elapsed: 0.0,
+            frames: 0,
+            finished: false,
+        }
+    }
+
+    pub fn repeating(mut self, repeating: bool) -> Self {
+        self.repeating = repeating;
+        self
+    }
+
+    /// Advances by one frame of delta game seconds and returns how many times the timer fired
+    /// a one shot timer fires once, a repeating one as often as delta covers
+    pub fn tick(&mut self, delta: f32) -> u32 {
+        if self.finished || delta <= 0.0 {
+            return 0;
+        }
+
+        let fired = match self.length {
+            TimerLength::Seconds(seconds) => {
+                self.elapsed += delta;
+                if seconds <= 0.0 {
+                    1
+                } else if self.elapsed >= seconds {
+                    let fired = (self.elapsed / seconds) as u32;
+                    self.elapsed -= fired as f32 * seconds;
+                    fired
+                } else {
+                    0
+                }
+            }
+            TimerLength::Frames(frames) => {
+                self.frames += 1;
+                if self.frames >= frames {
+                    self.frames = 0;
+                    1
+                } else {
+                    0
+                }
+            }
+        };
+
+        if fired > 0 && !self.repeating {
+            self.finished = true;
+            return 1;
+        }
+        fired
+    }
+
+    /// One shot timer that has fired
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Progress towards the next firing in 0..1
+    pub fn fraction(&self) -> f32 {
+        if self.finished {
+            return 1.0;
+        }
+        match self.length {
+            TimerLength::Seconds(seconds) if seconds > 0.0 => self.elapsed / seconds,
+            TimerLength::Frames(frames) if frames > 0 => self.frames as f32 / frames as f32,
+            _ => 1.0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.elapsed = 0.0;
+        self.frames = 0;
+        self.finished = false;
+    }
+}
+
+#[test]
+fn ease_test() {
+    let eases = [
+        Ease::Linear,
+        Ease::QuadIn,
+        Ease::QuadOut,
+        Ease::QuadInOut,
+        Ease::CubicIn,
+        Ease::CubicOut,
+        Ease::CubicInOut,
+        Ease::SineInOut,
+        Ease::ExpoOut,
+        Ease::BackIn,
+        Ease::BackOut,
+        Ease::ElasticOut,
+        Ease::BounceOut,
+    ];
+    // every curve starts at 0 and ends at 1
+    for ease in eases {
+        assert!(ease.apply(0.0).abs() < 1e-5, "{ease:?}");
+        assert!((ease.apply(1.0) - 1.0).abs() < 1e-5, "{ease:?}");
+    }
+    assert!(Ease::QuadIn.apply(0.5) < 0.5 && Ease::QuadOut.apply(0.5) > 0.5);
+    assert!((Ease::CubicInOut.apply(0.5) - 0.5).abs() < 1e-5);
+    // back pulls below 0 before heading to 1
+    assert!(Ease::BackIn.apply(0.2) < 0.0);
+}
+
+#[test]
+fn tween_sequence_test() {
+    let mut tween = Tween::new(0.0, 10.0, 2.0);
+    assert_eq!(tween.advance(0.5), 2.5);
+    assert_eq!(tween.advance(5.0), 10.0);
+    assert!(tween.is_finished());
+
+    let mut sequence = Sequence::new(0.0)
+        .then(1.0, 1.0, Ease::Linear)
+        .wait(1.0)
+        .then(0.0, 2.0, Ease::Linear);
+    assert_eq!(sequence.advance(0.5), 0.5);
+    // leftover time carries into the next step
+    assert_eq!(sequence.advance(1.0), 1.0);
+    assert_eq!(sequence.advance(1.5), 0.5);
+    assert!(!sequence.is_finished());
+    assert_eq!(sequence.advance(10.0), 0.0);
+    assert!(sequence.is_finished());
+
+    let mut looping = Sequence::new(Vec2::ZERO)
+        .then(Vec2::ONE, 1.0, Ease::Linear)
+        .looping(true);
+    assert_eq!(looping.advance(1.25), Vec2::splat(0.25));
+    assert!(!looping.is_finished());
+
+    let faded = LinearRgba::BLACK.tween(&LinearRgba::WHITE, 0.5);
+    assert_eq!(faded, LinearRgba::rgb(0.5, 0.5, 0.5));
+}
+
+#[test]
+fn timer_test() {
+    let mut once = Timer::from_seconds(1.0);
+    assert_eq!(once.tick(0.6), 0);
+    assert_eq!(once.tick(0.6), 1);
+    assert!(once.is_finished());
+    assert_eq!(once.tick(5.0), 0);
+
+    let mut repeating = Timer::from_seconds(0.5).repeating(true);
+    assert_eq!(repeating.tick(1.2), 2);
+    assert!((repeating.fraction() - 0.4).abs() < 1e-5);
+
+    // paused frames don't count towards frame timers
+    let mut frames = Timer::from_frames(3);
+    frames.tick(0.016);
+    frames.tick(0.0);
+    frames.tick(0.016);
+    assert!(!frames.is_finished());
+    assert_eq!(frames.tick(0.016), 1);
+}
@@ -0,0 +1,433 @@
+use crate::prefab::PropertyOverride;
+use crate::scene::{Node, NodeId, Scene, SceneError, SceneSubtree, Transform};
+
+/// An edit that can be taken back, commands keep whatever they need to revert themselves
+pub trait Command {
+    type Target;
+    type Error;
+
+    fn apply(&mut self, target: &mut Self::Target) -> Result<(), Self::Error>;
+    fn revert(&mut self, target: &mut Self::Target) -> Result<(), Self::Error>;
+
+    /// Folds next, which has already been applied, into self when both belong to one edit
+    /// like every step of a drag, so the whole drag is undone at once
+    fn merge(&mut self, _next: &Self) -> bool {
+        false
+    }
+}
+
+/// Applied commands that can be undone and undone ones that can be redone
+/// executing a new command forgets everything that could be redone
+/// Example Use:
+/// ```
+/// use glam::Vec3;
+/// use vulkan_engine::scene::{Node, Scene, Transform};
+/// use vulkan_engine::undo::{SceneCommand, UndoStack};
+///
+/// let mut scene = Scene::default();
+/// let mut history = UndoStack::default();
+/// history.execute(&mut scene, SceneCommand::create(Node::new("crate"), None)).unwrap();
+/// let node = scene.roots()[0];
+///
+/// // every frame of a drag, merged into one step
+/// for x in 1..=10 {
+///     let moved = Transform::from_translation(Vec3::X * x as f32);
+///     history.execute(&mut scene, SceneCommand::transform(node, moved)).unwrap();
+/// }
+/// // once the mouse is released
+/// history.seal();
+///
+/// history.undo(&mut scene).unwrap();
+/// assert_eq!(scene.get(node).unwrap().transform, Transform::IDENTITY);
+/// history.undo(&mut scene).unwrap();
+/// assert!(scene.get(node).is_none());
+/// ```
+#[derive(Debug)]
+pub struct UndoStack<C> {
+    done: Vec<C>,
+    undone: Vec<C>,
+    /// oldest commands are forgotten past this many
+    pub limit: usize,
+    // the next command can't merge into the last one
+    sealed: bool,
+}
+
+impl<C> Default for UndoStack<C> {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl<C> UndoStack<C> {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            done: Vec::new(),
+            undone: Vec::new(),
+            limit,
+            sealed: true,
+        }
+    }
+}
+
+impl<C: Command> UndoStack<C> {
+    /// Applies command and records it, merging it into the last command when that allows it
+    /// nothing is recorded if it fails
+    pub fn execute(&mut self, target: &mut C::Target, mut command: C) -> Result<(), C::Error> {
+        command.apply(target)?;
+        self.undone.clear();
+
+        let merged = !self.sealed
+            && self
+                .done
+                .last_mut()
+                .is_some_and(|last| last.merge(&command));
+        if !merged {
+            self.done.push(command);
+            if self.done.len() > self.limit {
+                let excess = self.done.len() - self.limit;
+                self.done.drain(..excess);
+            }
+        }
+        self.sealed = false;
+        Ok(())
+    }
+
+    /// Ends the current merge, call when a continuous edit like a drag finishes
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    /// Reverts the last command, returns false when there was nothing to undo
+    /// a command that fails to revert stays where it was
+    pub fn undo(&mut self, target: &mut C::Target) -> Result<bool, C::Error> {
+        let Some(mut command) = self.done.pop() else {
+            return Ok(false);
+        };
+        if let Err(error) = command.revert(target) {
+            self.done.push(command);
+            return Err(error);
+        }
+        self.undone.push(command);
+        self.sealed = true;
+        Ok(true)
+    }
+
+    /// Applies the last undone command again, returns false when there was nothing to redo
+    pub fn redo(&mut self, target: &mut C::Target) -> Result<bool, C::Error> {
+        let Some(mut command) = self.undone.pop() else {
+            return Ok(false);
+        };
+        if let Err(error) = command.apply(target) {
+            self.undone.push(command);
+            return Err(error);
+        }
+        self.done.push(command);
+        self.sealed = true;
+        Ok(true)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    /// Last command applied, the next to be undone
+    pub fn last(&self) -> Option<&C> {
+        self.done.last()
+    }
+
+    pub fn clear(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+        self.sealed = true;
+    }
+}
+
+/// Edits to a Scene, values to restore are captured when the command is first applied
+#[derive(Clone, Debug)]
+pub enum SceneCommand {
+    Create {
+        node: Node,
+        parent: Option<NodeId>,
+        /// id the node got, kept through undo and redo
+        created: Option<NodeId>,
+        removed: Option<Box<SceneSubtree>>,
+    },
+    /// removes the node and everything below it
+    Delete {
+        node: NodeId,
+        removed: Option<Box<SceneSubtree>>,
+    },
+    Transform {
+        node: NodeId,
+        before: Option<Transform>,
+        after: Transform,
+    },
+    Property {
+        node: NodeId,
+        before: Option<PropertyOverride>,
+        after: PropertyOverride,
+    },
+}
+
+impl SceneCommand {
+    pub fn create(node: Node, parent: Option<NodeId>) -> Self {
+        SceneCommand::Create {
+            node,
+            parent,
+            created: None,
+            removed: None,
+        }
+    }
+
+    pub fn delete(node: NodeId) -> Self {
+        SceneCommand::Delete {
+            node,
+            removed: None,
+        }
+    }
+
+    pub fn transform(node: NodeId, transform: Transform) -> Self {
+        SceneCommand::Transform {
+            node,
+            before: None,
+            after: transform,
+        }
+    }
+
+    pub fn property(node: NodeId, property: PropertyOverride) -> Self {
+        SceneCommand::Property {
+            node,
+            before: None,
+            after: property,
+        }
+    }
+
+    /// Node the command changes, None for a create that hasn't been applied yet
+    pub fn node(&self) -> Option<NodeId> {
+        match self {
+            SceneCommand::Create { created, .. } => *created,
+            SceneCommand::Delete { node, .. }
+            | SceneCommand::Transform { node, .. }
+            | SceneCommand::Property { node, .. } => Some(*node),
+        }
+    }
+}
+
+impl Command for SceneCommand {
+    type Target = Scene;
+    type Error = SceneError;
+
+    fn apply(&mut self, scene: &mut Scene) -> Result<(), SceneError> {
+        match self {
+            SceneCommand::Create {
+                node,
+                parent,
+                created,
+                removed,
+            } => {
+                // redo puts the same node back so later commands still find it
+                *created = Some(match removed {
+                    Some(subtree) => scene.restore_subtree(subtree)?,
+                    None => scene.add(node.clone(), *parent)?,
+                });
+                *removed = None;
+            }
+            SceneCommand::Delete { node, removed } => {
+                *removed = Some(Box::new(scene.take_subtree(*node)?));
+            }
+            SceneCommand::Transform {
+                node,
+                before,
+                after,
+            } => {
+                let target = scene.get_mut(*node).ok_or(SceneError::MissingNode(*node))?;
+                before.get_or_insert(target.transform);
+                target.transform = *after;
+            }
+            SceneCommand::Property {
+                node,
+                before,
+                after,
+            } => {
+                let target = scene.get_mut(*node).ok_or(SceneError::MissingNode(*node))?;
+                before.get_or_insert_with(|| after.read(target));
+                after.apply(target);
+            }
+        }
+        Ok(())
+    }
+
+    fn revert(&mut self, scene: &mut Scene) -> Result<(), SceneError> {
+        match self {
+            SceneCommand::Create {
+                created, removed, ..
+            } => {
+                if let Some(created) = *created {
+                    *removed = Some(Box::new(scene.take_subtree(created)?));
+                }
+            }
+            SceneCommand::Delete { removed, .. } => {
+                if let Some(subtree) = removed {
+                    scene.restore_subtree(subtree)?;
+                }
+                *removed = None;
+            }
+            SceneCommand::Transform { node, before, .. } => {
+                let target = scene.get_mut(*node).ok_or(SceneError::MissingNode(*node))?;
+                if let Some(before) = before {
+                    target.transform = *before;
+                }
+            }
+            SceneCommand::Property { node, before, .. } => {
+                let target = scene.get_mut(*node).ok_or(SceneError::MissingNode(*node))?;
+                if let Some(before) = before {
+                    before.apply(target);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // repeated edits of the same node's transform or property collapse into the first
+    fn merge(&mut self, next: &Self) -> bool {
+        match (self, next) {
+            (
+                SceneCommand::Transform { node, after, .. },
+                SceneCommand::Transform {
+                    node: next_node,
+                    after: next_after,
+                    ..
+                },
+            ) if node == next_node => {
+                *after = *next_after;
+                true
+            }
+            (
+                SceneCommand::Property { node, after, .. },
+                SceneCommand::Property {
+                    node: next_node,
+                    after: next_after,
+                    ..
+                },
+            ) if node == next_node && after.same_property(next_after) => {
+                after.clone_from(next_after);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[test]
+fn undo_test() {
+    use crate::color::LinearRgba;
+    use glam::Vec3;
+
+    let mut scene = Scene::default();
+    let mut history = UndoStack::new(8);
+    assert!(!history.undo(&mut scene).unwrap());
+
+    history
+        .execute(&mut scene, SceneCommand::create(Node::new("table"), None))
+        .unwrap();
+    let table = history.last().unwrap().node().unwrap();
+    history
+        .execute(
+            &mut scene,
+            SceneCommand::create(Node::new("cup"), Some(table)),
+        )
+        .unwrap();
+    let cup = history.last().unwrap().node().unwrap();
+    history.seal();
+
+    // a drag is one step, sealing starts the next
+    for x in 1..=5 {
+        let moved = Transform::from_translation(Vec3::X * x as f32);
+        history
+            .execute(&mut scene, SceneCommand::transform(table, moved))
+            .unwrap();
+    }
+    history.seal();
+    history
+        .execute(
+            &mut scene,
+            SceneCommand::transform(table, Transform::from_scale(Vec3::splat(2.0))),
+        )
+        .unwrap();
+    history.undo(&mut scene).unwrap();
+    assert_eq!(
+        scene.get(table).unwrap().transform,
+        Transform::from_translation(Vec3::X * 5.0)
+    );
+    history.undo(&mut scene).unwrap();
+    assert_eq!(scene.get(table).unwrap().transform, Transform::IDENTITY);
+    history.redo(&mut scene).unwrap();
+    assert_eq!(
+        scene.get(table).unwrap().transform,
+        Transform::from_translation(Vec3::X * 5.0)
+    );
+
+    // a new edit drops what could have been redone
+    assert!(history.can_redo());
+    history
+        .execute(
+            &mut scene,
+            SceneCommand::property(cup, PropertyOverride::Tint(LinearRgba::BLACK)),
+        )
+        .unwrap();
+    assert!(!history.can_redo());
+    history
+        .execute(
+            &mut scene,
+            SceneCommand::property(cup, PropertyOverride::Name("mug".into())),
+        )
+        .unwrap();
+    history.undo(&mut scene).unwrap();
+    assert_eq!(scene.get(cup).unwrap().name, "cup");
+    assert_eq!(scene.get(cup).unwrap().tint, LinearRgba::BLACK);
+    history.undo(&mut scene).unwrap();
+    assert_eq!(scene.get(cup).unwrap().tint, LinearRgba::WHITE);
+
+    // deleting takes the cup along, undo puts both back with the same ids
+    history
+        .execute(&mut scene, SceneCommand::delete(table))
+        .unwrap();
+    assert!(scene.get(cup).is_none());
+    history.undo(&mut scene).unwrap();
+    assert_eq!(scene.get(cup).unwrap().parent(), Some(table));
+    assert_eq!(scene.roots(), &[table]);
+
+    // undoing the creates and redoing them keeps the ids later commands refer to
+    while history.can_undo() {
+        history.undo(&mut scene).unwrap();
+    }
+    assert_eq!(scene.iter().count(), 0);
+    while history.can_redo() {
+        history.redo(&mut scene).unwrap();
+    }
+    assert!(scene.get(table).is_none());
+    history.undo(&mut scene).unwrap();
+    assert_eq!(scene.get(cup).unwrap().parent(), Some(table));
+
+    // failed commands aren't recorded
+    assert_eq!(
+        history.execute(&mut scene, SceneCommand::delete(99)),
+        Err(SceneError::MissingNode(99))
+    );
+
+    let mut limited = UndoStack::new(2);
+    for name in ["a", "b", "c"] {
+        limited
+            .execute(&mut scene, SceneCommand::create(Node::new(name), None))
+            .unwrap();
+    }
+    let mut undone = 0;
+    while limited.undo(&mut scene).unwrap() {
+        undone += 1;
+    }
+    assert_eq!(undone, 2);
+}
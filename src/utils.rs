@@ -5,6 +5,8 @@ use std::panic;
 /// Any Type that implements ReplaceWith that is of '&mut Self' can have the value Self Owned, as long as 'Self' is returned afterwards.
 /// # Example
 /// ```
+/// use vulkan_engine::utils::ReplaceWith;
+///
 /// enum Foo {
 ///   Bar,
 ///   Baz
@@ -37,6 +39,7 @@ pub trait ReplaceWith<F> {
 }
 
 #[test]
+#[allow(clippy::drop_non_drop)]
 fn replace_with_test() {
     #[derive(PartialEq, Eq, Debug)]
     enum Foo {
@@ -56,6 +59,26 @@ fn replace_with_test() {
     assert_eq!(&foo, &Foo::Baz);
 }
 
+#[test]
+fn replace_with_old_value_test() {
+    #[derive(PartialEq, Eq, Debug)]
+    enum Foo {
+        Bar,
+        Baz,
+    }
+
+    impl<F> ReplaceWith<F> for Foo {}
+
+    // the closure is handed the value being replaced
+    let mut foo = Foo::Bar;
+    foo.replace_with(|foo| {
+        assert_eq!(foo, Foo::Bar);
+        Foo::Baz
+    });
+
+    assert_eq!(foo, Foo::Baz);
+}
+
 #[allow(dead_code)]
 pub struct GameInfo {
     pub app_name: &'static CStr,
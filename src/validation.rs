@@ -0,0 +1,316 @@
+use glam::{Vec3, Vec4};
+use std::fmt;
+use thiserror::Error;
+
+use crate::renderer::MeshInstance;
+use crate::renderer::mesh::Vertex;
+use crate::renderer::render_texture::RenderTextureId;
+
+// how far from 1 a length can be before it counts as not normalized
+const UNIT_TOLERANCE: f32 = 1e-3;
+
+/// A problem in scene data that would otherwise show up as garbage on the gpu
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum SceneIssue {
+    #[error("instance {instance} has a transform containing NaN or infinity")]
+    NonFiniteTransform { instance: usize },
+    #[error("instance {instance} has a zero scale transform and will never be visible")]
+    DegenerateTransform { instance: usize },
+    #[error("instance {instance} has a tint containing NaN or infinity")]
+    NonFiniteTint { instance: usize },
+    #[error("vertex {vertex} has a position containing NaN or infinity")]
+    NonFiniteVertex { vertex: usize },
+    #[error("vertex {vertex} has a normal containing NaN or infinity")]
+    NonFiniteNormal { vertex: usize },
+    #[error("vertex {vertex} has a normal of length {length}, normals must be unit length")]
+    NonUnitNormal { vertex: usize, length: f32 },
+    #[error(
+        "vertex {vertex} has a tangent that is not unit length, at a right angle to the normal and with a handedness of 1 or -1"
+    )]
+    DegenerateTangent { vertex: usize },
+    #[error("instance {instance} uses mesh {mesh}, which does not exist")]
+    UnknownMesh { instance: usize, mesh: usize },
+    #[error("instance {instance} uses material {material}, which does not exist")]
+    UnknownMaterial { instance: usize, material: usize },
+    #[error("material {material} samples render texture {render_texture}, which does not exist")]
+    UnknownRenderTexture {
+        material: usize,
+        render_texture: usize,
+    },
+    #[error("triangle {triangle} has zero area, check for duplicated vertices")]
+    DegenerateTriangle { triangle: usize },
+    #[error("light {light} has a position or colour containing NaN or infinity")]
+    NonFiniteLight { light: usize },
+    #[error("light {light} has radius {radius}, radius must be greater than 0")]
+    InvalidLightRadius { light: usize, radius: f32 },
+}
+
+impl SceneIssue {
+    /// Whether drawing anyway would hand NaN or infinity to the gpu, the other issues only look wrong
+    pub fn is_non_finite(&self) -> bool {
+        matches!(
+            self,
+            Self::NonFiniteTransform { .. }
+                | Self::NonFiniteTint { .. }
+                | Self::NonFiniteVertex { .. }
+                | Self::NonFiniteNormal { .. }
+                | Self::NonFiniteLight { .. }
+        )
+    }
+}
+
+/// Every issue found while validating a scene
+#[derive(Debug, Clone, PartialEq, Error)]
+pub struct SceneValidationError {
+    pub issues: Vec<SceneIssue>,
+}
+
+impl fmt::Display for SceneValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "scene has {} issue(s)", self.issues.len())?;
+        for issue in &self.issues {
+            write!(f, "\n  {issue}")?;
+        }
+        Ok(())
+    }
+}
+
+impl SceneValidationError {
+    /// Ok if no issues were found
+    pub fn check(issues: Vec<SceneIssue>) -> Result<(), Self> {
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(Self { issues })
+        }
+    }
+
+    /// Ok unless one of issues is non finite, the error only holds those
+    pub fn check_finite(issues: &[SceneIssue]) -> Result<(), Self> {
+        Self::check(
+            issues
+                .iter()
+                .filter(|issue| issue.is_non_finite())
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+/// Checks instance transforms and tints
+pub fn validate_instances(instances: &[MeshInstance]) -> Vec<SceneIssue> {
+    let mut issues = Vec::new();
+    for (instance, mesh_instance) in instances.iter().enumerate() {
+        if !mesh_instance.transform.is_finite() {
+            issues.push(SceneIssue::NonFiniteTransform { instance });
+        } else if mesh_instance.transform.determinant().abs() <= f32::EPSILON * f32::EPSILON {
+            issues.push(SceneIssue::DegenerateTransform { instance });
+        }
+
+        if !mesh_instance.tint.is_finite() {
+            issues.push(SceneIssue::NonFiniteTint { instance });
+        }
+    }
+    issues
+}
+
+/// Checks the meshes, materials and render textures instances and materials refer to exist
+/// material_render_textures has the render texture of every material, in MaterialId order
+/// the renderer skips unknown meshes and draws unknown materials with the default one
+pub fn validate_references(
+    instances: &[MeshInstance],
+    mesh_count: usize,
+    material_render_textures: &[Option<RenderTextureId>],
+    render_texture_count: usize,
+) -> Vec<SceneIssue> {
+    let mut issues = Vec::new();
+    for (instance, mesh_instance) in instances.iter().enumerate() {
+        if mesh_instance.mesh >= mesh_count {
+            issues.push(SceneIssue::UnknownMesh {
+                instance,
+                mesh: mesh_instance.mesh,
+            });
+        }
+        if mesh_instance.material >= material_render_textures.len() {
+            issues.push(SceneIssue::UnknownMaterial {
+                instance,
+                material: mesh_instance.material,
+            });
+        }
+    }
+
+    for (material, render_texture) in material_render_textures.iter().enumerate() {
+        if let Some(render_texture) = *render_texture
+            && render_texture >= render_texture_count
+        {
+            issues.push(SceneIssue::UnknownRenderTexture {
+                material,
+                render_texture,
+            });
+        }
+    }
+    issues
+}
+
+/// Checks normals and tangents, run after generate_normals and generate_tangents
+/// zero tangents are left by tangent generation on degenerate uvs and are allowed
+pub fn validate_vertices(vertices: &[Vertex]) -> Vec<SceneIssue> {
+    let mut issues = Vec::new();
+    for (vertex, data) in vertices.iter().enumerate() {
+        if !data.normal.is_finite() {
+            issues.push(SceneIssue::NonFiniteNormal { vertex });
+            continue;
+        }
+
+        let length = data.normal.length();
+        if (length - 1.0).abs() > UNIT_TOLERANCE {
+            issues.push(SceneIssue::NonUnitNormal { vertex, length });
+            continue;
+        }
+
+        let tangent = data.tangent.truncate();
+        if data.tangent != Vec4::ZERO
+            && (!data.tangent.is_finite()
+                || (tangent.length() - 1.0).abs() > UNIT_TOLERANCE
+                || tangent.dot(data.normal).abs() > UNIT_TOLERANCE
+                || data.tangent.w.abs() != 1.0)
+        {
+            issues.push(SceneIssue::DegenerateTangent { vertex });
+        }
+    }
+    issues
+}
+
+/// Checks a triangle list of vertex positions
+pub fn validate_triangles(positions: &[Vec3]) -> Vec<SceneIssue> {
+    let mut issues: Vec<SceneIssue> = positions
+        .iter()
+        .enumerate()
+        .filter(|(_, position)| !position.is_finite())
+        .map(|(vertex, _)| SceneIssue::NonFiniteVertex { vertex })
+        .collect();
+
+    issues.extend(
+        positions
+            .chunks_exact(3)
+            .enumerate()
+            .filter(|(_, triangle)| {
+                let area = (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]);
+                area.is_finite() && area.length_squared() <= f32::EPSILON * f32::EPSILON
+            })
+            .map(|(triangle, _)| SceneIssue::DegenerateTriangle { triangle }),
+    );
+    issues
+}
+
+#[test]
+fn validate_instances_test() {
+    use crate::color::LinearRgba;
+    use glam::Mat4;
+
+    let instances = [
+        MeshInstance::default(),
+        MeshInstance {
+            transform: Mat4::from_scale(Vec3::ZERO),
+            ..Default::default()
+        },
+        MeshInstance {
+            transform: Mat4::from_translation(Vec3::new(f32::NAN, 0.0, 0.0)),
+            tint: LinearRgba::rgb(f32::INFINITY, 0.0, 0.0),
+            ..Default::default()
+        },
+    ];
+
+    assert_eq!(
+        validate_instances(&instances),
+        vec![
+            SceneIssue::DegenerateTransform { instance: 1 },
+            SceneIssue::NonFiniteTransform { instance: 2 },
+            SceneIssue::NonFiniteTint { instance: 2 },
+        ]
+    );
+}
+
+#[test]
+fn validate_triangles_test() {
+    let positions = [
+        Vec3::ZERO,
+        Vec3::X,
+        Vec3::Y,
+        // collapsed triangle
+        Vec3::ONE,
+        Vec3::ONE,
+        Vec3::ONE,
+    ];
+
+    let issues = validate_triangles(&positions);
+    assert_eq!(issues, vec![SceneIssue::DegenerateTriangle { triangle: 1 }]);
+    // collapsed triangles still draw, just invisibly
+    assert!(SceneValidationError::check_finite(&issues).is_ok());
+
+    let mut positions = positions;
+    positions[1].y = f32::NAN;
+    let error = SceneValidationError::check_finite(&validate_triangles(&positions)).unwrap_err();
+    assert_eq!(
+        error.issues,
+        vec![SceneIssue::NonFiniteVertex { vertex: 1 }]
+    );
+}
+
+#[test]
+fn validate_vertices_test() {
+    use glam::Vec2;
+
+    let vertex = Vertex::new(Vec3::ZERO, Vec3::ONE, Vec2::ZERO).with_normal(Vec3::Z);
+    let vertices = [
+        vertex.with_tangent(Vec4::new(1.0, 0.0, 0.0, -1.0)),
+        // no tangent, drawn without normal mapping
+        vertex,
+        vertex.with_normal(Vec3::new(0.0, 0.0, 2.0)),
+        vertex.with_normal(Vec3::new(f32::NAN, 0.0, 1.0)),
+        // along the normal
+        vertex.with_tangent(Vec4::new(0.0, 0.0, 1.0, 1.0)),
+    ];
+
+    assert_eq!(
+        validate_vertices(&vertices),
+        vec![
+            SceneIssue::NonUnitNormal {
+                vertex: 2,
+                length: 2.0
+            },
+            SceneIssue::NonFiniteNormal { vertex: 3 },
+            SceneIssue::DegenerateTangent { vertex: 4 },
+        ]
+    );
+}
+
+#[test]
+fn validate_references_test() {
+    let instances = [
+        MeshInstance::default(),
+        MeshInstance {
+            mesh: 3,
+            material: 2,
+            ..Default::default()
+        },
+    ];
+
+    assert_eq!(
+        validate_references(&instances, 1, &[Some(0)], 0),
+        vec![
+            SceneIssue::UnknownMesh {
+                instance: 1,
+                mesh: 3
+            },
+            SceneIssue::UnknownMaterial {
+                instance: 1,
+                material: 2
+            },
+            SceneIssue::UnknownRenderTexture {
+                material: 0,
+                render_texture: 0
+            },
+        ]
+    );
+}